@@ -1,87 +1,316 @@
 use std::fs;
-use std::io::{self, BufRead, ErrorKind, Write};
+use std::io::{self, BufRead, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
 
-use risl::cli::args::Args;
+use risl::cli::args::{Args, Emit};
 use risl::cli::error::Error;
 use risl::parser::context::ParseContext;
 use risl::parser::diagnostic::DiagContext;
-use risl::parser::emitter::new_emitter_human_readable;
-
-fn run_file(path: &String) -> Result<(), exitcode::ExitCode> {
-    let program = match fs::read_to_string(path) {
-        Ok(program) => program,
-        Err(err) => {
-            let exit_code = match err.kind() {
-                ErrorKind::NotFound | ErrorKind::PermissionDenied => exitcode::NOINPUT,
-                _ => exitcode::IOERR,
-            };
-            eprintln!("Cannot read input file '{path}': {err}");
-            return Err(exit_code);
+use risl::parser::emitter::{new_emitter_human_readable, new_emitter_none};
+use risl::parser::printer;
+use risl::parser::source_map::SourceMap;
+use risl::session::Session;
+use risl::vm::{serialize, Module};
+use risl::{Backend, CheckOptions, RunError, RunOptions};
+
+/// The extension of the files storing compiled modules.
+const COMPILED_EXTENSION: &str = "rislc";
+
+/// Reads a file, reporting the errors.
+fn read_file<T>(path: &Path, read: fn(&Path) -> io::Result<T>) -> Result<T, exitcode::ExitCode> {
+    read(path).map_err(|err| {
+        let exit_code = match err.kind() {
+            ErrorKind::NotFound | ErrorKind::PermissionDenied => exitcode::NOINPUT,
+            _ => exitcode::IOERR,
+        };
+        eprintln!("Cannot read input file '{}': {err}", path.display());
+        exit_code
+    })
+}
+
+fn is_compiled(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == COMPILED_EXTENSION)
+}
+
+fn run_file(path: &String, args: &Args) -> Result<(), exitcode::ExitCode> {
+    let path = Path::new(path);
+    if is_compiled(path) {
+        return run_compiled_file(path, args);
+    }
+    let program = read_file(path, |path| fs::read_to_string(path))?;
+    let source_map = SourceMap::new(path.display().to_string(), program.as_str());
+    if args.emit.is_none() && runs_on_vm(args) {
+        // Runs the module compiled from the source instead, if it is up to date
+        if let Ok(bytes) = fs::read(path.with_extension(COMPILED_EXTENSION)) {
+            if let Ok(module) = serialize::decode(&bytes, Some(&program), check_options(args)) {
+                return run_module(&module, Some(source_map), args);
+            }
         }
+    }
+    run(&program, source_map, args)
+}
+
+/// Returns true if the program runs on the virtual machine, the only backend running the compiled
+/// modules.
+fn runs_on_vm(args: &Args) -> bool {
+    args.backend.unwrap_or_default() == Backend::Vm
+}
+
+/// Runs a compiled module, or the source next to it if the module does not match it or the
+/// tree-walking interpreter is selected. The module can run without its source.
+fn run_compiled_file(path: &Path, args: &Args) -> Result<(), exitcode::ExitCode> {
+    let source_path = path.with_extension("risl");
+    let source_map = |program: &str| SourceMap::new(source_path.display().to_string(), program);
+    if args.emit.is_some() || !runs_on_vm(args) {
+        let program = read_file(&source_path, |path| fs::read_to_string(path))?;
+        return run(&program, source_map(&program), args);
+    }
+    let bytes = read_file(path, |path| fs::read(path))?;
+    let program = match source_path.exists() {
+        true => Some(read_file(&source_path, |path| fs::read_to_string(path))?),
+        false => None,
     };
-    run(&program).map_err(|_| exitcode::SOFTWARE)
+    match serialize::decode(&bytes, program.as_deref(), check_options(args)) {
+        Ok(module) => run_module(&module, program.as_deref().map(source_map), args),
+        Err(err) => match program {
+            Some(program) => run(&program, source_map(&program), args),
+            None => {
+                eprintln!("Cannot load compiled module '{}': {err}", path.display());
+                Err(exitcode::DATAERR)
+            }
+        },
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum IsInteractive {
-    No,
-    Yes,
+/// Compiles a source file to a `.rislc` file, next to it unless an output file is given.
+fn compile_file(path: &String, args: &Args) -> Result<(), exitcode::ExitCode> {
+    let path = Path::new(path);
+    let program = read_file(path, |path| fs::read_to_string(path))?;
+    let source_map = SourceMap::new(path.display().to_string(), program.as_str());
+    let context = ParseContext::new(
+        DiagContext::new(new_emitter_human_readable()).with_source_map(source_map),
+    );
+    let options = check_options(args);
+    let Some(module) = risl::compile(&context, &program, options) else {
+        return Err(exitcode::DATAERR);
+    };
+    let bytes = serialize::encode(&module, &program, options).map_err(|err| {
+        eprintln!("Cannot compile '{}': {err}", path.display());
+        exitcode::DATAERR
+    })?;
+    let output = match &args.output_file {
+        Some(output) => PathBuf::from(output),
+        None => path.with_extension(COMPILED_EXTENSION),
+    };
+    fs::write(&output, bytes).map_err(|err| {
+        eprintln!("Cannot write output file '{}': {err}", output.display());
+        exitcode::CANTCREAT
+    })
 }
 
-fn print_prompt() {
-    print!("> ");
-    io::stdout().flush().unwrap();
+/// Reads the whole program from the standard input and runs it.
+fn run_from_stdin(args: &Args) -> Result<(), exitcode::ExitCode> {
+    let mut program = String::new();
+    if let Err(err) = io::stdin().read_to_string(&mut program) {
+        eprintln!("Error reading input: {err}");
+        return Err(exitcode::IOERR);
+    }
+    run(&program, SourceMap::new("<stdin>", program.as_str()), args)
 }
 
-fn run_from_stdin(is_interactive: IsInteractive) -> Result<(), exitcode::ExitCode> {
-    // TODO handle multiline statements
-    if is_interactive == IsInteractive::Yes {
-        print_prompt();
+/// The name of the source of the interactive sessions.
+const INTERACTIVE: &str = "<interactive>";
+
+/// Runs the input after the previous ones of the session, reporting its errors without ending
+/// the session.
+fn eval(session: &mut Session, input: &str, args: &Args) {
+    if args.emit.is_some() {
+        let _ = run(input, SourceMap::new(INTERACTIVE, input), args);
+        return;
     }
+    // The spans of the input are offsets in the text of the whole session
+    let source = format!("{}{input}", session.source());
+    let source_map = SourceMap::new(INTERACTIVE, source.as_str());
+    let context = ParseContext::new(
+        DiagContext::new(new_emitter_human_readable()).with_source_map(source_map),
+    );
+    let output = &mut io::stdout().lock();
+    let _ = session.eval(&context, input, check_options(args), output);
+    let _ = output.flush();
+}
+
+/// Returns true if the input ends before its last item or statement does, like an unclosed block.
+fn is_incomplete(input: &str) -> bool {
+    let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+    risl::parser::parse(&context, input);
+    context.diag_ctx().diagnostics().iter().any(|diagnostic| {
+        diagnostic.level.is_error()
+            && diagnostic
+                .span
+                .is_some_and(|span| span.end as usize == input.len())
+    })
+}
+
+fn print_prompt(prompt: &str) {
+    print!("{prompt}");
+    io::stdout().flush().unwrap();
+}
+
+/// Runs the lines read from the standard input one after the other, until its end. An input
+/// continues on the next lines until it is complete or an empty line ends it.
+fn run_interactive(args: &Args) -> Result<(), exitcode::ExitCode> {
+    let mut session = Session::default();
+    let mut input = String::new();
+    print_prompt("> ");
     for line in io::stdin().lock().lines() {
-        match line {
-            Ok(line) => {
-                run(&line)?;
-                if is_interactive == IsInteractive::Yes {
-                    print_prompt();
-                }
-            }
-            Err(err) => {
-                eprintln!("Error reading input: {err}");
-                return Err(exitcode::IOERR);
-            }
+        let line = line.map_err(|err| {
+            eprintln!("Error reading input: {err}");
+            exitcode::IOERR
+        })?;
+        input.push_str(&line);
+        input.push('\n');
+        if input.trim().is_empty() {
+            input.clear();
+        } else if line.trim().is_empty() || !is_incomplete(&input) {
+            eval(&mut session, &input, args);
+            input.clear();
         }
+        print_prompt(if input.is_empty() { "> " } else { "... " });
     }
     Ok(())
 }
 
-fn run(program: &String) -> Result<(), exitcode::ExitCode> {
-    println!("INFO: Running program '{}'", program);
-    let context = ParseContext::new(DiagContext::new(new_emitter_human_readable()));
-    _ = risl::parser::lexer::lex(&context, program).collect::<Vec<_>>();
+fn check_options(args: &Args) -> CheckOptions {
+    CheckOptions {
+        type_check: args.check,
+    }
+}
+
+fn run_options(args: &Args) -> RunOptions {
+    RunOptions {
+        backend: args.backend.unwrap_or_default(),
+        trace: args.trace,
+        gc_stress: args.gc_stress,
+        gc_stats: args.gc_stats,
+    }
+}
+
+fn exit_code(result: Result<(), RunError>) -> Result<(), exitcode::ExitCode> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(RunError::Check) => Err(exitcode::DATAERR),
+        Err(RunError::Runtime) => Err(exitcode::SOFTWARE),
+    }
+}
+
+/// Runs a compiled module, reporting the warnings of its static checks first, and showing the
+/// locations of the errors in its source if known.
+fn run_module(
+    module: &Module,
+    source_map: Option<SourceMap>,
+    args: &Args,
+) -> Result<(), exitcode::ExitCode> {
+    let mut diag_ctx = DiagContext::new(new_emitter_human_readable());
+    if let Some(source_map) = source_map {
+        diag_ctx = diag_ctx.with_source_map(source_map);
+    }
+    let context = ParseContext::new(diag_ctx);
+    for warning in &module.warnings {
+        context.diag_ctx().emit(warning.clone());
+    }
+    let output = &mut io::stdout().lock();
+    exit_code(risl::run_module(
+        &context,
+        module,
+        run_options(args),
+        output,
+    ))
+}
+
+fn run(program: &str, source_map: SourceMap, args: &Args) -> Result<(), exitcode::ExitCode> {
+    let context = ParseContext::new(
+        DiagContext::new(new_emitter_human_readable()).with_source_map(source_map),
+    );
+    let options = check_options(args);
+    let Some(emit) = args.emit else {
+        let output = &mut io::stdout().lock();
+        let result = risl::run_with_options(&context, program, options, run_options(args), output);
+        return exit_code(result);
+    };
+    if emit == Emit::Bytecode {
+        let Some(module) = risl::compile(&context, program, options) else {
+            return Err(exitcode::DATAERR);
+        };
+        print!("{}", risl::vm::disassembler::disassemble(&module, program));
+        return Ok(());
+    }
+    let ast = risl::parser::parse(&context, program);
+    let format = match emit {
+        Emit::Ast => printer::Format::Tree,
+        Emit::AstSexp => printer::Format::Sexp,
+        Emit::Bytecode => unreachable!("the bytecode is printed above"),
+    };
+    print!("{}", printer::print(&ast, format));
+    if context.diag_ctx().has_errors() {
+        return Err(exitcode::DATAERR);
+    }
     Ok(())
 }
 
-// private static void run(String source) {
-//     Scanner scanner = new Scanner(source);
-//     List<Token> tokens = scanner.scanTokens();
-//
-//     // For now, just print the tokens.
-//     for (Token token : tokens) {
-//       System.out.println(token);
-//     }
-//   }
+fn explain(code: &str) -> Result<(), exitcode::ExitCode> {
+    match risl::error_codes::explain(code) {
+        Some(explanation) => {
+            print!("{explanation}");
+            Ok(())
+        }
+        None => {
+            eprintln!("Error: '{code}' is not a valid error code");
+            Err(exitcode::USAGE)
+        }
+    }
+}
 
-const USAGE: &'static str = "
+const USAGE: &str = "
 Usage:
-  risl [-hiv] [ --command=<command> | <file> | --stdin ] [ [--] <arguments>... ]
+  risl [-hiv] [--check] [--vm | --tree-walk] [--trace] [--gc-stress] [--gc-stats] [--emit=<kind>] [ --command=<command> | <file> | --stdin ] [ [--] <arguments>... ]
+  risl compile [--check] <file> [-o <output>]
+  risl --explain <code>
+
+Commands:
+  compile                   Compile <file> to a `.rislc` file, which is run instead of <file>
+                            until <file> changes. A `.rislc` file can also be run directly.
 
 Options:
   -h --help                 Show this screen.
   -v --version              Show version.
-  -i --interactive          Run interactivelly.
-  -s --stdin                Read program from the standard input.
+  -i --interactive          Run the lines read from the standard input interactively, keeping
+                            the variables and items of the previous ones. The lines run with
+                            the tree-walking interpreter.
+  -s --stdin                Read the whole program from the standard input.
   -c --command <command>    Read program from the <command> string.
+  --explain <code>          Show the detailed explanation of an error code.
+  --check                   Check the types of the program, as if it had the `#![type_check]`
+                            attribute.
+  --vm                      Run the program compiled to bytecode on the virtual machine
+                            (default).
+  --tree-walk               Run the program with the tree-walking interpreter. It has no cycle
+                            collector: the values forming cycles, like a closure stored in a
+                            variable it captures, are only freed at the end of the run.
+  --trace                   Print the stack before each instruction run by the virtual machine
+                            to the standard error, cannot be used with --tree-walk.
+  --gc-stress               Collect the garbage after every allocation, cannot be used with
+                            --tree-walk.
+  --gc-stats                Print the allocations and the collections of the virtual machine to
+                            the standard error at the end of the run, cannot be used with
+                            --tree-walk.
+  -o --output <output>      Write the module compiled by `compile` to <output> instead of the
+                            <file> with the `.rislc` extension.
+  --emit <kind>             Print the program instead of running it, <kind> being one of:
+                              ast       the AST as an indented tree,
+                              ast-sexp  the AST as an S-expression,
+                              bytecode  the bytecode of the virtual machine.
 ";
 
 fn try_main() -> Result<(), exitcode::ExitCode> {
@@ -96,27 +325,49 @@ fn try_main() -> Result<(), exitcode::ExitCode> {
             exitcode::USAGE
         }
     })?;
-    println!("{:?}", args);
+    if let Some(code) = &args.explain {
+        return explain(code);
+    }
 
     if let Some(file) = &args.input_file {
-        run_file(&file)?;
+        risl::ice::set_current_file(file);
+        if args.compile {
+            return compile_file(file, &args);
+        }
+        run_file(file, &args)?;
     } else if let Some(command) = &args.input_command {
-        run(&command)?;
+        risl::ice::set_current_file("<command>");
+        run(
+            command,
+            SourceMap::new("<command>", command.as_str()),
+            &args,
+        )?;
     } else if args.input_is_stdin {
-        run_from_stdin(IsInteractive::No)?;
+        risl::ice::set_current_file("<stdin>");
+        run_from_stdin(&args)?;
     }
 
     if args.interactive {
-        run_from_stdin(IsInteractive::Yes)?;
+        risl::ice::set_current_file(INTERACTIVE);
+        run_interactive(&args)?;
     }
 
     Ok(())
 }
 
 fn main() {
-    let exit_code = match try_main() {
-        Ok(_) => exitcode::OK,
-        Err(exit_code) => exit_code,
+    risl::ice::install_panic_hook();
+    // The compiled modules and the interactive sessions run on the thread of the caller of the
+    // library
+    let main = thread::Builder::new()
+        .stack_size(risl::worker::STACK_SIZE)
+        .spawn(try_main)
+        .expect("cannot spawn the main thread of the interpreter");
+    let exit_code = match main.join() {
+        Ok(Ok(())) => exitcode::OK,
+        Ok(Err(exit_code)) => exit_code,
+        // The panic hook reported the internal error
+        Err(_) => exitcode::SOFTWARE,
     };
     std::process::exit(exit_code);
 }
@@ -1,5 +1,30 @@
 use crate::cli::error::Error;
 use crate::cli::utils::str_vec;
+use crate::Backend;
+
+/// The intermediate representations of a program that can be printed instead of running it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Emit {
+    /// The AST as an indented tree.
+    Ast,
+    /// The AST as an S-expression.
+    AstSexp,
+    /// The bytecode of the virtual machine.
+    Bytecode,
+}
+
+impl std::str::FromStr for Emit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ast" => Ok(Emit::Ast),
+            "ast-sexp" => Ok(Emit::AstSexp),
+            "bytecode" => Ok(Emit::Bytecode),
+            _ => Err(()),
+        }
+    }
+}
 
 #[derive(Debug, Default, PartialEq)]
 pub struct Args {
@@ -9,6 +34,17 @@ pub struct Args {
     pub interactive: bool,
     pub help: bool,
     pub version: bool,
+    pub explain: Option<String>,
+    pub emit: Option<Emit>,
+    pub check: bool,
+    /// The backend chosen with `--vm` or `--tree-walk`, if any.
+    pub backend: Option<Backend>,
+    pub trace: bool,
+    pub gc_stress: bool,
+    pub gc_stats: bool,
+    /// Compiles the input file to a `.rislc` file instead of running it.
+    pub compile: bool,
+    pub output_file: Option<String>,
     pub script_arguments: Vec<String>,
 }
 
@@ -72,11 +108,42 @@ impl Args {
                         end_of_arg_list = true;
                     }
                     "-v" | "--version" => result.version = true,
+                    "--check" => result.check = true,
+                    "--vm" => result.backend = Some(Backend::Vm),
+                    "--tree-walk" => result.backend = Some(Backend::TreeWalk),
+                    "--trace" => result.trace = true,
+                    "--gc-stress" => result.gc_stress = true,
+                    "--gc-stats" => result.gc_stats = true,
+                    "-o" | "--output" => {
+                        if let Some(file) = args_iter.next() {
+                            result.output_file = Some(file.into());
+                        } else {
+                            return Err(Error::MissingArgValue(String::from("--output")));
+                        }
+                    }
+                    "--explain" => {
+                        if let Some(code) = args_iter.next() {
+                            result.explain = Some(code.into());
+                        } else {
+                            return Err(Error::MissingArgValue(String::from("--explain")));
+                        }
+                    }
+                    "--emit" => match args_iter.next() {
+                        Some(value) => result.emit = Some(Self::parse_emit(value.into())?),
+                        None => return Err(Error::MissingArgValue(String::from("--emit"))),
+                    },
+                    _ if arg.starts_with("--emit=") => {
+                        let value = String::from(&arg["--emit=".len()..]);
+                        result.emit = Some(Self::parse_emit(value)?);
+                    }
                     _ => unexpected_args.push(arg),
                 }
+            } else if arg == "compile" && !result.compile && result.input_file.is_none() {
+                result.compile = true;
             } else if result.input_file.is_none() {
                 result.input_file = Some(arg);
-                end_of_arg_list = true;
+                // The options of `compile` can follow the file, which has no arguments
+                end_of_arg_list = !result.compile;
             } else {
                 result.script_arguments.push(arg);
                 end_of_arg_list = true;
@@ -88,6 +155,12 @@ impl Args {
         Ok(result)
     }
 
+    fn parse_emit(value: String) -> Result<Emit, Error> {
+        value
+            .parse()
+            .map_err(|_| Error::InvalidArgValue(String::from("--emit"), value))
+    }
+
     fn validate_no_input_args_conflict(&self) -> Result<(), Error> {
         // Ensure there are not conflict between <input>, --command <command>
         // and --stdin
@@ -107,8 +180,61 @@ impl Args {
         Ok(())
     }
 
+    fn validate_compile(&self) -> Result<(), Error> {
+        if !self.compile {
+            if self.output_file.is_some() {
+                return Err(Error::RequiredArg(
+                    String::from("--output"),
+                    String::from("compile"),
+                ));
+            }
+            return Ok(());
+        }
+        if self.input_command.is_some() {
+            return Err(Error::ConflictingArgs(str_vec![
+                "compile",
+                "--command=<command>"
+            ]));
+        }
+        if self.input_is_stdin {
+            return Err(Error::ConflictingArgs(str_vec!["compile", "--stdin"]));
+        }
+        if self.input_file.is_none() {
+            return Err(Error::MissingArgValue(String::from("compile")));
+        }
+        if !self.script_arguments.is_empty() {
+            return Err(Error::UnexpectedArgs(self.script_arguments.clone()));
+        }
+        Ok(())
+    }
+
+    fn validate_backend(&self) -> Result<(), Error> {
+        // Only the virtual machine has a collector and runs instructions to trace
+        if self.backend != Some(Backend::TreeWalk) {
+            return Ok(());
+        }
+        let vm_only = [
+            ("--trace", self.trace),
+            ("--gc-stress", self.gc_stress),
+            ("--gc-stats", self.gc_stats),
+        ];
+        let mut conflicting_args = str_vec!["--tree-walk"];
+        conflicting_args.extend(
+            vm_only
+                .iter()
+                .filter(|(_, used)| *used)
+                .map(|(arg, _)| String::from(*arg)),
+        );
+        if conflicting_args.len() > 1 {
+            return Err(Error::ConflictingArgs(conflicting_args));
+        }
+        Ok(())
+    }
+
     fn validate(&self) -> Result<(), Error> {
         self.validate_no_input_args_conflict()?;
+        self.validate_compile()?;
+        self.validate_backend()?;
         Ok(())
     }
 }
@@ -132,6 +258,15 @@ mod tests {
                     interactive: true,
                     help: true,
                     version: true,
+                    explain: None,
+                    emit: None,
+                    check: false,
+                    backend: None,
+                    trace: false,
+                    gc_stress: false,
+                    gc_stats: false,
+                    compile: false,
+                    output_file: None,
                     script_arguments: vec![],
                 })
             }
@@ -163,6 +298,15 @@ mod tests {
                     interactive: true,
                     help: true,
                     version: true,
+                    explain: None,
+                    emit: None,
+                    check: false,
+                    backend: None,
+                    trace: false,
+                    gc_stress: false,
+                    gc_stats: false,
+                    compile: false,
+                    output_file: None,
                     script_arguments: vec![],
                 })
             }
@@ -195,6 +339,15 @@ mod tests {
                     interactive: true,
                     help: true,
                     version: true,
+                    explain: None,
+                    emit: None,
+                    check: false,
+                    backend: None,
+                    trace: false,
+                    gc_stress: false,
+                    gc_stats: false,
+                    compile: false,
+                    output_file: None,
                     script_arguments: vec![],
                 })
             }
@@ -258,6 +411,15 @@ mod tests {
                     interactive: true,
                     help: false,
                     version: true,
+                    explain: None,
+                    emit: None,
+                    check: false,
+                    backend: None,
+                    trace: false,
+                    gc_stress: false,
+                    gc_stats: false,
+                    compile: false,
+                    output_file: None,
                     script_arguments: str_vec!["-c", "hello"],
                 })
             );
@@ -275,6 +437,15 @@ mod tests {
                     interactive: true,
                     help: false,
                     version: true,
+                    explain: None,
+                    emit: None,
+                    check: false,
+                    backend: None,
+                    trace: false,
+                    gc_stress: false,
+                    gc_stats: false,
+                    compile: false,
+                    output_file: None,
                     script_arguments: str_vec!["-c", "hello"],
                 })
             );
@@ -293,6 +464,15 @@ mod tests {
                     interactive: true,
                     help: false,
                     version: true,
+                    explain: None,
+                    emit: None,
+                    check: false,
+                    backend: None,
+                    trace: false,
+                    gc_stress: false,
+                    gc_stats: false,
+                    compile: false,
+                    output_file: None,
                     script_arguments: str_vec!["-s", "-u", "hello"],
                 })
             );
@@ -312,6 +492,15 @@ mod tests {
                     interactive: true,
                     help: false,
                     version: true,
+                    explain: None,
+                    emit: None,
+                    check: false,
+                    backend: None,
+                    trace: false,
+                    gc_stress: false,
+                    gc_stats: false,
+                    compile: false,
+                    output_file: None,
                     script_arguments: str_vec!["-s", "-u", "hello"],
                 })
             );
@@ -329,6 +518,15 @@ mod tests {
                     interactive: false,
                     help: false,
                     version: false,
+                    explain: None,
+                    emit: None,
+                    check: false,
+                    backend: None,
+                    trace: false,
+                    gc_stress: false,
+                    gc_stats: false,
+                    compile: false,
+                    output_file: None,
                     script_arguments: str_vec!["hello", "-h"],
                 })
             );
@@ -346,6 +544,15 @@ mod tests {
                     interactive: false,
                     help: false,
                     version: false,
+                    explain: None,
+                    emit: None,
+                    check: false,
+                    backend: None,
+                    trace: false,
+                    gc_stress: false,
+                    gc_stats: false,
+                    compile: false,
+                    output_file: None,
                     script_arguments: str_vec!["hello", "-h"],
                 })
             );
@@ -358,6 +565,196 @@ mod tests {
         assert_eq!(args, Err(Error::MissingArgValue(String::from("--command"))));
     }
 
+    #[test]
+    fn explain() {
+        let args = Args::parse_from(["risl", "--explain", "E0001"]);
+        assert_eq!(
+            args,
+            Ok(Args {
+                input_file: None,
+                input_command: None,
+                input_is_stdin: false,
+                interactive: false,
+                help: false,
+                version: false,
+                explain: Some(String::from("E0001")),
+                emit: None,
+                check: false,
+                backend: None,
+                trace: false,
+                gc_stress: false,
+                gc_stats: false,
+                compile: false,
+                output_file: None,
+                script_arguments: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn missing_explain_code() {
+        let args = Args::parse_from(["risl", "--explain"]);
+        assert_eq!(args, Err(Error::MissingArgValue(String::from("--explain"))));
+    }
+
+    #[test]
+    fn emit() {
+        let args = Args::parse_from(["risl", "--emit=ast", "--emit", "ast-sexp", "file"]);
+        assert_eq!(
+            args,
+            Ok(Args {
+                input_file: Some(String::from("file")),
+                input_command: None,
+                input_is_stdin: false,
+                interactive: false,
+                help: false,
+                version: false,
+                explain: None,
+                emit: Some(Emit::AstSexp),
+                check: false,
+                backend: None,
+                trace: false,
+                gc_stress: false,
+                gc_stats: false,
+                compile: false,
+                output_file: None,
+                script_arguments: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn check() {
+        let args = Args::parse_from(["risl", "--check", "file"]);
+        assert_eq!(
+            args,
+            Ok(Args {
+                input_file: Some(String::from("file")),
+                input_command: None,
+                input_is_stdin: false,
+                interactive: false,
+                help: false,
+                version: false,
+                explain: None,
+                emit: None,
+                check: true,
+                backend: None,
+                trace: false,
+                gc_stress: false,
+                gc_stats: false,
+                compile: false,
+                output_file: None,
+                script_arguments: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn backend() {
+        let args = Args::parse_from(["risl", "--vm", "file"]);
+        assert_eq!(args.map(|args| args.backend), Ok(Some(Backend::Vm)));
+        let args = Args::parse_from(["risl", "--vm", "--tree-walk", "file"]);
+        assert_eq!(args.map(|args| args.backend), Ok(Some(Backend::TreeWalk)));
+        let args = Args::parse_from(["risl", "--trace", "--emit=bytecode", "file"]);
+        assert_eq!(
+            args.map(|args| (args.trace, args.emit)),
+            Ok((true, Some(Emit::Bytecode)))
+        );
+        let args = Args::parse_from(["risl", "--gc-stress", "--gc-stats", "file"]);
+        assert_eq!(
+            args.map(|args| (args.gc_stress, args.gc_stats)),
+            Ok((true, true))
+        );
+        // The tree-walking interpreter has no collector
+        let args = Args::parse_from(["risl", "--tree-walk", "--gc-stress", "--gc-stats", "file"]);
+        assert_eq!(
+            args,
+            Err(Error::ConflictingArgs(str_vec![
+                "--tree-walk",
+                "--gc-stress",
+                "--gc-stats"
+            ]))
+        );
+        let args = Args::parse_from(["risl", "--tree-walk", "--trace", "file"]);
+        assert_eq!(
+            args,
+            Err(Error::ConflictingArgs(str_vec!["--tree-walk", "--trace"]))
+        );
+    }
+
+    #[test]
+    fn compile() {
+        let args = Args::parse_from(["risl", "compile", "--check", "file.risl", "-o", "out"]);
+        assert_eq!(
+            args,
+            Ok(Args {
+                input_file: Some(String::from("file.risl")),
+                input_command: None,
+                input_is_stdin: false,
+                interactive: false,
+                help: false,
+                version: false,
+                explain: None,
+                emit: None,
+                check: true,
+                backend: None,
+                trace: false,
+                gc_stress: false,
+                gc_stats: false,
+                compile: true,
+                output_file: Some(String::from("out")),
+                script_arguments: vec![],
+            })
+        );
+        // Only the first argument is the command
+        let args = Args::parse_from(["risl", "compile", "compile"]);
+        assert_eq!(
+            args.map(|args| (args.compile, args.input_file)),
+            Ok((true, Some(String::from("compile"))))
+        );
+        let args = Args::parse_from(["risl", "compile"]);
+        assert_eq!(args, Err(Error::MissingArgValue(String::from("compile"))));
+        let args = Args::parse_from(["risl", "compile", "-c", "hello"]);
+        assert_eq!(
+            args,
+            Err(Error::ConflictingArgs(str_vec![
+                "compile",
+                "--command=<command>"
+            ]))
+        );
+        let args = Args::parse_from(["risl", "compile", "file", "other"]);
+        assert_eq!(args, Err(Error::UnexpectedArgs(str_vec!["other"])));
+        // The output is only written by `compile`
+        let args = Args::parse_from(["risl", "-o", "out", "file"]);
+        assert_eq!(
+            args,
+            Err(Error::RequiredArg(
+                String::from("--output"),
+                String::from("compile")
+            ))
+        );
+        // After the file, `-o` is an argument of the script
+        let args = Args::parse_from(["risl", "file", "-o"]);
+        assert_eq!(
+            args.map(|args| (args.output_file, args.script_arguments)),
+            Ok((None, str_vec!["-o"]))
+        );
+    }
+
+    #[test]
+    fn invalid_emit() {
+        let args = Args::parse_from(["risl", "--emit=tokens"]);
+        assert_eq!(
+            args,
+            Err(Error::InvalidArgValue(
+                String::from("--emit"),
+                String::from("tokens")
+            ))
+        );
+        let args = Args::parse_from(["risl", "--emit"]);
+        assert_eq!(args, Err(Error::MissingArgValue(String::from("--emit"))));
+    }
+
     mod conflicts {
         use super::*;
         #[test]
@@ -369,6 +766,15 @@ mod tests {
                 interactive: false,
                 help: false,
                 version: false,
+                explain: None,
+                emit: None,
+                check: false,
+                backend: None,
+                trace: false,
+                gc_stress: false,
+                gc_stats: false,
+                compile: false,
+                output_file: None,
                 script_arguments: vec![],
             };
             assert_eq!(
@@ -385,6 +791,15 @@ mod tests {
                 interactive: false,
                 help: false,
                 version: false,
+                explain: None,
+                emit: None,
+                check: false,
+                backend: None,
+                trace: false,
+                gc_stress: false,
+                gc_stats: false,
+                compile: false,
+                output_file: None,
                 script_arguments: vec![],
             };
             assert_eq!(
@@ -404,6 +819,15 @@ mod tests {
                 interactive: false,
                 help: false,
                 version: false,
+                explain: None,
+                emit: None,
+                check: false,
+                backend: None,
+                trace: false,
+                gc_stress: false,
+                gc_stats: false,
+                compile: false,
+                output_file: None,
                 script_arguments: vec![],
             };
             assert_eq!(
@@ -423,6 +847,15 @@ mod tests {
                 interactive: false,
                 help: false,
                 version: false,
+                explain: None,
+                emit: None,
+                check: false,
+                backend: None,
+                trace: false,
+                gc_stress: false,
+                gc_stats: false,
+                compile: false,
+                output_file: None,
                 script_arguments: vec![],
             };
             assert_eq!(
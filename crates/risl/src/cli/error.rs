@@ -2,13 +2,17 @@
 pub enum Error {
     HelpRequested,
     MissingArgValue(String),
+    /// An argument was given an unsupported value: the argument and its value.
+    InvalidArgValue(String, String),
     UnexpectedArgs(Vec<String>),
+    /// An argument was given without another one it requires: the argument and the required one.
+    RequiredArg(String, String),
     ConflictingArgs(Vec<String>),
 }
 
 impl Error {
-    fn format_unexpected_args(args: &Vec<String>, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        if args.len() == 0 {
+    fn format_unexpected_args(args: &[String], f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if args.is_empty() {
             panic!("Error::UnexpectedArgs must contain at least one argument");
         } else if args.len() == 1 {
             write!(f, "unexpected argument '{}' found", args[0])
@@ -17,10 +21,7 @@ impl Error {
         }
     }
 
-    fn format_conflicting_args(
-        args: &Vec<String>,
-        f: &mut std::fmt::Formatter,
-    ) -> std::fmt::Result {
+    fn format_conflicting_args(args: &[String], f: &mut std::fmt::Formatter) -> std::fmt::Result {
         if args.len() < 2 {
             panic!("Error::ConflictingArgs must contain at least two arguments");
         } else if args.len() == 2 {
@@ -43,8 +44,15 @@ impl Error {
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Error::UnexpectedArgs(args) => Self::format_unexpected_args(&args, f),
-            Error::ConflictingArgs(args) => Self::format_conflicting_args(&args, f),
+            Error::UnexpectedArgs(args) => Self::format_unexpected_args(args, f),
+            Error::ConflictingArgs(args) => Self::format_conflicting_args(args, f),
+            Error::MissingArgValue(arg) => write!(f, "a value is required for '{arg}'"),
+            Error::InvalidArgValue(arg, value) => {
+                write!(f, "invalid value '{value}' for '{arg}'")
+            }
+            Error::RequiredArg(arg, required) => {
+                write!(f, "the argument '{arg}' can only be used with '{required}'")
+            }
             _ => Ok(()),
         }
     }
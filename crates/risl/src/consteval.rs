@@ -0,0 +1,1130 @@
+//! Evaluation of the constants of the program at compile time, and folding of the constant
+//! expressions of the ordinary code.
+//!
+//! The evaluator walks the AST of the `const` items, computing integers with the exact semantics
+//! of their type: an arithmetic operation overflowing the range of its type, a division by zero
+//! or an out of bounds index is reported as an error instead of producing a value. Constants can
+//! use literals, other constants, arithmetic and logical operators, string concatenation, arrays,
+//! tuples, casts, blocks, `if`, `while` and `loop`, and calls to `const fn`s, whose bodies are
+//! restricted to the same subset.
+//!
+//! An integer literal without suffix takes the type expected by its context: the declared type of
+//! the constant, the type of the other operand of a binary operation (`x + 1`) or of a parameter
+//! of a `const fn`, and `i64` when nothing gives it a type.
+//!
+//! Once the constants are known, the expressions of the ordinary code made only of literals and
+//! constants, like `MAX_LEN * 2`, are replaced by their value so that they are not computed at run
+//! time. Folding reports the divisions by zero and the overflows, which would happen at run time
+//! too: the literals without suffix the folding gives no type to are `i64` values at run time as
+//! well. Only in the body of a `const fn`, which constants may call with other types, the overflows
+//! of the literals given the default type are left to the run time. Folding otherwise leaves the
+//! expressions it cannot evaluate untouched.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::error_codes::{self, ErrorCode};
+use crate::int::{self, in_range, int_op, wrap, IntError, IntTy};
+use crate::parser::ast::*;
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::{Diagnostic, Level};
+use crate::parser::lexer::Span;
+use crate::parser::mut_visit::{self, VisitorMut};
+use crate::parser::visit::{self, Visitor};
+use crate::resolve::{Res, Resolutions, BUILTIN_VARIANTS};
+
+/// The maximum number of loop iterations and calls evaluating a constant.
+const STEP_LIMIT: usize = 1 << 20;
+
+/// The maximum depth of the calls of `const fn`s evaluating a constant.
+const CALL_DEPTH_LIMIT: usize = 128;
+
+/// The value of a constant.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ConstValue {
+    /// An integer of the given type. The values of type `u128` are stored as their bits.
+    Int(i128, IntTy),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Nil,
+    Tuple(Vec<ConstValue>),
+    Array(Vec<ConstValue>),
+}
+
+impl ConstValue {
+    fn unit() -> ConstValue {
+        ConstValue::Tuple(vec![])
+    }
+
+    /// Returns the name of the type of the value, for diagnostics.
+    fn type_name(&self) -> &'static str {
+        match self {
+            ConstValue::Int(_, ty) => ty.name(),
+            ConstValue::Float(_) => "f64",
+            ConstValue::Bool(_) => "bool",
+            ConstValue::Str(_) => "str",
+            ConstValue::Nil => "nil",
+            ConstValue::Tuple(values) if values.is_empty() => "()",
+            ConstValue::Tuple(_) => "tuple",
+            ConstValue::Array(_) => "array",
+        }
+    }
+}
+
+impl fmt::Display for ConstValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn list(f: &mut fmt::Formatter, values: &[ConstValue]) -> fmt::Result {
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{value}")?;
+            }
+            Ok(())
+        }
+        match self {
+            ConstValue::Int(value, ty) => write!(f, "{}", int::to_string(*value, *ty)),
+            ConstValue::Float(value) => write!(f, "{value:?}"),
+            ConstValue::Bool(value) => write!(f, "{value}"),
+            ConstValue::Str(value) => write!(f, "{value:?}"),
+            ConstValue::Nil => write!(f, "nil"),
+            ConstValue::Tuple(values) => {
+                write!(f, "(")?;
+                list(f, values)?;
+                if values.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            ConstValue::Array(values) => {
+                write!(f, "[")?;
+                list(f, values)?;
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// The values of the constants of a program.
+#[derive(Default, Debug)]
+pub struct Consts {
+    /// The value of each constant evaluated without error, by item id.
+    pub values: HashMap<NodeId, ConstValue>,
+}
+
+/// Evaluates the constants of the program, reporting errors through the context.
+pub fn eval(context: &ParseContext, program: &Program, resolutions: &Resolutions) -> Consts {
+    let mut consts = Consts::default();
+    eval_input(context, &[], program, resolutions, &mut consts);
+    consts
+}
+
+/// Evaluates the constants of an input of an interactive session like [`eval`], given the previous
+/// inputs and the values of their constants, which are extended with the ones of the input.
+pub fn eval_input(
+    context: &ParseContext,
+    previous: &[Program],
+    program: &Program,
+    resolutions: &Resolutions,
+    consts: &mut Consts,
+) {
+    let mut collector = ItemCollector::default();
+    for program in previous {
+        collector.visit_program(program);
+    }
+    // The constants of the previous inputs are already evaluated
+    collector.consts.clear();
+    collector.visit_program(program);
+    let mut evaluator = Evaluator::new(context, resolutions, Mode::Const);
+    evaluator.items = collector.items;
+    evaluator.states = std::mem::take(&mut consts.values)
+        .into_iter()
+        .map(|(id, value)| (id, ConstState::Done(value)))
+        .collect();
+    // The constants of the input can call the `const fn`s of the previous inputs
+    for program in previous.iter().chain([program]) {
+        evaluator.analyze(program);
+    }
+    for id in collector.consts {
+        evaluator.steps = 0;
+        let span = evaluator.items[&id].span;
+        let _ = evaluator.eval_const(id, span);
+    }
+    consts.values = evaluator
+        .states
+        .into_iter()
+        .filter_map(|(id, state)| match state {
+            ConstState::Done(value) => Some((id, value)),
+            ConstState::InProgress | ConstState::Failed => None,
+        })
+        .collect();
+}
+
+/// Replaces the constant expressions of the program outside of the constants by their value.
+pub fn fold(
+    context: &ParseContext,
+    program: &mut Program,
+    resolutions: &Resolutions,
+    consts: &Consts,
+) {
+    let mut evaluator = Evaluator::new(context, resolutions, Mode::Fold);
+    evaluator.states = consts
+        .values
+        .iter()
+        .map(|(id, value)| (*id, ConstState::Done(value.clone())))
+        .collect();
+    evaluator.analyze(program);
+    Folder(evaluator).visit_program(program);
+}
+
+/// Collects the items declared anywhere in the program, by id, and the constants in order.
+#[derive(Default)]
+struct ItemCollector<'a> {
+    items: HashMap<NodeId, &'a Item>,
+    consts: Vec<NodeId>,
+}
+
+impl<'a> Visitor<'a> for ItemCollector<'a> {
+    fn visit_item(&mut self, item: &'a Item) {
+        self.items.insert(item.id, item);
+        if let ItemKind::Const(_) = item.kind {
+            self.consts.push(item.id);
+        }
+        visit::walk_item(self, item);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Mode {
+    /// Evaluating the constants, reporting every error.
+    Const,
+    /// Folding the ordinary code, only reporting the errors which would happen at run time.
+    Fold,
+}
+
+enum ConstState {
+    InProgress,
+    Done(ConstValue),
+    Failed,
+}
+
+/// Why the evaluation of an expression did not produce a value.
+enum Interrupt {
+    Return(ConstValue),
+    /// Exits the loop with the given expression id with the value.
+    Break(NodeId, ConstValue),
+    /// Continues the loop with the given expression id.
+    Continue(NodeId),
+    /// An error, which has already been reported if needed.
+    Error,
+}
+
+type EvalResult = Result<ConstValue, Interrupt>;
+
+/// Handles the result of an iteration of the loop with the given expression, returning the value
+/// of a `break` exiting it.
+fn exit(expr: &Expr, result: EvalResult) -> Result<Option<ConstValue>, Interrupt> {
+    match result {
+        Ok(_) => Ok(None),
+        Err(Interrupt::Continue(target)) if target == expr.id => Ok(None),
+        Err(Interrupt::Break(target, value)) if target == expr.id => Ok(Some(value)),
+        Err(interrupt) => Err(interrupt),
+    }
+}
+
+struct Evaluator<'a> {
+    context: &'a ParseContext,
+    resolutions: &'a Resolutions,
+    mode: Mode,
+    items: HashMap<NodeId, &'a Item>,
+    states: HashMap<NodeId, ConstState>,
+    /// The variables of the constant and `const fn` calls being evaluated, by slot.
+    frames: Vec<Vec<Option<ConstValue>>>,
+    steps: usize,
+    /// Whether an integer literal was given the default type `i64` for lack of a better one.
+    defaulted: bool,
+    /// Whether the folded code is the body of a `const fn`.
+    in_const_fn: bool,
+    /// The integer type of the expressions whose type can be told without evaluating them, by id.
+    hints: HashMap<NodeId, IntTy>,
+    /// The expressions only made of literals, constants and operators.
+    constants: HashSet<NodeId>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(context: &'a ParseContext, resolutions: &'a Resolutions, mode: Mode) -> Self {
+        Evaluator {
+            context,
+            resolutions,
+            mode,
+            items: HashMap::new(),
+            states: HashMap::new(),
+            frames: vec![],
+            steps: 0,
+            defaulted: false,
+            in_const_fn: false,
+            hints: HashMap::new(),
+            constants: HashSet::new(),
+        }
+    }
+
+    fn error(&self, code: ErrorCode, span: Span, message: String) -> Interrupt {
+        let report = match self.mode {
+            Mode::Const => true,
+            Mode::Fold => {
+                code == error_codes::E0081
+                    || (code == error_codes::E0080 && !(self.defaulted && self.in_const_fn))
+            }
+        };
+        if report {
+            self.context
+                .diag_ctx()
+                .emit(Diagnostic::spanned(Level::Error, span, message).with_code(code));
+        }
+        Interrupt::Error
+    }
+
+    fn unsupported(&self, span: Span, what: &str) -> Interrupt {
+        let message = format!("{what} are not supported in constants");
+        self.error(error_codes::E0082, span, message)
+    }
+
+    fn step(&mut self, span: Span) -> Result<(), Interrupt> {
+        self.steps += 1;
+        if self.steps > STEP_LIMIT {
+            let message = format!("constant evaluation exceeded the limit of {STEP_LIMIT} steps");
+            return Err(self.error(error_codes::E0083, span, message));
+        }
+        Ok(())
+    }
+
+    // Types
+
+    /// Returns the integer type named by the type, or of the elements of the array type.
+    fn int_ty(&self, ty: Option<&Type>) -> Option<IntTy> {
+        match &ty?.kind {
+            TypeKind::Path(..) => match self.resolutions.names.get(&ty?.id) {
+                Some(Res::Builtin(name)) => IntTy::from_name(name),
+                _ => None,
+            },
+            TypeKind::Array(elem) | TypeKind::Ref(_, elem) => self.int_ty(Some(elem)),
+            _ => None,
+        }
+    }
+
+    /// Computes the hints and the constness of the expressions of the program, once its items,
+    /// or the values of its constants, are known. Each expression is computed from its operands,
+    /// which are computed first.
+    fn analyze(&mut self, program: &Program) {
+        struct Analyzer<'e, 'a>(&'e mut Evaluator<'a>);
+
+        impl Visitor<'_> for Analyzer<'_, '_> {
+            fn visit_expr(&mut self, expr: &Expr) {
+                visit::walk_expr(self, expr);
+                if let Some(ty) = self.0.compute_hint(expr) {
+                    self.0.hints.insert(expr.id, ty);
+                }
+                if self.0.compute_constant(expr) {
+                    self.0.constants.insert(expr.id);
+                }
+            }
+        }
+
+        Analyzer(self).visit_program(program);
+    }
+
+    /// Returns the integer type of the expression when it can be told without evaluating it, to
+    /// give a type to the literals without suffix around it.
+    fn hint(&self, expr: &Expr) -> Option<IntTy> {
+        self.hints.get(&expr.id).copied()
+    }
+
+    /// Returns true if the expression is only made of literals, constants and operators.
+    fn is_constant(&self, expr: &Expr) -> bool {
+        self.constants.contains(&expr.id)
+    }
+
+    fn compute_hint(&self, expr: &Expr) -> Option<IntTy> {
+        match &expr.kind {
+            ExprKind::Lit(Lit::Int(lit)) => lit.suffix.as_deref().and_then(IntTy::from_name),
+            ExprKind::Cast(_, ty) => self.int_ty(Some(ty)),
+            ExprKind::Path(path) => match self.resolutions.names.get(&expr.id)? {
+                Res::Item(id) => match self.items.get(id).map(|item| &item.kind) {
+                    Some(ItemKind::Const(decl)) => self.int_ty(Some(&decl.ty)),
+                    _ => match self.states.get(id) {
+                        Some(ConstState::Done(ConstValue::Int(_, ty))) => Some(*ty),
+                        _ => None,
+                    },
+                },
+                Res::Builtin(name) => int_const(name, path).map(|(_, ty)| ty),
+                _ => None,
+            },
+            ExprKind::Paren(operand) | ExprKind::Unary(UnOp::Neg | UnOp::Not, operand) => {
+                self.hint(operand)
+            }
+            ExprKind::Binary(op, lhs, rhs) => match op {
+                BinOp::Shl | BinOp::Shr => self.hint(lhs),
+                _ if op.is_arithmetic() || op.is_bitwise() => {
+                    self.hint(lhs).or_else(|| self.hint(rhs))
+                }
+                _ => None,
+            },
+            ExprKind::Call(callee, _) => match self.resolutions.names.get(&callee.id)? {
+                Res::Item(id) => match &self.items.get(id)?.kind {
+                    ItemKind::Fn(decl) => self.int_ty(decl.ret.as_ref()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            ExprKind::Block(block) => self.hint(block.tail()?),
+            _ => None,
+        }
+    }
+
+    fn compute_constant(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Lit(_) => true,
+            ExprKind::Path(path) => match self.resolutions.names.get(&expr.id) {
+                Some(Res::Item(id)) => self.states.contains_key(id),
+                Some(Res::Builtin(name)) => int_const(name, path).is_some(),
+                _ => false,
+            },
+            ExprKind::Paren(operand) | ExprKind::Unary(UnOp::Neg | UnOp::Not, operand) => {
+                self.is_constant(operand)
+            }
+            ExprKind::Cast(operand, ty) => {
+                matches!(self.resolutions.names.get(&ty.id), Some(Res::Builtin(_)))
+                    && self.is_constant(operand)
+            }
+            ExprKind::Binary(_, lhs, rhs) => self.is_constant(lhs) && self.is_constant(rhs),
+            _ => false,
+        }
+    }
+
+    // Constants and calls
+
+    fn eval_const(&mut self, id: NodeId, span: Span) -> EvalResult {
+        match self.states.get(&id) {
+            Some(ConstState::Done(value)) => return Ok(value.clone()),
+            Some(ConstState::Failed) => return Err(Interrupt::Error),
+            Some(ConstState::InProgress) => {
+                let name = self.items.get(&id).and_then(|item| item.name());
+                let name = name.map_or("", |name| name.name.as_str());
+                let message = format!("cycle detected when evaluating constant `{name}`");
+                return Err(self.error(error_codes::E0083, span, message));
+            }
+            None => (),
+        }
+        let Some(ItemKind::Const(decl)) = self.items.get(&id).map(|item| &item.kind) else {
+            return Err(Interrupt::Error);
+        };
+        self.states.insert(id, ConstState::InProgress);
+        let frame_size = self.resolutions.frame_sizes.get(&id).copied().unwrap_or(0);
+        let frames = std::mem::replace(&mut self.frames, vec![vec![None; frame_size]]);
+        let result = match self.eval_expr(&decl.expr, self.int_ty(Some(&decl.ty))) {
+            Ok(value) | Err(Interrupt::Return(value)) => Ok(value),
+            Err(_) => Err(Interrupt::Error),
+        };
+        self.frames = frames;
+        let state = match &result {
+            Ok(value) => ConstState::Done(value.clone()),
+            Err(_) => ConstState::Failed,
+        };
+        self.states.insert(id, state);
+        result
+    }
+
+    fn eval_call(&mut self, expr: &Expr, callee: &Expr, args: &[Expr]) -> EvalResult {
+        let (fn_id, decl) = match self.resolutions.names.get(&callee.id) {
+            Some(Res::Item(id)) => match self.items.get(id).map(|item| &item.kind) {
+                Some(ItemKind::Fn(decl)) => (*id, decl),
+                _ => return Err(self.unsupported(callee.span, "calls to this item")),
+            },
+            Some(Res::Builtin(name)) if BUILTIN_VARIANTS.contains(name) => {
+                return Err(self.unsupported(callee.span, "struct and enum values"));
+            }
+            Some(Res::Builtin(name)) => {
+                let message = format!("cannot call non-const fn `{name}` in constants");
+                return Err(self.error(error_codes::E0082, callee.span, message));
+            }
+            Some(_) => return Err(self.unsupported(callee.span, "calls to closures")),
+            None => return Err(Interrupt::Error),
+        };
+        if !decl.is_const {
+            let message = format!("cannot call non-const fn `{}` in constants", decl.name.name);
+            return Err(self.error(error_codes::E0082, callee.span, message));
+        }
+        let Some(body) = &decl.body else {
+            return Err(Interrupt::Error);
+        };
+        if decl.self_param.is_some() || decl.params.len() != args.len() {
+            return Err(self.unsupported(expr.span, "calls with invalid arguments"));
+        }
+        let mut values = Vec::with_capacity(args.len());
+        for (param, arg) in decl.params.iter().zip(args) {
+            values.push(self.eval_expr(arg, self.int_ty(param.ty.as_ref()))?);
+        }
+        self.step(expr.span)?;
+        if self.frames.len() > CALL_DEPTH_LIMIT {
+            let message = format!(
+                "reached the recursion limit while calling `{}` in constants",
+                decl.name.name
+            );
+            return Err(self.error(error_codes::E0083, expr.span, message));
+        }
+        let frame_size = self
+            .resolutions
+            .frame_sizes
+            .get(&fn_id)
+            .copied()
+            .unwrap_or(0);
+        self.frames.push(vec![None; frame_size]);
+        let result = values
+            .into_iter()
+            .zip(&decl.params)
+            .try_for_each(|(value, param)| self.bind_pat(&param.pat, value))
+            .and_then(|()| self.eval_block(body, self.int_ty(decl.ret.as_ref())));
+        self.frames.pop();
+        match result {
+            Ok(value) | Err(Interrupt::Return(value)) => Ok(value),
+            Err(Interrupt::Break(..) | Interrupt::Continue(_)) => Err(Interrupt::Error),
+            Err(Interrupt::Error) => Err(Interrupt::Error),
+        }
+    }
+
+    // Variables
+
+    fn frame(&mut self) -> Option<&mut Vec<Option<ConstValue>>> {
+        self.frames.last_mut()
+    }
+
+    fn bind_pat(&mut self, pat: &Pattern, value: ConstValue) -> Result<(), Interrupt> {
+        match (&pat.kind, value) {
+            (PatKind::Wild, _) => Ok(()),
+            (PatKind::Ident(_, _, None), value) => {
+                if let Some(Res::Local(slot)) = self.resolutions.names.get(&pat.id) {
+                    if let Some(variable) = self.frame().and_then(|frame| frame.get_mut(*slot)) {
+                        *variable = Some(value);
+                        return Ok(());
+                    }
+                }
+                Err(Interrupt::Error)
+            }
+            (PatKind::Tuple(pats), ConstValue::Tuple(values)) if pats.len() == values.len() => {
+                for (pat, value) in pats.iter().zip(values) {
+                    self.bind_pat(pat, value)?;
+                }
+                Ok(())
+            }
+            _ => Err(self.unsupported(pat.span, "such patterns")),
+        }
+    }
+
+    fn variable(&mut self, expr: &Expr) -> Result<&mut Option<ConstValue>, Interrupt> {
+        match self.resolutions.names.get(&expr.id) {
+            Some(Res::Local(slot)) => {
+                let slot = *slot;
+                self.frame()
+                    .and_then(|frame| frame.get_mut(slot))
+                    .ok_or(Interrupt::Error)
+            }
+            Some(Res::Global(_) | Res::Upvalue(_)) => {
+                let message = String::from("attempt to use a non-constant value in a constant");
+                Err(self.error(error_codes::E0082, expr.span, message))
+            }
+            _ => Err(Interrupt::Error),
+        }
+    }
+
+    fn assign(&mut self, place: &Expr, value: ConstValue) -> Result<(), Interrupt> {
+        match &place.kind {
+            ExprKind::Path(_) => {
+                *self.variable(place)? = Some(value);
+                Ok(())
+            }
+            ExprKind::Index(array, index) if matches!(array.kind, ExprKind::Path(_)) => {
+                let index = self.eval_expr(index, Some(IntTy::Usize))?;
+                let message = match self.variable(array)? {
+                    Some(ConstValue::Array(values)) => match index_of(&index, values.len()) {
+                        Ok(i) => {
+                            values[i] = value;
+                            return Ok(());
+                        }
+                        Err(message) => message,
+                    },
+                    _ => return Err(self.unsupported(place.span, "such assignments")),
+                };
+                Err(self.error(error_codes::E0084, place.span, message))
+            }
+            _ => Err(self.unsupported(place.span, "such assignments")),
+        }
+    }
+
+    // Statements and expressions
+
+    fn eval_block(&mut self, block: &Block, expected: Option<IntTy>) -> EvalResult {
+        let tail = block.tail();
+        let stmts = &block.stmts[..block.stmts.len() - usize::from(tail.is_some())];
+        for stmt in stmts {
+            match &stmt.kind {
+                StmtKind::Let(local) => {
+                    if let Some(init) = &local.init {
+                        let value = self.eval_expr(init, self.int_ty(local.ty.as_ref()))?;
+                        self.bind_pat(&local.pat, value)?;
+                    }
+                }
+                StmtKind::Expr(expr) | StmtKind::Semi(expr) => {
+                    self.eval_expr(expr, None)?;
+                }
+                StmtKind::Item(_) | StmtKind::Empty => (),
+                StmtKind::Err => return Err(Interrupt::Error),
+            }
+        }
+        match tail {
+            Some(tail) => self.eval_expr(tail, expected),
+            None => Ok(ConstValue::unit()),
+        }
+    }
+
+    fn eval_bool(&mut self, expr: &Expr) -> Result<bool, Interrupt> {
+        match self.eval_expr(expr, None)? {
+            ConstValue::Bool(value) => Ok(value),
+            value => {
+                let message = format!("expected `bool`, found `{}`", value.type_name());
+                Err(self.error(error_codes::E0082, expr.span, message))
+            }
+        }
+    }
+
+    fn eval_expr(&mut self, expr: &Expr, expected: Option<IntTy>) -> EvalResult {
+        crate::ice::set_current_span(expr.span);
+        match &expr.kind {
+            ExprKind::Lit(lit) => self.eval_lit(lit, expr.span, expected),
+            ExprKind::Path(path) => match self.resolutions.names.get(&expr.id) {
+                Some(Res::Item(id)) if path.segments.len() == 1 => {
+                    match self.items.get(id).map(|item| &item.kind) {
+                        Some(ItemKind::Fn(_)) => Err(self.unsupported(expr.span, "functions")),
+                        Some(ItemKind::Const(_)) | None => self.eval_const(*id, expr.span),
+                        Some(_) => Err(self.unsupported(expr.span, "struct and enum values")),
+                    }
+                }
+                Some(Res::Local(_) | Res::Global(_) | Res::Upvalue(_)) => {
+                    match self.variable(expr)? {
+                        Some(value) => Ok(value.clone()),
+                        None => Err(Interrupt::Error),
+                    }
+                }
+                Some(Res::Builtin(name)) if BUILTIN_VARIANTS.contains(name) => {
+                    Err(self.unsupported(expr.span, "struct and enum values"))
+                }
+                Some(Res::Builtin(name)) => match int_const(name, path) {
+                    Some((value, ty)) => Ok(ConstValue::Int(value, ty)),
+                    None => Err(self.unsupported(expr.span, "such paths")),
+                },
+                Some(_) => Err(self.unsupported(expr.span, "such paths")),
+                None => Err(Interrupt::Error),
+            },
+            ExprKind::Unary(op, operand) => self.eval_unary(expr, *op, operand, expected),
+            ExprKind::AddrOf(..) => Err(self.unsupported(expr.span, "references")),
+            ExprKind::Binary(op, lhs, rhs) => self.eval_binary(expr, *op, lhs, rhs, expected),
+            ExprKind::Assign(place, value) => {
+                let value = self.eval_expr(value, self.hint(place))?;
+                self.assign(place, value)?;
+                Ok(ConstValue::unit())
+            }
+            ExprKind::AssignOp(op, place, value) => {
+                let current = self.eval_expr(place, self.hint(value))?;
+                let expected = match current {
+                    ConstValue::Int(_, ty) if !matches!(op, BinOp::Shl | BinOp::Shr) => Some(ty),
+                    _ => None,
+                };
+                let value = self.eval_expr(value, expected)?;
+                let value = self.binary_op(expr.span, *op, current, value)?;
+                self.assign(place, value)?;
+                Ok(ConstValue::unit())
+            }
+            ExprKind::Cast(operand, ty) => {
+                let value = self.eval_expr(operand, self.hint(operand))?;
+                let target = match self.resolutions.names.get(&ty.id) {
+                    Some(Res::Builtin(name)) => *name,
+                    _ => "_",
+                };
+                match cast(&value, target) {
+                    Some(value) => Ok(value),
+                    None => {
+                        let message =
+                            format!("cannot cast `{}` as `{}`", value.type_name(), target);
+                        Err(self.error(error_codes::E0082, expr.span, message))
+                    }
+                }
+            }
+            ExprKind::Call(callee, args) => self.eval_call(expr, callee, args),
+            ExprKind::MethodCall(..) => Err(self.unsupported(expr.span, "method calls")),
+            ExprKind::Field(receiver, name) => match self.eval_expr(receiver, None)? {
+                ConstValue::Tuple(mut values) => match name.name.parse::<usize>() {
+                    Ok(index) if index < values.len() => Ok(values.swap_remove(index)),
+                    _ => Err(self.unsupported(name.span, "such fields")),
+                },
+                _ => Err(self.unsupported(name.span, "field accesses")),
+            },
+            ExprKind::Index(array, index) => {
+                let array = self.eval_expr(array, None)?;
+                let index = self.eval_expr(index, Some(IntTy::Usize))?;
+                let ConstValue::Array(mut values) = array else {
+                    return Err(self.unsupported(expr.span, "such indexing operations"));
+                };
+                match index_of(&index, values.len()) {
+                    Ok(i) => Ok(values.swap_remove(i)),
+                    Err(message) => Err(self.error(error_codes::E0084, expr.span, message)),
+                }
+            }
+            ExprKind::Tuple(exprs) => Ok(ConstValue::Tuple(self.eval_exprs(exprs, None)?)),
+            ExprKind::Array(exprs) => Ok(ConstValue::Array(self.eval_exprs(exprs, expected)?)),
+            ExprKind::Struct(_) => Err(self.unsupported(expr.span, "struct literals")),
+            ExprKind::Paren(operand) => self.eval_expr(operand, expected),
+            ExprKind::Block(block) => self.eval_block(block, expected),
+            ExprKind::If(cond, then, otherwise) => {
+                if self.eval_bool(cond)? {
+                    self.eval_block(then, expected)
+                } else if let Some(otherwise) = otherwise {
+                    self.eval_expr(otherwise, expected)
+                } else {
+                    Ok(ConstValue::unit())
+                }
+            }
+            ExprKind::While(cond, body, _) => {
+                while self.eval_bool(cond)? {
+                    self.step(expr.span)?;
+                    if let Some(value) = exit(expr, self.eval_block(body, None))? {
+                        return Ok(value);
+                    }
+                }
+                Ok(ConstValue::unit())
+            }
+            ExprKind::Loop(body, _) => loop {
+                self.step(expr.span)?;
+                if let Some(value) = exit(expr, self.eval_block(body, None))? {
+                    return Ok(value);
+                }
+            },
+            ExprKind::For(..) => Err(self.unsupported(expr.span, "`for` loops")),
+            ExprKind::Match(..) => Err(self.unsupported(expr.span, "`match` expressions")),
+            ExprKind::Range(..) => Err(self.unsupported(expr.span, "ranges")),
+            ExprKind::Try(_) => Err(self.unsupported(expr.span, "the `?` operator")),
+            ExprKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval_expr(value, None)?,
+                    None => ConstValue::unit(),
+                };
+                Err(Interrupt::Return(value))
+            }
+            ExprKind::Break(_, value) => {
+                let value = match value {
+                    Some(value) => self.eval_expr(value, None)?,
+                    None => ConstValue::unit(),
+                };
+                Err(Interrupt::Break(self.loop_target(expr)?, value))
+            }
+            ExprKind::Continue(_) => Err(Interrupt::Continue(self.loop_target(expr)?)),
+            ExprKind::Closure(_) => Err(self.unsupported(expr.span, "closures")),
+            ExprKind::Err => Err(Interrupt::Error),
+        }
+    }
+
+    /// Returns the loop exited or continued by the `break` or `continue` expression.
+    fn loop_target(&self, expr: &Expr) -> Result<NodeId, Interrupt> {
+        let target = self.resolutions.loop_targets.get(&expr.id);
+        target.copied().ok_or(Interrupt::Error)
+    }
+
+    fn eval_exprs(
+        &mut self,
+        exprs: &[Expr],
+        expected: Option<IntTy>,
+    ) -> Result<Vec<ConstValue>, Interrupt> {
+        exprs
+            .iter()
+            .map(|expr| self.eval_expr(expr, expected))
+            .collect()
+    }
+
+    fn eval_lit(&mut self, lit: &Lit, span: Span, expected: Option<IntTy>) -> EvalResult {
+        Ok(match lit {
+            Lit::Int(lit) => {
+                let ty = self.lit_ty(lit, expected);
+                self.int_lit(lit.value, false, ty, span)?
+            }
+            Lit::Float(lit) => ConstValue::Float(lit.value),
+            Lit::Str(value) => ConstValue::Str(value.clone()),
+            Lit::Bool(value) => ConstValue::Bool(*value),
+            Lit::Nil => ConstValue::Nil,
+        })
+    }
+
+    fn lit_ty(&mut self, lit: &IntLit, expected: Option<IntTy>) -> IntTy {
+        match lit
+            .suffix
+            .as_deref()
+            .and_then(IntTy::from_name)
+            .or(expected)
+        {
+            Some(ty) => ty,
+            None => {
+                self.defaulted = true;
+                IntTy::I64
+            }
+        }
+    }
+
+    /// Returns the value of the integer literal, negated if `negative`, checking it fits its type.
+    fn int_lit(&self, value: u128, negative: bool, ty: IntTy, span: Span) -> EvalResult {
+        match int::lit(value, negative, ty) {
+            Some(value) => Ok(ConstValue::Int(value, ty)),
+            None => {
+                let message = format!("literal out of range for `{ty}`");
+                Err(self.error(error_codes::E0080, span, message))
+            }
+        }
+    }
+
+    fn eval_unary(
+        &mut self,
+        expr: &Expr,
+        op: UnOp,
+        operand: &Expr,
+        expected: Option<IntTy>,
+    ) -> EvalResult {
+        // The minimum of a signed type, like `-128i8`, is written as the negation of a literal
+        // out of range of the type
+        if let (UnOp::Neg, ExprKind::Lit(Lit::Int(lit))) = (op, &operand.kind) {
+            let ty = self.lit_ty(lit, expected);
+            if ty.is_signed() {
+                return self.int_lit(lit.value, true, ty, expr.span);
+            }
+        }
+        let value = match (op, self.eval_expr(operand, expected)?) {
+            (UnOp::Neg, ConstValue::Int(value, ty)) if ty.is_signed() => {
+                match value.checked_neg().filter(|value| in_range(*value, ty)) {
+                    Some(value) => ConstValue::Int(value, ty),
+                    None => {
+                        let message = String::from("attempt to negate with overflow");
+                        return Err(self.error(error_codes::E0080, expr.span, message));
+                    }
+                }
+            }
+            (UnOp::Neg, ConstValue::Float(value)) => ConstValue::Float(-value),
+            (UnOp::Not, ConstValue::Int(value, ty)) => ConstValue::Int(wrap(!value, ty), ty),
+            (UnOp::Not, ConstValue::Bool(value)) => ConstValue::Bool(!value),
+            (UnOp::Deref, _) => return Err(self.unsupported(expr.span, "dereferences")),
+            (op, value) => {
+                let message = format!(
+                    "cannot apply unary operator `{}` to `{}`",
+                    op.as_str(),
+                    value.type_name()
+                );
+                return Err(self.error(error_codes::E0082, expr.span, message));
+            }
+        };
+        Ok(value)
+    }
+
+    fn eval_binary(
+        &mut self,
+        expr: &Expr,
+        op: BinOp,
+        lhs: &Expr,
+        rhs: &Expr,
+        expected: Option<IntTy>,
+    ) -> EvalResult {
+        match op {
+            BinOp::And | BinOp::Or => {
+                let lhs = self.eval_bool(lhs)?;
+                if lhs == (op == BinOp::Or) {
+                    return Ok(ConstValue::Bool(lhs));
+                }
+                return Ok(ConstValue::Bool(self.eval_bool(rhs)?));
+            }
+            BinOp::Shl | BinOp::Shr => {
+                let lhs = self.eval_expr(lhs, self.hint(lhs).or(expected))?;
+                let rhs = self.eval_expr(rhs, self.hint(rhs))?;
+                return self.binary_op(expr.span, op, lhs, rhs);
+            }
+            _ => (),
+        }
+        let mut ty = self.hint(lhs).or_else(|| self.hint(rhs));
+        if op.is_arithmetic() || op.is_bitwise() {
+            ty = ty.or(expected);
+        }
+        let lhs = self.eval_expr(lhs, ty)?;
+        let ty = match lhs {
+            ConstValue::Int(_, lhs_ty) => ty.or(Some(lhs_ty)),
+            _ => ty,
+        };
+        let rhs = self.eval_expr(rhs, ty)?;
+        self.binary_op(expr.span, op, lhs, rhs)
+    }
+
+    fn binary_op(&self, span: Span, op: BinOp, lhs: ConstValue, rhs: ConstValue) -> EvalResult {
+        use std::cmp::Ordering;
+
+        let ordering = match (&lhs, &rhs) {
+            (ConstValue::Int(lhs, lhs_ty), ConstValue::Int(rhs, rhs_ty)) => {
+                Some(int::cmp((*lhs, *lhs_ty), (*rhs, *rhs_ty)))
+            }
+            (ConstValue::Float(lhs), ConstValue::Float(rhs)) => lhs.partial_cmp(rhs),
+            (ConstValue::Str(lhs), ConstValue::Str(rhs)) => Some(lhs.cmp(rhs)),
+            (ConstValue::Bool(lhs), ConstValue::Bool(rhs)) => Some(lhs.cmp(rhs)),
+            _ => None,
+        };
+        let value = match (op, lhs, rhs) {
+            (BinOp::Eq, lhs, rhs) => ConstValue::Bool(lhs == rhs),
+            (BinOp::Ne, lhs, rhs) => ConstValue::Bool(lhs != rhs),
+            (BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge, _, _) if ordering.is_some() => {
+                let ordering = ordering.unwrap();
+                ConstValue::Bool(match op {
+                    BinOp::Lt => ordering == Ordering::Less,
+                    BinOp::Le => ordering != Ordering::Greater,
+                    BinOp::Gt => ordering == Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                })
+            }
+            (BinOp::Shl | BinOp::Shr, ConstValue::Int(lhs, ty), ConstValue::Int(rhs, rhs_ty)) => {
+                let amount = if rhs_ty == IntTy::U128 {
+                    u32::try_from(rhs as u128).ok()
+                } else {
+                    u32::try_from(rhs).ok()
+                };
+                match int::shift(op, lhs, amount, ty) {
+                    Ok(value) => ConstValue::Int(value, ty),
+                    Err(err) => {
+                        let message = err.message(op).to_string();
+                        return Err(self.error(error_codes::E0080, span, message));
+                    }
+                }
+            }
+            (op, ConstValue::Int(lhs, ty), ConstValue::Int(rhs, _))
+                if op.is_arithmetic() || op.is_bitwise() =>
+            {
+                match int_op(op, lhs, rhs, ty) {
+                    Ok(value) => ConstValue::Int(value, ty),
+                    Err(err) => {
+                        let code = match err {
+                            IntError::Overflow => error_codes::E0080,
+                            IntError::DivisionByZero => error_codes::E0081,
+                        };
+                        return Err(self.error(code, span, err.message(op).to_string()));
+                    }
+                }
+            }
+            (op, ConstValue::Float(lhs), ConstValue::Float(rhs)) if op.is_arithmetic() => {
+                ConstValue::Float(match op {
+                    BinOp::Add => lhs + rhs,
+                    BinOp::Sub => lhs - rhs,
+                    BinOp::Mul => lhs * rhs,
+                    BinOp::Div => lhs / rhs,
+                    _ => lhs % rhs,
+                })
+            }
+            (BinOp::Add, ConstValue::Str(lhs), ConstValue::Str(rhs)) => ConstValue::Str(lhs + &rhs),
+            (op, ConstValue::Bool(lhs), ConstValue::Bool(rhs)) if op.is_bitwise() => {
+                ConstValue::Bool(match op {
+                    BinOp::BitAnd => lhs & rhs,
+                    BinOp::BitOr => lhs | rhs,
+                    _ => lhs ^ rhs,
+                })
+            }
+            (op, lhs, rhs) => {
+                let message = format!(
+                    "cannot apply operator `{}` to `{}` and `{}`",
+                    op.as_str(),
+                    lhs.type_name(),
+                    rhs.type_name()
+                );
+                return Err(self.error(error_codes::E0082, span, message));
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// Replaces the constant expressions of the ordinary code by literals.
+struct Folder<'a>(Evaluator<'a>);
+
+impl Folder<'_> {
+    /// Replaces the expression by its value if it is constant, or its constant subexpressions,
+    /// giving the expected type to its integers without suffix.
+    fn fold_expr(&mut self, expr: &mut Expr, expected: Option<IntTy>) {
+        crate::ice::set_current_span(expr.span);
+        match &mut expr.kind {
+            // The integers are checked against the range of `i64`
+            ExprKind::Lit(lit) if expected.is_none() && !matches!(lit, Lit::Int(_)) => return,
+            ExprKind::AddrOf(Mutability::Mut, _) => return,
+            ExprKind::Assign(_, value) | ExprKind::AssignOp(_, _, value) => {
+                self.visit_expr(value);
+                return;
+            }
+            _ => (),
+        }
+        if self.0.is_constant(expr) {
+            self.0.steps = 0;
+            self.0.defaulted = false;
+            // The subexpressions failing to evaluate fail again, without more to fold
+            if let Ok(value) = self.0.eval_expr(expr, expected) {
+                if let Some(kind) = self.fold(expr, value) {
+                    expr.kind = kind;
+                }
+            }
+            return;
+        }
+        mut_visit::walk_expr(self, expr);
+    }
+
+    /// Returns the literal expression with the value of the constant expression, if it can be
+    /// written as a literal.
+    fn fold(&self, expr: &Expr, value: ConstValue) -> Option<ExprKind> {
+        let (lit, negative) = match value {
+            ConstValue::Int(value, ty) => {
+                // A literal of another type than the default one keeps its type, even if an
+                // operand was defaulted, like the amount of a shift
+                let suffix = (!self.0.defaulted || ty != IntTy::I64).then(|| ty.to_string());
+                let (value, negative) = match ty {
+                    IntTy::U128 => (value as u128, false),
+                    _ => (value.unsigned_abs(), value < 0),
+                };
+                (Lit::Int(IntLit { value, suffix }), negative)
+            }
+            ConstValue::Float(value) if value.is_finite() => {
+                let lit = FloatLit {
+                    value: value.abs(),
+                    suffix: None,
+                };
+                (Lit::Float(lit), value.is_sign_negative())
+            }
+            ConstValue::Str(value) => (Lit::Str(value), false),
+            ConstValue::Bool(value) => (Lit::Bool(value), false),
+            ConstValue::Nil => (Lit::Nil, false),
+            _ => return None,
+        };
+        if !negative {
+            return Some(ExprKind::Lit(lit));
+        }
+        // The negated literal reuses the id of an operand of the folded expression, which is
+        // dropped
+        let id = match &expr.kind {
+            ExprKind::Paren(operand)
+            | ExprKind::Unary(_, operand)
+            | ExprKind::Cast(operand, _)
+            | ExprKind::Binary(_, operand, _) => operand.id,
+            _ => return None,
+        };
+        let lit = Expr {
+            id,
+            kind: ExprKind::Lit(lit),
+            span: expr.span,
+        };
+        Some(ExprKind::Unary(UnOp::Neg, Box::new(lit)))
+    }
+}
+
+impl VisitorMut for Folder<'_> {
+    fn visit_item(&mut self, item: &mut Item) {
+        // The constants are already evaluated
+        if matches!(item.kind, ItemKind::Const(_)) {
+            return;
+        }
+        let in_const_fn = self.0.in_const_fn;
+        if let ItemKind::Fn(decl) = &item.kind {
+            self.0.in_const_fn = decl.is_const;
+        }
+        mut_visit::walk_item(self, item);
+        self.0.in_const_fn = in_const_fn;
+    }
+
+    // The patterns only contain literals
+    fn visit_pat(&mut self, _pat: &mut Pattern) {}
+
+    fn visit_local(&mut self, local: &mut Local) {
+        // The integers without suffix initializing a variable are of its type
+        let expected = match &local.ty {
+            Some(
+                ty @ Type {
+                    kind: TypeKind::Path(..),
+                    ..
+                },
+            ) => self.0.int_ty(Some(ty)),
+            _ => None,
+        };
+        if let Some(init) = &mut local.init {
+            self.fold_expr(init, expected);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        self.fold_expr(expr, None);
+    }
+}
+
+// Integers
+
+/// Returns the value of the associated constant of an integer type named by the path starting
+/// with the built-in type, like `u8::MAX`.
+fn int_const(name: &str, path: &Path) -> Option<(i128, IntTy)> {
+    let ty = IntTy::from_name(name)?;
+    match &path.segments[1..] {
+        [assoc] => Some((int::associated_const(ty, &assoc.name)?, ty)),
+        _ => None,
+    }
+}
+
+/// Returns the index the value gives in an array of the given length, or the error message.
+fn index_of(index: &ConstValue, len: usize) -> Result<usize, String> {
+    match index {
+        ConstValue::Int(index, ty) => {
+            let index = if *ty == IntTy::U128 {
+                usize::try_from(*index as u128).ok()
+            } else {
+                usize::try_from(*index).ok()
+            };
+            match index {
+                Some(index) if index < len => Ok(index),
+                Some(index) => Err(format!(
+                    "index out of bounds: the length is {len} but the index is {index}"
+                )),
+                None => Err(String::from("index out of bounds")),
+            }
+        }
+        index => Err(format!(
+            "cannot index an array with `{}`",
+            index.type_name()
+        )),
+    }
+}
+
+/// Converts the value with the semantics of the `as` operator.
+fn cast(value: &ConstValue, target: &'static str) -> Option<ConstValue> {
+    let value = match (value, target, IntTy::from_name(target)) {
+        (ConstValue::Int(value, ty), "f32" | "f64", _) => {
+            ConstValue::Float(round_float(int::to_f64(*value, *ty), target))
+        }
+        (ConstValue::Int(value, _), _, Some(ty)) => ConstValue::Int(wrap(*value, ty), ty),
+        (ConstValue::Float(value), "f32" | "f64", _) => {
+            ConstValue::Float(round_float(*value, target))
+        }
+        (ConstValue::Float(value), _, Some(ty)) => ConstValue::Int(int::from_f64(*value, ty), ty),
+        (ConstValue::Bool(value), _, Some(ty)) => ConstValue::Int(i128::from(*value), ty),
+        (ConstValue::Bool(_), "bool", _) | (ConstValue::Str(_), "str", _) => value.clone(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn round_float(value: f64, ty: &str) -> f64 {
+    match ty {
+        "f32" => value as f32 as f64,
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod tests;
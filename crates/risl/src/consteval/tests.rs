@@ -0,0 +1,342 @@
+use crate::parser::ast::{ItemKind, StmtKind};
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::DiagContext;
+use crate::parser::emitter::new_emitter_none;
+use crate::parser::printer::{self, Format};
+use crate::test_utils::diagnostics;
+
+/// Evaluates the top-level constants of the source, returning them as `NAME = value`, and the
+/// emitted diagnostics as `level: message`.
+fn eval(source: &str) -> (Vec<String>, Vec<String>) {
+    let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+    let mut program = crate::parser::parse(&context, source);
+    let resolutions = crate::resolve::resolve(&context, &mut program);
+    let consts = super::eval(&context, &program, &resolutions);
+    let values = program
+        .stmts
+        .iter()
+        .filter_map(|stmt| match &stmt.kind {
+            StmtKind::Item(item) => match &item.kind {
+                ItemKind::Const(decl) => {
+                    let value = consts.values.get(&item.id)?;
+                    Some(format!("{} = {value}", decl.name.name))
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    (values, diagnostics(&context))
+}
+
+/// Evaluates the constants of the source and folds its constant expressions, returning the
+/// program as an S-expression along with the emitted diagnostics.
+fn fold(source: &str) -> (String, Vec<String>) {
+    let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+    let mut program = crate::parser::parse(&context, source);
+    let resolutions = crate::resolve::resolve(&context, &mut program);
+    let consts = super::eval(&context, &program, &resolutions);
+    super::fold(&context, &mut program, &resolutions, &consts);
+    (
+        printer::print(&program, Format::Sexp),
+        diagnostics(&context),
+    )
+}
+
+fn values(source: &str) -> Vec<String> {
+    let (values, diagnostics) = eval(source);
+    assert_eq!(diagnostics, Vec::<String>::new());
+    values
+}
+
+fn errors(source: &str) -> Vec<String> {
+    eval(source).1
+}
+
+#[test]
+fn arithmetic() {
+    assert_eq!(
+        values(
+            "const KIB: i64 = 1 << 10;\n\
+             const MIB: i64 = KIB * KIB;\n\
+             const HALF: i64 = -MIB / 2 + 7 % 4;\n\
+             const MASK: u8 = !0b1010 & 0xff;\n\
+             const RATIO: f64 = 1.5 * 4.0;"
+        ),
+        [
+            "KIB = 1024",
+            "MIB = 1048576",
+            "HALF = -524285",
+            "MASK = 245",
+            "RATIO = 6.0",
+        ]
+    );
+    // The constants can be used before their declaration
+    assert_eq!(
+        values("const A: i64 = B + 1;\nconst B: i64 = 41;"),
+        ["A = 42", "B = 41"]
+    );
+    assert_eq!(
+        values(
+            "const MIN: i8 = -128;\n\
+             const MAX: u128 = 340282366920938463463374607431768211455;\n\
+             const WRAPPED: u8 = 300 as u8;\n\
+             const TRUNCATED: i32 = 2.9 as i32;\n\
+             const SATURATED: u8 = -1.5 as u8;"
+        ),
+        [
+            "MIN = -128",
+            "MAX = 340282366920938463463374607431768211455",
+            "WRAPPED = 44",
+            "TRUNCATED = 2",
+            "SATURATED = 0",
+        ]
+    );
+    assert_eq!(
+        values("const A: u8 = u8::MAX;\nconst B: i64 = i64::MIN + 1;\nconst C: u128 = u128::MAX;"),
+        [
+            "A = 255",
+            "B = -9223372036854775807",
+            "C = 340282366920938463463374607431768211455",
+        ]
+    );
+}
+
+#[test]
+fn other_values() {
+    assert_eq!(
+        values(
+            "const GREETING: str = \"Hello, \" + \"world\";\n\
+             const PRIMES: [i64] = [2, 3, 5, 7];\n\
+             const THIRD: i64 = PRIMES[2];\n\
+             const PAIR: (i64, bool) = (1, 2 < 3 && !false);\n\
+             const FIRST: i64 = PAIR.0;\n\
+             const SAME: bool = PRIMES == [2, 3, 5, 7];"
+        ),
+        [
+            "GREETING = \"Hello, world\"",
+            "PRIMES = [2, 3, 5, 7]",
+            "THIRD = 5",
+            "PAIR = (1, true)",
+            "FIRST = 1",
+            "SAME = true",
+        ]
+    );
+}
+
+#[test]
+fn const_fn() {
+    assert_eq!(
+        values(
+            "const fn factorial(n: u64) -> u64 {\n\
+                 let mut result = 1;\n\
+                 let mut i = 2;\n\
+                 while i <= n {\n\
+                     result *= i;\n\
+                     i += 1;\n\
+                 }\n\
+                 result\n\
+             }\n\
+             const fn fib(n: i64) -> i64 {\n\
+                 if n < 2 { return n; }\n\
+                 fib(n - 1) + fib(n - 2)\n\
+             }\n\
+             const F: u64 = factorial(20);\n\
+             const G: i64 = fib(15);\n\
+             const H: i64 = { let (a, b) = (3, 4); a * b };"
+        ),
+        ["F = 2432902008176640000", "G = 610", "H = 12"]
+    );
+    assert_eq!(
+        errors("fn square(x) { x * x }\nconst A: i64 = square(2);"),
+        ["error: cannot call non-const fn `square` in constants"]
+    );
+    assert_eq!(
+        errors("const A: i64 = print(2);"),
+        ["error: cannot call non-const fn `print` in constants"]
+    );
+    assert_eq!(
+        errors("const fn f(x: i64) -> i64 { for i in 0..x {} x }\nconst A: i64 = f(2);"),
+        ["error: `for` loops are not supported in constants"]
+    );
+    assert_eq!(
+        errors("let x = 1;\nconst A: i64 = x;"),
+        ["error: attempt to use a non-constant value in a constant"]
+    );
+}
+
+#[test]
+fn overflow() {
+    assert_eq!(
+        errors("const A: u8 = 200 + 100;"),
+        ["error: attempt to add with overflow"]
+    );
+    assert_eq!(
+        errors("const A: i64 = 9223372036854775807 * 2;"),
+        ["error: attempt to multiply with overflow"]
+    );
+    assert_eq!(
+        errors("const A: u32 = 1 - 2;"),
+        ["error: attempt to subtract with overflow"]
+    );
+    assert_eq!(
+        errors("const A: i8 = -128;\nconst B: i8 = -A;"),
+        ["error: attempt to negate with overflow"]
+    );
+    assert_eq!(
+        errors("const A: i32 = 1 << 32;"),
+        ["error: attempt to shift left with overflow"]
+    );
+    assert_eq!(
+        errors("const A: i64 = 1000u8 as i64;"),
+        ["error: literal out of range for `u8`"]
+    );
+    // The error is reported once, by the constant which overflows
+    assert_eq!(
+        errors("const A: u8 = 255 + 1;\nconst B: u8 = A + 1;"),
+        ["error: attempt to add with overflow"]
+    );
+    assert_eq!(
+        errors("const fn double(x: u8) -> u8 { x * 2 }\nconst A: u8 = double(200);"),
+        ["error: attempt to multiply with overflow"]
+    );
+}
+
+#[test]
+fn division_by_zero() {
+    assert_eq!(
+        errors("const A: i64 = 1 / 0;"),
+        ["error: attempt to divide by zero"]
+    );
+    assert_eq!(
+        errors("const Z: i64 = 0;\nconst A: i64 = 5 % Z;"),
+        ["error: attempt to calculate the remainder with a divisor of zero"]
+    );
+    // Dividing the minimum by -1 overflows
+    assert_eq!(
+        errors("const A: i8 = -128 / -1;"),
+        ["error: attempt to divide with overflow"]
+    );
+    // Floats have infinities
+    assert_eq!(values("const A: f64 = 1.0 / 0.0;"), ["A = inf"]);
+}
+
+#[test]
+fn invalid_constants() {
+    assert_eq!(
+        errors("const A: i64 = B;\nconst B: i64 = A;"),
+        ["error: cycle detected when evaluating constant `A`"]
+    );
+    assert_eq!(
+        errors("const fn f(n: i64) -> i64 { f(n + 1) }\nconst A: i64 = f(0);"),
+        ["error: reached the recursion limit while calling `f` in constants"]
+    );
+    assert_eq!(
+        errors("const A: [i64] = [1, 2];\nconst B: i64 = A[2];"),
+        ["error: index out of bounds: the length is 2 but the index is 2"]
+    );
+    assert_eq!(
+        errors("const A: i64 = \"a\" - 1;"),
+        ["error: cannot apply operator `-` to `str` and `i64`"]
+    );
+    assert_eq!(
+        errors("const A: i64 = if 1 { 2 } else { 3 };"),
+        ["error: expected `bool`, found `i64`"]
+    );
+}
+
+#[test]
+fn folding() {
+    assert_eq!(
+        fold("const LEN: i64 = 8;\nfn f(x) { x + LEN * 2 }\nlet y = -(LEN + 1);").0,
+        "(program\n  \
+           (const LEN (: i64) (= 8))\n  \
+           (fn f (params (param x)) (block (+ x 16i64)))\n  \
+           (let y (= (- 9i64))))\n"
+    );
+    assert_eq!(
+        fold("let s = \"a\" + \"b\";\nlet b = 1 < 2;\nlet c = 2.5 * 2.0;").0,
+        "(program\n  \
+           (let s (= \"ab\"))\n  \
+           (let b (= true))\n  \
+           (let c (= 5.0)))\n"
+    );
+    // Variables and calls are not folded, and neither are the assigned places
+    assert_eq!(
+        fold("let mut x = 1;\nx = x + (2 * 3);\nprint(1 + 1);").0,
+        "(program\n  \
+           (let (mut x) (= 1))\n  \
+           (; (= x (+ x 6)))\n  \
+           (; (call print 2)))\n"
+    );
+    assert_eq!(
+        fold("fn f() { let x = 1 / 0; }").1,
+        ["error: attempt to divide by zero"]
+    );
+    assert_eq!(
+        fold("fn f() { let x = 255u8 + 1; }").1,
+        ["error: attempt to add with overflow"]
+    );
+    // Without a suffix, the literals are `i64` values, as at run time
+    assert_eq!(
+        fold("let x = 9223372036854775807 + 1;").1,
+        ["error: attempt to add with overflow"]
+    );
+    // unless they initialize a variable with an integer type
+    assert_eq!(
+        fold("let x: u8 = 255;\nlet y: i8 = -128;\nlet z: u8 = 256;\nlet w: u8 = 200 + 100;"),
+        (
+            String::from(
+                "(program\n  \
+                   (let x (: u8) (= 255u8))\n  \
+                   (let y (: i8) (= (- 128i8)))\n  \
+                   (let z (: u8) (= 256))\n  \
+                   (let w (: u8) (= (+ 200 100))))\n"
+            ),
+            vec![
+                String::from("error: literal out of range for `u8`"),
+                String::from("error: attempt to add with overflow"),
+            ]
+        )
+    );
+}
+
+#[test]
+fn defaulted_literals_when_folding() {
+    // Outside of constants, the literals without suffix are `i64` values at run time, so their
+    // range is checked
+    assert_eq!(
+        fold("fn f(x: u128) -> u128 { x + 18446744073709551615 * 2 }").1,
+        ["error: literal out of range for `i64`"]
+    );
+    assert_eq!(
+        fold("println(1 << 64, -(-9223372036854775807 - 1), 9223372036854775808);").1,
+        [
+            "error: attempt to shift left with overflow",
+            "error: attempt to negate with overflow",
+            "error: literal out of range for `i64`",
+        ]
+    );
+    // as is the type of the other operand, even if the amount of a shift is defaulted
+    assert_eq!(
+        fold("println(1u8 << 8);").1,
+        ["error: attempt to shift left with overflow"]
+    );
+    // The constants may call a `const fn` with other types, so its defaulted literals are not
+    // checked
+    let (program, diagnostics) = fold("const fn f() -> u128 { 18446744073709551615 * 2 }");
+    assert_eq!(diagnostics, Vec::<String>::new());
+    assert!(program.contains("(* 18446744073709551615 2)"), "{program}");
+    // Errors that no type avoids are still reported
+    assert_eq!(
+        fold("const fn f(x: u128) -> u128 { x + 1 / 0 }").1,
+        ["error: attempt to divide by zero"]
+    );
+    // while constants always have a type
+    assert_eq!(
+        errors(
+            "const X: u128 = 18446744073709551615 * 2;\nconst Y: i64 = 18446744073709551615 * 2;"
+        ),
+        ["error: literal out of range for `i64`"]
+    );
+}
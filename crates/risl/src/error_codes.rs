@@ -0,0 +1,219 @@
+//! The registry of the error codes attached to diagnostics.
+//!
+//! Each error code has a markdown explanation in `error_codes/EXXXX.md`, displayed by
+//! `risl --explain EXXXX`. The explanation must contain at least one erroneous example in a code
+//! block annotated with `risl,compile_fail,EXXXX`, and may contain fixed examples in code blocks
+//! annotated with `risl`. Those examples are checked at test time.
+//!
+//! Diagnostics can only refer to error codes through the constants defined here, ensuring every
+//! emitted error code is registered.
+
+/// A stable identifier for a kind of error, displayed as `EXXXX`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct ErrorCode(u32);
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "E{:04}", self.0)
+    }
+}
+
+impl std::str::FromStr for ErrorCode {
+    type Err = ();
+
+    /// Parses an error code, with or without its leading `E`.
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let digits = code.strip_prefix(['E', 'e']).unwrap_or(code);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(());
+        }
+        digits.parse().map(ErrorCode).map_err(|_| ())
+    }
+}
+
+macro_rules! register_error_codes {
+    ($($code:ident: $value:literal,)*) => {
+        $(pub const $code: ErrorCode = ErrorCode($value);)*
+
+        /// All the registered error codes along with their explanation.
+        pub static REGISTRY: &[(ErrorCode, &str)] = &[
+            $((ErrorCode($value), include_str!(concat!("error_codes/", stringify!($code), ".md"))),)*
+        ];
+    };
+}
+
+register_error_codes! {
+    E0001: 1,
+    E0002: 2,
+    E0003: 3,
+    E0004: 4,
+    E0005: 5,
+    E0006: 6,
+    E0007: 7,
+    E0008: 8,
+    E0010: 10,
+    E0011: 11,
+    E0012: 12,
+    E0013: 13,
+    E0014: 14,
+    E0015: 15,
+    E0016: 16,
+    E0017: 17,
+    E0018: 18,
+    E0019: 19,
+    E0020: 20,
+    E0021: 21,
+    E0022: 22,
+    E0023: 23,
+    E0024: 24,
+    E0030: 30,
+    E0031: 31,
+    E0032: 32,
+    E0040: 40,
+    E0041: 41,
+    E0042: 42,
+    E0043: 43,
+    E0044: 44,
+    E0045: 45,
+    E0060: 60,
+    E0061: 61,
+    E0062: 62,
+    E0063: 63,
+    E0064: 64,
+    E0070: 70,
+    E0071: 71,
+    E0072: 72,
+    E0080: 80,
+    E0081: 81,
+    E0082: 82,
+    E0083: 83,
+    E0084: 84,
+    E0090: 90,
+}
+
+/// Returns the explanation for the given error code, if registered.
+pub fn explain(code: &str) -> Option<&'static str> {
+    let code: ErrorCode = code.parse().ok()?;
+    REGISTRY
+        .iter()
+        .find(|(registered, _)| *registered == code)
+        .map(|(_, explanation)| *explanation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::context::ParseContext;
+    use crate::parser::diagnostic::DiagContext;
+    use crate::parser::emitter::new_emitter_none;
+
+    /// An example code block extracted from an explanation.
+    struct Example {
+        code: String,
+        /// The error code the example must produce, if it is an erroneous example.
+        expected_error: Option<String>,
+    }
+
+    fn extract_examples(explanation: &str) -> Vec<Example> {
+        let mut examples = vec![];
+        let mut lines = explanation.lines();
+        while let Some(line) = lines.next() {
+            let Some(info) = line.strip_prefix("```") else {
+                continue;
+            };
+            let attributes: Vec<_> = info.split(',').map(str::trim).collect();
+            let code = lines
+                .by_ref()
+                .take_while(|line| !line.starts_with("```"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            if attributes[0] != "risl" {
+                continue;
+            }
+            let expected_error = if attributes.contains(&"compile_fail") {
+                attributes
+                    .iter()
+                    .find(|a| a.starts_with('E'))
+                    .map(|a| a.to_string())
+            } else {
+                None
+            };
+            examples.push(Example {
+                code,
+                expected_error,
+            });
+        }
+        examples
+    }
+
+    fn emitted_codes(source: &str) -> Vec<String> {
+        let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+        // Compiling to bytecode runs the static checks, and reports the limits of the bytecode
+        crate::compile(&context, source, crate::CheckOptions::default());
+        context
+            .diag_ctx()
+            .diagnostics()
+            .iter()
+            .filter(|diagnostic| diagnostic.level.is_error())
+            .map(|diagnostic| match diagnostic.code {
+                Some(code) => code.to_string(),
+                None => String::from("<no code>"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn error_code_display_and_parse() {
+        assert_eq!(E0001.to_string(), "E0001");
+        assert_eq!("E0001".parse(), Ok(E0001));
+        assert_eq!("0001".parse(), Ok(E0001));
+        assert_eq!("E".parse::<ErrorCode>(), Err(()));
+        assert_eq!("E00x1".parse::<ErrorCode>(), Err(()));
+    }
+
+    #[test]
+    fn explain_registered_code() {
+        assert!(explain("E0001").unwrap().starts_with("#"));
+        assert_eq!(explain("E9999"), None);
+        assert_eq!(explain("hello"), None);
+    }
+
+    #[test]
+    fn registry_is_sorted_without_duplicates() {
+        assert!(REGISTRY.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn explanations_have_erroneous_examples() {
+        for (code, explanation) in REGISTRY {
+            let examples = extract_examples(explanation);
+            assert!(
+                examples
+                    .iter()
+                    .any(|example| example.expected_error == Some(code.to_string())),
+                "{code} has no erroneous example"
+            );
+        }
+    }
+
+    #[test]
+    fn examples_produce_expected_errors() {
+        for (code, explanation) in REGISTRY {
+            for example in extract_examples(explanation) {
+                let emitted = emitted_codes(&example.code);
+                match &example.expected_error {
+                    Some(expected) => assert!(
+                        emitted.contains(expected),
+                        "{code}: example does not produce {expected} (produced {emitted:?}):\n{}",
+                        example.code
+                    ),
+                    None => assert!(
+                        emitted.is_empty(),
+                        "{code}: fixed example produces {emitted:?}:\n{}",
+                        example.code
+                    ),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1450 @@
+//! Checking of the patterns of the program, and of the exhaustiveness of `match` expressions.
+//!
+//! A match is exhaustive when every value of its scrutinee is matched by one of its arms, and an
+//! arm is unreachable when the arms before it already match every value it matches. Both are
+//! computed with the usefulness algorithm of "Warnings for pattern matching" (Maranget, 2007): a
+//! pattern is useful with respect to a list of patterns if it matches a value none of them match.
+//! The values witnessing the usefulness of a wildcard after all the arms are the missing patterns.
+//!
+//! As the checker runs without type information, the type of each matched value is deduced from
+//! the patterns matching it: `true` makes a boolean, `Shape::Circle(r)` an enum declared in the
+//! program, `1..=5u8` an 8-bit unsigned integer... The literals without suffix take the integer
+//! type of the matched value when it is known besides the patterns: from the expression of the
+//! scrutinee, like a cast, a suffixed literal or a variable annotated with `u8` or initialized with
+//! one, pushed down into tuples and variants built by the scrutinee, and from the declared types
+//! of the fields of structs and variants. When the integer type is unknown, the literals only
+//! cover the values of every integer type and a missing value is reported as `_`. Values of types
+//! which cannot be enumerated, like strings, or matched by patterns of different types can only be
+//! covered by a wildcard or a binding.
+//!
+//! Arms with a guard are checked for reachability, but never count towards exhaustiveness. The
+//! built-in `Option` and `Result` enums are checked like enums declared in the program, and their
+//! variants can be named without the name of the enum.
+
+use std::collections::HashMap;
+
+use crate::error_codes;
+use crate::int::IntTy;
+use crate::parser::ast::*;
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::{Diagnostic, Level};
+use crate::parser::lexer::Span;
+use crate::parser::visit::{self, Visitor};
+use crate::resolve::{Res, Resolutions, Variable, BUILTIN_ENUMS};
+
+/// Checks the patterns of the program, reporting invalid patterns, non-exhaustive matches and
+/// unreachable arms through the context.
+pub fn check(context: &ParseContext, program: &Program, resolutions: &Resolutions) {
+    check_input(context, &[], program, resolutions, &mut Globals::default());
+}
+
+/// What is known of the types of the globals declared by the previous inputs of an interactive
+/// session.
+#[derive(Default)]
+pub struct Globals(HashMap<Variable, Hint>);
+
+/// Checks the patterns of an input of an interactive session like [`check`], given the previous
+/// inputs and what is known of the types of their globals, which is extended with the globals of
+/// the input.
+pub fn check_input(
+    context: &ParseContext,
+    previous: &[Program],
+    program: &Program,
+    resolutions: &Resolutions,
+    globals: &mut Globals,
+) {
+    let builtins = builtin_enums();
+    let mut collector = AdtCollector::default();
+    let mut variants = HashMap::new();
+    for decl in &builtins {
+        collector.0.insert(&decl.name.name, Adt::Enum(decl));
+        for (index, variant) in decl.variants.iter().enumerate() {
+            variants.insert(variant.name.name.as_str(), (decl, index));
+        }
+    }
+    // The enums of the program shadow the built-in ones
+    for program in previous.iter().chain([program]) {
+        collector.visit_program(program);
+    }
+    let mut checker = Checker {
+        context,
+        resolutions,
+        adts: collector.0,
+        variants,
+        var_hints: std::mem::take(&mut globals.0),
+        frames: vec![None],
+        deferred: vec![],
+    };
+    checker.visit_program(program);
+    // Functions are checked once the types of all the globals they can use are known
+    while !checker.deferred.is_empty() {
+        for (id, decl) in std::mem::take(&mut checker.deferred) {
+            let frames = std::mem::replace(&mut checker.frames, vec![Some(id)]);
+            visit::walk_fn_decl(&mut checker, decl);
+            checker.frames = frames;
+        }
+    }
+    globals.0 = checker.var_hints;
+}
+
+/// The id of the variants of the built-in enums, which are not declared in the program.
+const BUILTIN_VARIANT_ID: NodeId = NodeId::MAX;
+
+/// Returns the declarations of the built-in enums, with untyped fields.
+fn builtin_enums() -> Vec<EnumDecl> {
+    let span = Span::new(0, 0);
+    let ident = |name: &str| Ident {
+        name: String::from(name),
+        span,
+    };
+    let field = |index: usize| FieldDecl {
+        name: ident(&index.to_string()),
+        ty: Type {
+            id: BUILTIN_VARIANT_ID,
+            kind: TypeKind::Infer,
+            span,
+        },
+        is_pub: true,
+        span,
+    };
+    let variant = |(name, arity): &(&str, usize)| Variant {
+        id: BUILTIN_VARIANT_ID,
+        name: ident(name),
+        fields: match arity {
+            0 => VariantFields::Unit,
+            _ => VariantFields::Tuple((0..*arity).map(field).collect()),
+        },
+        span,
+    };
+    BUILTIN_ENUMS
+        .iter()
+        .map(|(name, variants)| EnumDecl {
+            name: ident(name),
+            variants: variants.iter().map(variant).collect(),
+        })
+        .collect()
+}
+
+/// The maximum number of missing patterns listed in a diagnostic.
+const MAX_REPORTED_WITNESSES: usize = 3;
+
+#[derive(Clone, Copy)]
+enum Adt<'a> {
+    Struct(&'a StructDecl),
+    Enum(&'a EnumDecl),
+}
+
+/// Collects the structs and enums declared anywhere in the program, by name.
+#[derive(Default)]
+struct AdtCollector<'a>(HashMap<&'a str, Adt<'a>>);
+
+impl<'a> Visitor<'a> for AdtCollector<'a> {
+    fn visit_item(&mut self, item: &'a Item) {
+        match &item.kind {
+            ItemKind::Struct(decl) => {
+                self.0.insert(&decl.name.name, Adt::Struct(decl));
+            }
+            ItemKind::Enum(decl) => {
+                self.0.insert(&decl.name.name, Adt::Enum(decl));
+            }
+            ItemKind::Fn(_) | ItemKind::Impl(_) | ItemKind::Trait(_) | ItemKind::Const(_) => (),
+        }
+        visit::walk_item(self, item);
+    }
+}
+
+/// The type of a matched value, as deduced from the patterns matching it.
+#[derive(Clone, Copy, Debug)]
+enum Ty<'a> {
+    Bool,
+    Nil,
+    /// An integer, whose type is given by the suffix of the literals or by the scrutinee, if any.
+    Int(Option<IntTy>),
+    Tuple(usize),
+    Struct(&'a StructDecl),
+    Enum(&'a EnumDecl),
+    Slice,
+    /// A type whose values are not enumerated, like strings or floats, or an unknown type.
+    Opaque,
+}
+
+impl<'a> Ty<'a> {
+    /// Returns the type of values matched by patterns of both types.
+    fn merge(self, other: Ty<'a>) -> Ty<'a> {
+        match (self, other) {
+            (Ty::Bool, Ty::Bool) => Ty::Bool,
+            (Ty::Nil, Ty::Nil) => Ty::Nil,
+            (Ty::Int(a), Ty::Int(b)) => match (a, b) {
+                (Some(a), Some(b)) if a != b => Ty::Opaque,
+                _ => Ty::Int(a.or(b)),
+            },
+            // A tuple pattern with a rest pattern only gives the minimum arity of the tuple
+            (Ty::Tuple(a), Ty::Tuple(b)) => Ty::Tuple(a.max(b)),
+            (Ty::Struct(a), Ty::Struct(b)) if std::ptr::eq(a, b) => self,
+            (Ty::Enum(a), Ty::Enum(b)) if std::ptr::eq(a, b) => self,
+            (Ty::Slice, Ty::Slice) => Ty::Slice,
+            _ => Ty::Opaque,
+        }
+    }
+
+    /// Returns the fields of the values built by the given constructor of this type.
+    fn fields(&self, ctor: &Ctor) -> Option<&'a VariantFields> {
+        match (self, ctor) {
+            (Ty::Struct(decl), Ctor::Single) => Some(&decl.fields),
+            (Ty::Enum(decl), Ctor::Variant(index)) => Some(&decl.variants[*index].fields),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of fields of the values built by the given constructor of this type.
+    fn arity(&self, ctor: &Ctor) -> usize {
+        match (self, ctor) {
+            (Ty::Tuple(arity), Ctor::Single) => *arity,
+            (_, Ctor::Slice(slice)) => slice.arity(),
+            _ => self.fields(ctor).map_or(0, |fields| fields.fields().len()),
+        }
+    }
+}
+
+/// What is known of the type of a matched value besides the patterns matching it.
+#[derive(Clone, Default, Debug)]
+enum Hint {
+    #[default]
+    Unknown,
+    Int(IntTy),
+    Tuple(Vec<Hint>),
+    /// A value of an enum, with what is known of the fields of some of its variants, by index.
+    Variants(Vec<(usize, Vec<Hint>)>),
+}
+
+impl Hint {
+    fn int(&self) -> Option<IntTy> {
+        match self {
+            Hint::Int(ty) => Some(*ty),
+            _ => None,
+        }
+    }
+
+    /// Returns what is known of the fields of the values built by the given constructor.
+    fn fields(&self, ctor: &Ctor) -> &[Hint] {
+        match (self, ctor) {
+            (Hint::Tuple(fields), Ctor::Single) => fields,
+            (Hint::Variants(variants), Ctor::Variant(index)) => variants
+                .iter()
+                .find(|(variant, _)| variant == index)
+                .map_or(&[], |(_, fields)| fields),
+            _ => &[],
+        }
+    }
+}
+
+/// The integer type whose values stand for the values of an unknown integer type. It holds the
+/// values of every other integer type, but the largest `u128` ones.
+const UNKNOWN_INT_TY: IntTy = IntTy::I128;
+
+/// The values of an integer type are encoded as `u128` preserving their order: signed values
+/// have their sign bit flipped.
+impl IntTy {
+    const SIGN_BIT: u128 = 1 << 127;
+
+    fn encoded_min(self) -> u128 {
+        if self.is_signed() {
+            Self::encode_signed(i128::MIN >> (128 - self.bits()))
+        } else {
+            0
+        }
+    }
+
+    fn encoded_max(self) -> u128 {
+        if self.is_signed() {
+            Self::encode_signed(i128::MAX >> (128 - self.bits()))
+        } else {
+            u128::MAX >> (128 - self.bits())
+        }
+    }
+
+    fn encode_signed(value: i128) -> u128 {
+        value as u128 ^ Self::SIGN_BIT
+    }
+
+    /// Encodes the given literal value, if it is a value of this type.
+    fn encode(self, value: IntValue) -> Option<u128> {
+        let encoded = if self.is_signed() {
+            Self::encode_signed(value.to_i128()?)
+        } else if value.negative && value.magnitude != 0 {
+            return None;
+        } else {
+            value.magnitude
+        };
+        (self.encoded_min()..=self.encoded_max())
+            .contains(&encoded)
+            .then_some(encoded)
+    }
+
+    fn format_encoded(self, encoded: u128) -> String {
+        if self.is_signed() {
+            ((encoded ^ Self::SIGN_BIT) as i128).to_string()
+        } else {
+            encoded.to_string()
+        }
+    }
+}
+
+/// An integer literal value, possibly negated.
+#[derive(Clone, Copy, Debug)]
+struct IntValue {
+    negative: bool,
+    magnitude: u128,
+}
+
+impl IntValue {
+    fn to_i128(self) -> Option<i128> {
+        if self.negative {
+            0i128.checked_sub_unsigned(self.magnitude)
+        } else {
+            i128::try_from(self.magnitude).ok()
+        }
+    }
+}
+
+/// The value of a literal pattern or of a bound of a range pattern.
+enum LitValue<'a> {
+    Int(IntValue, Option<&'a str>),
+    Float(f64),
+    Str(&'a str),
+    Bool(bool),
+    Nil,
+}
+
+impl<'a> LitValue<'a> {
+    fn of(expr: &'a Expr) -> Option<LitValue<'a>> {
+        let (negative, lit) = match &expr.kind {
+            ExprKind::Lit(lit) => (false, lit),
+            ExprKind::Unary(UnOp::Neg, operand) => match &operand.kind {
+                ExprKind::Lit(lit) => (true, lit),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(match lit {
+            Lit::Int(int) => LitValue::Int(
+                IntValue {
+                    negative,
+                    magnitude: int.value,
+                },
+                int.suffix.as_deref(),
+            ),
+            Lit::Float(float) if negative => LitValue::Float(-float.value),
+            Lit::Float(float) => LitValue::Float(float.value),
+            Lit::Str(string) => LitValue::Str(string),
+            Lit::Bool(value) => LitValue::Bool(*value),
+            Lit::Nil => LitValue::Nil,
+        })
+    }
+
+    fn ty(&self) -> Ty<'static> {
+        match self {
+            LitValue::Int(_, None) => Ty::Int(None),
+            LitValue::Int(_, Some(suffix)) => {
+                IntTy::from_name(suffix).map_or(Ty::Opaque, |ty| Ty::Int(Some(ty)))
+            }
+            LitValue::Bool(_) => Ty::Bool,
+            LitValue::Nil => Ty::Nil,
+            LitValue::Float(_) | LitValue::Str(_) => Ty::Opaque,
+        }
+    }
+}
+
+impl std::fmt::Display for LitValue<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LitValue::Int(value, _) if value.negative => write!(f, "-{}", value.magnitude),
+            LitValue::Int(value, _) => write!(f, "{}", value.magnitude),
+            LitValue::Float(value) => write!(f, "{value:?}"),
+            LitValue::Str(value) => write!(f, "{value:?}"),
+            LitValue::Bool(value) => write!(f, "{value}"),
+            LitValue::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+/// An inclusive range of encoded integers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct IntRange {
+    lo: u128,
+    hi: u128,
+}
+
+impl IntRange {
+    fn contains(&self, other: &IntRange) -> bool {
+        self.lo <= other.lo && other.hi <= self.hi
+    }
+
+    /// Splits the range at the boundaries of the given ranges, so that each part is either
+    /// contained in or disjoint from each of them.
+    fn split(&self, ranges: impl Iterator<Item = IntRange>) -> Vec<IntRange> {
+        let mut starts = vec![self.lo];
+        for range in ranges {
+            if self.lo < range.lo && range.lo <= self.hi {
+                starts.push(range.lo);
+            }
+            if self.lo <= range.hi && range.hi < self.hi {
+                starts.push(range.hi + 1);
+            }
+        }
+        starts.sort_unstable();
+        starts.dedup();
+        let ends = starts
+            .iter()
+            .skip(1)
+            .map(|start| start - 1)
+            .chain([self.hi]);
+        starts
+            .iter()
+            .zip(ends)
+            .map(|(&lo, hi)| IntRange { lo, hi })
+            .collect()
+    }
+}
+
+/// The lengths of the slices matched by a slice pattern.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SliceKind {
+    /// Slices of exactly the given length: `[a, b]`.
+    Fixed(usize),
+    /// Slices of at least `prefix + suffix` elements: `[a, .., b]`.
+    Var(usize, usize),
+}
+
+impl SliceKind {
+    fn arity(&self) -> usize {
+        match self {
+            SliceKind::Fixed(len) => *len,
+            SliceKind::Var(prefix, suffix) => prefix + suffix,
+        }
+    }
+
+    fn covers(&self, other: &SliceKind) -> bool {
+        match (self, other) {
+            (SliceKind::Fixed(a), SliceKind::Fixed(b)) => a == b,
+            (SliceKind::Fixed(_), SliceKind::Var(..)) => false,
+            (SliceKind::Var(prefix, suffix), SliceKind::Fixed(len)) => prefix + suffix <= *len,
+            (SliceKind::Var(prefix, suffix), SliceKind::Var(other_prefix, other_suffix)) => {
+                prefix <= other_prefix && suffix <= other_suffix
+            }
+        }
+    }
+
+    /// Splits the lengths of the slices matched by a slice pattern of at least `min_len` elements
+    /// into the lengths distinguished by the given slice patterns.
+    ///
+    /// Past the longest fixed-length pattern, no pattern distinguishes lengths anymore: the
+    /// remaining lengths are represented by a variable-length slice whose prefix and suffix are
+    /// long enough to hold the prefix and suffix of every pattern.
+    fn split(min_len: usize, slices: impl Iterator<Item = SliceKind>) -> Vec<SliceKind> {
+        let mut max_prefix = 0;
+        let mut max_suffix = 0;
+        let mut max_fixed_len = None;
+        for slice in slices {
+            match slice {
+                SliceKind::Fixed(len) => {
+                    max_fixed_len = Some(max_fixed_len.map_or(len, |max: usize| max.max(len)))
+                }
+                SliceKind::Var(prefix, suffix) => {
+                    max_prefix = max_prefix.max(prefix);
+                    max_suffix = max_suffix.max(suffix);
+                }
+            }
+        }
+        if let Some(max_fixed_len) = max_fixed_len {
+            if max_fixed_len + 1 >= max_prefix + max_suffix {
+                max_prefix = max_fixed_len + 1 - max_suffix;
+            }
+        }
+        (min_len..max_prefix + max_suffix)
+            .map(SliceKind::Fixed)
+            .chain([SliceKind::Var(max_prefix, max_suffix)])
+            .collect()
+    }
+}
+
+/// A constructor of values, the head of a pattern once deconstructed.
+#[derive(Clone, PartialEq, Debug)]
+enum Ctor {
+    Bool(bool),
+    Nil,
+    /// The only constructor of tuples and structs.
+    Single,
+    /// A variant of an enum, by index.
+    Variant(usize),
+    IntRange(IntRange),
+    Slice(SliceKind),
+    /// A literal of a type whose values are not enumerated, only covering the same literal.
+    Lit(String),
+    /// A pattern the checker cannot reason about, covering no other pattern.
+    Opaque,
+    /// Any value, only used in witnesses.
+    Wild,
+}
+
+impl Ctor {
+    /// Returns true if the values built by `other` are all built by `self`.
+    fn covers(&self, other: &Ctor) -> bool {
+        match (self, other) {
+            (Ctor::IntRange(a), Ctor::IntRange(b)) => a.contains(b),
+            (Ctor::Slice(a), Ctor::Slice(b)) => a.covers(b),
+            (Ctor::Opaque, _) | (Ctor::Wild, _) => false,
+            _ => self == other,
+        }
+    }
+}
+
+/// A row of the pattern matrix, `None` standing for a wildcard.
+type Row<'a> = Vec<Option<&'a Pattern>>;
+
+/// A value matched by a pattern, but by none of the patterns it was checked against.
+#[derive(Clone, Debug)]
+struct Witness<'a> {
+    ctor: Ctor,
+    ty: Ty<'a>,
+    fields: Vec<Witness<'a>>,
+}
+
+impl Witness<'_> {
+    fn wild() -> Self {
+        Witness {
+            ctor: Ctor::Wild,
+            ty: Ty::Opaque,
+            fields: vec![],
+        }
+    }
+
+    fn fmt_fields(&self, f: &mut std::fmt::Formatter, fields: &VariantFields) -> std::fmt::Result {
+        match fields {
+            VariantFields::Unit => Ok(()),
+            VariantFields::Tuple(_) => write_list(f, "(", &self.fields, ")"),
+            VariantFields::Named(decls) => {
+                let mut written = vec![];
+                for (decl, field) in decls.iter().zip(&self.fields) {
+                    if field.ctor != Ctor::Wild {
+                        written.push(format!("{}: {field}", decl.name.name));
+                    }
+                }
+                if written.len() < decls.len() {
+                    written.push(String::from(".."));
+                }
+                write!(f, " {{ {} }}", written.join(", "))
+            }
+        }
+    }
+}
+
+fn write_list(
+    f: &mut std::fmt::Formatter,
+    open: &str,
+    elements: &[impl std::fmt::Display],
+    close: &str,
+) -> std::fmt::Result {
+    write!(f, "{open}")?;
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{element}")?;
+    }
+    write!(f, "{close}")
+}
+
+impl std::fmt::Display for Witness<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.ctor, self.ty) {
+            (Ctor::Bool(value), _) => write!(f, "{value}"),
+            (Ctor::Nil, _) => write!(f, "nil"),
+            (Ctor::IntRange(range), Ty::Int(Some(ty))) => {
+                if range.lo == range.hi {
+                    write!(f, "{}", ty.format_encoded(range.lo))
+                } else {
+                    write!(
+                        f,
+                        "{}..={}",
+                        ty.format_encoded(range.lo),
+                        ty.format_encoded(range.hi)
+                    )
+                }
+            }
+            (Ctor::Single, Ty::Tuple(1)) => write!(f, "({},)", self.fields[0]),
+            (Ctor::Single, Ty::Tuple(_)) => write_list(f, "(", &self.fields, ")"),
+            (Ctor::Single, Ty::Struct(decl)) => {
+                write!(f, "{}", decl.name.name)?;
+                self.fmt_fields(f, &decl.fields)
+            }
+            (Ctor::Variant(index), Ty::Enum(decl)) => {
+                let variant = &decl.variants[*index];
+                if variant.id != BUILTIN_VARIANT_ID {
+                    write!(f, "{}::", decl.name.name)?;
+                }
+                write!(f, "{}", variant.name.name)?;
+                self.fmt_fields(f, &variant.fields)
+            }
+            (Ctor::Slice(SliceKind::Fixed(_)), _) => write_list(f, "[", &self.fields, "]"),
+            (Ctor::Slice(SliceKind::Var(prefix, _)), _) => {
+                let (prefix, suffix) = self.fields.split_at(*prefix);
+                let mut elements: Vec<_> = prefix.iter().map(ToString::to_string).collect();
+                elements.push(String::from(".."));
+                elements.extend(suffix.iter().map(ToString::to_string));
+                write_list(f, "[", &elements, "]")
+            }
+            (Ctor::Lit(literal), _) => write!(f, "{literal}"),
+            _ => write!(f, "_"),
+        }
+    }
+}
+
+/// Where a pattern appears, restricting where a rest pattern `..` can be used.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Position {
+    TupleElement,
+    SliceElement,
+    Other,
+}
+
+/// Returns true if the pattern is a rest pattern, possibly bound: `..` or `rest @ ..`.
+fn is_rest(pat: &Pattern) -> bool {
+    match &pat.kind {
+        PatKind::Rest => true,
+        PatKind::Ident(_, _, Some(subpattern)) => is_rest(subpattern),
+        _ => false,
+    }
+}
+
+/// Returns true if the pattern contains a pattern that could not be parsed.
+fn has_error(pat: &Pattern) -> bool {
+    struct ErrorFinder(bool);
+
+    impl<'a> Visitor<'a> for ErrorFinder {
+        fn visit_pat(&mut self, pat: &'a Pattern) {
+            self.0 |= matches!(pat.kind, PatKind::Err);
+            visit::walk_pat(self, pat);
+        }
+    }
+
+    let mut finder = ErrorFinder(false);
+    finder.visit_pat(pat);
+    finder.0
+}
+
+/// Returns the patterns matching the elements of a tuple or slice of the given arity, with the
+/// rest pattern replaced by wildcards.
+fn expand_rest(elements: &[Pattern], arity: usize) -> Row<'_> {
+    let mut fields: Row = match elements.iter().position(is_rest) {
+        Some(rest) => {
+            let (prefix, suffix) = (&elements[..rest], &elements[rest + 1..]);
+            let wildcards = arity.saturating_sub(prefix.len() + suffix.len());
+            let prefix = prefix.iter().map(Some);
+            let suffix = suffix.iter().map(Some);
+            prefix
+                .chain(std::iter::repeat_n(None, wildcards))
+                .chain(suffix)
+                .collect()
+        }
+        None => elements.iter().map(Some).collect(),
+    };
+    fields.resize(arity, None);
+    fields
+}
+
+struct Checker<'a> {
+    context: &'a ParseContext,
+    resolutions: &'a Resolutions,
+    adts: HashMap<&'a str, Adt<'a>>,
+    /// The variants of the built-in enums, by name.
+    variants: HashMap<&'a str, (&'a EnumDecl, usize)>,
+    /// What is known of the type of the variables, when anything is.
+    var_hints: HashMap<Variable, Hint>,
+    /// The functions and closures being checked, by id, the top-level code having none.
+    frames: Vec<Option<NodeId>>,
+    /// The functions whose body remains to be checked, by item id.
+    deferred: Vec<(NodeId, &'a FnDecl)>,
+}
+
+impl<'a> Checker<'a> {
+    fn error(&self, code: error_codes::ErrorCode, span: Span, message: String) {
+        self.context
+            .diag_ctx()
+            .emit(Diagnostic::spanned(Level::Error, span, message).with_code(code));
+    }
+
+    // Pattern validation
+
+    fn check_pattern(&self, pat: &Pattern, position: Position) {
+        match &pat.kind {
+            PatKind::Rest if position == Position::Other => self.error(
+                error_codes::E0032,
+                pat.span,
+                String::from("`..` patterns are not allowed here"),
+            ),
+            PatKind::Ident(_, _, Some(subpattern)) => {
+                // Only the elements of slices can be bound to a rest pattern: `[first, rest @ ..]`
+                let position = match position {
+                    Position::SliceElement => Position::SliceElement,
+                    _ => Position::Other,
+                };
+                self.check_pattern(subpattern, position);
+            }
+            PatKind::Range(start, end, limits) => self.check_range(pat.span, start, end, *limits),
+            PatKind::Tuple(elements) | PatKind::TupleStruct(_, elements) => {
+                self.check_elements(elements, Position::TupleElement, "tuple")
+            }
+            PatKind::Slice(elements) => {
+                self.check_elements(elements, Position::SliceElement, "slice")
+            }
+            PatKind::Struct(_, fields, _) => {
+                for field in fields {
+                    self.check_pattern(&field.pat, Position::Other);
+                }
+            }
+            PatKind::Or(alternatives) => {
+                for alternative in alternatives {
+                    self.check_pattern(alternative, Position::Other);
+                }
+            }
+            PatKind::Wild
+            | PatKind::Ident(_, _, None)
+            | PatKind::Lit(_)
+            | PatKind::Path(_)
+            | PatKind::Rest
+            | PatKind::Err => (),
+        }
+    }
+
+    fn check_elements(&self, elements: &[Pattern], position: Position, kind: &str) {
+        let mut has_rest = false;
+        for element in elements {
+            self.check_pattern(element, position);
+            if is_rest(element) {
+                if has_rest {
+                    let message = format!("`..` can only be used once per {kind} pattern");
+                    self.error(error_codes::E0032, element.span, message);
+                }
+                has_rest = true;
+            }
+        }
+    }
+
+    fn check_range(
+        &self,
+        span: Span,
+        start: &Option<Box<Expr>>,
+        end: &Option<Box<Expr>>,
+        limits: RangeLimits,
+    ) {
+        let mut bounds = vec![];
+        for bound in [start, end].into_iter().flatten() {
+            match LitValue::of(bound) {
+                Some(value @ (LitValue::Int(..) | LitValue::Float(_))) => bounds.push(value),
+                _ => {
+                    let message = String::from("only numbers are allowed in range patterns");
+                    self.error(error_codes::E0031, bound.span, message);
+                    return;
+                }
+            }
+        }
+        let [lo, hi] = bounds.as_slice() else {
+            return;
+        };
+        let ordering = match (lo, hi) {
+            (LitValue::Int(lo, _), LitValue::Int(hi, _)) => match (lo.to_i128(), hi.to_i128()) {
+                (Some(lo), Some(hi)) => lo.partial_cmp(&hi),
+                _ => None,
+            },
+            (LitValue::Float(lo), LitValue::Float(hi)) => lo.partial_cmp(hi),
+            _ => {
+                let message = String::from("mismatched types of range pattern bounds");
+                self.error(error_codes::E0031, span, message);
+                return;
+            }
+        };
+        match (ordering, limits) {
+            (Some(std::cmp::Ordering::Greater), RangeLimits::Closed) => self.error(
+                error_codes::E0031,
+                span,
+                String::from("lower range bound must be less than or equal to upper"),
+            ),
+            (
+                Some(std::cmp::Ordering::Greater | std::cmp::Ordering::Equal),
+                RangeLimits::HalfOpen,
+            ) => self.error(
+                error_codes::E0031,
+                span,
+                String::from("lower range bound must be less than upper"),
+            ),
+            _ => (),
+        }
+    }
+
+    // Type hints
+
+    /// Records what is known of the type of the variable declared by the binding pattern with
+    /// the given id.
+    fn declare(&mut self, id: NodeId, hint: Hint) {
+        let Some(variable) = self
+            .resolutions
+            .names
+            .get(&id)
+            .and_then(|res| self.resolutions.variable(*res, &self.frames))
+        else {
+            return;
+        };
+        match hint {
+            Hint::Unknown => self.var_hints.remove(&variable),
+            hint => self.var_hints.insert(variable, hint),
+        };
+    }
+
+    /// Records what is known of the types of the variables declared by the pattern matching a
+    /// value of the given hint, after the bindings of the pattern were declared without hint.
+    fn declare_pattern(&mut self, pat: &Pattern, hint: Hint) {
+        match (&pat.kind, hint) {
+            (_, Hint::Unknown) => (),
+            (PatKind::Ident(_, _, None), hint) => self.declare(pat.id, hint),
+            (PatKind::Tuple(elements), Hint::Tuple(hints)) if !elements.iter().any(is_rest) => {
+                for (element, hint) in elements.iter().zip(hints) {
+                    self.declare_pattern(element, hint);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Returns what is known of the values of the type.
+    fn type_hint(&self, ty: &Type) -> Hint {
+        match &ty.kind {
+            TypeKind::Path(_, args) => match self.resolutions.names.get(&ty.id) {
+                Some(Res::Builtin(name)) => match IntTy::from_name(name) {
+                    Some(int_ty) => Hint::Int(int_ty),
+                    None => self.builtin_enum_hint(name, args),
+                },
+                _ => Hint::Unknown,
+            },
+            TypeKind::Tuple(types) => {
+                Hint::Tuple(types.iter().map(|ty| self.type_hint(ty)).collect())
+            }
+            _ => Hint::Unknown,
+        }
+    }
+
+    /// Returns what is known of the values of the built-in enum with the given generic
+    /// arguments, which are the types of the fields of its variants in order: `Result<u8, str>`.
+    fn builtin_enum_hint(&self, name: &str, args: &[Type]) -> Hint {
+        let Some((_, variants)) = BUILTIN_ENUMS.iter().find(|(builtin, _)| *builtin == name) else {
+            return Hint::Unknown;
+        };
+        let mut args = args.iter();
+        let variants = variants
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, arity))| *arity > 0)
+            .map(|(index, (_, arity))| {
+                let fields = args.by_ref().take(*arity).map(|ty| self.type_hint(ty));
+                (index, fields.collect())
+            })
+            .collect();
+        Hint::Variants(variants)
+    }
+
+    /// Returns what is known of the value of the expression, to give a type to the literals
+    /// without suffix matching it.
+    fn expr_hint(&self, expr: &Expr) -> Hint {
+        match &expr.kind {
+            ExprKind::Lit(Lit::Int(lit)) => lit
+                .suffix
+                .as_deref()
+                .and_then(IntTy::from_name)
+                .map_or(Hint::Unknown, Hint::Int),
+            ExprKind::Cast(_, ty) => self.type_hint(ty),
+            ExprKind::Path(path) => match self.resolutions.names.get(&expr.id) {
+                // The associated constants of the integer types, like `u8::MAX`
+                Some(Res::Builtin(name)) if path.segments.len() == 2 => {
+                    IntTy::from_name(name).map_or(Hint::Unknown, Hint::Int)
+                }
+                Some(res) => self
+                    .resolutions
+                    .variable(*res, &self.frames)
+                    .and_then(|variable| self.var_hints.get(&variable).cloned())
+                    .unwrap_or_default(),
+                None => Hint::Unknown,
+            },
+            ExprKind::Paren(operand) | ExprKind::Unary(UnOp::Neg | UnOp::Not, operand) => {
+                self.expr_hint(operand)
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let int_ty = match op {
+                    BinOp::Shl | BinOp::Shr => self.expr_hint(lhs).int(),
+                    _ if op.is_arithmetic() || op.is_bitwise() => self
+                        .expr_hint(lhs)
+                        .int()
+                        .or_else(|| self.expr_hint(rhs).int()),
+                    _ => None,
+                };
+                int_ty.map_or(Hint::Unknown, Hint::Int)
+            }
+            ExprKind::Tuple(elements) => Hint::Tuple(
+                elements
+                    .iter()
+                    .map(|element| self.expr_hint(element))
+                    .collect(),
+            ),
+            ExprKind::Call(callee, args) => match &callee.kind {
+                ExprKind::Path(path) => match self.resolve(path) {
+                    Some((Ty::Enum(_), Ctor::Variant(index))) => {
+                        let fields = args.iter().map(|arg| self.expr_hint(arg)).collect();
+                        Hint::Variants(vec![(index, fields)])
+                    }
+                    _ => Hint::Unknown,
+                },
+                _ => Hint::Unknown,
+            },
+            _ => Hint::Unknown,
+        }
+    }
+
+    /// Returns what is known of the fields of the values of the given type built by the given
+    /// constructor, from their declared types or else from the hint of the values.
+    fn field_hints(&self, ty: Ty<'a>, ctor: &Ctor, hint: &Hint) -> Vec<Hint> {
+        let arity = ty.arity(ctor);
+        let hints = hint.fields(ctor);
+        let decls = ty.fields(ctor).map_or(&[][..], VariantFields::fields);
+        (0..arity)
+            .map(
+                |index| match decls.get(index).map(|decl| self.type_hint(&decl.ty)) {
+                    Some(Hint::Unknown) | None => hints.get(index).cloned().unwrap_or_default(),
+                    Some(hint) => hint,
+                },
+            )
+            .collect()
+    }
+
+    // Match checking
+
+    fn check_match(&self, scrutinee: &Expr, arms: &'a [Arm]) {
+        if arms.iter().any(|arm| has_error(&arm.pat)) {
+            return;
+        }
+        let hints = [self.expr_hint(scrutinee)];
+        let mut rows: Vec<Row<'a>> = vec![];
+        for arm in arms {
+            let alternatives = match &arm.pat.kind {
+                PatKind::Or(alternatives) => alternatives.iter().collect(),
+                _ => vec![&arm.pat],
+            };
+            let mut arm_rows = vec![];
+            for alternative in alternatives {
+                let row = vec![Some(alternative)];
+                let previous: Vec<_> = rows.iter().chain(&arm_rows).cloned().collect();
+                if self.usefulness(&previous, &row, &hints).is_empty() {
+                    self.context.diag_ctx().emit(Diagnostic::spanned(
+                        Level::Warning,
+                        alternative.span,
+                        "unreachable pattern",
+                    ));
+                }
+                arm_rows.push(row);
+            }
+            if arm.guard.is_none() {
+                rows.extend(arm_rows);
+            }
+        }
+        let mut missing: Vec<String> = vec![];
+        for witness in self.usefulness(&rows, &[None], &hints) {
+            let witness = witness[0].to_string();
+            if !missing.contains(&witness) {
+                missing.push(witness);
+            }
+        }
+        if missing.is_empty() {
+            return;
+        }
+        let listed: Vec<_> = missing
+            .iter()
+            .take(MAX_REPORTED_WITNESSES)
+            .map(|witness| format!("`{witness}`"))
+            .collect();
+        let patterns = match (listed.as_slice(), missing.len() - listed.len()) {
+            ([single], 0) => single.clone(),
+            ([init @ .., last], 0) => format!("{} and {last}", init.join(", ")),
+            (listed, more) => format!("{} and {more} more", listed.join(", ")),
+        };
+        let message = format!("non-exhaustive patterns: {patterns} not covered");
+        self.error(error_codes::E0030, scrutinee.span, message);
+    }
+
+    /// Returns the pattern matching the same values as the given one, ignoring bindings, or
+    /// `None` if it matches any value.
+    fn peel(&self, pat: Option<&'a Pattern>) -> Option<&'a Pattern> {
+        let mut pat = pat?;
+        loop {
+            match &pat.kind {
+                PatKind::Ident(_, _, Some(subpattern)) => pat = subpattern,
+                PatKind::Ident(_, name, None) if self.unit_struct(&name.name).is_none() => {
+                    return None
+                }
+                PatKind::Wild | PatKind::Rest | PatKind::Err => return None,
+                _ => return Some(pat),
+            }
+        }
+    }
+
+    /// Returns the unit struct of the given name: binding patterns with its name match it.
+    fn unit_struct(&self, name: &str) -> Option<&'a StructDecl> {
+        match self.adts.get(name) {
+            Some(Adt::Struct(decl)) if decl.fields == VariantFields::Unit => Some(decl),
+            _ => None,
+        }
+    }
+
+    /// Returns the type and constructor of the struct or variant the path refers to.
+    fn resolve(&self, path: &Path) -> Option<(Ty<'a>, Ctor)> {
+        match path.segments.as_slice() {
+            [name] => match self.adts.get(name.name.as_str()) {
+                Some(Adt::Struct(decl)) => Some((Ty::Struct(decl), Ctor::Single)),
+                Some(Adt::Enum(_)) => None,
+                None => {
+                    let (decl, index) = self.variants.get(name.name.as_str())?;
+                    Some((Ty::Enum(decl), Ctor::Variant(*index)))
+                }
+            },
+            [enum_name, variant] => match self.adts.get(enum_name.name.as_str())? {
+                Adt::Enum(decl) => {
+                    let index = decl
+                        .variants
+                        .iter()
+                        .position(|v| v.name.name == variant.name)?;
+                    Some((Ty::Enum(decl), Ctor::Variant(index)))
+                }
+                Adt::Struct(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the type of the values matched by the given peeled pattern.
+    fn pat_ty(&self, pat: &'a Pattern) -> Ty<'a> {
+        match &pat.kind {
+            PatKind::Lit(expr) => LitValue::of(expr).map_or(Ty::Opaque, |value| value.ty()),
+            PatKind::Range(start, end, _) => {
+                let mut ty = Ty::Int(None);
+                for bound in [start, end].into_iter().flatten() {
+                    ty = ty.merge(LitValue::of(bound).map_or(Ty::Opaque, |value| value.ty()));
+                }
+                ty
+            }
+            PatKind::Tuple(elements) => {
+                Ty::Tuple(elements.iter().filter(|element| !is_rest(element)).count())
+            }
+            PatKind::Slice(_) => Ty::Slice,
+            PatKind::Path(path) | PatKind::TupleStruct(path, _) | PatKind::Struct(path, ..) => {
+                self.resolve(path).map_or(Ty::Opaque, |(ty, _)| ty)
+            }
+            PatKind::Ident(_, name, _) => {
+                self.unit_struct(&name.name).map_or(Ty::Opaque, Ty::Struct)
+            }
+            _ => Ty::Opaque,
+        }
+    }
+
+    /// Returns the constructor of the given peeled pattern, matching a value of the given type.
+    fn ctor(&self, pat: &'a Pattern, ty: Ty<'a>) -> Ctor {
+        match (&pat.kind, ty) {
+            (PatKind::Lit(expr), _) => match (LitValue::of(expr), ty) {
+                (Some(LitValue::Bool(value)), Ty::Bool) => Ctor::Bool(value),
+                (Some(LitValue::Nil), Ty::Nil) => Ctor::Nil,
+                (Some(LitValue::Int(value, _)), Ty::Int(int_ty)) => {
+                    match int_ty.unwrap_or(UNKNOWN_INT_TY).encode(value) {
+                        Some(value) => Ctor::IntRange(IntRange {
+                            lo: value,
+                            hi: value,
+                        }),
+                        None => Ctor::Opaque,
+                    }
+                }
+                (Some(value), _) => Ctor::Lit(value.to_string()),
+                (None, _) => Ctor::Opaque,
+            },
+            (PatKind::Range(start, end, limits), Ty::Int(int_ty)) => {
+                let int_ty = int_ty.unwrap_or(UNKNOWN_INT_TY);
+                let encode = |bound: &Expr| match LitValue::of(bound) {
+                    Some(LitValue::Int(value, _)) => int_ty.encode(value),
+                    _ => None,
+                };
+                let lo = match start {
+                    Some(start) => encode(start),
+                    None => Some(int_ty.encoded_min()),
+                };
+                let hi = match (end, limits) {
+                    (Some(end), RangeLimits::Closed) => encode(end),
+                    (Some(end), RangeLimits::HalfOpen) => {
+                        encode(end).and_then(|end| end.checked_sub(1))
+                    }
+                    (None, _) => Some(int_ty.encoded_max()),
+                };
+                match (lo, hi) {
+                    (Some(lo), Some(hi)) if lo <= hi => Ctor::IntRange(IntRange { lo, hi }),
+                    _ => Ctor::Opaque,
+                }
+            }
+            (PatKind::Tuple(_), Ty::Tuple(_)) => Ctor::Single,
+            (PatKind::Slice(elements), Ty::Slice) => {
+                let len = elements.len();
+                Ctor::Slice(match elements.iter().position(is_rest) {
+                    Some(rest) => SliceKind::Var(rest, len - rest - 1),
+                    None => SliceKind::Fixed(len),
+                })
+            }
+            (
+                PatKind::Path(path) | PatKind::TupleStruct(path, _) | PatKind::Struct(path, ..),
+                _,
+            ) => match self.resolve(path) {
+                Some((Ty::Struct(decl), ctor)) if matches!(ty, Ty::Struct(d) if std::ptr::eq(d, decl)) => {
+                    ctor
+                }
+                Some((Ty::Enum(decl), ctor)) if matches!(ty, Ty::Enum(d) if std::ptr::eq(d, decl)) => {
+                    ctor
+                }
+                _ => Ctor::Opaque,
+            },
+            (PatKind::Ident(..), Ty::Struct(_)) => Ctor::Single,
+            _ => Ctor::Opaque,
+        }
+    }
+
+    /// Returns the patterns matching the fields of the values matched by the given peeled
+    /// pattern, which must cover the given constructor.
+    fn fields(&self, pat: &'a Pattern, ctor: &Ctor, ty: Ty<'a>) -> Row<'a> {
+        let arity = ty.arity(ctor);
+        match &pat.kind {
+            PatKind::Tuple(elements)
+            | PatKind::TupleStruct(_, elements)
+            | PatKind::Slice(elements) => expand_rest(elements, arity),
+            PatKind::Struct(_, fields, _) => {
+                let decls = ty.fields(ctor).map_or(&[][..], VariantFields::fields);
+                let mut row = vec![None; arity];
+                for field in fields {
+                    if let Some(index) = decls
+                        .iter()
+                        .position(|decl| decl.name.name == field.name.name)
+                    {
+                        row[index] = Some(&field.pat);
+                    }
+                }
+                row
+            }
+            _ => vec![None; arity],
+        }
+    }
+
+    /// Returns the constructors of the given type, split so that each of them is either covered
+    /// or not by each of the given constructors. Returns `None` if the type is not enumerable.
+    fn all_ctors(&self, ty: Ty<'a>, column: &[Ctor]) -> Option<Vec<Ctor>> {
+        match ty {
+            Ty::Bool => Some(vec![Ctor::Bool(false), Ctor::Bool(true)]),
+            Ty::Nil => Some(vec![Ctor::Nil]),
+            Ty::Tuple(_) | Ty::Struct(_) => Some(vec![Ctor::Single]),
+            Ty::Enum(decl) => Some((0..decl.variants.len()).map(Ctor::Variant).collect()),
+            Ty::Int(int_ty) => {
+                let int_ty = int_ty.unwrap_or(UNKNOWN_INT_TY);
+                let full = IntRange {
+                    lo: int_ty.encoded_min(),
+                    hi: int_ty.encoded_max(),
+                };
+                Some(self.split(&Ctor::IntRange(full), column))
+            }
+            Ty::Slice => Some(self.split(&Ctor::Slice(SliceKind::Var(0, 0)), column)),
+            Ty::Opaque => None,
+        }
+    }
+
+    /// Splits the constructor into the constructors distinguished by the given ones.
+    fn split(&self, ctor: &Ctor, column: &[Ctor]) -> Vec<Ctor> {
+        match ctor {
+            Ctor::IntRange(range) => {
+                let ranges = column.iter().filter_map(|ctor| match ctor {
+                    Ctor::IntRange(range) => Some(*range),
+                    _ => None,
+                });
+                range
+                    .split(ranges)
+                    .into_iter()
+                    .map(Ctor::IntRange)
+                    .collect()
+            }
+            Ctor::Slice(SliceKind::Var(prefix, suffix)) => {
+                let slices = column.iter().filter_map(|ctor| match ctor {
+                    Ctor::Slice(slice) => Some(*slice),
+                    _ => None,
+                });
+                let slices = slices.chain([SliceKind::Var(*prefix, *suffix)]);
+                SliceKind::split(prefix + suffix, slices)
+                    .into_iter()
+                    .map(Ctor::Slice)
+                    .collect()
+            }
+            _ => vec![ctor.clone()],
+        }
+    }
+
+    /// Replaces the rows whose first pattern is an or-pattern by a row per alternative.
+    fn expand_or_patterns(&self, rows: &[Row<'a>]) -> Vec<Row<'a>> {
+        let mut expanded = vec![];
+        let mut pending: Vec<Row<'a>> = rows.iter().rev().cloned().collect();
+        while let Some(row) = pending.pop() {
+            match self.peel(row[0]) {
+                Some(Pattern {
+                    kind: PatKind::Or(alternatives),
+                    ..
+                }) => {
+                    for alternative in alternatives.iter().rev() {
+                        let mut row = row.clone();
+                        row[0] = Some(alternative);
+                        pending.push(row);
+                    }
+                }
+                _ => expanded.push(row),
+            }
+        }
+        expanded
+    }
+
+    /// Returns the witnesses of the usefulness of the row `v` with respect to the given rows:
+    /// the lists of values matched by `v` but by none of the rows, one per column. An empty list
+    /// means `v` is useless. The hints are what is known of the values of the first columns.
+    fn usefulness(
+        &self,
+        rows: &[Row<'a>],
+        v: &[Option<&'a Pattern>],
+        hints: &[Hint],
+    ) -> Vec<Vec<Witness<'a>>> {
+        let Some((&head, tail)) = v.split_first() else {
+            return if rows.is_empty() {
+                vec![vec![]]
+            } else {
+                vec![]
+            };
+        };
+        let head = self.peel(head);
+        if let Some(Pattern {
+            kind: PatKind::Or(alternatives),
+            ..
+        }) = head
+        {
+            return alternatives
+                .iter()
+                .flat_map(|alternative| {
+                    let mut v = vec![Some(alternative)];
+                    v.extend_from_slice(tail);
+                    self.usefulness(rows, &v, hints)
+                })
+                .collect();
+        }
+
+        let rows = self.expand_or_patterns(rows);
+        let heads: Vec<_> = rows.iter().filter_map(|row| self.peel(row[0])).collect();
+        let ty = heads
+            .iter()
+            .chain(&head)
+            .map(|pat| self.pat_ty(pat))
+            .reduce(Ty::merge)
+            .unwrap_or(Ty::Opaque);
+        let (hint, tail_hints) = hints.split_first().unwrap_or((&Hint::Unknown, &[]));
+        let ty = match ty {
+            Ty::Int(None) => Ty::Int(hint.int()),
+            ty => ty,
+        };
+        let column: Vec<_> = heads.iter().map(|pat| self.ctor(pat, ty)).collect();
+
+        let mut witnesses = vec![];
+        match head {
+            Some(pat) => {
+                for ctor in self.split(&self.ctor(pat, ty), &column) {
+                    let fields = self.fields(pat, &ctor, ty);
+                    witnesses
+                        .extend(self.specialized_usefulness(&rows, fields, tail, hints, &ctor, ty));
+                }
+            }
+            None => {
+                // Going through the present constructors is only needed to find the witnesses when
+                // the missing ones make the wildcard useful, but lists every missing pattern. The
+                // witnesses are sorted in the order of their constructors.
+                let mut sorted_witnesses = vec![];
+                let mut missing = vec![];
+                let all_ctors = self.all_ctors(ty, &column).unwrap_or_default();
+                for (index, ctor) in all_ctors.into_iter().enumerate() {
+                    if column.iter().any(|covering| covering.covers(&ctor)) {
+                        let fields = vec![None; ty.arity(&ctor)];
+                        let found =
+                            self.specialized_usefulness(&rows, fields, tail, hints, &ctor, ty);
+                        sorted_witnesses.extend(found.into_iter().map(|witness| (index, witness)));
+                    } else {
+                        missing.push((index, ctor));
+                    }
+                }
+                if column.is_empty() || matches!(ty, Ty::Opaque) {
+                    missing = vec![(0, Ctor::Wild)];
+                }
+                if !missing.is_empty() {
+                    // The values built by the missing constructors are only matched by the rows
+                    // starting with a wildcard
+                    let default: Vec<Row<'a>> = rows
+                        .iter()
+                        .filter(|row| self.peel(row[0]).is_none())
+                        .map(|row| row[1..].to_vec())
+                        .collect();
+                    let default_witnesses = self.usefulness(&default, tail, tail_hints);
+                    for (index, ctor) in merge_ranges(missing) {
+                        for witness in &default_witnesses {
+                            let head = Witness {
+                                ctor: ctor.clone(),
+                                ty,
+                                fields: vec![Witness::wild(); ty.arity(&ctor)],
+                            };
+                            sorted_witnesses.push((index, [vec![head], witness.clone()].concat()));
+                        }
+                    }
+                }
+                sorted_witnesses.sort_by_key(|(index, _)| *index);
+                witnesses.extend(sorted_witnesses.into_iter().map(|(_, witness)| witness));
+            }
+        }
+        witnesses
+    }
+
+    /// Returns the witnesses of the usefulness of `v`, whose first pattern matches values built
+    /// by the given constructor with the given fields, with respect to the rows matching such
+    /// values. The hints are what is known of the values of the first columns of the rows.
+    fn specialized_usefulness(
+        &self,
+        rows: &[Row<'a>],
+        v_fields: Row<'a>,
+        v_tail: &[Option<&'a Pattern>],
+        hints: &[Hint],
+        ctor: &Ctor,
+        ty: Ty<'a>,
+    ) -> Vec<Vec<Witness<'a>>> {
+        let arity = ty.arity(ctor);
+        let specialized: Vec<Row<'a>> = rows
+            .iter()
+            .filter_map(|row| {
+                let mut fields = match self.peel(row[0]) {
+                    None => vec![None; arity],
+                    Some(pat) if self.ctor(pat, ty).covers(ctor) => self.fields(pat, ctor, ty),
+                    Some(_) => return None,
+                };
+                fields.extend_from_slice(&row[1..]);
+                Some(fields)
+            })
+            .collect();
+        let v = [v_fields, v_tail.to_vec()].concat();
+        let (hint, tail_hints) = hints.split_first().unwrap_or((&Hint::Unknown, &[]));
+        let hints = [self.field_hints(ty, ctor, hint), tail_hints.to_vec()].concat();
+        self.usefulness(&specialized, &v, &hints)
+            .into_iter()
+            .map(|mut witness| {
+                let fields = witness.drain(..arity).collect();
+                let head = Witness {
+                    ctor: ctor.clone(),
+                    ty,
+                    fields,
+                };
+                [vec![head], witness].concat()
+            })
+            .collect()
+    }
+}
+
+/// Merges the adjacent integer ranges of the given sorted constructors, for reporting.
+fn merge_ranges(ctors: Vec<(usize, Ctor)>) -> Vec<(usize, Ctor)> {
+    let mut merged: Vec<(usize, Ctor)> = vec![];
+    for (index, ctor) in ctors {
+        if let (Some((_, Ctor::IntRange(last))), Ctor::IntRange(range)) = (merged.last_mut(), &ctor)
+        {
+            if last.hi.checked_add(1) == Some(range.lo) {
+                last.hi = range.hi;
+                continue;
+            }
+        }
+        merged.push((index, ctor));
+    }
+    merged
+}
+
+/// Collects the ids of the binding patterns of a pattern.
+#[derive(Default)]
+struct BindingCollector(Vec<NodeId>);
+
+impl<'a> Visitor<'a> for BindingCollector {
+    fn visit_pat(&mut self, pat: &'a Pattern) {
+        if let PatKind::Ident(..) = pat.kind {
+            self.0.push(pat.id);
+        }
+        visit::walk_pat(self, pat);
+    }
+}
+
+impl<'a> Visitor<'a> for Checker<'a> {
+    fn visit_item(&mut self, item: &'a Item) {
+        match &item.kind {
+            ItemKind::Fn(decl) => self.deferred.push((item.id, decl)),
+            ItemKind::Const(_) => {
+                let frames = std::mem::replace(&mut self.frames, vec![Some(item.id)]);
+                visit::walk_item(self, item);
+                self.frames = frames;
+            }
+            _ => visit::walk_item(self, item),
+        }
+    }
+
+    fn visit_local(&mut self, local: &'a Local) {
+        visit::walk_local(self, local);
+        let hint = match (local.ty.as_ref().map(|ty| self.type_hint(ty)), &local.init) {
+            (Some(Hint::Unknown) | None, Some(init)) => self.expr_hint(init),
+            (hint, _) => hint.unwrap_or_default(),
+        };
+        self.declare_pattern(&local.pat, hint);
+    }
+
+    fn visit_param(&mut self, param: &'a Param) {
+        visit::walk_param(self, param);
+        let hint = param
+            .ty
+            .as_ref()
+            .map_or(Hint::Unknown, |ty| self.type_hint(ty));
+        self.declare_pattern(&param.pat, hint);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        crate::ice::set_current_span(expr.span);
+        match &expr.kind {
+            ExprKind::Match(scrutinee, arms) => self.check_match(scrutinee, arms),
+            ExprKind::Closure(_) => {
+                self.frames.push(Some(expr.id));
+                visit::walk_expr(self, expr);
+                self.frames.pop();
+                return;
+            }
+            _ => (),
+        }
+        visit::walk_expr(self, expr);
+    }
+
+    fn visit_pat(&mut self, pat: &'a Pattern) {
+        // Patterns only contain literal expressions, there is nothing more to check inside them
+        self.check_pattern(pat, Position::Other);
+        let mut bindings = BindingCollector::default();
+        bindings.visit_pat(pat);
+        for id in bindings.0 {
+            self.declare(id, Hint::Unknown);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,275 @@
+use crate::test_utils::check;
+
+/// Checks a match of `x` with the given arms, returning the emitted diagnostics.
+fn check_arms(arms: &str) -> Vec<String> {
+    check(&format!(
+        "enum Shape {{ Circle(f64), Rect {{ w: f64, h: f64 }}, Empty }}\n\
+         struct Point {{ x: i64, y: i64 }}\n\
+         fn f(x, cond) {{ match x {{ {arms} }} }}"
+    ))
+}
+
+/// Checks the given statements in a function with the parameters `x` and `p`, returning the
+/// emitted diagnostics.
+fn check_stmts(stmts: &str) -> Vec<String> {
+    check(&format!(
+        "struct Point(i64, i64);\nfn f(x, p) {{ {stmts} }}"
+    ))
+}
+
+fn non_exhaustive(patterns: &str) -> Vec<String> {
+    vec![format!(
+        "error: non-exhaustive patterns: {patterns} not covered"
+    )]
+}
+
+#[test]
+fn exhaustive_matches() {
+    let arms = [
+        "true => 1, false => 2",
+        "_ => 1",
+        "x => x",
+        "Shape::Circle(_) => 1, Shape::Rect { .. } => 2, Shape::Empty => 3",
+        "Shape::Circle(r) | Shape::Rect { w: r, .. } => r, Shape::Empty => 0.0",
+        "(true, _) => 1, (_, true) => 2, (false, false) => 3",
+        "Point { x: 0, y } => y, Point { x, .. } => x",
+        "..=-1 => 1, 0 => 2, 1..=9 => 3, 10.. => 4",
+        "0u8..128 => 1, 128u8..=255 => 2",
+        "[] => 0, [x] => 1, [x, y, ..] => 2",
+        "[] => 0, [.., last] => last",
+        "nil => 0",
+    ];
+    for arms in arms {
+        assert_eq!(check_arms(arms), Vec::<String>::new(), "{arms}");
+    }
+}
+
+#[test]
+fn missing_variants_are_listed() {
+    assert_eq!(
+        check_arms("Shape::Circle(_) => 1"),
+        non_exhaustive("`Shape::Rect { .. }` and `Shape::Empty`")
+    );
+    assert_eq!(check_arms("true => 1"), non_exhaustive("`false`"));
+    assert_eq!(
+        check_arms("(true, true) => 1, (false, _) => 2"),
+        non_exhaustive("`(true, false)`")
+    );
+    assert_eq!(
+        check_arms("Shape::Rect { w: 1.0, .. } => 1, Shape::Circle(_) | Shape::Empty => 2"),
+        non_exhaustive("`Shape::Rect { .. }`")
+    );
+    assert_eq!(
+        check_arms("Point { x: true, y: false } => 1, Point { y: true, .. } => 2"),
+        non_exhaustive("`Point { x: false, y: false }`")
+    );
+}
+
+#[test]
+fn builtin_enums() {
+    assert_eq!(check_arms("Some(_) => 1, None => 2"), Vec::<String>::new());
+    assert_eq!(check_arms("Ok(x) | Err(x) => x"), Vec::<String>::new());
+    assert_eq!(
+        check_arms("Some(1) => 1"),
+        non_exhaustive("`None` and `Some(_)`")
+    );
+    assert_eq!(
+        check("fn f(x: Option<i64>) { match x { Some(1) => 1 } }"),
+        non_exhaustive(
+            "`None`, `Some(-9223372036854775808..=0)` and `Some(2..=9223372036854775807)`"
+        )
+    );
+    assert_eq!(
+        check_arms("Ok(Some(_)) => 1, Err(_) => 2"),
+        non_exhaustive("`Ok(None)`")
+    );
+    assert_eq!(check_arms("Option::None => 1"), non_exhaustive("`Some(_)`"));
+    // The enums of the program shadow the built-in ones
+    assert_eq!(
+        check("enum Option { None, Some(i64), Other }\nfn f(x) { match x { Option::None => 1 } }"),
+        non_exhaustive("`Option::Some(_)` and `Option::Other`")
+    );
+}
+
+#[test]
+fn missing_integer_ranges_are_listed() {
+    assert_eq!(
+        check("fn f(x: i64) { match x { 0 => 1, 5..=9 => 2 } }"),
+        non_exhaustive("`-9223372036854775808..=-1`, `1..=4` and `10..=9223372036854775807`")
+    );
+    assert_eq!(check_arms("0u8..=254 => 1"), non_exhaustive("`255`"));
+    assert_eq!(
+        check("fn f(x: i64) { match x { 1.. => 1 } }"),
+        non_exhaustive("`-9223372036854775808..=0`")
+    );
+    // The values of an unknown integer type are not listed
+    assert_eq!(check_arms("0 => 1, 5..=9 => 2"), non_exhaustive("`_`"));
+    assert_eq!(check_arms("0..=255 => 1"), non_exhaustive("`_`"));
+}
+
+#[test]
+fn literals_take_the_integer_type_of_the_scrutinee() {
+    let matches = [
+        "fn f(x: u8) { match x { 0..=255 => 1 } }",
+        "let x: u8 = 1;\nfn f() { match x { 0..=127 => 1, 128.. => 2 } }",
+        "fn f(x) { match (x as u8) { ..=127 => 1, 128.. => 2 } }",
+        "fn f(x: i8) { match -x { -128..=-1 => 1, 0..=127 => 2 } }",
+        "fn f() { match u8::MAX { 0..=255 => 1 } }",
+        "fn f(x: u8) { let g = || match x + 1 { 0..=255 => 1 }; }",
+        // The type of the suffixed arms applies to the other ones
+        "fn f(x) { match x { 0u8 => 1, 1..=255 => 2 } }",
+        // The type is taken from the initializer of the variable
+        "let x = 1u8;\nmatch x { 0..=255 => 1 }",
+        "let x = 300 as u8;\nmatch x { 0..=255 => 1 }",
+        // The type is pushed down into tuples and variants
+        "match (1u8, true) { (0..=255, _) => 1 }",
+        "match Some(1u8) { Some(0..=255) => 1, None => 0 }",
+        "let t = (1u8, Some(2u16));\nmatch t { (0..=255, Some(0..=65535) | None) => 1 }",
+        "fn f(x: Result<u8, i8>) { match x { Ok(0..=255) => 1, Err(-128..=127) => 2 } }",
+        // The type is taken from the declared types of the fields
+        "struct S { v: u8 }\nfn f(s) { match s { S { v: 0..=255 } => 1 } }",
+        "enum E { A(u8), B { v: (bool, i8) } }\n\
+         fn f(e) { match e { E::A(0..=255) => 1, E::B { v: (_, -128..=127) } => 2 } }",
+    ];
+    for source in matches {
+        assert_eq!(check(source), Vec::<String>::new(), "{source}");
+    }
+    assert_eq!(
+        check("fn f(x: u16) { match x { 0..=10 => 1 } }"),
+        non_exhaustive("`11..=65535`")
+    );
+    assert_eq!(
+        check("match Some(1u8) { Some(0..=254) => 1, None => 0 }"),
+        non_exhaustive("`Some(255)`")
+    );
+    // A binding shadowing the annotated variable has no known type
+    assert_eq!(
+        check("fn f(x: u8) { let x = 1; match x { 0..=255 => 1 } }"),
+        non_exhaustive("`_`")
+    );
+}
+
+#[test]
+fn missing_slices_are_listed() {
+    assert_eq!(check_arms("[] => 0"), non_exhaustive("`[_, ..]`"));
+    assert_eq!(
+        check_arms("[] => 0, [_, _, ..] => 2"),
+        non_exhaustive("`[_]`")
+    );
+    assert_eq!(
+        check_arms("[true, ..] => 0, [.., false] => 1"),
+        non_exhaustive("`[]` and `[false, .., true]`")
+    );
+}
+
+#[test]
+fn values_of_unknown_types_need_a_wildcard() {
+    assert_eq!(check_arms("\"a\" => 1, \"b\" => 2"), non_exhaustive("`_`"));
+    assert_eq!(check_arms("1 => 1, true => 2"), non_exhaustive("`_`"));
+    assert_eq!(check_arms(""), non_exhaustive("`_`"));
+}
+
+#[test]
+fn guarded_arms_do_not_count() {
+    assert_eq!(
+        check_arms("true if cond => 1, false => 2"),
+        non_exhaustive("`true`")
+    );
+    assert_eq!(check_arms("n if n > 0 => 1, _ => 2"), Vec::<String>::new());
+}
+
+#[test]
+fn many_missing_patterns_are_summarized() {
+    assert_eq!(
+        check_arms("(Shape::Empty, Shape::Empty) => 1"),
+        non_exhaustive(
+            "`(Shape::Circle(_), _)`, `(Shape::Rect { .. }, _)`, \
+             `(Shape::Empty, Shape::Circle(_))` and 1 more"
+        )
+    );
+}
+
+#[test]
+fn unreachable_arms() {
+    assert_eq!(
+        check_arms("_ => 1, true => 2"),
+        ["warning: unreachable pattern"]
+    );
+    assert_eq!(
+        check_arms("1..=10 => 1, 5 => 2, _ => 3"),
+        ["warning: unreachable pattern"]
+    );
+    assert_eq!(
+        check_arms("\"a\" => 1, \"a\" => 2, _ => 3"),
+        ["warning: unreachable pattern"]
+    );
+    assert_eq!(
+        check_arms("Shape::Empty | Shape::Empty => 1, _ => 2"),
+        ["warning: unreachable pattern"]
+    );
+    assert_eq!(
+        check_arms("[..] => 1, [x] => 2"),
+        ["warning: unreachable pattern"]
+    );
+    // A guarded arm may not match, so the arms after it stay reachable
+    assert_eq!(
+        check_arms("x if x => 1, true => 2, false => 3"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn nested_matches_are_checked() {
+    assert_eq!(
+        check("fn f(x) { let y = match x { true => match x { false => 1 }, false => 2 }; }"),
+        non_exhaustive("`true`")
+    );
+}
+
+#[test]
+fn invalid_range_patterns() {
+    assert_eq!(
+        check_stmts("let 5..=1 = x;"),
+        ["error: lower range bound must be less than or equal to upper"]
+    );
+    assert_eq!(
+        check_stmts("let 1..1 = x;"),
+        ["error: lower range bound must be less than upper"]
+    );
+    assert_eq!(
+        check_stmts("let \"a\"..=\"z\" = x;"),
+        ["error: only numbers are allowed in range patterns"]
+    );
+    assert_eq!(
+        check_stmts("let 1..=2.0 = x;"),
+        ["error: mismatched types of range pattern bounds"]
+    );
+    assert_eq!(
+        check_stmts("let -5..=-1 = x; let 0.5..1.5 = x;"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn misplaced_rest_patterns() {
+    assert_eq!(
+        check_stmts("let .. = x;"),
+        ["error: `..` patterns are not allowed here"]
+    );
+    assert_eq!(
+        check_stmts("let (a, .., b, ..) = x;"),
+        ["error: `..` can only be used once per tuple pattern"]
+    );
+    assert_eq!(
+        check_stmts("let [rest @ .., last @ ..] = x;"),
+        ["error: `..` can only be used once per slice pattern"]
+    );
+    assert_eq!(
+        check_stmts("let (rest @ ..) = x;"),
+        ["error: `..` patterns are not allowed here"]
+    );
+    assert_eq!(
+        check_stmts("let [first, rest @ ..] = x; let Point(a, ..) = p;"),
+        Vec::<String>::new()
+    );
+}
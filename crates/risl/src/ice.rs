@@ -0,0 +1,259 @@
+//! Reporting of internal compiler errors (ICE).
+//!
+//! Any panic occurring while a program is processed, be it an explicit `Level::Bug` diagnostic or
+//! an unexpected panic in the interpreter itself, is a bug in Risl. The panic hook installed by
+//! [`install_panic_hook`] replaces the raw Rust panic dump by a structured report telling the user
+//! what was being processed when the bug occurred and where to report it.
+
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+
+use crate::parser::diagnostic::ExplicitBug;
+use crate::parser::lexer::Span;
+use crate::parser::source_map::{Location, SourceMap};
+
+/// The URL where bugs in Risl must be reported.
+pub const BUG_REPORT_URL: &str = "https://github.com/juliencombattelli/risl/issues/new";
+
+/// The location in the user program being processed, reported in the ICE report.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct IceLocation {
+    pub file: Option<String>,
+    pub span: Option<Span>,
+    /// The line and column of the start of the span, if the source of the file is known.
+    pub position: Option<Location>,
+}
+
+/// The file being processed, with its source once it is read.
+struct CurrentFile {
+    name: String,
+    source_map: Option<SourceMap>,
+}
+
+thread_local! {
+    static CURRENT_FILE: RefCell<Option<CurrentFile>> = const { RefCell::new(None) };
+    // Set by every pass for each node it processes, so it must be cheap to update
+    static CURRENT_SPAN: Cell<Option<Span>> = const { Cell::new(None) };
+}
+
+/// Records the name of the file being processed.
+pub fn set_current_file(file: impl Into<String>) {
+    let file = CurrentFile {
+        name: file.into(),
+        source_map: None,
+    };
+    CURRENT_FILE.with(|current| *current.borrow_mut() = Some(file));
+    CURRENT_SPAN.with(|span| span.set(None));
+}
+
+/// Records the source of the file being processed, to report the lines and columns of the spans.
+pub fn set_current_source(source_map: &SourceMap) {
+    let file = CurrentFile {
+        name: String::from(source_map.name()),
+        source_map: Some(source_map.clone()),
+    };
+    CURRENT_FILE.with(|current| *current.borrow_mut() = Some(file));
+    CURRENT_SPAN.with(|span| span.set(None));
+}
+
+/// Records the span of the file being processed.
+pub fn set_current_span(span: Span) {
+    CURRENT_SPAN.with(|current| current.set(Some(span)));
+}
+
+/// Returns the location being processed by the current thread.
+pub fn current_location() -> IceLocation {
+    let span = CURRENT_SPAN.with(Cell::get);
+    let file = CURRENT_FILE.with(|current| Some(current.borrow().as_ref()?.name.clone()));
+    IceLocation {
+        file,
+        span,
+        position: span.and_then(position),
+    }
+}
+
+/// Returns the line and column of the start of the span in the file being processed, if its
+/// source is known.
+fn position(span: Span) -> Option<Location> {
+    CURRENT_FILE.with(|current| {
+        let current = current.borrow();
+        Some(current.as_ref()?.source_map.as_ref()?.location(span.start))
+    })
+}
+
+/// A structured report of an internal compiler error.
+pub struct IceReport {
+    pub message: String,
+    /// The location of the panic in the Risl sources, if the panic was not an explicit bug.
+    pub panic_location: Option<String>,
+    pub location: IceLocation,
+    pub version: &'static str,
+    pub backtrace: Option<String>,
+}
+
+impl IceReport {
+    /// Builds a report from the information provided to a panic hook.
+    pub fn from_panic_info(info: &std::panic::PanicHookInfo) -> Self {
+        let payload = info.payload();
+        let mut location = current_location();
+        let (message, panic_location) = if let Some(bug) = payload.downcast_ref::<ExplicitBug>() {
+            if let Some(span) = bug.span {
+                location.span = Some(span);
+                location.position = position(span);
+            }
+            (bug.message.clone(), None)
+        } else {
+            let message = if let Some(message) = payload.downcast_ref::<&str>() {
+                String::from(*message)
+            } else if let Some(message) = payload.downcast_ref::<String>() {
+                message.clone()
+            } else {
+                String::from("unknown panic payload")
+            };
+            (message, info.location().map(|l| l.to_string()))
+        };
+        Self {
+            message,
+            panic_location,
+            location,
+            version: env!("CARGO_PKG_VERSION"),
+            backtrace: Some(Backtrace::force_capture().to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for IceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "error: internal compiler error: {}", self.message)?;
+        let location = &self.location;
+        match (&location.file, location.span, location.position) {
+            (Some(file), _, Some(Location { line, column })) => {
+                writeln!(f, " --> {file}:{line}:{column}")?
+            }
+            (Some(file), Some(span), None) => {
+                writeln!(f, " --> {file}:{}..{}", span.start, span.end)?
+            }
+            (Some(file), None, None) => writeln!(f, " --> {file}")?,
+            (None, Some(span), _) => writeln!(f, " --> {}..{}", span.start, span.end)?,
+            (None, None, _) => (),
+        }
+        writeln!(f)?;
+        if let Some(panic_location) = &self.panic_location {
+            writeln!(f, "note: risl unexpectedly panicked at {panic_location}")?;
+        }
+        writeln!(f, "note: this is a bug in risl, not in your program")?;
+        writeln!(
+            f,
+            "note: we would appreciate a bug report: {BUG_REPORT_URL}"
+        )?;
+        writeln!(
+            f,
+            "note: risl {} running on {}-{}",
+            self.version,
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        )?;
+        if let Some(backtrace) = &self.backtrace {
+            writeln!(f, "stack backtrace:")?;
+            write!(f, "{backtrace}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the default panic hook by one printing an ICE report on the standard error.
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        eprint!("{}", IceReport::from_panic_info(info));
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::diagnostic::{DiagContext, Diagnostic, Level};
+    use crate::parser::emitter::new_emitter_none;
+
+    fn report(location: IceLocation) -> IceReport {
+        IceReport {
+            message: String::from("something went wrong"),
+            panic_location: None,
+            location,
+            version: "1.2.3",
+            backtrace: None,
+        }
+    }
+
+    #[test]
+    fn report_with_file_and_span() {
+        let report = report(IceLocation {
+            file: Some(String::from("main.risl")),
+            span: Some(Span::new(4, 10)),
+            position: Some(Location { line: 2, column: 3 }),
+        });
+        let report = report.to_string();
+        assert!(report.starts_with("error: internal compiler error: something went wrong\n"));
+        assert!(report.contains(" --> main.risl:2:3\n"));
+        assert!(report.contains(BUG_REPORT_URL));
+        assert!(report.contains("note: risl 1.2.3 running on"));
+    }
+
+    #[test]
+    fn report_with_span_without_source() {
+        let report = report(IceLocation {
+            file: Some(String::from("main.risl")),
+            span: Some(Span::new(4, 10)),
+            position: None,
+        });
+        assert!(report.to_string().contains(" --> main.risl:4..10\n"));
+    }
+
+    #[test]
+    fn report_without_location() {
+        let report = report(IceLocation::default()).to_string();
+        assert!(!report.contains(" --> "));
+    }
+
+    #[test]
+    fn location_tracking() {
+        set_current_span(Span::new(1, 2));
+        set_current_file("file.risl");
+        assert_eq!(current_location().span, None);
+        set_current_span(Span::new(3, 4));
+        assert_eq!(
+            current_location(),
+            IceLocation {
+                file: Some(String::from("file.risl")),
+                span: Some(Span::new(3, 4)),
+                position: None,
+            }
+        );
+    }
+
+    #[test]
+    fn location_tracking_with_source() {
+        set_current_source(&SourceMap::new("main.risl", "let a = 1;\nlet b = a;\n"));
+        assert_eq!(current_location().span, None);
+        set_current_span(Span::new(19, 20));
+        assert_eq!(
+            current_location(),
+            IceLocation {
+                file: Some(String::from("main.risl")),
+                span: Some(Span::new(19, 20)),
+                position: Some(Location { line: 2, column: 9 }),
+            }
+        );
+    }
+
+    #[test]
+    fn bug_diagnostic_panics_with_explicit_bug() {
+        let diag_ctx = DiagContext::new(new_emitter_none());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            diag_ctx.emit(Diagnostic::spanned(Level::Bug, Span::new(0, 1), "oops"));
+        }));
+        let payload = result.unwrap_err();
+        let bug = payload.downcast_ref::<ExplicitBug>().unwrap();
+        assert_eq!(bug.message, "oops");
+        assert_eq!(bug.span, Some(Span::new(0, 1)));
+    }
+}
@@ -0,0 +1,286 @@
+//! The integer types, with the exact semantics of Rust integers in debug builds: an arithmetic
+//! operation overflowing the range of its type is an error, and the `wrapping_*`, `saturating_*`
+//! and `checked_*` operations handle the overflows explicitly.
+//!
+//! The integers of every type are computed as `i128`, which is wide enough to hold the exact
+//! result of an operation on two values of the types up to 64 bits before checking that it fits
+//! the type. The values of `i128` and `u128` fall back to the checked operations of the 128-bit
+//! types, the values of `u128` being stored as their bits.
+//!
+//! These operations are shared by the constant evaluator and the runtime.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::parser::ast::BinOp;
+
+/// Why an operation on integers did not produce a value.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum IntError {
+    Overflow,
+    DivisionByZero,
+}
+
+impl IntError {
+    /// Returns the message of the error raised by the operator.
+    pub fn message(self, op: BinOp) -> &'static str {
+        match (self, op) {
+            (IntError::DivisionByZero, BinOp::Div) => "attempt to divide by zero",
+            (IntError::DivisionByZero, _) => {
+                "attempt to calculate the remainder with a divisor of zero"
+            }
+            (IntError::Overflow, BinOp::Add) => "attempt to add with overflow",
+            (IntError::Overflow, BinOp::Sub) => "attempt to subtract with overflow",
+            (IntError::Overflow, BinOp::Mul) => "attempt to multiply with overflow",
+            (IntError::Overflow, BinOp::Div) => "attempt to divide with overflow",
+            (IntError::Overflow, BinOp::Rem) => "attempt to calculate the remainder with overflow",
+            (IntError::Overflow, BinOp::Shl) => "attempt to shift left with overflow",
+            (IntError::Overflow, _) => "attempt to shift right with overflow",
+        }
+    }
+}
+
+/// A built-in integer type.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum IntTy {
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Isize,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    Usize,
+}
+
+impl IntTy {
+    pub const ALL: [IntTy; 12] = [
+        IntTy::I8,
+        IntTy::I16,
+        IntTy::I32,
+        IntTy::I64,
+        IntTy::I128,
+        IntTy::Isize,
+        IntTy::U8,
+        IntTy::U16,
+        IntTy::U32,
+        IntTy::U64,
+        IntTy::U128,
+        IntTy::Usize,
+    ];
+
+    /// Returns the integer type with the given name, like `u8`.
+    pub fn from_name(name: &str) -> Option<IntTy> {
+        IntTy::ALL.into_iter().find(|ty| ty.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntTy::I8 => "i8",
+            IntTy::I16 => "i16",
+            IntTy::I32 => "i32",
+            IntTy::I64 => "i64",
+            IntTy::I128 => "i128",
+            IntTy::Isize => "isize",
+            IntTy::U8 => "u8",
+            IntTy::U16 => "u16",
+            IntTy::U32 => "u32",
+            IntTy::U64 => "u64",
+            IntTy::U128 => "u128",
+            IntTy::Usize => "usize",
+        }
+    }
+
+    pub fn is_signed(self) -> bool {
+        matches!(
+            self,
+            IntTy::I8 | IntTy::I16 | IntTy::I32 | IntTy::I64 | IntTy::I128 | IntTy::Isize
+        )
+    }
+
+    pub fn bits(self) -> u32 {
+        match self {
+            IntTy::I8 | IntTy::U8 => 8,
+            IntTy::I16 | IntTy::U16 => 16,
+            IntTy::I32 | IntTy::U32 => 32,
+            IntTy::I64 | IntTy::U64 | IntTy::Isize | IntTy::Usize => 64,
+            IntTy::I128 | IntTy::U128 => 128,
+        }
+    }
+
+    /// Returns the minimum and maximum values of the type, capping the maximum of `u128`.
+    pub fn range(self) -> (i128, i128) {
+        let bits = self.bits();
+        match (self.is_signed(), bits) {
+            (true, 128) => (i128::MIN, i128::MAX),
+            (true, _) => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+            (false, 128) => (0, i128::MAX),
+            (false, _) => (0, (1 << bits) - 1),
+        }
+    }
+}
+
+impl fmt::Display for IntTy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Returns the value of the associated constant of the integer type with the given name, like
+/// `u8::MAX`.
+pub fn associated_const(ty: IntTy, name: &str) -> Option<i128> {
+    let (min, max) = ty.range();
+    match name {
+        "MIN" => Some(min),
+        "MAX" if ty == IntTy::U128 => Some(u128::MAX as i128),
+        "MAX" => Some(max),
+        _ => None,
+    }
+}
+
+/// Truncates the value to the bits of the integer type, as a cast does.
+pub fn wrap(value: i128, ty: IntTy) -> i128 {
+    let bits = ty.bits();
+    if bits == 128 {
+        return value;
+    }
+    if ty.is_signed() {
+        let shift = 128 - bits;
+        (value << shift) >> shift
+    } else {
+        value & ((1 << bits) - 1)
+    }
+}
+
+pub fn in_range(value: i128, ty: IntTy) -> bool {
+    wrap(value, ty) == value
+}
+
+/// Returns the value of an integer literal of the type, negated if `negative`, or `None` if it
+/// does not fit the type.
+pub fn lit(value: u128, negative: bool, ty: IntTy) -> Option<i128> {
+    let (min, max) = ty.range();
+    let fits = match (negative, ty) {
+        (_, IntTy::U128) => !negative || value == 0,
+        (false, _) => value <= max as u128,
+        (true, _) => value <= min.unsigned_abs(),
+    };
+    if !fits {
+        return None;
+    }
+    Some(if negative {
+        (value as i128).wrapping_neg()
+    } else {
+        value as i128
+    })
+}
+
+/// Applies an arithmetic or bitwise operator to integers of the type, checking for overflows.
+pub fn int_op(op: BinOp, lhs: i128, rhs: i128, ty: IntTy) -> Result<i128, IntError> {
+    if matches!(op, BinOp::Div | BinOp::Rem) && rhs == 0 {
+        return Err(IntError::DivisionByZero);
+    }
+    if ty == IntTy::U128 {
+        let (lhs, rhs) = (lhs as u128, rhs as u128);
+        let value = match op {
+            BinOp::Add => lhs.checked_add(rhs),
+            BinOp::Sub => lhs.checked_sub(rhs),
+            BinOp::Mul => lhs.checked_mul(rhs),
+            BinOp::Div => lhs.checked_div(rhs),
+            BinOp::Rem => lhs.checked_rem(rhs),
+            BinOp::BitAnd => Some(lhs & rhs),
+            BinOp::BitOr => Some(lhs | rhs),
+            _ => Some(lhs ^ rhs),
+        };
+        return value.map(|value| value as i128).ok_or(IntError::Overflow);
+    }
+    let value = match op {
+        BinOp::Add => lhs.checked_add(rhs),
+        BinOp::Sub => lhs.checked_sub(rhs),
+        BinOp::Mul => lhs.checked_mul(rhs),
+        BinOp::Div => lhs.checked_div(rhs),
+        BinOp::Rem => lhs.checked_rem(rhs),
+        BinOp::BitAnd => Some(lhs & rhs),
+        BinOp::BitOr => Some(lhs | rhs),
+        _ => Some(lhs ^ rhs),
+    };
+    value
+        .filter(|value| in_range(*value, ty))
+        .ok_or(IntError::Overflow)
+}
+
+/// Shifts an integer of the type by the amount, which must be less than its number of bits.
+pub fn shift(op: BinOp, lhs: i128, amount: Option<u32>, ty: IntTy) -> Result<i128, IntError> {
+    match amount.filter(|amount| *amount < ty.bits()) {
+        Some(amount) if op == BinOp::Shl => Ok(wrap(lhs << amount, ty)),
+        Some(amount) if ty == IntTy::U128 => Ok(((lhs as u128) >> amount) as i128),
+        Some(amount) => Ok(lhs >> amount),
+        None => Err(IntError::Overflow),
+    }
+}
+
+/// Adds, subtracts or multiplies integers of the type, wrapping around at the bounds of the type.
+pub fn wrapping_op(op: BinOp, lhs: i128, rhs: i128, ty: IntTy) -> i128 {
+    // The low bits of the results are the same as with an infinite precision
+    let value = match op {
+        BinOp::Add => lhs.wrapping_add(rhs),
+        BinOp::Sub => lhs.wrapping_sub(rhs),
+        _ => lhs.wrapping_mul(rhs),
+    };
+    wrap(value, ty)
+}
+
+/// Adds, subtracts or multiplies integers of the type, saturating at the bounds of the type.
+pub fn saturating_op(op: BinOp, lhs: i128, rhs: i128, ty: IntTy) -> i128 {
+    let signed = ty.is_signed();
+    int_op(op, lhs, rhs, ty).unwrap_or_else(|_| {
+        let negative = |value: i128| signed && value < 0;
+        let above = match op {
+            BinOp::Add => !negative(rhs),
+            BinOp::Sub => negative(rhs),
+            _ => negative(lhs) == negative(rhs),
+        };
+        match (above, ty) {
+            (true, IntTy::U128) => u128::MAX as i128,
+            (true, _) => ty.range().1,
+            (false, _) => ty.range().0,
+        }
+    })
+}
+
+/// Compares integers, of the same type or not.
+pub fn cmp(lhs: (i128, IntTy), rhs: (i128, IntTy)) -> Ordering {
+    // The values of `u128` above the maximum of `i128` have negative bits
+    let key = |(value, ty): (i128, IntTy)| (ty == IntTy::U128 && value < 0, value);
+    key(lhs).cmp(&key(rhs))
+}
+
+pub fn to_f64(value: i128, ty: IntTy) -> f64 {
+    if ty == IntTy::U128 {
+        value as u128 as f64
+    } else {
+        value as f64
+    }
+}
+
+/// Converts the float to the integer type, saturating at its bounds, as a cast does.
+pub fn from_f64(value: f64, ty: IntTy) -> i128 {
+    if ty == IntTy::U128 {
+        return value as u128 as i128;
+    }
+    let (min, max) = ty.range();
+    (value as i128).clamp(min, max)
+}
+
+/// Returns the integer of the type written in decimal.
+pub fn to_string(value: i128, ty: IntTy) -> String {
+    if ty == IntTy::U128 {
+        (value as u128).to_string()
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,917 @@
+//! The tree-walking interpreter, running a checked program.
+//!
+//! The interpreter evaluates the AST directly, using the side tables of the previous passes: the
+//! resolutions give the slot of each variable in the frame of its function, closure or of the
+//! top-level code, and the values of the constants come from the constant evaluator. The program
+//! must have passed the static checks: the errors they report are not checked again, and running
+//! an invalid program is a bug.
+//!
+//! Operations that cannot be checked statically, like an arithmetic overflow, an out of bounds
+//! index or a failed assertion, stop the program with a [`RuntimeError`], which records the calls
+//! it went through to be reported as a backtrace. The semantics of the operations are shared
+//! with the virtual machine, in the [`runtime`](crate::runtime) module.
+//!
+//! Values are reference counted and, unlike on the virtual machine, the cycles are never collected:
+//! the interpreter keeps the values being evaluated on the host stack, out of reach of a collector
+//! looking for the roots. A cycle, like a closure stored in a variable it captures, stays allocated
+//! until the end of the run.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::rc::Rc;
+
+use crate::parser::ast::*;
+use crate::parser::context::ParseContext;
+use crate::parser::lexer::Span;
+use crate::resolve::{Res, Resolutions};
+use crate::runtime::{
+    self, builtin_type_key, builtin_variant, collect_items, deref, lit_value, read, stack_overflow,
+    temporary, write, Declarations, Operand, RunResult, Runtime, RuntimeError,
+};
+use crate::value::{self, Adt, Env, Function, OpenUpvalues, Place, TypeKey, Upvalue, Value};
+
+/// Runs the checked program, writing what it prints to the output.
+pub fn run(
+    context: &ParseContext,
+    program: &Program,
+    resolutions: &Resolutions,
+    decls: &Declarations,
+    output: &mut dyn Write,
+) -> Result<(), RuntimeError> {
+    let globals = value::globals_env(resolutions.globals.len());
+    run_input(context, &[], program, resolutions, decls, &globals, output)
+}
+
+/// Runs the checked input of an interactive session like [`run`], given the previous inputs, whose
+/// items and closures the input can use, and the environment of the globals, which is extended
+/// with the globals of the input.
+pub fn run_input(
+    context: &ParseContext,
+    previous: &[Program],
+    program: &Program,
+    resolutions: &Resolutions,
+    decls: &Declarations,
+    globals: &Env,
+    output: &mut dyn Write,
+) -> Result<(), RuntimeError> {
+    globals
+        .borrow_mut()
+        .resize(resolutions.globals.len(), Value::Uninit);
+    let (items, closures) = collect_items(previous.iter().chain([program]));
+    let mut interpreter = Interpreter {
+        context,
+        resolutions,
+        decls,
+        items,
+        closures,
+        globals: globals.clone(),
+        frames: vec![],
+        depth: 0,
+        output,
+    };
+    interpreter.frames.push(Frame {
+        env: value::new_env(resolutions.main_frame_size),
+        closure: None,
+        self_ty: None,
+        returns: None,
+        open_upvalues: OpenUpvalues::default(),
+    });
+    let last = program.stmts.last().map_or(program.span, |stmt| stmt.span);
+    let result = match interpreter.eval_stmts(&program.stmts) {
+        Ok(value) => interpreter.check_exit(&value, last).map_err(Control::from),
+        Err(Control::Return(value, span)) => {
+            interpreter.check_exit(&value, span).map_err(Control::from)
+        }
+        Err(control) => Err(control),
+    };
+    let flushed = interpreter.output.flush();
+    match result {
+        Ok(()) => (),
+        Err(Control::Error(error)) => return Err(*error),
+        Err(Control::Return(..) | Control::Break(..) | Control::Continue(_)) => context
+            .diag_ctx()
+            .bug(None, "`break` or `continue` outside of a loop"),
+    }
+    flushed.map_err(|err| *runtime::error(program.span, runtime::output_error(err)))
+}
+
+/// Why the evaluation of an expression stopped before producing a value.
+#[derive(Debug)]
+enum Control {
+    /// Returns the value from the function, at the span of the `return` or `?` expression.
+    Return(Value, Span),
+    /// Exits the loop with the given expression id with the value.
+    Break(NodeId, Value),
+    /// Continues the loop with the given expression id.
+    Continue(NodeId),
+    Error(Box<RuntimeError>),
+}
+
+type EvalResult<T = Value> = Result<T, Control>;
+
+fn error(span: Span, message: impl Into<String>) -> Control {
+    Control::Error(runtime::error(span, message))
+}
+
+/// Handles the result of an iteration of the loop with the given expression: breaks with the value
+/// of a `break` exiting it, and continues on a `continue` continuing it.
+fn exit(expr: &Expr, result: EvalResult) -> EvalResult<ControlFlow<Value>> {
+    match result {
+        Ok(_) => Ok(ControlFlow::Continue(())),
+        Err(Control::Continue(target)) if target == expr.id => Ok(ControlFlow::Continue(())),
+        Err(Control::Break(target, value)) if target == expr.id => Ok(ControlFlow::Break(value)),
+        Err(control) => Err(control),
+    }
+}
+
+impl From<Box<RuntimeError>> for Control {
+    fn from(error: Box<RuntimeError>) -> Self {
+        Control::Error(error)
+    }
+}
+
+/// The variables of a running function or closure, or of the top-level code.
+struct Frame {
+    env: Env,
+    /// The running closure, giving access to its captured variables.
+    closure: Option<Rc<value::Closure>>,
+    /// The type implementing the running method.
+    self_ty: Option<TypeKey>,
+    /// The built-in enum the running function returns, which its `?` operators must return.
+    returns: Option<NodeId>,
+    /// The upvalues capturing the variables of the frame, closed when their iteration ends.
+    open_upvalues: OpenUpvalues,
+}
+
+struct Interpreter<'a> {
+    context: &'a ParseContext,
+    resolutions: &'a Resolutions,
+    decls: &'a Declarations,
+    items: HashMap<NodeId, &'a Item>,
+    closures: HashMap<NodeId, &'a Closure>,
+    globals: Env,
+    frames: Vec<Frame>,
+    /// The number of nested expressions being evaluated, across calls.
+    depth: usize,
+    output: &'a mut dyn Write,
+}
+
+impl<'a> Interpreter<'a> {
+    fn bug(&self, span: Span, message: &str) -> ! {
+        self.context.diag_ctx().bug(Some(span), message)
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("a frame is always running")
+    }
+
+    /// Returns the type designated by `Self` in the item with the given id.
+    fn self_ty(&self, id: NodeId) -> Option<TypeKey> {
+        // In the default methods of a trait, `Self` is the type implementing it
+        self.decls.self_ty(id).or(self.frame().self_ty)
+    }
+
+    // Variables and places
+
+    fn res(&self, id: NodeId, span: Span) -> Res {
+        match self.resolutions.names.get(&id) {
+            Some(res) => *res,
+            None => self.bug(span, "unresolved name"),
+        }
+    }
+
+    /// Returns the place of a variable.
+    fn variable(&self, res: Res) -> Option<Place> {
+        match res {
+            Res::Local(slot) => Some(Place::variable(self.frame().env.clone(), slot)),
+            Res::Upvalue(index) => {
+                let upvalue = &self.frame().closure.as_ref()?.upvalues[index];
+                Some(upvalue.place())
+            }
+            Res::Global(slot) => Some(Place::variable(self.globals.clone(), slot)),
+            Res::Item(_) | Res::SelfTy(_) | Res::Builtin(_) => None,
+        }
+    }
+
+    /// Stores the value in the variable declared by the pattern or `self` parameter with the
+    /// given id.
+    fn bind(&mut self, id: NodeId, span: Span, value: Value) {
+        let env = match self.res(id, span) {
+            Res::Local(slot) => (self.frame().env.clone(), slot),
+            Res::Global(slot) => (self.globals.clone(), slot),
+            _ => self.bug(span, "expected a variable"),
+        };
+        env.0.borrow_mut()[env.1] = value;
+    }
+
+    /// Evaluates an expression, as a place if it designates one.
+    fn eval_operand(&mut self, expr: &Expr) -> EvalResult<Operand> {
+        match &expr.kind {
+            ExprKind::Path(_) => match self.variable(self.res(expr.id, expr.span)) {
+                Some(place) => Ok(Operand::Place(place)),
+                None => Ok(Operand::Value(self.eval_expr(expr)?)),
+            },
+            ExprKind::Paren(operand) => self.eval_operand(operand),
+            ExprKind::Unary(UnOp::Deref, operand) => match self.eval_expr(operand)? {
+                Value::Ref(place) => Ok(Operand::Place((*place).clone())),
+                value => Ok(Operand::Value(value)),
+            },
+            ExprKind::Field(base, name) => {
+                let base = self.eval_operand(base)?;
+                Ok(self.field_operand(base, name, expr.span)?)
+            }
+            ExprKind::Index(base, index) => {
+                let base = self.eval_operand(base)?;
+                let index = self.eval_expr(index)?;
+                Ok(self.index_operand(base, index, expr.span)?)
+            }
+            _ => Ok(Operand::Value(self.eval_expr(expr)?)),
+        }
+    }
+
+    fn assign(&mut self, place: &Expr, value: Value) -> EvalResult<()> {
+        // Assigning to a missing key of a map inserts it
+        if let ExprKind::Index(base, index) = &place.kind {
+            let base = self.eval_operand(base)?;
+            let index = self.eval_expr(index)?;
+            return Ok(self.assign_index(base, index, value, place.span)?);
+        }
+        match self.eval_operand(place)? {
+            Operand::Place(target) => Ok(write(&target, place.span, value)?),
+            Operand::Value(_) => Err(error(place.span, "invalid left-hand side of assignment")),
+        }
+    }
+
+    // Statements
+
+    /// Evaluates the statements, returning the value of the trailing expression if any.
+    fn eval_stmts(&mut self, stmts: &[Stmt]) -> EvalResult {
+        let mut value = Value::unit();
+        for stmt in stmts {
+            value = Value::unit();
+            match &stmt.kind {
+                StmtKind::Let(local) => self.eval_local(stmt, local)?,
+                StmtKind::Expr(expr) => value = self.eval_expr(expr)?,
+                StmtKind::Semi(expr) => {
+                    self.eval_expr(expr)?;
+                }
+                StmtKind::Item(_) | StmtKind::Empty => (),
+                StmtKind::Err => self.bug(stmt.span, "invalid statement"),
+            }
+        }
+        Ok(value)
+    }
+
+    fn eval_local(&mut self, stmt: &Stmt, local: &Local) -> EvalResult<()> {
+        let Some(init) = &local.init else {
+            return Ok(());
+        };
+        let mut value = self.eval_expr(init)?;
+        if let Some(bounds) = local.ty.as_ref().and_then(runtime::Bounds::of) {
+            value = self.decls.check_bounds(&bounds, value, init.span)?;
+        }
+        if !self.match_pat(&local.pat, &value)? {
+            let message = format!(
+                "refutable pattern in local binding does not match `{}`",
+                self.repr(&value, stmt.span)?
+            );
+            return Err(error(local.pat.span, message));
+        }
+        Ok(())
+    }
+
+    fn eval_block(&mut self, block: &Block) -> EvalResult {
+        self.eval_stmts(&block.stmts)
+    }
+
+    // Expressions
+
+    fn eval_expr(&mut self, expr: &Expr) -> EvalResult {
+        if self.depth == runtime::MAX_EVAL_DEPTH {
+            return Err(runtime::nesting_overflow(expr.span).into());
+        }
+        self.depth += 1;
+        let result = self.eval_expr_kind(expr);
+        self.depth -= 1;
+        result
+    }
+
+    fn eval_expr_kind(&mut self, expr: &Expr) -> EvalResult {
+        crate::ice::set_current_span(expr.span);
+        match &expr.kind {
+            ExprKind::Lit(lit) => self.eval_lit(lit, false, expr.span),
+            ExprKind::Path(path) => self.eval_path(expr.id, path, expr.span),
+            ExprKind::Unary(op, operand) => self.eval_unary(expr, *op, operand),
+            ExprKind::AddrOf(Mutability::Not, operand) => self.eval_expr(operand),
+            ExprKind::AddrOf(Mutability::Mut, operand) => self.eval_borrow_mut(operand),
+            ExprKind::Binary(op, lhs, rhs) => self.eval_binary(expr, *op, lhs, rhs),
+            ExprKind::Assign(place, value) => {
+                let value = self.eval_expr(value)?;
+                self.assign(place, value)?;
+                Ok(Value::unit())
+            }
+            ExprKind::AssignOp(op, place, value) => self.eval_assign_op(expr, *op, place, value),
+            ExprKind::Cast(operand, ty) => {
+                let value = self.eval_expr(operand)?;
+                let target = match self.resolutions.names.get(&ty.id) {
+                    Some(Res::Builtin(name)) => *name,
+                    _ => "_",
+                };
+                Ok(self.cast(value, target, expr.span)?)
+            }
+            ExprKind::Call(callee, args) => self.eval_call(expr, callee, args),
+            ExprKind::MethodCall(receiver, name, args) => {
+                self.eval_method_call(expr, receiver, name, args)
+            }
+            ExprKind::Field(base, name) => {
+                let base = self.eval_expr(base)?;
+                Ok(self.project(base, name, expr.span)?)
+            }
+            ExprKind::Index(base, index) => self.eval_index(expr, base, index),
+            ExprKind::Try(operand) => {
+                let value = self.eval_expr(operand)?;
+                match self.try_branch(value, self.frame().returns, expr.span)? {
+                    ControlFlow::Continue(value) => Ok(value),
+                    ControlFlow::Break(residual) => Err(Control::Return(residual, expr.span)),
+                }
+            }
+            ExprKind::Tuple(exprs) => Ok(Value::Tuple(Rc::new(self.eval_args(exprs)?))),
+            ExprKind::Array(exprs) => Ok(Value::Array(Rc::new(self.eval_args(exprs)?))),
+            ExprKind::Struct(struct_expr) => self.eval_struct(expr, struct_expr),
+            ExprKind::Paren(operand) => self.eval_expr(operand),
+            ExprKind::Block(block) => self.eval_block(block),
+            ExprKind::If(cond, then, otherwise) => {
+                if self.eval_bool(cond)? {
+                    self.eval_block(then)
+                } else if let Some(otherwise) = otherwise {
+                    self.eval_expr(otherwise)
+                } else {
+                    Ok(Value::unit())
+                }
+            }
+            ExprKind::While(cond, body, _) => self.eval_while(expr, cond, body),
+            ExprKind::For(pat, iterable, body, _) => self.eval_for(expr, pat, iterable, body),
+            ExprKind::Loop(body, _) => loop {
+                let result = self.eval_block(body);
+                if let ControlFlow::Break(value) = self.end_iteration(expr, result)? {
+                    return Ok(value);
+                }
+            },
+            ExprKind::Match(scrutinee, arms) => self.eval_match(expr, scrutinee, arms),
+            ExprKind::Range(start, end, limits) => {
+                let start = self.eval_opt(start)?;
+                let end = self.eval_opt(end)?;
+                let inclusive = *limits == RangeLimits::Closed;
+                Ok(self.range(start, end, inclusive, expr.span)?)
+            }
+            ExprKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval_expr(value)?,
+                    None => Value::unit(),
+                };
+                Err(Control::Return(value, expr.span))
+            }
+            ExprKind::Break(_, value) => {
+                let value = match value {
+                    Some(value) => self.eval_expr(value)?,
+                    None => Value::unit(),
+                };
+                Err(Control::Break(self.loop_target(expr), value))
+            }
+            ExprKind::Continue(_) => Err(Control::Continue(self.loop_target(expr))),
+            ExprKind::Closure(closure) => Ok(self.eval_closure(expr, closure)),
+            ExprKind::Err => self.bug(expr.span, "invalid expression"),
+        }
+    }
+
+    fn eval_borrow_mut(&mut self, operand: &Expr) -> EvalResult {
+        match self.eval_operand(operand)? {
+            Operand::Place(place) => Ok(Value::Ref(Rc::new(place))),
+            Operand::Value(value) => Ok(Value::Ref(Rc::new(temporary(value)))),
+        }
+    }
+
+    fn eval_index(&mut self, expr: &Expr, base: &Expr, index: &Expr) -> EvalResult {
+        let base = self.eval_expr(base)?;
+        let index = self.eval_expr(index)?;
+        Ok(self.index(base, index, expr.span)?)
+    }
+
+    fn eval_binary(&mut self, expr: &Expr, op: BinOp, lhs: &Expr, rhs: &Expr) -> EvalResult {
+        if op.is_lazy() {
+            let lhs = self.eval_bool(lhs)?;
+            if lhs == (op == BinOp::Or) {
+                return Ok(Value::Bool(lhs));
+            }
+            return Ok(Value::Bool(self.eval_bool(rhs)?));
+        }
+        let lhs = self.eval_expr(lhs)?;
+        let rhs = self.eval_expr(rhs)?;
+        Ok(self.binary_op(op, lhs, rhs, expr.span)?)
+    }
+
+    fn eval_assign_op(&mut self, expr: &Expr, op: BinOp, place: &Expr, value: &Expr) -> EvalResult {
+        let target = match self.eval_operand(place)? {
+            Operand::Place(target) => target,
+            Operand::Value(_) => {
+                return Err(error(place.span, "invalid left-hand side of assignment"))
+            }
+        };
+        let value = self.eval_expr(value)?;
+        let current = read(&target, place.span)?;
+        let value = self.binary_op(op, current, value, expr.span)?;
+        write(&target, place.span, value)?;
+        Ok(Value::unit())
+    }
+
+    fn eval_call(&mut self, expr: &Expr, callee: &Expr, args: &[Expr]) -> EvalResult {
+        let callee = self.eval_expr(callee)?;
+        let args = self.eval_args(args)?;
+        Ok(self.call(callee, args, expr.span)?)
+    }
+
+    fn eval_while(&mut self, expr: &Expr, cond: &Expr, body: &Block) -> EvalResult {
+        loop {
+            let result = match self.eval_bool(cond) {
+                Ok(true) => self.eval_block(body),
+                Ok(false) => return Ok(Value::unit()),
+                Err(control) => Err(control),
+            };
+            if let ControlFlow::Break(value) = self.end_iteration(expr, result)? {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Ends an iteration of the loop with the given expression, closing the upvalues capturing the
+    /// variables it declared.
+    fn end_iteration(&mut self, expr: &Expr, result: EvalResult) -> EvalResult<ControlFlow<Value>> {
+        if let Some(first) = self.resolutions.loop_slots.get(&expr.id) {
+            let frame = self.frames.last_mut().expect("a frame is always running");
+            frame.open_upvalues.close_from(*first, |_| ());
+        }
+        exit(expr, result)
+    }
+
+    /// Returns the loop exited or continued by the `break` or `continue` expression.
+    fn loop_target(&self, expr: &Expr) -> NodeId {
+        match self.resolutions.loop_targets.get(&expr.id) {
+            Some(target) => *target,
+            None => self.bug(expr.span, "`break` or `continue` outside of a loop"),
+        }
+    }
+
+    fn eval_match(&mut self, expr: &Expr, scrutinee: &Expr, arms: &[Arm]) -> EvalResult {
+        let value = self.eval_expr(scrutinee)?;
+        for arm in arms {
+            if !self.match_pat(&arm.pat, &value)? {
+                continue;
+            }
+            if let Some(guard) = &arm.guard {
+                if !self.eval_bool(guard)? {
+                    continue;
+                }
+            }
+            return self.eval_expr(&arm.body);
+        }
+        let message = format!("no match arm matches `{}`", self.repr(&value, expr.span)?);
+        Err(error(scrutinee.span, message))
+    }
+
+    fn eval_opt(&mut self, expr: &Option<Box<Expr>>) -> EvalResult<Option<Value>> {
+        expr.as_ref().map(|expr| self.eval_expr(expr)).transpose()
+    }
+
+    fn eval_args(&mut self, exprs: &[Expr]) -> EvalResult<Vec<Value>> {
+        exprs.iter().map(|expr| self.eval_expr(expr)).collect()
+    }
+
+    fn eval_bool(&mut self, expr: &Expr) -> EvalResult<bool> {
+        match self.eval_expr(expr)? {
+            Value::Bool(value) => Ok(value),
+            value => {
+                let message = format!("expected `bool`, found `{}`", self.decls.type_name(&value));
+                Err(error(expr.span, message))
+            }
+        }
+    }
+
+    fn eval_lit(&self, lit: &Lit, negative: bool, span: Span) -> EvalResult {
+        lit_value(lit, negative).map_err(|message| error(span, message))
+    }
+
+    fn eval_path(&mut self, id: NodeId, path: &Path, span: Span) -> EvalResult {
+        let res = self.res(id, span);
+        if let Some(place) = self.variable(res) {
+            return Ok(read(&place, span)?);
+        }
+        let rest = &path.segments[1..];
+        match res {
+            Res::Item(id) => Ok(self.decls.item_value(id, rest, span)?),
+            Res::SelfTy(id) => match self.self_ty(id) {
+                Some(key) => Ok(self.decls.assoc_value(key, rest, span)?),
+                None => self.bug(span, "unknown `Self` type"),
+            },
+            Res::Builtin(name) => Ok(self.decls.builtin_value(name, rest, span)?),
+            Res::Local(_) | Res::Upvalue(_) | Res::Global(_) => self.bug(span, "unknown variable"),
+        }
+    }
+
+    /// Returns the struct or enum variant named by the path of a pattern or struct expression.
+    fn resolve_variant(&self, id: NodeId, path: &Path) -> Option<(NodeId, usize)> {
+        let key = match self.resolutions.names.get(&id)? {
+            Res::Item(id) => TypeKey::Adt(*id),
+            Res::SelfTy(id) => self.self_ty(*id)?,
+            Res::Builtin(name) if path.segments.len() == 1 => return builtin_variant(name),
+            Res::Builtin(name) => builtin_type_key(name),
+            _ => return None,
+        };
+        self.decls.resolve_variant(key, &path.segments[1..])
+    }
+
+    fn eval_unary(&mut self, expr: &Expr, op: UnOp, operand: &Expr) -> EvalResult {
+        // The minimum of `i64` is written as the negation of a literal out of its range
+        if let (UnOp::Neg, ExprKind::Lit(lit)) = (op, &operand.kind) {
+            return self.eval_lit(lit, true, expr.span);
+        }
+        let value = self.eval_expr(operand)?;
+        Ok(self.unary_op(op, value, expr.span)?)
+    }
+
+    fn eval_struct(&mut self, expr: &Expr, struct_expr: &StructExpr) -> EvalResult {
+        let Some((id, variant)) = self.resolve_variant(expr.id, &struct_expr.path) else {
+            let message = format!("expected struct, found `{}`", struct_expr.path);
+            return Err(error(struct_expr.path.span, message));
+        };
+        let decl = self.decls.variant(id, variant);
+        let mut fields: Vec<Option<Value>> = vec![None; decl.fields.len()];
+        for field in &struct_expr.fields {
+            let Some(index) = decl.fields.iter().position(|name| *name == field.name.name) else {
+                let message = format!("`{}` has no field named `{}`", decl.name, field.name.name);
+                return Err(error(field.name.span, message));
+            };
+            fields[index] = Some(self.eval_expr(&field.expr)?);
+        }
+        if let Some(rest) = &struct_expr.rest {
+            let base = self.eval_expr(rest)?;
+            let base = deref(base, rest.span)?;
+            match base {
+                Value::Adt(base) if base.id == id && base.variant == variant => {
+                    for (field, value) in fields.iter_mut().zip(base.fields.iter()) {
+                        field.get_or_insert_with(|| value.clone());
+                    }
+                }
+                base => {
+                    let message = format!(
+                        "expected `{}`, found `{}`",
+                        decl.name,
+                        self.decls.type_name(&base)
+                    );
+                    return Err(error(rest.span, message));
+                }
+            }
+        }
+        let fields = fields
+            .into_iter()
+            .zip(&decl.fields)
+            .map(|(value, name)| {
+                value.ok_or_else(|| {
+                    let message =
+                        format!("missing field `{name}` in initializer of `{}`", decl.name);
+                    error(struct_expr.path.span, message)
+                })
+            })
+            .collect::<EvalResult<_>>()?;
+        Ok(Value::Adt(Rc::new(Adt {
+            id,
+            variant,
+            fields,
+        })))
+    }
+
+    fn eval_closure(&mut self, expr: &Expr, closure: &Closure) -> Value {
+        let captures = self
+            .resolutions
+            .captures
+            .get(&expr.id)
+            .map_or(&[][..], Vec::as_slice);
+        let frame = self.frames.last_mut().expect("a frame is always running");
+        let mut upvalues: Vec<Upvalue> = captures
+            .iter()
+            .map(|res| match *res {
+                Res::Local(slot) => frame.open_upvalues.capture(&frame.env, slot),
+                Res::Upvalue(index) => match &frame.closure {
+                    Some(closure) => closure.upvalues[index].clone(),
+                    None => self
+                        .context
+                        .diag_ctx()
+                        .bug(Some(expr.span), "expected a closure"),
+                },
+                _ => self
+                    .context
+                    .diag_ctx()
+                    .bug(Some(expr.span), "expected a captured variable"),
+            })
+            .collect();
+        if closure.capture_by == CaptureBy::Value {
+            let env = value::new_env(upvalues.len());
+            for (slot, upvalue) in upvalues.iter_mut().enumerate() {
+                env.borrow_mut()[slot] = upvalue.get();
+                *upvalue = Upvalue::new(env.clone(), slot);
+            }
+        }
+        Value::Closure(Rc::new(value::Closure {
+            id: expr.id,
+            upvalues,
+            self_ty: self.frame().self_ty,
+        }))
+    }
+
+    fn eval_for(
+        &mut self,
+        expr: &Expr,
+        pat: &Pattern,
+        iterable: &Expr,
+        body: &Block,
+    ) -> EvalResult {
+        let value = self.eval_expr(iterable)?;
+        let mut iter = self.iter(value, iterable.span)?;
+        while let Some(item) = self.next(&mut iter, iterable.span)? {
+            if !self.match_pat(pat, &item)? {
+                let message = format!(
+                    "refutable pattern in `for` loop does not match `{}`",
+                    self.repr(&item, pat.span)?
+                );
+                return Err(error(pat.span, message));
+            }
+            let result = self.eval_block(body);
+            if let ControlFlow::Break(value) = self.end_iteration(expr, result)? {
+                return Ok(value);
+            }
+        }
+        Ok(Value::unit())
+    }
+
+    // Patterns
+
+    /// Matches the value against the pattern, binding its variables. The variables bound before a
+    /// mismatch keep their value, which is unobservable as they are not in scope after it.
+    fn match_pat(&mut self, pat: &Pattern, value: &Value) -> EvalResult<bool> {
+        // Patterns match the referenced value, while bindings bind the reference
+        if let Value::Ref(place) = value {
+            if !matches!(pat.kind, PatKind::Ident(..) | PatKind::Wild) {
+                let value = read(place, pat.span)?;
+                return self.match_pat(pat, &value);
+            }
+        }
+        Ok(match &pat.kind {
+            PatKind::Wild | PatKind::Rest => true,
+            PatKind::Ident(_, _, sub) => {
+                if let Some(sub) = sub {
+                    if !self.match_pat(sub, value)? {
+                        return Ok(false);
+                    }
+                }
+                self.bind(pat.id, pat.span, value.clone());
+                true
+            }
+            PatKind::Lit(expr) => {
+                let lit = self.eval_expr(expr)?;
+                self.values_eq(&lit, value, pat.span)?
+            }
+            PatKind::Range(start, end, limits) => {
+                if let Some(start) = start {
+                    let start = self.eval_expr(start)?;
+                    if self.compare(&start, value, pat.span)? == Some(Ordering::Greater) {
+                        return Ok(false);
+                    }
+                }
+                if let Some(end) = end {
+                    let end = self.eval_expr(end)?;
+                    let ordering = self.compare(value, &end, pat.span)?;
+                    return Ok(match limits {
+                        RangeLimits::HalfOpen => ordering == Some(Ordering::Less),
+                        RangeLimits::Closed => ordering.is_some_and(Ordering::is_le),
+                    });
+                }
+                true
+            }
+            PatKind::Tuple(pats) => match value {
+                Value::Tuple(values) => self.match_seq(pats, values)?,
+                _ => false,
+            },
+            PatKind::Slice(pats) => match value {
+                Value::Array(values) => self.match_seq(pats, values)?,
+                _ => false,
+            },
+            PatKind::Or(alternatives) => {
+                for alternative in alternatives {
+                    if self.match_pat(alternative, value)? {
+                        return Ok(true);
+                    }
+                }
+                false
+            }
+            PatKind::Path(path) => {
+                if let Some(Res::Item(id)) = self.resolutions.names.get(&pat.id) {
+                    if let Some(constant) = self.decls.consts.get(id) {
+                        return Ok(self.values_eq(constant, value, pat.span)?);
+                    }
+                }
+                self.match_variant(pat, path, value).is_some()
+            }
+            PatKind::TupleStruct(path, pats) => match self.match_variant(pat, path, value) {
+                Some(adt) => self.match_seq(pats, &adt.fields)?,
+                None => false,
+            },
+            PatKind::Struct(path, fields, _) => {
+                let Some(adt) = self.match_variant(pat, path, value) else {
+                    return Ok(false);
+                };
+                for field in fields {
+                    let Some(index) = self.decls.field_index(adt, &field.name.name) else {
+                        return Ok(false);
+                    };
+                    if !self.match_pat(&field.pat, &adt.fields[index])? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+            PatKind::Err => self.bug(pat.span, "invalid pattern"),
+        })
+    }
+
+    /// Returns the value if it is the struct or enum variant named by the path of the pattern.
+    fn match_variant<'v>(&self, pat: &Pattern, path: &Path, value: &'v Value) -> Option<&'v Adt> {
+        let (id, variant) = self.resolve_variant(pat.id, path)?;
+        match value {
+            Value::Adt(adt) if adt.id == id && adt.variant == variant => Some(adt),
+            _ => None,
+        }
+    }
+
+    /// Matches the elements of a tuple, array or tuple struct against the patterns, which can
+    /// contain a rest pattern `..`, bound to the remaining elements in a slice pattern.
+    fn match_seq(&mut self, pats: &[Pattern], values: &[Value]) -> EvalResult<bool> {
+        let is_rest = |pat: &Pattern| match &pat.kind {
+            PatKind::Rest => true,
+            PatKind::Ident(_, _, Some(sub)) => matches!(sub.kind, PatKind::Rest),
+            _ => false,
+        };
+        let Some(rest) = pats.iter().position(is_rest) else {
+            if pats.len() != values.len() {
+                return Ok(false);
+            }
+            for (pat, value) in pats.iter().zip(values) {
+                if !self.match_pat(pat, value)? {
+                    return Ok(false);
+                }
+            }
+            return Ok(true);
+        };
+        let (before, after) = (&pats[..rest], &pats[rest + 1..]);
+        if values.len() < before.len() + after.len() {
+            return Ok(false);
+        }
+        let middle = &values[before.len()..values.len() - after.len()];
+        for (pat, value) in before.iter().zip(values) {
+            if !self.match_pat(pat, value)? {
+                return Ok(false);
+            }
+        }
+        for (pat, value) in after.iter().zip(&values[values.len() - after.len()..]) {
+            if !self.match_pat(pat, value)? {
+                return Ok(false);
+            }
+        }
+        let rest = &pats[rest];
+        if let PatKind::Ident(..) = rest.kind {
+            self.bind(rest.id, rest.span, Value::Array(Rc::new(middle.to_vec())));
+        }
+        Ok(true)
+    }
+
+    fn bind_params(&mut self, params: &[Param], args: Vec<Value>) -> EvalResult<()> {
+        for (param, arg) in params.iter().zip(args) {
+            if !self.match_pat(&param.pat, &arg)? {
+                let message = format!(
+                    "refutable pattern in function argument does not match `{}`",
+                    self.repr(&arg, param.span)?
+                );
+                return Err(error(param.span, message));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the body of a function or closure in the given frame, returning its value.
+    fn with_frame(
+        &mut self,
+        frame: Frame,
+        span: Span,
+        f: impl FnOnce(&mut Self) -> EvalResult,
+    ) -> EvalResult {
+        if self.frames.len() > runtime::MAX_CALL_DEPTH {
+            return Err(stack_overflow(span).into());
+        }
+        self.frames.push(frame);
+        let result = f(self);
+        self.frames.pop();
+        match result {
+            Err(Control::Return(value, _)) => Ok(value),
+            Err(Control::Break(..) | Control::Continue(_)) => {
+                self.bug(span, "`break` or `continue` outside of a loop")
+            }
+            result => result,
+        }
+    }
+
+    /// Returns the result of a call, adding the call to the backtrace of an error.
+    fn trace(&self, result: EvalResult, name: String, span: Span) -> RunResult {
+        match result {
+            Ok(value) => Ok(value),
+            Err(Control::Error(mut error)) => {
+                error.trace.push((name, span));
+                Err(error)
+            }
+            Err(_) => self.bug(span, "unexpected control flow out of a call"),
+        }
+    }
+
+    fn eval_method_call(
+        &mut self,
+        expr: &Expr,
+        receiver: &Expr,
+        name: &Ident,
+        args: &[Expr],
+    ) -> EvalResult {
+        let receiver = self.eval_operand(receiver)?;
+        let args = self.eval_args(args)?;
+        Ok(self.call_method(receiver, name, args, expr.span)?)
+    }
+}
+
+impl<'a> Runtime<'a> for Interpreter<'a> {
+    fn decls(&self) -> &'a Declarations {
+        self.decls
+    }
+
+    fn output(&mut self) -> &mut dyn Write {
+        self.output
+    }
+
+    fn call_function(&mut self, function: Function, args: Vec<Value>, span: Span) -> RunResult {
+        let args = self.function_args(function, args, span)?;
+        let ItemKind::Fn(decl) = &self.items[&function.id].kind else {
+            self.bug(span, "expected a function");
+        };
+        let Some(body) = &decl.body else {
+            self.bug(decl.name.span, "call to a function without body");
+        };
+        let frame = Frame {
+            env: value::new_env(self.resolutions.frame_sizes[&function.id]),
+            closure: None,
+            self_ty: function.self_ty,
+            returns: runtime::returned_enum(decl.ret.as_ref(), self.resolutions),
+            open_upvalues: OpenUpvalues::default(),
+        };
+        let result = self.with_frame(frame, span, |this| {
+            let mut args = args.into_iter();
+            if let Some(param) = &decl.self_param {
+                let receiver = args.next().expect("the arity was checked");
+                this.bind(param.id, param.span, receiver);
+            }
+            this.bind_params(&decl.params, args.collect())?;
+            this.eval_block(body)
+        });
+        self.trace(result, self.decls.fn_name(function), span)
+    }
+
+    fn call_closure(
+        &mut self,
+        closure: Rc<value::Closure>,
+        args: Vec<Value>,
+        span: Span,
+    ) -> RunResult {
+        let args = self.closure_args(&closure, args, span)?;
+        let decl = self.closures[&closure.id];
+        let frame = Frame {
+            env: value::new_env(self.resolutions.frame_sizes[&closure.id]),
+            self_ty: closure.self_ty,
+            returns: runtime::returned_enum(decl.ret.as_ref(), self.resolutions),
+            closure: Some(closure),
+            open_upvalues: OpenUpvalues::default(),
+        };
+        let result = self.with_frame(frame, span, |this| {
+            this.bind_params(&decl.params, args)?;
+            this.eval_expr(&decl.body)
+        });
+        self.trace(result, String::from("{closure}"), span)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,372 @@
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::DiagContext;
+use crate::parser::emitter::new_emitter_none;
+use crate::parser::source_map::SourceMap;
+use crate::test_utils::diagnostics;
+use crate::{Backend, CheckOptions, RunOptions};
+
+/// Runs the source with the tree-walking interpreter, returning what it printed and the emitted
+/// diagnostics as `level: message`.
+fn run(source: &str) -> (String, Vec<String>) {
+    let source_map = SourceMap::new("test.risl", source);
+    let context =
+        ParseContext::new(DiagContext::new(new_emitter_none()).with_source_map(source_map));
+    let mut output = vec![];
+    let options = RunOptions {
+        backend: Backend::TreeWalk,
+        ..RunOptions::default()
+    };
+    let _ = crate::run_with_options(
+        &context,
+        source,
+        CheckOptions::default(),
+        options,
+        &mut output,
+    );
+    let diagnostics = diagnostics(&context);
+    (String::from_utf8(output).unwrap(), diagnostics)
+}
+
+fn output(source: &str) -> String {
+    let (output, diagnostics) = run(source);
+    assert_eq!(diagnostics, Vec::<String>::new());
+    output
+}
+
+fn errors(source: &str) -> Vec<String> {
+    run(source).1
+}
+
+#[test]
+fn values() {
+    assert_eq!(
+        output(
+            "println(1 + 2 * 3, 7 / 2, -7 % 3, 1.5 * 2.0, \"a\" + \"b\");\n\
+             println(true && !false, 1 < 2, (1, \"x\"), [1, 2][1], ());\n\
+             let mut m = Map::new();\n\
+             m.insert(\"k\", [1.0]);\n\
+             println(m, m.get(\"k\"), m.get(\"z\"));"
+        ),
+        "7 3 -1 3.0 ab\ntrue true (1, \"x\") 2 ()\n{\"k\": [1.0]} Some([1.0]) None\n"
+    );
+}
+
+#[test]
+fn control_flow() {
+    assert_eq!(
+        output(
+            "let mut total = 0;\n\
+             for i in 0..10 {\n\
+                 if i % 2 == 0 { continue; }\n\
+                 if i > 7 { break; }\n\
+                 total += i;\n\
+             }\n\
+             let mut n = 3;\n\
+             while n > 0 { n -= 1; }\n\
+             let kind = match total { 0 => \"zero\", x if x < 0 => \"negative\", _ => \"positive\" };\n\
+             println(total, n, kind);"
+        ),
+        "16 0 positive\n"
+    );
+}
+
+#[test]
+fn labelled_loops() {
+    assert_eq!(
+        output(
+            "let mut n = 1;\n\
+             let power = loop { n *= 2; if n > 100 { break n; } };\n\
+             let mut pairs = [];\n\
+             'rows: for i in 0..4 {\n\
+                 for j in 0..4 {\n\
+                     if j > i { continue 'rows; }\n\
+                     if i == 3 { break 'rows; }\n\
+                     pairs.push((i, j));\n\
+                 }\n\
+             }\n\
+             let found = 'search: loop { for x in [1, 2, 3] { if x * x > 3 { break 'search x; } } break 0; };\n\
+             println(power, pairs.len(), found, 1 + loop { break 2; });"
+        ),
+        "128 6 2 3\n"
+    );
+}
+
+#[test]
+fn functions_and_closures() {
+    assert_eq!(
+        output(
+            "fn fib(n) { if n < 2 { return n; } fib(n - 1) + fib(n - 2) }\n\
+             fn adder(x) { |y| x + y }\n\
+             let mut count = 0;\n\
+             let inc = || count += 1;\n\
+             inc();\n\
+             inc();\n\
+             println(fib(20), adder(1)(2), count);"
+        ),
+        "6765 3 2\n"
+    );
+    // Shadowing a global does not change the variable captured by a closure
+    assert_eq!(
+        output("let x = 1;\nlet f = || x;\nlet x = 10;\nprintln(f(), x);"),
+        "1 10\n"
+    );
+}
+
+#[test]
+fn value_semantics() {
+    // Assigning an aggregate copies it, and `&mut` modifies the original
+    assert_eq!(
+        output(
+            "struct Point { x: i64, y: i64 }\n\
+             fn shift(p: &mut Point) { p.x += 10; }\n\
+             let mut a = [1, 2];\n\
+             let b = a;\n\
+             a.push(3);\n\
+             let mut p = Point { x: 1, y: 2 };\n\
+             let q = p;\n\
+             shift(&mut p);\n\
+             println(a, b, p.x, q.x);"
+        ),
+        "[1, 2, 3] [1, 2] 11 1\n"
+    );
+}
+
+#[test]
+fn methods_and_traits() {
+    assert_eq!(
+        output(
+            "trait Shape { fn area(&self) -> f64; fn describe(&self) -> str { \"area \" + self.area().to_string() } }\n\
+             struct Square { side: f64 }\n\
+             impl Square { fn new(side: f64) -> Square { Square { side } } }\n\
+             impl Shape for Square { fn area(&self) -> f64 { self.side * self.side } }\n\
+             println(Square::new(3.0).describe());"
+        ),
+        "area 9.0\n"
+    );
+}
+
+#[test]
+fn runtime_errors() {
+    assert_eq!(
+        errors("fn div(a, b) { a / b }\nprintln(div(1, 0));"),
+        [
+            "error: attempt to divide by zero",
+            "note: backtrace:\n   0: div at test.risl:1:16\n   1: <main> at test.risl:2:9"
+        ]
+    );
+    assert_eq!(
+        errors("let a = [1, 2];\nprintln(a[5]);"),
+        ["error: index out of bounds: the length is 2 but the index is 5"]
+    );
+    assert_eq!(
+        errors("let x = 9223372036854775807;\nprintln(x + 1);"),
+        ["error: attempt to add with overflow"]
+    );
+    assert_eq!(
+        errors("let x = 1;\nx.frobnicate();"),
+        ["error: no method named `frobnicate` found for `i64`"]
+    );
+    assert_eq!(
+        errors("fn f(x) { x }\nf(1, 2);"),
+        ["error: this function takes 1 argument but 2 arguments were supplied"]
+    );
+    // A function can only read a global once its declaration has run
+    assert_eq!(
+        errors("fn f() { x }\nf();\nlet x = 1;"),
+        [
+            "error: use of uninitialized variable",
+            "note: backtrace:\n   0: f at test.risl:1:10\n   1: <main> at test.risl:2:1"
+        ]
+    );
+    assert_eq!(
+        errors("assert_eq(1 + 1, 3);"),
+        ["error: assertion `left == right` failed\n  left: 2\n right: 3"]
+    );
+    // The program is not run when the static checks fail
+    assert_eq!(
+        run("println(1);\nlet y = z;"),
+        (
+            String::new(),
+            vec!["error: cannot find value `z` in this scope".into()]
+        )
+    );
+}
+
+#[test]
+fn integer_types() {
+    assert_eq!(
+        output(
+            "let x = 200u8;\n\
+             println(x * 1, x as i8, -(x as i64), 1u16 << 15, x.checked_mul(2), x.wrapping_mul(2));"
+        ),
+        "200 -56 -200 32768 None 144\n"
+    );
+    assert_eq!(
+        errors("let x = 200u8;\nprintln(x + 56);"),
+        ["error: attempt to add with overflow"]
+    );
+    assert_eq!(
+        errors("let x = 200u8;\nprintln(x + 1u16);"),
+        ["error: cannot apply operator `+` to `u8` and `u16`"]
+    );
+    assert_eq!(
+        errors("let x = 200u8;\nprintln(x + 256);"),
+        ["error: integer 256 out of range for `u8`"]
+    );
+    assert_eq!(
+        errors("let x = 1u32;\nprintln(-x);"),
+        ["error: cannot apply unary operator `-` to `u32`"]
+    );
+    assert_eq!(
+        errors("let x = 1u32;\nprintln(x << 32);"),
+        ["error: attempt to shift left with overflow"]
+    );
+    assert_eq!(
+        errors("println(256u8);"),
+        ["error: literal out of range for `u8`"]
+    );
+    // The values bound to an annotated variable or parameter are checked at run time
+    assert_eq!(
+        errors("fn f(x: u8) { x }\nlet y = 256;\nf(y);"),
+        ["error: integer 256 out of range for `u8`"]
+    );
+    assert_eq!(
+        errors("let x = 1u16;\nlet y: u8 = x;"),
+        ["error: mismatched types: expected `u8`, found `u16`"]
+    );
+}
+
+#[test]
+fn option_and_result() {
+    assert_eq!(
+        output(
+            "fn get(a, i) { if i < a.len() { Some(a[i]) } else { None } }\n\
+             fn sum(a) { Some(get(a, 0)? + get(a, 1)?) }\n\
+             fn check(x) { if x > 0 { Ok(x) } else { Err(\"negative\") } }\n\
+             fn double(x) { let x = check(x)?; Ok(x * 2) }\n\
+             println(sum([1, 2]), sum([1]), double(3), double(-1));\n\
+             println(None.unwrap_or(1), Some(2).map(|x| x + 1), Err(1).is_ok(), Ok(4).ok());"
+        ),
+        "Some(3) None Ok(6) Err(\"negative\")\n1 Some(3) false Some(4)\n"
+    );
+    assert_eq!(
+        errors("println(None.unwrap());"),
+        ["error: called `Option::unwrap()` on a `None` value"]
+    );
+    assert_eq!(
+        errors("Err(\"bad\").expect(\"failed\");"),
+        ["error: failed: \"bad\""]
+    );
+    // The `?` operator must give the enum its function returns
+    assert_eq!(
+        errors("fn f(a) -> Result<i64, str> { Ok(a.first()?) }\nf([1]);"),
+        [
+            "error: the `?` operator can only be used on `Result`s, not `Option`s, in a function \
+             that returns `Result`",
+            "note: backtrace:\n   0: f at test.risl:1:34\n   1: <main> at test.risl:2:1"
+        ]
+    );
+    assert_eq!(
+        errors("fn f(a) -> Option<i64> { Some(Ok(a)?) }\nf(1);"),
+        [
+            "error: the `?` operator can only be used on `Option`s, not `Result`s, in a function \
+             that returns `Option`",
+            "note: backtrace:\n   0: f at test.risl:1:31\n   1: <main> at test.risl:2:1"
+        ]
+    );
+    assert_eq!(
+        errors("let x = 1?;"),
+        ["error: the `?` operator can only be applied to values of type `Option` or `Result`, found `i64`"]
+    );
+    // An `Err` returned by the top-level code is an error, but not a `None`
+    assert_eq!(
+        errors("println(1);\nErr(\"bad\")?;\nprintln(2);"),
+        ["error: the program returned an error: \"bad\""]
+    );
+    assert_eq!(
+        errors("Err(1)"),
+        ["error: the program returned an error: 1"]
+    );
+    assert_eq!(output("println(1);\nNone?;\nprintln(2);"), "1\n");
+}
+
+#[test]
+fn backtrace() {
+    let (output, diagnostics) = run("fn f(n) { if n == 0 { 1 / n } else { f(n - 1) } }\nf(30);");
+    assert_eq!(output, "");
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0], "error: attempt to divide by zero");
+    let lines: Vec<_> = diagnostics[1].lines().collect();
+    assert_eq!(lines.len(), 1 + crate::runtime::MAX_TRACE_LEN + 1);
+    assert_eq!(lines[0], "note: backtrace:");
+    assert_eq!(lines[1], "   0: f at test.risl:1:23");
+    assert_eq!(lines[2], "   1: f at test.risl:1:38");
+    // The 31 calls of `f` and the top-level code
+    assert_eq!(
+        lines.last().unwrap(),
+        &format!(
+            "      ... {} more frames omitted",
+            32 - crate::runtime::MAX_TRACE_LEN
+        )
+    );
+    // Errors in the top-level code have no backtrace
+    assert_eq!(errors("1 / 0;"), ["error: attempt to divide by zero"]);
+}
+
+#[test]
+fn catch_unwind() {
+    assert_eq!(
+        output(
+            "fn f(n) { if n == 0 { [][n] } else { f(n - 1) } }\n\
+             println(catch_unwind(|| f(3)), catch_unwind(|| f));\n\
+             println(catch_unwind(|| catch_unwind(|| assert(false))));\n\
+             println(catch_unwind(|| panic(\"at \" + \"the disco\")), catch_unwind(|| panic(1)));"
+        ),
+        "Err(\"index out of bounds: the length is 0 but the index is 0\") Ok(<fn f>)\n\
+         Ok(Err(\"assertion failed\"))\n\
+         Err(\"at the disco\") Err(\"1\")\n"
+    );
+    assert_eq!(
+        errors("catch_unwind();"),
+        ["error: this function takes 1 argument but 0 arguments were supplied"]
+    );
+    assert_eq!(
+        errors("panic(\"a\", \"b\");"),
+        ["error: this function takes 1 argument but 2 arguments were supplied"]
+    );
+}
+
+#[test]
+fn iterators() {
+    assert_eq!(
+        output(
+            "struct Count(i64);\n\
+             impl Iterator for Count { fn next(&mut self) { self.0 += 1; if self.0 > 3 { None } else { Some(self.0) } } }\n\
+             println(Count(0).map(|x| x * 2).collect(), Count(1).zip(\"ab\".chars()).collect());\n\
+             println((0..10).filter(|x| x % 3 == 0).rev().skip(1).collect(), [1, 2].iter().chain(5..6).fold(0, |a, b| a + b));\n\
+             let mut it = (1..3).enumerate();\n\
+             println(it.next(), it.next(), it.next());"
+        ),
+        "[2, 4, 6] [(2, \"a\"), (3, \"b\")]\n[6, 3, 0] 8\nSome((0, 1)) Some((1, 2)) None\n"
+    );
+    assert_eq!(
+        errors("struct Bad;\nimpl Iterator for Bad { fn next(&mut self) { 1 } }\nfor x in Bad {}"),
+        ["error: expected `Iterator::next` to return `Option`, found `i64`"]
+    );
+    assert_eq!(
+        errors("println((0..3).filter(|x| x).collect());"),
+        ["error: expected the predicate of `filter` to return `bool`, found `i64`"]
+    );
+    assert_eq!(
+        errors("println((0..3).take(-1));"),
+        ["error: cannot take -1 items"]
+    );
+    assert_eq!(
+        errors("println((..3).map(|x| x));"),
+        ["error: cannot iterate over a range without a start"]
+    );
+    assert_eq!(
+        errors("println([1].map(|x| x));"),
+        ["error: no method named `map` found for `array`"]
+    );
+}
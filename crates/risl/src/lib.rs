@@ -1,2 +1,204 @@
 pub mod cli;
+pub mod consteval;
+pub mod error_codes;
+pub mod exhaustiveness;
+pub mod ice;
+pub mod int;
+pub mod interpreter;
+pub mod mutability;
 pub mod parser;
+pub mod resolve;
+pub mod runtime;
+pub mod session;
+pub mod traits;
+pub mod typeck;
+pub mod value;
+pub mod vm;
+pub mod worker;
+
+#[cfg(test)]
+mod test_utils;
+
+use std::io::Write;
+
+use consteval::Consts;
+use parser::ast::Program;
+use parser::context::ParseContext;
+use resolve::Resolutions;
+
+/// Options of the static checks.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CheckOptions {
+    /// Checks the types of the program even without the `#![type_check]` attribute.
+    pub type_check: bool,
+}
+
+/// Runs all the static checks on the given source, reporting errors through the context.
+pub fn check(context: &ParseContext, source: &str) {
+    check_with_options(context, source, CheckOptions::default());
+}
+
+/// Runs all the static checks on the given source with the given options, reporting errors
+/// through the context.
+pub fn check_with_options(context: &ParseContext, source: &str, options: CheckOptions) {
+    analyze(context, source, options);
+}
+
+/// Why a program did not run to completion.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RunError {
+    /// The static checks reported errors, and the program was not run.
+    Check,
+    /// The program stopped on a runtime error.
+    Runtime,
+}
+
+/// How programs are run. Both backends give the same results.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Backend {
+    /// The tree-walking interpreter, evaluating the AST. Unlike the virtual machine, it does not
+    /// collect the reference cycles, like a closure stored in a variable it captures: a program
+    /// creating many of them runs out of memory sooner.
+    TreeWalk,
+    /// The virtual machine, running the program compiled to bytecode.
+    #[default]
+    Vm,
+}
+
+/// Options of the execution of the programs.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RunOptions {
+    pub backend: Backend,
+    /// Prints the stack before each instruction run by the virtual machine to the standard error.
+    pub trace: bool,
+    /// Collects the cycles after every allocation of the virtual machine, to test the collector.
+    pub gc_stress: bool,
+    /// Prints the statistics of the collector of the virtual machine to the standard error at the
+    /// end of the run.
+    pub gc_stats: bool,
+}
+
+/// Checks and runs the given source on the virtual machine, writing what it prints to the output
+/// and reporting errors through the context. See [`run_with_options`].
+pub fn run(
+    context: &ParseContext,
+    source: &str,
+    options: CheckOptions,
+    output: &mut dyn Write,
+) -> Result<(), RunError> {
+    run_with_options(context, source, options, RunOptions::default(), output)
+}
+
+/// Checks and runs the given source with the given options, writing what it prints to the output
+/// and reporting errors through the context.
+///
+/// The program runs on a thread of its own, whose stack is large enough for deep recursion, see
+/// [`worker::STACK_SIZE`].
+pub fn run_with_options(
+    context: &ParseContext,
+    source: &str,
+    options: CheckOptions,
+    run_options: RunOptions,
+    output: &mut dyn Write,
+) -> Result<(), RunError> {
+    worker::run(context, output, |context, output| {
+        run_on_current_thread(context, source, options, run_options, output)
+    })
+}
+
+fn run_on_current_thread(
+    context: &ParseContext,
+    source: &str,
+    options: CheckOptions,
+    run_options: RunOptions,
+    output: &mut dyn Write,
+) -> Result<(), RunError> {
+    let result = match run_options.backend {
+        Backend::TreeWalk => {
+            let (program, resolutions, consts) = analyze(context, source, options);
+            if context.diag_ctx().has_errors() {
+                return Err(RunError::Check);
+            }
+            let decls = runtime::Declarations::new(&program, &resolutions, &consts);
+            interpreter::run(context, &program, &resolutions, &decls, output)
+        }
+        Backend::Vm => {
+            let module = compile(context, source, options).ok_or(RunError::Check)?;
+            return run_module(context, &module, run_options, output);
+        }
+    };
+    result.map_err(|error| {
+        error.emit(context.diag_ctx());
+        RunError::Runtime
+    })
+}
+
+/// Runs a compiled module on the virtual machine, writing what it prints to the output and
+/// reporting errors through the context. The backend of the options is ignored.
+///
+/// Unlike [`run_with_options`], the module runs on the thread of the caller. The machine recurses
+/// on its stack for each call of a built-in back into the program, like `catch_unwind`, up to
+/// [`runtime::MAX_CALL_DEPTH`] calls: deep recursion through them needs a stack of
+/// [`worker::STACK_SIZE`].
+pub fn run_module(
+    context: &ParseContext,
+    module: &vm::Module,
+    run_options: RunOptions,
+    output: &mut dyn Write,
+) -> Result<(), RunError> {
+    if let Some(source_map) = context.diag_ctx().source_map() {
+        ice::set_current_source(source_map);
+    }
+    let (mut trace, mut gc_stats) = (std::io::stderr(), std::io::stderr());
+    let options = vm::Options {
+        trace: run_options.trace.then_some(&mut trace as &mut dyn Write),
+        gc_stress: run_options.gc_stress,
+        gc_stats: run_options
+            .gc_stats
+            .then_some(&mut gc_stats as &mut dyn Write),
+    };
+    vm::run(context, module, output, options).map_err(|error| {
+        error.emit(context.diag_ctx());
+        RunError::Runtime
+    })
+}
+
+/// Checks the given source and compiles it to bytecode for the virtual machine, returning `None`
+/// if errors were reported through the context.
+pub fn compile(context: &ParseContext, source: &str, options: CheckOptions) -> Option<vm::Module> {
+    let first = context.diag_ctx().diagnostics().len();
+    let (program, resolutions, consts) = analyze(context, source, options);
+    if context.diag_ctx().has_errors() {
+        return None;
+    }
+    let decls = runtime::Declarations::new(&program, &resolutions, &consts);
+    let mut module = vm::compile(context, &program, &resolutions, decls);
+    if context.diag_ctx().has_errors() {
+        return None;
+    }
+    module.warnings = context.diag_ctx().diagnostics().split_off(first);
+    Some(module)
+}
+
+/// Parses the source and runs the static checks, returning the program with the results of the
+/// passes the interpreter uses.
+fn analyze(
+    context: &ParseContext,
+    source: &str,
+    options: CheckOptions,
+) -> (Program, Resolutions, Consts) {
+    if let Some(source_map) = context.diag_ctx().source_map() {
+        ice::set_current_source(source_map);
+    }
+    let mut program = parser::parse(context, source);
+    let resolutions = resolve::resolve(context, &mut program);
+    mutability::check(context, &program, &resolutions);
+    exhaustiveness::check(context, &program, &resolutions);
+    traits::check(context, &program);
+    if options.type_check || program.has_attr("type_check") {
+        typeck::check(context, &program, &resolutions);
+    }
+    let consts = consteval::eval(context, &program, &resolutions);
+    consteval::fold(context, &mut program, &resolutions, &consts);
+    (program, resolutions, consts)
+}
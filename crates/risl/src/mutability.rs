@@ -0,0 +1,554 @@
+//! Checking of the mutability of the variables.
+//!
+//! A variable can only be assigned, or borrowed mutably with `&mut` or by calling a method taking
+//! `&mut self`, if it is declared with `mut`, except for a `let` declaration without initializer
+//! which can be assigned later. Fields and elements are mutable when their variable is, or when it
+//! holds a `&mut` reference. In methods, `self` is mutable when taken as `&mut self` or
+//! `mut self`. The `const` items can never be assigned nor borrowed mutably.
+//!
+//! As the checker runs without type information, a method call is seen as borrowing its receiver
+//! mutably when every method of that name declared in the program, in a built-in trait or on a
+//! built-in type takes `&mut self`: the built-in methods modifying an array or a map, like `push`
+//! or `insert`, count as such. Calls of the methods not declared anywhere may mutate their
+//! receiver but are never reported.
+//!
+//! The `mut` variables never mutated are reported as warnings, except for the globals of an
+//! interactive session, which its next inputs can mutate.
+
+use std::collections::HashMap;
+
+use crate::error_codes::{self, ErrorCode};
+use crate::parser::ast::*;
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::{Diagnostic, Level};
+use crate::parser::lexer::Span;
+use crate::parser::visit::{self, Visitor};
+use crate::resolve::{Res, Resolutions, Variable};
+use crate::runtime::BUILTIN_MUT_METHODS;
+use crate::traits::BUILTIN_TRAITS;
+
+/// Checks the mutability of the variables of the program, reporting errors through the context.
+pub fn check(context: &ParseContext, program: &Program, resolutions: &Resolutions) {
+    check_program(
+        context,
+        &[],
+        program,
+        resolutions,
+        &mut Globals::default(),
+        false,
+    );
+}
+
+/// The variables declared by the previous inputs of an interactive session, whose globals the next
+/// inputs can mutate.
+#[derive(Default)]
+pub struct Globals {
+    bindings: Vec<Binding>,
+    /// The binding currently stored in each variable, by index in the bindings.
+    variables: HashMap<Variable, usize>,
+}
+
+/// Checks the mutability of the variables of an input of an interactive session like [`check`],
+/// given the previous inputs and the variables they declared, which are extended with the ones of
+/// the input.
+pub fn check_input(
+    context: &ParseContext,
+    previous: &[Program],
+    program: &Program,
+    resolutions: &Resolutions,
+    globals: &mut Globals,
+) {
+    check_program(context, previous, program, resolutions, globals, true);
+}
+
+fn check_program(
+    context: &ParseContext,
+    previous: &[Program],
+    program: &Program,
+    resolutions: &Resolutions,
+    globals: &mut Globals,
+    is_session: bool,
+) {
+    let mut collector = MethodCollector::default();
+    for program in previous.iter().chain([program]) {
+        collector.visit_program(program);
+    }
+    for builtin in BUILTIN_TRAITS {
+        for method in builtin.methods {
+            collector.record(method.name, method.receiver);
+        }
+    }
+    for name in BUILTIN_MUT_METHODS {
+        collector.record(name, SelfKind::Ref(Mutability::Mut));
+    }
+    let mut consts = ConstCollector::default();
+    for program in previous.iter().chain([program]) {
+        consts.visit_program(program);
+    }
+    let first_binding = globals.bindings.len();
+    let mut checker = Checker {
+        context,
+        resolutions,
+        methods: collector.0,
+        consts: consts.0,
+        bindings: std::mem::take(&mut globals.bindings),
+        variables: std::mem::take(&mut globals.variables),
+        frames: vec![None],
+        binding_kind: BindingKind::Let,
+        in_or_alternative: false,
+        init_is_mut_ref: false,
+        deferred: vec![],
+    };
+    checker.visit_program(program);
+    // Functions are checked once all the globals they can use are declared
+    while !checker.deferred.is_empty() {
+        for (id, decl) in std::mem::take(&mut checker.deferred) {
+            checker.check_fn(id, decl);
+        }
+    }
+    checker.report_unused_mut(first_binding, is_session);
+    globals.bindings = checker.bindings;
+    globals.variables = checker.variables;
+}
+
+/// How the methods of a given name take their receiver.
+#[derive(Clone, Copy, Default)]
+struct Receivers {
+    mutable: bool,
+    other: bool,
+}
+
+/// Collects how the methods declared in the program take their receiver, by method name.
+#[derive(Default)]
+struct MethodCollector<'a>(HashMap<&'a str, Receivers>);
+
+impl<'a> MethodCollector<'a> {
+    fn record(&mut self, name: &'a str, receiver: SelfKind) {
+        let receivers = self.0.entry(name).or_default();
+        match receiver {
+            SelfKind::Ref(Mutability::Mut) => receivers.mutable = true,
+            _ => receivers.other = true,
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for MethodCollector<'a> {
+    fn visit_fn_decl(&mut self, decl: &'a FnDecl) {
+        if let Some(self_param) = &decl.self_param {
+            self.record(&decl.name.name, self_param.kind);
+        }
+        visit::walk_fn_decl(self, decl);
+    }
+}
+
+/// Collects the names of the `const` items, by item id.
+#[derive(Default)]
+struct ConstCollector<'a>(HashMap<NodeId, &'a Ident>);
+
+impl<'a> Visitor<'a> for ConstCollector<'a> {
+    fn visit_item(&mut self, item: &'a Item) {
+        if let ItemKind::Const(decl) = &item.kind {
+            self.0.insert(item.id, &decl.name);
+        }
+        visit::walk_item(self, item);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum BindingKind {
+    /// A variable declared by a `let` statement with an initializer, a `match` arm or a `for`
+    /// loop.
+    Let,
+    /// A variable declared by a `let` statement without initializer, assigned later.
+    Uninit,
+    /// A parameter of a function or closure.
+    Param,
+    /// The `self` parameter of a method.
+    SelfParam(SelfKind),
+}
+
+struct Binding {
+    name: String,
+    /// The span of the name of the variable.
+    name_span: Span,
+    /// The span of the whole binding, `mut` included.
+    span: Span,
+    kind: BindingKind,
+    is_mut: bool,
+    /// True if the variable holds a `&mut` reference, through which its fields can be mutated.
+    is_mut_ref: bool,
+    is_mutated: bool,
+    /// True if the variable is a global, which is known once declared.
+    is_global: bool,
+}
+
+/// How a place is mutated.
+#[derive(Clone, Copy, PartialEq)]
+enum Mutation {
+    Assign,
+    Borrow,
+}
+
+struct Checker<'a> {
+    context: &'a ParseContext,
+    resolutions: &'a Resolutions,
+    methods: HashMap<&'a str, Receivers>,
+    /// The names of the `const` items, by item id.
+    consts: HashMap<NodeId, &'a Ident>,
+    bindings: Vec<Binding>,
+    /// The binding currently stored in each variable, by index in the bindings.
+    variables: HashMap<Variable, usize>,
+    /// The functions and closures being checked, by id, the top-level code having none.
+    frames: Vec<Option<NodeId>>,
+    /// The kind of the bindings declared by the pattern being visited.
+    binding_kind: BindingKind,
+    /// True while visiting an alternative of an or-pattern other than the first one, which binds
+    /// the variables of the first one.
+    in_or_alternative: bool,
+    /// Whether the initializer of the `let` statement being visited is a `&mut` borrow.
+    init_is_mut_ref: bool,
+    /// The functions whose body remains to be checked, by item id.
+    deferred: Vec<(NodeId, &'a FnDecl)>,
+}
+
+impl<'a> Checker<'a> {
+    fn emit(&self, level: Level, span: Span, message: String, code: Option<ErrorCode>) {
+        let mut diagnostic = Diagnostic::spanned(level, span, message);
+        if let Some(code) = code {
+            diagnostic = diagnostic.with_code(code);
+        }
+        self.context.diag_ctx().emit(diagnostic);
+    }
+
+    /// Returns where the variable a name resolved to is stored, seen from the current frame.
+    fn variable(&self, res: Res) -> Option<Variable> {
+        self.resolutions.variable(res, &self.frames)
+    }
+
+    /// Records the binding declared by the binding pattern or `self` parameter with the given id.
+    fn declare(&mut self, id: NodeId, mut binding: Binding) {
+        let Some(res) = self.resolutions.names.get(&id) else {
+            return;
+        };
+        if let Some(variable) = self.variable(*res) {
+            binding.is_global = matches!(variable, Variable::Global(_));
+            self.bindings.push(binding);
+            self.variables.insert(variable, self.bindings.len() - 1);
+        }
+    }
+
+    fn check_fn(&mut self, id: NodeId, decl: &'a FnDecl) {
+        let Some(body) = &decl.body else {
+            return;
+        };
+        let frames = std::mem::replace(&mut self.frames, vec![Some(id)]);
+        if let Some(self_param) = &decl.self_param {
+            self.declare(
+                self_param.id,
+                Binding {
+                    name: String::from("self"),
+                    name_span: self_param.span,
+                    span: self_param.span,
+                    kind: BindingKind::SelfParam(self_param.kind),
+                    is_mut: self_param.kind == SelfKind::Value(Mutability::Mut),
+                    is_mut_ref: self_param.kind == SelfKind::Ref(Mutability::Mut),
+                    is_mutated: false,
+                    is_global: false,
+                },
+            );
+        }
+        for param in &decl.params {
+            self.visit_param(param);
+        }
+        self.visit_block(body);
+        self.frames = frames;
+    }
+
+    /// Checks the mutation of the place expression, reporting an error if its variable is not
+    /// mutable.
+    fn mutate(&mut self, place: &Expr, mutation: Mutation, span: Span) {
+        let mut root = place;
+        let mut is_projection = false;
+        loop {
+            match &root.kind {
+                ExprKind::Field(inner, _) | ExprKind::Index(inner, _) => {
+                    root = inner;
+                    is_projection = true;
+                }
+                ExprKind::Paren(inner) => root = inner,
+                ExprKind::Path(_) => break,
+                // Mutations through references, or of temporary values
+                _ => return,
+            }
+        }
+        let Some(res) = self.resolutions.names.get(&root.id) else {
+            return;
+        };
+        if let Res::Item(id) = res {
+            if let Some(name) = self.consts.get(id) {
+                self.mutate_const(place, name, mutation, span);
+            }
+            return;
+        }
+        let Some(variable) = self.variable(*res) else {
+            return;
+        };
+        let Some(&index) = self.variables.get(&variable) else {
+            return;
+        };
+        let binding = &mut self.bindings[index];
+        if binding.is_mut {
+            binding.is_mutated = true;
+            return;
+        }
+        if binding.is_mut_ref && (is_projection || mutation == Mutation::Borrow) {
+            return;
+        }
+        if binding.kind == BindingKind::Uninit && mutation == Mutation::Assign && !is_projection {
+            return;
+        }
+
+        let binding = &self.bindings[index];
+        let place = describe_place(place);
+        if let BindingKind::SelfParam(SelfKind::Ref(Mutability::Not)) = binding.kind {
+            let message = match mutation {
+                Mutation::Assign => {
+                    format!("cannot assign to `{place}`, which is behind a `&` reference")
+                }
+                Mutation::Borrow if place == "self" => String::from(
+                    "cannot borrow `*self` as mutable, as it is behind a `&` reference",
+                ),
+                Mutation::Borrow => {
+                    format!("cannot borrow `{place}` as mutable, as it is behind a `&` reference")
+                }
+            };
+            let code = match mutation {
+                Mutation::Assign => error_codes::E0070,
+                Mutation::Borrow => error_codes::E0071,
+            };
+            self.emit(Level::Error, span, message, Some(code));
+            let help =
+                String::from("consider changing this to be a mutable reference: `&mut self`");
+            self.emit(Level::Help, binding.span, help, None);
+            return;
+        }
+
+        let name = &binding.name;
+        let (message, code) = match (mutation, binding.kind) {
+            (Mutation::Assign, _) if is_projection => (
+                format!("cannot assign to `{place}`, as `{name}` is not declared as mutable"),
+                error_codes::E0070,
+            ),
+            (Mutation::Assign, BindingKind::Param) => (
+                format!("cannot assign to immutable argument `{name}`"),
+                error_codes::E0070,
+            ),
+            (Mutation::Assign, _) => (
+                format!("cannot assign twice to immutable variable `{name}`"),
+                error_codes::E0070,
+            ),
+            (Mutation::Borrow, _) => (
+                format!(
+                    "cannot borrow `{place}` as mutable, as `{name}` is not declared as mutable"
+                ),
+                error_codes::E0071,
+            ),
+        };
+        self.emit(Level::Error, span, message, Some(code));
+        let help = format!("consider making this binding mutable: `mut {name}`");
+        self.emit(Level::Help, binding.name_span, help, None);
+    }
+
+    /// Reports the mutation of the place expression, whose root is the `const` item with the given
+    /// name.
+    fn mutate_const(&self, place: &Expr, name: &Ident, mutation: Mutation, span: Span) {
+        let place = describe_place(place);
+        let name = &name.name;
+        let message = match mutation {
+            Mutation::Assign if place == *name => format!("cannot assign to constant `{name}`"),
+            Mutation::Assign => format!("cannot assign to `{place}`, as `{name}` is a constant"),
+            Mutation::Borrow if place == *name => {
+                format!("cannot borrow constant `{name}` as mutable")
+            }
+            Mutation::Borrow => {
+                format!("cannot borrow `{place}` as mutable, as `{name}` is a constant")
+            }
+        };
+        self.emit(Level::Error, span, message, Some(error_codes::E0072));
+    }
+
+    /// Returns true if calling the method with the given name borrows the receiver mutably.
+    fn borrows_receiver_mutably(&self, name: &str) -> bool {
+        self.methods
+            .get(name)
+            .is_some_and(|receivers| receivers.mutable && !receivers.other)
+    }
+
+    /// Returns true if calling the method with the given name may mutate the receiver.
+    fn may_mutate_receiver(&self, name: &str) -> bool {
+        self.methods
+            .get(name)
+            .is_none_or(|receivers| receivers.mutable)
+    }
+
+    /// Reports the `mut` variables never mutated from the binding with the given index, except for
+    /// the globals of an interactive session.
+    fn report_unused_mut(&self, first: usize, is_session: bool) {
+        for binding in &self.bindings[first..] {
+            if binding.is_mut && !binding.is_mutated && !(is_session && binding.is_global) {
+                let message = String::from("variable does not need to be mutable");
+                self.emit(Level::Warning, binding.span, message, None);
+            }
+        }
+    }
+
+    /// Marks the variable of the receiver of a method call as mutated, if mutable.
+    fn mark_mutated(&mut self, receiver: &Expr) {
+        let mut root = receiver;
+        while let ExprKind::Field(inner, _) | ExprKind::Index(inner, _) | ExprKind::Paren(inner) =
+            &root.kind
+        {
+            root = inner;
+        }
+        let variable = self
+            .resolutions
+            .names
+            .get(&root.id)
+            .and_then(|res| self.variable(*res));
+        if let Some(index) = variable.and_then(|variable| self.variables.get(&variable)) {
+            let binding = &mut self.bindings[*index];
+            binding.is_mutated |= binding.is_mut;
+        }
+    }
+}
+
+/// Returns the place expression as written in Risl, indices elided.
+fn describe_place(place: &Expr) -> String {
+    match &place.kind {
+        ExprKind::Path(path) => path.to_string(),
+        ExprKind::Field(inner, name) => format!("{}.{}", describe_place(inner), name.name),
+        ExprKind::Index(inner, _) => format!("{}[_]", describe_place(inner)),
+        ExprKind::Paren(inner) => describe_place(inner),
+        _ => String::from("_"),
+    }
+}
+
+impl<'a> Visitor<'a> for Checker<'a> {
+    fn visit_local(&mut self, local: &'a Local) {
+        // The initializer uses the variables the pattern may shadow
+        if let Some(init) = &local.init {
+            self.visit_expr(init);
+        }
+        let is_mut_ref = matches!(
+            (&local.ty, &local.init),
+            (
+                Some(Type {
+                    kind: TypeKind::Ref(Mutability::Mut, _),
+                    ..
+                }),
+                _
+            ) | (
+                _,
+                Some(Expr {
+                    kind: ExprKind::AddrOf(Mutability::Mut, _),
+                    ..
+                })
+            )
+        );
+        self.binding_kind = match local.init {
+            Some(_) => BindingKind::Let,
+            None => BindingKind::Uninit,
+        };
+        self.init_is_mut_ref = is_mut_ref;
+        self.visit_pat(&local.pat);
+        self.binding_kind = BindingKind::Let;
+        self.init_is_mut_ref = false;
+    }
+
+    fn visit_param(&mut self, param: &'a Param) {
+        self.binding_kind = BindingKind::Param;
+        self.init_is_mut_ref = matches!(
+            &param.ty,
+            Some(Type {
+                kind: TypeKind::Ref(Mutability::Mut, _),
+                ..
+            })
+        );
+        self.visit_pat(&param.pat);
+        self.binding_kind = BindingKind::Let;
+        self.init_is_mut_ref = false;
+    }
+
+    fn visit_pat(&mut self, pat: &'a Pattern) {
+        match &pat.kind {
+            PatKind::Ident(mutability, name, _) if !self.in_or_alternative => {
+                self.declare(
+                    pat.id,
+                    Binding {
+                        name: name.name.clone(),
+                        name_span: name.span,
+                        span: Span::new(pat.span.start, name.span.end),
+                        kind: self.binding_kind,
+                        is_mut: *mutability == Mutability::Mut,
+                        is_mut_ref: self.init_is_mut_ref,
+                        is_mutated: false,
+                        is_global: false,
+                    },
+                );
+            }
+            PatKind::Or(alternatives) => {
+                let in_or_alternative = self.in_or_alternative;
+                for (index, alternative) in alternatives.iter().enumerate() {
+                    self.in_or_alternative = in_or_alternative || index > 0;
+                    self.visit_pat(alternative);
+                }
+                self.in_or_alternative = in_or_alternative;
+                return;
+            }
+            _ => (),
+        }
+        visit::walk_pat(self, pat);
+    }
+
+    fn visit_item(&mut self, item: &'a Item) {
+        match &item.kind {
+            ItemKind::Fn(decl) => self.deferred.push((item.id, decl)),
+            ItemKind::Const(_) => {
+                let frames = std::mem::replace(&mut self.frames, vec![Some(item.id)]);
+                visit::walk_item(self, item);
+                self.frames = frames;
+            }
+            _ => visit::walk_item(self, item),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        crate::ice::set_current_span(expr.span);
+        match &expr.kind {
+            ExprKind::Assign(place, _) | ExprKind::AssignOp(_, place, _) => {
+                self.mutate(place, Mutation::Assign, expr.span);
+            }
+            ExprKind::AddrOf(Mutability::Mut, place) => {
+                self.mutate(place, Mutation::Borrow, expr.span);
+            }
+            ExprKind::MethodCall(receiver, name, _) => {
+                if self.borrows_receiver_mutably(&name.name) {
+                    self.mutate(receiver, Mutation::Borrow, expr.span);
+                } else if self.may_mutate_receiver(&name.name) {
+                    self.mark_mutated(receiver);
+                }
+            }
+            ExprKind::Closure(_) => {
+                self.frames.push(Some(expr.id));
+                visit::walk_expr(self, expr);
+                self.frames.pop();
+                return;
+            }
+            _ => (),
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,188 @@
+use crate::test_utils::check;
+
+#[test]
+fn assignments() {
+    assert_eq!(
+        check("let x = 1;\nx = 2;"),
+        [
+            "error: cannot assign twice to immutable variable `x`",
+            "help: consider making this binding mutable: `mut x`",
+        ]
+    );
+    assert_eq!(
+        check("let mut x = 1;\nx = 2;\nx += 1;"),
+        Vec::<String>::new()
+    );
+    // A variable declared without initializer can be assigned later
+    assert_eq!(check("let x;\nx = 2;"), Vec::<String>::new());
+    assert_eq!(
+        check("fn f(n) { n = 0; }"),
+        [
+            "error: cannot assign to immutable argument `n`",
+            "help: consider making this binding mutable: `mut n`",
+        ]
+    );
+    assert_eq!(
+        check("let point = (1, 2);\npoint.0 = 3;"),
+        [
+            "error: cannot assign to `point.0`, as `point` is not declared as mutable",
+            "help: consider making this binding mutable: `mut point`",
+        ]
+    );
+    // Fields can be mutated through a `&mut` reference
+    assert_eq!(
+        check("let mut point = (1, 2);\nlet r = &mut point;\nr.0 = 3;"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn shadowing_and_captures() {
+    assert_eq!(
+        check("let mut x = 1;\nlet x = x + 1;\nx = 3;"),
+        [
+            "error: cannot assign twice to immutable variable `x`",
+            "help: consider making this binding mutable: `mut x`",
+            "warning: variable does not need to be mutable",
+        ]
+    );
+    assert_eq!(
+        check("let mut count = 0;\nlet increment = || count += 1;"),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        check("let count = 0;\nlet increment = || count += 1;"),
+        [
+            "error: cannot assign twice to immutable variable `count`",
+            "help: consider making this binding mutable: `mut count`",
+        ]
+    );
+    // Closures mutating their captures use the `mut` of the captured variables
+    let captures = [
+        "let mut items = [];\nlet add = |item| items.push(item);",
+        "fn f() { let mut n = 0; let add = || || n += 1; }",
+        "fn f(mut v) { let g = move || { let r = &mut v; r.push(1); }; }",
+    ];
+    for source in captures {
+        assert_eq!(check(source), Vec::<String>::new(), "{source}");
+    }
+    assert_eq!(
+        check("let items = [];\nlet add = |item| items.push(item);"),
+        [
+            "error: cannot borrow `items` as mutable, as `items` is not declared as mutable",
+            "help: consider making this binding mutable: `mut items`",
+        ]
+    );
+    // Functions see the globals declared after them
+    assert_eq!(
+        check("fn reset() { total = 0; }\nlet mut total = 10;"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn mutable_borrows() {
+    let counter = "struct Counter { count: i64 }\n\
+         impl Counter {\n\
+             fn get(&self) -> i64 { self.count }\n\
+             fn increment(&mut self) { self.count += 1; }\n\
+         }\n";
+    assert_eq!(
+        check(&format!(
+            "{counter}let counter = Counter {{ count: 0 }};\ncounter.increment();"
+        )),
+        [
+            "error: cannot borrow `counter` as mutable, as `counter` is not declared as mutable",
+            "help: consider making this binding mutable: `mut counter`",
+        ]
+    );
+    assert_eq!(
+        check(&format!(
+            "{counter}let mut counter = Counter {{ count: 0 }};\ncounter.increment();\ncounter.get();"
+        )),
+        Vec::<String>::new()
+    );
+    // The built-in methods modifying arrays and maps take `&mut self`
+    assert_eq!(
+        check("let items = [3, 1];\nitems.sort();\nlet m = Map::new();\nm.insert(1, 2);"),
+        [
+            "error: cannot borrow `items` as mutable, as `items` is not declared as mutable",
+            "help: consider making this binding mutable: `mut items`",
+            "error: cannot borrow `m` as mutable, as `m` is not declared as mutable",
+            "help: consider making this binding mutable: `mut m`",
+        ]
+    );
+    assert_eq!(
+        check("fn f(items: &mut [i64]) { items.push(1); }"),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        check("let x = 1;\nlet r = &mut x;"),
+        [
+            "error: cannot borrow `x` as mutable, as `x` is not declared as mutable",
+            "help: consider making this binding mutable: `mut x`",
+        ]
+    );
+}
+
+#[test]
+fn constants() {
+    assert_eq!(
+        check(
+            "const LIMIT: i64 = 10;\nconst PRIMES: [i64] = [2, 3];\n\
+             LIMIT = 20;\nfn f() { PRIMES[0] += 1; PRIMES.push(5); }"
+        ),
+        [
+            "error: cannot assign to constant `LIMIT`",
+            "error: cannot assign to `PRIMES[_]`, as `PRIMES` is a constant",
+            "error: cannot borrow constant `PRIMES` as mutable",
+        ]
+    );
+    // A variable shadowing a constant is not the constant
+    assert_eq!(
+        check("const LIMIT: i64 = 10;\nlet mut LIMIT = LIMIT;\nLIMIT = 20;"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn self_behind_a_shared_reference() {
+    assert_eq!(
+        check(
+            "struct Counter { count: i64 }\n\
+             impl Counter {\n\
+                 fn increment(&self) { self.count += 1; }\n\
+                 fn bump(&mut self) { self.count += 1; }\n\
+                 fn twice(&self) { self.bump(); }\n\
+             }"
+        ),
+        [
+            "error: cannot assign to `self.count`, which is behind a `&` reference",
+            "help: consider changing this to be a mutable reference: `&mut self`",
+            "error: cannot borrow `*self` as mutable, as it is behind a `&` reference",
+            "help: consider changing this to be a mutable reference: `&mut self`",
+        ]
+    );
+}
+
+#[test]
+fn unused_mut() {
+    assert_eq!(
+        check("let mut x = 1;\nprintln(x);"),
+        ["warning: variable does not need to be mutable"]
+    );
+    assert_eq!(
+        check("fn f(mut n) { n }"),
+        ["warning: variable does not need to be mutable"]
+    );
+    // The built-in methods modifying their receiver count as mutations
+    assert_eq!(
+        check("let mut items = [];\nitems.push(1);"),
+        Vec::<String>::new()
+    );
+    // The alternatives of an or-pattern bind the same variables
+    assert_eq!(
+        check("fn f(pair) { match pair { (mut x, 0) | (0, mut x) => { x += 1; } _ => {} } }"),
+        Vec::<String>::new()
+    );
+}
@@ -0,0 +1,626 @@
+//! The abstract syntax tree of the Risl language produced by the parser.
+//!
+//! Every node owns its children and carries the span of the source it was parsed from. Nodes that
+//! later passes need to attach information to (expressions, patterns, items...) also carry a
+//! [`NodeId`] unique in the parsed program, so that side tables can be keyed by node.
+
+use super::lexer::Span;
+
+/// A unique identifier of an AST node in a program.
+pub type NodeId = u32;
+
+/// An identifier, as used for variables, fields, functions or types.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+/// A path to a named entity, like `foo`, `Point` or `Shape::Circle`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Path {
+    pub segments: Vec<Ident>,
+    pub span: Span,
+}
+
+impl Path {
+    /// Returns the identifier if the path is made of a single segment.
+    pub fn as_ident(&self) -> Option<&Ident> {
+        match self.segments.as_slice() {
+            [ident] => Some(ident),
+            _ => None,
+        }
+    }
+
+    /// Returns the last segment of the path.
+    pub fn last(&self) -> &Ident {
+        self.segments
+            .last()
+            .expect("paths have at least one segment")
+    }
+}
+
+impl std::fmt::Display for Path {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i > 0 {
+                write!(f, "::")?;
+            }
+            write!(f, "{}", segment.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// A whole Risl program, made of items and statements executed in order.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Program {
+    pub attrs: Vec<Attribute>,
+    pub stmts: Vec<Stmt>,
+    pub span: Span,
+}
+
+impl Program {
+    /// Returns true if the program has the given attribute.
+    pub fn has_attr(&self, name: &str) -> bool {
+        self.attrs.iter().any(|attr| attr.name.name == name)
+    }
+}
+
+/// An attribute applying to the whole program, written at its start: `#![type_check]`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Attribute {
+    pub name: Ident,
+    pub span: Span,
+}
+
+/// The attributes a program can have.
+pub const KNOWN_ATTRIBUTES: &[&str] = &[
+    // Enables the static type checking of the program
+    "type_check",
+];
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Item {
+    pub id: NodeId,
+    pub kind: ItemKind,
+    pub is_pub: bool,
+    pub span: Span,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ItemKind {
+    Fn(FnDecl),
+    Struct(StructDecl),
+    Enum(EnumDecl),
+    Impl(ImplDecl),
+    Trait(TraitDecl),
+    Const(ConstDecl),
+}
+
+impl Item {
+    /// Returns the name of the item, if it has one.
+    pub fn name(&self) -> Option<&Ident> {
+        match &self.kind {
+            ItemKind::Fn(decl) => Some(&decl.name),
+            ItemKind::Struct(decl) => Some(&decl.name),
+            ItemKind::Enum(decl) => Some(&decl.name),
+            ItemKind::Trait(decl) => Some(&decl.name),
+            ItemKind::Const(decl) => Some(&decl.name),
+            ItemKind::Impl(_) => None,
+        }
+    }
+}
+
+/// A function declaration: `fn name(params) -> ret { body }`.
+///
+/// Functions declared in an `impl` block are methods if they have a `self` parameter, and
+/// associated functions otherwise. Only the functions of a `trait` can lack a body, in which case
+/// they are required from its implementations.
+///
+/// A `const fn` can be called at compile time, by constants.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FnDecl {
+    pub is_const: bool,
+    pub name: Ident,
+    pub self_param: Option<SelfParam>,
+    pub params: Vec<Param>,
+    pub ret: Option<Type>,
+    pub body: Option<Block>,
+}
+
+/// The `self` parameter of a method, giving access to its receiver.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SelfParam {
+    pub id: NodeId,
+    pub kind: SelfKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SelfKind {
+    /// The receiver taken by value: `self` or `mut self`.
+    Value(Mutability),
+    /// The receiver taken by reference: `&self` or `&mut self`.
+    Ref(Mutability),
+}
+
+/// A function parameter, with an optional type annotation.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Param {
+    pub id: NodeId,
+    pub pat: Pattern,
+    pub ty: Option<Type>,
+    pub span: Span,
+}
+
+/// A struct declaration, with named fields, tuple fields or no fields.
+#[derive(Clone, PartialEq, Debug)]
+pub struct StructDecl {
+    pub name: Ident,
+    pub fields: VariantFields,
+}
+
+/// An enum declaration, whose variants can carry data.
+#[derive(Clone, PartialEq, Debug)]
+pub struct EnumDecl {
+    pub name: Ident,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Variant {
+    pub id: NodeId,
+    pub name: Ident,
+    pub fields: VariantFields,
+    pub span: Span,
+}
+
+/// An `impl` block, declaring the methods and associated functions of a type:
+/// `impl Point { fn new() -> Self { ... } }`, or implementing a trait for a type:
+/// `impl Display for Point { fn fmt(&self) -> str { ... } }`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ImplDecl {
+    pub trait_ref: Option<Path>,
+    pub self_ty: Type,
+    pub items: Vec<Item>,
+}
+
+/// A trait declaration, made of required methods without a body and default methods with one:
+/// `trait Shape { fn area(&self) -> f64; fn name(&self) -> str { "shape" } }`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TraitDecl {
+    pub name: Ident,
+    pub items: Vec<Item>,
+}
+
+/// A constant, evaluated at compile time: `const MAX_LEN: usize = 1 << 10;`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ConstDecl {
+    pub name: Ident,
+    pub ty: Type,
+    pub expr: Expr,
+}
+
+/// The fields of a struct or of an enum variant.
+#[derive(Clone, PartialEq, Debug)]
+pub enum VariantFields {
+    /// Named fields: `{ x: f64, y: f64 }`.
+    Named(Vec<FieldDecl>),
+    /// Positional fields: `(f64, f64)`.
+    Tuple(Vec<FieldDecl>),
+    /// No fields at all.
+    Unit,
+}
+
+impl VariantFields {
+    /// Returns the declared fields, if any.
+    pub fn fields(&self) -> &[FieldDecl] {
+        match self {
+            VariantFields::Named(fields) | VariantFields::Tuple(fields) => fields,
+            VariantFields::Unit => &[],
+        }
+    }
+
+    /// Returns the declared fields mutably, if any.
+    pub fn fields_mut(&mut self) -> &mut [FieldDecl] {
+        match self {
+            VariantFields::Named(fields) | VariantFields::Tuple(fields) => fields,
+            VariantFields::Unit => &mut [],
+        }
+    }
+}
+
+/// A field of a struct or variant. Tuple fields are named after their position.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FieldDecl {
+    pub name: Ident,
+    pub ty: Type,
+    pub is_pub: bool,
+    pub span: Span,
+}
+
+/// A block of statements, whose value is the value of its trailing expression if any.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Block {
+    pub id: NodeId,
+    pub stmts: Vec<Stmt>,
+    pub span: Span,
+}
+
+impl Block {
+    /// Returns the trailing expression of the block, giving its value.
+    pub fn tail(&self) -> Option<&Expr> {
+        match self.stmts.last() {
+            Some(Stmt {
+                kind: StmtKind::Expr(expr),
+                ..
+            }) => Some(expr),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Stmt {
+    pub id: NodeId,
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum StmtKind {
+    /// A local variable declaration: `let pat: ty = init;`.
+    Let(Local),
+    /// An item declared in a block.
+    Item(Box<Item>),
+    /// An expression without a trailing semicolon.
+    Expr(Expr),
+    /// An expression followed by a semicolon, discarding its value.
+    Semi(Expr),
+    /// A lone semicolon.
+    Empty,
+    /// A statement that could not be parsed.
+    Err,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Local {
+    pub pat: Pattern,
+    pub ty: Option<Type>,
+    pub init: Option<Expr>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Expr {
+    pub id: NodeId,
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ExprKind {
+    /// A literal: `42`, `1.5`, `"hello"`, `true` or `nil`.
+    Lit(Lit),
+    /// A path to a variable, function, constant or unit variant: `x`, `Shape::Empty`.
+    Path(Path),
+    /// A unary operation: `-x`, `!x`, `*x`.
+    Unary(UnOp, Box<Expr>),
+    /// A reference: `&x` or `&mut x`.
+    AddrOf(Mutability, Box<Expr>),
+    /// A binary operation: `a + b`.
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    /// An assignment: `a = b`.
+    Assign(Box<Expr>, Box<Expr>),
+    /// A compound assignment: `a += b`.
+    AssignOp(BinOp, Box<Expr>, Box<Expr>),
+    /// A cast: `x as f64`.
+    Cast(Box<Expr>, Type),
+    /// A function call: `f(a, b)`.
+    Call(Box<Expr>, Vec<Expr>),
+    /// A method call: `receiver.method(a, b)`.
+    MethodCall(Box<Expr>, Ident, Vec<Expr>),
+    /// A field access: `point.x` or `tuple.0`.
+    Field(Box<Expr>, Ident),
+    /// An indexing operation: `array[index]`.
+    Index(Box<Expr>, Box<Expr>),
+    /// The `?` operator, returning the `None` or `Err` operand from the enclosing function:
+    /// `parse(line)?`.
+    Try(Box<Expr>),
+    /// A tuple: `(a, b)` or the unit value `()`.
+    Tuple(Vec<Expr>),
+    /// An array: `[a, b, c]`.
+    Array(Vec<Expr>),
+    /// A struct literal: `Point { x: 1, y: 2 }`.
+    Struct(StructExpr),
+    /// A parenthesized expression: `(a + b)`.
+    Paren(Box<Expr>),
+    /// A block expression: `{ stmts }`.
+    Block(Block),
+    /// A conditional: `if cond { then } else { otherwise }`.
+    If(Box<Expr>, Block, Option<Box<Expr>>),
+    /// A while loop: `while cond { body }`, with an optional label like `'outer`.
+    While(Box<Expr>, Block, Option<Ident>),
+    /// A for loop: `for pat in iter { body }`, with an optional label.
+    For(Pattern, Box<Expr>, Block, Option<Ident>),
+    /// An infinite loop: `loop { body }`, with an optional label. Its value is the one of the
+    /// `break` exiting it.
+    Loop(Block, Option<Ident>),
+    /// A match expression: `match scrutinee { arms }`.
+    Match(Box<Expr>, Vec<Arm>),
+    /// A range: `a..b`, `a..=b`, `a..`, `..b` or `..`.
+    Range(Option<Box<Expr>>, Option<Box<Expr>>, RangeLimits),
+    /// A return from the enclosing function, with an optional value.
+    Return(Option<Box<Expr>>),
+    /// A break out of the enclosing or labelled loop, with an optional value: `break 'outer 42`.
+    Break(Option<Ident>, Option<Box<Expr>>),
+    /// A continue to the next iteration of the enclosing or labelled loop.
+    Continue(Option<Ident>),
+    /// A closure: `|x, y| x + y` or `move |x: i64| -> i64 { x }`.
+    Closure(Box<Closure>),
+    /// An expression that could not be parsed.
+    Err,
+}
+
+impl ExprKind {
+    /// Returns true if the expression ends with a block and does not need a semicolon to be used
+    /// as a statement.
+    pub fn is_block_like(&self) -> bool {
+        matches!(
+            self,
+            ExprKind::Block(_)
+                | ExprKind::If(..)
+                | ExprKind::While(..)
+                | ExprKind::For(..)
+                | ExprKind::Loop(..)
+                | ExprKind::Match(..)
+        )
+    }
+}
+
+/// A closure expression.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Closure {
+    pub capture_by: CaptureBy,
+    pub params: Vec<Param>,
+    pub ret: Option<Type>,
+    pub body: Box<Expr>,
+    /// The variables of the enclosing functions and closures used by the body, in order of first
+    /// use. Computed by the resolver, empty until then.
+    pub captures: Vec<Ident>,
+}
+
+/// How a closure captures the variables of its environment.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CaptureBy {
+    /// By reference: `|x| x + y`.
+    Ref,
+    /// By value, with `move`: `move |x| x + y`.
+    Value,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct StructExpr {
+    pub path: Path,
+    pub fields: Vec<ExprField>,
+    /// The base struct the remaining fields are taken from: `Point { x: 1, ..other }`.
+    pub rest: Option<Box<Expr>>,
+}
+
+/// A field initializer in a struct literal. `Point { x }` is a shorthand for `Point { x: x }`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ExprField {
+    pub name: Ident,
+    pub expr: Expr,
+    pub is_shorthand: bool,
+    pub span: Span,
+}
+
+/// A match arm: `pat => body`, or `pat if guard => body`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Arm {
+    pub id: NodeId,
+    pub pat: Pattern,
+    pub guard: Option<Box<Expr>>,
+    pub body: Expr,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RangeLimits {
+    /// The end of the range is excluded: `a..b`.
+    HalfOpen,
+    /// The end of the range is included: `a..=b`.
+    Closed,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mutability {
+    Not,
+    Mut,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Lit {
+    Int(IntLit),
+    Float(FloatLit),
+    Str(String),
+    Bool(bool),
+    Nil,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct IntLit {
+    pub value: u128,
+    pub suffix: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct FloatLit {
+    pub value: f64,
+    pub suffix: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnOp {
+    /// The `-` operator.
+    Neg,
+    /// The `!` operator.
+    Not,
+    /// The `*` operator.
+    Deref,
+}
+
+impl UnOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnOp::Neg => "-",
+            UnOp::Not => "!",
+            UnOp::Deref => "*",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+    BitXor,
+    BitAnd,
+    BitOr,
+    Shl,
+    Shr,
+    Eq,
+    Lt,
+    Le,
+    Ne,
+    Ge,
+    Gt,
+}
+
+impl BinOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+            BinOp::BitXor => "^",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Eq => "==",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Ne => "!=",
+            BinOp::Ge => ">=",
+            BinOp::Gt => ">",
+        }
+    }
+
+    /// Returns true for the comparison operators, which cannot be chained.
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinOp::Eq | BinOp::Lt | BinOp::Le | BinOp::Ne | BinOp::Ge | BinOp::Gt
+        )
+    }
+
+    /// Returns true for the lazy boolean operators `&&` and `||`.
+    pub fn is_lazy(&self) -> bool {
+        matches!(self, BinOp::And | BinOp::Or)
+    }
+
+    /// Returns true for the arithmetic operators `+`, `-`, `*`, `/` and `%`.
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(
+            self,
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem
+        )
+    }
+
+    /// Returns true for the bitwise operators `&`, `|` and `^`.
+    pub fn is_bitwise(&self) -> bool {
+        matches!(self, BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Pattern {
+    pub id: NodeId,
+    pub kind: PatKind,
+    pub span: Span,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum PatKind {
+    /// The wildcard pattern `_`.
+    Wild,
+    /// A binding: `x` or `mut x`, optionally matching a subpattern: `x @ 1..=5`.
+    Ident(Mutability, Ident, Option<Box<Pattern>>),
+    /// A literal pattern: `42`, `-1`, `"hello"`, `true` or `nil`.
+    Lit(Box<Expr>),
+    /// A range pattern: `1..=5`, `0..10`, `5..` or `..=5`. The bounds are literal expressions.
+    Range(Option<Box<Expr>>, Option<Box<Expr>>, RangeLimits),
+    /// A tuple pattern: `(a, b)`.
+    Tuple(Vec<Pattern>),
+    /// A slice pattern: `[first, .., last]`.
+    Slice(Vec<Pattern>),
+    /// The rest pattern `..` in a tuple, tuple struct or slice pattern.
+    Rest,
+    /// An or-pattern: `1 | 2 | 3`.
+    Or(Vec<Pattern>),
+    /// A path pattern matching a unit struct or variant: `Shape::Empty`.
+    Path(Path),
+    /// A tuple struct or variant pattern: `Shape::Circle(radius)`.
+    TupleStruct(Path, Vec<Pattern>),
+    /// A struct or struct variant pattern: `Point { x, y: 0, .. }`.
+    Struct(Path, Vec<FieldPat>, bool),
+    /// A pattern that could not be parsed.
+    Err,
+}
+
+/// A field pattern in a struct pattern. `Point { x }` is a shorthand for `Point { x: x }`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FieldPat {
+    pub name: Ident,
+    pub pat: Pattern,
+    pub is_shorthand: bool,
+    pub span: Span,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Type {
+    pub id: NodeId,
+    pub kind: TypeKind,
+    pub span: Span,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum TypeKind {
+    /// A named type with optional generic arguments: `i64`, `Point`, `Vec<str>`.
+    Path(Path, Vec<Type>),
+    /// A tuple type: `(i64, str)` or the unit type `()`.
+    Tuple(Vec<Type>),
+    /// An array type: `[i64]`.
+    Array(Box<Type>),
+    /// A reference type: `&T` or `&mut T`.
+    Ref(Mutability, Box<Type>),
+    /// A function type: `fn(i64) -> bool`.
+    Fn(Vec<Type>, Option<Box<Type>>),
+    /// Any type implementing the given traits: `impl Display + Eq`.
+    ImplTrait(Vec<Path>),
+    /// A type to infer: `_`.
+    Infer,
+    /// A type that could not be parsed.
+    Err,
+}
@@ -8,4 +8,9 @@ impl ParseContext {
     pub fn new(diag_ctx: DiagContext) -> Self {
         Self { diag_ctx }
     }
+
+    /// Returns the diagnostic context used to report errors found while parsing.
+    pub fn diag_ctx(&self) -> &DiagContext {
+        &self.diag_ctx
+    }
 }
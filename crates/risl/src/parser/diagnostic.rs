@@ -1,5 +1,11 @@
+use std::cell::RefCell;
+
 use super::emitter::Emitter;
+use super::lexer::Span;
+use super::source_map::SourceMap;
+use crate::error_codes::ErrorCode;
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Level {
     /// For bugs in the compiler. Manifests as an ICE (internal compiler error) panic.
     Bug,
@@ -18,20 +24,130 @@ pub enum Level {
     Help,
 }
 
+impl Level {
+    /// Returns true if a diagnostic of this level prevents compilation from finishing.
+    pub fn is_error(&self) -> bool {
+        matches!(self, Level::Bug | Level::Fatal | Level::Error)
+    }
+}
+
+impl std::fmt::Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let level = match self {
+            Level::Bug => "error: internal compiler error",
+            Level::Fatal | Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+            Level::Help => "help",
+        };
+        write!(f, "{level}")
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
 pub struct Diagnostic {
-    level: Level,
+    pub level: Level,
+    pub code: Option<ErrorCode>,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic not attached to any location of the source file.
+    pub fn new(level: Level, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            code: None,
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    /// Creates a diagnostic pointing to the given span of the source file.
+    pub fn spanned(level: Level, span: Span, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            code: None,
+            message: message.into(),
+            span: Some(span),
+        }
+    }
+
+    /// Attaches the given error code to the diagnostic.
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = Some(code);
+        self
+    }
+}
+
+/// The panic payload used when a `Level::Bug` diagnostic is emitted.
+///
+/// The ICE panic hook (see [`crate::ice`]) recognizes it to report the diagnostic message and span
+/// instead of the location of the panic in the interpreter sources.
+#[derive(Debug)]
+pub struct ExplicitBug {
+    pub message: String,
+    pub span: Option<Span>,
 }
 
 pub struct DiagContext {
-    diagnostics: Vec<Diagnostic>,
+    diagnostics: RefCell<Vec<Diagnostic>>,
     emitter: Box<dyn Emitter>,
+    source_map: Option<SourceMap>,
 }
 
 impl DiagContext {
     pub fn new(emitter: Box<dyn Emitter>) -> Self {
         Self {
-            diagnostics: Vec::new(),
+            diagnostics: RefCell::new(Vec::new()),
             emitter,
+            source_map: None,
+        }
+    }
+
+    /// Shows the spans of the diagnostics as locations in the given source file, instead of byte
+    /// offsets.
+    pub fn with_source_map(mut self, source_map: SourceMap) -> Self {
+        self.source_map = Some(source_map);
+        self
+    }
+
+    /// Returns the source file the spans of the diagnostics are in, if known.
+    pub fn source_map(&self) -> Option<&SourceMap> {
+        self.source_map.as_ref()
+    }
+
+    /// Emits the given diagnostic through the emitter and records it.
+    ///
+    /// # Panics
+    ///
+    /// Panics with an [`ExplicitBug`] payload if the diagnostic level is `Level::Bug`.
+    pub fn emit(&self, diagnostic: Diagnostic) {
+        if diagnostic.level == Level::Bug {
+            // The ICE report is printed by the panic hook, do not print it twice
+            self.bug(diagnostic.span, diagnostic.message);
         }
+        self.emitter
+            .emit_diagnostic(&diagnostic, self.source_map.as_ref());
+        self.diagnostics.borrow_mut().push(diagnostic);
+    }
+
+    /// Reports a bug in the compiler, aborting the current compilation with an ICE.
+    pub fn bug(&self, span: Option<Span>, message: impl Into<String>) -> ! {
+        let message = message.into();
+        std::panic::panic_any(ExplicitBug { message, span })
+    }
+
+    /// Returns true if at least one emitted diagnostic prevents compilation from finishing.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .borrow()
+            .iter()
+            .any(|diagnostic| diagnostic.level.is_error())
+    }
+
+    /// Returns a copy of all the diagnostics emitted so far.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.borrow().clone()
     }
 }
@@ -1,19 +1,106 @@
-pub trait Emitter {}
+use super::diagnostic::Diagnostic;
+use super::source_map::SourceMap;
 
-/// An emitter printing diagnostics to the standard output.
+pub trait Emitter {
+    /// Reports the given diagnostic to the user, showing its span in the source map if any.
+    fn emit_diagnostic(&self, diagnostic: &Diagnostic, source_map: Option<&SourceMap>);
+}
+
+/// An emitter printing diagnostics to the standard error.
 struct EmitterHumanReadable();
 
-impl Emitter for EmitterHumanReadable {}
+impl Emitter for EmitterHumanReadable {
+    fn emit_diagnostic(&self, diagnostic: &Diagnostic, source_map: Option<&SourceMap>) {
+        eprint!("{}", render(diagnostic, source_map));
+    }
+}
 
 pub fn new_emitter_human_readable() -> Box<dyn Emitter> {
-    return Box::new(EmitterHumanReadable());
+    Box::new(EmitterHumanReadable())
 }
 
 /// An emitter discarding all diagnostics emitted.
 struct EmitterNone();
 
-impl Emitter for EmitterNone {}
+impl Emitter for EmitterNone {
+    fn emit_diagnostic(&self, _diagnostic: &Diagnostic, _source_map: Option<&SourceMap>) {}
+}
 
 pub fn new_emitter_none() -> Box<dyn Emitter> {
-    return Box::new(EmitterNone());
+    Box::new(EmitterNone())
+}
+
+/// Formats the diagnostic for humans, like rustc does. With a source map, the location of the
+/// span is shown as `file:line:column` followed by its first line, underlined:
+///
+/// ```text
+/// error: attempt to divide by zero
+///  --> script.risl:2:5
+///   |
+/// 2 |     a / b
+///   |     ^^^^^
+/// ```
+///
+/// Without one, the span is shown as a range of byte offsets.
+pub fn render(diagnostic: &Diagnostic, source_map: Option<&SourceMap>) -> String {
+    let mut out = match diagnostic.code {
+        Some(code) => format!("{}[{code}]: {}\n", diagnostic.level, diagnostic.message),
+        None => format!("{}: {}\n", diagnostic.level, diagnostic.message),
+    };
+    let (span, source_map) = match (diagnostic.span, source_map) {
+        (Some(span), Some(source_map)) => (span, source_map),
+        (Some(span), None) => return out + &format!(" --> {}..{}\n", span.start, span.end),
+        (None, _) => return out,
+    };
+    let start = source_map.location(span.start);
+    let end = source_map.location(span.end);
+    let text = source_map.line(start.line);
+    let width = start.line.to_string().len();
+    let gutter = " ".repeat(width);
+    // Tabs are kept to align the underline with the text
+    let indent: String = text
+        .chars()
+        .take(start.column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let len = if end.line == start.line {
+        end.column.saturating_sub(start.column)
+    } else {
+        // Spans over several lines are underlined up to the end of their first line
+        text.chars().count().saturating_sub(start.column - 1)
+    };
+    out += &format!("{gutter}--> {}\n", source_map.describe(span));
+    out += &format!("{gutter} |\n");
+    out += &format!("{} | {text}\n", start.line);
+    out += &format!("{gutter} | {indent}{}\n", "^".repeat(len.max(1)));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::diagnostic::Level;
+    use crate::parser::lexer::Span;
+
+    #[test]
+    fn rendering() {
+        let source_map = SourceMap::new("test.risl", "fn f(a, b) {\n\ta / b\n}\n");
+        let diagnostic = Diagnostic::spanned(Level::Error, Span::new(14, 19), "division");
+        assert_eq!(
+            render(&diagnostic, Some(&source_map)),
+            "error: division\n --> test.risl:2:2\n  |\n2 | \ta / b\n  | \t^^^^^\n"
+        );
+        assert_eq!(render(&diagnostic, None), "error: division\n --> 14..19\n");
+        // Spans over several lines are underlined to the end of their first line
+        let diagnostic = Diagnostic::spanned(Level::Note, Span::new(11, 21), "block");
+        assert_eq!(
+            render(&diagnostic, Some(&source_map)),
+            "note: block\n --> test.risl:1:12\n  |\n1 | fn f(a, b) {\n  |            ^\n"
+        );
+        let diagnostic = Diagnostic::new(Level::Warning, "spanless");
+        assert_eq!(
+            render(&diagnostic, Some(&source_map)),
+            "warning: spanless\n"
+        );
+    }
 }
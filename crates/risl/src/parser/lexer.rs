@@ -3,12 +3,14 @@ mod span;
 mod token;
 
 pub use span::Span;
-pub use token::{FloatLiteral, IntegerBase, IntegerLiteral, Token, TokenStr};
+pub use token::{FloatLiteral, IntegerBase, IntegerLiteral, SpannedToken, Token, TokenStr};
 
 use super::context::ParseContext;
+use super::diagnostic::{Diagnostic, Level};
+use crate::error_codes::{self, ErrorCode};
 
 use cursor::Cursor;
-use span::SpanMerger;
+use span::{SpanMerger, SpanSubstr};
 
 /// The error type from the lexer raised for diagnostic purposes.
 #[derive(Eq, PartialEq, Debug)]
@@ -18,6 +20,34 @@ pub enum Error {
     InvalidDigitLiteral,
     EmptyExponentFloat,
     FloatLiteralUnsupportedBase,
+    UnterminatedString,
+    UnknownCharacterEscape,
+}
+
+impl Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::UnknownToken => error_codes::E0001,
+            Error::NoDigitLiteral => error_codes::E0002,
+            Error::InvalidDigitLiteral => error_codes::E0003,
+            Error::EmptyExponentFloat => error_codes::E0004,
+            Error::FloatLiteralUnsupportedBase => error_codes::E0005,
+            Error::UnterminatedString => error_codes::E0006,
+            Error::UnknownCharacterEscape => error_codes::E0007,
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self {
+            Error::UnknownToken => "unknown start of token",
+            Error::NoDigitLiteral => "no valid digits found for number",
+            Error::InvalidDigitLiteral => "invalid digit for the base of the literal",
+            Error::EmptyExponentFloat => "expected at least one digit in exponent",
+            Error::FloatLiteralUnsupportedBase => "float literals must be written in decimal",
+            Error::UnterminatedString => "unterminated string literal",
+            Error::UnknownCharacterEscape => "unknown character escape",
+        }
+    }
 }
 
 /// Iterates over the lexed tokens in the given source file.
@@ -25,10 +55,90 @@ pub fn lex<'ctx, 'src>(
     context: &'ctx ParseContext,
     source: &'src str,
 ) -> impl Iterator<Item = Token> + use<'ctx, 'src> {
-    let mut lexer = Lexer::new(&context, &source);
+    let mut lexer = Lexer::new(context, source);
     std::iter::from_fn(move || lexer.next_token())
 }
 
+/// Iterates over the lexed tokens in the given source file, along with their span.
+pub fn lex_spanned<'ctx, 'src>(
+    context: &'ctx ParseContext,
+    source: &'src str,
+) -> impl Iterator<Item = SpannedToken> + use<'ctx, 'src> {
+    let mut lexer = Lexer::new(context, source);
+    std::iter::from_fn(move || lexer.next_spanned_token())
+}
+
+/// Iterates over the lexed tokens of the given source file from the given byte offset, along with
+/// their span in the whole file.
+pub(crate) fn lex_spanned_from<'ctx, 'src>(
+    context: &'ctx ParseContext,
+    source: &'src str,
+    start: usize,
+) -> impl Iterator<Item = SpannedToken> + use<'ctx, 'src> {
+    let mut lexer = Lexer::new(context, source);
+    lexer.cursor = Cursor::new_at(source, start);
+    std::iter::from_fn(move || lexer.next_spanned_token())
+}
+
+/// Iterates over the lexed tokens in the given source file, along with their span, including the
+/// whitespaces and comments.
+///
+/// Contrary to [`lex_spanned`], unknown characters separated by whitespaces or comments are not
+/// grouped into a single error token.
+pub fn lex_spanned_with_trivia<'ctx, 'src>(
+    context: &'ctx ParseContext,
+    source: &'src str,
+) -> impl Iterator<Item = SpannedToken> + use<'ctx, 'src> {
+    let mut lexer = Lexer::new(context, source);
+    lexer.keep_trivia = true;
+    std::iter::from_fn(move || lexer.next_spanned_token())
+}
+
+/// Returns the value of a string literal content, with its escape sequences processed.
+///
+/// On error, returns the span of the invalid escape sequence, relative to the content start.
+pub fn unescape_string(content: &str) -> Result<String, Span> {
+    let mut value = String::with_capacity(content.len());
+    let mut chars = content.char_indices();
+    while let Some((start, c)) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some((_, 'n')) => '\n',
+            Some((_, 'r')) => '\r',
+            Some((_, 't')) => '\t',
+            Some((_, '0')) => '\0',
+            Some((_, '\\')) => '\\',
+            Some((_, '"')) => '"',
+            Some((_, '\'')) => '\'',
+            Some((_, 'u')) => {
+                let rest = &content[start + 2..];
+                let escape = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .map(|(digits, _)| digits);
+                let Some(c) = escape
+                    .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                    .and_then(char::from_u32)
+                else {
+                    return Err(Span::new(start, start + 2));
+                };
+                // Skip the braces and the digits
+                for _ in 0..escape.unwrap().len() + 2 {
+                    chars.next();
+                }
+                c
+            }
+            Some((end, c)) => return Err(Span::new(start, end + c.len_utf8())),
+            None => return Err(Span::new(start, start + 1)),
+        };
+        value.push(escaped);
+    }
+    Ok(value)
+}
+
 #[doc(hidden)]
 fn is_identifier_start(c: char) -> bool {
     unicode_ident::is_xid_start(c) || c == '_'
@@ -42,7 +152,7 @@ fn is_identifier_continuation(c: char) -> bool {
 #[doc(hidden)]
 fn is_digit_start(c: char) -> bool {
     // Start of number is always a character between 0 and 9
-    return c.is_ascii_digit();
+    c.is_ascii_digit()
 }
 
 // Only continuation variants exist to check digits for a specific base as the
@@ -50,22 +160,22 @@ fn is_digit_start(c: char) -> bool {
 
 #[doc(hidden)]
 fn is_digit_base10_continuation(c: char) -> bool {
-    return c.is_ascii_digit() || c == '_';
+    c.is_ascii_digit() || c == '_'
 }
 
 #[doc(hidden)]
 fn is_digit_base2_continuation(c: char) -> bool {
-    return ('0'..='1').contains(&c) || c == '_';
+    ('0'..='1').contains(&c) || c == '_'
 }
 
 #[doc(hidden)]
 fn is_digit_base8_continuation(c: char) -> bool {
-    return ('0'..='7').contains(&c) || c == '_';
+    ('0'..='7').contains(&c) || c == '_'
 }
 
 #[doc(hidden)]
 fn is_digit_base16_continuation(c: char) -> bool {
-    return c.is_ascii_hexdigit() || c == '_';
+    c.is_ascii_hexdigit() || c == '_'
 }
 
 #[doc(hidden)]
@@ -83,7 +193,9 @@ struct Lexer<'ctx, 'src> {
     context: &'ctx ParseContext,
     source: &'src str,
     cursor: Cursor<'src>,
-    pending_token: Option<Token>,
+    pending_token: Option<SpannedToken>,
+    /// Whether whitespaces and comments are returned instead of being skipped.
+    keep_trivia: bool,
 }
 
 impl<'ctx, 'src> Lexer<'ctx, 'src> {
@@ -94,6 +206,70 @@ impl<'ctx, 'src> Lexer<'ctx, 'src> {
             source,
             cursor: Cursor::new(source),
             pending_token: None,
+            keep_trivia: false,
+        }
+    }
+
+    /// Reports a lexing error through the diagnostic context.
+    fn report(&self, error: Error, span: Span) {
+        self.context
+            .diag_ctx()
+            .emit(Diagnostic::spanned(Level::Error, span, error.message()).with_code(error.code()));
+    }
+
+    /// Reports the errors found in the digits of an integer literal or an integer part of a float.
+    fn validate_digits(&self, base: IntegerBase, digits: Span) {
+        let is_valid_digit = match base {
+            IntegerBase::Bin => is_digit_base2_continuation,
+            IntegerBase::Oct => is_digit_base8_continuation,
+            IntegerBase::Dec => is_digit_base10_continuation,
+            IntegerBase::Hex => is_digit_base16_continuation,
+        };
+        if !self.source.substr(digits).chars().any(|c| c != '_') {
+            self.report(Error::NoDigitLiteral, digits);
+            return;
+        }
+        for (offset, c) in self.source.substr(digits).char_indices() {
+            if !is_valid_digit(c) {
+                let start = digits.start as usize + offset;
+                self.report(
+                    Error::InvalidDigitLiteral,
+                    Span::new(start, start + c.len_utf8()),
+                );
+            }
+        }
+    }
+
+    /// Reports the errors found in a literal token.
+    fn validate_literal(&self, token: &Token) {
+        match token {
+            Token::Integer(integer) => self.validate_digits(integer.base, integer.value),
+            Token::Float(float) => {
+                if float.base != IntegerBase::Dec {
+                    self.report(
+                        Error::FloatLiteralUnsupportedBase,
+                        Span::new(float.integer_part.start, float.suffix.start),
+                    );
+                    return;
+                }
+                let exponent = self.source.substr(float.exponent);
+                let has_exponent_marker = float.exponent.start > 0
+                    && matches!(
+                        self.source.as_bytes()[float.exponent.start as usize - 1],
+                        b'e' | b'E'
+                    );
+                if has_exponent_marker && !exponent.chars().any(|c| c.is_ascii_digit()) {
+                    self.report(Error::EmptyExponentFloat, float.exponent);
+                }
+            }
+            Token::String(content) => {
+                if let Err(escape) = unescape_string(self.source.substr(*content)) {
+                    let start = content.start + escape.start;
+                    let end = content.start + escape.end;
+                    self.report(Error::UnknownCharacterEscape, Span::new(start, end));
+                }
+            }
+            _ => (),
         }
     }
 
@@ -115,6 +291,18 @@ impl<'ctx, 'src> Lexer<'ctx, 'src> {
         Token::Identifier(identifier)
     }
 
+    /// Extracts the current loop label, the quote being already consumed.
+    fn tokenize_lifetime(&mut self) -> Token {
+        let start = self.cursor.consumed - 1;
+        match self.cursor.peek() {
+            Some(c) if is_identifier_start(c) => {
+                let end = self.take_while(is_identifier_continuation).end;
+                Token::Lifetime(Span::new(start, end))
+            }
+            _ => Token::Err(Span::new(start, self.cursor.consumed)),
+        }
+    }
+
     /// Extracts the base prefix for the current number and return it.
     fn extract_number_base(&mut self, first_digit: char) -> Option<IntegerBase> {
         if first_digit == '0' {
@@ -261,6 +449,41 @@ impl<'ctx, 'src> Lexer<'ctx, 'src> {
         }
     }
 
+    /// Advances the cursor until the closing quote of a string literal is encountered.
+    /// Returns the span of the string content, without the quotes.
+    fn tokenize_string(&mut self) -> Token {
+        let start = self.cursor.consumed;
+        loop {
+            match self.cursor.next() {
+                Some('"') => {
+                    return Token::String(Span::new(start, self.cursor.consumed - 1));
+                }
+                Some('\\') => {
+                    // Skip the escaped character, which can be a quote
+                    self.cursor.next();
+                }
+                Some(_) => (),
+                None => {
+                    self.report(
+                        Error::UnterminatedString,
+                        Span::new(start - 1, self.cursor.consumed),
+                    );
+                    return Token::String(Span::new(start, self.cursor.consumed));
+                }
+            }
+        }
+    }
+
+    /// Consumes the next character if it is the expected one.
+    fn eat(&mut self, expected: char) -> bool {
+        if self.cursor.peek() == Some(expected) {
+            self.cursor.next();
+            true
+        } else {
+            false
+        }
+    }
+
     /// Takes the current character and advance the cursor until a token is found.
     fn parse_token(&mut self, c: char) -> Token {
         match c {
@@ -277,11 +500,10 @@ impl<'ctx, 'src> Lexer<'ctx, 'src> {
             '[' => Token::LeftBracket,
             ']' => Token::RightBracket,
             ',' => Token::Comma,
-            '.' => Token::Dot,
-            '-' => Token::Minus,
-            '+' => Token::Plus,
-            ':' => Token::Colon,
             ';' => Token::Semicolon,
+            '?' => Token::Question,
+            '@' => Token::At,
+            '#' => Token::Pound,
             '/' => match self.cursor.peek() {
                 Some('/') => {
                     self.cursor.next();
@@ -294,72 +516,118 @@ impl<'ctx, 'src> Lexer<'ctx, 'src> {
                     let end = self.cursor.consumed - 2; // Remove the last */
                     Token::BlockComment(Span::new(start, end))
                 }
+                Some('=') => {
+                    self.cursor.next();
+                    Token::SlashEqual
+                }
                 _ => Token::Slash,
             },
             '\\' => Token::Backslash,
+            // One or two characters tokens
+            '-' if self.eat('>') => Token::Arrow,
+            '-' if self.eat('=') => Token::MinusEqual,
+            '-' => Token::Minus,
+            '+' if self.eat('=') => Token::PlusEqual,
+            '+' => Token::Plus,
+            '*' if self.eat('=') => Token::StarEqual,
             '*' => Token::Star,
+            '%' if self.eat('=') => Token::PercentEqual,
+            '%' => Token::Percent,
+            '^' if self.eat('=') => Token::CaretEqual,
+            '^' => Token::Caret,
+            '&' if self.eat('&') => Token::AmpersandAmpersand,
+            '&' if self.eat('=') => Token::AmpersandEqual,
             '&' => Token::Ampersand,
+            '|' if self.eat('|') => Token::PipePipe,
+            '|' if self.eat('=') => Token::PipeEqual,
             '|' => Token::Pipe,
-            // One or two characters tokens
-            '!' => match self.cursor.peek() {
-                Some('=') => {
-                    self.cursor.next();
-                    Token::NotEqual
-                }
-                _ => Token::Not,
-            },
-            '=' => match self.cursor.peek() {
-                Some('=') => {
-                    self.cursor.next();
-                    Token::EqualEqual
+            ':' if self.eat(':') => Token::ColonColon,
+            ':' => Token::Colon,
+            '!' if self.eat('=') => Token::NotEqual,
+            '!' => Token::Not,
+            '=' if self.eat('=') => Token::EqualEqual,
+            '=' if self.eat('>') => Token::FatArrow,
+            '=' => Token::Equal,
+            // One, two or three characters tokens
+            '.' if self.eat('.') => {
+                if self.eat('=') {
+                    Token::DotDotEqual
+                } else {
+                    Token::DotDot
                 }
-                _ => Token::Equal,
-            },
-            '>' => match self.cursor.peek() {
-                Some('=') => {
-                    self.cursor.next();
-                    Token::GreaterEqual
+            }
+            '.' => Token::Dot,
+            '>' if self.eat('>') => {
+                if self.eat('=') {
+                    Token::GreaterGreaterEqual
+                } else {
+                    Token::GreaterGreater
                 }
-                _ => Token::Greater,
-            },
-            '<' => match self.cursor.peek() {
-                Some('=') => {
-                    self.cursor.next();
-                    Token::LessEqual
+            }
+            '>' if self.eat('=') => Token::GreaterEqual,
+            '>' => Token::Greater,
+            '<' if self.eat('<') => {
+                if self.eat('=') {
+                    Token::LessLessEqual
+                } else {
+                    Token::LessLess
                 }
-                _ => Token::Less,
-            },
+            }
+            '<' if self.eat('=') => Token::LessEqual,
+            '<' => Token::Less,
             // Literals
+            '"' => self.tokenize_string(),
             c if is_digit_start(c) => self.tokenize_number(c),
             c if is_identifier_start(c) => self.tokenize_identifier(c),
+            '\'' => self.tokenize_lifetime(),
             // Unknown characters
-            _ => Token::Err(Span::new(self.cursor.consumed - 1, self.cursor.consumed)),
+            _ => Token::Err(Span::new(
+                self.cursor.consumed - c.len_utf8(),
+                self.cursor.consumed,
+            )),
         }
     }
 
     /// Returns the next token in the source file.
     /// Returns None if the source file end is reached, iteration is not resumed.
     fn next_token(&mut self) -> Option<Token> {
-        if let Some(_) = self.pending_token {
+        self.next_spanned_token().map(|spanned| spanned.token)
+    }
+
+    /// Returns the next token in the source file along with its span.
+    /// Returns None if the source file end is reached, iteration is not resumed.
+    fn next_spanned_token(&mut self) -> Option<SpannedToken> {
+        if self.pending_token.is_some() {
             return self.pending_token.take();
         }
         let mut invalid_token_span: Option<Span> = None;
         loop {
             match self.cursor.next() {
                 Some(c) => {
+                    let start = self.cursor.consumed - c.len_utf8();
                     let token = match self.parse_token(c) {
-                        token if token.is_skippable() => continue,
+                        token if token.is_skippable() && !self.keep_trivia => continue,
                         Token::Err(span) => {
                             // Group consecutive unknown characters
                             invalid_token_span.merge(span);
                             continue;
                         }
                         token => {
+                            let span = Span::new(start, self.cursor.consumed);
+                            crate::ice::set_current_span(span);
+                            if let Some(span) = invalid_token_span {
+                                self.report(Error::UnknownToken, span);
+                            }
+                            self.validate_literal(&token);
+                            let token = SpannedToken { token, span };
                             if let Some(span) = invalid_token_span {
                                 // Invalid token extracted at previous iteration
                                 // Return it and save current valid token for the next iteration
                                 self.pending_token = Some(token);
-                                Token::Err(span)
+                                SpannedToken {
+                                    token: Token::Err(span),
+                                    span,
+                                }
                             } else {
                                 token
                             }
@@ -370,7 +638,13 @@ impl<'ctx, 'src> Lexer<'ctx, 'src> {
                 None => {
                     // If EOF is reached and an invalid token is pending then return it now
                     // If no invalid token is pending then None is returned immediately
-                    return invalid_token_span.and_then(|span| Some(Token::Err(span)));
+                    if let Some(span) = invalid_token_span {
+                        self.report(Error::UnknownToken, span);
+                    }
+                    return invalid_token_span.map(|span| SpannedToken {
+                        token: Token::Err(span),
+                        span,
+                    });
                 }
             }
         }
@@ -2,6 +2,7 @@
 #[derive(Debug)]
 pub struct Cursor<'src> {
     chars: std::str::Chars<'src>,
+    /// The number of bytes consumed so far.
     pub consumed: usize,
 }
 
@@ -14,9 +15,12 @@ impl<'src> Cursor<'src> {
         }
     }
 
-    /// Returns the remaining string.
-    pub fn as_str(&self) -> &'src str {
-        self.chars.as_str()
+    /// Creates a new cursor for the given source string, starting at the given byte offset.
+    pub fn new_at(source: &'src str, start: usize) -> Self {
+        Self {
+            chars: source[start..].chars(),
+            consumed: start,
+        }
     }
 
     /// Peeks the next next character, if any.
@@ -33,34 +37,19 @@ impl<'src> Cursor<'src> {
     /// Does not move the cursor if the next character does not exist.
     pub fn next(&mut self) -> Option<char> {
         let next = self.chars.next();
-        if let Some(_) = next {
-            self.consumed += 1;
+        if let Some(c) = next {
+            self.consumed += c.len_utf8();
         }
         next
     }
 
-    /// Moves to the n-th next character.
-    /// Does not move the cursor if the n-th character does not exist.
-    pub fn next_nth(&mut self, n: usize) -> Option<char> {
-        let nth = self.chars.nth(n);
-        if let Some(_) = nth {
-            self.consumed += n;
-        }
-        nth
-    }
-
     /// Moves to the next character while the predicate returns true for that character.
     pub fn advance_while(&mut self, mut predicate: impl FnMut(char) -> bool) {
-        loop {
-            match self.peek() {
-                Some(c) => {
-                    if predicate(c) {
-                        self.next();
-                    } else {
-                        break;
-                    }
-                }
-                None => break,
+        while let Some(c) = self.peek() {
+            if predicate(c) {
+                self.next();
+            } else {
+                break;
             }
         }
     }
@@ -51,6 +51,19 @@ fn tokenize_identifier() {
     assert_eq!(result, Token::Identifier(Span::new(0, 5)));
 }
 
+#[test]
+fn tokenize_lifetime() {
+    let context = stubbed_parse_context();
+    let mut lexer = Lexer::new(&context, "'outer: '");
+    lexer.cursor.next();
+    assert_eq!(lexer.tokenize_lifetime(), Token::Lifetime(Span::new(0, 6)));
+    lexer.cursor.next();
+    lexer.cursor.next();
+    lexer.cursor.next();
+    // A quote not followed by an identifier is unknown
+    assert_eq!(lexer.tokenize_lifetime(), Token::Err(Span::new(8, 9)));
+}
+
 #[test]
 fn tokenize_number_decimal() {
     let context = stubbed_parse_context();
@@ -45,27 +45,52 @@ pub enum Token {
     Slash,
     Backslash,
     Star,
+    Percent,
+    Caret,
     Ampersand,
     Pipe,
+    Question,
+    At,
+    Pound,
     // One or two character tokens
+    Arrow,
+    FatArrow,
+    ColonColon,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    CaretEqual,
+    AmpersandEqual,
+    AmpersandAmpersand,
+    PipeEqual,
+    PipePipe,
     Not,
     NotEqual,
     Equal,
     EqualEqual,
     Greater,
     GreaterEqual,
+    GreaterGreater,
+    GreaterGreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    LessLessEqual,
     Dot,
     DotDot,
     DotDotEqual,
     // Literals
     Identifier(Span),
+    /// A loop label like `'outer`, its span including the quote.
+    Lifetime(Span),
     String(Span),
     Integer(IntegerLiteral),
     Float(FloatLiteral),
     // Keywords
     And,
+    As,
     Break,
     Const,
     Continue,
@@ -75,9 +100,12 @@ pub enum Token {
     Fn,
     For,
     If,
+    Impl,
     In,
     Let,
+    Loop,
     Match,
+    Move,
     Mut,
     Nil,
     Or,
@@ -88,6 +116,7 @@ pub enum Token {
     Struct,
     Super,
     This,
+    Trait,
     True,
     While,
     // Others
@@ -95,6 +124,8 @@ pub enum Token {
     LineComment(Span),
     BlockComment(Span),
     Err(Span),
+    /// The end of the source file, never produced by the lexer but appended by the parser.
+    Eof,
 }
 
 impl Token {
@@ -104,6 +135,91 @@ impl Token {
             Token::Whitespace | Token::LineComment(_) | Token::BlockComment(_)
         )
     }
+
+    /// Returns the keyword token corresponding to the given identifier, if any.
+    ///
+    /// The lexer produces identifiers for keywords, which are converted to keyword tokens by the
+    /// parser using this function.
+    pub fn keyword(identifier: &str) -> Option<Token> {
+        let keyword = match identifier {
+            "and" => Token::And,
+            "as" => Token::As,
+            "break" => Token::Break,
+            "const" => Token::Const,
+            "continue" => Token::Continue,
+            "else" => Token::Else,
+            "enum" => Token::Enum,
+            "false" => Token::False,
+            "fn" => Token::Fn,
+            "for" => Token::For,
+            "if" => Token::If,
+            "impl" => Token::Impl,
+            "in" => Token::In,
+            "let" => Token::Let,
+            "loop" => Token::Loop,
+            "match" => Token::Match,
+            "move" => Token::Move,
+            "mut" => Token::Mut,
+            "nil" => Token::Nil,
+            "or" => Token::Or,
+            "pub" => Token::Pub,
+            "return" => Token::Return,
+            "self" => Token::SelfValue,
+            "Self" => Token::SelfType,
+            "struct" => Token::Struct,
+            "super" => Token::Super,
+            "this" => Token::This,
+            "trait" => Token::Trait,
+            "true" => Token::True,
+            "while" => Token::While,
+            _ => return None,
+        };
+        Some(keyword)
+    }
+
+    /// Returns true if the token is a keyword.
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            Token::And
+                | Token::As
+                | Token::Break
+                | Token::Const
+                | Token::Continue
+                | Token::Else
+                | Token::Enum
+                | Token::False
+                | Token::Fn
+                | Token::For
+                | Token::If
+                | Token::Impl
+                | Token::In
+                | Token::Let
+                | Token::Loop
+                | Token::Match
+                | Token::Move
+                | Token::Mut
+                | Token::Nil
+                | Token::Or
+                | Token::Pub
+                | Token::Return
+                | Token::SelfValue
+                | Token::SelfType
+                | Token::Struct
+                | Token::Super
+                | Token::This
+                | Token::Trait
+                | Token::True
+                | Token::While
+        )
+    }
+}
+
+/// A token along with its location in the source file.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
 }
 
 pub struct TokenStr<'src> {
@@ -134,20 +250,43 @@ impl<'src> std::fmt::Display for TokenStr<'src> {
             Token::Slash => "/",
             Token::Backslash => "\\",
             Token::Star => "*",
+            Token::Percent => "%",
+            Token::Caret => "^",
             Token::Ampersand => "&",
             Token::Pipe => "|",
+            Token::Question => "?",
+            Token::At => "@",
+            Token::Pound => "#",
+            Token::Arrow => "->",
+            Token::FatArrow => "=>",
+            Token::ColonColon => "::",
+            Token::PlusEqual => "+=",
+            Token::MinusEqual => "-=",
+            Token::StarEqual => "*=",
+            Token::SlashEqual => "/=",
+            Token::PercentEqual => "%=",
+            Token::CaretEqual => "^=",
+            Token::AmpersandEqual => "&=",
+            Token::AmpersandAmpersand => "&&",
+            Token::PipeEqual => "|=",
+            Token::PipePipe => "||",
             Token::Not => "!",
             Token::NotEqual => "!=",
             Token::Equal => "=",
             Token::EqualEqual => "==",
             Token::Greater => ">",
             Token::GreaterEqual => ">=",
+            Token::GreaterGreater => ">>",
+            Token::GreaterGreaterEqual => ">>=",
             Token::Less => "<",
             Token::LessEqual => "<=",
+            Token::LessLess => "<<",
+            Token::LessLessEqual => "<<=",
             Token::Dot => ".",
             Token::DotDot => "..",
             Token::DotDotEqual => "..=",
             Token::Identifier(span) => self.source.substr(span),
+            Token::Lifetime(span) => self.source.substr(span),
             Token::String(span) => self.source.substr(span),
             Token::Integer(integer_literal) => {
                 return write!(
@@ -170,6 +309,7 @@ impl<'src> std::fmt::Display for TokenStr<'src> {
                 );
             }
             Token::And => "and",
+            Token::As => "as",
             Token::Break => "break",
             Token::Const => "const",
             Token::Continue => "continue",
@@ -179,11 +319,14 @@ impl<'src> std::fmt::Display for TokenStr<'src> {
             Token::Fn => "fn",
             Token::For => "for",
             Token::If => "if",
+            Token::Impl => "impl",
             Token::In => "in",
             Token::Let => "let",
+            Token::Loop => "loop",
             Token::Match => "match",
+            Token::Move => "move",
             Token::Mut => "mut",
-            Token::Nil => "Nil",
+            Token::Nil => "nil",
             Token::Or => "or",
             Token::Pub => "pub",
             Token::Return => "return",
@@ -192,12 +335,14 @@ impl<'src> std::fmt::Display for TokenStr<'src> {
             Token::Struct => "struct",
             Token::Super => "super",
             Token::This => "this",
+            Token::Trait => "trait",
             Token::True => "true",
             Token::While => "while",
             Token::Whitespace => " ",
             Token::LineComment(span) => self.source.substr(span),
             Token::BlockComment(span) => self.source.substr(span),
             Token::Err(span) => self.source.substr(span),
+            Token::Eof => "<eof>",
         };
         write!(f, "{token}")
     }
@@ -1,5 +1,14 @@
+pub mod ast;
 // FIXME temporarily public to be used from main
 pub mod context;
 pub mod diagnostic;
 pub mod emitter;
 pub mod lexer;
+pub mod mut_visit;
+pub mod parse;
+pub mod printer;
+pub mod source_map;
+pub mod syntax;
+pub mod visit;
+
+pub use parse::parse;
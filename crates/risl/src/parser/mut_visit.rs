@@ -0,0 +1,426 @@
+//! A mutable walk over the AST, for the passes rewriting it in place like desugaring.
+//!
+//! This is the mutable counterpart of [`super::visit`], working the same way: the default
+//! implementation of each method of [`VisitorMut`] calls the corresponding `walk_*` function
+//! visiting the children of the node. A node can be replaced by assigning to it, typically before
+//! or after walking it.
+
+use super::ast::*;
+
+pub trait VisitorMut: Sized {
+    fn visit_program(&mut self, program: &mut Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_stmt(&mut self, stmt: &mut Stmt) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_local(&mut self, local: &mut Local) {
+        walk_local(self, local)
+    }
+
+    fn visit_item(&mut self, item: &mut Item) {
+        walk_item(self, item)
+    }
+
+    fn visit_fn_decl(&mut self, decl: &mut FnDecl) {
+        walk_fn_decl(self, decl)
+    }
+
+    fn visit_self_param(&mut self, _param: &mut SelfParam) {}
+
+    fn visit_param(&mut self, param: &mut Param) {
+        walk_param(self, param)
+    }
+
+    fn visit_variant(&mut self, variant: &mut Variant) {
+        walk_variant(self, variant)
+    }
+
+    fn visit_variant_fields(&mut self, fields: &mut VariantFields) {
+        walk_variant_fields(self, fields)
+    }
+
+    fn visit_field_decl(&mut self, field: &mut FieldDecl) {
+        walk_field_decl(self, field)
+    }
+
+    fn visit_block(&mut self, block: &mut Block) {
+        walk_block(self, block)
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_expr_field(&mut self, field: &mut ExprField) {
+        walk_expr_field(self, field)
+    }
+
+    fn visit_closure(&mut self, closure: &mut Closure) {
+        walk_closure(self, closure)
+    }
+
+    fn visit_arm(&mut self, arm: &mut Arm) {
+        walk_arm(self, arm)
+    }
+
+    fn visit_lit(&mut self, _lit: &mut Lit) {}
+
+    fn visit_pat(&mut self, pat: &mut Pattern) {
+        walk_pat(self, pat)
+    }
+
+    fn visit_field_pat(&mut self, field: &mut FieldPat) {
+        walk_field_pat(self, field)
+    }
+
+    fn visit_ty(&mut self, ty: &mut Type) {
+        walk_ty(self, ty)
+    }
+
+    fn visit_path(&mut self, path: &mut Path) {
+        walk_path(self, path)
+    }
+
+    fn visit_ident(&mut self, _ident: &mut Ident) {}
+}
+
+pub fn walk_program<V: VisitorMut>(visitor: &mut V, program: &mut Program) {
+    for stmt in &mut program.stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<V: VisitorMut>(visitor: &mut V, stmt: &mut Stmt) {
+    match &mut stmt.kind {
+        StmtKind::Let(local) => visitor.visit_local(local),
+        StmtKind::Item(item) => visitor.visit_item(item),
+        StmtKind::Expr(expr) | StmtKind::Semi(expr) => visitor.visit_expr(expr),
+        StmtKind::Empty | StmtKind::Err => (),
+    }
+}
+
+pub fn walk_local<V: VisitorMut>(visitor: &mut V, local: &mut Local) {
+    visitor.visit_pat(&mut local.pat);
+    if let Some(ty) = &mut local.ty {
+        visitor.visit_ty(ty);
+    }
+    if let Some(init) = &mut local.init {
+        visitor.visit_expr(init);
+    }
+}
+
+pub fn walk_item<V: VisitorMut>(visitor: &mut V, item: &mut Item) {
+    match &mut item.kind {
+        ItemKind::Fn(decl) => visitor.visit_fn_decl(decl),
+        ItemKind::Struct(decl) => {
+            visitor.visit_ident(&mut decl.name);
+            visitor.visit_variant_fields(&mut decl.fields);
+        }
+        ItemKind::Enum(decl) => {
+            visitor.visit_ident(&mut decl.name);
+            for variant in &mut decl.variants {
+                visitor.visit_variant(variant);
+            }
+        }
+        ItemKind::Impl(decl) => {
+            if let Some(trait_ref) = &mut decl.trait_ref {
+                visitor.visit_path(trait_ref);
+            }
+            visitor.visit_ty(&mut decl.self_ty);
+            for item in &mut decl.items {
+                visitor.visit_item(item);
+            }
+        }
+        ItemKind::Trait(decl) => {
+            visitor.visit_ident(&mut decl.name);
+            for item in &mut decl.items {
+                visitor.visit_item(item);
+            }
+        }
+        ItemKind::Const(decl) => {
+            visitor.visit_ident(&mut decl.name);
+            visitor.visit_ty(&mut decl.ty);
+            visitor.visit_expr(&mut decl.expr);
+        }
+    }
+}
+
+pub fn walk_fn_decl<V: VisitorMut>(visitor: &mut V, decl: &mut FnDecl) {
+    visitor.visit_ident(&mut decl.name);
+    if let Some(self_param) = &mut decl.self_param {
+        visitor.visit_self_param(self_param);
+    }
+    for param in &mut decl.params {
+        visitor.visit_param(param);
+    }
+    if let Some(ret) = &mut decl.ret {
+        visitor.visit_ty(ret);
+    }
+    if let Some(body) = &mut decl.body {
+        visitor.visit_block(body);
+    }
+}
+
+pub fn walk_param<V: VisitorMut>(visitor: &mut V, param: &mut Param) {
+    visitor.visit_pat(&mut param.pat);
+    if let Some(ty) = &mut param.ty {
+        visitor.visit_ty(ty);
+    }
+}
+
+pub fn walk_variant<V: VisitorMut>(visitor: &mut V, variant: &mut Variant) {
+    visitor.visit_ident(&mut variant.name);
+    visitor.visit_variant_fields(&mut variant.fields);
+}
+
+pub fn walk_variant_fields<V: VisitorMut>(visitor: &mut V, fields: &mut VariantFields) {
+    for field in fields.fields_mut() {
+        visitor.visit_field_decl(field);
+    }
+}
+
+pub fn walk_field_decl<V: VisitorMut>(visitor: &mut V, field: &mut FieldDecl) {
+    visitor.visit_ident(&mut field.name);
+    visitor.visit_ty(&mut field.ty);
+}
+
+pub fn walk_block<V: VisitorMut>(visitor: &mut V, block: &mut Block) {
+    for stmt in &mut block.stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_expr<V: VisitorMut>(visitor: &mut V, expr: &mut Expr) {
+    match &mut expr.kind {
+        ExprKind::Lit(lit) => visitor.visit_lit(lit),
+        ExprKind::Path(path) => visitor.visit_path(path),
+        ExprKind::Unary(_, operand) | ExprKind::AddrOf(_, operand) => visitor.visit_expr(operand),
+        ExprKind::Binary(_, lhs, rhs)
+        | ExprKind::Assign(lhs, rhs)
+        | ExprKind::AssignOp(_, lhs, rhs)
+        | ExprKind::Index(lhs, rhs) => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+        ExprKind::Cast(expr, ty) => {
+            visitor.visit_expr(expr);
+            visitor.visit_ty(ty);
+        }
+        ExprKind::Call(callee, args) => {
+            visitor.visit_expr(callee);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        ExprKind::MethodCall(receiver, name, args) => {
+            visitor.visit_expr(receiver);
+            visitor.visit_ident(name);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        ExprKind::Field(receiver, name) => {
+            visitor.visit_expr(receiver);
+            visitor.visit_ident(name);
+        }
+        ExprKind::Tuple(exprs) | ExprKind::Array(exprs) => {
+            for expr in exprs {
+                visitor.visit_expr(expr);
+            }
+        }
+        ExprKind::Struct(struct_expr) => {
+            visitor.visit_path(&mut struct_expr.path);
+            for field in &mut struct_expr.fields {
+                visitor.visit_expr_field(field);
+            }
+            if let Some(rest) = &mut struct_expr.rest {
+                visitor.visit_expr(rest);
+            }
+        }
+        ExprKind::Paren(expr) | ExprKind::Try(expr) => visitor.visit_expr(expr),
+        ExprKind::Block(block) => visitor.visit_block(block),
+        ExprKind::If(cond, then, otherwise) => {
+            visitor.visit_expr(cond);
+            visitor.visit_block(then);
+            if let Some(otherwise) = otherwise {
+                visitor.visit_expr(otherwise);
+            }
+        }
+        ExprKind::While(cond, body, _) => {
+            visitor.visit_expr(cond);
+            visitor.visit_block(body);
+        }
+        ExprKind::For(pat, iterable, body, _) => {
+            visitor.visit_pat(pat);
+            visitor.visit_expr(iterable);
+            visitor.visit_block(body);
+        }
+        ExprKind::Loop(body, _) => visitor.visit_block(body),
+        ExprKind::Match(scrutinee, arms) => {
+            visitor.visit_expr(scrutinee);
+            for arm in arms {
+                visitor.visit_arm(arm);
+            }
+        }
+        ExprKind::Range(start, end, _) => {
+            if let Some(start) = start {
+                visitor.visit_expr(start);
+            }
+            if let Some(end) = end {
+                visitor.visit_expr(end);
+            }
+        }
+        ExprKind::Return(value) | ExprKind::Break(_, value) => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        ExprKind::Closure(closure) => visitor.visit_closure(closure),
+        ExprKind::Continue(_) | ExprKind::Err => (),
+    }
+}
+
+pub fn walk_closure<V: VisitorMut>(visitor: &mut V, closure: &mut Closure) {
+    for param in &mut closure.params {
+        visitor.visit_param(param);
+    }
+    if let Some(ret) = &mut closure.ret {
+        visitor.visit_ty(ret);
+    }
+    visitor.visit_expr(&mut closure.body);
+}
+
+pub fn walk_expr_field<V: VisitorMut>(visitor: &mut V, field: &mut ExprField) {
+    visitor.visit_ident(&mut field.name);
+    visitor.visit_expr(&mut field.expr);
+}
+
+pub fn walk_arm<V: VisitorMut>(visitor: &mut V, arm: &mut Arm) {
+    visitor.visit_pat(&mut arm.pat);
+    if let Some(guard) = &mut arm.guard {
+        visitor.visit_expr(guard);
+    }
+    visitor.visit_expr(&mut arm.body);
+}
+
+pub fn walk_pat<V: VisitorMut>(visitor: &mut V, pat: &mut Pattern) {
+    match &mut pat.kind {
+        PatKind::Ident(_, name, subpattern) => {
+            visitor.visit_ident(name);
+            if let Some(subpattern) = subpattern {
+                visitor.visit_pat(subpattern);
+            }
+        }
+        PatKind::Lit(expr) => visitor.visit_expr(expr),
+        PatKind::Range(start, end, _) => {
+            if let Some(start) = start {
+                visitor.visit_expr(start);
+            }
+            if let Some(end) = end {
+                visitor.visit_expr(end);
+            }
+        }
+        PatKind::Tuple(pats) | PatKind::Slice(pats) | PatKind::Or(pats) => {
+            for pat in pats {
+                visitor.visit_pat(pat);
+            }
+        }
+        PatKind::Path(path) => visitor.visit_path(path),
+        PatKind::TupleStruct(path, pats) => {
+            visitor.visit_path(path);
+            for pat in pats {
+                visitor.visit_pat(pat);
+            }
+        }
+        PatKind::Struct(path, fields, _) => {
+            visitor.visit_path(path);
+            for field in fields {
+                visitor.visit_field_pat(field);
+            }
+        }
+        PatKind::Wild | PatKind::Rest | PatKind::Err => (),
+    }
+}
+
+pub fn walk_field_pat<V: VisitorMut>(visitor: &mut V, field: &mut FieldPat) {
+    visitor.visit_ident(&mut field.name);
+    visitor.visit_pat(&mut field.pat);
+}
+
+pub fn walk_ty<V: VisitorMut>(visitor: &mut V, ty: &mut Type) {
+    match &mut ty.kind {
+        TypeKind::Path(path, args) => {
+            visitor.visit_path(path);
+            for arg in args {
+                visitor.visit_ty(arg);
+            }
+        }
+        TypeKind::Tuple(types) => {
+            for ty in types {
+                visitor.visit_ty(ty);
+            }
+        }
+        TypeKind::Array(elem) | TypeKind::Ref(_, elem) => visitor.visit_ty(elem),
+        TypeKind::Fn(params, ret) => {
+            for param in params {
+                visitor.visit_ty(param);
+            }
+            if let Some(ret) = ret {
+                visitor.visit_ty(ret);
+            }
+        }
+        TypeKind::ImplTrait(bounds) => {
+            for bound in bounds {
+                visitor.visit_path(bound);
+            }
+        }
+        TypeKind::Infer | TypeKind::Err => (),
+    }
+}
+
+pub fn walk_path<V: VisitorMut>(visitor: &mut V, path: &mut Path) {
+    for segment in &mut path.segments {
+        visitor.visit_ident(segment);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::context::ParseContext;
+    use crate::parser::diagnostic::DiagContext;
+    use crate::parser::emitter::new_emitter_none;
+    use crate::parser::printer::{self, Format};
+
+    /// Removes the parentheses and renames the variable `a` to `b`.
+    struct Rewriter;
+
+    impl VisitorMut for Rewriter {
+        fn visit_expr(&mut self, expr: &mut Expr) {
+            walk_expr(self, expr);
+            if let ExprKind::Paren(inner) = &expr.kind {
+                *expr = (**inner).clone();
+            }
+        }
+
+        fn visit_ident(&mut self, ident: &mut Ident) {
+            if ident.name == "a" {
+                ident.name = String::from("b");
+            }
+        }
+    }
+
+    #[test]
+    fn rewrite_in_place() {
+        let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+        let mut program = crate::parser::parse(&context, "let a = ((a + 1)) * (2);");
+        Rewriter.visit_program(&mut program);
+        assert_eq!(
+            printer::print(&program, Format::Sexp),
+            "(program\n  (let b (= (* (+ b 1) 2))))\n"
+        );
+    }
+}
@@ -0,0 +1,1867 @@
+//! The recursive-descent parser of the Risl language.
+//!
+//! Expressions are parsed by precedence climbing, with the operator precedences and associativity
+//! of Rust, from the lowest to the highest:
+//!
+//! | Operators                        | Associativity        |
+//! |----------------------------------|----------------------|
+//! | `=` `+=` `-=` `*=` `/=` ...      | right to left        |
+//! | `..` `..=`                       | require parentheses  |
+//! | `\|\|` `or`                      | left to right        |
+//! | `&&` `and`                       | left to right        |
+//! | `==` `!=` `<` `>` `<=` `>=`      | require parentheses  |
+//! | `\|`                             | left to right        |
+//! | `^`                              | left to right        |
+//! | `&`                              | left to right        |
+//! | `<<` `>>`                        | left to right        |
+//! | `+` `-`                          | left to right        |
+//! | `*` `/` `%`                      | left to right        |
+//! | `as`                             | left to right        |
+//! | unary `-` `!` `*` `&` `&mut`     |                      |
+//! | calls, method calls, fields, indexing |                 |
+//!
+//! The parser recovers from syntax errors to report all of them at once. When a statement cannot
+//! be parsed, the tokens are skipped up to a synchronisation point (a `;`, a `}` closing the
+//! enclosing block, or a keyword starting a statement like `fn`, `struct`, `enum` or `let`) and an
+//! error statement is produced. Missing expressions, patterns and types followed by a delimiter are
+//! replaced by error nodes without abandoning the enclosing statement.
+
+use super::ast::*;
+use super::context::ParseContext;
+use super::diagnostic::{Diagnostic, Level};
+use super::lexer::{self, IntegerBase, Span, SpannedToken, Token, TokenStr};
+use crate::error_codes::{self, ErrorCode};
+
+/// Parses the given source file into a program.
+///
+/// Syntax errors are reported through the diagnostic context of the parse context.
+pub fn parse(context: &ParseContext, source: &str) -> Program {
+    let mut parser = Parser::new(context, source);
+    parser.parse_program()
+}
+
+/// Parses the end of the given source file from the given byte offset into a program, whose node
+/// ids start from the given one, like an input of an interactive session following the previous
+/// ones. Returns the program, whose spans are offsets in the whole source file, and the next
+/// unused node id.
+pub fn parse_input(
+    context: &ParseContext,
+    source: &str,
+    start: usize,
+    first_id: NodeId,
+) -> (Program, NodeId) {
+    let mut parser = Parser::new_at(context, source, start);
+    parser.next_id = first_id;
+    let mut program = parser.parse_program();
+    program.span = Span::new(start, source.len());
+    (program, parser.next_id)
+}
+
+/// Parses the given source file into a program, also returning the tokens of the source file.
+///
+/// The returned tokens have their keywords identified but do not include the trivia nor the final
+/// `Token::Eof`. They are returned as lexed, before the parser splits some of them.
+pub(crate) fn parse_with_tokens(
+    context: &ParseContext,
+    source: &str,
+) -> (Program, Vec<SpannedToken>) {
+    let mut parser = Parser::new(context, source);
+    let tokens = parser.tokens[..parser.tokens.len() - 1].to_vec();
+    (parser.parse_program(), tokens)
+}
+
+/// Parses the given source as a single block, also returning its tokens like
+/// [`parse_with_tokens`].
+///
+/// Returns `None` if the source is not exactly one block, that is if it does not start with a `{`
+/// whose matching `}` is its last token.
+pub(crate) fn parse_block_with_tokens(
+    context: &ParseContext,
+    source: &str,
+) -> Option<(Block, Vec<SpannedToken>)> {
+    let mut parser = Parser::new(context, source);
+    let tokens = parser.tokens[..parser.tokens.len() - 1].to_vec();
+    if tokens.first()?.token != Token::LeftBrace {
+        return None;
+    }
+    let mut depth = 0usize;
+    for (index, spanned) in tokens.iter().enumerate() {
+        match spanned.token {
+            Token::LeftBrace => depth += 1,
+            Token::RightBrace => {
+                depth -= 1;
+                if depth == 0 && index != tokens.len() - 1 {
+                    return None;
+                }
+            }
+            _ => (),
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+    let block = parser.parse_block().ok()?;
+    Some((block, tokens))
+}
+
+/// The maximum nesting depth of the expressions, blocks, patterns and types of a program. Deeper
+/// nesting is reported as an error instead of exhausting the stack of the parser and of the passes
+/// walking the syntax tree.
+pub const MAX_NESTING_DEPTH: usize = 256;
+
+/// The error returned by the parsing functions. The corresponding diagnostic is already emitted.
+#[derive(Debug)]
+struct ParseError;
+
+type PResult<T> = Result<T, ParseError>;
+
+/// Restrictions on the expressions being parsed, depending on their context.
+#[derive(Clone, Copy, Default)]
+struct Restrictions {
+    /// Struct literals are not allowed, as in `if` conditions where `{` starts the block.
+    no_struct_literal: bool,
+}
+
+/// The precedence of the binary operators, higher binding tighter.
+mod precedence {
+    pub const OR: u8 = 1;
+    pub const AND: u8 = 2;
+    pub const COMPARISON: u8 = 3;
+    pub const BIT_OR: u8 = 4;
+    pub const BIT_XOR: u8 = 5;
+    pub const BIT_AND: u8 = 6;
+    pub const SHIFT: u8 = 7;
+    pub const SUM: u8 = 8;
+    pub const PRODUCT: u8 = 9;
+    pub const CAST: u8 = 10;
+}
+
+/// Returns the binary operator and its precedence for the given token, if any.
+fn binary_operator(token: Token) -> Option<(BinOp, u8)> {
+    let operator = match token {
+        Token::PipePipe | Token::Or => (BinOp::Or, precedence::OR),
+        Token::AmpersandAmpersand | Token::And => (BinOp::And, precedence::AND),
+        Token::EqualEqual => (BinOp::Eq, precedence::COMPARISON),
+        Token::NotEqual => (BinOp::Ne, precedence::COMPARISON),
+        Token::Less => (BinOp::Lt, precedence::COMPARISON),
+        Token::LessEqual => (BinOp::Le, precedence::COMPARISON),
+        Token::Greater => (BinOp::Gt, precedence::COMPARISON),
+        Token::GreaterEqual => (BinOp::Ge, precedence::COMPARISON),
+        Token::Pipe => (BinOp::BitOr, precedence::BIT_OR),
+        Token::Caret => (BinOp::BitXor, precedence::BIT_XOR),
+        Token::Ampersand => (BinOp::BitAnd, precedence::BIT_AND),
+        Token::LessLess => (BinOp::Shl, precedence::SHIFT),
+        Token::GreaterGreater => (BinOp::Shr, precedence::SHIFT),
+        Token::Plus => (BinOp::Add, precedence::SUM),
+        Token::Minus => (BinOp::Sub, precedence::SUM),
+        Token::Star => (BinOp::Mul, precedence::PRODUCT),
+        Token::Slash => (BinOp::Div, precedence::PRODUCT),
+        Token::Percent => (BinOp::Rem, precedence::PRODUCT),
+        _ => return None,
+    };
+    Some(operator)
+}
+
+/// Returns the binary operator of the given compound assignment token, if any.
+fn compound_assignment_operator(token: Token) -> Option<BinOp> {
+    let operator = match token {
+        Token::PlusEqual => BinOp::Add,
+        Token::MinusEqual => BinOp::Sub,
+        Token::StarEqual => BinOp::Mul,
+        Token::SlashEqual => BinOp::Div,
+        Token::PercentEqual => BinOp::Rem,
+        Token::CaretEqual => BinOp::BitXor,
+        Token::AmpersandEqual => BinOp::BitAnd,
+        Token::PipeEqual => BinOp::BitOr,
+        Token::LessLessEqual => BinOp::Shl,
+        Token::GreaterGreaterEqual => BinOp::Shr,
+        _ => return None,
+    };
+    Some(operator)
+}
+
+struct Parser<'ctx, 'src> {
+    context: &'ctx ParseContext,
+    source: &'src str,
+    /// The tokens of the source file, with keywords identified and ending with `Token::Eof`.
+    tokens: Vec<SpannedToken>,
+    position: usize,
+    /// The span of the last consumed token.
+    prev_span: Span,
+    next_id: NodeId,
+    restrictions: Restrictions,
+    /// The number of nested expressions, blocks, patterns and types being parsed.
+    depth: usize,
+}
+
+impl<'ctx, 'src> Parser<'ctx, 'src> {
+    fn new(context: &'ctx ParseContext, source: &'src str) -> Self {
+        Self::new_at(context, source, 0)
+    }
+
+    /// Creates a parser of the given source from the given byte offset.
+    fn new_at(context: &'ctx ParseContext, source: &'src str, start: usize) -> Self {
+        let mut tokens: Vec<_> = lexer::lex_spanned_from(context, source, start)
+            .map(|spanned| match spanned.token {
+                Token::Identifier(span) => SpannedToken {
+                    token: Token::keyword(&source[span.start as usize..span.end as usize])
+                        .unwrap_or(spanned.token),
+                    span,
+                },
+                _ => spanned,
+            })
+            .collect();
+        tokens.push(SpannedToken {
+            token: Token::Eof,
+            span: Span::new_empty(source.len()),
+        });
+        Self {
+            context,
+            source,
+            tokens,
+            position: 0,
+            prev_span: Span::new_empty(start),
+            next_id: 0,
+            restrictions: Restrictions::default(),
+            depth: 0,
+        }
+    }
+
+    // Token stream handling
+
+    /// Returns the current token.
+    fn token(&self) -> Token {
+        self.tokens[self.position].token
+    }
+
+    /// Returns the token `n` tokens after the current one.
+    fn look_ahead(&self, n: usize) -> Token {
+        let position = (self.position + n).min(self.tokens.len() - 1);
+        self.tokens[position].token
+    }
+
+    /// Returns the span of the current token.
+    fn span(&self) -> Span {
+        self.tokens[self.position].span
+    }
+
+    /// Consumes the current token and returns it.
+    fn bump(&mut self) -> SpannedToken {
+        let token = self.tokens[self.position];
+        if token.token != Token::Eof {
+            self.position += 1;
+        }
+        self.prev_span = token.span;
+        token
+    }
+
+    /// Returns true if the current token is the given one.
+    fn check(&self, token: Token) -> bool {
+        self.token() == token
+    }
+
+    /// Consumes the current token if it is the given one.
+    fn eat(&mut self, token: Token) -> bool {
+        if self.check(token) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes the current token if it is the given one, or reports an error.
+    fn expect(&mut self, token: Token) -> PResult<Span> {
+        if self.check(token) {
+            Ok(self.bump().span)
+        } else {
+            let expected = format!("`{}`", TokenStr::new(token, self.source));
+            Err(self.unexpected(&expected))
+        }
+    }
+
+    /// Consumes a `>` closing generic arguments, splitting `>>`, `>=` and `>>=` if needed.
+    fn expect_closing_angle(&mut self) -> PResult<()> {
+        let remaining = match self.token() {
+            Token::Greater => {
+                self.bump();
+                return Ok(());
+            }
+            Token::GreaterGreater => Token::Greater,
+            Token::GreaterEqual => Token::Equal,
+            Token::GreaterGreaterEqual => Token::GreaterEqual,
+            _ => return Err(self.unexpected("`>`")),
+        };
+        let span = self.span();
+        self.prev_span = Span::new(span.start, span.start + 1);
+        self.tokens[self.position] = SpannedToken {
+            token: remaining,
+            span: Span::new(span.start + 1, span.end),
+        };
+        Ok(())
+    }
+
+    /// Returns the source text of the given span.
+    fn text(&self, span: Span) -> &'src str {
+        &self.source[span.start as usize..span.end as usize]
+    }
+
+    /// Returns a human readable description of the current token.
+    fn describe_token(&self) -> String {
+        match self.token() {
+            Token::Eof => String::from("end of file"),
+            token if token.is_keyword() => {
+                format!("keyword `{}`", TokenStr::new(token, self.source))
+            }
+            _ => format!("`{}`", self.text(self.span())),
+        }
+    }
+
+    /// Returns a span from the start of the given span to the end of the last consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        Span::new(start.start, self.prev_span.end.max(start.start))
+    }
+
+    fn new_id(&mut self) -> NodeId {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    // Diagnostics
+
+    fn error(&self, code: ErrorCode, span: Span, message: impl Into<String>) -> ParseError {
+        self.context
+            .diag_ctx()
+            .emit(Diagnostic::spanned(Level::Error, span, message).with_code(code));
+        ParseError
+    }
+
+    /// Reports that something else than the current token was expected.
+    fn unexpected(&self, expected: &str) -> ParseError {
+        let message = format!("expected {expected}, found {}", self.describe_token());
+        self.error(error_codes::E0010, self.span(), message)
+    }
+
+    /// Returns true if the current token ends the construct being parsed, so that a missing
+    /// expression, pattern or type can be replaced by an error node.
+    fn is_recovery_point(&self) -> bool {
+        matches!(
+            self.token(),
+            Token::RightParen
+                | Token::RightBracket
+                | Token::RightBrace
+                | Token::Semicolon
+                | Token::Comma
+                | Token::Equal
+                | Token::FatArrow
+                | Token::Eof
+        )
+    }
+
+    /// Runs the given parsing function with the given restrictions.
+    fn with_restrictions<T>(
+        &mut self,
+        restrictions: Restrictions,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let saved = std::mem::replace(&mut self.restrictions, restrictions);
+        let result = f(self);
+        self.restrictions = saved;
+        result
+    }
+
+    /// Runs the given parsing function one nesting level deeper, or reports an error if the
+    /// nesting limit is reached.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> PResult<T>) -> PResult<T> {
+        self.deepen()?;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Goes one nesting level deeper, or reports an error if the nesting limit is reached.
+    fn deepen(&mut self) -> PResult<()> {
+        if self.depth == MAX_NESTING_DEPTH {
+            let message = format!("nested too deeply: more than {MAX_NESTING_DEPTH} levels");
+            return Err(self.error(error_codes::E0008, self.span(), message));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Runs the given parsing function building a chain of operators like `a + b + c` in a loop,
+    /// each operator nesting the expression on its left one level deeper with [`Self::deepen`].
+    fn chained<T>(&mut self, f: impl FnOnce(&mut Self) -> PResult<T>) -> PResult<T> {
+        let depth = self.depth;
+        let result = f(self);
+        self.depth = depth;
+        result
+    }
+
+    /// Parses a comma separated list of elements until the closing token, which is consumed.
+    fn parse_comma_separated<T>(
+        &mut self,
+        closing: Token,
+        mut parse_element: impl FnMut(&mut Self) -> PResult<T>,
+    ) -> PResult<Vec<T>> {
+        let mut elements = vec![];
+        while !self.check(closing) {
+            elements.push(parse_element(self)?);
+            if !self.eat(Token::Comma) {
+                break;
+            }
+        }
+        self.expect(closing)?;
+        Ok(elements)
+    }
+
+    // Program, items and statements
+
+    fn parse_program(&mut self) -> Program {
+        let mut attrs = vec![];
+        while self.check(Token::Pound) {
+            match self.parse_attr() {
+                Ok(attr) => attrs.push(attr),
+                Err(ParseError) => {
+                    self.eat(Token::LeftBracket);
+                    self.skip_until(|token| token == Token::RightBracket);
+                    self.eat(Token::RightBracket);
+                }
+            }
+        }
+        let mut stmts = vec![];
+        while !self.check(Token::Eof) {
+            if self.check(Token::RightBrace) {
+                let span = self.bump().span;
+                self.error(
+                    error_codes::E0016,
+                    span,
+                    "unexpected closing delimiter: `}`",
+                );
+                continue;
+            }
+            stmts.push(self.parse_stmt());
+        }
+        Program {
+            attrs,
+            stmts,
+            span: Span::new(0, self.source.len()),
+        }
+    }
+
+    /// Parses an attribute of the program: `#![type_check]`.
+    fn parse_attr(&mut self) -> PResult<Attribute> {
+        let start = self.expect(Token::Pound)?;
+        self.expect(Token::Not)?;
+        self.expect(Token::LeftBracket)?;
+        let name = self.ident()?;
+        self.expect(Token::RightBracket)?;
+        if !KNOWN_ATTRIBUTES.contains(&name.name.as_str()) {
+            let message = format!("unknown attribute `{}`", name.name);
+            self.error(error_codes::E0019, name.span, message);
+        }
+        Ok(Attribute {
+            name,
+            span: self.span_from(start),
+        })
+    }
+
+    fn ident(&mut self) -> PResult<Ident> {
+        match self.token() {
+            Token::Identifier(span) => {
+                self.bump();
+                Ok(Ident {
+                    name: String::from(self.text(span)),
+                    span,
+                })
+            }
+            _ => Err(self.unexpected("identifier")),
+        }
+    }
+
+    /// Returns true if the current token starts an item.
+    fn is_item_start(&self) -> bool {
+        matches!(
+            self.token(),
+            Token::Pub
+                | Token::Fn
+                | Token::Const
+                | Token::Struct
+                | Token::Enum
+                | Token::Impl
+                | Token::Trait
+        )
+    }
+
+    fn parse_item(&mut self) -> PResult<Item> {
+        let start = self.span();
+        let is_pub = self.eat(Token::Pub);
+        let kind = match self.token() {
+            Token::Const if self.look_ahead(1) != Token::Fn => ItemKind::Const(self.parse_const()?),
+            Token::Fn | Token::Const => {
+                let decl = self.parse_fn()?;
+                if let Some(self_param) = &decl.self_param {
+                    self.error(
+                        error_codes::E0017,
+                        self_param.span,
+                        "`self` parameter is only allowed in associated functions",
+                    );
+                }
+                self.check_fn_body(&decl, self.span_from(start));
+                ItemKind::Fn(decl)
+            }
+            Token::Struct => ItemKind::Struct(self.parse_struct()?),
+            Token::Enum => ItemKind::Enum(self.parse_enum()?),
+            Token::Impl => ItemKind::Impl(self.parse_impl()?),
+            Token::Trait => ItemKind::Trait(self.parse_trait()?),
+            _ => {
+                return Err(self.unexpected("`fn`, `const`, `struct`, `enum`, `impl` or `trait`"));
+            }
+        };
+        Ok(Item {
+            id: self.new_id(),
+            kind,
+            is_pub,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_fn(&mut self) -> PResult<FnDecl> {
+        let is_const = self.eat(Token::Const);
+        self.expect(Token::Fn)?;
+        let name = self.ident()?;
+        self.expect(Token::LeftParen)?;
+        let self_param = self.parse_self_param()?;
+        if self_param.is_some() && !self.check(Token::RightParen) {
+            self.expect(Token::Comma)?;
+        }
+        let params = self.parse_comma_separated(Token::RightParen, Self::parse_param)?;
+        let ret = if self.eat(Token::Arrow) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        let body = if self.eat(Token::Semicolon) {
+            None
+        } else {
+            Some(self.parse_block()?)
+        };
+        Ok(FnDecl {
+            is_const,
+            name,
+            self_param,
+            params,
+            ret,
+            body,
+        })
+    }
+
+    /// Reports a function declared outside of a trait without a body.
+    fn check_fn_body(&self, decl: &FnDecl, span: Span) {
+        if decl.body.is_none() {
+            self.error(error_codes::E0018, span, "function without a body");
+        }
+    }
+
+    /// Parses the `self` parameter of a method if present: `self`, `mut self`, `&self` or
+    /// `&mut self`.
+    fn parse_self_param(&mut self) -> PResult<Option<SelfParam>> {
+        let (kind, len) = match (self.token(), self.look_ahead(1), self.look_ahead(2)) {
+            (Token::SelfValue, _, _) => (SelfKind::Value(Mutability::Not), 1),
+            (Token::Mut, Token::SelfValue, _) => (SelfKind::Value(Mutability::Mut), 2),
+            (Token::Ampersand, Token::SelfValue, _) => (SelfKind::Ref(Mutability::Not), 2),
+            (Token::Ampersand, Token::Mut, Token::SelfValue) => (SelfKind::Ref(Mutability::Mut), 3),
+            _ => return Ok(None),
+        };
+        let start = self.span();
+        for _ in 0..len {
+            self.bump();
+        }
+        Ok(Some(SelfParam {
+            id: self.new_id(),
+            kind,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn parse_param(&mut self) -> PResult<Param> {
+        let start = self.span();
+        // Or-patterns must be parenthesized in parameters, as `|` also closes closure parameters
+        let pat = self.parse_pattern_no_alt()?;
+        let ty = if self.eat(Token::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        Ok(Param {
+            id: self.new_id(),
+            pat,
+            ty,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_struct(&mut self) -> PResult<StructDecl> {
+        self.expect(Token::Struct)?;
+        let name = self.ident()?;
+        let fields = self.parse_variant_fields()?;
+        if !matches!(fields, VariantFields::Named(_)) {
+            self.expect(Token::Semicolon)?;
+        }
+        Ok(StructDecl { name, fields })
+    }
+
+    fn parse_enum(&mut self) -> PResult<EnumDecl> {
+        self.expect(Token::Enum)?;
+        let name = self.ident()?;
+        self.expect(Token::LeftBrace)?;
+        let variants = self.parse_comma_separated(Token::RightBrace, |p| {
+            let start = p.span();
+            let name = p.ident()?;
+            let fields = p.parse_variant_fields()?;
+            Ok(Variant {
+                id: p.new_id(),
+                name,
+                fields,
+                span: p.span_from(start),
+            })
+        })?;
+        Ok(EnumDecl { name, variants })
+    }
+
+    fn parse_impl(&mut self) -> PResult<ImplDecl> {
+        self.expect(Token::Impl)?;
+        let ty = self.parse_type()?;
+        let (trait_ref, self_ty) = if self.eat(Token::For) {
+            let trait_ref = match ty.kind {
+                TypeKind::Path(path, args) if args.is_empty() => path,
+                _ => {
+                    let message = format!("expected trait, found type `{}`", self.text(ty.span));
+                    return Err(self.error(error_codes::E0010, ty.span, message));
+                }
+            };
+            (Some(trait_ref), self.parse_type()?)
+        } else {
+            (None, ty)
+        };
+        let items = self.parse_assoc_items()?;
+        for item in &items {
+            if let ItemKind::Fn(decl) = &item.kind {
+                self.check_fn_body(decl, item.span);
+            }
+        }
+        Ok(ImplDecl {
+            trait_ref,
+            self_ty,
+            items,
+        })
+    }
+
+    fn parse_const(&mut self) -> PResult<ConstDecl> {
+        self.expect(Token::Const)?;
+        let name = self.ident()?;
+        self.expect(Token::Colon)?;
+        let ty = self.parse_type()?;
+        self.expect(Token::Equal)?;
+        let expr = self.parse_expr()?;
+        self.expect_semicolon()?;
+        Ok(ConstDecl { name, ty, expr })
+    }
+
+    fn parse_trait(&mut self) -> PResult<TraitDecl> {
+        self.expect(Token::Trait)?;
+        let name = self.ident()?;
+        let items = self.parse_assoc_items()?;
+        Ok(TraitDecl { name, items })
+    }
+
+    /// Parses the braced functions of an `impl` or `trait` block.
+    fn parse_assoc_items(&mut self) -> PResult<Vec<Item>> {
+        self.expect(Token::LeftBrace)?;
+        let mut items = vec![];
+        while !self.check(Token::RightBrace) && !self.check(Token::Eof) {
+            let start = self.span();
+            let is_pub = self.eat(Token::Pub);
+            let is_const_fn = self.check(Token::Const) && self.look_ahead(1) == Token::Fn;
+            if !self.check(Token::Fn) && !is_const_fn {
+                // Skip to the next function of the block
+                self.unexpected("`fn`");
+                self.skip_until(|token| {
+                    matches!(token, Token::Fn | Token::Pub | Token::RightBrace)
+                });
+                continue;
+            }
+            let kind = ItemKind::Fn(self.parse_fn()?);
+            items.push(Item {
+                id: self.new_id(),
+                kind,
+                is_pub,
+                span: self.span_from(start),
+            });
+        }
+        self.expect(Token::RightBrace)?;
+        Ok(items)
+    }
+
+    /// Parses the optional named or tuple fields of a struct or an enum variant.
+    fn parse_variant_fields(&mut self) -> PResult<VariantFields> {
+        if self.eat(Token::LeftBrace) {
+            let fields = self.parse_comma_separated(Token::RightBrace, |p| {
+                let start = p.span();
+                let is_pub = p.eat(Token::Pub);
+                let name = p.ident()?;
+                p.expect(Token::Colon)?;
+                let ty = p.parse_type()?;
+                Ok(FieldDecl {
+                    name,
+                    ty,
+                    is_pub,
+                    span: p.span_from(start),
+                })
+            })?;
+            Ok(VariantFields::Named(fields))
+        } else if self.eat(Token::LeftParen) {
+            let mut position = 0;
+            let fields = self.parse_comma_separated(Token::RightParen, |p| {
+                let start = p.span();
+                let is_pub = p.eat(Token::Pub);
+                let ty = p.parse_type()?;
+                let name = Ident {
+                    name: position.to_string(),
+                    span: ty.span,
+                };
+                position += 1;
+                Ok(FieldDecl {
+                    name,
+                    ty,
+                    is_pub,
+                    span: p.span_from(start),
+                })
+            })?;
+            Ok(VariantFields::Tuple(fields))
+        } else {
+            Ok(VariantFields::Unit)
+        }
+    }
+
+    /// Parses a statement, recovering from syntax errors by producing an error statement.
+    fn parse_stmt(&mut self) -> Stmt {
+        let start = self.span();
+        let start_position = self.position;
+        match self.parse_stmt_without_recovery() {
+            Ok(stmt) => stmt,
+            Err(ParseError) => {
+                self.synchronize(start_position);
+                Stmt {
+                    id: self.new_id(),
+                    kind: StmtKind::Err,
+                    span: self.span_from(start),
+                }
+            }
+        }
+    }
+
+    /// Skips the tokens up to the next synchronisation point, after a syntax error.
+    ///
+    /// Stops after a `;` or before a `}` closing the enclosing block, or before a keyword starting
+    /// a statement if at least one token was consumed since the start of the erroneous statement.
+    /// Delimited groups are skipped as a whole.
+    fn synchronize(&mut self, start_position: usize) {
+        let mut depth = 0usize;
+        loop {
+            match self.token() {
+                Token::Eof => return,
+                Token::LeftBrace | Token::LeftParen | Token::LeftBracket => depth += 1,
+                Token::RightBrace if depth == 0 => return,
+                Token::RightBrace | Token::RightParen | Token::RightBracket => {
+                    depth = depth.saturating_sub(1);
+                }
+                Token::Semicolon if depth == 0 => {
+                    self.bump();
+                    return;
+                }
+                Token::Fn
+                | Token::Const
+                | Token::Struct
+                | Token::Enum
+                | Token::Impl
+                | Token::Trait
+                | Token::Let
+                | Token::Pub
+                    if depth == 0 && self.position > start_position =>
+                {
+                    return;
+                }
+                _ => (),
+            }
+            self.bump();
+        }
+    }
+
+    /// Skips tokens until one matching the predicate is found outside of delimited groups.
+    fn skip_until(&mut self, is_stop: impl Fn(Token) -> bool) {
+        let mut depth = 0usize;
+        loop {
+            match self.token() {
+                Token::Eof => return,
+                token if depth == 0 && is_stop(token) => return,
+                Token::LeftBrace | Token::LeftParen | Token::LeftBracket => depth += 1,
+                Token::RightBrace | Token::RightParen | Token::RightBracket => {
+                    depth = depth.saturating_sub(1);
+                }
+                _ => (),
+            }
+            self.bump();
+        }
+    }
+
+    /// Returns true if the current token can follow a statement missing its terminating `;`, in
+    /// which case the parser continues as if the `;` was present.
+    fn can_recover_missing_semicolon(&self) -> bool {
+        let gap = &self.source[self.prev_span.end as usize..self.span().start as usize];
+        gap.contains('\n')
+            || matches!(
+                self.token(),
+                Token::Let
+                    | Token::Fn
+                    | Token::Const
+                    | Token::Struct
+                    | Token::Enum
+                    | Token::Impl
+                    | Token::Trait
+                    | Token::Pub
+                    | Token::Eof
+            )
+    }
+
+    /// Reports a missing `;` after a statement, failing if the parser cannot recover from it.
+    fn expect_semicolon(&mut self) -> PResult<()> {
+        if self.eat(Token::Semicolon) {
+            return Ok(());
+        }
+        let error = self.unexpected("`;`");
+        if self.can_recover_missing_semicolon() {
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+
+    fn parse_stmt_without_recovery(&mut self) -> PResult<Stmt> {
+        let start = self.span();
+        crate::ice::set_current_span(start);
+        let kind = if self.eat(Token::Semicolon) {
+            StmtKind::Empty
+        } else if self.check(Token::Let) {
+            StmtKind::Let(self.parse_let()?)
+        } else if self.is_item_start() {
+            StmtKind::Item(Box::new(self.parse_item()?))
+        } else {
+            let expr = self.with_restrictions(Restrictions::default(), Self::parse_stmt_expr)?;
+            if self.eat(Token::Semicolon) {
+                StmtKind::Semi(expr)
+            } else if expr.kind.is_block_like()
+                || self.check(Token::RightBrace)
+                || self.check(Token::Eof)
+            {
+                StmtKind::Expr(expr)
+            } else {
+                self.expect_semicolon()?;
+                StmtKind::Semi(expr)
+            }
+        };
+        Ok(Stmt {
+            id: self.new_id(),
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Parses an expression in statement position.
+    ///
+    /// As in Rust, an expression statement starting with a block-like expression ends with its
+    /// block, unless it is followed by a method call or a field access.
+    fn parse_stmt_expr(&mut self) -> PResult<Expr> {
+        if !self.is_block_like_start() {
+            return self.parse_expr();
+        }
+        let expr = self.parse_primary()?;
+        if !self.check(Token::Dot) {
+            return Ok(expr);
+        }
+        let expr = self.parse_postfix(expr)?;
+        let expr = self.parse_binary_rest(expr, 0)?;
+        self.parse_assign_rest(expr)
+    }
+
+    fn is_block_like_start(&self) -> bool {
+        matches!(
+            self.token(),
+            Token::LeftBrace
+                | Token::If
+                | Token::While
+                | Token::For
+                | Token::Loop
+                | Token::Match
+                | Token::Lifetime(_)
+        )
+    }
+
+    fn parse_let(&mut self) -> PResult<Local> {
+        self.expect(Token::Let)?;
+        let pat = self.parse_pattern()?;
+        let ty = if self.eat(Token::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        let init = if self.eat(Token::Equal) {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        self.expect_semicolon()?;
+        Ok(Local { pat, ty, init })
+    }
+
+    fn parse_block(&mut self) -> PResult<Block> {
+        // The nesting is checked before the `{` to recover after the matching `}`
+        self.nested(|p| {
+            let start = p.expect(Token::LeftBrace)?;
+            let stmts = p.with_restrictions(Restrictions::default(), |p| {
+                let mut stmts = vec![];
+                while !p.check(Token::RightBrace) && !p.check(Token::Eof) {
+                    stmts.push(p.parse_stmt());
+                }
+                stmts
+            });
+            // A missing `}` can only be at the end of the file, the block is complete anyway
+            _ = p.expect(Token::RightBrace);
+            Ok(Block {
+                id: p.new_id(),
+                stmts,
+                span: p.span_from(start),
+            })
+        })
+    }
+
+    // Expressions
+
+    fn make_expr(&mut self, kind: ExprKind, span: Span) -> Expr {
+        Expr {
+            id: self.new_id(),
+            kind,
+            span,
+        }
+    }
+
+    fn parse_expr(&mut self) -> PResult<Expr> {
+        self.nested(|p| {
+            let lhs = if p.check(Token::DotDot) || p.check(Token::DotDotEqual) {
+                p.parse_range_rest(None)?
+            } else {
+                let lhs = p.parse_binary(0)?;
+                p.parse_range_rest(Some(lhs))?
+            };
+            p.parse_assign_rest(lhs)
+        })
+    }
+
+    /// Parses an expression without allowing struct literals, as in `if` conditions.
+    fn parse_expr_no_struct(&mut self) -> PResult<Expr> {
+        let restrictions = Restrictions {
+            no_struct_literal: true,
+        };
+        self.with_restrictions(restrictions, Self::parse_expr)
+    }
+
+    /// Parses an assignment or compound assignment if the left-hand side is followed by one.
+    fn parse_assign_rest(&mut self, lhs: Expr) -> PResult<Expr> {
+        let token = self.token();
+        if token == Token::Equal {
+            self.bump();
+            let rhs = self.parse_expr()?;
+            let span = Span::new(lhs.span.start, rhs.span.end);
+            Ok(self.make_expr(ExprKind::Assign(Box::new(lhs), Box::new(rhs)), span))
+        } else if let Some(op) = compound_assignment_operator(token) {
+            self.bump();
+            let rhs = self.parse_expr()?;
+            let span = Span::new(lhs.span.start, rhs.span.end);
+            Ok(self.make_expr(ExprKind::AssignOp(op, Box::new(lhs), Box::new(rhs)), span))
+        } else {
+            Ok(lhs)
+        }
+    }
+
+    /// Returns true if the current token can start the end of a range or a returned value.
+    fn can_begin_expr(&self) -> bool {
+        match self.token() {
+            Token::RightParen
+            | Token::RightBracket
+            | Token::RightBrace
+            | Token::Comma
+            | Token::Semicolon
+            | Token::FatArrow
+            | Token::Equal
+            | Token::Eof => false,
+            Token::LeftBrace => !self.restrictions.no_struct_literal,
+            _ => true,
+        }
+    }
+
+    /// Parses a range if the start, if any, is followed by `..` or `..=`.
+    fn parse_range_rest(&mut self, start: Option<Expr>) -> PResult<Expr> {
+        let limits = match self.token() {
+            Token::DotDot => RangeLimits::HalfOpen,
+            Token::DotDotEqual => RangeLimits::Closed,
+            _ => return Ok(start.expect("a range without start has a `..` token")),
+        };
+        let operator = self.bump().span;
+        let end = if self.can_begin_expr() {
+            Some(Box::new(self.parse_binary(0)?))
+        } else if limits == RangeLimits::Closed {
+            return Err(self.error(error_codes::E0011, operator, "inclusive range with no end"));
+        } else {
+            None
+        };
+        let span_start = start.as_ref().map_or(operator, |start| start.span);
+        let span = self.span_from(span_start);
+        Ok(self.make_expr(ExprKind::Range(start.map(Box::new), end, limits), span))
+    }
+
+    /// Parses a binary expression whose operators have at least the given precedence.
+    fn parse_binary(&mut self, min_precedence: u8) -> PResult<Expr> {
+        let lhs = self.parse_unary()?;
+        self.parse_binary_rest(lhs, min_precedence)
+    }
+
+    fn parse_binary_rest(&mut self, mut lhs: Expr, min_precedence: u8) -> PResult<Expr> {
+        self.chained(|p| loop {
+            if p.check(Token::As) && precedence::CAST >= min_precedence {
+                p.deepen()?;
+                p.bump();
+                let ty = p.parse_type()?;
+                let span = Span::new(lhs.span.start, ty.span.end);
+                lhs = p.make_expr(ExprKind::Cast(Box::new(lhs), ty), span);
+                continue;
+            }
+            let Some((op, precedence)) = binary_operator(p.token()) else {
+                return Ok(lhs);
+            };
+            if precedence < min_precedence {
+                return Ok(lhs);
+            }
+            p.deepen()?;
+            p.bump();
+            let rhs = p.parse_binary(precedence + 1)?;
+            if op.is_comparison() {
+                if let Some((next, _)) = binary_operator(p.token()) {
+                    if next.is_comparison() {
+                        return Err(p.error(
+                            error_codes::E0015,
+                            p.span(),
+                            "comparison operators cannot be chained",
+                        ));
+                    }
+                }
+            }
+            let span = Span::new(lhs.span.start, rhs.span.end);
+            lhs = p.make_expr(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), span);
+        })
+    }
+
+    fn parse_unary(&mut self) -> PResult<Expr> {
+        let start = self.span();
+        let op = match self.token() {
+            Token::Minus => UnOp::Neg,
+            Token::Not => UnOp::Not,
+            Token::Star => UnOp::Deref,
+            Token::Ampersand | Token::AmpersandAmpersand => return self.parse_addr_of(),
+            _ => {
+                let expr = self.parse_primary()?;
+                return self.parse_postfix(expr);
+            }
+        };
+        self.bump();
+        let operand = self.nested(Self::parse_unary)?;
+        let span = self.span_from(start);
+        Ok(self.make_expr(ExprKind::Unary(op, Box::new(operand)), span))
+    }
+
+    /// Parses `&expr` or `&mut expr`, where `&&expr` is a reference to a reference.
+    fn parse_addr_of(&mut self) -> PResult<Expr> {
+        let start = self.span();
+        let is_double = self.bump().token == Token::AmpersandAmpersand;
+        let mutability = if self.eat(Token::Mut) {
+            Mutability::Mut
+        } else {
+            Mutability::Not
+        };
+        let operand = self.nested(Self::parse_unary)?;
+        let span = self.span_from(start);
+        let mut expr = self.make_expr(ExprKind::AddrOf(mutability, Box::new(operand)), span);
+        if is_double {
+            expr = self.make_expr(ExprKind::AddrOf(Mutability::Not, Box::new(expr)), span);
+        }
+        Ok(expr)
+    }
+
+    /// Parses the calls, method calls, field accesses, indexing and `?` operators following an
+    /// expression.
+    fn parse_postfix(&mut self, mut expr: Expr) -> PResult<Expr> {
+        self.chained(|p| loop {
+            match p.token() {
+                Token::LeftParen => {
+                    p.deepen()?;
+                    p.bump();
+                    let args = p.parse_call_args()?;
+                    let span = p.span_from(expr.span);
+                    expr = p.make_expr(ExprKind::Call(Box::new(expr), args), span);
+                }
+                Token::LeftBracket => {
+                    p.deepen()?;
+                    p.bump();
+                    let index = p.with_restrictions(Restrictions::default(), Self::parse_expr)?;
+                    p.expect(Token::RightBracket)?;
+                    let span = p.span_from(expr.span);
+                    expr = p.make_expr(ExprKind::Index(Box::new(expr), Box::new(index)), span);
+                }
+                Token::Dot => {
+                    p.deepen()?;
+                    p.bump();
+                    expr = p.parse_dot_suffix(expr)?;
+                }
+                Token::Question => {
+                    p.deepen()?;
+                    p.bump();
+                    let span = p.span_from(expr.span);
+                    expr = p.make_expr(ExprKind::Try(Box::new(expr)), span);
+                }
+                _ => return Ok(expr),
+            }
+        })
+    }
+
+    fn parse_call_args(&mut self) -> PResult<Vec<Expr>> {
+        self.with_restrictions(Restrictions::default(), |p| {
+            p.parse_comma_separated(Token::RightParen, Self::parse_expr)
+        })
+    }
+
+    /// Parses what follows a `.`: a method call, a field access or a tuple index.
+    fn parse_dot_suffix(&mut self, expr: Expr) -> PResult<Expr> {
+        match self.token() {
+            Token::Identifier(_) => {
+                let name = self.ident()?;
+                if self.eat(Token::LeftParen) {
+                    let args = self.parse_call_args()?;
+                    let span = self.span_from(expr.span);
+                    Ok(self.make_expr(ExprKind::MethodCall(Box::new(expr), name, args), span))
+                } else {
+                    let span = self.span_from(expr.span);
+                    Ok(self.make_expr(ExprKind::Field(Box::new(expr), name), span))
+                }
+            }
+            Token::Integer(literal) if literal.base == IntegerBase::Dec => {
+                self.bump();
+                let name = Ident {
+                    name: String::from(self.text(literal.value)),
+                    span: literal.value,
+                };
+                let span = self.span_from(expr.span);
+                Ok(self.make_expr(ExprKind::Field(Box::new(expr), name), span))
+            }
+            // `tuple.0.1` is lexed as a tuple followed by a float literal
+            Token::Float(literal)
+                if literal.base == IntegerBase::Dec
+                    && literal.exponent.start == literal.exponent.end
+                    && literal.suffix.start == literal.suffix.end
+                    && literal.fractional_part.start != literal.fractional_part.end =>
+            {
+                self.bump();
+                let first = Ident {
+                    name: String::from(self.text(literal.integer_part)),
+                    span: literal.integer_part,
+                };
+                let span = Span::new(expr.span.start, literal.integer_part.end);
+                let expr = self.make_expr(ExprKind::Field(Box::new(expr), first), span);
+                let second = Ident {
+                    name: String::from(self.text(literal.fractional_part)),
+                    span: literal.fractional_part,
+                };
+                let span = self.span_from(expr.span);
+                Ok(self.make_expr(ExprKind::Field(Box::new(expr), second), span))
+            }
+            _ => Err(self.unexpected("identifier or field index")),
+        }
+    }
+
+    fn parse_primary(&mut self) -> PResult<Expr> {
+        let start = self.span();
+        let kind = match self.token() {
+            Token::Integer(_) | Token::Float(_) | Token::String(_) => {
+                ExprKind::Lit(self.parse_literal()?)
+            }
+            Token::True => {
+                self.bump();
+                ExprKind::Lit(Lit::Bool(true))
+            }
+            Token::False => {
+                self.bump();
+                ExprKind::Lit(Lit::Bool(false))
+            }
+            Token::Nil => {
+                self.bump();
+                ExprKind::Lit(Lit::Nil)
+            }
+            Token::Identifier(_) | Token::SelfValue | Token::SelfType => {
+                let path = self.parse_path()?;
+                if self.check(Token::LeftBrace) && !self.restrictions.no_struct_literal {
+                    ExprKind::Struct(self.parse_struct_expr(path)?)
+                } else {
+                    ExprKind::Path(path)
+                }
+            }
+            Token::LeftParen => {
+                self.bump();
+                self.with_restrictions(Restrictions::default(), |p| {
+                    let mut elements = vec![];
+                    let mut trailing_comma = false;
+                    while !p.check(Token::RightParen) {
+                        elements.push(p.parse_expr()?);
+                        trailing_comma = p.eat(Token::Comma);
+                        if !trailing_comma {
+                            break;
+                        }
+                    }
+                    p.expect(Token::RightParen)?;
+                    if elements.len() == 1 && !trailing_comma {
+                        Ok(ExprKind::Paren(Box::new(elements.pop().unwrap())))
+                    } else {
+                        Ok(ExprKind::Tuple(elements))
+                    }
+                })?
+            }
+            Token::LeftBracket => {
+                self.bump();
+                let elements = self.with_restrictions(Restrictions::default(), |p| {
+                    p.parse_comma_separated(Token::RightBracket, Self::parse_expr)
+                })?;
+                ExprKind::Array(elements)
+            }
+            Token::LeftBrace => ExprKind::Block(self.parse_block()?),
+            Token::If => return self.parse_if(),
+            Token::While | Token::For | Token::Loop => self.parse_loop(None)?,
+            Token::Lifetime(_) => {
+                let label = self.label();
+                self.expect(Token::Colon)?;
+                if !matches!(self.token(), Token::While | Token::For | Token::Loop) {
+                    return Err(self.unexpected("`while`, `for` or `loop` after a label"));
+                }
+                self.parse_loop(label)?
+            }
+            Token::Match => return self.parse_match(),
+            Token::Pipe | Token::PipePipe | Token::Move => self.parse_closure()?,
+            Token::Return => {
+                self.bump();
+                ExprKind::Return(self.parse_optional_operand()?)
+            }
+            Token::Break => {
+                self.bump();
+                let label = self.label();
+                ExprKind::Break(label, self.parse_optional_operand()?)
+            }
+            Token::Continue => {
+                self.bump();
+                ExprKind::Continue(self.label())
+            }
+            _ => {
+                let message = format!("expected expression, found {}", self.describe_token());
+                let error = self.error(error_codes::E0011, start, message);
+                if !self.is_recovery_point() {
+                    return Err(error);
+                }
+                return Ok(self.make_expr(ExprKind::Err, Span::new_empty(start.start)));
+            }
+        };
+        let span = self.span_from(start);
+        Ok(self.make_expr(kind, span))
+    }
+
+    /// Parses a `while`, `for` or `loop` loop, the label preceding it being already parsed.
+    fn parse_loop(&mut self, label: Option<Ident>) -> PResult<ExprKind> {
+        match self.bump().token {
+            Token::While => {
+                let condition = self.parse_expr_no_struct()?;
+                let body = self.parse_block()?;
+                Ok(ExprKind::While(Box::new(condition), body, label))
+            }
+            Token::For => {
+                let pat = self.parse_pattern()?;
+                self.expect(Token::In)?;
+                let iterable = self.parse_expr_no_struct()?;
+                let body = self.parse_block()?;
+                Ok(ExprKind::For(pat, Box::new(iterable), body, label))
+            }
+            _ => Ok(ExprKind::Loop(self.parse_block()?, label)),
+        }
+    }
+
+    /// Parses the optional label following `break` or `continue`, or preceding a loop.
+    fn label(&mut self) -> Option<Ident> {
+        let Token::Lifetime(span) = self.token() else {
+            return None;
+        };
+        self.bump();
+        Some(Ident {
+            name: String::from(self.text(span)),
+            span,
+        })
+    }
+
+    /// Parses the optional value following `return` or `break`.
+    fn parse_optional_operand(&mut self) -> PResult<Option<Box<Expr>>> {
+        if self.can_begin_expr() {
+            Ok(Some(Box::new(self.parse_expr()?)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parses a closure: `|x, y: i64| x + y` or `move || -> i64 { 1 }`.
+    ///
+    /// A `|` or `||` only starts a closure in operand position, elsewhere it is a binary operator.
+    fn parse_closure(&mut self) -> PResult<ExprKind> {
+        let capture_by = if self.eat(Token::Move) {
+            CaptureBy::Value
+        } else {
+            CaptureBy::Ref
+        };
+        let params = if self.eat(Token::PipePipe) {
+            vec![]
+        } else {
+            self.expect(Token::Pipe)?;
+            self.parse_comma_separated(Token::Pipe, Self::parse_param)?
+        };
+        let (ret, body) = if self.eat(Token::Arrow) {
+            // With an explicit return type, the body must be a block
+            let ret = self.parse_type()?;
+            let start = self.span();
+            let block = self.parse_block()?;
+            let span = self.span_from(start);
+            (Some(ret), self.make_expr(ExprKind::Block(block), span))
+        } else {
+            (None, self.parse_expr()?)
+        };
+        Ok(ExprKind::Closure(Box::new(Closure {
+            capture_by,
+            params,
+            ret,
+            body: Box::new(body),
+            captures: vec![],
+        })))
+    }
+
+    fn parse_if(&mut self) -> PResult<Expr> {
+        let start = self.expect(Token::If)?;
+        let condition = self.parse_expr_no_struct()?;
+        let then = self.parse_block()?;
+        let otherwise = if self.eat(Token::Else) {
+            if self.check(Token::If) {
+                Some(Box::new(self.nested(Self::parse_if)?))
+            } else {
+                let block = self.parse_block()?;
+                let span = block.span;
+                Some(Box::new(self.make_expr(ExprKind::Block(block), span)))
+            }
+        } else {
+            None
+        };
+        let span = self.span_from(start);
+        Ok(self.make_expr(ExprKind::If(Box::new(condition), then, otherwise), span))
+    }
+
+    fn parse_match(&mut self) -> PResult<Expr> {
+        let start = self.expect(Token::Match)?;
+        let scrutinee = self.parse_expr_no_struct()?;
+        self.expect(Token::LeftBrace)?;
+        let arms = self.with_restrictions(Restrictions::default(), |p| {
+            let mut arms = vec![];
+            while !p.check(Token::RightBrace) && !p.check(Token::Eof) {
+                let arm = p.parse_arm();
+                let needs_comma = !arm.body.kind.is_block_like();
+                arms.push(arm);
+                if !p.eat(Token::Comma) && needs_comma {
+                    break;
+                }
+            }
+            arms
+        });
+        self.expect(Token::RightBrace)?;
+        let span = self.span_from(start);
+        Ok(self.make_expr(ExprKind::Match(Box::new(scrutinee), arms), span))
+    }
+
+    /// Parses a match arm, recovering from syntax errors by skipping to the next arm.
+    fn parse_arm(&mut self) -> Arm {
+        let start = self.span();
+        match self.parse_arm_without_recovery() {
+            Ok(arm) => arm,
+            Err(ParseError) => {
+                self.skip_until(|token| matches!(token, Token::Comma | Token::RightBrace));
+                let span = self.span_from(start);
+                Arm {
+                    id: self.new_id(),
+                    pat: self.make_pattern(PatKind::Err, span),
+                    guard: None,
+                    body: self.make_expr(ExprKind::Err, span),
+                    span,
+                }
+            }
+        }
+    }
+
+    fn parse_arm_without_recovery(&mut self) -> PResult<Arm> {
+        let start = self.span();
+        // A leading `|` is allowed to align the alternatives of an or-pattern
+        self.eat(Token::Pipe);
+        let pat = self.parse_pattern()?;
+        let guard = if self.eat(Token::If) {
+            Some(Box::new(self.parse_expr()?))
+        } else {
+            None
+        };
+        self.expect(Token::FatArrow)?;
+        let body = self.parse_stmt_expr()?;
+        Ok(Arm {
+            id: self.new_id(),
+            pat,
+            guard,
+            body,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_struct_expr(&mut self, path: Path) -> PResult<StructExpr> {
+        self.expect(Token::LeftBrace)?;
+        let mut fields = vec![];
+        let mut rest = None;
+        while !self.check(Token::RightBrace) {
+            if self.eat(Token::DotDot) {
+                rest = Some(Box::new(self.parse_expr()?));
+                break;
+            }
+            let start = self.span();
+            let name = self.ident()?;
+            let (expr, is_shorthand) = if self.eat(Token::Colon) {
+                (self.parse_expr()?, false)
+            } else {
+                let path = Path {
+                    segments: vec![name.clone()],
+                    span: name.span,
+                };
+                (self.make_expr(ExprKind::Path(path), name.span), true)
+            };
+            fields.push(ExprField {
+                name,
+                expr,
+                is_shorthand,
+                span: self.span_from(start),
+            });
+            if !self.eat(Token::Comma) {
+                break;
+            }
+        }
+        self.expect(Token::RightBrace)?;
+        Ok(StructExpr { path, fields, rest })
+    }
+
+    /// Parses a path like `foo`, `self`, `Self::new` or `Shape::Circle`.
+    fn parse_path(&mut self) -> PResult<Path> {
+        let start = self.span();
+        let mut segments = vec![self.path_segment()?];
+        while self.eat(Token::ColonColon) {
+            segments.push(self.path_segment()?);
+        }
+        Ok(Path {
+            segments,
+            span: self.span_from(start),
+        })
+    }
+
+    fn path_segment(&mut self) -> PResult<Ident> {
+        match self.token() {
+            Token::SelfValue | Token::SelfType => {
+                let span = self.bump().span;
+                Ok(Ident {
+                    name: String::from(self.text(span)),
+                    span,
+                })
+            }
+            _ => self.ident(),
+        }
+    }
+
+    fn parse_literal(&mut self) -> PResult<Lit> {
+        let SpannedToken { token, span } = self.bump();
+        match token {
+            Token::Integer(literal) => {
+                let digits = self.text(literal.value).replace('_', "");
+                let radix = match literal.base {
+                    IntegerBase::Bin => 2,
+                    IntegerBase::Oct => 8,
+                    IntegerBase::Dec => 10,
+                    IntegerBase::Hex => 16,
+                };
+                let value = match u128::from_str_radix(&digits, radix) {
+                    Ok(value) => value,
+                    Err(err) if *err.kind() == std::num::IntErrorKind::PosOverflow => {
+                        return Err(self.error(
+                            error_codes::E0014,
+                            span,
+                            "integer literal is too large",
+                        ));
+                    }
+                    // Invalid digits are already reported by the lexer
+                    Err(_) => 0,
+                };
+                Ok(Lit::Int(IntLit {
+                    value,
+                    suffix: self.suffix(literal.suffix),
+                }))
+            }
+            Token::Float(literal) => {
+                let mut text = self.text(literal.integer_part).replace('_', "");
+                text.push('.');
+                text.push_str(&self.text(literal.fractional_part).replace('_', ""));
+                if literal.exponent.start != literal.exponent.end {
+                    text.push('e');
+                    text.push_str(&self.text(literal.exponent).replace('_', ""));
+                }
+                // Invalid floats are already reported by the lexer
+                let value = text.parse().unwrap_or(0.0);
+                Ok(Lit::Float(FloatLit {
+                    value,
+                    suffix: self.suffix(literal.suffix),
+                }))
+            }
+            Token::String(content) => {
+                // Invalid escape sequences are already reported by the lexer
+                let text = self.text(content);
+                let value = lexer::unescape_string(text).unwrap_or_else(|_| String::from(text));
+                Ok(Lit::Str(value))
+            }
+            _ => unreachable!("parse_literal called on a non literal token"),
+        }
+    }
+
+    fn suffix(&self, suffix: Span) -> Option<String> {
+        if suffix.start == suffix.end {
+            None
+        } else {
+            Some(String::from(self.text(suffix)))
+        }
+    }
+
+    // Patterns
+
+    fn make_pattern(&mut self, kind: PatKind, span: Span) -> Pattern {
+        Pattern {
+            id: self.new_id(),
+            kind,
+            span,
+        }
+    }
+
+    /// Parses a pattern, which can be an or-pattern: `Some(0) | None`.
+    fn parse_pattern(&mut self) -> PResult<Pattern> {
+        let start = self.span();
+        let first = self.parse_pattern_no_alt()?;
+        if !self.check(Token::Pipe) {
+            return Ok(first);
+        }
+        let mut alternatives = vec![first];
+        while self.eat(Token::Pipe) {
+            alternatives.push(self.parse_pattern_no_alt()?);
+        }
+        let span = self.span_from(start);
+        Ok(self.make_pattern(PatKind::Or(alternatives), span))
+    }
+
+    /// Parses a pattern which is not an or-pattern, unless it is parenthesized.
+    fn parse_pattern_no_alt(&mut self) -> PResult<Pattern> {
+        let start = self.span();
+        let kind = match self.token() {
+            Token::Identifier(span) if self.text(span) == "_" => {
+                self.bump();
+                PatKind::Wild
+            }
+            Token::Mut => {
+                self.bump();
+                let name = self.ident()?;
+                PatKind::Ident(Mutability::Mut, name, self.parse_binding_subpattern()?)
+            }
+            Token::Integer(_)
+            | Token::Float(_)
+            | Token::String(_)
+            | Token::True
+            | Token::False
+            | Token::Nil
+            | Token::Minus => {
+                let literal = self.parse_literal_pattern()?;
+                match self.token() {
+                    Token::DotDotEqual => {
+                        self.bump();
+                        let end = self.parse_literal_pattern()?;
+                        PatKind::Range(
+                            Some(Box::new(literal)),
+                            Some(Box::new(end)),
+                            RangeLimits::Closed,
+                        )
+                    }
+                    Token::DotDot => {
+                        self.bump();
+                        let end = if self.can_begin_literal_pattern() {
+                            Some(Box::new(self.parse_literal_pattern()?))
+                        } else {
+                            None
+                        };
+                        PatKind::Range(Some(Box::new(literal)), end, RangeLimits::HalfOpen)
+                    }
+                    _ => PatKind::Lit(Box::new(literal)),
+                }
+            }
+            Token::DotDotEqual => {
+                self.bump();
+                let end = self.parse_literal_pattern()?;
+                PatKind::Range(None, Some(Box::new(end)), RangeLimits::Closed)
+            }
+            Token::DotDot => {
+                self.bump();
+                PatKind::Rest
+            }
+            Token::LeftParen => {
+                self.bump();
+                let mut elements = vec![];
+                let mut trailing_comma = false;
+                while !self.check(Token::RightParen) {
+                    elements.push(self.nested(Self::parse_pattern)?);
+                    trailing_comma = self.eat(Token::Comma);
+                    if !trailing_comma {
+                        break;
+                    }
+                }
+                self.expect(Token::RightParen)?;
+                let is_rest = |pat: &Pattern| matches!(pat.kind, PatKind::Rest);
+                if elements.len() == 1 && !trailing_comma && !is_rest(&elements[0]) {
+                    return Ok(elements.pop().unwrap());
+                }
+                PatKind::Tuple(elements)
+            }
+            Token::LeftBracket => {
+                self.bump();
+                PatKind::Slice(self.parse_comma_separated(Token::RightBracket, |p| {
+                    p.nested(Self::parse_pattern)
+                })?)
+            }
+            Token::Identifier(_) | Token::SelfValue | Token::SelfType => {
+                let path = self.parse_path()?;
+                if self.eat(Token::LeftParen) {
+                    let fields = self.parse_comma_separated(Token::RightParen, |p| {
+                        p.nested(Self::parse_pattern)
+                    })?;
+                    PatKind::TupleStruct(path, fields)
+                } else if self.check(Token::LeftBrace) {
+                    let (fields, has_rest) = self.parse_struct_pattern_fields()?;
+                    PatKind::Struct(path, fields, has_rest)
+                } else if path.segments.len() == 1 && path.segments[0].name != "Self" {
+                    let name = path.segments.into_iter().next().unwrap();
+                    PatKind::Ident(Mutability::Not, name, self.parse_binding_subpattern()?)
+                } else {
+                    PatKind::Path(path)
+                }
+            }
+            _ => {
+                let message = format!("expected pattern, found {}", self.describe_token());
+                let error = self.error(error_codes::E0012, start, message);
+                if !self.is_recovery_point() {
+                    return Err(error);
+                }
+                return Ok(self.make_pattern(PatKind::Err, Span::new_empty(start.start)));
+            }
+        };
+        let span = self.span_from(start);
+        Ok(self.make_pattern(kind, span))
+    }
+
+    /// Parses the subpattern of a binding after an `@`, if any: `x @ 1..=5`.
+    fn parse_binding_subpattern(&mut self) -> PResult<Option<Box<Pattern>>> {
+        if self.eat(Token::At) {
+            Ok(Some(Box::new(self.nested(Self::parse_pattern_no_alt)?)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn can_begin_literal_pattern(&self) -> bool {
+        matches!(
+            self.token(),
+            Token::Integer(_)
+                | Token::Float(_)
+                | Token::String(_)
+                | Token::True
+                | Token::False
+                | Token::Nil
+                | Token::Minus
+        )
+    }
+
+    /// Parses a literal pattern, which can be a negated number.
+    fn parse_literal_pattern(&mut self) -> PResult<Expr> {
+        let start = self.span();
+        if self.eat(Token::Minus) {
+            if !matches!(self.token(), Token::Integer(_) | Token::Float(_)) {
+                return Err(self.unexpected("number literal"));
+            }
+            let literal = self.parse_primary()?;
+            let span = self.span_from(start);
+            return Ok(self.make_expr(ExprKind::Unary(UnOp::Neg, Box::new(literal)), span));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_struct_pattern_fields(&mut self) -> PResult<(Vec<FieldPat>, bool)> {
+        self.expect(Token::LeftBrace)?;
+        let mut fields = vec![];
+        let mut has_rest = false;
+        while !self.check(Token::RightBrace) {
+            if self.eat(Token::DotDot) {
+                has_rest = true;
+                break;
+            }
+            let start = self.span();
+            let (name, pat, is_shorthand) = if self.check(Token::Mut) {
+                let pat = self.nested(Self::parse_pattern_no_alt)?;
+                let PatKind::Ident(_, name, _) = &pat.kind else {
+                    unreachable!("`mut` always starts a binding pattern");
+                };
+                (name.clone(), pat, true)
+            } else {
+                let name = self.ident()?;
+                if self.eat(Token::Colon) {
+                    (name, self.nested(Self::parse_pattern)?, false)
+                } else {
+                    let kind = PatKind::Ident(Mutability::Not, name.clone(), None);
+                    let pat = self.make_pattern(kind, name.span);
+                    (name, pat, true)
+                }
+            };
+            fields.push(FieldPat {
+                name,
+                pat,
+                is_shorthand,
+                span: self.span_from(start),
+            });
+            if !self.eat(Token::Comma) {
+                break;
+            }
+        }
+        self.expect(Token::RightBrace)?;
+        Ok((fields, has_rest))
+    }
+
+    // Types
+
+    fn parse_type(&mut self) -> PResult<Type> {
+        let start = self.span();
+        let kind = match self.token() {
+            Token::Identifier(span) if self.text(span) == "_" => {
+                self.bump();
+                TypeKind::Infer
+            }
+            Token::Identifier(_) | Token::SelfType => {
+                let path = self.parse_path()?;
+                let mut args = vec![];
+                if self.eat(Token::Less) {
+                    loop {
+                        args.push(self.nested(Self::parse_type)?);
+                        if !self.eat(Token::Comma) || self.check(Token::Greater) {
+                            break;
+                        }
+                    }
+                    self.expect_closing_angle()?;
+                }
+                TypeKind::Path(path, args)
+            }
+            Token::LeftParen => {
+                self.bump();
+                let mut elements = vec![];
+                let mut trailing_comma = false;
+                while !self.check(Token::RightParen) {
+                    elements.push(self.nested(Self::parse_type)?);
+                    trailing_comma = self.eat(Token::Comma);
+                    if !trailing_comma {
+                        break;
+                    }
+                }
+                self.expect(Token::RightParen)?;
+                if elements.len() == 1 && !trailing_comma {
+                    return Ok(elements.pop().unwrap());
+                }
+                TypeKind::Tuple(elements)
+            }
+            Token::LeftBracket => {
+                self.bump();
+                let element = self.nested(Self::parse_type)?;
+                self.expect(Token::RightBracket)?;
+                TypeKind::Array(Box::new(element))
+            }
+            Token::Ampersand => {
+                self.bump();
+                let mutability = if self.eat(Token::Mut) {
+                    Mutability::Mut
+                } else {
+                    Mutability::Not
+                };
+                TypeKind::Ref(mutability, Box::new(self.nested(Self::parse_type)?))
+            }
+            Token::Fn => {
+                self.bump();
+                self.expect(Token::LeftParen)?;
+                let params =
+                    self.parse_comma_separated(Token::RightParen, |p| p.nested(Self::parse_type))?;
+                let ret = if self.eat(Token::Arrow) {
+                    Some(Box::new(self.nested(Self::parse_type)?))
+                } else {
+                    None
+                };
+                TypeKind::Fn(params, ret)
+            }
+            Token::Impl => {
+                self.bump();
+                let mut bounds = vec![self.parse_path()?];
+                while self.eat(Token::Plus) {
+                    bounds.push(self.parse_path()?);
+                }
+                TypeKind::ImplTrait(bounds)
+            }
+            _ => {
+                let message = format!("expected type, found {}", self.describe_token());
+                let error = self.error(error_codes::E0013, start, message);
+                if !self.is_recovery_point() {
+                    return Err(error);
+                }
+                TypeKind::Err
+            }
+        };
+        Ok(Type {
+            id: self.new_id(),
+            kind,
+            span: self.span_from(start),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests;
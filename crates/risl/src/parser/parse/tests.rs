@@ -0,0 +1,737 @@
+use crate::parser::ast::*;
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::DiagContext;
+use crate::parser::emitter::new_emitter_none;
+use crate::parser::lexer::Span;
+
+use super::parse;
+
+fn stubbed_parse_context() -> ParseContext {
+    ParseContext::new(DiagContext::new(new_emitter_none()))
+}
+
+/// Parses the given source, asserting no error is reported.
+fn parse_ok(source: &str) -> Program {
+    let context = stubbed_parse_context();
+    let program = parse(&context, source);
+    assert_eq!(context.diag_ctx().diagnostics(), vec![]);
+    program
+}
+
+/// Parses the given source as a single expression statement and returns the expression.
+fn parse_expr(source: &str) -> Expr {
+    let mut program = parse_ok(source);
+    assert_eq!(program.stmts.len(), 1);
+    match program.stmts.pop().unwrap().kind {
+        StmtKind::Expr(expr) | StmtKind::Semi(expr) => expr,
+        kind => panic!("expected an expression statement, found {kind:?}"),
+    }
+}
+
+/// Renders an expression with explicit parentheses to check precedence and associativity.
+fn parenthesize(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Lit(Lit::Int(int)) => int.value.to_string(),
+        ExprKind::Path(path) => path.to_string(),
+        ExprKind::Unary(op, operand) => format!("({}{})", op.as_str(), parenthesize(operand)),
+        ExprKind::Binary(op, lhs, rhs) => format!(
+            "({} {} {})",
+            parenthesize(lhs),
+            op.as_str(),
+            parenthesize(rhs)
+        ),
+        ExprKind::Assign(lhs, rhs) => format!("({} = {})", parenthesize(lhs), parenthesize(rhs)),
+        ExprKind::Cast(expr, _) => format!("({} as _)", parenthesize(expr)),
+        ExprKind::Range(start, end, _) => format!(
+            "({}..{})",
+            start.as_deref().map(parenthesize).unwrap_or_default(),
+            end.as_deref().map(parenthesize).unwrap_or_default()
+        ),
+        ExprKind::Call(callee, args) => format!(
+            "{}({})",
+            parenthesize(callee),
+            args.iter().map(parenthesize).collect::<Vec<_>>().join(", ")
+        ),
+        ExprKind::MethodCall(receiver, name, args) => format!(
+            "{}.{}({})",
+            parenthesize(receiver),
+            name.name,
+            args.iter().map(parenthesize).collect::<Vec<_>>().join(", ")
+        ),
+        ExprKind::Field(expr, name) => format!("{}.{}", parenthesize(expr), name.name),
+        ExprKind::Index(expr, index) => format!("{}[{}]", parenthesize(expr), parenthesize(index)),
+        ExprKind::Paren(expr) => parenthesize(expr),
+        kind => panic!("unsupported expression {kind:?}"),
+    }
+}
+
+#[test]
+fn parse_empty() {
+    let program = parse_ok("");
+    assert_eq!(program.stmts, vec![]);
+}
+
+#[test]
+fn parse_let() {
+    let program = parse_ok("let answer: i64 = 42;");
+    let StmtKind::Let(local) = &program.stmts[0].kind else {
+        panic!("expected a let statement");
+    };
+    assert!(matches!(
+        &local.pat.kind,
+        PatKind::Ident(Mutability::Not, Ident { name, .. }, None) if name == "answer"
+    ));
+    assert!(
+        matches!(&local.ty, Some(Type { kind: TypeKind::Path(path, args), .. })
+        if path.to_string() == "i64" && args.is_empty())
+    );
+    assert!(matches!(
+        &local.init,
+        Some(Expr {
+            kind: ExprKind::Lit(Lit::Int(IntLit {
+                value: 42,
+                suffix: None
+            })),
+            ..
+        })
+    ));
+    assert_eq!(program.stmts[0].span, Span::new(0, 21));
+}
+
+#[test]
+fn binary_precedence() {
+    let expr = parse_expr("1 + 2 * 3 - 4 / 5 % 6;");
+    assert_eq!(parenthesize(&expr), "((1 + (2 * 3)) - ((4 / 5) % 6))");
+    let expr = parse_expr("a || b && c == d | e ^ f & g << h + i;");
+    assert_eq!(
+        parenthesize(&expr),
+        "(a || (b && (c == (d | (e ^ (f & (g << (h + i))))))))"
+    );
+}
+
+#[test]
+fn unary_cast_and_postfix_precedence() {
+    let expr = parse_expr("-a.b(c)[d] as f64 * !e;");
+    assert_eq!(parenthesize(&expr), "(((-a.b(c)[d]) as _) * (!e))");
+}
+
+#[test]
+fn assignment_is_right_associative() {
+    let expr = parse_expr("a = b = c + 1;");
+    assert_eq!(parenthesize(&expr), "(a = (b = (c + 1)))");
+}
+
+#[test]
+fn range_precedence() {
+    let expr = parse_expr("x = a + 1..b * 2;");
+    assert_eq!(parenthesize(&expr), "(x = ((a + 1)..(b * 2)))");
+    let expr = parse_expr("..;");
+    assert_eq!(parenthesize(&expr), "(..)");
+}
+
+#[test]
+fn keyword_logical_operators() {
+    let expr = parse_expr("a or b and c;");
+    assert_eq!(parenthesize(&expr), "(a || (b && c))");
+}
+
+#[test]
+fn tuple_index() {
+    let expr = parse_expr("t.0.1;");
+    assert_eq!(parenthesize(&expr), "t.0.1");
+}
+
+#[test]
+fn chained_comparison() {
+    let context = stubbed_parse_context();
+    parse(&context, "a < b < c;");
+    let diagnostics = context.diag_ctx().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code, Some(crate::error_codes::E0015));
+}
+
+#[test]
+fn parse_fn() {
+    let program = parse_ok("fn add(a: i64, b) -> i64 { a + b }");
+    let StmtKind::Item(item) = &program.stmts[0].kind else {
+        panic!("expected an item");
+    };
+    let ItemKind::Fn(decl) = &item.kind else {
+        panic!("expected a function");
+    };
+    assert_eq!(decl.name.name, "add");
+    assert_eq!(decl.params.len(), 2);
+    assert!(decl.params[0].ty.is_some());
+    assert!(decl.params[1].ty.is_none());
+    assert!(decl.ret.is_some());
+    assert!(matches!(
+        decl.body.as_ref().and_then(Block::tail),
+        Some(Expr {
+            kind: ExprKind::Binary(BinOp::Add, _, _),
+            ..
+        })
+    ));
+}
+
+#[test]
+fn parse_struct_and_enum() {
+    let program = parse_ok(
+        "pub struct Point { pub x: f64, y: f64 }
+        struct Pair(i64, i64);
+        struct Unit;
+        enum Shape { Circle(f64), Rect { w: f64, h: f64 }, Empty }",
+    );
+    let kinds: Vec<_> = program
+        .stmts
+        .iter()
+        .map(|stmt| match &stmt.kind {
+            StmtKind::Item(item) => &item.kind,
+            kind => panic!("expected an item, found {kind:?}"),
+        })
+        .collect();
+    let ItemKind::Struct(point) = kinds[0] else {
+        panic!("expected a struct");
+    };
+    assert!(
+        matches!(&point.fields, VariantFields::Named(fields) if fields.len() == 2 && fields[0].is_pub)
+    );
+    let ItemKind::Struct(pair) = kinds[1] else {
+        panic!("expected a struct");
+    };
+    assert!(matches!(&pair.fields, VariantFields::Tuple(fields) if fields[1].name.name == "1"));
+    assert!(matches!(
+        kinds[2],
+        ItemKind::Struct(StructDecl {
+            fields: VariantFields::Unit,
+            ..
+        })
+    ));
+    let ItemKind::Enum(shape) = kinds[3] else {
+        panic!("expected an enum");
+    };
+    assert_eq!(shape.variants.len(), 3);
+    assert!(matches!(shape.variants[2].fields, VariantFields::Unit));
+}
+
+#[test]
+fn parse_control_flow() {
+    let program = parse_ok(
+        "if a { 1 } else if b { 2 } else { 3 }
+        while i < 10 { i += 1; }
+        for x in 0..10 { continue; }
+        let y = { break 1 };
+        return;",
+    );
+    assert!(matches!(
+        &program.stmts[0].kind,
+        StmtKind::Expr(Expr { kind: ExprKind::If(_, _, Some(otherwise)), .. })
+            if matches!(otherwise.kind, ExprKind::If(..))
+    ));
+    assert!(matches!(
+        &program.stmts[1].kind,
+        StmtKind::Expr(Expr {
+            kind: ExprKind::While(..),
+            ..
+        })
+    ));
+    assert!(matches!(
+        &program.stmts[2].kind,
+        StmtKind::Expr(Expr {
+            kind: ExprKind::For(..),
+            ..
+        })
+    ));
+    assert_eq!(program.stmts.len(), 5);
+}
+
+#[test]
+fn parse_labelled_loops() {
+    let program = parse_ok(
+        "'outer: for x in xs { 'inner: while x { continue 'outer; } }
+        let y = loop { break 'outer 1 };",
+    );
+    let StmtKind::Expr(outer) = &program.stmts[0].kind else {
+        panic!("expected an expression statement");
+    };
+    let ExprKind::For(_, _, body, Some(label)) = &outer.kind else {
+        panic!("expected a labelled `for` loop");
+    };
+    assert_eq!(label.name, "'outer");
+    assert_eq!(outer.span.start, 0);
+    let Some(Expr {
+        kind: ExprKind::While(_, body, Some(label)),
+        ..
+    }) = body.tail()
+    else {
+        panic!("expected a labelled `while` loop");
+    };
+    assert_eq!(label.name, "'inner");
+    assert!(matches!(
+        &body.stmts[0].kind,
+        StmtKind::Semi(Expr { kind: ExprKind::Continue(Some(label)), .. }) if label.name == "'outer"
+    ));
+    let StmtKind::Let(local) = &program.stmts[1].kind else {
+        panic!("expected a `let` statement");
+    };
+    let Some(Expr {
+        kind: ExprKind::Loop(body, None),
+        ..
+    }) = &local.init
+    else {
+        panic!("expected a `loop` expression");
+    };
+    assert!(matches!(
+        body.tail(),
+        Some(Expr { kind: ExprKind::Break(Some(label), Some(_)), .. }) if label.name == "'outer"
+    ));
+    let (_, errors) = parse_err("'a: if x {}");
+    assert_eq!(
+        errors,
+        ["expected `while`, `for` or `loop` after a label, found keyword `if`"]
+    );
+}
+
+#[test]
+fn struct_literal_not_allowed_in_condition() {
+    let program = parse_ok("if x { y } match p { Point { x, .. } => x, _ => 0 }");
+    assert_eq!(program.stmts.len(), 2);
+    let expr = parse_expr("Point { x: 1, y };");
+    let ExprKind::Struct(literal) = expr.kind else {
+        panic!("expected a struct literal");
+    };
+    assert!(literal.fields[1].is_shorthand);
+}
+
+#[test]
+fn parse_match() {
+    let expr = parse_expr(
+        "match shape {
+            Shape::Circle(r) => r * r,
+            Shape::Rect { w, h: height } => { w * height }
+            Shape::Empty => -1,
+            (a, _) => a,
+            \"x\" => 0,
+        }",
+    );
+    let ExprKind::Match(_, arms) = expr.kind else {
+        panic!("expected a match expression");
+    };
+    assert!(matches!(arms[0].pat.kind, PatKind::TupleStruct(_, _)));
+    assert!(matches!(arms[1].pat.kind, PatKind::Struct(_, _, false)));
+    assert!(matches!(arms[2].pat.kind, PatKind::Path(_)));
+    assert!(matches!(arms[3].pat.kind, PatKind::Tuple(_)));
+}
+
+#[test]
+fn parse_types() {
+    let program = parse_ok("let v: Map<str, Vec<(i64, [f64])>> = x; let f: fn(&mut i64) -> _ = g;");
+    let StmtKind::Let(local) = &program.stmts[0].kind else {
+        panic!("expected a let statement");
+    };
+    let Some(Type {
+        kind: TypeKind::Path(_, args),
+        ..
+    }) = &local.ty
+    else {
+        panic!("expected a path type");
+    };
+    assert_eq!(args.len(), 2);
+    assert_eq!(program.stmts.len(), 2);
+}
+
+#[test]
+fn parse_literals() {
+    let program = parse_ok(r#"let a = 0xff_u8; let b = 1.5e3; let c = "a\tb\u{41}"; let d = nil;"#);
+    let inits: Vec<_> = program
+        .stmts
+        .iter()
+        .map(|stmt| match &stmt.kind {
+            StmtKind::Let(Local {
+                init:
+                    Some(Expr {
+                        kind: ExprKind::Lit(lit),
+                        ..
+                    }),
+                ..
+            }) => lit.clone(),
+            kind => panic!("expected a let statement, found {kind:?}"),
+        })
+        .collect();
+    assert_eq!(
+        inits,
+        vec![
+            Lit::Int(IntLit {
+                value: 255,
+                suffix: Some(String::from("u8")),
+            }),
+            Lit::Float(FloatLit {
+                value: 1500.0,
+                suffix: None,
+            }),
+            Lit::Str(String::from("a\tbA")),
+            Lit::Nil,
+        ]
+    );
+}
+
+#[test]
+fn missing_semicolon() {
+    let context = stubbed_parse_context();
+    parse(&context, "let a = 1 let b = 2;");
+    let diagnostics = context.diag_ctx().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "expected `;`, found keyword `let`");
+    assert_eq!(diagnostics[0].span, Some(Span::new(10, 13)));
+}
+
+/// Parses the given source, returning the program and the messages of the reported diagnostics.
+fn parse_err(source: &str) -> (Program, Vec<String>) {
+    let context = stubbed_parse_context();
+    let program = parse(&context, source);
+    let messages = context
+        .diag_ctx()
+        .diagnostics()
+        .into_iter()
+        .map(|diagnostic| diagnostic.message)
+        .collect();
+    (program, messages)
+}
+
+#[test]
+fn recover_missing_semicolon() {
+    let (program, messages) = parse_err("let a = 1 let b = 2;");
+    assert_eq!(messages.len(), 1);
+    assert!(matches!(
+        program.stmts[..],
+        [
+            Stmt {
+                kind: StmtKind::Let(_),
+                ..
+            },
+            Stmt {
+                kind: StmtKind::Let(_),
+                ..
+            },
+        ]
+    ));
+}
+
+#[test]
+fn recover_at_semicolon() {
+    let (program, messages) = parse_err("let a = 1 2 3; let b = * ; let c = 3;");
+    assert_eq!(
+        messages,
+        vec!["expected `;`, found `2`", "expected expression, found `;`",]
+    );
+    assert_eq!(program.stmts.len(), 3);
+    assert_eq!(program.stmts[0].kind, StmtKind::Err);
+    assert_eq!(program.stmts[0].span, Span::new(0, 14));
+    let StmtKind::Let(local) = &program.stmts[1].kind else {
+        panic!("expected a let statement");
+    };
+    let init = local.init.as_ref().unwrap();
+    assert!(
+        matches!(&init.kind, ExprKind::Unary(UnOp::Deref, operand) if operand.kind == ExprKind::Err)
+    );
+    assert!(matches!(program.stmts[2].kind, StmtKind::Let(_)));
+}
+
+#[test]
+fn recover_at_item_keyword() {
+    let (program, messages) = parse_err("let a = (1 + 2\nfn f() {}\nstruct S;");
+    assert_eq!(messages.len(), 1);
+    assert_eq!(program.stmts.len(), 3);
+    assert_eq!(program.stmts[0].kind, StmtKind::Err);
+    assert!(matches!(program.stmts[1].kind, StmtKind::Item(_)));
+    assert!(matches!(program.stmts[2].kind, StmtKind::Item(_)));
+}
+
+#[test]
+fn recover_inside_block() {
+    let (program, messages) = parse_err("fn f() { let = 1; g(,); }\nfn g() { 1 + }");
+    assert_eq!(
+        messages,
+        vec![
+            "expected pattern, found `=`",
+            "expected expression, found `,`",
+            "expected expression, found `}`",
+        ]
+    );
+    assert_eq!(program.stmts.len(), 2);
+    let StmtKind::Item(item) = &program.stmts[0].kind else {
+        panic!("expected an item");
+    };
+    let ItemKind::Fn(decl) = &item.kind else {
+        panic!("expected a function");
+    };
+    assert_eq!(decl.body.as_ref().unwrap().stmts.len(), 2);
+}
+
+#[test]
+fn recover_in_match_arm() {
+    let (program, messages) = parse_err("match x { 1 => + , 2 => b, }; a;");
+    assert_eq!(messages.len(), 1);
+    assert_eq!(program.stmts.len(), 2);
+    let StmtKind::Semi(Expr {
+        kind: ExprKind::Match(_, arms),
+        ..
+    }) = &program.stmts[0].kind
+    else {
+        panic!("expected a match expression");
+    };
+    assert_eq!(arms.len(), 2);
+    assert_eq!(arms[0].body.kind, ExprKind::Err);
+    assert!(matches!(arms[1].body.kind, ExprKind::Path(_)));
+}
+
+#[test]
+fn unexpected_closing_delimiter() {
+    let (program, messages) = parse_err("}\nlet a = 1;");
+    assert_eq!(messages, vec!["unexpected closing delimiter: `}`"]);
+    assert_eq!(program.stmts.len(), 1);
+}
+
+#[test]
+fn parse_closures() {
+    let expr = parse_expr("|x, y: i64| x + y");
+    let ExprKind::Closure(closure) = expr.kind else {
+        panic!("expected a closure, found {:?}", expr.kind);
+    };
+    assert_eq!(closure.capture_by, CaptureBy::Ref);
+    assert_eq!(closure.params.len(), 2);
+    assert!(closure.params[0].ty.is_none());
+    assert!(closure.params[1].ty.is_some());
+    assert_eq!(parenthesize(&closure.body), "(x + y)");
+    assert_eq!(closure.captures, vec![]);
+
+    let expr = parse_expr("move || -> i64 { 1 }");
+    let ExprKind::Closure(closure) = expr.kind else {
+        panic!("expected a closure, found {:?}", expr.kind);
+    };
+    assert_eq!(closure.capture_by, CaptureBy::Value);
+    assert!(closure.params.is_empty());
+    assert!(closure.ret.is_some());
+    assert!(matches!(closure.body.kind, ExprKind::Block(_)));
+    assert_eq!(expr.span, Span::new(0, 20));
+}
+
+#[test]
+fn closures_and_bitwise_or() {
+    assert_eq!(parenthesize(&parse_expr("a | b || c")), "((a | b) || c)");
+    let expr = parse_expr("f(|x| x | 1, || 2)");
+    let ExprKind::Call(_, args) = expr.kind else {
+        panic!("expected a call");
+    };
+    let [first, second] = &args[..] else {
+        panic!("expected two arguments");
+    };
+    let (ExprKind::Closure(first), ExprKind::Closure(second)) = (&first.kind, &second.kind) else {
+        panic!("expected closures");
+    };
+    assert_eq!(parenthesize(&first.body), "(x | 1)");
+    assert!(second.params.is_empty());
+}
+
+#[test]
+fn closure_return_type_requires_block() {
+    let context = stubbed_parse_context();
+    parse(&context, "|x| -> i64 x;");
+    let diagnostics = context.diag_ctx().diagnostics();
+    assert_eq!(diagnostics[0].message, "expected `{`, found `x`");
+}
+
+#[test]
+fn parse_impl_blocks() {
+    let program = parse_ok(
+        "impl Point {
+            fn new() -> Self { Self { x: 0, y: 0 } }
+            pub fn get(&self) -> i64 { self.x }
+            fn set(&mut self, x: i64) { self.x = x; }
+            fn consume(mut self) {}
+        }",
+    );
+    let StmtKind::Item(item) = &program.stmts[0].kind else {
+        panic!("expected an item");
+    };
+    let ItemKind::Impl(decl) = &item.kind else {
+        panic!("expected an impl block");
+    };
+    assert!(matches!(&decl.self_ty.kind, TypeKind::Path(path, _) if path.to_string() == "Point"));
+    let methods: Vec<_> = decl
+        .items
+        .iter()
+        .map(|item| match &item.kind {
+            ItemKind::Fn(decl) => (decl.self_param.as_ref().map(|p| p.kind), decl.params.len()),
+            kind => panic!("expected a function, found {kind:?}"),
+        })
+        .collect();
+    assert_eq!(
+        methods,
+        vec![
+            (None, 0),
+            (Some(SelfKind::Ref(Mutability::Not)), 0),
+            (Some(SelfKind::Ref(Mutability::Mut)), 1),
+            (Some(SelfKind::Value(Mutability::Mut)), 0),
+        ]
+    );
+    assert!(decl.items[1].is_pub);
+}
+
+#[test]
+fn impl_blocks_only_contain_functions() {
+    let (_, errors) = parse_err("impl Point { let x = 1; }");
+    assert_eq!(errors, vec!["expected `fn`, found keyword `let`"]);
+    let (_, errors) = parse_err("fn get(&self) {}");
+    assert_eq!(
+        errors,
+        vec!["`self` parameter is only allowed in associated functions"]
+    );
+}
+
+#[test]
+fn parse_traits() {
+    let program = parse_ok(
+        "trait Shape {
+            fn area(&self) -> f64;
+            fn name(&self) -> str { \"shape\" }
+        }
+        impl Shape for Circle { fn area(&self) -> f64 { 0.0 } }
+        fn total(shape: impl Shape + Display) {}",
+    );
+    let items: Vec<_> = program
+        .stmts
+        .iter()
+        .map(|stmt| match &stmt.kind {
+            StmtKind::Item(item) => &item.kind,
+            kind => panic!("expected an item, found {kind:?}"),
+        })
+        .collect();
+    let ItemKind::Trait(decl) = items[0] else {
+        panic!("expected a trait");
+    };
+    assert_eq!(decl.name.name, "Shape");
+    let bodies: Vec<_> = decl
+        .items
+        .iter()
+        .map(|item| match &item.kind {
+            ItemKind::Fn(decl) => decl.body.is_some(),
+            kind => panic!("expected a function, found {kind:?}"),
+        })
+        .collect();
+    assert_eq!(bodies, vec![false, true]);
+    let ItemKind::Impl(decl) = items[1] else {
+        panic!("expected an impl block");
+    };
+    assert_eq!(decl.trait_ref.as_ref().unwrap().to_string(), "Shape");
+    assert!(matches!(&decl.self_ty.kind, TypeKind::Path(path, _) if path.to_string() == "Circle"));
+    let ItemKind::Fn(decl) = items[2] else {
+        panic!("expected a function");
+    };
+    let bounds = match &decl.params[0].ty.as_ref().unwrap().kind {
+        TypeKind::ImplTrait(bounds) => bounds.iter().map(Path::to_string).collect::<Vec<_>>(),
+        kind => panic!("expected an impl trait type, found {kind:?}"),
+    };
+    assert_eq!(bounds, vec!["Shape", "Display"]);
+}
+
+#[test]
+fn only_trait_functions_can_lack_a_body() {
+    let (_, errors) = parse_err("fn area(x: f64) -> f64;");
+    assert_eq!(errors, vec!["function without a body"]);
+    let (_, errors) = parse_err("impl Circle { fn area(&self) -> f64; }");
+    assert_eq!(errors, vec!["function without a body"]);
+    let (_, errors) = parse_err("impl &Shape for Circle {}");
+    assert_eq!(errors, vec!["expected trait, found type `&Shape`"]);
+}
+
+#[test]
+fn parse_attributes() {
+    let program = parse_ok("#![type_check]\nlet x: i64 = 1;");
+    assert!(program.has_attr("type_check"));
+    assert_eq!(program.attrs[0].span, Span::new(0, 14));
+    assert_eq!(program.stmts.len(), 1);
+
+    let (program, errors) = parse_err("#![typecheck]\n#[type_check]\nlet x = 1;");
+    assert_eq!(
+        errors,
+        vec!["unknown attribute `typecheck`", "expected `!`, found `[`"]
+    );
+    assert!(!program.has_attr("type_check"));
+    assert_eq!(program.stmts.len(), 1);
+}
+
+#[test]
+fn parse_const_items() {
+    let program = parse_ok(
+        "const MAX_LEN: usize = 1 << 10;
+        const fn double(x: i64) -> i64 { x * 2 }
+        impl Point { const fn origin() -> Self { Self { x: 0, y: 0 } } }",
+    );
+    let items: Vec<_> = program
+        .stmts
+        .iter()
+        .map(|stmt| match &stmt.kind {
+            StmtKind::Item(item) => &item.kind,
+            kind => panic!("expected an item, found {kind:?}"),
+        })
+        .collect();
+    let ItemKind::Const(decl) = items[0] else {
+        panic!("expected a constant");
+    };
+    assert_eq!(decl.name.name, "MAX_LEN");
+    assert!(matches!(&decl.ty.kind, TypeKind::Path(path, _) if path.to_string() == "usize"));
+    assert!(matches!(decl.expr.kind, ExprKind::Binary(BinOp::Shl, ..)));
+    assert!(matches!(items[1], ItemKind::Fn(decl) if decl.is_const));
+    let ItemKind::Impl(decl) = items[2] else {
+        panic!("expected an impl block");
+    };
+    assert!(matches!(&decl.items[0].kind, ItemKind::Fn(decl) if decl.is_const));
+
+    let (program, errors) = parse_err("const X = 1;\nconst Y: i64;\nlet z = 2;");
+    assert_eq!(
+        errors,
+        vec!["expected `:`, found `=`", "expected `=`, found `;`"]
+    );
+    assert!(matches!(program.stmts[2].kind, StmtKind::Let(_)));
+}
+
+#[test]
+fn nesting_limit() {
+    use super::MAX_NESTING_DEPTH;
+
+    let nested = |open: &str, close: &str, depth| open.repeat(depth) + "1" + &close.repeat(depth);
+    let check = move || {
+        parse_ok(&format!("{};", nested("(", ")", MAX_NESTING_DEPTH - 1)));
+        parse_ok(&format!("{};", ["1"; MAX_NESTING_DEPTH - 1].join(" + ")));
+        let deep = MAX_NESTING_DEPTH * 100;
+        let message = format!("nested too deeply: more than {MAX_NESTING_DEPTH} levels");
+        for source in [
+            nested("(", ")", deep),
+            nested("[", "]", deep),
+            nested("{ ", " }", deep),
+            nested("-", "", deep),
+            nested("x = ", "", deep),
+            format!("let {} = 1", nested("(", ",)", deep)),
+            format!("let x: {} = 1", nested("& ", "", deep).replace('1', "i64")),
+            // The operators of a chain nest the expression on their left
+            ["1"; MAX_NESTING_DEPTH * 100].join(" + "),
+            format!("1{}", " as i64".repeat(deep)),
+            format!("x{}", ".f()?[0]".repeat(deep)),
+            format!("{}{{}}", "if x {} else ".repeat(deep)),
+        ] {
+            let (program, errors) = parse_err(&format!("{source};\nlet y = 2;"));
+            assert_eq!(errors, [message.as_str()], "{}", &source[..20]);
+            assert!(matches!(
+                program.stmts.last().unwrap().kind,
+                StmtKind::Let(_)
+            ));
+        }
+    };
+    // The parser needs more than the default stack of the test threads in debug builds
+    std::thread::Builder::new()
+        .stack_size(16 << 20)
+        .spawn(check)
+        .unwrap()
+        .join()
+        .unwrap();
+}
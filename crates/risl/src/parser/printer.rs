@@ -0,0 +1,454 @@
+//! Printing of the AST, to debug the parser and write golden tests.
+//!
+//! A program can be printed as an indented tree, with one node per line, or as a single line
+//! S-expression in the spirit of the `AstPrinter` of Crafting Interpreters: `(+ 1 (* 2 3))`. Both
+//! formats show the same labels, types being printed inline with the Risl syntax.
+
+use super::ast::*;
+
+/// The formats the AST can be printed in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    Tree,
+    Sexp,
+}
+
+/// Prints the given program in the given format, ending with a newline.
+pub fn print(program: &Program, format: Format) -> String {
+    let node = Node::program(program);
+    let mut out = String::new();
+    match format {
+        Format::Tree => node.write_tree(0, &mut out),
+        Format::Sexp => {
+            // One statement per line for the program to stay readable
+            out.push_str("(program");
+            for stmt in &node.children {
+                out.push_str("\n  ");
+                stmt.write_sexp(&mut out);
+            }
+            out.push_str(")\n");
+        }
+    }
+    out
+}
+
+/// A generic node of the printed tree: a label and its children.
+struct Node {
+    label: String,
+    children: Vec<Node>,
+    /// Whether the node is printed as an atom in S-expressions, not as a list.
+    is_atom: bool,
+}
+
+impl Node {
+    fn leaf(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            children: vec![],
+            is_atom: true,
+        }
+    }
+
+    fn new(label: impl Into<String>, children: Vec<Node>) -> Self {
+        Self {
+            label: label.into(),
+            children,
+            is_atom: false,
+        }
+    }
+
+    fn write_tree(&self, depth: usize, out: &mut String) {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(&self.label);
+        out.push('\n');
+        for child in &self.children {
+            child.write_tree(depth + 1, out);
+        }
+    }
+
+    fn write_sexp(&self, out: &mut String) {
+        if self.is_atom {
+            out.push_str(&self.label);
+            return;
+        }
+        out.push('(');
+        out.push_str(&self.label);
+        for child in &self.children {
+            out.push(' ');
+            child.write_sexp(out);
+        }
+        out.push(')');
+    }
+
+    /// Placeholder for an optional child which is absent.
+    fn none() -> Self {
+        Self::leaf("_")
+    }
+
+    fn error() -> Self {
+        Self::leaf("<error>")
+    }
+
+    fn program(program: &Program) -> Self {
+        let attrs = program
+            .attrs
+            .iter()
+            .map(|attr| Self::new("attr", vec![Self::leaf(&attr.name.name)]));
+        Self::new(
+            "program",
+            attrs.chain(program.stmts.iter().map(Self::stmt)).collect(),
+        )
+    }
+
+    fn stmt(stmt: &Stmt) -> Self {
+        match &stmt.kind {
+            StmtKind::Let(local) => {
+                let mut children = vec![Self::pat(&local.pat)];
+                if let Some(ty) = &local.ty {
+                    children.push(Self::new(":", vec![Self::ty(ty)]));
+                }
+                if let Some(init) = &local.init {
+                    children.push(Self::new("=", vec![Self::expr(init)]));
+                }
+                Self::new("let", children)
+            }
+            StmtKind::Item(item) => Self::item(item),
+            StmtKind::Expr(expr) => Self::expr(expr),
+            StmtKind::Semi(expr) => Self::new(";", vec![Self::expr(expr)]),
+            StmtKind::Empty => Self::leaf(";"),
+            StmtKind::Err => Self::error(),
+        }
+    }
+
+    fn item(item: &Item) -> Self {
+        let node = match &item.kind {
+            ItemKind::Fn(decl) => {
+                let mut children = vec![Self::leaf(&decl.name.name)];
+                let self_param = decl.self_param.as_ref().map(|param| {
+                    Self::leaf(match param.kind {
+                        SelfKind::Value(Mutability::Not) => "self",
+                        SelfKind::Value(Mutability::Mut) => "mut self",
+                        SelfKind::Ref(Mutability::Not) => "&self",
+                        SelfKind::Ref(Mutability::Mut) => "&mut self",
+                    })
+                });
+                let params = self_param
+                    .into_iter()
+                    .chain(decl.params.iter().map(Self::param))
+                    .collect();
+                children.push(Self::new("params", params));
+                if let Some(ret) = &decl.ret {
+                    children.push(Self::new("->", vec![Self::ty(ret)]));
+                }
+                children.extend(decl.body.as_ref().map(Self::block));
+                Self::new(if decl.is_const { "const fn" } else { "fn" }, children)
+            }
+            ItemKind::Struct(decl) => {
+                let mut children = vec![Self::leaf(&decl.name.name)];
+                children.extend(Self::fields(&decl.fields));
+                Self::new("struct", children)
+            }
+            ItemKind::Enum(decl) => {
+                let mut children = vec![Self::leaf(&decl.name.name)];
+                children.extend(decl.variants.iter().map(|variant| {
+                    let mut children = vec![Self::leaf(&variant.name.name)];
+                    children.extend(Self::fields(&variant.fields));
+                    Self::new("variant", children)
+                }));
+                Self::new("enum", children)
+            }
+            ItemKind::Impl(decl) => {
+                let header = match &decl.trait_ref {
+                    Some(trait_ref) => {
+                        Self::leaf(format!("{trait_ref} for {}", type_label(&decl.self_ty)))
+                    }
+                    None => Self::ty(&decl.self_ty),
+                };
+                let mut children = vec![header];
+                children.extend(decl.items.iter().map(Self::item));
+                Self::new("impl", children)
+            }
+            ItemKind::Trait(decl) => {
+                let mut children = vec![Self::leaf(&decl.name.name)];
+                children.extend(decl.items.iter().map(Self::item));
+                Self::new("trait", children)
+            }
+            ItemKind::Const(decl) => Self::new(
+                "const",
+                vec![
+                    Self::leaf(&decl.name.name),
+                    Self::new(":", vec![Self::ty(&decl.ty)]),
+                    Self::new("=", vec![Self::expr(&decl.expr)]),
+                ],
+            ),
+        };
+        if item.is_pub {
+            Self::new("pub", vec![node])
+        } else {
+            node
+        }
+    }
+
+    fn param(param: &Param) -> Self {
+        let mut children = vec![Self::pat(&param.pat)];
+        children.extend(param.ty.as_ref().map(Self::ty));
+        Self::new("param", children)
+    }
+
+    fn fields(fields: &VariantFields) -> Vec<Self> {
+        fields
+            .fields()
+            .iter()
+            .map(|field| {
+                let label = if field.is_pub { "pub field" } else { "field" };
+                Self::new(
+                    label,
+                    vec![Self::leaf(&field.name.name), Self::ty(&field.ty)],
+                )
+            })
+            .collect()
+    }
+
+    fn block(block: &Block) -> Self {
+        Self::new("block", block.stmts.iter().map(Self::stmt).collect())
+    }
+
+    fn optional_expr(expr: &Option<Box<Expr>>) -> Self {
+        expr.as_deref().map_or_else(Self::none, Self::expr)
+    }
+
+    fn range(start: &Option<Box<Expr>>, end: &Option<Box<Expr>>, limits: RangeLimits) -> Self {
+        let label = match limits {
+            RangeLimits::HalfOpen => "..",
+            RangeLimits::Closed => "..=",
+        };
+        Self::new(
+            label,
+            vec![Self::optional_expr(start), Self::optional_expr(end)],
+        )
+    }
+
+    fn exprs(label: &str, first: Option<&Expr>, rest: &[Expr]) -> Self {
+        let children = first.into_iter().chain(rest).map(Self::expr).collect();
+        Self::new(label, children)
+    }
+
+    fn expr(expr: &Expr) -> Self {
+        match &expr.kind {
+            ExprKind::Lit(lit) => Self::leaf(lit_label(lit)),
+            ExprKind::Path(path) => Self::leaf(path.to_string()),
+            ExprKind::Unary(op, operand) => Self::new(op.as_str(), vec![Self::expr(operand)]),
+            ExprKind::AddrOf(mutability, operand) => {
+                let label = match mutability {
+                    Mutability::Not => "&",
+                    Mutability::Mut => "&mut",
+                };
+                Self::new(label, vec![Self::expr(operand)])
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                Self::new(op.as_str(), vec![Self::expr(lhs), Self::expr(rhs)])
+            }
+            ExprKind::Assign(lhs, rhs) => Self::new("=", vec![Self::expr(lhs), Self::expr(rhs)]),
+            ExprKind::AssignOp(op, lhs, rhs) => Self::new(
+                format!("{}=", op.as_str()),
+                vec![Self::expr(lhs), Self::expr(rhs)],
+            ),
+            ExprKind::Cast(expr, ty) => Self::new("as", vec![Self::expr(expr), Self::ty(ty)]),
+            ExprKind::Call(callee, args) => Self::exprs("call", Some(callee), args),
+            ExprKind::MethodCall(receiver, name, args) => {
+                Self::exprs(&format!(".{}", name.name), Some(receiver), args)
+            }
+            ExprKind::Field(receiver, name) => {
+                Self::new(".", vec![Self::expr(receiver), Self::leaf(&name.name)])
+            }
+            ExprKind::Index(expr, index) => {
+                Self::new("index", vec![Self::expr(expr), Self::expr(index)])
+            }
+            ExprKind::Tuple(exprs) => Self::exprs("tuple", None, exprs),
+            ExprKind::Array(exprs) => Self::exprs("array", None, exprs),
+            ExprKind::Struct(struct_expr) => {
+                let mut children = vec![Self::leaf(struct_expr.path.to_string())];
+                children.extend(
+                    struct_expr
+                        .fields
+                        .iter()
+                        .map(|field| Self::new(&field.name.name, vec![Self::expr(&field.expr)])),
+                );
+                if let Some(rest) = &struct_expr.rest {
+                    children.push(Self::new("..", vec![Self::expr(rest)]));
+                }
+                Self::new("struct-lit", children)
+            }
+            ExprKind::Try(expr) => Self::new("?", vec![Self::expr(expr)]),
+            ExprKind::Paren(expr) => Self::new("group", vec![Self::expr(expr)]),
+            ExprKind::Block(block) => Self::block(block),
+            ExprKind::If(cond, then, otherwise) => {
+                let mut children = vec![Self::expr(cond), Self::block(then)];
+                children.extend(otherwise.as_deref().map(Self::expr));
+                Self::new("if", children)
+            }
+            ExprKind::While(cond, body, label) => Self::new(
+                labelled("while", label),
+                vec![Self::expr(cond), Self::block(body)],
+            ),
+            ExprKind::For(pat, iterable, body, label) => Self::new(
+                labelled("for", label),
+                vec![Self::pat(pat), Self::expr(iterable), Self::block(body)],
+            ),
+            ExprKind::Loop(body, label) => {
+                Self::new(labelled("loop", label), vec![Self::block(body)])
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                let mut children = vec![Self::expr(scrutinee)];
+                children.extend(arms.iter().map(|arm| {
+                    let mut children = vec![Self::pat(&arm.pat)];
+                    if let Some(guard) = &arm.guard {
+                        children.push(Self::new("if", vec![Self::expr(guard)]));
+                    }
+                    children.push(Self::expr(&arm.body));
+                    Self::new("arm", children)
+                }));
+                Self::new("match", children)
+            }
+            ExprKind::Range(start, end, limits) => Self::range(start, end, *limits),
+            ExprKind::Return(value) => Self::exprs("return", value.as_deref(), &[]),
+            ExprKind::Break(label, value) => {
+                Self::exprs(&labelled("break", label), value.as_deref(), &[])
+            }
+            ExprKind::Closure(closure) => {
+                let params = closure.params.iter().map(Self::param).collect();
+                let mut children = vec![Self::new("params", params)];
+                if let Some(ret) = &closure.ret {
+                    children.push(Self::new("->", vec![Self::ty(ret)]));
+                }
+                children.push(Self::expr(&closure.body));
+                let label = match closure.capture_by {
+                    CaptureBy::Ref => "closure",
+                    CaptureBy::Value => "move closure",
+                };
+                Self::new(label, children)
+            }
+            ExprKind::Continue(label) => Self::leaf(labelled("continue", label)),
+            ExprKind::Err => Self::error(),
+        }
+    }
+
+    fn pat(pat: &Pattern) -> Self {
+        match &pat.kind {
+            PatKind::Wild => Self::leaf("_"),
+            PatKind::Ident(mutability, name, subpattern) => {
+                let binding = match mutability {
+                    Mutability::Not => Self::leaf(&name.name),
+                    Mutability::Mut => Self::new("mut", vec![Self::leaf(&name.name)]),
+                };
+                match subpattern {
+                    Some(subpattern) => Self::new("@", vec![binding, Self::pat(subpattern)]),
+                    None => binding,
+                }
+            }
+            PatKind::Lit(expr) => Self::expr(expr),
+            PatKind::Range(start, end, limits) => Self::range(start, end, *limits),
+            PatKind::Tuple(pats) => Self::new("tuple", pats.iter().map(Self::pat).collect()),
+            PatKind::Slice(pats) => Self::new("slice", pats.iter().map(Self::pat).collect()),
+            PatKind::Rest => Self::leaf(".."),
+            PatKind::Or(pats) => Self::new("|", pats.iter().map(Self::pat).collect()),
+            PatKind::Path(path) => Self::leaf(path.to_string()),
+            PatKind::TupleStruct(path, pats) => {
+                let mut children = vec![Self::leaf(path.to_string())];
+                children.extend(pats.iter().map(Self::pat));
+                Self::new("tuple-struct", children)
+            }
+            PatKind::Struct(path, fields, has_rest) => {
+                let mut children = vec![Self::leaf(path.to_string())];
+                children.extend(
+                    fields
+                        .iter()
+                        .map(|field| Self::new(&field.name.name, vec![Self::pat(&field.pat)])),
+                );
+                if *has_rest {
+                    children.push(Self::leaf(".."));
+                }
+                Self::new("struct", children)
+            }
+            PatKind::Err => Self::error(),
+        }
+    }
+
+    fn ty(ty: &Type) -> Self {
+        Self::leaf(type_label(ty))
+    }
+}
+
+fn lit_label(lit: &Lit) -> String {
+    match lit {
+        Lit::Int(int) => format!("{}{}", int.value, int.suffix.as_deref().unwrap_or_default()),
+        Lit::Float(float) => format!(
+            "{:?}{}",
+            float.value,
+            float.suffix.as_deref().unwrap_or_default()
+        ),
+        Lit::Str(value) => format!("{value:?}"),
+        Lit::Bool(value) => value.to_string(),
+        Lit::Nil => String::from("nil"),
+    }
+}
+
+/// Returns the keyword followed by the loop label, if any: `break 'outer`.
+fn labelled(keyword: &str, label: &Option<Ident>) -> String {
+    match label {
+        Some(label) => format!("{keyword} {}", label.name),
+        None => String::from(keyword),
+    }
+}
+
+/// Returns the Risl syntax of the given type.
+fn type_label(ty: &Type) -> String {
+    let list = |types: &[Type]| types.iter().map(type_label).collect::<Vec<_>>().join(", ");
+    match &ty.kind {
+        TypeKind::Path(path, args) if args.is_empty() => path.to_string(),
+        TypeKind::Path(path, args) => format!("{path}<{}>", list(args)),
+        TypeKind::Tuple(types) if types.len() == 1 => format!("({},)", type_label(&types[0])),
+        TypeKind::Tuple(types) => format!("({})", list(types)),
+        TypeKind::Array(elem) => format!("[{}]", type_label(elem)),
+        TypeKind::Ref(Mutability::Not, elem) => format!("&{}", type_label(elem)),
+        TypeKind::Ref(Mutability::Mut, elem) => format!("&mut {}", type_label(elem)),
+        TypeKind::Fn(params, None) => format!("fn({})", list(params)),
+        TypeKind::Fn(params, Some(ret)) => format!("fn({}) -> {}", list(params), type_label(ret)),
+        TypeKind::ImplTrait(bounds) => {
+            let bounds: Vec<_> = bounds.iter().map(Path::to_string).collect();
+            format!("impl {}", bounds.join(" + "))
+        }
+        TypeKind::Infer => String::from("_"),
+        TypeKind::Err => String::from("<error>"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::context::ParseContext;
+    use crate::parser::diagnostic::DiagContext;
+    use crate::parser::emitter::new_emitter_none;
+
+    fn print_source(source: &str, format: Format) -> String {
+        let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+        let program = crate::parser::parse(&context, source);
+        print(&program, format)
+    }
+
+    #[test]
+    fn sexp() {
+        assert_eq!(
+            print_source("1 + 2 * -(3 - x);", Format::Sexp),
+            "(program\n  (; (+ 1 (* 2 (- (group (- 3 x)))))))\n"
+        );
+    }
+
+    #[test]
+    fn tree() {
+        assert_eq!(
+            print_source("let a: i64 = f(1);", Format::Tree),
+            "program\n  let\n    a\n    :\n      i64\n    =\n      call\n        f\n        1\n"
+        );
+    }
+}
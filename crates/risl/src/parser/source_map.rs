@@ -0,0 +1,96 @@
+//! The mapping of the byte offsets of the spans to the lines and columns of a source file, to show
+//! the locations of the diagnostics and of the frames of the backtraces to the user.
+
+use super::lexer::Span;
+
+/// A source file, with the offsets of the start of its lines.
+#[derive(Clone, Debug)]
+pub struct SourceMap {
+    name: String,
+    source: String,
+    /// The offset of the start of each line.
+    starts: Vec<usize>,
+}
+
+/// A position in a source file, both numbered from 1. The column counts characters, not bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceMap {
+    /// Creates the map of the given source, named by the path of its file or by a placeholder like
+    /// `<stdin>`.
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        let source = source.into();
+        let newlines = source.match_indices('\n').map(|(index, _)| index + 1);
+        let starts = std::iter::once(0).chain(newlines).collect();
+        SourceMap {
+            name: name.into(),
+            source,
+            starts,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the location of the given offset, which is clamped to the end of the source.
+    pub fn location(&self, offset: u32) -> Location {
+        let offset = (offset as usize).min(self.source.len());
+        let line = self.starts.partition_point(|start| *start <= offset) - 1;
+        let start = self.starts[line];
+        let column = match self.source.get(start..offset) {
+            Some(prefix) => prefix.chars().count() + 1,
+            // The offset is within a character
+            None => offset - start + 1,
+        };
+        Location {
+            line: line + 1,
+            column,
+        }
+    }
+
+    /// Returns the text of the line with the given number, without its trailing whitespace.
+    pub fn line(&self, line: usize) -> &str {
+        let start = self.starts[line - 1];
+        let end = self.starts.get(line).copied().unwrap_or(self.source.len());
+        self.source[start..end].trim_end()
+    }
+
+    /// Returns the location of the start of the span as `file:line:column`.
+    pub fn describe(&self, span: Span) -> String {
+        let Location { line, column } = self.location(span.start);
+        format!("{}:{line}:{column}", self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations() {
+        let map = SourceMap::new("test.risl", "let x = 1;\nlet é = x;\r\n\nend");
+        assert_eq!(map.location(0), Location { line: 1, column: 1 });
+        assert_eq!(map.location(8), Location { line: 1, column: 9 });
+        assert_eq!(
+            map.location(10),
+            Location {
+                line: 1,
+                column: 11
+            }
+        );
+        assert_eq!(map.location(11), Location { line: 2, column: 1 });
+        // `é` takes two bytes
+        assert_eq!(map.location(18), Location { line: 2, column: 7 });
+        assert_eq!(map.location(25), Location { line: 4, column: 1 });
+        assert_eq!(map.location(1000), Location { line: 4, column: 4 });
+        assert_eq!(map.line(2), "let é = x;");
+        assert_eq!(map.line(3), "");
+        assert_eq!(map.line(4), "end");
+        assert_eq!(map.describe(Span::new(11, 14)), "test.risl:2:1");
+    }
+}
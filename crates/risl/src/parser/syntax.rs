@@ -0,0 +1,41 @@
+//! The lossless concrete syntax tree (CST) of the Risl language, for tooling.
+//!
+//! Contrary to the AST, the CST preserves every token of the source, including the whitespaces,
+//! the comments and the erroneous tokens, so that the source text can be reconstructed exactly. It
+//! is designed after the green/red trees of Roslyn and rust-analyzer:
+//! - the green tree ([`GreenNode`], [`GreenToken`]) is immutable and position independent. Its
+//!   nodes only know their kind, their text length and their children, so that identical subtrees
+//!   can be shared between the versions of a tree being edited.
+//! - the red tree ([`SyntaxNode`], [`SyntaxToken`]) is a cheap view over the green tree created on
+//!   demand while traversing it, adding the absolute positions and the parent pointers.
+//!
+//! The [`typed`] views give access to the children of the nodes by their meaning, and [`reparse`]
+//! updates a tree after an edit of its source by only reparsing the affected subtree.
+
+mod green;
+mod kind;
+mod lower;
+mod red;
+mod reparse;
+pub mod typed;
+
+use std::rc::Rc;
+
+pub use green::{GreenElement, GreenNode, GreenToken};
+pub use kind::SyntaxKind;
+pub use red::{SyntaxElement, SyntaxNode, SyntaxToken};
+pub use reparse::{reparse, Edit};
+
+use super::context::ParseContext;
+
+/// Parses the given source file into a syntax tree.
+///
+/// Syntax errors are reported through the diagnostic context of the parse context.
+pub fn parse(context: &ParseContext, source: &str) -> SyntaxNode {
+    let (program, tokens) = super::parse::parse_with_tokens(context, source);
+    let tokens = lower::with_trivia(source, &tokens);
+    SyntaxNode::new_root(Rc::new(lower::lower_program(source, &tokens, &program)))
+}
+
+#[cfg(test)]
+mod tests;
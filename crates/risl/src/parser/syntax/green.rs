@@ -0,0 +1,117 @@
+//! The green tree: immutable, position independent and shareable nodes and tokens.
+
+use std::rc::Rc;
+
+use super::SyntaxKind;
+
+/// A leaf of the green tree, owning its text.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct GreenToken {
+    kind: SyntaxKind,
+    text: String,
+}
+
+impl GreenToken {
+    pub fn new(kind: SyntaxKind, text: impl Into<String>) -> Self {
+        debug_assert!(kind.is_token());
+        Self {
+            kind,
+            text: text.into(),
+        }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the length of the token text in bytes.
+    pub fn text_len(&self) -> u32 {
+        self.text.len() as u32
+    }
+}
+
+/// An inner node of the green tree. Only knows the length of its text, not its position.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct GreenNode {
+    kind: SyntaxKind,
+    text_len: u32,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> Self {
+        debug_assert!(!kind.is_token());
+        let text_len = children.iter().map(GreenElement::text_len).sum();
+        Self {
+            kind,
+            text_len,
+            children,
+        }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    /// Returns the length of the node text in bytes.
+    pub fn text_len(&self) -> u32 {
+        self.text_len
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+
+    /// Returns a copy of the node with the child at the given index replaced.
+    ///
+    /// The other children are shared with the original node.
+    pub fn replace_child(&self, index: usize, child: GreenElement) -> GreenNode {
+        let mut children = self.children.clone();
+        children[index] = child;
+        GreenNode::new(self.kind, children)
+    }
+
+    fn write_text(&self, text: &mut String) {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => node.write_text(text),
+                GreenElement::Token(token) => text.push_str(token.text()),
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for GreenNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut text = String::with_capacity(self.text_len as usize);
+        self.write_text(&mut text);
+        write!(f, "{text}")
+    }
+}
+
+/// A child of a green node.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    pub fn kind(&self) -> SyntaxKind {
+        match self {
+            GreenElement::Node(node) => node.kind(),
+            GreenElement::Token(token) => token.kind(),
+        }
+    }
+
+    pub fn text_len(&self) -> u32 {
+        match self {
+            GreenElement::Node(node) => node.text_len(),
+            GreenElement::Token(token) => token.text_len(),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+/// The kinds of the tokens and nodes of the concrete syntax tree.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SyntaxKind {
+    // Tokens
+    Whitespace,
+    Comment,
+    Ident,
+    /// A loop label like `'outer`.
+    Lifetime,
+    Keyword,
+    IntLiteral,
+    FloatLiteral,
+    StringLiteral,
+    /// A punctuation or operator token, like `;` or `+=`.
+    Punct,
+    /// A sequence of unknown characters.
+    Error,
+
+    // Program, items and statements
+    SourceFile,
+    /// An attribute of the program, like `#![type_check]`.
+    Attr,
+    FnItem,
+    StructItem,
+    EnumItem,
+    ImplItem,
+    TraitItem,
+    ConstItem,
+    SelfParam,
+    Param,
+    Variant,
+    FieldDecl,
+    Block,
+    LetStmt,
+    /// An expression statement, with or without a terminating `;`.
+    ExprStmt,
+    EmptyStmt,
+    ErrorStmt,
+    /// An identifier introduced by a declaration, like the name of a function or a loop label.
+    Name,
+    /// An identifier referring to a field, a method or a loop label.
+    NameRef,
+    Path,
+
+    // Expressions
+    LitExpr,
+    PathExpr,
+    PrefixExpr,
+    RefExpr,
+    BinExpr,
+    /// An assignment, possibly compound like `a += 1`.
+    AssignExpr,
+    CastExpr,
+    CallExpr,
+    MethodCallExpr,
+    FieldExpr,
+    IndexExpr,
+    TryExpr,
+    TupleExpr,
+    ArrayExpr,
+    StructExpr,
+    ExprField,
+    ParenExpr,
+    BlockExpr,
+    IfExpr,
+    WhileExpr,
+    ForExpr,
+    LoopExpr,
+    MatchExpr,
+    MatchArm,
+    MatchGuard,
+    RangeExpr,
+    ReturnExpr,
+    BreakExpr,
+    ContinueExpr,
+    ClosureExpr,
+    ErrorExpr,
+
+    // Patterns
+    WildPat,
+    IdentPat,
+    LitPat,
+    RangePat,
+    TuplePat,
+    SlicePat,
+    RestPat,
+    OrPat,
+    PathPat,
+    TupleStructPat,
+    StructPat,
+    FieldPat,
+    ErrorPat,
+
+    // Types
+    PathType,
+    TupleType,
+    ArrayType,
+    RefType,
+    FnType,
+    ImplTraitType,
+    InferType,
+    ErrorType,
+}
+
+impl SyntaxKind {
+    /// Returns true if the kind is the one of a token.
+    pub fn is_token(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::Whitespace
+                | SyntaxKind::Comment
+                | SyntaxKind::Ident
+                | SyntaxKind::Lifetime
+                | SyntaxKind::Keyword
+                | SyntaxKind::IntLiteral
+                | SyntaxKind::FloatLiteral
+                | SyntaxKind::StringLiteral
+                | SyntaxKind::Punct
+                | SyntaxKind::Error
+        )
+    }
+
+    /// Returns true if the kind is the one of a token without meaning for the parser.
+    pub fn is_trivia(self) -> bool {
+        matches!(self, SyntaxKind::Whitespace | SyntaxKind::Comment)
+    }
+
+    pub fn is_item(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::FnItem
+                | SyntaxKind::StructItem
+                | SyntaxKind::EnumItem
+                | SyntaxKind::ImplItem
+                | SyntaxKind::TraitItem
+                | SyntaxKind::ConstItem
+        )
+    }
+
+    /// Returns true if the kind is the one of a statement. Items are statements too.
+    pub fn is_stmt(self) -> bool {
+        self.is_item()
+            || matches!(
+                self,
+                SyntaxKind::LetStmt
+                    | SyntaxKind::ExprStmt
+                    | SyntaxKind::EmptyStmt
+                    | SyntaxKind::ErrorStmt
+            )
+    }
+
+    pub fn is_expr(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::LitExpr
+                | SyntaxKind::PathExpr
+                | SyntaxKind::PrefixExpr
+                | SyntaxKind::RefExpr
+                | SyntaxKind::BinExpr
+                | SyntaxKind::AssignExpr
+                | SyntaxKind::CastExpr
+                | SyntaxKind::CallExpr
+                | SyntaxKind::MethodCallExpr
+                | SyntaxKind::FieldExpr
+                | SyntaxKind::IndexExpr
+                | SyntaxKind::TryExpr
+                | SyntaxKind::TupleExpr
+                | SyntaxKind::ArrayExpr
+                | SyntaxKind::StructExpr
+                | SyntaxKind::ParenExpr
+                | SyntaxKind::BlockExpr
+                | SyntaxKind::IfExpr
+                | SyntaxKind::WhileExpr
+                | SyntaxKind::ForExpr
+                | SyntaxKind::LoopExpr
+                | SyntaxKind::MatchExpr
+                | SyntaxKind::RangeExpr
+                | SyntaxKind::ReturnExpr
+                | SyntaxKind::BreakExpr
+                | SyntaxKind::ContinueExpr
+                | SyntaxKind::ClosureExpr
+                | SyntaxKind::ErrorExpr
+        )
+    }
+
+    pub fn is_pat(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::WildPat
+                | SyntaxKind::IdentPat
+                | SyntaxKind::LitPat
+                | SyntaxKind::RangePat
+                | SyntaxKind::TuplePat
+                | SyntaxKind::SlicePat
+                | SyntaxKind::RestPat
+                | SyntaxKind::OrPat
+                | SyntaxKind::PathPat
+                | SyntaxKind::TupleStructPat
+                | SyntaxKind::StructPat
+                | SyntaxKind::ErrorPat
+        )
+    }
+
+    pub fn is_type(self) -> bool {
+        matches!(
+            self,
+            SyntaxKind::PathType
+                | SyntaxKind::TupleType
+                | SyntaxKind::ArrayType
+                | SyntaxKind::RefType
+                | SyntaxKind::FnType
+                | SyntaxKind::ImplTraitType
+                | SyntaxKind::InferType
+                | SyntaxKind::ErrorType
+        )
+    }
+}
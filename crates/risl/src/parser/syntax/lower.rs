@@ -0,0 +1,502 @@
+//! The construction of the green tree from the AST and the tokens of the source.
+//!
+//! Every AST node becomes a syntax node covering its span. The tokens, trivia included, are
+//! attached to the innermost node whose span contains them, so whitespaces and comments between
+//! two nodes belong to their common parent.
+
+use std::rc::Rc;
+
+use super::green::{GreenElement, GreenNode, GreenToken};
+use super::SyntaxKind;
+use crate::parser::ast::*;
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::DiagContext;
+use crate::parser::emitter::new_emitter_none;
+use crate::parser::lexer::{self, Span, SpannedToken, Token};
+
+/// Builds the green tree of a whole program.
+pub(super) fn lower_program(source: &str, tokens: &[SpannedToken], program: &Program) -> GreenNode {
+    let mut lowering = Lowering::new(source, tokens);
+    lowering.start_node();
+    for attr in &program.attrs {
+        lowering.node(SyntaxKind::Attr, attr.span, |this| this.name(&attr.name));
+    }
+    for stmt in &program.stmts {
+        lowering.stmt(stmt);
+    }
+    lowering.finish_root(SyntaxKind::SourceFile)
+}
+
+/// Builds the green tree of a block, spanning its whole source.
+pub(super) fn lower_block(source: &str, tokens: &[SpannedToken], block: &Block) -> GreenNode {
+    let mut lowering = Lowering::new(source, tokens);
+    lowering.start_node();
+    for stmt in &block.stmts {
+        lowering.stmt(stmt);
+    }
+    lowering.finish_root(SyntaxKind::Block)
+}
+
+/// Returns the tokens of the source including the trivia, given its tokens without trivia.
+///
+/// The trivia are found by lexing the gaps between the tokens, which only contain whitespaces and
+/// comments.
+pub(super) fn with_trivia(source: &str, tokens: &[SpannedToken]) -> Vec<SpannedToken> {
+    // The gaps were already lexed once, their errors are already reported
+    let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+    let mut all_tokens = Vec::with_capacity(tokens.len() * 2);
+    let mut end = 0;
+    let gap_ends = tokens.iter().map(Some).chain(std::iter::once(None));
+    for token in gap_ends {
+        let gap_end = token.map_or(source.len(), |token| token.span.start as usize);
+        let gap = &source[end..gap_end];
+        all_tokens.extend(lexer::lex_spanned_with_trivia(&context, gap).map(|trivia| {
+            SpannedToken {
+                token: trivia.token,
+                span: Span::new(
+                    trivia.span.start as usize + end,
+                    trivia.span.end as usize + end,
+                ),
+            }
+        }));
+        if let Some(token) = token {
+            all_tokens.push(*token);
+            end = token.span.end as usize;
+        }
+    }
+    all_tokens
+}
+
+fn token_kind(token: Token) -> SyntaxKind {
+    match token {
+        Token::Whitespace => SyntaxKind::Whitespace,
+        Token::LineComment(_) | Token::BlockComment(_) => SyntaxKind::Comment,
+        Token::Identifier(_) => SyntaxKind::Ident,
+        Token::Lifetime(_) => SyntaxKind::Lifetime,
+        Token::Integer(_) => SyntaxKind::IntLiteral,
+        Token::Float(_) => SyntaxKind::FloatLiteral,
+        Token::String(_) => SyntaxKind::StringLiteral,
+        Token::Err(_) => SyntaxKind::Error,
+        token if token.is_keyword() => SyntaxKind::Keyword,
+        _ => SyntaxKind::Punct,
+    }
+}
+
+struct Lowering<'src, 'tok> {
+    source: &'src str,
+    tokens: &'tok [SpannedToken],
+    /// The index of the next token to attach.
+    position: usize,
+    /// The children of the nodes being built, from the outermost one.
+    stack: Vec<Vec<GreenElement>>,
+}
+
+impl<'src, 'tok> Lowering<'src, 'tok> {
+    fn new(source: &'src str, tokens: &'tok [SpannedToken]) -> Self {
+        Self {
+            source,
+            tokens,
+            position: 0,
+            stack: vec![],
+        }
+    }
+
+    /// Attaches the pending tokens ending before the given offset to the current node.
+    fn flush(&mut self, offset: u32) {
+        while let Some(spanned) = self.tokens.get(self.position) {
+            if spanned.span.end > offset {
+                break;
+            }
+            let text = &self.source[spanned.span.start as usize..spanned.span.end as usize];
+            let token = GreenToken::new(token_kind(spanned.token), text);
+            self.stack
+                .last_mut()
+                .expect("a node is being built")
+                .push(GreenElement::Token(Rc::new(token)));
+            self.position += 1;
+        }
+    }
+
+    fn start_node(&mut self) {
+        self.stack.push(vec![]);
+    }
+
+    fn finish_node(&mut self, kind: SyntaxKind) {
+        let children = self.stack.pop().expect("a node is being built");
+        let node = GreenNode::new(kind, children);
+        self.stack
+            .last_mut()
+            .expect("the root node is being built")
+            .push(GreenElement::Node(Rc::new(node)));
+    }
+
+    fn finish_root(mut self, kind: SyntaxKind) -> GreenNode {
+        self.flush(u32::MAX);
+        let children = self.stack.pop().expect("the root node is being built");
+        debug_assert!(self.stack.is_empty());
+        GreenNode::new(kind, children)
+    }
+
+    /// Builds a node of the given kind covering the given span, whose children are built by `f`.
+    fn node(&mut self, kind: SyntaxKind, span: Span, f: impl FnOnce(&mut Self)) {
+        self.flush(span.start);
+        self.start_node();
+        f(self);
+        self.flush(span.end);
+        self.finish_node(kind);
+    }
+
+    fn name(&mut self, ident: &Ident) {
+        self.node(SyntaxKind::Name, ident.span, |_| ());
+    }
+
+    fn name_ref(&mut self, ident: &Ident) {
+        self.node(SyntaxKind::NameRef, ident.span, |_| ());
+    }
+
+    /// Lowers the label of a loop, its `:` being attached to the loop.
+    fn label(&mut self, label: &Option<Ident>) {
+        if let Some(label) = label {
+            self.name(label);
+        }
+    }
+
+    fn path(&mut self, path: &Path) {
+        self.node(SyntaxKind::Path, path.span, |_| ());
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let kind = match &stmt.kind {
+            StmtKind::Item(item) => return self.item(item),
+            StmtKind::Let(_) => SyntaxKind::LetStmt,
+            StmtKind::Expr(_) | StmtKind::Semi(_) => SyntaxKind::ExprStmt,
+            StmtKind::Empty => SyntaxKind::EmptyStmt,
+            StmtKind::Err => SyntaxKind::ErrorStmt,
+        };
+        self.node(kind, stmt.span, |this| match &stmt.kind {
+            StmtKind::Let(local) => {
+                this.pat(&local.pat);
+                if let Some(ty) = &local.ty {
+                    this.ty(ty);
+                }
+                if let Some(init) = &local.init {
+                    this.expr(init);
+                }
+            }
+            StmtKind::Expr(expr) | StmtKind::Semi(expr) => this.expr(expr),
+            StmtKind::Item(_) | StmtKind::Empty | StmtKind::Err => (),
+        });
+    }
+
+    fn item(&mut self, item: &Item) {
+        let kind = match &item.kind {
+            ItemKind::Fn(_) => SyntaxKind::FnItem,
+            ItemKind::Struct(_) => SyntaxKind::StructItem,
+            ItemKind::Enum(_) => SyntaxKind::EnumItem,
+            ItemKind::Impl(_) => SyntaxKind::ImplItem,
+            ItemKind::Trait(_) => SyntaxKind::TraitItem,
+            ItemKind::Const(_) => SyntaxKind::ConstItem,
+        };
+        self.node(kind, item.span, |this| match &item.kind {
+            ItemKind::Fn(decl) => {
+                this.name(&decl.name);
+                if let Some(self_param) = &decl.self_param {
+                    this.node(SyntaxKind::SelfParam, self_param.span, |_| ());
+                }
+                decl.params.iter().for_each(|param| this.param(param));
+                if let Some(ret) = &decl.ret {
+                    this.ty(ret);
+                }
+                if let Some(body) = &decl.body {
+                    this.block(body);
+                }
+            }
+            ItemKind::Struct(decl) => {
+                this.name(&decl.name);
+                this.variant_fields(&decl.fields);
+            }
+            ItemKind::Enum(decl) => {
+                this.name(&decl.name);
+                for variant in &decl.variants {
+                    this.node(SyntaxKind::Variant, variant.span, |this| {
+                        this.name(&variant.name);
+                        this.variant_fields(&variant.fields);
+                    });
+                }
+            }
+            ItemKind::Impl(decl) => {
+                if let Some(trait_ref) = &decl.trait_ref {
+                    this.path(trait_ref);
+                }
+                this.ty(&decl.self_ty);
+                decl.items.iter().for_each(|item| this.item(item));
+            }
+            ItemKind::Trait(decl) => {
+                this.name(&decl.name);
+                decl.items.iter().for_each(|item| this.item(item));
+            }
+            ItemKind::Const(decl) => {
+                this.name(&decl.name);
+                this.ty(&decl.ty);
+                this.expr(&decl.expr);
+            }
+        });
+    }
+
+    fn param(&mut self, param: &Param) {
+        self.node(SyntaxKind::Param, param.span, |this| {
+            this.pat(&param.pat);
+            if let Some(ty) = &param.ty {
+                this.ty(ty);
+            }
+        });
+    }
+
+    fn variant_fields(&mut self, fields: &VariantFields) {
+        let is_tuple = matches!(fields, VariantFields::Tuple(_));
+        for field in fields.fields() {
+            self.node(SyntaxKind::FieldDecl, field.span, |this| {
+                // The names of tuple fields are their position, not present in the source
+                if !is_tuple {
+                    this.name(&field.name);
+                }
+                this.ty(&field.ty);
+            });
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.node(SyntaxKind::Block, block.span, |this| {
+            for stmt in &block.stmts {
+                this.stmt(stmt);
+            }
+        });
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let kind = match &expr.kind {
+            ExprKind::Lit(_) => SyntaxKind::LitExpr,
+            ExprKind::Path(_) => SyntaxKind::PathExpr,
+            ExprKind::Unary(..) => SyntaxKind::PrefixExpr,
+            ExprKind::AddrOf(..) => SyntaxKind::RefExpr,
+            ExprKind::Binary(..) => SyntaxKind::BinExpr,
+            ExprKind::Assign(..) | ExprKind::AssignOp(..) => SyntaxKind::AssignExpr,
+            ExprKind::Cast(..) => SyntaxKind::CastExpr,
+            ExprKind::Call(..) => SyntaxKind::CallExpr,
+            ExprKind::MethodCall(..) => SyntaxKind::MethodCallExpr,
+            ExprKind::Field(..) => SyntaxKind::FieldExpr,
+            ExprKind::Index(..) => SyntaxKind::IndexExpr,
+            ExprKind::Try(_) => SyntaxKind::TryExpr,
+            ExprKind::Tuple(_) => SyntaxKind::TupleExpr,
+            ExprKind::Array(_) => SyntaxKind::ArrayExpr,
+            ExprKind::Struct(_) => SyntaxKind::StructExpr,
+            ExprKind::Paren(_) => SyntaxKind::ParenExpr,
+            ExprKind::Block(_) => SyntaxKind::BlockExpr,
+            ExprKind::If(..) => SyntaxKind::IfExpr,
+            ExprKind::While(..) => SyntaxKind::WhileExpr,
+            ExprKind::For(..) => SyntaxKind::ForExpr,
+            ExprKind::Loop(..) => SyntaxKind::LoopExpr,
+            ExprKind::Match(..) => SyntaxKind::MatchExpr,
+            ExprKind::Range(..) => SyntaxKind::RangeExpr,
+            ExprKind::Return(_) => SyntaxKind::ReturnExpr,
+            ExprKind::Break(..) => SyntaxKind::BreakExpr,
+            ExprKind::Closure(_) => SyntaxKind::ClosureExpr,
+            ExprKind::Continue(_) => SyntaxKind::ContinueExpr,
+            ExprKind::Err => SyntaxKind::ErrorExpr,
+        };
+        self.node(kind, expr.span, |this| match &expr.kind {
+            ExprKind::Lit(_) | ExprKind::Continue(None) | ExprKind::Err => (),
+            ExprKind::Continue(Some(label)) => this.name_ref(label),
+            ExprKind::Path(path) => this.path(path),
+            ExprKind::Unary(_, operand) | ExprKind::AddrOf(_, operand) => this.expr(operand),
+            ExprKind::Binary(_, lhs, rhs)
+            | ExprKind::Assign(lhs, rhs)
+            | ExprKind::AssignOp(_, lhs, rhs)
+            | ExprKind::Index(lhs, rhs) => {
+                this.expr(lhs);
+                this.expr(rhs);
+            }
+            ExprKind::Cast(expr, ty) => {
+                this.expr(expr);
+                this.ty(ty);
+            }
+            ExprKind::Call(callee, args) => {
+                this.expr(callee);
+                args.iter().for_each(|arg| this.expr(arg));
+            }
+            ExprKind::MethodCall(receiver, name, args) => {
+                this.expr(receiver);
+                this.name_ref(name);
+                args.iter().for_each(|arg| this.expr(arg));
+            }
+            ExprKind::Field(receiver, name) => {
+                this.expr(receiver);
+                this.name_ref(name);
+            }
+            ExprKind::Tuple(exprs) | ExprKind::Array(exprs) => {
+                exprs.iter().for_each(|expr| this.expr(expr));
+            }
+            ExprKind::Struct(struct_expr) => {
+                this.path(&struct_expr.path);
+                for field in &struct_expr.fields {
+                    this.node(SyntaxKind::ExprField, field.span, |this| {
+                        if !field.is_shorthand {
+                            this.name_ref(&field.name);
+                        }
+                        this.expr(&field.expr);
+                    });
+                }
+                if let Some(rest) = &struct_expr.rest {
+                    this.expr(rest);
+                }
+            }
+            ExprKind::Paren(expr) | ExprKind::Try(expr) => this.expr(expr),
+            ExprKind::Block(block) => this.block(block),
+            ExprKind::If(cond, then, otherwise) => {
+                this.expr(cond);
+                this.block(then);
+                if let Some(otherwise) = otherwise {
+                    this.expr(otherwise);
+                }
+            }
+            ExprKind::While(cond, body, label) => {
+                this.label(label);
+                this.expr(cond);
+                this.block(body);
+            }
+            ExprKind::For(pat, iterable, body, label) => {
+                this.label(label);
+                this.pat(pat);
+                this.expr(iterable);
+                this.block(body);
+            }
+            ExprKind::Loop(body, label) => {
+                this.label(label);
+                this.block(body);
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                this.expr(scrutinee);
+                for arm in arms {
+                    this.node(SyntaxKind::MatchArm, arm.span, |this| {
+                        this.pat(&arm.pat);
+                        if let Some(guard) = &arm.guard {
+                            this.node(SyntaxKind::MatchGuard, guard.span, |this| this.expr(guard));
+                        }
+                        this.expr(&arm.body);
+                    });
+                }
+            }
+            ExprKind::Range(start, end, _) => {
+                if let Some(start) = start {
+                    this.expr(start);
+                }
+                if let Some(end) = end {
+                    this.expr(end);
+                }
+            }
+            ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    this.expr(value);
+                }
+            }
+            ExprKind::Break(label, value) => {
+                if let Some(label) = label {
+                    this.name_ref(label);
+                }
+                if let Some(value) = value {
+                    this.expr(value);
+                }
+            }
+            ExprKind::Closure(closure) => {
+                closure.params.iter().for_each(|param| this.param(param));
+                if let Some(ret) = &closure.ret {
+                    this.ty(ret);
+                }
+                this.expr(&closure.body);
+            }
+        });
+    }
+
+    fn pat(&mut self, pat: &Pattern) {
+        let kind = match &pat.kind {
+            PatKind::Wild => SyntaxKind::WildPat,
+            PatKind::Ident(..) => SyntaxKind::IdentPat,
+            PatKind::Lit(_) => SyntaxKind::LitPat,
+            PatKind::Range(..) => SyntaxKind::RangePat,
+            PatKind::Tuple(_) => SyntaxKind::TuplePat,
+            PatKind::Slice(_) => SyntaxKind::SlicePat,
+            PatKind::Rest => SyntaxKind::RestPat,
+            PatKind::Or(_) => SyntaxKind::OrPat,
+            PatKind::Path(_) => SyntaxKind::PathPat,
+            PatKind::TupleStruct(..) => SyntaxKind::TupleStructPat,
+            PatKind::Struct(..) => SyntaxKind::StructPat,
+            PatKind::Err => SyntaxKind::ErrorPat,
+        };
+        self.node(kind, pat.span, |this| match &pat.kind {
+            PatKind::Wild | PatKind::Rest | PatKind::Err => (),
+            PatKind::Ident(_, name, subpattern) => {
+                this.name(name);
+                if let Some(subpattern) = subpattern {
+                    this.pat(subpattern);
+                }
+            }
+            PatKind::Lit(expr) => this.expr(expr),
+            PatKind::Range(start, end, _) => {
+                if let Some(start) = start {
+                    this.expr(start);
+                }
+                if let Some(end) = end {
+                    this.expr(end);
+                }
+            }
+            PatKind::Tuple(pats) | PatKind::Slice(pats) | PatKind::Or(pats) => {
+                pats.iter().for_each(|pat| this.pat(pat))
+            }
+            PatKind::Path(path) => this.path(path),
+            PatKind::TupleStruct(path, pats) => {
+                this.path(path);
+                pats.iter().for_each(|pat| this.pat(pat));
+            }
+            PatKind::Struct(path, fields, _) => {
+                this.path(path);
+                for field in fields {
+                    this.node(SyntaxKind::FieldPat, field.span, |this| {
+                        if !field.is_shorthand {
+                            this.name_ref(&field.name);
+                        }
+                        this.pat(&field.pat);
+                    });
+                }
+            }
+        });
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        let kind = match &ty.kind {
+            TypeKind::Path(..) => SyntaxKind::PathType,
+            TypeKind::Tuple(_) => SyntaxKind::TupleType,
+            TypeKind::Array(_) => SyntaxKind::ArrayType,
+            TypeKind::Ref(..) => SyntaxKind::RefType,
+            TypeKind::Fn(..) => SyntaxKind::FnType,
+            TypeKind::ImplTrait(_) => SyntaxKind::ImplTraitType,
+            TypeKind::Infer => SyntaxKind::InferType,
+            TypeKind::Err => SyntaxKind::ErrorType,
+        };
+        self.node(kind, ty.span, |this| match &ty.kind {
+            TypeKind::Infer | TypeKind::Err => (),
+            TypeKind::Path(path, args) => {
+                this.path(path);
+                args.iter().for_each(|arg| this.ty(arg));
+            }
+            TypeKind::Tuple(types) => types.iter().for_each(|ty| this.ty(ty)),
+            TypeKind::Array(elem) | TypeKind::Ref(_, elem) => this.ty(elem),
+            TypeKind::Fn(params, ret) => {
+                params.iter().for_each(|param| this.ty(param));
+                if let Some(ret) = ret {
+                    this.ty(ret);
+                }
+            }
+            TypeKind::ImplTrait(bounds) => bounds.iter().for_each(|bound| this.path(bound)),
+        });
+    }
+}
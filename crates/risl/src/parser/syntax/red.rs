@@ -0,0 +1,292 @@
+//! The red tree: a view over the green tree computing positions and parent pointers on demand.
+
+use std::rc::Rc;
+
+use super::green::{GreenElement, GreenNode, GreenToken};
+use super::SyntaxKind;
+use crate::parser::lexer::Span;
+
+/// A node of the syntax tree, knowing its position in the source and its parent.
+///
+/// Red nodes are cheap to clone and created lazily while the tree is traversed.
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    parent: Option<SyntaxNode>,
+    /// The index of the node in the children of its parent.
+    index: usize,
+    offset: u32,
+}
+
+impl SyntaxNode {
+    /// Creates the root of a syntax tree from a green node.
+    pub fn new_root(green: Rc<GreenNode>) -> Self {
+        Self(Rc::new(NodeData {
+            green,
+            parent: None,
+            index: 0,
+            offset: 0,
+        }))
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.0.green.kind()
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.0.green
+    }
+
+    /// Returns the range of the source covered by the node, trivia included.
+    pub fn text_range(&self) -> Span {
+        Span::new(self.0.offset, self.0.offset + self.0.green.text_len())
+    }
+
+    /// Returns the source text of the node, trivia included.
+    pub fn text(&self) -> String {
+        self.0.green.to_string()
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        self.0.parent.clone()
+    }
+
+    /// Iterates over the node and its ancestors, up to the root.
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode> {
+        std::iter::successors(Some(self.clone()), SyntaxNode::parent)
+    }
+
+    /// Iterates over the child nodes and tokens.
+    pub fn children_with_tokens(&self) -> impl Iterator<Item = SyntaxElement> + '_ {
+        let mut offset = self.0.offset;
+        self.0
+            .green
+            .children()
+            .iter()
+            .enumerate()
+            .map(move |(index, green)| {
+                let element = self.child(index, green, offset);
+                offset += green.text_len();
+                element
+            })
+    }
+
+    /// Iterates over the child nodes, skipping the tokens.
+    pub fn children(&self) -> impl Iterator<Item = SyntaxNode> + '_ {
+        self.children_with_tokens()
+            .filter_map(SyntaxElement::into_node)
+    }
+
+    /// Iterates over the node and all its descendant nodes, in preorder.
+    pub fn descendants(&self) -> impl Iterator<Item = SyntaxNode> {
+        let mut stack = vec![self.clone()];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            let children: Vec<_> = node.children().collect();
+            stack.extend(children.into_iter().rev());
+            Some(node)
+        })
+    }
+
+    /// Iterates over all the tokens of the node, in source order.
+    pub fn tokens(&self) -> impl Iterator<Item = SyntaxToken> {
+        let mut stack = vec![SyntaxElement::Node(self.clone())];
+        std::iter::from_fn(move || loop {
+            match stack.pop()? {
+                SyntaxElement::Node(node) => {
+                    let children: Vec<_> = node.children_with_tokens().collect();
+                    stack.extend(children.into_iter().rev());
+                }
+                SyntaxElement::Token(token) => return Some(token),
+            }
+        })
+    }
+
+    /// Returns the token containing the given offset, or the last token if the offset is the end
+    /// of the node.
+    pub fn token_at_offset(&self, offset: u32) -> Option<SyntaxToken> {
+        let range = self.text_range();
+        if offset < range.start || offset > range.end {
+            return None;
+        }
+        let mut node = self.clone();
+        loop {
+            let children: Vec<_> = node
+                .children_with_tokens()
+                .filter(|child| child.text_range().end > child.text_range().start)
+                .collect();
+            let child = children
+                .iter()
+                .find(|child| offset < child.text_range().end)
+                .or(children.last())?
+                .clone();
+            match child {
+                SyntaxElement::Node(child) => node = child,
+                SyntaxElement::Token(token) => return Some(token),
+            }
+        }
+    }
+
+    /// Returns the smallest node or token fully covering the given range.
+    pub fn covering_element(&self, range: Span) -> SyntaxElement {
+        let covers = |element: &SyntaxElement| {
+            let element_range = element.text_range();
+            element_range.start <= range.start
+                && range.end <= element_range.end
+                && (element_range.start < element_range.end || range.start == range.end)
+        };
+        let mut element = SyntaxElement::Node(self.clone());
+        while let SyntaxElement::Node(node) = &element {
+            // Prefer non-empty children for empty ranges at the boundary of two elements
+            let child = node
+                .children_with_tokens()
+                .filter(covers)
+                .max_by_key(|child| child.text_range().end > child.text_range().start);
+            match child {
+                Some(child) => element = child,
+                None => break,
+            }
+        }
+        element
+    }
+
+    /// Returns the green root of a new tree, where this node is replaced by the given one.
+    ///
+    /// Only the ancestors of the node are copied, the rest of the tree is shared.
+    pub fn replace_with(&self, replacement: Rc<GreenNode>) -> Rc<GreenNode> {
+        match &self.0.parent {
+            None => replacement,
+            Some(parent) => {
+                let green = parent
+                    .green()
+                    .replace_child(self.0.index, GreenElement::Node(replacement));
+                parent.replace_with(Rc::new(green))
+            }
+        }
+    }
+
+    fn child(&self, index: usize, green: &GreenElement, offset: u32) -> SyntaxElement {
+        match green {
+            GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                green: green.clone(),
+                parent: Some(self.clone()),
+                index,
+                offset,
+            }))),
+            GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                green: green.clone(),
+                parent: self.clone(),
+                index,
+                offset,
+            }),
+        }
+    }
+}
+
+impl PartialEq for SyntaxNode {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0.green, &other.0.green) && self.0.offset == other.0.offset
+    }
+}
+
+impl std::fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let range = self.text_range();
+        write!(f, "{:?}@{}..{}", self.kind(), range.start, range.end)
+    }
+}
+
+impl std::fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0.green)
+    }
+}
+
+/// A token of the syntax tree, knowing its position in the source and its parent.
+#[derive(Clone, PartialEq)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    parent: SyntaxNode,
+    index: usize,
+    offset: u32,
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> SyntaxKind {
+        self.green.kind()
+    }
+
+    pub fn text(&self) -> &str {
+        self.green.text()
+    }
+
+    pub fn text_range(&self) -> Span {
+        Span::new(self.offset, self.offset + self.green.text_len())
+    }
+
+    pub fn parent(&self) -> SyntaxNode {
+        self.parent.clone()
+    }
+
+    /// Returns the green root of a new tree, where this token is replaced by the given one.
+    pub fn replace_with(&self, replacement: GreenToken) -> Rc<GreenNode> {
+        let green = self
+            .parent
+            .green()
+            .replace_child(self.index, GreenElement::Token(Rc::new(replacement)));
+        self.parent.replace_with(Rc::new(green))
+    }
+}
+
+impl std::fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let range = self.text_range();
+        write!(
+            f,
+            "{:?}@{}..{} {:?}",
+            self.kind(),
+            range.start,
+            range.end,
+            self.text()
+        )
+    }
+}
+
+/// A node or a token of the syntax tree.
+#[derive(Clone, PartialEq, Debug)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxElement {
+    pub fn kind(&self) -> SyntaxKind {
+        match self {
+            SyntaxElement::Node(node) => node.kind(),
+            SyntaxElement::Token(token) => token.kind(),
+        }
+    }
+
+    pub fn text_range(&self) -> Span {
+        match self {
+            SyntaxElement::Node(node) => node.text_range(),
+            SyntaxElement::Token(token) => token.text_range(),
+        }
+    }
+
+    pub fn into_node(self) -> Option<SyntaxNode> {
+        match self {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        }
+    }
+
+    pub fn into_token(self) -> Option<SyntaxToken> {
+        match self {
+            SyntaxElement::Node(_) => None,
+            SyntaxElement::Token(token) => Some(token),
+        }
+    }
+}
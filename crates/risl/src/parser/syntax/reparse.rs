@@ -0,0 +1,143 @@
+//! Incremental reparsing of a syntax tree after an edit of its source.
+//!
+//! Only the smallest part of the tree affected by the edit is rebuilt, the rest of the green tree
+//! being shared with the original one:
+//! - an edit inside an identifier, a comment or a whitespace keeping a single token of the same
+//!   kind only replaces that token,
+//! - an edit inside the braces of a block keeping them balanced only reparses that block,
+//! - any other edit reparses the whole source.
+
+use std::rc::Rc;
+
+use super::green::GreenToken;
+use super::{lower, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::{DiagContext, Diagnostic};
+use crate::parser::emitter::new_emitter_none;
+use crate::parser::lexer::{self, Span, Token};
+use crate::parser::parse;
+
+/// An edit of the source: the text of a range is replaced by a new text.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Edit {
+    pub delete: Span,
+    pub insert: String,
+}
+
+impl Edit {
+    pub fn new(delete: Span, insert: impl Into<String>) -> Self {
+        Self {
+            delete,
+            insert: insert.into(),
+        }
+    }
+
+    /// Returns the given text with the edit applied, the edit range being relative to `offset`.
+    fn apply_at(&self, text: &str, offset: u32) -> String {
+        let start = (self.delete.start - offset) as usize;
+        let end = (self.delete.end - offset) as usize;
+        format!("{}{}{}", &text[..start], self.insert, &text[end..])
+    }
+
+    /// Returns the given text with the edit applied.
+    pub fn apply(&self, text: &str) -> String {
+        self.apply_at(text, 0)
+    }
+}
+
+/// Returns the syntax tree of the source of the given tree once edited.
+///
+/// Syntax errors are reported through the diagnostic context of the parse context, but only for
+/// the reparsed part of the tree.
+pub fn reparse(context: &ParseContext, root: &SyntaxNode, edit: &Edit) -> SyntaxNode {
+    if let Some(green) = reparse_token(root, edit) {
+        return SyntaxNode::new_root(green);
+    }
+    if let Some(green) = reparse_block(context, root, edit) {
+        return SyntaxNode::new_root(green);
+    }
+    super::parse(context, &edit.apply(&root.text()))
+}
+
+/// Returns true if the edit range is inside the given range, boundaries included.
+fn is_inside(edit: &Edit, range: Span) -> bool {
+    range.start <= edit.delete.start && edit.delete.end <= range.end
+}
+
+fn reparse_token(root: &SyntaxNode, edit: &Edit) -> Option<Rc<super::GreenNode>> {
+    // An insertion between two tokens can extend any of them
+    let mut candidates = vec![root.token_at_offset(edit.delete.start)?];
+    if edit.delete.start > 0 {
+        candidates.extend(root.token_at_offset(edit.delete.start - 1));
+    }
+    candidates
+        .into_iter()
+        .filter(|token| is_inside(edit, token.text_range()))
+        .find_map(|token| {
+            let text = edit.apply_at(token.text(), token.text_range().start);
+            is_same_single_token(&token, &text)
+                .then(|| token.replace_with(GreenToken::new(token.kind(), text)))
+        })
+}
+
+/// Returns true if the given text lexes to a single token of the same kind as the given token,
+/// whose change cannot modify the structure of the tree.
+fn is_same_single_token(token: &SyntaxToken, text: &str) -> bool {
+    if !matches!(
+        token.kind(),
+        SyntaxKind::Ident | SyntaxKind::Comment | SyntaxKind::Whitespace
+    ) {
+        return false;
+    }
+    let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+    let tokens: Vec<_> = lexer::lex_spanned_with_trivia(&context, text).collect();
+    let [spanned] = tokens[..] else {
+        return false;
+    };
+    let same_kind = match spanned.token {
+        Token::Identifier(_) => token.kind() == SyntaxKind::Ident && Token::keyword(text).is_none(),
+        Token::LineComment(_) | Token::BlockComment(_) => token.kind() == SyntaxKind::Comment,
+        // Newlines matter when recovering from a missing `;`
+        Token::Whitespace => {
+            token.kind() == SyntaxKind::Whitespace
+                && token.text().contains('\n') == text.contains('\n')
+        }
+        _ => false,
+    };
+    same_kind && !context.diag_ctx().has_errors()
+}
+
+fn reparse_block(
+    context: &ParseContext,
+    root: &SyntaxNode,
+    edit: &Edit,
+) -> Option<Rc<super::GreenNode>> {
+    let covering = match root.covering_element(edit.delete) {
+        SyntaxElement::Node(node) => node,
+        SyntaxElement::Token(token) => token.parent(),
+    };
+    // The edit must not touch the braces of the block
+    let block = covering.ancestors().find(|node| {
+        let range = node.text_range();
+        node.kind() == SyntaxKind::Block
+            && range.start < edit.delete.start
+            && edit.delete.end < range.end
+    })?;
+    let offset = block.text_range().start;
+    let text = edit.apply_at(&block.text(), offset);
+
+    let block_context = ParseContext::new(DiagContext::new(new_emitter_none()));
+    let (ast, tokens) = parse::parse_block_with_tokens(&block_context, &text)?;
+    let tokens = lower::with_trivia(&text, &tokens);
+    let green = lower::lower_block(&text, &tokens, &ast);
+
+    for diagnostic in block_context.diag_ctx().diagnostics() {
+        context.diag_ctx().emit(Diagnostic {
+            span: diagnostic
+                .span
+                .map(|span| Span::new(span.start + offset, span.end + offset)),
+            ..diagnostic
+        });
+    }
+    Some(block.replace_with(Rc::new(green)))
+}
@@ -0,0 +1,205 @@
+use super::typed::{self, AstNode};
+use super::*;
+use crate::parser::diagnostic::DiagContext;
+use crate::parser::emitter::new_emitter_none;
+use crate::parser::lexer::Span;
+
+fn stubbed_parse_context() -> ParseContext {
+    ParseContext::new(DiagContext::new(new_emitter_none()))
+}
+
+fn parse_syntax(source: &str) -> SyntaxNode {
+    parse(&stubbed_parse_context(), source)
+}
+
+/// Renders the tree with one line per node or non-trivia token.
+fn dump(node: &SyntaxNode) -> String {
+    fn dump_node(node: &SyntaxNode, depth: usize, out: &mut String) {
+        out.push_str(&format!("{}{node:?}\n", "  ".repeat(depth)));
+        for child in node.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(child) => dump_node(&child, depth + 1, out),
+                SyntaxElement::Token(token) if !token.kind().is_trivia() => {
+                    out.push_str(&format!("{}{token:?}\n", "  ".repeat(depth + 1)));
+                }
+                SyntaxElement::Token(_) => (),
+            }
+        }
+    }
+    let mut out = String::new();
+    dump_node(node, 0, &mut out);
+    out
+}
+
+const SOURCES: &[&str] = &[
+    "",
+    "  // only a comment\n",
+    "fn main() {\n    let x = 1 + 2 * 3; /* inline */ print(x);\n}\n",
+    "pub struct Point { pub x: f64, y: f64 }\nenum Shape { Circle(f64), Empty }",
+    "let p = Point { x, y: 2 }; match p { Point { x, .. } => x, _ => 0 }",
+    "let v: Vec<Vec<i64>> = [a.0.1, b[2], -c as f64, &mut d];",
+    "if a { b } else if c { d } else { e } while x < 10 { x += 1; } for i in 0..=3 {}",
+    "let a = 1 2 3; } fn g( { $$ let",
+    "let s = \"unterminated",
+];
+
+#[test]
+fn lossless() {
+    for source in SOURCES {
+        let root = parse_syntax(source);
+        assert_eq!(root.text(), *source);
+        assert_eq!(root.text_range(), Span::new(0, source.len()));
+        let tokens: String = root
+            .tokens()
+            .map(|token| String::from(token.text()))
+            .collect();
+        assert_eq!(tokens, *source);
+    }
+}
+
+#[test]
+fn tree_structure() {
+    let root = parse_syntax("fn f(a: i64) { a + 1 } // done");
+    assert_eq!(
+        dump(&root),
+        "\
+SourceFile@0..30
+  FnItem@0..22
+    Keyword@0..2 \"fn\"
+    Name@3..4
+      Ident@3..4 \"f\"
+    Punct@4..5 \"(\"
+    Param@5..11
+      IdentPat@5..6
+        Name@5..6
+          Ident@5..6 \"a\"
+      Punct@6..7 \":\"
+      PathType@8..11
+        Path@8..11
+          Ident@8..11 \"i64\"
+    Punct@11..12 \")\"
+    Block@13..22
+      Punct@13..14 \"{\"
+      ExprStmt@15..20
+        BinExpr@15..20
+          PathExpr@15..16
+            Path@15..16
+              Ident@15..16 \"a\"
+          Punct@17..18 \"+\"
+          LitExpr@19..20
+            IntLiteral@19..20 \"1\"
+      Punct@21..22 \"}\"
+"
+    );
+}
+
+#[test]
+fn parent_pointers_and_offsets() {
+    let root = parse_syntax("let a = f(1);");
+    let token = root.token_at_offset(10).unwrap();
+    assert_eq!(token.text(), "1");
+    assert_eq!(token.text_range(), Span::new(10, 11));
+    let kinds: Vec<_> = token.parent().ancestors().map(|node| node.kind()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            SyntaxKind::LitExpr,
+            SyntaxKind::CallExpr,
+            SyntaxKind::LetStmt,
+            SyntaxKind::SourceFile,
+        ]
+    );
+    assert_eq!(root.token_at_offset(13).unwrap().text(), ";");
+    assert_eq!(root.token_at_offset(14), None);
+}
+
+#[test]
+fn covering_element() {
+    let root = parse_syntax("let a = f(1, 2);");
+    let element = root.covering_element(Span::new(10, 14));
+    assert_eq!(element.kind(), SyntaxKind::CallExpr);
+    let element = root.covering_element(Span::new(4, 5));
+    assert_eq!(element.kind(), SyntaxKind::Ident);
+    assert_eq!(element.text_range(), Span::new(4, 5));
+}
+
+#[test]
+fn typed_views() {
+    let root = parse_syntax("fn add(a: i64, b) -> i64 { let mut c = a; c + b }");
+    let file = typed::SourceFile::cast(root).unwrap();
+    let item = file.items().next().unwrap();
+    let func = typed::FnItem::cast(item.syntax().clone()).unwrap();
+    assert_eq!(func.name().unwrap().text(), "add");
+    assert_eq!(func.params().count(), 2);
+    assert!(func.params().nth(1).unwrap().ty().is_none());
+    assert_eq!(func.ret_type().unwrap().syntax().text(), "i64");
+
+    let body = func.body().unwrap();
+    let stmts: Vec<_> = body.stmts().collect();
+    let local = typed::LetStmt::cast(stmts[0].syntax().clone()).unwrap();
+    let pat = typed::IdentPat::cast(local.pat().unwrap().syntax().clone()).unwrap();
+    assert!(pat.is_mut());
+    assert_eq!(pat.name().unwrap().text(), "c");
+    assert_eq!(local.init().unwrap().syntax().text(), "a");
+
+    let tail = typed::BinExpr::cast(body.tail_expr().unwrap().syntax().clone()).unwrap();
+    assert_eq!(tail.op_token().unwrap().text(), "+");
+    assert_eq!(tail.lhs().unwrap().syntax().text(), "c");
+    assert_eq!(tail.rhs().unwrap().syntax().text(), "b");
+    assert!(typed::BinExpr::cast(func.syntax().clone()).is_none());
+}
+
+/// Asserts that reparsing after the edit gives the same tree as parsing the edited source.
+fn check_reparse(source: &str, edit: Edit) -> (SyntaxNode, SyntaxNode) {
+    let root = parse_syntax(source);
+    let reparsed = reparse(&stubbed_parse_context(), &root, &edit);
+    let expected = parse_syntax(&edit.apply(source));
+    assert_eq!(reparsed.green(), expected.green());
+    (root, reparsed)
+}
+
+/// Returns true if the first top-level nodes of both trees share the same green node.
+fn shares_first_item(old: &SyntaxNode, new: &SyntaxNode) -> bool {
+    let old = old.children().next().unwrap();
+    let new = new.children().next().unwrap();
+    Rc::ptr_eq(old.green(), new.green())
+}
+
+#[test]
+fn reparse_token() {
+    let source = "fn f() {}\nfn g() { let abc = 1; // note\n}";
+    let (old, new) = check_reparse(source, Edit::new(Span::new(26, 26), "d"));
+    assert!(shares_first_item(&old, &new));
+    assert_eq!(new.token_at_offset(23).unwrap().text(), "abcd");
+    check_reparse(source, Edit::new(Span::new(36, 37), "remark"));
+    check_reparse(source, Edit::new(Span::new(9, 10), "\n\n"));
+}
+
+#[test]
+fn reparse_block() {
+    let source = "fn f() {}\nfn g() { let a = 1; }";
+    let (old, new) = check_reparse(source, Edit::new(Span::new(27, 28), "2 + 3; a"));
+    assert!(shares_first_item(&old, &new));
+    // Keywords change the structure, identifiers turned into keywords reparse the block
+    check_reparse(source, Edit::new(Span::new(23, 24), "let"));
+    check_reparse(source, Edit::new(Span::new(19, 19), "{ { }"));
+}
+
+#[test]
+fn reparse_whole_file() {
+    let source = "fn f() {}\nfn g() { let a = 1; }";
+    check_reparse(source, Edit::new(Span::new(0, 2), "struct"));
+    check_reparse(source, Edit::new(Span::new(19, 19), "}"));
+    check_reparse(source, Edit::new(Span::new(30, 31), ""));
+}
+
+#[test]
+fn reparse_reports_errors_at_source_offsets() {
+    let source = "fn f() {}\nfn g() { let a = 1; }";
+    let root = parse_syntax(source);
+    let context = stubbed_parse_context();
+    reparse(&context, &root, &Edit::new(Span::new(27, 28), "*"));
+    let diagnostics = context.diag_ctx().diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].span, Some(Span::new(28, 29)));
+}
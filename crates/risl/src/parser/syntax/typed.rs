@@ -0,0 +1,522 @@
+//! Typed views over the syntax nodes, giving access to their meaningful children.
+//!
+//! A view is a syntax node whose kind was checked, obtained with [`AstNode::cast`]. The accessors
+//! return `None` when the corresponding child is missing, which can happen in erroneous programs.
+
+use super::{SyntaxKind, SyntaxNode, SyntaxToken};
+
+/// A typed view over a syntax node.
+pub trait AstNode: Sized {
+    /// Returns true if nodes of the given kind can be viewed as this type.
+    fn can_cast(kind: SyntaxKind) -> bool;
+
+    /// Views the given node as this type, if its kind allows it.
+    fn cast(node: SyntaxNode) -> Option<Self>;
+
+    /// Returns the underlying syntax node.
+    fn syntax(&self) -> &SyntaxNode;
+}
+
+macro_rules! ast_nodes {
+    ($($(#[$meta:meta])* $name:ident => $can_cast:expr;)*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Clone, PartialEq, Debug)]
+            pub struct $name(SyntaxNode);
+
+            impl AstNode for $name {
+                fn can_cast(kind: SyntaxKind) -> bool {
+                    let can_cast: fn(SyntaxKind) -> bool = $can_cast;
+                    can_cast(kind)
+                }
+
+                fn cast(node: SyntaxNode) -> Option<Self> {
+                    Self::can_cast(node.kind()).then(|| Self(node))
+                }
+
+                fn syntax(&self) -> &SyntaxNode {
+                    &self.0
+                }
+            }
+        )*
+    };
+}
+
+ast_nodes! {
+    SourceFile => |kind| kind == SyntaxKind::SourceFile;
+    Attr => |kind| kind == SyntaxKind::Attr;
+    /// Any item.
+    Item => SyntaxKind::is_item;
+    FnItem => |kind| kind == SyntaxKind::FnItem;
+    StructItem => |kind| kind == SyntaxKind::StructItem;
+    EnumItem => |kind| kind == SyntaxKind::EnumItem;
+    ImplItem => |kind| kind == SyntaxKind::ImplItem;
+    TraitItem => |kind| kind == SyntaxKind::TraitItem;
+    ConstItem => |kind| kind == SyntaxKind::ConstItem;
+    SelfParam => |kind| kind == SyntaxKind::SelfParam;
+    Param => |kind| kind == SyntaxKind::Param;
+    Variant => |kind| kind == SyntaxKind::Variant;
+    FieldDecl => |kind| kind == SyntaxKind::FieldDecl;
+    Block => |kind| kind == SyntaxKind::Block;
+    /// Any statement, items included.
+    Stmt => SyntaxKind::is_stmt;
+    LetStmt => |kind| kind == SyntaxKind::LetStmt;
+    ExprStmt => |kind| kind == SyntaxKind::ExprStmt;
+    Name => |kind| kind == SyntaxKind::Name;
+    NameRef => |kind| kind == SyntaxKind::NameRef;
+    Path => |kind| kind == SyntaxKind::Path;
+    /// Any expression.
+    Expr => SyntaxKind::is_expr;
+    LitExpr => |kind| kind == SyntaxKind::LitExpr;
+    PathExpr => |kind| kind == SyntaxKind::PathExpr;
+    PrefixExpr => |kind| kind == SyntaxKind::PrefixExpr;
+    BinExpr => |kind| kind == SyntaxKind::BinExpr;
+    CallExpr => |kind| kind == SyntaxKind::CallExpr;
+    MethodCallExpr => |kind| kind == SyntaxKind::MethodCallExpr;
+    FieldExpr => |kind| kind == SyntaxKind::FieldExpr;
+    IfExpr => |kind| kind == SyntaxKind::IfExpr;
+    WhileExpr => |kind| kind == SyntaxKind::WhileExpr;
+    ForExpr => |kind| kind == SyntaxKind::ForExpr;
+    LoopExpr => |kind| kind == SyntaxKind::LoopExpr;
+    MatchExpr => |kind| kind == SyntaxKind::MatchExpr;
+    MatchArm => |kind| kind == SyntaxKind::MatchArm;
+    MatchGuard => |kind| kind == SyntaxKind::MatchGuard;
+    ClosureExpr => |kind| kind == SyntaxKind::ClosureExpr;
+    /// Any pattern.
+    Pat => SyntaxKind::is_pat;
+    IdentPat => |kind| kind == SyntaxKind::IdentPat;
+    /// Any type.
+    Type => SyntaxKind::is_type;
+}
+
+/// Returns the first child of the node of the given view type.
+fn child<N: AstNode>(node: &SyntaxNode) -> Option<N> {
+    node.children().find_map(N::cast)
+}
+
+/// Returns the n-th child of the node of the given view type.
+fn nth_child<N: AstNode>(node: &SyntaxNode, n: usize) -> Option<N> {
+    node.children().filter_map(N::cast).nth(n)
+}
+
+/// Iterates over the children of the node of the given view type.
+fn children<'a, N: AstNode + 'a>(node: &'a SyntaxNode) -> impl Iterator<Item = N> + 'a {
+    node.children().filter_map(N::cast)
+}
+
+/// Returns the first token of the node with the given kind and text, not looking into children.
+fn token(node: &SyntaxNode, kind: SyntaxKind, text: &str) -> Option<SyntaxToken> {
+    node.children_with_tokens()
+        .filter_map(|element| element.into_token())
+        .find(|token| token.kind() == kind && token.text() == text)
+}
+
+/// Returns the first non-trivia token of the node, not looking into children.
+fn first_significant_token(node: &SyntaxNode) -> Option<SyntaxToken> {
+    node.children_with_tokens()
+        .filter_map(|element| element.into_token())
+        .find(|token| !token.kind().is_trivia())
+}
+
+impl SourceFile {
+    pub fn attrs(&self) -> impl Iterator<Item = Attr> + '_ {
+        children(&self.0)
+    }
+
+    pub fn stmts(&self) -> impl Iterator<Item = Stmt> + '_ {
+        children(&self.0)
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = Item> + '_ {
+        children(&self.0)
+    }
+}
+
+impl Attr {
+    pub fn name(&self) -> Option<Name> {
+        child(&self.0)
+    }
+}
+
+impl Item {
+    pub fn name(&self) -> Option<Name> {
+        child(&self.0)
+    }
+
+    pub fn is_pub(&self) -> bool {
+        token(&self.0, SyntaxKind::Keyword, "pub").is_some()
+    }
+}
+
+impl FnItem {
+    pub fn name(&self) -> Option<Name> {
+        child(&self.0)
+    }
+
+    pub fn self_param(&self) -> Option<SelfParam> {
+        child(&self.0)
+    }
+
+    pub fn params(&self) -> impl Iterator<Item = Param> + '_ {
+        children(&self.0)
+    }
+
+    pub fn ret_type(&self) -> Option<Type> {
+        child(&self.0)
+    }
+
+    pub fn body(&self) -> Option<Block> {
+        child(&self.0)
+    }
+
+    pub fn is_const(&self) -> bool {
+        token(&self.0, SyntaxKind::Keyword, "const").is_some()
+    }
+}
+
+impl SelfParam {
+    pub fn is_ref(&self) -> bool {
+        token(&self.0, SyntaxKind::Punct, "&").is_some()
+    }
+
+    pub fn is_mut(&self) -> bool {
+        token(&self.0, SyntaxKind::Keyword, "mut").is_some()
+    }
+}
+
+impl StructItem {
+    pub fn name(&self) -> Option<Name> {
+        child(&self.0)
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = FieldDecl> + '_ {
+        children(&self.0)
+    }
+}
+
+impl EnumItem {
+    pub fn name(&self) -> Option<Name> {
+        child(&self.0)
+    }
+
+    pub fn variants(&self) -> impl Iterator<Item = Variant> + '_ {
+        children(&self.0)
+    }
+}
+
+impl ImplItem {
+    /// Returns the implemented trait, for `impl Trait for Type` blocks.
+    pub fn trait_ref(&self) -> Option<Path> {
+        child(&self.0)
+    }
+
+    pub fn self_ty(&self) -> Option<Type> {
+        child(&self.0)
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = FnItem> + '_ {
+        children(&self.0)
+    }
+}
+
+impl TraitItem {
+    pub fn name(&self) -> Option<Name> {
+        child(&self.0)
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = FnItem> + '_ {
+        children(&self.0)
+    }
+}
+
+impl ConstItem {
+    pub fn name(&self) -> Option<Name> {
+        child(&self.0)
+    }
+
+    pub fn ty(&self) -> Option<Type> {
+        child(&self.0)
+    }
+
+    pub fn expr(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+}
+
+impl Param {
+    pub fn pat(&self) -> Option<Pat> {
+        child(&self.0)
+    }
+
+    pub fn ty(&self) -> Option<Type> {
+        child(&self.0)
+    }
+}
+
+impl Variant {
+    pub fn name(&self) -> Option<Name> {
+        child(&self.0)
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = FieldDecl> + '_ {
+        children(&self.0)
+    }
+}
+
+impl FieldDecl {
+    /// Returns the name of the field, absent for the fields of tuple structs and variants.
+    pub fn name(&self) -> Option<Name> {
+        child(&self.0)
+    }
+
+    pub fn ty(&self) -> Option<Type> {
+        child(&self.0)
+    }
+}
+
+impl Block {
+    pub fn stmts(&self) -> impl Iterator<Item = Stmt> + '_ {
+        children(&self.0)
+    }
+
+    /// Returns the expression whose value is the value of the block, if any.
+    pub fn tail_expr(&self) -> Option<Expr> {
+        let stmt = ExprStmt::cast(self.stmts().last()?.0)?;
+        if stmt.has_semicolon() {
+            return None;
+        }
+        stmt.expr()
+    }
+}
+
+impl LetStmt {
+    pub fn pat(&self) -> Option<Pat> {
+        child(&self.0)
+    }
+
+    pub fn ty(&self) -> Option<Type> {
+        child(&self.0)
+    }
+
+    pub fn init(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+}
+
+impl ExprStmt {
+    pub fn expr(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+
+    pub fn has_semicolon(&self) -> bool {
+        token(&self.0, SyntaxKind::Punct, ";").is_some()
+    }
+}
+
+impl Name {
+    pub fn text(&self) -> String {
+        self.0.text()
+    }
+}
+
+impl NameRef {
+    pub fn text(&self) -> String {
+        self.0.text()
+    }
+}
+
+impl Path {
+    /// Returns the names of the segments of the path.
+    pub fn segments(&self) -> Vec<String> {
+        self.0
+            .tokens()
+            .filter(|token| matches!(token.kind(), SyntaxKind::Ident | SyntaxKind::Keyword))
+            .map(|token| String::from(token.text()))
+            .collect()
+    }
+}
+
+impl LitExpr {
+    pub fn token(&self) -> Option<SyntaxToken> {
+        first_significant_token(&self.0)
+    }
+}
+
+impl PathExpr {
+    pub fn path(&self) -> Option<Path> {
+        child(&self.0)
+    }
+}
+
+impl PrefixExpr {
+    pub fn op_token(&self) -> Option<SyntaxToken> {
+        first_significant_token(&self.0)
+    }
+
+    pub fn operand(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+}
+
+impl BinExpr {
+    pub fn lhs(&self) -> Option<Expr> {
+        nth_child(&self.0, 0)
+    }
+
+    pub fn rhs(&self) -> Option<Expr> {
+        nth_child(&self.0, 1)
+    }
+
+    pub fn op_token(&self) -> Option<SyntaxToken> {
+        first_significant_token(&self.0)
+    }
+}
+
+impl CallExpr {
+    pub fn callee(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+
+    pub fn args(&self) -> impl Iterator<Item = Expr> + '_ {
+        children(&self.0).skip(1)
+    }
+}
+
+impl MethodCallExpr {
+    pub fn receiver(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+
+    pub fn name_ref(&self) -> Option<NameRef> {
+        child(&self.0)
+    }
+
+    pub fn args(&self) -> impl Iterator<Item = Expr> + '_ {
+        children(&self.0).skip(1)
+    }
+}
+
+impl FieldExpr {
+    pub fn receiver(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+
+    pub fn name_ref(&self) -> Option<NameRef> {
+        child(&self.0)
+    }
+}
+
+impl IfExpr {
+    pub fn condition(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+
+    pub fn then_branch(&self) -> Option<Block> {
+        child(&self.0)
+    }
+
+    /// Returns the `else` branch, either a block expression or another `if` expression.
+    pub fn else_branch(&self) -> Option<Expr> {
+        nth_child(&self.0, 1)
+    }
+}
+
+impl WhileExpr {
+    pub fn label(&self) -> Option<Name> {
+        child(&self.0)
+    }
+
+    pub fn condition(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+
+    pub fn body(&self) -> Option<Block> {
+        child(&self.0)
+    }
+}
+
+impl ForExpr {
+    pub fn label(&self) -> Option<Name> {
+        child(&self.0)
+    }
+
+    pub fn pat(&self) -> Option<Pat> {
+        child(&self.0)
+    }
+
+    pub fn iterable(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+
+    pub fn body(&self) -> Option<Block> {
+        child(&self.0)
+    }
+}
+
+impl LoopExpr {
+    pub fn label(&self) -> Option<Name> {
+        child(&self.0)
+    }
+
+    pub fn body(&self) -> Option<Block> {
+        child(&self.0)
+    }
+}
+
+impl MatchExpr {
+    pub fn scrutinee(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+
+    pub fn arms(&self) -> impl Iterator<Item = MatchArm> + '_ {
+        children(&self.0)
+    }
+}
+
+impl MatchArm {
+    pub fn pat(&self) -> Option<Pat> {
+        child(&self.0)
+    }
+
+    pub fn guard(&self) -> Option<MatchGuard> {
+        child(&self.0)
+    }
+
+    pub fn body(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+}
+
+impl MatchGuard {
+    pub fn condition(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+}
+
+impl ClosureExpr {
+    pub fn is_move(&self) -> bool {
+        token(&self.0, SyntaxKind::Keyword, "move").is_some()
+    }
+
+    pub fn params(&self) -> impl Iterator<Item = Param> + '_ {
+        children(&self.0)
+    }
+
+    pub fn ret_type(&self) -> Option<Type> {
+        child(&self.0)
+    }
+
+    pub fn body(&self) -> Option<Expr> {
+        child(&self.0)
+    }
+}
+
+impl IdentPat {
+    pub fn name(&self) -> Option<Name> {
+        child(&self.0)
+    }
+
+    pub fn is_mut(&self) -> bool {
+        token(&self.0, SyntaxKind::Keyword, "mut").is_some()
+    }
+}
@@ -0,0 +1,439 @@
+//! A read-only walk over the AST.
+//!
+//! Each overridable method of the [`Visitor`] trait is a hook to be called when visiting the
+//! corresponding node. Their default implementation calls the corresponding `walk_*` function,
+//! which visits the children of the node. A pass overriding a method must call the `walk_*`
+//! function itself to keep visiting the children of the node. See [`super::mut_visit`] for the
+//! mutable counterpart.
+
+use super::ast::*;
+
+pub trait Visitor<'ast>: Sized {
+    fn visit_program(&mut self, program: &'ast Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_local(&mut self, local: &'ast Local) {
+        walk_local(self, local)
+    }
+
+    fn visit_item(&mut self, item: &'ast Item) {
+        walk_item(self, item)
+    }
+
+    fn visit_fn_decl(&mut self, decl: &'ast FnDecl) {
+        walk_fn_decl(self, decl)
+    }
+
+    fn visit_self_param(&mut self, _param: &'ast SelfParam) {}
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        walk_param(self, param)
+    }
+
+    fn visit_variant(&mut self, variant: &'ast Variant) {
+        walk_variant(self, variant)
+    }
+
+    fn visit_variant_fields(&mut self, fields: &'ast VariantFields) {
+        walk_variant_fields(self, fields)
+    }
+
+    fn visit_field_decl(&mut self, field: &'ast FieldDecl) {
+        walk_field_decl(self, field)
+    }
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        walk_block(self, block)
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_expr_field(&mut self, field: &'ast ExprField) {
+        walk_expr_field(self, field)
+    }
+
+    fn visit_closure(&mut self, closure: &'ast Closure) {
+        walk_closure(self, closure)
+    }
+
+    fn visit_arm(&mut self, arm: &'ast Arm) {
+        walk_arm(self, arm)
+    }
+
+    fn visit_lit(&mut self, _lit: &'ast Lit) {}
+
+    fn visit_pat(&mut self, pat: &'ast Pattern) {
+        walk_pat(self, pat)
+    }
+
+    fn visit_field_pat(&mut self, field: &'ast FieldPat) {
+        walk_field_pat(self, field)
+    }
+
+    fn visit_ty(&mut self, ty: &'ast Type) {
+        walk_ty(self, ty)
+    }
+
+    fn visit_path(&mut self, path: &'ast Path) {
+        walk_path(self, path)
+    }
+
+    fn visit_ident(&mut self, _ident: &'ast Ident) {}
+}
+
+pub fn walk_program<'a, V: Visitor<'a>>(visitor: &mut V, program: &'a Program) {
+    for stmt in &program.stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<'a, V: Visitor<'a>>(visitor: &mut V, stmt: &'a Stmt) {
+    match &stmt.kind {
+        StmtKind::Let(local) => visitor.visit_local(local),
+        StmtKind::Item(item) => visitor.visit_item(item),
+        StmtKind::Expr(expr) | StmtKind::Semi(expr) => visitor.visit_expr(expr),
+        StmtKind::Empty | StmtKind::Err => (),
+    }
+}
+
+pub fn walk_local<'a, V: Visitor<'a>>(visitor: &mut V, local: &'a Local) {
+    visitor.visit_pat(&local.pat);
+    if let Some(ty) = &local.ty {
+        visitor.visit_ty(ty);
+    }
+    if let Some(init) = &local.init {
+        visitor.visit_expr(init);
+    }
+}
+
+pub fn walk_item<'a, V: Visitor<'a>>(visitor: &mut V, item: &'a Item) {
+    match &item.kind {
+        ItemKind::Fn(decl) => visitor.visit_fn_decl(decl),
+        ItemKind::Struct(decl) => {
+            visitor.visit_ident(&decl.name);
+            visitor.visit_variant_fields(&decl.fields);
+        }
+        ItemKind::Enum(decl) => {
+            visitor.visit_ident(&decl.name);
+            for variant in &decl.variants {
+                visitor.visit_variant(variant);
+            }
+        }
+        ItemKind::Impl(decl) => {
+            if let Some(trait_ref) = &decl.trait_ref {
+                visitor.visit_path(trait_ref);
+            }
+            visitor.visit_ty(&decl.self_ty);
+            for item in &decl.items {
+                visitor.visit_item(item);
+            }
+        }
+        ItemKind::Trait(decl) => {
+            visitor.visit_ident(&decl.name);
+            for item in &decl.items {
+                visitor.visit_item(item);
+            }
+        }
+        ItemKind::Const(decl) => {
+            visitor.visit_ident(&decl.name);
+            visitor.visit_ty(&decl.ty);
+            visitor.visit_expr(&decl.expr);
+        }
+    }
+}
+
+pub fn walk_fn_decl<'a, V: Visitor<'a>>(visitor: &mut V, decl: &'a FnDecl) {
+    visitor.visit_ident(&decl.name);
+    if let Some(self_param) = &decl.self_param {
+        visitor.visit_self_param(self_param);
+    }
+    for param in &decl.params {
+        visitor.visit_param(param);
+    }
+    if let Some(ret) = &decl.ret {
+        visitor.visit_ty(ret);
+    }
+    if let Some(body) = &decl.body {
+        visitor.visit_block(body);
+    }
+}
+
+pub fn walk_param<'a, V: Visitor<'a>>(visitor: &mut V, param: &'a Param) {
+    visitor.visit_pat(&param.pat);
+    if let Some(ty) = &param.ty {
+        visitor.visit_ty(ty);
+    }
+}
+
+pub fn walk_variant<'a, V: Visitor<'a>>(visitor: &mut V, variant: &'a Variant) {
+    visitor.visit_ident(&variant.name);
+    visitor.visit_variant_fields(&variant.fields);
+}
+
+pub fn walk_variant_fields<'a, V: Visitor<'a>>(visitor: &mut V, fields: &'a VariantFields) {
+    for field in fields.fields() {
+        visitor.visit_field_decl(field);
+    }
+}
+
+pub fn walk_field_decl<'a, V: Visitor<'a>>(visitor: &mut V, field: &'a FieldDecl) {
+    visitor.visit_ident(&field.name);
+    visitor.visit_ty(&field.ty);
+}
+
+pub fn walk_block<'a, V: Visitor<'a>>(visitor: &mut V, block: &'a Block) {
+    for stmt in &block.stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_expr<'a, V: Visitor<'a>>(visitor: &mut V, expr: &'a Expr) {
+    match &expr.kind {
+        ExprKind::Lit(lit) => visitor.visit_lit(lit),
+        ExprKind::Path(path) => visitor.visit_path(path),
+        ExprKind::Unary(_, operand) | ExprKind::AddrOf(_, operand) => visitor.visit_expr(operand),
+        ExprKind::Binary(_, lhs, rhs)
+        | ExprKind::Assign(lhs, rhs)
+        | ExprKind::AssignOp(_, lhs, rhs)
+        | ExprKind::Index(lhs, rhs) => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+        ExprKind::Cast(expr, ty) => {
+            visitor.visit_expr(expr);
+            visitor.visit_ty(ty);
+        }
+        ExprKind::Call(callee, args) => {
+            visitor.visit_expr(callee);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        ExprKind::MethodCall(receiver, name, args) => {
+            visitor.visit_expr(receiver);
+            visitor.visit_ident(name);
+            for arg in args {
+                visitor.visit_expr(arg);
+            }
+        }
+        ExprKind::Field(receiver, name) => {
+            visitor.visit_expr(receiver);
+            visitor.visit_ident(name);
+        }
+        ExprKind::Tuple(exprs) | ExprKind::Array(exprs) => {
+            for expr in exprs {
+                visitor.visit_expr(expr);
+            }
+        }
+        ExprKind::Struct(struct_expr) => {
+            visitor.visit_path(&struct_expr.path);
+            for field in &struct_expr.fields {
+                visitor.visit_expr_field(field);
+            }
+            if let Some(rest) = &struct_expr.rest {
+                visitor.visit_expr(rest);
+            }
+        }
+        ExprKind::Paren(expr) | ExprKind::Try(expr) => visitor.visit_expr(expr),
+        ExprKind::Block(block) => visitor.visit_block(block),
+        ExprKind::If(cond, then, otherwise) => {
+            visitor.visit_expr(cond);
+            visitor.visit_block(then);
+            if let Some(otherwise) = otherwise {
+                visitor.visit_expr(otherwise);
+            }
+        }
+        ExprKind::While(cond, body, _) => {
+            visitor.visit_expr(cond);
+            visitor.visit_block(body);
+        }
+        ExprKind::For(pat, iterable, body, _) => {
+            visitor.visit_pat(pat);
+            visitor.visit_expr(iterable);
+            visitor.visit_block(body);
+        }
+        ExprKind::Loop(body, _) => visitor.visit_block(body),
+        ExprKind::Match(scrutinee, arms) => {
+            visitor.visit_expr(scrutinee);
+            for arm in arms {
+                visitor.visit_arm(arm);
+            }
+        }
+        ExprKind::Range(start, end, _) => {
+            if let Some(start) = start {
+                visitor.visit_expr(start);
+            }
+            if let Some(end) = end {
+                visitor.visit_expr(end);
+            }
+        }
+        ExprKind::Return(value) | ExprKind::Break(_, value) => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        ExprKind::Closure(closure) => visitor.visit_closure(closure),
+        ExprKind::Continue(_) | ExprKind::Err => (),
+    }
+}
+
+pub fn walk_closure<'a, V: Visitor<'a>>(visitor: &mut V, closure: &'a Closure) {
+    for param in &closure.params {
+        visitor.visit_param(param);
+    }
+    if let Some(ret) = &closure.ret {
+        visitor.visit_ty(ret);
+    }
+    visitor.visit_expr(&closure.body);
+}
+
+pub fn walk_expr_field<'a, V: Visitor<'a>>(visitor: &mut V, field: &'a ExprField) {
+    visitor.visit_ident(&field.name);
+    visitor.visit_expr(&field.expr);
+}
+
+pub fn walk_arm<'a, V: Visitor<'a>>(visitor: &mut V, arm: &'a Arm) {
+    visitor.visit_pat(&arm.pat);
+    if let Some(guard) = &arm.guard {
+        visitor.visit_expr(guard);
+    }
+    visitor.visit_expr(&arm.body);
+}
+
+pub fn walk_pat<'a, V: Visitor<'a>>(visitor: &mut V, pat: &'a Pattern) {
+    match &pat.kind {
+        PatKind::Ident(_, name, subpattern) => {
+            visitor.visit_ident(name);
+            if let Some(subpattern) = subpattern {
+                visitor.visit_pat(subpattern);
+            }
+        }
+        PatKind::Lit(expr) => visitor.visit_expr(expr),
+        PatKind::Range(start, end, _) => {
+            if let Some(start) = start {
+                visitor.visit_expr(start);
+            }
+            if let Some(end) = end {
+                visitor.visit_expr(end);
+            }
+        }
+        PatKind::Tuple(pats) | PatKind::Slice(pats) | PatKind::Or(pats) => {
+            for pat in pats {
+                visitor.visit_pat(pat);
+            }
+        }
+        PatKind::Path(path) => visitor.visit_path(path),
+        PatKind::TupleStruct(path, pats) => {
+            visitor.visit_path(path);
+            for pat in pats {
+                visitor.visit_pat(pat);
+            }
+        }
+        PatKind::Struct(path, fields, _) => {
+            visitor.visit_path(path);
+            for field in fields {
+                visitor.visit_field_pat(field);
+            }
+        }
+        PatKind::Wild | PatKind::Rest | PatKind::Err => (),
+    }
+}
+
+pub fn walk_field_pat<'a, V: Visitor<'a>>(visitor: &mut V, field: &'a FieldPat) {
+    visitor.visit_ident(&field.name);
+    visitor.visit_pat(&field.pat);
+}
+
+pub fn walk_ty<'a, V: Visitor<'a>>(visitor: &mut V, ty: &'a Type) {
+    match &ty.kind {
+        TypeKind::Path(path, args) => {
+            visitor.visit_path(path);
+            for arg in args {
+                visitor.visit_ty(arg);
+            }
+        }
+        TypeKind::Tuple(types) => {
+            for ty in types {
+                visitor.visit_ty(ty);
+            }
+        }
+        TypeKind::Array(elem) | TypeKind::Ref(_, elem) => visitor.visit_ty(elem),
+        TypeKind::Fn(params, ret) => {
+            for param in params {
+                visitor.visit_ty(param);
+            }
+            if let Some(ret) = ret {
+                visitor.visit_ty(ret);
+            }
+        }
+        TypeKind::ImplTrait(bounds) => {
+            for bound in bounds {
+                visitor.visit_path(bound);
+            }
+        }
+        TypeKind::Infer | TypeKind::Err => (),
+    }
+}
+
+pub fn walk_path<'a, V: Visitor<'a>>(visitor: &mut V, path: &'a Path) {
+    for segment in &path.segments {
+        visitor.visit_ident(segment);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::context::ParseContext;
+    use crate::parser::diagnostic::DiagContext;
+    use crate::parser::emitter::new_emitter_none;
+
+    /// Collects the identifiers in visiting order, except the ones of the blocks of `if`s.
+    #[derive(Default)]
+    struct IdentCollector {
+        idents: Vec<String>,
+    }
+
+    impl<'ast> Visitor<'ast> for IdentCollector {
+        fn visit_expr(&mut self, expr: &'ast Expr) {
+            if let ExprKind::If(cond, _, _) = &expr.kind {
+                // Not walking the children, the branches are skipped
+                self.visit_expr(cond);
+                return;
+            }
+            walk_expr(self, expr);
+        }
+
+        fn visit_ident(&mut self, ident: &'ast Ident) {
+            self.idents.push(ident.name.clone());
+        }
+    }
+
+    #[test]
+    fn visit_all_identifiers() {
+        let source = "
+            fn f(a: T, Point { x, .. }: Point) -> R { a.b(c) + x }
+            enum E { V(U), W { w: i64 } }
+            if cond { skipped } else { skipped }
+            let s = S { field: value, ..rest };
+        ";
+        let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+        let program = crate::parser::parse(&context, source);
+        let mut collector = IdentCollector::default();
+        collector.visit_program(&program);
+        assert_eq!(
+            collector.idents,
+            [
+                "f", "a", "T", "Point", "x", "x", "Point", "R", "a", "b", "c", "x", "E", "V", "0",
+                "U", "W", "w", "i64", "cond", "s", "S", "field", "value", "rest",
+            ]
+        );
+    }
+}
@@ -0,0 +1,1129 @@
+//! Name resolution.
+//!
+//! Resolves every name used by the program to what it refers to, recorded in [`Resolutions`]:
+//! - a local variable, stored in a slot of the frame of the enclosing function or closure, or of
+//!   the top-level code,
+//! - an upvalue, a variable of an enclosing function or closure captured by the closure using it,
+//! - a global, a variable declared at the top level of the program,
+//! - an item, a function, struct, enum or trait visible in the whole block declaring it,
+//! - a built-in function or type, or a variant of the built-in enums `Option` and `Result`, in
+//!   scope without the name of its enum like in Rust.
+//!
+//! Names are looked up in a tree of scopes following the blocks of the program. A `let` statement
+//! declares new variables, shadowing the ones with the same name in scope, after its initializer
+//! so that the initializer can use the shadowed ones. Functions cannot use the variables of their
+//! enclosing scopes, but can use the items of the enclosing blocks and the globals, which are late
+//! bound: a function can use a global declared after it, reading it before its declaration has
+//! run being a runtime error. Each `let` declares its own variables, even at the top level, and
+//! functions use the last global declared with a name.
+//!
+//! Undefined names, names used before their declaration and items defined twice in the same block
+//! are reported, suggesting the visible name closest to an undefined one.
+//!
+//! Each `break` and `continue` is resolved to the loop it exits or continues: the innermost
+//! enclosing loop of its function or closure, or the one declaring its label. Undeclared labels,
+//! `break` and `continue` outside of a loop, and `break` with a value from a `while` or `for`
+//! loop are reported.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::error_codes::{self, ErrorCode};
+use crate::parser::ast::*;
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::{Diagnostic, Level};
+use crate::parser::lexer::Span;
+use crate::parser::mut_visit::{self, VisitorMut};
+
+/// The functions provided by the interpreter.
+pub const BUILTIN_FUNCTIONS: &[&str] = &[
+    "assert",
+    "assert_eq",
+    "catch_unwind",
+    "panic",
+    "print",
+    "println",
+];
+
+/// The primitive types, and the types provided by the interpreter.
+pub const BUILTIN_TYPES: &[&str] = &[
+    "Map", "Option", "Result", "bool", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize",
+    "str", "u8", "u16", "u32", "u64", "u128", "usize",
+];
+
+/// The built-in enums, with the names of their variants in order and the number of values each
+/// variant holds.
+pub const BUILTIN_ENUMS: &[(&str, &[(&str, usize)])] = &[
+    ("Option", &[("None", 0), ("Some", 1)]),
+    ("Result", &[("Ok", 1), ("Err", 1)]),
+];
+
+/// The variants of the built-in enums, in scope without the name of their enum.
+pub const BUILTIN_VARIANTS: &[&str] = &["Err", "None", "Ok", "Some"];
+
+/// What a name refers to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Res {
+    /// A variable of the enclosing function or closure, or of the top-level code, stored in the
+    /// given slot of its frame.
+    Local(usize),
+    /// A variable captured by the enclosing closure, at the given index of its captures.
+    Upvalue(usize),
+    /// A variable declared at the top level of the program, stored in the given global slot.
+    Global(usize),
+    /// An item, by node id.
+    Item(NodeId),
+    /// The `Self` type of the enclosing item, by node id.
+    SelfTy(NodeId),
+    /// A built-in function, type or variant.
+    Builtin(&'static str),
+}
+
+/// The result of the name resolution of a program.
+#[derive(Default, Debug)]
+pub struct Resolutions {
+    /// What the paths of the expressions, patterns and types refer to, and where the variables
+    /// declared by binding patterns and `self` parameters are stored, by node id. The paths with
+    /// several segments, like `Shape::Circle`, are resolved by their first segment.
+    pub names: HashMap<NodeId, Res>,
+    /// The number of slots of the frame of each function and closure, by item or closure
+    /// expression id.
+    pub frame_sizes: HashMap<NodeId, usize>,
+    /// The number of slots of the frame of the top-level code.
+    pub main_frame_size: usize,
+    /// The names of the globals, by slot.
+    pub globals: Vec<String>,
+    /// Where the captures of each closure are taken from in the frame enclosing the closure, by
+    /// closure expression id. The captures are either locals or upvalues of that frame.
+    pub captures: HashMap<NodeId, Vec<Res>>,
+    /// The loop exited or continued by each `break` and `continue`, by expression id.
+    pub loop_targets: HashMap<NodeId, NodeId>,
+    /// The first slot of the variables declared in each loop whose variables are captured by
+    /// closures, by loop expression id. The captured variables from that slot are closed at the
+    /// end of each iteration, so that every iteration gets its own.
+    pub loop_slots: HashMap<NodeId, usize>,
+}
+
+/// Where a variable is stored: in a slot of the frame of the function or closure with the given
+/// id, or of the top-level code, or in a global slot.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Variable {
+    Local(Option<NodeId>, usize),
+    Global(usize),
+}
+
+impl Resolutions {
+    /// Returns where the variable a name resolved to is stored, seen from the last of the given
+    /// frames: the ids of the enclosing functions and closures, the top-level code having none.
+    pub fn variable(&self, res: Res, frames: &[Option<NodeId>]) -> Option<Variable> {
+        let (frame, enclosing) = frames.split_last()?;
+        match res {
+            Res::Local(slot) => Some(Variable::Local(*frame, slot)),
+            Res::Global(slot) => Some(Variable::Global(slot)),
+            Res::Upvalue(index) => {
+                let res = *self.captures.get(&(*frame)?)?.get(index)?;
+                self.variable(res, enclosing)
+            }
+            Res::Item(_) | Res::SelfTy(_) | Res::Builtin(_) => None,
+        }
+    }
+}
+
+/// Resolves the names of the given program, reporting errors through the context and filling the
+/// captures of its closures.
+pub fn resolve(context: &ParseContext, program: &mut Program) -> Resolutions {
+    let mut resolver = Resolver::new(context, Resolutions::default());
+    resolver.visit_program(program);
+    resolver.resolutions
+}
+
+/// The globals and the items declared at the top level of the previous inputs of an interactive
+/// session, which the next inputs can use.
+#[derive(Default)]
+pub struct TopLevel {
+    scope: Scope,
+    /// The slot of the last global declared with each name.
+    global_slots: HashMap<String, usize>,
+    /// The declarations of the last input resolved, until it is committed.
+    pending: Option<(Scope, HashMap<String, usize>)>,
+}
+
+impl TopLevel {
+    /// Makes the declarations of the last input resolved visible to the next inputs, its items
+    /// shadowing the ones of the previous inputs with the same name. The declarations of an input
+    /// which is not committed, because it did not pass the static checks, are forgotten.
+    pub fn commit(&mut self) {
+        let Some((scope, global_slots)) = self.pending.take() else {
+            return;
+        };
+        let items = &mut self.scope.items;
+        items.retain(|(name, _)| !scope.items.iter().any(|(item, _)| item == name));
+        items.extend(scope.items);
+        self.scope.variables.extend(scope.variables);
+        self.global_slots.extend(global_slots);
+    }
+}
+
+/// Resolves the names of an input of an interactive session like [`resolve`], the input seeing the
+/// declarations of the previous inputs, whose resolutions are extended with the ones of the input.
+/// The globals and items of the input are visible to the next inputs once committed with
+/// [`TopLevel::commit`].
+pub fn resolve_input(
+    context: &ParseContext,
+    program: &mut Program,
+    top_level: &mut TopLevel,
+    resolutions: &mut Resolutions,
+) {
+    let mut resolver = Resolver::new(context, std::mem::take(resolutions));
+    resolver.scopes.push(std::mem::take(&mut top_level.scope));
+    resolver.previous_global_slots = std::mem::take(&mut top_level.global_slots);
+    resolver.visit_program(program);
+    let scope = resolver.scopes.pop().expect("the scope was pushed above");
+    top_level.scope = scope;
+    top_level.global_slots = resolver.previous_global_slots;
+    top_level.pending = Some((resolver.program_scope, resolver.global_slots));
+    *resolutions = resolver.resolutions;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    Main,
+    Fn,
+    Closure,
+}
+
+/// The variables of a function, a closure or of the top-level code.
+struct Frame {
+    kind: FrameKind,
+    /// The index of the outermost scope of the frame.
+    scope: usize,
+    slots: usize,
+    /// The variables captured by a closure, with where they are taken from in the enclosing frame.
+    captures: Vec<(Ident, Res)>,
+    /// The loops enclosing the expression being resolved, from the outermost one.
+    loops: Vec<Loop>,
+}
+
+impl Frame {
+    fn new(kind: FrameKind, scope: usize) -> Self {
+        Frame {
+            kind,
+            scope,
+            slots: 0,
+            captures: vec![],
+            loops: vec![],
+        }
+    }
+}
+
+/// A loop that `break` and `continue` can refer to.
+struct Loop {
+    id: NodeId,
+    label: Option<String>,
+    /// The keyword of the loop, as only `loop` can be exited with a value.
+    keyword: &'static str,
+    /// The first slot of the variables declared in the loop.
+    first_slot: usize,
+    /// Whether a closure captures a variable declared in the loop.
+    captured: bool,
+}
+
+#[derive(Default)]
+struct Scope {
+    /// The variables declared so far, in declaration order.
+    variables: Vec<(String, Res)>,
+    /// The items declared in the scope, visible in the whole scope.
+    items: Vec<(String, NodeId)>,
+    /// The names of the variables declared by the `let` statements of the scope.
+    lets: Vec<String>,
+}
+
+/// The namespace of a name: variables and functions are values, while structs, enums and traits
+/// are types. Items are found in both.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Namespace {
+    Value,
+    Type,
+}
+
+struct Resolver<'a> {
+    context: &'a ParseContext,
+    scopes: Vec<Scope>,
+    frames: Vec<Frame>,
+    /// The items whose `Self` type is in scope.
+    self_tys: Vec<NodeId>,
+    /// The slot of the last global declared with each name, which functions use.
+    global_slots: HashMap<String, usize>,
+    /// The slots of the globals declared by the previous inputs of an interactive session, which
+    /// are shadowed by the ones of the program.
+    previous_global_slots: HashMap<String, usize>,
+    /// The index of the scope of the top-level code of the program, whose variables are globals.
+    /// The scope of the previous inputs of an interactive session is below it.
+    global_scope: usize,
+    /// The scope of the top-level code of the program once resolved.
+    program_scope: Scope,
+    /// The slots allocated upfront for the globals not declared yet, in declaration order.
+    pending_globals: HashMap<String, VecDeque<usize>>,
+    /// The variables declared by the first alternative of the or-patterns being resolved, that the
+    /// other alternatives must bind to the same slots.
+    or_bindings: Vec<Vec<(String, Res)>>,
+    resolutions: Resolutions,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(context: &'a ParseContext, resolutions: Resolutions) -> Self {
+        Resolver {
+            context,
+            scopes: vec![],
+            frames: vec![],
+            self_tys: vec![],
+            global_slots: HashMap::new(),
+            previous_global_slots: HashMap::new(),
+            global_scope: 0,
+            program_scope: Scope::default(),
+            pending_globals: HashMap::new(),
+            or_bindings: vec![],
+            resolutions,
+        }
+    }
+
+    fn error(&self, code: ErrorCode, span: Span, message: String) {
+        self.context
+            .diag_ctx()
+            .emit(Diagnostic::spanned(Level::Error, span, message).with_code(code));
+    }
+
+    /// Runs the given function in a new frame, whose outermost scope is the given one.
+    fn with_frame(&mut self, kind: FrameKind, scope: Scope, f: impl FnOnce(&mut Self)) -> Frame {
+        self.frames.push(Frame::new(kind, self.scopes.len()));
+        self.with_scope(scope, f);
+        self.frames.pop().expect("the frame was pushed")
+    }
+
+    fn with_scope(&mut self, scope: Scope, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(scope);
+        f(self);
+        self.scopes.pop();
+    }
+
+    /// Creates the scope of the given statements, declaring their items.
+    fn block_scope(&self, stmts: &[Stmt]) -> Scope {
+        let mut scope = Scope::default();
+        for stmt in stmts {
+            match &stmt.kind {
+                StmtKind::Let(local) => bindings(&local.pat, &mut scope.lets),
+                StmtKind::Item(item) => {
+                    let Some(name) = item.name() else {
+                        continue;
+                    };
+                    if scope.items.iter().any(|(item, _)| *item == name.name) {
+                        let message = format!("the name `{}` is defined multiple times", name.name);
+                        self.error(error_codes::E0042, name.span, message);
+                        continue;
+                    }
+                    scope.items.push((name.name.clone(), item.id));
+                }
+                _ => (),
+            }
+        }
+        scope
+    }
+
+    fn declare(&mut self, name: &Ident, id: NodeId) {
+        let reused = self.or_bindings.last().and_then(|bindings| {
+            bindings
+                .iter()
+                .find(|(binding, _)| *binding == name.name)
+                .map(|(_, res)| *res)
+        });
+        let res = match reused {
+            Some(res) => res,
+            None if self.scopes.len() == self.global_scope + 1 => {
+                Res::Global(self.global_slot(&name.name))
+            }
+            None => {
+                let frame = self.frames.last_mut().expect("a frame is always open");
+                frame.slots += 1;
+                Res::Local(frame.slots - 1)
+            }
+        };
+        let scope = self.scopes.last_mut().expect("a scope is always open");
+        scope.variables.push((name.name.clone(), res));
+        self.resolutions.names.insert(id, res);
+    }
+
+    /// Returns the slot of the global being declared with the given name, allocating it if it was
+    /// not allocated upfront. Each declaration has its own slot, even when shadowing another one.
+    fn global_slot(&mut self, name: &str) -> usize {
+        let pending = self.pending_globals.get_mut(name);
+        if let Some(slot) = pending.and_then(VecDeque::pop_front) {
+            return slot;
+        }
+        self.new_global(name)
+    }
+
+    /// Returns the slot of the last global declared with the given name, if any.
+    fn last_global(&self, name: &str) -> Option<usize> {
+        let slot = self.global_slots.get(name);
+        slot.or_else(|| self.previous_global_slots.get(name))
+            .copied()
+    }
+
+    fn new_global(&mut self, name: &str) -> usize {
+        let slot = self.resolutions.globals.len();
+        self.resolutions.globals.push(String::from(name));
+        self.global_slots.insert(String::from(name), slot);
+        slot
+    }
+
+    /// Returns the scopes of the given frame.
+    fn frame_scopes(&self, frame: usize) -> &[Scope] {
+        let end = self
+            .frames
+            .get(frame + 1)
+            .map_or(self.scopes.len(), |next| next.scope);
+        &self.scopes[self.frames[frame].scope..end]
+    }
+
+    /// Looks up a variable or an item visible from the given frame, capturing the variables of the
+    /// enclosing frames in closures.
+    fn lookup_in_frame(&mut self, frame: usize, name: &Ident) -> Option<Res> {
+        for scope in self.frame_scopes(frame).iter().rev() {
+            let variables = &scope.variables;
+            if let Some((_, res)) = variables.iter().rev().find(|(v, _)| *v == name.name) {
+                return Some(*res);
+            }
+            if let Some((_, id)) = scope.items.iter().find(|(item, _)| *item == name.name) {
+                return Some(Res::Item(*id));
+            }
+        }
+        let scope = self.frames[frame].scope;
+        match self.frames[frame].kind {
+            FrameKind::Main => None,
+            FrameKind::Fn => lookup_item(&self.scopes[..scope], &name.name)
+                .or_else(|| Some(Res::Global(self.last_global(&name.name)?))),
+            FrameKind::Closure => {
+                let captures = &self.frames[frame].captures;
+                if let Some(index) = captures.iter().position(|(c, _)| c.name == name.name) {
+                    return Some(Res::Upvalue(index));
+                }
+                match self.lookup_in_frame(frame - 1, name)? {
+                    res @ (Res::Local(_) | Res::Upvalue(_)) => {
+                        if let Res::Local(slot) = res {
+                            for lp in &mut self.frames[frame - 1].loops {
+                                lp.captured |= lp.first_slot <= slot;
+                            }
+                        }
+                        let captures = &mut self.frames[frame].captures;
+                        captures.push((name.clone(), res));
+                        Some(Res::Upvalue(captures.len() - 1))
+                    }
+                    res => Some(res),
+                }
+            }
+        }
+    }
+
+    /// Returns the scopes visible from the current frame: its own, and the ones of the frames
+    /// enclosing the closures it is nested in.
+    fn visible_scopes(&self) -> impl Iterator<Item = &Scope> {
+        let mut frame = self.frames.len() - 1;
+        while frame > 0 && self.frames[frame].kind == FrameKind::Closure {
+            frame -= 1;
+        }
+        self.scopes[self.frames[frame].scope..].iter()
+    }
+
+    fn resolve_value(&mut self, name: &Ident) -> Option<Res> {
+        if name.name == "Self" {
+            return self.resolve_type(name);
+        }
+        let res = self
+            .lookup_in_frame(self.frames.len() - 1, name)
+            .or_else(|| builtin(BUILTIN_FUNCTIONS, &name.name))
+            .or_else(|| builtin(BUILTIN_VARIANTS, &name.name));
+        if res.is_some() {
+            return res;
+        }
+        let is_declared_later = self
+            .visible_scopes()
+            .any(|scope| scope.lets.contains(&name.name));
+        if is_declared_later {
+            let message = format!("cannot use `{}` before its declaration", name.name);
+            self.error(error_codes::E0041, name.span, message);
+        } else {
+            self.report_undefined(name, Namespace::Value);
+        }
+        None
+    }
+
+    fn resolve_type(&mut self, name: &Ident) -> Option<Res> {
+        let res = if name.name == "Self" {
+            self.self_tys.last().map(|id| Res::SelfTy(*id))
+        } else {
+            lookup_item(&self.scopes, &name.name).or_else(|| builtin(BUILTIN_TYPES, &name.name))
+        };
+        if res.is_none() {
+            self.report_undefined(name, Namespace::Type);
+        }
+        res
+    }
+
+    /// Resolves the path of an expression, a pattern or a type, by its first segment.
+    fn resolve_path(&mut self, id: NodeId, path: &Path, namespace: Namespace) {
+        let first = &path.segments[0];
+        let res = if namespace == Namespace::Value && path.segments.len() == 1 {
+            self.resolve_value(first)
+        } else {
+            self.resolve_type(first)
+        };
+        if let Some(res) = res {
+            self.resolutions.names.insert(id, res);
+        }
+    }
+
+    /// Runs the given function in the body of a loop.
+    fn with_loop(
+        &mut self,
+        id: NodeId,
+        label: &Option<Ident>,
+        keyword: &'static str,
+        f: impl FnOnce(&mut Self),
+    ) {
+        let frame = self.frames.last_mut().expect("a frame is always open");
+        frame.loops.push(Loop {
+            id,
+            label: label.as_ref().map(|label| label.name.clone()),
+            keyword,
+            first_slot: frame.slots,
+            captured: false,
+        });
+        f(self);
+        let frame = self.frames.last_mut().expect("a frame is always open");
+        let lp = frame.loops.pop().expect("the loop was pushed above");
+        if lp.captured {
+            self.resolutions.loop_slots.insert(id, lp.first_slot);
+        }
+    }
+
+    /// Resolves the loop exited or continued by a `break` or `continue`, returning the keyword of
+    /// the loop.
+    fn resolve_loop(
+        &mut self,
+        id: NodeId,
+        label: &Option<Ident>,
+        keyword: &str,
+        span: Span,
+    ) -> Option<&'static str> {
+        let loops = &self.frames.last().expect("a frame is always open").loops;
+        let target = match label {
+            Some(label) => {
+                let target = loops
+                    .iter()
+                    .rev()
+                    .find(|target| target.label.as_ref() == Some(&label.name));
+                if target.is_none() {
+                    let message = format!("use of undeclared label `{}`", label.name);
+                    self.error(error_codes::E0043, label.span, message);
+                }
+                target
+            }
+            None => {
+                let target = loops.last();
+                if target.is_none() {
+                    let message = format!("`{keyword}` outside of a loop");
+                    self.error(error_codes::E0044, span, message);
+                }
+                target
+            }
+        }?;
+        let keyword = target.keyword;
+        self.resolutions.loop_targets.insert(id, target.id);
+        Some(keyword)
+    }
+
+    fn report_undefined(&self, name: &Ident, namespace: Namespace) {
+        let kind = match namespace {
+            Namespace::Value => "value",
+            Namespace::Type => "type",
+        };
+        let message = format!("cannot find {kind} `{}` in this scope", name.name);
+        self.error(error_codes::E0040, name.span, message);
+
+        let mut candidates: Vec<&str> = vec![];
+        for scope in &self.scopes {
+            candidates.extend(scope.items.iter().map(|(item, _)| item.as_str()));
+        }
+        match namespace {
+            Namespace::Value => {
+                for scope in self.visible_scopes() {
+                    candidates.extend(scope.variables.iter().map(|(v, _)| v.as_str()));
+                }
+                candidates.extend(self.global_slots.keys().map(String::as_str));
+                candidates.extend(self.previous_global_slots.keys().map(String::as_str));
+                candidates.extend(BUILTIN_FUNCTIONS);
+                candidates.extend(BUILTIN_VARIANTS);
+            }
+            Namespace::Type => candidates.extend(BUILTIN_TYPES),
+        }
+        if let Some(suggestion) = closest_name(&name.name, candidates) {
+            let message = format!("did you mean `{suggestion}`?");
+            self.context
+                .diag_ctx()
+                .emit(Diagnostic::spanned(Level::Help, name.span, message));
+        }
+    }
+}
+
+/// Looks up an item declared in the given scopes.
+fn lookup_item(scopes: &[Scope], name: &str) -> Option<Res> {
+    scopes.iter().rev().find_map(|scope| {
+        scope
+            .items
+            .iter()
+            .find(|(item, _)| item == name)
+            .map(|(_, id)| Res::Item(*id))
+    })
+}
+
+/// Returns the built-in variant named by the path of a pattern, unless an item shadows it.
+fn builtin_variant(scopes: &[Scope], path: &Path) -> Option<Res> {
+    let name = &path.as_ident()?.name;
+    if lookup_item(scopes, name).is_some() {
+        return None;
+    }
+    builtin(BUILTIN_VARIANTS, name)
+}
+
+fn builtin(builtins: &[&'static str], name: &str) -> Option<Res> {
+    builtins
+        .iter()
+        .find(|builtin| **builtin == name)
+        .map(|builtin| Res::Builtin(builtin))
+}
+
+/// Appends the names of the variables declared by the pattern.
+fn bindings(pat: &Pattern, names: &mut Vec<String>) {
+    match &pat.kind {
+        PatKind::Ident(_, name, sub) => {
+            names.push(name.name.clone());
+            if let Some(sub) = sub {
+                bindings(sub, names);
+            }
+        }
+        PatKind::Tuple(pats) | PatKind::Slice(pats) | PatKind::TupleStruct(_, pats) => {
+            pats.iter().for_each(|pat| bindings(pat, names))
+        }
+        // The alternatives bind the same variables
+        PatKind::Or(pats) => pats.iter().take(1).for_each(|pat| bindings(pat, names)),
+        PatKind::Struct(_, fields, _) => {
+            fields.iter().for_each(|field| bindings(&field.pat, names))
+        }
+        PatKind::Wild
+        | PatKind::Lit(_)
+        | PatKind::Range(..)
+        | PatKind::Rest
+        | PatKind::Path(_)
+        | PatKind::Err => (),
+    }
+}
+
+/// Returns the candidate closest to the given name, if close enough to be a likely typo: candidates
+/// replacing every character of the name, like `b` for `a`, are not.
+fn closest_name<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let length = name.chars().count();
+    let max_distance = (length.max(3) / 3).min(length.saturating_sub(1));
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Returns the edit distance between the two strings: the minimum number of inserted, removed or
+/// substituted characters, or of swapped adjacent characters, to turn one into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // The distances between the prefixes of `a` of the previous two rows and each prefix of `b`
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let substitution = previous[j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            current[j] = substitution.min(previous[j] + 1).min(current[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut previous, current);
+    }
+    previous[b.len()]
+}
+
+impl VisitorMut for Resolver<'_> {
+    fn visit_program(&mut self, program: &mut Program) {
+        // The global scope is the outermost scope of the main frame, above the scope of the previous
+        // inputs of an interactive session. The slots of the globals are allocated upfront, as
+        // functions can use globals declared after them.
+        let scope = self.block_scope(&program.stmts);
+        for name in &scope.lets {
+            let slot = self.new_global(name);
+            let pending = self.pending_globals.entry(name.clone()).or_default();
+            pending.push_back(slot);
+        }
+        self.global_scope = self.scopes.len();
+        self.frames.push(Frame::new(FrameKind::Main, 0));
+        self.scopes.push(scope);
+        mut_visit::walk_program(self, program);
+        self.program_scope = self.scopes.pop().expect("the scope was pushed above");
+        let frame = self.frames.pop().expect("the frame was pushed above");
+        self.resolutions.main_frame_size = frame.slots;
+    }
+
+    fn visit_block(&mut self, block: &mut Block) {
+        let scope = self.block_scope(&block.stmts);
+        self.with_scope(scope, |this| mut_visit::walk_block(this, block));
+    }
+
+    fn visit_local(&mut self, local: &mut Local) {
+        // The variables are declared after their initializer, which can use shadowed ones
+        if let Some(ty) = &mut local.ty {
+            self.visit_ty(ty);
+        }
+        if let Some(init) = &mut local.init {
+            self.visit_expr(init);
+        }
+        self.visit_pat(&mut local.pat);
+    }
+
+    fn visit_item(&mut self, item: &mut Item) {
+        match &mut item.kind {
+            ItemKind::Fn(decl) => {
+                let frame = self.with_frame(FrameKind::Fn, Scope::default(), |this| {
+                    mut_visit::walk_fn_decl(this, decl)
+                });
+                self.resolutions.frame_sizes.insert(item.id, frame.slots);
+            }
+            ItemKind::Impl(decl) => {
+                // The implemented trait is resolved by the trait checker
+                self.visit_ty(&mut decl.self_ty);
+                self.self_tys.push(item.id);
+                for item in &mut decl.items {
+                    self.visit_item(item);
+                }
+                self.self_tys.pop();
+            }
+            ItemKind::Const(_) => {
+                let frame = self.with_frame(FrameKind::Fn, Scope::default(), |this| {
+                    mut_visit::walk_item(this, item)
+                });
+                self.resolutions.frame_sizes.insert(item.id, frame.slots);
+            }
+            ItemKind::Struct(_) | ItemKind::Enum(_) | ItemKind::Trait(_) => {
+                self.self_tys.push(item.id);
+                mut_visit::walk_item(self, item);
+                self.self_tys.pop();
+            }
+        }
+    }
+
+    fn visit_self_param(&mut self, param: &mut SelfParam) {
+        let name = Ident {
+            name: String::from("self"),
+            span: param.span,
+        };
+        self.declare(&name, param.id);
+    }
+
+    fn visit_arm(&mut self, arm: &mut Arm) {
+        self.with_scope(Scope::default(), |this| mut_visit::walk_arm(this, arm));
+    }
+
+    fn visit_expr(&mut self, expr: &mut Expr) {
+        crate::ice::set_current_span(expr.span);
+        match &mut expr.kind {
+            ExprKind::Path(path) => self.resolve_path(expr.id, path, Namespace::Value),
+            ExprKind::Struct(struct_expr) => {
+                self.resolve_path(expr.id, &struct_expr.path, Namespace::Type);
+                for field in &mut struct_expr.fields {
+                    self.visit_expr_field(field);
+                }
+                if let Some(rest) = &mut struct_expr.rest {
+                    self.visit_expr(rest);
+                }
+            }
+            ExprKind::While(cond, body, label) => {
+                self.with_loop(expr.id, label, "while", |this| {
+                    this.visit_expr(cond);
+                    this.visit_block(body);
+                });
+            }
+            ExprKind::For(pat, iterable, body, label) => {
+                self.visit_expr(iterable);
+                self.with_loop(expr.id, label, "for", |this| {
+                    this.with_scope(Scope::default(), |this| {
+                        this.visit_pat(pat);
+                        this.visit_block(body);
+                    });
+                });
+            }
+            ExprKind::Loop(body, label) => {
+                self.with_loop(expr.id, label, "loop", |this| this.visit_block(body));
+            }
+            ExprKind::Break(label, value) => {
+                let keyword = self.resolve_loop(expr.id, label, "break", expr.span);
+                if let Some(value) = value {
+                    if let Some(keyword @ ("while" | "for")) = keyword {
+                        let message = format!("`break` with value from a `{keyword}` loop");
+                        self.error(error_codes::E0045, expr.span, message);
+                    }
+                    self.visit_expr(value);
+                }
+            }
+            ExprKind::Continue(label) => {
+                self.resolve_loop(expr.id, label, "continue", expr.span);
+            }
+            ExprKind::Closure(closure) => {
+                let frame = self.with_frame(FrameKind::Closure, Scope::default(), |this| {
+                    mut_visit::walk_closure(this, closure)
+                });
+                let (captures, sources) = frame.captures.into_iter().unzip();
+                closure.captures = captures;
+                self.resolutions.captures.insert(expr.id, sources);
+                self.resolutions.frame_sizes.insert(expr.id, frame.slots);
+            }
+            _ => mut_visit::walk_expr(self, expr),
+        }
+    }
+
+    fn visit_pat(&mut self, pat: &mut Pattern) {
+        // `None` matches the variant rather than binding a variable, as a path pattern would
+        if let PatKind::Ident(Mutability::Not, name, None) = &pat.kind {
+            let path = Path {
+                segments: vec![name.clone()],
+                span: name.span,
+            };
+            if name.name == "None" && builtin_variant(&self.scopes, &path).is_some() {
+                pat.kind = PatKind::Path(path);
+            }
+        }
+        match &mut pat.kind {
+            PatKind::Ident(_, name, sub) => {
+                // The subpattern is matched before the value is bound
+                if let Some(sub) = sub {
+                    self.visit_pat(sub);
+                }
+                let name = name.clone();
+                self.declare(&name, pat.id);
+            }
+            PatKind::Or(alternatives) => {
+                let Some((first, others)) = alternatives.split_first_mut() else {
+                    return;
+                };
+                let scope = self.scopes.len() - 1;
+                let declared = self.scopes[scope].variables.len();
+                self.visit_pat(first);
+                let bindings = self.scopes[scope].variables[declared..].to_vec();
+                self.or_bindings.push(bindings);
+                for alternative in others {
+                    self.visit_pat(alternative);
+                }
+                self.or_bindings.pop();
+            }
+            PatKind::Path(path) | PatKind::TupleStruct(path, _) | PatKind::Struct(path, ..) => {
+                match builtin_variant(&self.scopes, path) {
+                    Some(res) => {
+                        self.resolutions.names.insert(pat.id, res);
+                    }
+                    None => self.resolve_path(pat.id, path, Namespace::Type),
+                }
+                mut_visit::walk_pat(self, pat);
+            }
+            _ => mut_visit::walk_pat(self, pat),
+        }
+    }
+
+    fn visit_ty(&mut self, ty: &mut Type) {
+        match &mut ty.kind {
+            TypeKind::Path(path, args) => {
+                self.resolve_path(ty.id, path, Namespace::Type);
+                for arg in args {
+                    self.visit_ty(arg);
+                }
+            }
+            // The bounds are resolved by the trait checker
+            TypeKind::ImplTrait(_) => (),
+            _ => mut_visit::walk_ty(self, ty),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::context::ParseContext;
+    use crate::parser::diagnostic::DiagContext;
+    use crate::parser::emitter::new_emitter_none;
+    use crate::parser::visit::{self, Visitor};
+
+    /// Resolves the source, returning the emitted diagnostics as `level: message`.
+    fn diagnostics(source: &str) -> Vec<String> {
+        let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+        let mut program = crate::parser::parse(&context, source);
+        resolve(&context, &mut program);
+        crate::test_utils::diagnostics(&context)
+    }
+
+    /// Returns the resolution of each path expression of the source, in visiting order.
+    fn uses(source: &str) -> Vec<(String, Option<Res>)> {
+        struct Collector<'r>(&'r Resolutions, Vec<(String, Option<Res>)>);
+
+        impl<'ast> Visitor<'ast> for Collector<'_> {
+            fn visit_expr(&mut self, expr: &'ast Expr) {
+                if let ExprKind::Path(path) = &expr.kind {
+                    let res = self.0.names.get(&expr.id).copied();
+                    self.1.push((path.to_string(), res));
+                }
+                visit::walk_expr(self, expr);
+            }
+        }
+
+        let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+        let mut program = crate::parser::parse(&context, source);
+        let resolutions = resolve(&context, &mut program);
+        assert!(!context.diag_ctx().has_errors());
+        let mut collector = Collector(&resolutions, vec![]);
+        collector.visit_program(&program);
+        collector.1
+    }
+
+    fn resolved(name: &str, res: Res) -> (String, Option<Res>) {
+        (String::from(name), Some(res))
+    }
+
+    /// Returns the names captured by each closure of the source, in visiting order.
+    fn captures(source: &str) -> Vec<Vec<String>> {
+        #[derive(Default)]
+        struct Collector(Vec<Vec<String>>);
+
+        impl<'ast> Visitor<'ast> for Collector {
+            fn visit_closure(&mut self, closure: &'ast Closure) {
+                let names = closure.captures.iter().map(|c| c.name.clone()).collect();
+                self.0.push(names);
+                visit::walk_closure(self, closure);
+            }
+        }
+
+        let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+        let mut program = crate::parser::parse(&context, source);
+        assert!(!context.diag_ctx().has_errors());
+        resolve(&context, &mut program);
+        let mut collector = Collector::default();
+        collector.visit_program(&program);
+        collector.0
+    }
+
+    #[test]
+    fn captures_enclosing_locals() {
+        let source = "fn f(a) { let b = 1; let c = |x| a + b + x + a; }";
+        assert_eq!(captures(source), [["a", "b"]]);
+    }
+
+    #[test]
+    fn globals_and_later_declarations_are_not_captured() {
+        let source = "let g = 1; fn f() { let h = || g + l; let l = 2; }";
+        assert_eq!(captures(source), [[] as [&str; 0]]);
+    }
+
+    #[test]
+    fn shadowing() {
+        let source = "fn f(x) { let c = |x| { let y = x; y }; let d = move || { let x = x; x }; }";
+        assert_eq!(captures(source), [vec![], vec!["x"]]);
+    }
+
+    #[test]
+    fn nested_closures() {
+        let source = "fn f(a) { || { let b = 1; || a + b } }";
+        assert_eq!(captures(source), [vec!["a"], vec!["a", "b"]]);
+    }
+
+    #[test]
+    fn functions_do_not_capture() {
+        let source = "fn f(a) { fn g() { || a } for i in a { match i { n => || n + i } } }";
+        assert_eq!(captures(source), [vec![], vec!["n", "i"]]);
+    }
+
+    #[test]
+    fn locals_globals_and_upvalues() {
+        let source = "let g = 1;
+            fn f(a) { let b = a; let c = |x| a + x + g; b }
+            { let l = g; l }";
+        assert_eq!(
+            uses(source),
+            [
+                resolved("a", Res::Local(0)),
+                resolved("a", Res::Upvalue(0)),
+                resolved("x", Res::Local(0)),
+                resolved("g", Res::Global(0)),
+                resolved("b", Res::Local(1)),
+                resolved("g", Res::Global(0)),
+                resolved("l", Res::Local(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn shadowing_allocates_new_slots() {
+        let source = "fn f(x) { let x = x + 1; let x = x * 2; x }";
+        assert_eq!(
+            uses(source),
+            [
+                resolved("x", Res::Local(0)),
+                resolved("x", Res::Local(1)),
+                resolved("x", Res::Local(2)),
+            ]
+        );
+        // Globals shadowing each other have their own slot, functions using the last one
+        assert_eq!(
+            uses("let x = 1; let x = x; let y = x; fn f() { x }"),
+            [
+                resolved("x", Res::Global(0)),
+                resolved("x", Res::Global(1)),
+                resolved("x", Res::Global(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn or_patterns_bind_the_same_slots() {
+        let source = "fn f(p) { match p { (x, 0) | (0, x) => x, _ => 0 } }";
+        assert_eq!(
+            uses(source),
+            [resolved("p", Res::Local(0)), resolved("x", Res::Local(1))]
+        );
+    }
+
+    #[test]
+    fn items_are_visible_in_their_whole_block() {
+        let source = "fn f() { g() } fn g() { fn h() {} h() } let later = f;";
+        let uses = uses(source);
+        assert!(matches!(uses[0].1, Some(Res::Item(_))));
+        assert!(matches!(uses[1].1, Some(Res::Item(_))));
+        assert!(matches!(uses[2].1, Some(Res::Item(_))));
+    }
+
+    #[test]
+    fn functions_use_globals_declared_later() {
+        assert_eq!(
+            uses("fn f() { count } let count = 0;"),
+            [resolved("count", Res::Global(0))]
+        );
+    }
+
+    #[test]
+    fn undefined_names() {
+        assert_eq!(
+            diagnostics("fn f(count) { cuont + total }"),
+            [
+                "error: cannot find value `cuont` in this scope",
+                "help: did you mean `count`?",
+                "error: cannot find value `total` in this scope",
+            ]
+        );
+        assert_eq!(
+            diagnostics("struct Point; let p: Piont = Pointt;"),
+            [
+                "error: cannot find type `Piont` in this scope",
+                "help: did you mean `Point`?",
+                "error: cannot find value `Pointt` in this scope",
+                "help: did you mean `Point`?",
+            ]
+        );
+        assert_eq!(
+            diagnostics("fn f(a) { fn g() { a } }"),
+            ["error: cannot find value `a` in this scope"]
+        );
+        assert_eq!(
+            diagnostics("fn f() -> Self { self }"),
+            [
+                "error: cannot find type `Self` in this scope",
+                "error: cannot find value `self` in this scope",
+            ]
+        );
+    }
+
+    #[test]
+    fn use_before_declaration() {
+        assert_eq!(
+            diagnostics("let a = b; let b = 1;"),
+            ["error: cannot use `b` before its declaration"]
+        );
+        assert_eq!(
+            diagnostics("fn f() { let c = || d; let d = 1; }"),
+            ["error: cannot use `d` before its declaration"]
+        );
+    }
+
+    #[test]
+    fn duplicate_items() {
+        assert_eq!(
+            diagnostics("fn area() {} struct area; { fn area() {} }"),
+            ["error: the name `area` is defined multiple times"]
+        );
+    }
+
+    #[test]
+    fn loop_labels() {
+        assert_eq!(
+            diagnostics("'a: loop { 'b: for x in 0..1 { while x { break 'a; continue 'b; } } }"),
+            [] as [&str; 0]
+        );
+        assert_eq!(
+            diagnostics("'a: loop { break 'b; } continue 'a;"),
+            [
+                "error: use of undeclared label `'b`",
+                "error: use of undeclared label `'a`"
+            ]
+        );
+        // Labels are not visible in the functions and closures nested in the loop
+        assert_eq!(
+            diagnostics("'a: loop { let f = || { break 'a; }; }"),
+            ["error: use of undeclared label `'a`"]
+        );
+    }
+
+    #[test]
+    fn break_outside_of_a_loop() {
+        assert_eq!(
+            diagnostics("break; fn f() { continue; } loop { fn g() { break 1; } }"),
+            [
+                "error: `break` outside of a loop",
+                "error: `continue` outside of a loop",
+                "error: `break` outside of a loop"
+            ]
+        );
+        assert_eq!(
+            diagnostics("while true { break 1; } 'a: for x in 0..1 { loop { break 'a x; } }"),
+            [
+                "error: `break` with value from a `while` loop",
+                "error: `break` with value from a `for` loop"
+            ]
+        );
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("count", "cuont"), 1);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(
+            closest_name("lenght", ["length", "len", "height"]),
+            Some("length")
+        );
+        assert_eq!(closest_name("x", ["y", "foo"]), None);
+    }
+}
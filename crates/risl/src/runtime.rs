@@ -0,0 +1,2198 @@
+//! The run-time semantics shared by the tree-walking interpreter and the virtual machine.
+//!
+//! Both backends run the same programs with the same results: they only differ by how they
+//! evaluate the code of the functions. Everything else is defined here once, on the
+//! [`Runtime`] trait they implement: the arithmetic, the comparisons, the printing of values, the
+//! built-in functions and methods, the method lookup and the iteration of `for` loops.
+//!
+//! Integers follow the semantics of Rust in debug builds, defined in [`crate::int`]: the literals
+//! without suffix are `i64` values, the suffixed ones and the results of casts keep their type,
+//! and the arithmetic raises an error on overflow. An `i64` operand takes the type of the other
+//! operand, as a literal without suffix would.
+//!
+//! The [`Declarations`] describe the items of the program the runtime needs, like the fields of
+//! the structs or the methods implemented by each type, without referencing the AST so that
+//! compiled programs can run without it.
+//!
+//! The built-in enums `Option` and `Result` are declared by every program, under ids no item of
+//! the program has, so that their values are enum values like any other. The `?` operator returns
+//! their `None` and `Err` values from the enclosing function, and an `Err` returned by the
+//! top-level code stops the program with an error, as returning one from `main` does in Rust.
+//!
+//! A [`RuntimeError`] stops the program, recording the calls it unwinds to report a backtrace,
+//! unless it is raised within a call of the built-in `catch_unwind(f)`: `f` is then abandoned and
+//! the call returns an `Err` with the message of the error, so that code like plugins can fail
+//! without stopping the program. It returns `Ok` with the value `f` returns otherwise.
+//!
+//! The methods of the built-in traits are called implicitly: `Display` to print the values of
+//! structs and enums, `Eq` and `Ord` to compare them, `Hash` to use them as map keys and
+//! `Iterator` to iterate over them in `for` loops. Without an implementation, values are printed,
+//! compared for equality and hashed structurally.
+//!
+//! Arrays, maps, strings and ranges with a start are iterated over natively, as are the iterators
+//! returned by their `iter` and `chars` methods. A struct or an enum is an iterator if it
+//! implements `Iterator`, whose `next` method returns `Some` of each item then `None`. Every
+//! iterator has the adapters `map`, `filter`, `enumerate`, `zip`, `take`, `skip`, `chain` and
+//! `rev`, which return a lazy [`Iter`] advancing the iterator they wrap on demand, and the methods
+//! `fold` and `collect`, which consume it.
+
+mod builtins;
+
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::rc::Rc;
+
+use crate::consteval::{ConstValue, Consts};
+use crate::int::{self, wrap, IntTy};
+use crate::parser::ast::*;
+use crate::parser::diagnostic::{DiagContext, Diagnostic, Level};
+use crate::parser::lexer::Span;
+use crate::parser::source_map::SourceMap;
+use crate::parser::visit::{self, Visitor};
+use crate::resolve::{Res, Resolutions, BUILTIN_ENUMS};
+use crate::value::{self, Adt, Function, Iter, Map, Place, Projection, TypeKey, Value};
+
+pub(crate) use builtins::{Builtins, BUILTIN_MUT_METHODS};
+
+/// The maximum depth of the calls of functions and closures. Deeper calls are reported as a stack
+/// overflow instead of exhausting the stack of the interpreter.
+pub const MAX_CALL_DEPTH: usize = 2000;
+
+/// The maximum depth of the nested expressions evaluated by the tree-walking interpreter, across
+/// calls. Deeper nesting is reported as a stack overflow, like deeper calls.
+pub const MAX_EVAL_DEPTH: usize = 8 * MAX_CALL_DEPTH;
+
+/// The maximum number of frames reported in the backtrace of a runtime error.
+pub(crate) const MAX_TRACE_LEN: usize = 16;
+
+/// An error stopping the program while it runs.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RuntimeError {
+    pub message: String,
+    pub span: Span,
+    /// The calls the error went through, innermost first, as the name of the called function and
+    /// the span of the call.
+    pub trace: Vec<(String, Span)>,
+}
+
+/// A frame of the backtrace of a runtime error.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Frame {
+    /// The name of the function the frame runs, `{closure}` for a closure and `<main>` for the
+    /// top-level code.
+    pub function: String,
+    /// Where the frame was when the error occurred: at the error for the innermost frame, and at
+    /// the call of the frame above it for the others.
+    pub span: Span,
+}
+
+impl RuntimeError {
+    fn new(span: Span, message: impl Into<String>) -> Self {
+        RuntimeError {
+            message: message.into(),
+            span,
+            trace: vec![],
+        }
+    }
+
+    /// Returns the backtrace of the error, innermost frame first, down to the top-level code.
+    pub fn backtrace(&self) -> Vec<Frame> {
+        let functions = self.trace.iter().map(|(name, _)| name.as_str());
+        let spans = self.trace.iter().map(|(_, span)| *span);
+        functions
+            .chain(std::iter::once("<main>"))
+            .zip(std::iter::once(self.span).chain(spans))
+            .map(|(function, span)| Frame {
+                function: String::from(function),
+                span,
+            })
+            .collect()
+    }
+
+    /// Formats the backtrace one frame per line, with the location of each frame in the source
+    /// map if any, and as byte offsets otherwise. Only the innermost frames of deep backtraces are
+    /// listed.
+    pub fn format_backtrace(&self, source_map: Option<&SourceMap>) -> String {
+        let backtrace = self.backtrace();
+        let mut lines = vec![String::from("backtrace:")];
+        for (index, frame) in backtrace.iter().enumerate().take(MAX_TRACE_LEN) {
+            let location = match source_map {
+                Some(source_map) => source_map.describe(frame.span),
+                None => format!("{}..{}", frame.span.start, frame.span.end),
+            };
+            lines.push(format!("{index:>4}: {} at {location}", frame.function));
+        }
+        if backtrace.len() > MAX_TRACE_LEN {
+            let omitted = backtrace.len() - MAX_TRACE_LEN;
+            lines.push(format!("      ... {omitted} more frames omitted"));
+        }
+        lines.join("\n")
+    }
+
+    /// Reports the error through the diagnostic context, followed by a note with its backtrace if
+    /// it went through calls.
+    pub fn emit(&self, diag_ctx: &DiagContext) {
+        diag_ctx.emit(Diagnostic::spanned(
+            Level::Error,
+            self.span,
+            self.message.clone(),
+        ));
+        if !self.trace.is_empty() {
+            let backtrace = self.format_backtrace(diag_ctx.source_map());
+            diag_ctx.emit(Diagnostic::new(Level::Note, backtrace));
+        }
+    }
+}
+
+pub(crate) type RunResult<T = Value> = Result<T, Box<RuntimeError>>;
+
+pub(crate) fn error(span: Span, message: impl Into<String>) -> Box<RuntimeError> {
+    Box::new(RuntimeError::new(span, message))
+}
+
+pub(crate) fn output_error(err: std::io::Error) -> String {
+    format!("cannot write the output of the program: {err}")
+}
+
+pub(crate) fn stack_overflow(span: Span) -> Box<RuntimeError> {
+    error(
+        span,
+        format!("stack overflow: more than {MAX_CALL_DEPTH} nested calls"),
+    )
+}
+
+pub(crate) fn nesting_overflow(span: Span) -> Box<RuntimeError> {
+    error(
+        span,
+        format!("stack overflow: more than {MAX_EVAL_DEPTH} nested expressions"),
+    )
+}
+
+/// An evaluated expression which is either a place, that can be assigned or borrowed mutably, or
+/// a temporary value.
+pub(crate) enum Operand {
+    Place(Place),
+    Value(Value),
+}
+
+/// The method called by a method call expression.
+pub(crate) enum Method {
+    /// A method declared in the program, with the receiver to pass as its first argument.
+    Fn(Function, Value),
+    /// A built-in method, with the receiver and its value.
+    Builtin(Operand, Value),
+}
+
+// Declarations
+
+/// The id of the built-in enum `Option`.
+pub const OPTION_ID: NodeId = NodeId::MAX;
+
+/// The id of the built-in enum `Result`.
+pub const RESULT_ID: NodeId = NodeId::MAX - 1;
+
+/// The items of the program needed to run it.
+#[derive(Clone, Default, Debug)]
+pub struct Declarations {
+    /// The structs and enums, by item id.
+    pub types: HashMap<NodeId, TypeDecl>,
+    /// The functions and methods, by item id.
+    pub functions: HashMap<NodeId, FnSig>,
+    /// The parameters of the closures, by expression id.
+    pub closures: HashMap<NodeId, Vec<Option<Bounds>>>,
+    /// The values of the constants, by item id.
+    pub consts: HashMap<NodeId, Value>,
+    /// The names of the other items, like traits, for diagnostics.
+    pub other_items: HashMap<NodeId, String>,
+    /// The type designated by `Self` in each `impl` block, struct and enum.
+    pub self_types: HashMap<NodeId, TypeKey>,
+    /// The methods and associated functions of the `impl` blocks without a trait, by type and
+    /// name.
+    pub inherent: HashMap<TypeKey, HashMap<String, Function>>,
+    /// The methods of the traits implemented by each type, by type, trait name and method name,
+    /// including the default methods not overridden by the implementation.
+    pub trait_impls: HashMap<TypeKey, HashMap<String, HashMap<String, Function>>>,
+}
+
+/// A struct, or an enum and its variants.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TypeDecl {
+    pub name: String,
+    pub is_enum: bool,
+    /// The variants of the enum, or the struct as a single variant.
+    pub variants: Vec<VariantDecl>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct VariantDecl {
+    pub name: String,
+    pub kind: VariantKind,
+    /// The names of the fields, which are their indices for tuple fields.
+    pub fields: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VariantKind {
+    Named,
+    Tuple,
+    Unit,
+}
+
+/// The signature of a function, as far as calling it goes.
+#[derive(Clone, PartialEq, Debug)]
+pub struct FnSig {
+    pub name: String,
+    pub self_param: Option<SelfKind>,
+    /// The trait bounds of the type of each parameter, if any.
+    pub params: Vec<Option<Bounds>>,
+}
+
+/// The trait bounds the value of a parameter or variable must satisfy, from its type.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Bounds {
+    /// An `impl Trait` type, with the names of the traits.
+    Traits(Vec<String>),
+    /// An integer type, which an `i64` is converted to if it fits.
+    Int(IntTy),
+    Ref(Box<Bounds>),
+    Array(Box<Bounds>),
+    Tuple(Vec<Option<Bounds>>),
+}
+
+impl Bounds {
+    /// Returns the bounds of the type, or `None` if it has none.
+    pub fn of(ty: &Type) -> Option<Bounds> {
+        match &ty.kind {
+            TypeKind::ImplTrait(bounds) => Some(Bounds::Traits(
+                bounds
+                    .iter()
+                    .map(|bound| bound.last().name.clone())
+                    .collect(),
+            )),
+            TypeKind::Path(path, _) => {
+                let ty = IntTy::from_name(&path.as_ident()?.name)?;
+                (ty != IntTy::I64).then_some(Bounds::Int(ty))
+            }
+            TypeKind::Ref(_, ty) => Some(Bounds::Ref(Box::new(Bounds::of(ty)?))),
+            TypeKind::Array(ty) => Some(Bounds::Array(Box::new(Bounds::of(ty)?))),
+            TypeKind::Tuple(tys) => {
+                let bounds: Vec<_> = tys.iter().map(Bounds::of).collect();
+                bounds
+                    .iter()
+                    .any(Option::is_some)
+                    .then_some(Bounds::Tuple(bounds))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Collects the items and closures of the program, in visiting order.
+#[derive(Default)]
+struct ItemCollector<'a> {
+    items: Vec<&'a Item>,
+    closures: Vec<(NodeId, &'a Closure)>,
+}
+
+impl<'a> Visitor<'a> for ItemCollector<'a> {
+    fn visit_item(&mut self, item: &'a Item) {
+        self.items.push(item);
+        visit::walk_item(self, item);
+    }
+
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let ExprKind::Closure(closure) = &expr.kind {
+            self.closures.push((expr.id, closure));
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+/// Returns the items and the closures of the programs, by node id.
+pub(crate) fn collect_items<'a>(
+    programs: impl IntoIterator<Item = &'a Program>,
+) -> (HashMap<NodeId, &'a Item>, HashMap<NodeId, &'a Closure>) {
+    let mut collector = ItemCollector::default();
+    for program in programs {
+        collector.visit_program(program);
+    }
+    let items = collector
+        .items
+        .iter()
+        .map(|item| (item.id, *item))
+        .collect();
+    (items, collector.closures.into_iter().collect())
+}
+
+impl Declarations {
+    pub fn new(program: &Program, resolutions: &Resolutions, consts: &Consts) -> Self {
+        Self::new_input(&[], program, resolutions, consts)
+    }
+
+    /// Returns the declarations of an input of an interactive session and of the previous inputs,
+    /// whose items the input can use.
+    pub fn new_input(
+        previous: &[Program],
+        program: &Program,
+        resolutions: &Resolutions,
+        consts: &Consts,
+    ) -> Self {
+        let mut decls = Declarations::default();
+        for (name, variants) in BUILTIN_ENUMS {
+            let variants = variants
+                .iter()
+                .map(|(name, arity)| VariantDecl {
+                    name: String::from(*name),
+                    kind: match arity {
+                        0 => VariantKind::Unit,
+                        _ => VariantKind::Tuple,
+                    },
+                    fields: (0..*arity).map(|index| index.to_string()).collect(),
+                })
+                .collect();
+            let id = builtin_enum_id(name).expect("the enum is built in");
+            decls.types.insert(
+                id,
+                TypeDecl {
+                    name: String::from(*name),
+                    is_enum: true,
+                    variants,
+                },
+            );
+        }
+        let mut collector = ItemCollector::default();
+        for program in previous.iter().chain([program]) {
+            collector.visit_program(program);
+        }
+        for item in &collector.items {
+            let name = item.name().map(|name| name.name.clone());
+            match &item.kind {
+                ItemKind::Fn(decl) => {
+                    let sig = FnSig {
+                        name: decl.name.name.clone(),
+                        self_param: decl.self_param.as_ref().map(|param| param.kind),
+                        params: param_bounds(&decl.params),
+                    };
+                    decls.functions.insert(item.id, sig);
+                }
+                ItemKind::Struct(decl) => {
+                    let variant = variant_decl(&decl.name, &decl.fields);
+                    decls.add_type(item.id, &decl.name, false, vec![variant]);
+                }
+                ItemKind::Enum(decl) => {
+                    let variants = decl
+                        .variants
+                        .iter()
+                        .map(|variant| variant_decl(&variant.name, &variant.fields))
+                        .collect();
+                    decls.add_type(item.id, &decl.name, true, variants);
+                }
+                ItemKind::Const(_) => {
+                    // Constants which failed to evaluate stop the program before it runs
+                    if let Some(value) = consts.values.get(&item.id) {
+                        decls.consts.insert(item.id, from_const(value));
+                    }
+                }
+                ItemKind::Trait(_) | ItemKind::Impl(_) => {
+                    if let Some(name) = name {
+                        decls.other_items.insert(item.id, name);
+                    }
+                }
+            }
+        }
+        for (id, closure) in &collector.closures {
+            decls.closures.insert(*id, param_bounds(&closure.params));
+        }
+        decls.collect_impls(&collector.items, resolutions);
+        decls
+    }
+
+    fn add_type(&mut self, id: NodeId, name: &Ident, is_enum: bool, variants: Vec<VariantDecl>) {
+        let decl = TypeDecl {
+            name: name.name.clone(),
+            is_enum,
+            variants,
+        };
+        self.types.insert(id, decl);
+        self.self_types.insert(id, TypeKey::Adt(id));
+    }
+
+    fn collect_impls(&mut self, items: &[&Item], resolutions: &Resolutions) {
+        let traits: HashMap<&str, &TraitDecl> = items
+            .iter()
+            .filter_map(|item| match &item.kind {
+                ItemKind::Trait(decl) => Some((decl.name.name.as_str(), decl)),
+                _ => None,
+            })
+            .collect();
+        for item in items {
+            let ItemKind::Impl(decl) = &item.kind else {
+                continue;
+            };
+            let Some(key) = type_key(&decl.self_ty, resolutions) else {
+                continue;
+            };
+            self.self_types.insert(item.id, key);
+            let methods = decl.items.iter().filter_map(|item| match &item.kind {
+                ItemKind::Fn(method) => Some((method.name.name.clone(), item.id)),
+                _ => None,
+            });
+            let function = |id| Function {
+                id,
+                self_ty: Some(key),
+            };
+            let Some(trait_ref) = &decl.trait_ref else {
+                let inherent = self.inherent.entry(key).or_default();
+                for (name, id) in methods {
+                    inherent.insert(name, function(id));
+                }
+                continue;
+            };
+            let trait_name = trait_ref.last().name.as_str();
+            let mut implemented: HashMap<String, Function> =
+                methods.map(|(name, id)| (name, function(id))).collect();
+            if let Some(trait_decl) = traits.get(trait_name) {
+                for item in &trait_decl.items {
+                    if let ItemKind::Fn(method @ FnDecl { body: Some(_), .. }) = &item.kind {
+                        implemented
+                            .entry(method.name.name.clone())
+                            .or_insert(function(item.id));
+                    }
+                }
+            }
+            let impls = self.trait_impls.entry(key).or_default();
+            impls.insert(String::from(trait_name), implemented);
+        }
+    }
+
+    /// Returns the type designated by `Self` in the `impl` block, struct or enum with the given
+    /// id, or `None` in a trait where it depends on the type implementing it.
+    pub fn self_ty(&self, id: NodeId) -> Option<TypeKey> {
+        self.self_types.get(&id).copied()
+    }
+
+    pub fn variant(&self, id: NodeId, variant: usize) -> &VariantDecl {
+        &self.types[&id].variants[variant]
+    }
+
+    pub fn field_index(&self, adt: &Adt, name: &str) -> Option<usize> {
+        let fields = &self.variant(adt.id, adt.variant).fields;
+        fields.iter().position(|field| field == name)
+    }
+
+    pub fn type_key_name(&self, key: TypeKey) -> String {
+        match key {
+            TypeKey::Adt(id) => self.types[&id].name.clone(),
+            TypeKey::Builtin(name) => String::from(name),
+        }
+    }
+
+    /// Returns the name of the type of the value, for diagnostics.
+    pub fn type_name(&self, value: &Value) -> String {
+        match value {
+            Value::Tuple(values) if values.is_empty() => String::from("()"),
+            Value::Ref(place) => match place.read() {
+                Some(value) => format!("&mut {}", self.type_name(&value)),
+                None => String::from("&mut _"),
+            },
+            value => self.type_key_name(value.type_key()),
+        }
+    }
+
+    /// Returns the name of the function, qualified by the type implementing it for a method.
+    pub fn fn_name(&self, function: Function) -> String {
+        let name = &self.functions[&function.id].name;
+        match function.self_ty {
+            Some(key) => format!("{}::{name}", self.type_key_name(key)),
+            None => name.clone(),
+        }
+    }
+
+    /// Returns true if the value implements the trait, natively or through an `impl` block.
+    pub fn implements(&self, value: &Value, trait_name: &str) -> bool {
+        let impls = self.trait_impls.get(&value.type_key());
+        if impls.is_some_and(|impls| impls.contains_key(trait_name)) {
+            return true;
+        }
+        match (trait_name, value) {
+            ("Display", Value::Nil | Value::Bool(_) | Value::Int(_) | Value::Fixed(..)) => true,
+            ("Display", Value::Float(_) | Value::Str(_)) => true,
+            ("Eq" | "Ord" | "Hash", Value::Adt(adt)) if is_builtin_enum(adt.id) => adt
+                .fields
+                .iter()
+                .all(|field| self.implements(field, trait_name)),
+            ("Eq", value) => !matches!(
+                value,
+                Value::Iter(_)
+                    | Value::Adt(_)
+                    | Value::Fn(_)
+                    | Value::Closure(_)
+                    | Value::Builtin(_)
+            ),
+            ("Ord", Value::Bool(_) | Value::Int(_) | Value::Fixed(..) | Value::Float(_)) => true,
+            ("Ord", Value::Str(_)) => true,
+            ("Ord", Value::Tuple(values) | Value::Array(values)) => {
+                values.iter().all(|value| self.implements(value, "Ord"))
+            }
+            ("Hash", Value::Nil | Value::Bool(_) | Value::Int(_) | Value::Fixed(..)) => true,
+            ("Hash", Value::Str(_)) => true,
+            ("Hash", Value::Tuple(values) | Value::Array(values)) => {
+                values.iter().all(|value| self.implements(value, "Hash"))
+            }
+            ("Iterator", Value::Range(range)) => range.start.is_some(),
+            ("Iterator", Value::Iter(_)) => true,
+            (_, Value::Ref(place)) => place
+                .read()
+                .is_some_and(|value| self.implements(&value, trait_name)),
+            _ => false,
+        }
+    }
+
+    /// Returns the method of the trait implemented by the type of the value, if any.
+    pub fn trait_method(&self, value: &Value, trait_name: &str, method: &str) -> Option<Function> {
+        let impls = self.trait_impls.get(&value.type_key())?;
+        impls.get(trait_name)?.get(method).copied()
+    }
+
+    /// Checks that the value satisfies the bounds, returning it with its integers converted to
+    /// the annotated types.
+    pub(crate) fn check_bounds(&self, bounds: &Bounds, value: Value, span: Span) -> RunResult {
+        Ok(match (bounds, value) {
+            (Bounds::Traits(traits), value) => {
+                for name in traits {
+                    if !self.implements(&value, name) {
+                        let message = format!(
+                            "the trait bound `{}: {name}` is not satisfied",
+                            self.type_name(&value)
+                        );
+                        return Err(error(span, message));
+                    }
+                }
+                value
+            }
+            (Bounds::Int(ty), Value::Int(value)) => {
+                if !int::in_range(value.into(), *ty) {
+                    let message = format!("integer {value} out of range for `{ty}`");
+                    return Err(error(span, message));
+                }
+                Value::int(value.into(), *ty)
+            }
+            (Bounds::Int(ty), Value::Fixed(_, value_ty)) if value_ty != *ty => {
+                let message = format!("mismatched types: expected `{ty}`, found `{value_ty}`");
+                return Err(error(span, message));
+            }
+            // The value behind a reference is checked but stays in its place
+            (Bounds::Ref(bounds), Value::Ref(place)) => {
+                if let Some(value) = place.read() {
+                    self.check_bounds(bounds, value, span)?;
+                }
+                Value::Ref(place)
+            }
+            (Bounds::Ref(bounds), value) => self.check_bounds(bounds, value, span)?,
+            (Bounds::Array(bounds), Value::Array(values)) => {
+                let values = values
+                    .iter()
+                    .map(|value| self.check_bounds(bounds, value.clone(), span))
+                    .collect::<RunResult<_>>()?;
+                Value::Array(Rc::new(values))
+            }
+            (Bounds::Tuple(bounds), Value::Tuple(values)) => {
+                let values = values
+                    .iter()
+                    .zip(bounds.iter().chain(std::iter::repeat(&None)))
+                    .map(|(value, bounds)| match bounds {
+                        Some(bounds) => self.check_bounds(bounds, value.clone(), span),
+                        None => Ok(value.clone()),
+                    })
+                    .collect::<RunResult<_>>()?;
+                Value::Tuple(Rc::new(values))
+            }
+            (_, value) => value,
+        })
+    }
+
+    /// Returns the method or associated function of the type with the given name, looking at the
+    /// inherent implementations first.
+    pub(crate) fn lookup_method(
+        &self,
+        key: TypeKey,
+        name: &str,
+        span: Span,
+    ) -> RunResult<Option<Function>> {
+        if let Some(function) = self
+            .inherent
+            .get(&key)
+            .and_then(|methods| methods.get(name))
+        {
+            return Ok(Some(*function));
+        }
+        let mut found = self
+            .trait_impls
+            .get(&key)
+            .into_iter()
+            .flat_map(|impls| impls.values())
+            .filter_map(|methods| methods.get(name));
+        match (found.next(), found.next()) {
+            (None, _) => Ok(None),
+            (Some(function), None) => Ok(Some(*function)),
+            (Some(_), Some(_)) => {
+                let message = format!(
+                    "multiple applicable items named `{name}` found for `{}`",
+                    self.type_key_name(key)
+                );
+                Err(error(span, message))
+            }
+        }
+    }
+
+    /// Returns the value of the item, or of its associated item named by the rest of the path.
+    pub(crate) fn item_value(&self, id: NodeId, rest: &[Ident], span: Span) -> RunResult {
+        if self.functions.contains_key(&id) && rest.is_empty() {
+            return Ok(Value::Fn(Function { id, self_ty: None }));
+        }
+        if let Some(value) = self.consts.get(&id) {
+            if rest.is_empty() {
+                return Ok(value.clone());
+            }
+        }
+        if self.types.contains_key(&id) {
+            return self.assoc_value(TypeKey::Adt(id), rest, span);
+        }
+        let name = match self.functions.get(&id) {
+            Some(sig) => &sig.name,
+            None => self.other_items.get(&id).map_or("", String::as_str),
+        };
+        Err(error(span, format!("expected value, found item `{name}`")))
+    }
+
+    /// Returns the value of the type used as a value, like a unit struct or the constructor of a
+    /// tuple struct, or of its associated item named by the rest of the path.
+    pub(crate) fn assoc_value(&self, key: TypeKey, rest: &[Ident], span: Span) -> RunResult {
+        let name = match (key, rest) {
+            (TypeKey::Adt(id), []) => return self.ctor_value(id, 0, span),
+            (_, [name]) => name,
+            _ => {
+                let message = format!("expected value, found type `{}`", self.type_key_name(key));
+                return Err(error(span, message));
+            }
+        };
+        if let Some(variant) = self.enum_variant(key, &name.name) {
+            let TypeKey::Adt(id) = key else {
+                unreachable!("only enums have variants");
+            };
+            return self.ctor_value(id, variant, span);
+        }
+        if let Some(function) = self.lookup_method(key, &name.name, span)? {
+            return Ok(Value::Fn(function));
+        }
+        if let TypeKey::Builtin(ty) = key {
+            if let Some(builtin) = builtins::associated_function(ty, &name.name) {
+                return Ok(Value::Builtin(builtin));
+            }
+            if let Some(ty) = IntTy::from_name(ty) {
+                if let Some(value) = int::associated_const(ty, &name.name) {
+                    return Ok(Value::int(value, ty));
+                }
+            }
+        }
+        let message = format!(
+            "no function or associated item named `{}` found for `{}`",
+            name.name,
+            self.type_key_name(key)
+        );
+        Err(error(name.span, message))
+    }
+
+    /// Returns the index of the variant of the enum with the given name.
+    fn enum_variant(&self, key: TypeKey, name: &str) -> Option<usize> {
+        let TypeKey::Adt(id) = key else {
+            return None;
+        };
+        let decl = &self.types[&id];
+        if !decl.is_enum {
+            return None;
+        }
+        decl.variants
+            .iter()
+            .position(|variant| variant.name == name)
+    }
+
+    /// Returns the value of a unit variant, or the constructor of a tuple variant.
+    pub(crate) fn ctor_value(&self, id: NodeId, variant: usize, span: Span) -> RunResult {
+        let decl = self.variant(id, variant);
+        match decl.kind {
+            VariantKind::Unit => Ok(Value::Adt(Rc::new(Adt {
+                id,
+                variant,
+                fields: vec![],
+            }))),
+            VariantKind::Tuple => Ok(Value::Ctor(id, variant)),
+            VariantKind::Named => {
+                let message = format!("expected value, found struct variant `{}`", decl.name);
+                Err(error(span, message))
+            }
+        }
+    }
+
+    /// Returns the value of a built-in function or variant, or of the associated item of a built-in
+    /// type named by the rest of the path.
+    pub(crate) fn builtin_value(
+        &self,
+        name: &'static str,
+        rest: &[Ident],
+        span: Span,
+    ) -> RunResult {
+        match (rest, builtin_variant(name)) {
+            ([], Some((id, variant))) => self.ctor_value(id, variant, span),
+            ([], None) => Ok(Value::Builtin(name)),
+            (rest, _) => self.assoc_value(builtin_type_key(name), rest, span),
+        }
+    }
+
+    /// Returns the struct or enum variant of the type named by the rest of a path.
+    pub(crate) fn resolve_variant(&self, key: TypeKey, rest: &[Ident]) -> Option<(NodeId, usize)> {
+        let TypeKey::Adt(id) = key else {
+            return None;
+        };
+        match rest {
+            [] if !self.types[&id].is_enum => Some((id, 0)),
+            [name] => Some((id, self.enum_variant(key, &name.name)?)),
+            _ => None,
+        }
+    }
+}
+
+fn variant_decl(name: &Ident, fields: &VariantFields) -> VariantDecl {
+    let kind = match fields {
+        VariantFields::Named(_) => VariantKind::Named,
+        VariantFields::Tuple(_) => VariantKind::Tuple,
+        VariantFields::Unit => VariantKind::Unit,
+    };
+    VariantDecl {
+        name: name.name.clone(),
+        kind,
+        fields: fields
+            .fields()
+            .iter()
+            .map(|field| field.name.name.clone())
+            .collect(),
+    }
+}
+
+fn param_bounds(params: &[Param]) -> Vec<Option<Bounds>> {
+    params
+        .iter()
+        .map(|param| param.ty.as_ref().and_then(Bounds::of))
+        .collect()
+}
+
+/// Returns the type named by the type of an `impl` block.
+fn type_key(ty: &Type, resolutions: &Resolutions) -> Option<TypeKey> {
+    match &ty.kind {
+        TypeKind::Path(..) => match resolutions.names.get(&ty.id)? {
+            Res::Item(id) => Some(TypeKey::Adt(*id)),
+            Res::Builtin(name) => Some(builtin_type_key(name)),
+            _ => None,
+        },
+        TypeKind::Tuple(_) => Some(TypeKey::Builtin("tuple")),
+        TypeKind::Array(_) => Some(TypeKey::Builtin("array")),
+        _ => None,
+    }
+}
+
+// Places
+
+/// Returns a copy of the value of the place.
+pub(crate) fn read(place: &Place, span: Span) -> RunResult {
+    match place.read() {
+        Some(Value::Uninit) => Err(uninit_error(span)),
+        Some(value) => Ok(value),
+        None => Err(error(span, "the referenced value no longer exists")),
+    }
+}
+
+/// Returns the error of a use of a global before its declaration has run.
+pub(crate) fn uninit_error(span: Span) -> Box<RuntimeError> {
+    error(span, "use of uninitialized variable")
+}
+
+pub(crate) fn write(place: &Place, span: Span, value: Value) -> RunResult<()> {
+    place
+        .modify(|current| *current = value)
+        .ok_or_else(|| error(span, "the referenced value no longer exists"))
+}
+
+/// Returns a reference to a new place holding the value, for a temporary borrowed mutably.
+pub(crate) fn temporary(value: Value) -> Place {
+    let env = value::new_env(1);
+    env.borrow_mut()[0] = value;
+    Place::variable(env, 0)
+}
+
+/// Follows the mutable references of the value.
+pub(crate) fn deref(mut value: Value, span: Span) -> RunResult {
+    while let Value::Ref(place) = value {
+        value = read(&place, span)?;
+    }
+    Ok(value)
+}
+
+/// Follows the mutable references of the operand, returning the operand they point to along with
+/// its value.
+pub(crate) fn deref_operand(mut operand: Operand, span: Span) -> RunResult<(Operand, Value)> {
+    loop {
+        let value = match &operand {
+            Operand::Place(place) => read(place, span)?,
+            Operand::Value(value) => value.clone(),
+        };
+        match value {
+            Value::Ref(place) => operand = Operand::Place((*place).clone()),
+            value => return Ok((operand, value)),
+        }
+    }
+}
+
+/// Modifies the map stored in the place.
+pub(crate) fn modify_map<R>(
+    place: &Place,
+    span: Span,
+    f: impl FnOnce(&mut Map) -> R,
+) -> RunResult<R> {
+    let result = place.modify(|value| match value {
+        Value::Map(map) => Some(f(Rc::make_mut(map))),
+        _ => None,
+    });
+    result
+        .flatten()
+        .ok_or_else(|| error(span, "the referenced value no longer exists"))
+}
+
+/// The run-time operations on values, implemented by each backend on top of its way of calling
+/// the functions and closures of the program.
+pub(crate) trait Runtime<'a> {
+    fn decls(&self) -> &'a Declarations;
+
+    fn output(&mut self) -> &mut dyn Write;
+
+    /// Calls a function declared in the program, whose arguments are not checked yet.
+    fn call_function(&mut self, function: Function, args: Vec<Value>, span: Span) -> RunResult;
+
+    /// Calls a closure, whose arguments are not checked yet.
+    fn call_closure(
+        &mut self,
+        closure: Rc<value::Closure>,
+        args: Vec<Value>,
+        span: Span,
+    ) -> RunResult;
+
+    /// Returns the values kept alive while a built-in calls back into the program, if the backend
+    /// collects cycles and a built-in is running.
+    fn roots(&mut self) -> Option<&mut Vec<Value>> {
+        None
+    }
+
+    /// Runs a built-in, whose callbacks into the program can only collect cycles if it roots the
+    /// values it holds outside of the runtime, which are released once it returns.
+    fn rooted<T>(&mut self, f: impl FnOnce(&mut Self) -> RunResult<T>) -> RunResult<T> {
+        f(self)
+    }
+
+    /// Keeps the value alive until the end of the running built-in.
+    fn root(&mut self, value: &Value) {
+        if let Some(roots) = self.roots() {
+            roots.push(value.clone());
+        }
+    }
+
+    // Calls
+
+    fn call(&mut self, callee: Value, args: Vec<Value>, span: Span) -> RunResult {
+        match callee {
+            Value::Fn(function) => self.call_function(function, args, span),
+            Value::Closure(closure) => self.call_closure(closure, args, span),
+            Value::Builtin(name) => self.rooted(|this| {
+                for arg in &args {
+                    this.root(arg);
+                }
+                this.call_builtin(name, args, span)
+            }),
+            Value::Ctor(id, variant) => {
+                let arity = self.decls().variant(id, variant).fields.len();
+                check_arity("function", arity, args.len(), span)?;
+                Ok(Value::Adt(Rc::new(Adt {
+                    id,
+                    variant,
+                    fields: args,
+                })))
+            }
+            Value::Ref(place) => {
+                let callee = read(&place, span)?;
+                self.call(callee, args, span)
+            }
+            callee => {
+                let message = format!(
+                    "expected function, found `{}`",
+                    self.decls().type_name(&callee)
+                );
+                Err(error(span, message))
+            }
+        }
+    }
+
+    /// Checks the arguments of a call to the function, and passes its receiver as its parameter
+    /// expects.
+    fn function_args(
+        &mut self,
+        function: Function,
+        mut args: Vec<Value>,
+        span: Span,
+    ) -> RunResult<Vec<Value>> {
+        let sig = &self.decls().functions[&function.id];
+        let arity = sig.params.len() + usize::from(sig.self_param.is_some());
+        check_arity("function", arity, args.len(), span)?;
+        if let Some(kind) = sig.self_param {
+            let receiver = std::mem::replace(&mut args[0], Value::Nil);
+            args[0] = match (kind, receiver) {
+                (SelfKind::Ref(Mutability::Mut), receiver @ Value::Ref(_)) => receiver,
+                (SelfKind::Ref(Mutability::Mut), receiver) => {
+                    Value::Ref(Rc::new(temporary(receiver)))
+                }
+                (_, receiver) => deref(receiver, span)?,
+            };
+        }
+        let params = &mut args[usize::from(sig.self_param.is_some())..];
+        for (bounds, arg) in sig.params.iter().zip(params) {
+            if let Some(bounds) = bounds {
+                let value = std::mem::replace(arg, Value::Nil);
+                *arg = self.decls().check_bounds(bounds, value, span)?;
+            }
+        }
+        Ok(args)
+    }
+
+    /// Checks the arguments of a call to the closure.
+    fn closure_args(
+        &mut self,
+        closure: &value::Closure,
+        mut args: Vec<Value>,
+        span: Span,
+    ) -> RunResult<Vec<Value>> {
+        let params = &self.decls().closures[&closure.id];
+        check_arity("closure", params.len(), args.len(), span)?;
+        for (bounds, arg) in params.iter().zip(&mut args) {
+            if let Some(bounds) = bounds {
+                let value = std::mem::replace(arg, Value::Nil);
+                *arg = self.decls().check_bounds(bounds, value, span)?;
+            }
+        }
+        Ok(args)
+    }
+
+    /// Returns the method called on the receiver, declared in the program or built in.
+    fn resolve_method(&mut self, receiver: Operand, name: &Ident) -> RunResult<Method> {
+        let decls = self.decls();
+        let (receiver, value) = deref_operand(receiver, name.span)?;
+        if let Some(function) = decls.lookup_method(value.type_key(), &name.name, name.span)? {
+            let receiver = match (decls.functions[&function.id].self_param, receiver) {
+                (Some(SelfKind::Ref(Mutability::Mut)), Operand::Place(place)) => {
+                    Value::Ref(Rc::new(place))
+                }
+                (Some(_), _) => value,
+                (None, _) => {
+                    let message = format!(
+                        "`{}` is an associated function, not a method",
+                        decls.fn_name(function)
+                    );
+                    return Err(error(name.span, message));
+                }
+            };
+            return Ok(Method::Fn(function, receiver));
+        }
+        let is_iterator_method = builtins::is_iterator_method(&name.name)
+            && decls.trait_method(&value, "Iterator", "next").is_some();
+        if !builtins::has_method(&value, &name.name) && !is_iterator_method {
+            let message = format!(
+                "no method named `{}` found for `{}`",
+                name.name,
+                decls.type_name(&value)
+            );
+            return Err(error(name.span, message));
+        }
+        Ok(Method::Builtin(receiver, value))
+    }
+
+    fn call_method(
+        &mut self,
+        receiver: Operand,
+        name: &Ident,
+        args: Vec<Value>,
+        span: Span,
+    ) -> RunResult {
+        match self.resolve_method(receiver, name)? {
+            Method::Fn(function, receiver) => {
+                let args = std::iter::once(receiver).chain(args).collect();
+                self.call_function(function, args, span)
+            }
+            Method::Builtin(receiver, value) => {
+                self.call_builtin_method(receiver, value, &name.name, args, span)
+            }
+        }
+    }
+
+    // Option and Result
+
+    /// Applies the `?` operator to the value: continues with the value of a `Some` or an `Ok`, or
+    /// breaks with a `None` or an `Err` to return from the enclosing function.
+    /// Returns the value of the `Some` or `Ok` of the operand of a `?` operator, or the value to
+    /// return, checking it is of the built-in enum the function returns if it names one.
+    fn try_branch(
+        &mut self,
+        value: Value,
+        returns: Option<NodeId>,
+        span: Span,
+    ) -> RunResult<ControlFlow<Value, Value>> {
+        let value = deref(value, span)?;
+        if let Value::Adt(adt) = &value {
+            if is_builtin_enum(adt.id) {
+                if let Some(returns) = returns.filter(|returns| *returns != adt.id) {
+                    let types = &self.decls().types;
+                    let (expected, found) = (&types[&returns].name, &types[&adt.id].name);
+                    let message = format!(
+                        "the `?` operator can only be used on `{expected}`s, not `{found}`s, in a \
+                         function that returns `{expected}`"
+                    );
+                    return Err(error(span, message));
+                }
+                let is_some_or_ok = match adt.id {
+                    OPTION_ID => adt.variant == 1,
+                    _ => adt.variant == 0,
+                };
+                return Ok(if is_some_or_ok {
+                    ControlFlow::Continue(adt.fields[0].clone())
+                } else {
+                    ControlFlow::Break(value)
+                });
+            }
+        }
+        let message = format!(
+            "the `?` operator can only be applied to values of type `Option` or `Result`, found \
+             `{}`",
+            self.decls().type_name(&value)
+        );
+        Err(error(span, message))
+    }
+
+    /// Checks the value the top-level code of the program ends with: an `Err` stops the program
+    /// with an error, as returning one from `main` does in Rust.
+    fn check_exit(&mut self, value: &Value, span: Span) -> RunResult<()> {
+        match value {
+            Value::Adt(adt) if adt.id == RESULT_ID && adt.variant == 1 => {
+                let message = format!(
+                    "the program returned an error: {}",
+                    self.repr(&adt.fields[0], span)?
+                );
+                Err(error(span, message))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Operators
+
+    fn unary_op(&mut self, op: UnOp, value: Value, span: Span) -> RunResult {
+        Ok(match (op, value) {
+            (UnOp::Neg, Value::Int(value)) => match value.checked_neg() {
+                Some(value) => Value::Int(value),
+                None => return Err(error(span, "attempt to negate with overflow")),
+            },
+            (UnOp::Neg, Value::Fixed(value, ty)) if ty.is_signed() => {
+                match int::int_op(BinOp::Sub, 0, value, ty) {
+                    Ok(value) => Value::Fixed(value, ty),
+                    Err(_) => return Err(error(span, "attempt to negate with overflow")),
+                }
+            }
+            (UnOp::Neg, Value::Float(value)) => Value::Float(-value),
+            (UnOp::Not, Value::Int(value)) => Value::Int(!value),
+            (UnOp::Not, Value::Fixed(value, ty)) => Value::Fixed(wrap(!value, ty), ty),
+            (UnOp::Not, Value::Bool(value)) => Value::Bool(!value),
+            (UnOp::Deref, Value::Ref(place)) => read(&place, span)?,
+            (UnOp::Deref, value) => value,
+            (op, value) => {
+                let message = format!(
+                    "cannot apply unary operator `{}` to `{}`",
+                    op.as_str(),
+                    self.decls().type_name(&value)
+                );
+                return Err(error(span, message));
+            }
+        })
+    }
+
+    fn binary_op(&mut self, op: BinOp, lhs: Value, rhs: Value, span: Span) -> RunResult {
+        let lhs = deref(lhs, span)?;
+        let rhs = deref(rhs, span)?;
+        let value = match (op, lhs, rhs) {
+            (BinOp::Eq, lhs, rhs) => Value::Bool(self.values_eq(&lhs, &rhs, span)?),
+            (BinOp::Ne, lhs, rhs) => Value::Bool(!self.values_eq(&lhs, &rhs, span)?),
+            (BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge, lhs, rhs) => {
+                let ordering = self.compare(&lhs, &rhs, span)?;
+                Value::Bool(ordering.is_some_and(|ordering| match op {
+                    BinOp::Lt => ordering == Ordering::Less,
+                    BinOp::Le => ordering != Ordering::Greater,
+                    BinOp::Gt => ordering == Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                }))
+            }
+            (
+                op,
+                lhs @ (Value::Int(_) | Value::Fixed(..)),
+                rhs @ (Value::Int(_) | Value::Fixed(..)),
+            ) if fixed_ty(op, &lhs, &rhs).is_some() => {
+                let ty = fixed_ty(op, &lhs, &rhs).expect("checked above");
+                fixed_op(op, &lhs, &rhs, ty).map_err(|message| error(span, message))?
+            }
+            (op, Value::Float(lhs), Value::Float(rhs)) if op.is_arithmetic() => {
+                Value::Float(match op {
+                    BinOp::Add => lhs + rhs,
+                    BinOp::Sub => lhs - rhs,
+                    BinOp::Mul => lhs * rhs,
+                    BinOp::Div => lhs / rhs,
+                    _ => lhs % rhs,
+                })
+            }
+            (BinOp::Add, Value::Str(lhs), Value::Str(rhs)) => {
+                Value::Str(Rc::from(format!("{lhs}{rhs}")))
+            }
+            (op, Value::Bool(lhs), Value::Bool(rhs)) if op.is_bitwise() => Value::Bool(match op {
+                BinOp::BitAnd => lhs & rhs,
+                BinOp::BitOr => lhs | rhs,
+                _ => lhs ^ rhs,
+            }),
+            (op, lhs, rhs) => {
+                let message = format!(
+                    "cannot apply operator `{}` to `{}` and `{}`",
+                    op.as_str(),
+                    self.decls().type_name(&lhs),
+                    self.decls().type_name(&rhs)
+                );
+                return Err(error(span, message));
+            }
+        };
+        Ok(value)
+    }
+
+    /// Casts the value to the built-in type with the given name.
+    fn cast(&mut self, value: Value, target: &str, span: Span) -> RunResult {
+        let int = value.as_int();
+        let value = match (value, target) {
+            (_, "f32") if int.is_some() => {
+                let (value, ty) = int.expect("checked above");
+                Value::Float(int::to_f64(value, ty) as f32 as f64)
+            }
+            (_, "f64") if int.is_some() => {
+                let (value, ty) = int.expect("checked above");
+                Value::Float(int::to_f64(value, ty))
+            }
+            (Value::Float(value), "f32") => Value::Float(value as f32 as f64),
+            (Value::Float(value), "f64") => Value::Float(value),
+            (value, target) if IntTy::from_name(target).is_some() => {
+                let ty = IntTy::from_name(target).expect("checked above");
+                match (value, int) {
+                    (_, Some((value, _))) => Value::int(wrap(value, ty), ty),
+                    (Value::Float(value), _) => Value::int(int::from_f64(value, ty), ty),
+                    (Value::Bool(value), _) => Value::int(i128::from(value), ty),
+                    (value, _) => return Err(self.cast_error(&value, target, span)),
+                }
+            }
+            (value @ Value::Bool(_), "bool") | (value @ Value::Str(_), "str") => value,
+            (value, target) => return Err(self.cast_error(&value, target, span)),
+        };
+        Ok(value)
+    }
+
+    fn cast_error(&self, value: &Value, target: &str, span: Span) -> Box<RuntimeError> {
+        let message = format!(
+            "cannot cast `{}` as `{target}`",
+            self.decls().type_name(value)
+        );
+        error(span, message)
+    }
+
+    /// Returns the range with the given bounds, which must be integers.
+    fn range(
+        &mut self,
+        start: Option<Value>,
+        end: Option<Value>,
+        inclusive: bool,
+        span: Span,
+    ) -> RunResult {
+        let bound = |bound: Option<Value>| match bound {
+            None => Ok(None),
+            Some(Value::Int(value)) => Ok(Some(value)),
+            Some(Value::Fixed(value, ty)) => match i64::try_from(value) {
+                Ok(value) if ty != IntTy::U128 || value >= 0 => Ok(Some(value)),
+                _ => {
+                    let value = int::to_string(value, ty);
+                    let message = format!("the bound {value} of the range does not fit in `i64`");
+                    Err(error(span, message))
+                }
+            },
+            Some(value) => {
+                let message = format!(
+                    "the bounds of a range must be integers, found `{}`",
+                    self.decls().type_name(&value)
+                );
+                Err(error(span, message))
+            }
+        };
+        let range = value::Range {
+            start: bound(start)?,
+            end: bound(end)?,
+            inclusive,
+        };
+        Ok(Value::Range(Rc::new(range)))
+    }
+
+    // Fields and indexing
+
+    /// Returns the projection to the field of the value.
+    fn field_position(&self, value: &Value, name: &Ident) -> RunResult<Projection> {
+        let index = match value {
+            Value::Tuple(values) => name.name.parse().ok().filter(|i| *i < values.len()),
+            Value::Adt(adt) => self.decls().field_index(adt, &name.name),
+            _ => None,
+        };
+        match index {
+            Some(index) => Ok(Projection::Field(index)),
+            None => {
+                let message = format!(
+                    "no field `{}` on type `{}`",
+                    name.name,
+                    self.decls().type_name(value)
+                );
+                Err(error(name.span, message))
+            }
+        }
+    }
+
+    fn project(&mut self, value: Value, name: &Ident, span: Span) -> RunResult {
+        let value = deref(value, span)?;
+        let Projection::Field(index) = self.field_position(&value, name)? else {
+            unreachable!("fields are projected by index");
+        };
+        Ok(match value {
+            Value::Tuple(values) => values[index].clone(),
+            Value::Adt(adt) => adt.fields[index].clone(),
+            _ => unreachable!("only tuples and structs have fields"),
+        })
+    }
+
+    /// Returns the field of the operand, as a place if the operand is one.
+    fn field_operand(&mut self, base: Operand, name: &Ident, span: Span) -> RunResult<Operand> {
+        let (base, value) = deref_operand(base, span)?;
+        let index = self.field_position(&value, name)?;
+        match base {
+            Operand::Place(place) => Ok(Operand::Place(place.project(index))),
+            Operand::Value(_) => Ok(Operand::Value(self.project(value, name, span)?)),
+        }
+    }
+
+    fn index(&mut self, value: Value, index: Value, span: Span) -> RunResult {
+        let value = deref(value, span)?;
+        match (&value, &index) {
+            (Value::Array(values), Value::Int(_) | Value::Fixed(..)) => {
+                Ok(values[array_index(values.len(), &index, span)?].clone())
+            }
+            (Value::Array(values), Value::Range(range)) => {
+                let start = range.start.unwrap_or(0);
+                let end = range.exclusive_end().unwrap_or(values.len() as i64);
+                if start < 0 || start > end || end > values.len() as i64 {
+                    let message = format!(
+                        "range {} out of bounds for an array of length {}",
+                        self.repr(&index, span)?,
+                        values.len()
+                    );
+                    return Err(error(span, message));
+                }
+                Ok(Value::Array(Rc::new(
+                    values[start as usize..end as usize].to_vec(),
+                )))
+            }
+            (Value::Map(map), key) => match self.map_find(map, key, span)?.1 {
+                Some(i) => Ok(map.entries()[i].1.clone()),
+                None => Err(self.key_not_found(key, span)),
+            },
+            _ => {
+                let message = format!(
+                    "cannot index into a value of type `{}` with `{}`",
+                    self.decls().type_name(&value),
+                    self.decls().type_name(&index)
+                );
+                Err(error(span, message))
+            }
+        }
+    }
+
+    /// Returns the element of the operand at the index, as a place if the operand is one.
+    fn index_operand(&mut self, base: Operand, index: Value, span: Span) -> RunResult<Operand> {
+        let (base, value) = deref_operand(base, span)?;
+        let projection = match (&value, &index) {
+            (Value::Array(values), Value::Int(_) | Value::Fixed(..)) => {
+                Projection::Elem(array_index(values.len(), &index, span)?)
+            }
+            (Value::Map(map), key) => match self.map_find(map, key, span)?.1 {
+                Some(i) => Projection::MapValue(i),
+                None => return Err(self.key_not_found(key, span)),
+            },
+            _ => return Ok(Operand::Value(self.index(value, index, span)?)),
+        };
+        drop(value);
+        match base {
+            Operand::Place(place) => Ok(Operand::Place(place.project(projection))),
+            Operand::Value(value) => Ok(Operand::Value(self.index(value, index, span)?)),
+        }
+    }
+
+    /// Assigns the value to the element of the operand at the index. Assigning to a missing key
+    /// of a map inserts it.
+    fn assign_index(
+        &mut self,
+        base: Operand,
+        index: Value,
+        value: Value,
+        span: Span,
+    ) -> RunResult<()> {
+        let (base, current) = deref_operand(base, span)?;
+        if let (Operand::Place(base), Value::Map(map)) = (&base, &current) {
+            let (hash, found) = self.map_find(map, &index, span)?;
+            drop(current);
+            return modify_map(base, span, |map| match found {
+                Some(i) => *map.value_mut(i).expect("the key was found") = value,
+                None => map.push(hash, index, value),
+            });
+        }
+        match self.index_operand(base, index, span)? {
+            Operand::Place(target) => write(&target, span, value),
+            Operand::Value(_) => Err(error(span, "invalid left-hand side of assignment")),
+        }
+    }
+
+    fn key_not_found(&mut self, key: &Value, span: Span) -> Box<RuntimeError> {
+        match self.repr(key, span) {
+            Ok(key) => error(span, format!("key {key} not found in the map")),
+            Err(error) => error,
+        }
+    }
+
+    // Comparisons and hashing
+
+    /// Compares the values for equality, with the `Eq` implementation of structs and enums if
+    /// any. Values of different types are never equal.
+    fn values_eq(&mut self, lhs: &Value, rhs: &Value, span: Span) -> RunResult<bool> {
+        Ok(match (lhs, rhs) {
+            (Value::Ref(lhs), rhs) => {
+                let lhs = read(lhs, span)?;
+                self.values_eq(&lhs, rhs, span)?
+            }
+            (lhs, Value::Ref(rhs)) => {
+                let rhs = read(rhs, span)?;
+                self.values_eq(lhs, &rhs, span)?
+            }
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(lhs), Value::Bool(rhs)) => lhs == rhs,
+            (Value::Int(lhs), Value::Int(rhs)) => lhs == rhs,
+            (Value::Int(_) | Value::Fixed(..), Value::Int(_) | Value::Fixed(..)) => {
+                int_cmp(lhs, rhs) == Some(Ordering::Equal)
+            }
+            (Value::Float(lhs), Value::Float(rhs)) => lhs == rhs,
+            (Value::Str(lhs), Value::Str(rhs)) => lhs == rhs,
+            (Value::Tuple(lhs), Value::Tuple(rhs)) | (Value::Array(lhs), Value::Array(rhs)) => {
+                self.all_eq(lhs, rhs, span)?
+            }
+            (Value::Map(lhs), Value::Map(rhs)) => {
+                if lhs.len() != rhs.len() {
+                    return Ok(false);
+                }
+                for (key, value) in lhs.entries() {
+                    let Some(i) = self.map_find(rhs, key, span)?.1 else {
+                        return Ok(false);
+                    };
+                    if !self.values_eq(value, &rhs.entries()[i].1, span)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+            (Value::Range(lhs), Value::Range(rhs)) => lhs == rhs,
+            (Value::Adt(adt), other @ Value::Adt(_)) => {
+                if let Some(eq) = self.decls().trait_method(lhs, "Eq", "eq") {
+                    if lhs.type_key() != other.type_key() {
+                        return Ok(false);
+                    }
+                    let result = self.call_function(eq, vec![lhs.clone(), other.clone()], span)?;
+                    return self.expect_bool(result, "Eq::eq", span);
+                }
+                let Value::Adt(other) = other else {
+                    unreachable!()
+                };
+                adt.id == other.id
+                    && adt.variant == other.variant
+                    && self.all_eq(&adt.fields, &other.fields, span)?
+            }
+            (Value::Fn(lhs), Value::Fn(rhs)) => lhs == rhs,
+            (Value::Closure(lhs), Value::Closure(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Builtin(lhs), Value::Builtin(rhs)) => lhs == rhs,
+            (Value::Ctor(lhs, i), Value::Ctor(rhs, j)) => lhs == rhs && i == j,
+            _ => false,
+        })
+    }
+
+    fn all_eq(&mut self, lhs: &[Value], rhs: &[Value], span: Span) -> RunResult<bool> {
+        if lhs.len() != rhs.len() {
+            return Ok(false);
+        }
+        for (lhs, rhs) in lhs.iter().zip(rhs) {
+            if !self.values_eq(lhs, rhs, span)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn expect_bool(&self, value: Value, method: &str, span: Span) -> RunResult<bool> {
+        match value {
+            Value::Bool(value) => Ok(value),
+            value => {
+                let message = format!(
+                    "expected `{method}` to return `bool`, found `{}`",
+                    self.decls().type_name(&value)
+                );
+                Err(error(span, message))
+            }
+        }
+    }
+
+    /// Compares the values, with the `Ord` implementation of structs and enums. Returns `None`
+    /// for unordered values, like a NaN float.
+    fn compare(&mut self, lhs: &Value, rhs: &Value, span: Span) -> RunResult<Option<Ordering>> {
+        Ok(match (lhs, rhs) {
+            (Value::Ref(lhs), rhs) => {
+                let lhs = read(lhs, span)?;
+                self.compare(&lhs, rhs, span)?
+            }
+            (lhs, Value::Ref(rhs)) => {
+                let rhs = read(rhs, span)?;
+                self.compare(lhs, &rhs, span)?
+            }
+            (Value::Bool(lhs), Value::Bool(rhs)) => Some(lhs.cmp(rhs)),
+            (Value::Int(lhs), Value::Int(rhs)) => Some(lhs.cmp(rhs)),
+            (Value::Int(_) | Value::Fixed(..), Value::Int(_) | Value::Fixed(..))
+                if int_cmp(lhs, rhs).is_some() =>
+            {
+                int_cmp(lhs, rhs)
+            }
+            (Value::Float(lhs), Value::Float(rhs)) => lhs.partial_cmp(rhs),
+            (Value::Str(lhs), Value::Str(rhs)) => Some(lhs.cmp(rhs)),
+            (Value::Tuple(lhs), Value::Tuple(rhs)) | (Value::Array(lhs), Value::Array(rhs)) => {
+                for (lhs, rhs) in lhs.iter().zip(rhs.iter()) {
+                    match self.compare(lhs, rhs, span)? {
+                        Some(Ordering::Equal) => (),
+                        ordering => return Ok(ordering),
+                    }
+                }
+                Some(lhs.len().cmp(&rhs.len()))
+            }
+            (Value::Adt(_), Value::Adt(_))
+                if lhs.type_key() == rhs.type_key()
+                    && self.decls().trait_method(lhs, "Ord", "cmp").is_some() =>
+            {
+                let cmp = self.decls().trait_method(lhs, "Ord", "cmp");
+                let cmp = cmp.expect("checked above");
+                match self.call_function(cmp, vec![lhs.clone(), rhs.clone()], span)? {
+                    Value::Int(ordering) => Some(ordering.cmp(&0)),
+                    value => {
+                        let message = format!(
+                            "expected `Ord::cmp` to return `i64`, found `{}`",
+                            self.decls().type_name(&value)
+                        );
+                        return Err(error(span, message));
+                    }
+                }
+            }
+            // The variants of the built-in enums are ordered as declared, as by a derived `Ord`
+            (Value::Adt(lhs), Value::Adt(rhs)) if is_builtin_enum(lhs.id) && lhs.id == rhs.id => {
+                match (
+                    lhs.variant.cmp(&rhs.variant),
+                    lhs.fields.first(),
+                    rhs.fields.first(),
+                ) {
+                    (Ordering::Equal, Some(lhs), Some(rhs)) => self.compare(lhs, rhs, span)?,
+                    (ordering, _, _) => Some(ordering),
+                }
+            }
+            _ => {
+                let message = format!(
+                    "cannot compare `{}` with `{}`",
+                    self.decls().type_name(lhs),
+                    self.decls().type_name(rhs)
+                );
+                return Err(error(span, message));
+            }
+        })
+    }
+
+    /// Hashes the value as a map key, with the `Hash` implementation of structs and enums if any.
+    fn hash(&mut self, value: &Value, span: Span) -> RunResult<i64> {
+        let mut hasher = DefaultHasher::new();
+        self.hash_into(value, &mut hasher, span)?;
+        Ok(hasher.finish() as i64)
+    }
+
+    fn hash_into(
+        &mut self,
+        value: &Value,
+        hasher: &mut DefaultHasher,
+        span: Span,
+    ) -> RunResult<()> {
+        // The integers equal to an `i64` of the same value hash alike
+        match value {
+            Value::Fixed(..) => std::mem::discriminant(&Value::Int(0)).hash(hasher),
+            value => std::mem::discriminant(value).hash(hasher),
+        }
+        match value {
+            Value::Nil => (),
+            Value::Bool(value) => value.hash(hasher),
+            Value::Int(value) => i128::from(*value).hash(hasher),
+            Value::Fixed(value, _) => value.hash(hasher),
+            Value::Str(value) => value.hash(hasher),
+            Value::Tuple(values) | Value::Array(values) => {
+                values.len().hash(hasher);
+                for value in values.iter() {
+                    self.hash_into(value, hasher, span)?;
+                }
+            }
+            Value::Adt(adt) => {
+                if let Some(hash) = self.decls().trait_method(value, "Hash", "hash") {
+                    match self.call_function(hash, vec![value.clone()], span)? {
+                        Value::Int(hash) => hash.hash(hasher),
+                        value => {
+                            let message = format!(
+                                "expected `Hash::hash` to return `i64`, found `{}`",
+                                self.decls().type_name(&value)
+                            );
+                            return Err(error(span, message));
+                        }
+                    }
+                } else {
+                    (adt.id, adt.variant).hash(hasher);
+                    for field in &adt.fields {
+                        self.hash_into(field, hasher, span)?;
+                    }
+                }
+            }
+            Value::Ref(place) => {
+                let value = read(place, span)?;
+                self.hash_into(&value, hasher, span)?;
+            }
+            value => {
+                let message = format!(
+                    "the trait bound `{}: Hash` is not satisfied",
+                    self.decls().type_name(value)
+                );
+                return Err(error(span, message));
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks up the key in the map, returning its hash and the index of its entry if found.
+    fn map_find(&mut self, map: &Map, key: &Value, span: Span) -> RunResult<(i64, Option<usize>)> {
+        let hash = self.hash(key, span)?;
+        for index in map.candidates(hash) {
+            if self.values_eq(&map.entries()[*index].0, key, span)? {
+                return Ok((hash, Some(*index)));
+            }
+        }
+        Ok((hash, None))
+    }
+
+    // Printing
+
+    /// Returns the value as printed by `print`, with the `Display` implementation of structs and
+    /// enums if any.
+    fn display(&mut self, value: &Value, span: Span) -> RunResult<String> {
+        let mut out = String::new();
+        self.write_value(&mut out, value, false, span)?;
+        Ok(out)
+    }
+
+    /// Returns the value as printed in an array, quoting strings.
+    fn repr(&mut self, value: &Value, span: Span) -> RunResult<String> {
+        let mut out = String::new();
+        self.write_value(&mut out, value, true, span)?;
+        Ok(out)
+    }
+
+    fn write_value(
+        &mut self,
+        out: &mut String,
+        value: &Value,
+        quoted: bool,
+        span: Span,
+    ) -> RunResult<()> {
+        use std::fmt::Write;
+
+        let decls = self.decls();
+        match value {
+            Value::Nil => out.push_str("nil"),
+            Value::Bool(value) => write!(out, "{value}").unwrap(),
+            Value::Int(value) => write!(out, "{value}").unwrap(),
+            Value::Fixed(value, ty) => out.push_str(&int::to_string(*value, *ty)),
+            Value::Float(value) => write!(out, "{value:?}").unwrap(),
+            Value::Str(value) if quoted => write!(out, "{value:?}").unwrap(),
+            Value::Str(value) => out.push_str(value),
+            Value::Tuple(values) => {
+                out.push('(');
+                self.write_list(out, values, span)?;
+                if values.len() == 1 {
+                    out.push(',');
+                }
+                out.push(')');
+            }
+            Value::Array(values) => {
+                out.push('[');
+                self.write_list(out, values, span)?;
+                out.push(']');
+            }
+            Value::Map(map) => {
+                out.push('{');
+                for (i, (key, value)) in map.entries().iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    self.write_value(out, key, true, span)?;
+                    out.push_str(": ");
+                    self.write_value(out, value, true, span)?;
+                }
+                out.push('}');
+            }
+            Value::Range(range) => {
+                if let Some(start) = range.start {
+                    write!(out, "{start}").unwrap();
+                }
+                out.push_str(if range.inclusive { "..=" } else { ".." });
+                if let Some(end) = range.end {
+                    write!(out, "{end}").unwrap();
+                }
+            }
+            Value::Iter(_) => out.push_str("<iterator>"),
+            Value::Adt(adt) => {
+                if let Some(fmt) = decls.trait_method(value, "Display", "fmt") {
+                    match self.call_function(fmt, vec![value.clone()], span)? {
+                        Value::Str(value) => out.push_str(&value),
+                        value => {
+                            let message = format!(
+                                "expected `Display::fmt` to return `str`, found `{}`",
+                                decls.type_name(&value)
+                            );
+                            return Err(error(span, message));
+                        }
+                    }
+                    return Ok(());
+                }
+                let variant = decls.variant(adt.id, adt.variant);
+                out.push_str(&variant.name);
+                match variant.kind {
+                    VariantKind::Named => {
+                        out.push_str(" { ");
+                        for (i, (name, field)) in variant.fields.iter().zip(&adt.fields).enumerate()
+                        {
+                            if i > 0 {
+                                out.push_str(", ");
+                            }
+                            write!(out, "{name}: ").unwrap();
+                            self.write_value(out, field, true, span)?;
+                        }
+                        out.push_str(" }");
+                    }
+                    VariantKind::Tuple => {
+                        out.push('(');
+                        self.write_list(out, &adt.fields, span)?;
+                        out.push(')');
+                    }
+                    VariantKind::Unit => (),
+                }
+            }
+            Value::Fn(function) => write!(out, "<fn {}>", decls.fn_name(*function)).unwrap(),
+            Value::Closure(_) => out.push_str("<closure>"),
+            Value::Uninit => return Err(uninit_error(span)),
+            Value::Builtin(name) => write!(out, "<fn {name}>").unwrap(),
+            Value::Ctor(id, variant) => {
+                write!(out, "<fn {}>", decls.variant(*id, *variant).name).unwrap()
+            }
+            Value::Ref(place) => {
+                let value = read(place, span)?;
+                self.write_value(out, &value, quoted, span)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_list(&mut self, out: &mut String, values: &[Value], span: Span) -> RunResult<()> {
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            self.write_value(out, value, true, span)?;
+        }
+        Ok(())
+    }
+
+    // Iteration
+
+    /// Starts iterating over the value of a `for` loop, or over the receiver of an iterator method.
+    fn iter(&mut self, value: Value, span: Span) -> RunResult<Iter> {
+        let decls = self.decls();
+        // A mutable reference to an iterator advances the referenced iterator
+        if let Value::Ref(place) = &value {
+            let referenced = read(place, span)?;
+            if let Some(next) = decls.trait_method(&referenced, "Iterator", "next") {
+                return Ok(Iter::Iterator(next, place.clone()));
+            }
+            if let Value::Iter(_) = referenced {
+                return Ok(Iter::Place(place.clone()));
+            }
+            return self.iter(referenced, span);
+        }
+        if let Some(next) = decls.trait_method(&value, "Iterator", "next") {
+            return Ok(Iter::Iterator(next, Rc::new(temporary(value))));
+        }
+        match value {
+            Value::Array(values) => {
+                let len = values.len();
+                Ok(Iter::Array(values, 0, len))
+            }
+            Value::Range(range) => match range.start {
+                Some(start) => Ok(Iter::Range(start, range.exclusive_end())),
+                None => Err(error(span, "cannot iterate over a range without a start")),
+            },
+            Value::Map(map) => {
+                let len = map.len();
+                Ok(Iter::Entries(map, 0, len))
+            }
+            Value::Str(value) => {
+                let len = value.len();
+                Ok(Iter::Chars(value, 0, len))
+            }
+            Value::Iter(iter) => Ok(Rc::unwrap_or_clone(iter)),
+            value => {
+                let message = format!("`{}` is not an iterator", decls.type_name(&value));
+                Err(error(span, message))
+            }
+        }
+    }
+
+    /// Returns the next item of the iteration, if any.
+    fn next(&mut self, iter: &mut Iter, span: Span) -> RunResult<Option<Value>> {
+        Ok(match iter {
+            Iter::Array(values, start, end) => {
+                if start == end {
+                    return Ok(None);
+                }
+                *start += 1;
+                Some(values[*start - 1].clone())
+            }
+            Iter::Range(start, end) => {
+                if end.is_some_and(|end| *start >= end) {
+                    return Ok(None);
+                }
+                let value = *start;
+                match start.checked_add(1) {
+                    Some(next) => *start = next,
+                    // The last value of a range up to the maximum
+                    None => *end = Some(value),
+                }
+                Some(Value::Int(value))
+            }
+            Iter::Entries(map, start, end) => {
+                if start == end {
+                    return Ok(None);
+                }
+                *start += 1;
+                Some(entry(map, *start - 1))
+            }
+            Iter::Chars(value, start, end) => {
+                let c = value[*start..*end].chars().next();
+                if let Some(c) = c {
+                    *start += c.len_utf8();
+                }
+                c.map(|c| Value::str(c.encode_utf8(&mut [0; 4])))
+            }
+            Iter::Iterator(next, place) => {
+                let receiver = Value::Ref(place.clone());
+                match self.call_function(*next, vec![receiver], span)? {
+                    Value::Adt(adt) if adt.id == OPTION_ID => adt.fields.first().cloned(),
+                    value => {
+                        let message = format!(
+                            "expected `Iterator::next` to return `Option`, found `{}`",
+                            self.decls().type_name(&value)
+                        );
+                        return Err(error(span, message));
+                    }
+                }
+            }
+            Iter::Place(place) => {
+                let place = place.clone();
+                self.advance(&place, span)?
+            }
+            Iter::Map(iter, f) => match self.next(iter, span)? {
+                Some(item) => Some(self.call(f.clone(), vec![item], span)?),
+                None => None,
+            },
+            Iter::Filter(iter, predicate) => loop {
+                let Some(item) = self.next(iter, span)? else {
+                    return Ok(None);
+                };
+                if self.test(predicate, &item, span)? {
+                    break Some(item);
+                }
+            },
+            Iter::Enumerate(iter, index) => match self.next(iter, span)? {
+                Some(item) => {
+                    *index += 1;
+                    Some(Value::Tuple(Rc::new(vec![Value::Int(*index - 1), item])))
+                }
+                None => None,
+            },
+            Iter::Zip(first, second) => {
+                let Some(lhs) = self.next(first, span)? else {
+                    return Ok(None);
+                };
+                self.root(&lhs);
+                let Some(rhs) = self.next(second, span)? else {
+                    return Ok(None);
+                };
+                Some(Value::Tuple(Rc::new(vec![lhs, rhs])))
+            }
+            Iter::Take(iter, left) => {
+                if *left == 0 {
+                    return Ok(None);
+                }
+                *left -= 1;
+                self.next(iter, span)?
+            }
+            Iter::Skip(iter, left) => {
+                while *left > 0 {
+                    *left -= 1;
+                    if self.next(iter, span)?.is_none() {
+                        return Ok(None);
+                    }
+                }
+                self.next(iter, span)?
+            }
+            Iter::Chain(first, second) => match self.next(first, span)? {
+                Some(item) => Some(item),
+                None => {
+                    // The chain goes on with the second iteration alone
+                    *iter = std::mem::replace(second, Iter::Range(0, Some(0)));
+                    return self.next(iter, span);
+                }
+            },
+            Iter::Rev(iter) => self.next_back(iter, span)?,
+        })
+    }
+
+    /// Returns the last item of a double-ended iteration, if any.
+    fn next_back(&mut self, iter: &mut Iter, span: Span) -> RunResult<Option<Value>> {
+        Ok(match iter {
+            Iter::Array(values, start, end) => {
+                if start == end {
+                    return Ok(None);
+                }
+                *end -= 1;
+                Some(values[*end].clone())
+            }
+            Iter::Range(start, Some(end)) => {
+                if *start >= *end {
+                    return Ok(None);
+                }
+                *end -= 1;
+                Some(Value::Int(*end))
+            }
+            Iter::Entries(map, start, end) => {
+                if start == end {
+                    return Ok(None);
+                }
+                *end -= 1;
+                Some(entry(map, *end))
+            }
+            Iter::Chars(value, start, end) => {
+                let c = value[*start..*end].chars().next_back();
+                if let Some(c) = c {
+                    *end -= c.len_utf8();
+                }
+                c.map(|c| Value::str(c.encode_utf8(&mut [0; 4])))
+            }
+            Iter::Map(iter, f) => match self.next_back(iter, span)? {
+                Some(item) => Some(self.call(f.clone(), vec![item], span)?),
+                None => None,
+            },
+            Iter::Filter(iter, predicate) => loop {
+                let Some(item) = self.next_back(iter, span)? else {
+                    return Ok(None);
+                };
+                if self.test(predicate, &item, span)? {
+                    break Some(item);
+                }
+            },
+            Iter::Chain(first, second) => match self.next_back(second, span)? {
+                Some(item) => Some(item),
+                None => {
+                    *iter = std::mem::replace(first, Iter::Range(0, Some(0)));
+                    return self.next_back(iter, span);
+                }
+            },
+            Iter::Rev(iter) => self.next(iter, span)?,
+            _ => unreachable!("the iteration is not double-ended"),
+        })
+    }
+
+    /// Advances the iterator value stored in the place, as its `next` method does.
+    fn advance(&mut self, place: &Place, span: Span) -> RunResult<Option<Value>> {
+        // The iterator is taken out of its place while advanced, as the functions it calls can
+        // access the place
+        let mut iter = match place.modify(|value| std::mem::replace(value, Value::Nil)) {
+            Some(Value::Iter(iter)) => Rc::unwrap_or_clone(iter),
+            Some(value) => {
+                let message = format!("`{}` is not an iterator", self.decls().type_name(&value));
+                write(place, span, value)?;
+                return Err(error(span, message));
+            }
+            None => return Err(error(span, "the referenced value no longer exists")),
+        };
+        if let Some(roots) = self.roots() {
+            roots.push(Value::Iter(Rc::new(iter.clone())));
+        }
+        let item = self.next(&mut iter, span);
+        write(place, span, Value::Iter(Rc::new(iter)))?;
+        item
+    }
+
+    /// Calls the predicate of a `filter` adapter with the item.
+    fn test(&mut self, predicate: &Value, item: &Value, span: Span) -> RunResult<bool> {
+        self.root(item);
+        match self.call(predicate.clone(), vec![item.clone()], span)? {
+            Value::Bool(value) => Ok(value),
+            value => {
+                let message = format!(
+                    "expected the predicate of `filter` to return `bool`, found `{}`",
+                    self.decls().type_name(&value)
+                );
+                Err(error(span, message))
+            }
+        }
+    }
+}
+
+/// Returns the entry of the map at the given index as a `(key, value)` tuple.
+fn entry(map: &Map, index: usize) -> Value {
+    let (key, value) = &map.entries()[index];
+    Value::Tuple(Rc::new(vec![key.clone(), value.clone()]))
+}
+
+/// Converts the value of a constant.
+pub(crate) fn from_const(value: &ConstValue) -> Value {
+    match value {
+        ConstValue::Int(value, ty) => Value::int(*value, *ty),
+        ConstValue::Float(value) => Value::Float(*value),
+        ConstValue::Bool(value) => Value::Bool(*value),
+        ConstValue::Str(value) => Value::str(value),
+        ConstValue::Nil => Value::Nil,
+        ConstValue::Tuple(values) => Value::Tuple(Rc::new(values.iter().map(from_const).collect())),
+        ConstValue::Array(values) => Value::Array(Rc::new(values.iter().map(from_const).collect())),
+    }
+}
+
+/// Returns the value of a literal, negated for the operand of a negation so that the minimum of
+/// a signed type can be written, or the error if it is out of the range of its type.
+pub(crate) fn lit_value(lit: &Lit, negative: bool) -> Result<Value, String> {
+    Ok(match lit {
+        Lit::Int(lit) => {
+            let ty = match lit.suffix.as_deref() {
+                None => IntTy::I64,
+                Some("f32" | "f64") => {
+                    let value = lit.value as f64;
+                    return Ok(Value::Float(if negative { -value } else { value }));
+                }
+                Some(suffix) => IntTy::from_name(suffix)
+                    .ok_or_else(|| format!("invalid suffix `{suffix}` for number literal"))?,
+            };
+            match int::lit(lit.value, negative, ty) {
+                Some(value) => Value::int(value, ty),
+                None => return Err(format!("literal out of range for `{ty}`")),
+            }
+        }
+        Lit::Float(lit) if negative => Value::Float(-lit.value),
+        Lit::Float(lit) => Value::Float(lit.value),
+        Lit::Str(value) => Value::str(value),
+        Lit::Bool(value) => Value::Bool(*value),
+        Lit::Nil => Value::Nil,
+    })
+}
+
+pub(crate) fn check_arity(what: &str, expected: usize, found: usize, span: Span) -> RunResult<()> {
+    if expected == found {
+        return Ok(());
+    }
+    let arguments = |count: usize| match count {
+        1 => String::from("1 argument"),
+        count => format!("{count} arguments"),
+    };
+    let verb = if found == 1 { "was" } else { "were" };
+    let message = format!(
+        "this {what} takes {} but {} {verb} supplied",
+        arguments(expected),
+        arguments(found)
+    );
+    Err(error(span, message))
+}
+
+/// Returns the index of an array element, given by an integer of any type, checking that it is in
+/// bounds.
+pub(crate) fn array_index(len: usize, index: &Value, span: Span) -> RunResult<usize> {
+    let (index, ty) = index.as_int().expect("the index is an integer");
+    let position = match ty {
+        IntTy::U128 => usize::try_from(index as u128).ok(),
+        _ => usize::try_from(index).ok(),
+    };
+    match position {
+        Some(position) if position < len => Ok(position),
+        _ => {
+            let message = format!(
+                "index out of bounds: the length is {len} but the index is {}",
+                int::to_string(index, ty)
+            );
+            Err(error(span, message))
+        }
+    }
+}
+
+/// Returns the type the values of a built-in type have at run time: floats are all represented as
+/// `f64`, and the built-in enums are enums with reserved ids.
+pub(crate) fn builtin_type_key(name: &'static str) -> TypeKey {
+    match (name, builtin_enum_id(name)) {
+        (_, Some(id)) => TypeKey::Adt(id),
+        ("f32", None) => TypeKey::Builtin("f64"),
+        (name, None) => TypeKey::Builtin(name),
+    }
+}
+
+pub(crate) fn is_builtin_enum(id: NodeId) -> bool {
+    matches!(id, OPTION_ID | RESULT_ID)
+}
+
+/// Returns the built-in enum named by the return type of a function or closure, which the values
+/// returned by its `?` operators must be of.
+pub(crate) fn returned_enum(ret: Option<&Type>, resolutions: &Resolutions) -> Option<NodeId> {
+    let ty = ret?;
+    match (&ty.kind, resolutions.names.get(&ty.id)?) {
+        (TypeKind::Path(..), Res::Builtin(name)) => builtin_enum_id(name),
+        _ => None,
+    }
+}
+
+fn builtin_enum_id(name: &str) -> Option<NodeId> {
+    match name {
+        "Option" => Some(OPTION_ID),
+        "Result" => Some(RESULT_ID),
+        _ => None,
+    }
+}
+
+/// Returns the variant of a built-in enum with the given name, as the id of the enum and the
+/// index of the variant.
+pub(crate) fn builtin_variant(name: &str) -> Option<(NodeId, usize)> {
+    BUILTIN_ENUMS.iter().find_map(|(enum_name, variants)| {
+        let index = variants.iter().position(|(variant, _)| *variant == name)?;
+        Some((builtin_enum_id(enum_name)?, index))
+    })
+}
+
+/// Returns `Some(value)`, or `None` without a value.
+pub(crate) fn option(value: Option<Value>) -> Value {
+    let (variant, fields) = match value {
+        Some(value) => (1, vec![value]),
+        None => (0, vec![]),
+    };
+    Value::Adt(Rc::new(Adt {
+        id: OPTION_ID,
+        variant,
+        fields,
+    }))
+}
+
+/// Returns `Ok(value)` or `Err(error)`.
+pub(crate) fn result(value: Result<Value, Value>) -> Value {
+    let (variant, value) = match value {
+        Ok(value) => (0, value),
+        Err(error) => (1, error),
+    };
+    Value::Adt(Rc::new(Adt {
+        id: RESULT_ID,
+        variant,
+        fields: vec![value],
+    }))
+}
+
+/// Returns the type of the result of an operator applied to integers. An `i64` operand takes the
+/// type of the other operand, as an integer literal without suffix does, and the result of a
+/// shift has the type of its left operand. Returns `None` for operands of different types.
+fn fixed_ty(op: BinOp, lhs: &Value, rhs: &Value) -> Option<IntTy> {
+    let (_, lhs_ty) = lhs.as_int()?;
+    let (_, rhs_ty) = rhs.as_int()?;
+    match (lhs_ty, rhs_ty) {
+        (ty, _) if matches!(op, BinOp::Shl | BinOp::Shr) => Some(ty),
+        (lhs_ty, rhs_ty) if lhs_ty == rhs_ty => Some(lhs_ty),
+        (IntTy::I64, ty) | (ty, IntTy::I64) if op.is_arithmetic() || op.is_bitwise() => Some(ty),
+        _ => None,
+    }
+}
+
+/// Applies an arithmetic, bitwise or shift operator to integers, the result having the given
+/// type, checking for overflows.
+fn fixed_op(op: BinOp, lhs: &Value, rhs: &Value, ty: IntTy) -> Result<Value, String> {
+    let (lhs, lhs_ty) = lhs.as_int().expect("the operand is an integer");
+    let (rhs, rhs_ty) = rhs.as_int().expect("the operand is an integer");
+    let value = if matches!(op, BinOp::Shl | BinOp::Shr) {
+        let amount = match rhs_ty {
+            IntTy::U128 => u32::try_from(rhs as u128).ok(),
+            _ => u32::try_from(rhs).ok(),
+        };
+        int::shift(op, lhs, amount, ty)
+    } else {
+        for (value, value_ty) in [(lhs, lhs_ty), (rhs, rhs_ty)] {
+            if value_ty != ty && !int::in_range(value, ty) {
+                return Err(format!("integer {value} out of range for `{ty}`"));
+            }
+        }
+        int::int_op(op, lhs, rhs, ty)
+    };
+    value
+        .map(|value| Value::int(value, ty))
+        .map_err(|err| err.message(op).to_string())
+}
+
+/// Compares integers of the same type, or an `i64` with an integer of any type. Returns `None`
+/// for integers of different types.
+fn int_cmp(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    let lhs = lhs.as_int()?;
+    let rhs = rhs.as_int()?;
+    if lhs.1 != rhs.1 && lhs.1 != IntTy::I64 && rhs.1 != IntTy::I64 {
+        return None;
+    }
+    Some(int::cmp(lhs, rhs))
+}
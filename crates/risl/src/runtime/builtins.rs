@@ -0,0 +1,854 @@
+//! The built-in functions, and the methods of the built-in types and enums.
+
+use std::rc::Rc;
+
+use super::{
+    array_index, check_arity, deref, error, modify_map, option, output_error, result, temporary,
+    Operand, RunResult, Runtime, RuntimeError, OPTION_ID, RESULT_ID,
+};
+use crate::int::{self, IntTy};
+use crate::parser::ast::BinOp;
+use crate::parser::lexer::Span;
+use crate::value::{Iter, Map, Place, Value};
+
+/// The methods of the built-in types, with their number of parameters.
+static ANY_METHODS: &[(&str, usize)] = &[("clone", 0), ("to_string", 0)];
+
+static ARRAY_METHODS: &[(&str, usize)] = &[
+    ("clear", 0),
+    ("contains", 1),
+    ("extend", 1),
+    ("first", 0),
+    ("get", 1),
+    ("insert", 2),
+    ("is_empty", 0),
+    ("iter", 0),
+    ("join", 1),
+    ("last", 0),
+    ("len", 0),
+    ("pop", 0),
+    ("push", 1),
+    ("remove", 1),
+    ("reverse", 0),
+    ("sort", 0),
+];
+
+static STR_METHODS: &[(&str, usize)] = &[
+    ("chars", 0),
+    ("contains", 1),
+    ("ends_with", 1),
+    ("is_empty", 0),
+    ("len", 0),
+    ("repeat", 1),
+    ("replace", 2),
+    ("split", 1),
+    ("starts_with", 1),
+    ("to_lowercase", 0),
+    ("to_uppercase", 0),
+    ("trim", 0),
+];
+
+static MAP_METHODS: &[(&str, usize)] = &[
+    ("clear", 0),
+    ("contains_key", 1),
+    ("get", 1),
+    ("insert", 2),
+    ("is_empty", 0),
+    ("iter", 0),
+    ("keys", 0),
+    ("len", 0),
+    ("remove", 1),
+    ("values", 0),
+];
+
+static INT_METHODS: &[(&str, usize)] = &[
+    ("abs", 0),
+    ("checked_add", 1),
+    ("checked_div", 1),
+    ("checked_mul", 1),
+    ("checked_sub", 1),
+    ("max", 1),
+    ("min", 1),
+    ("pow", 1),
+    ("saturating_add", 1),
+    ("saturating_mul", 1),
+    ("saturating_sub", 1),
+    ("wrapping_add", 1),
+    ("wrapping_mul", 1),
+    ("wrapping_sub", 1),
+];
+
+static FLOAT_METHODS: &[(&str, usize)] = &[
+    ("abs", 0),
+    ("ceil", 0),
+    ("floor", 0),
+    ("max", 1),
+    ("min", 1),
+    ("pow", 1),
+    ("round", 0),
+    ("sqrt", 0),
+];
+
+/// The methods of the built-in types modifying the array or map they are called on, as if they
+/// took `&mut self`.
+pub(crate) static BUILTIN_MUT_METHODS: &[&str] = &[
+    "clear", "extend", "insert", "pop", "push", "remove", "reverse", "sort",
+];
+
+static RANGE_METHODS: &[(&str, usize)] = &[("contains", 1)];
+
+static ITER_METHODS: &[(&str, usize)] = &[("next", 0)];
+
+/// The methods of the iterators, of the ranges and of the values implementing `Iterator`. The
+/// adapters return a new iterator, advancing the receiver lazily, while `fold` and `collect`
+/// consume it.
+static ITERATOR_METHODS: &[(&str, usize)] = &[
+    ("chain", 1),
+    ("collect", 0),
+    ("enumerate", 0),
+    ("filter", 1),
+    ("fold", 2),
+    ("map", 1),
+    ("rev", 0),
+    ("skip", 1),
+    ("take", 1),
+    ("zip", 1),
+];
+
+static OPTION_METHODS: &[(&str, usize)] = &[
+    ("and_then", 1),
+    ("expect", 1),
+    ("filter", 1),
+    ("is_none", 0),
+    ("is_some", 0),
+    ("map", 1),
+    ("ok_or", 1),
+    ("or_else", 1),
+    ("unwrap", 0),
+    ("unwrap_or", 1),
+    ("unwrap_or_else", 1),
+];
+
+static RESULT_METHODS: &[(&str, usize)] = &[
+    ("and_then", 1),
+    ("err", 0),
+    ("expect", 1),
+    ("is_err", 0),
+    ("is_ok", 0),
+    ("map", 1),
+    ("map_err", 1),
+    ("ok", 0),
+    ("or_else", 1),
+    ("unwrap", 0),
+    ("unwrap_err", 0),
+    ("unwrap_or", 1),
+    ("unwrap_or_else", 1),
+];
+
+fn methods(value: &Value) -> &'static [(&'static str, usize)] {
+    match value {
+        Value::Array(_) => ARRAY_METHODS,
+        Value::Str(_) => STR_METHODS,
+        Value::Map(_) => MAP_METHODS,
+        Value::Int(_) | Value::Fixed(..) => INT_METHODS,
+        Value::Float(_) => FLOAT_METHODS,
+        Value::Range(_) => RANGE_METHODS,
+        Value::Iter(_) => ITER_METHODS,
+        Value::Adt(adt) if adt.id == OPTION_ID => OPTION_METHODS,
+        Value::Adt(adt) if adt.id == RESULT_ID => RESULT_METHODS,
+        _ => &[],
+    }
+}
+
+/// Returns the number of parameters of the built-in method of the value, if it has one.
+fn method_arity(value: &Value, name: &str) -> Option<usize> {
+    let iterator_methods = match value {
+        Value::Range(_) | Value::Iter(_) => ITERATOR_METHODS,
+        _ => &[],
+    };
+    methods(value)
+        .iter()
+        .chain(ANY_METHODS)
+        .chain(iterator_methods)
+        .find(|(method, _)| *method == name)
+        .map(|(_, arity)| *arity)
+}
+
+/// Returns the number of parameters of the iterator method, which the values implementing
+/// `Iterator` have without declaring it.
+fn iterator_method_arity(name: &str) -> Option<usize> {
+    ITERATOR_METHODS
+        .iter()
+        .find(|(method, _)| *method == name)
+        .map(|(_, arity)| *arity)
+}
+
+/// Returns true if the value has a built-in method with the given name.
+pub(crate) fn has_method(value: &Value, name: &str) -> bool {
+    method_arity(value, name).is_some()
+}
+
+/// Returns true if the values implementing `Iterator` have a built-in method with the given name.
+pub(crate) fn is_iterator_method(name: &str) -> bool {
+    iterator_method_arity(name).is_some()
+}
+
+/// Returns the built-in associated function of the built-in type, if any.
+pub(crate) fn associated_function(ty: &str, name: &str) -> Option<&'static str> {
+    match (ty, name) {
+        ("Map", "new") => Some("Map::new"),
+        _ => None,
+    }
+}
+
+/// The built-in functions and methods, available to every runtime.
+pub(crate) trait Builtins<'a>: Runtime<'a> {
+    fn call_builtin(&mut self, name: &str, args: Vec<Value>, span: Span) -> RunResult {
+        match name {
+            "print" | "println" => {
+                let mut line = String::new();
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        line.push(' ');
+                    }
+                    line.push_str(&self.display(arg, span)?);
+                }
+                if name == "println" {
+                    line.push('\n');
+                }
+                self.output()
+                    .write_all(line.as_bytes())
+                    .map_err(|err| error(span, output_error(err)))?;
+            }
+            "assert" => {
+                if !(1..=2).contains(&args.len()) {
+                    check_arity("function", 1, args.len(), span)?;
+                }
+                if !self.bool_arg(&args[0], span)? {
+                    let message = match args.get(1) {
+                        Some(message) => {
+                            format!("assertion failed: {}", self.display(message, span)?)
+                        }
+                        None => String::from("assertion failed"),
+                    };
+                    return Err(error(span, message));
+                }
+            }
+            "assert_eq" => {
+                if !(2..=3).contains(&args.len()) {
+                    check_arity("function", 2, args.len(), span)?;
+                }
+                if !self.values_eq(&args[0], &args[1], span)? {
+                    let mut message = String::from("assertion `left == right` failed");
+                    if let Some(details) = args.get(2) {
+                        message = format!("{message}: {}", self.display(details, span)?);
+                    }
+                    let left = self.repr(&args[0], span)?;
+                    let right = self.repr(&args[1], span)?;
+                    message = format!("{message}\n  left: {left}\n right: {right}");
+                    return Err(error(span, message));
+                }
+            }
+            "panic" => {
+                check_arity("function", 1, args.len(), span)?;
+                return Err(error(span, self.display(&args[0], span)?));
+            }
+            "Map::new" => {
+                check_arity("function", 0, args.len(), span)?;
+                return Ok(Value::Map(Rc::new(Map::default())));
+            }
+            "catch_unwind" => {
+                check_arity("function", 1, args.len(), span)?;
+                let callee = args.into_iter().next().expect("the arity was checked");
+                return Ok(match self.call(callee, vec![], span) {
+                    Ok(value) => result(Ok(value)),
+                    Err(error) => result(Err(Value::str(&error.message))),
+                });
+            }
+            _ => {
+                let message = format!("unknown built-in function `{name}`");
+                return Err(error(span, message));
+            }
+        }
+        Ok(Value::unit())
+    }
+
+    /// Calls the built-in method of the receiver, whose value is given, which must have been
+    /// checked with [`has_method`].
+    fn call_builtin_method(
+        &mut self,
+        receiver: Operand,
+        value: Value,
+        name: &str,
+        args: Vec<Value>,
+        span: Span,
+    ) -> RunResult {
+        let arity = method_arity(&value, name)
+            .or_else(|| iterator_method_arity(name))
+            .expect("the method exists");
+        check_arity("method", arity, args.len(), span)?;
+        // The mutating methods modify the receiver in place
+        let place = match receiver {
+            Operand::Place(place) => place,
+            Operand::Value(value) => temporary(value),
+        };
+        // The receiver and the arguments stay alive while the method calls back into the program
+        self.rooted(|this| {
+            this.root(&Value::Ref(Rc::new(place.clone())));
+            this.root(&value);
+            for arg in &args {
+                this.root(arg);
+            }
+            this.builtin_method(&place, value, name, args, span)
+        })
+    }
+
+    fn builtin_method(
+        &mut self,
+        place: &Place,
+        value: Value,
+        name: &str,
+        args: Vec<Value>,
+        span: Span,
+    ) -> RunResult {
+        match (name, value) {
+            ("clone", value) => Ok(value),
+            ("to_string", value) => Ok(Value::str(&self.display(&value, span)?)),
+            (name, Value::Array(values)) => self.array_method(place, values, name, args, span),
+            (name, Value::Str(value)) => self.str_method(&value, name, args, span),
+            (name, Value::Map(map)) => self.map_method(place, map, name, args, span),
+            (name, value @ (Value::Int(_) | Value::Fixed(..))) => {
+                let (value, ty) = value.as_int().expect("the receiver is an integer");
+                self.int_method(value, ty, name, &args, span)
+            }
+            (name, Value::Float(value)) => self.float_method(value, name, &args, span),
+            ("contains", Value::Range(range)) => {
+                let value = self.int_arg(&args[0], span)?;
+                let after_start = range.start.is_none_or(|start| start <= value);
+                let before_end = range.exclusive_end().is_none_or(|end| value < end);
+                Ok(Value::Bool(after_start && before_end))
+            }
+            (name, Value::Adt(adt)) if adt.id == OPTION_ID => {
+                let value = adt.fields.first().cloned();
+                self.option_method(value, name, args, span)
+            }
+            (name, Value::Adt(adt)) if adt.id == RESULT_ID => {
+                let value = adt.fields[0].clone();
+                let value = if adt.variant == 0 {
+                    Ok(value)
+                } else {
+                    Err(value)
+                };
+                self.result_method(value, name, args, span)
+            }
+            (name, value) => self.iterator_method(place, value, name, args, span),
+        }
+    }
+
+    fn array_method(
+        &mut self,
+        place: &Place,
+        values: Rc<Vec<Value>>,
+        name: &str,
+        mut args: Vec<Value>,
+        span: Span,
+    ) -> RunResult {
+        let len = values.len();
+        let value = match name {
+            "len" => Value::Int(len as i64),
+            "is_empty" => Value::Bool(values.is_empty()),
+            "contains" => {
+                for value in values.iter() {
+                    if self.values_eq(value, &args[0], span)? {
+                        return Ok(Value::Bool(true));
+                    }
+                }
+                Value::Bool(false)
+            }
+            "iter" => Value::Iter(Rc::new(Iter::Array(values.clone(), 0, len))),
+            "first" => option(values.first().cloned()),
+            "last" => option(values.last().cloned()),
+            "get" => {
+                let index = self.int_arg(&args[0], span)?;
+                let index = usize::try_from(index).ok();
+                option(index.and_then(|index| values.get(index)).cloned())
+            }
+            "join" => {
+                let separator = self.str_arg(&args[0], span)?;
+                let mut parts = vec![];
+                for value in values.iter() {
+                    parts.push(self.display(value, span)?);
+                }
+                Value::str(&parts.join(&separator))
+            }
+            "sort" => {
+                let mut sorted = values.to_vec();
+                drop(values);
+                let mut failure = None;
+                sorted.sort_by(|lhs, rhs| match self.compare(lhs, rhs, span) {
+                    Ok(ordering) => ordering.unwrap_or(std::cmp::Ordering::Equal),
+                    Err(control) => {
+                        failure.get_or_insert(control);
+                        std::cmp::Ordering::Equal
+                    }
+                });
+                if let Some(control) = failure {
+                    return Err(control);
+                }
+                modify_array(place, span, |values| *values = sorted)?;
+                Value::unit()
+            }
+            name => {
+                // The other methods modify the array, which must not be shared to be modified in
+                // place
+                drop(values);
+                match name {
+                    "push" => {
+                        let value = args.pop().expect("the arity was checked");
+                        modify_array(place, span, |values| values.push(value))?;
+                        Value::unit()
+                    }
+                    "pop" => option(modify_array(place, span, Vec::pop)?),
+                    "insert" => {
+                        let value = args.pop().expect("the arity was checked");
+                        let index = self.int_arg(&args[0], span)?;
+                        if !(0..=len as i64).contains(&index) {
+                            let message =
+                                format!("insertion index (is {index}) should be <= len (is {len})");
+                            return Err(error(span, message));
+                        }
+                        let index = index as usize;
+                        modify_array(place, span, |values| values.insert(index, value))?;
+                        Value::unit()
+                    }
+                    "remove" => {
+                        self.int_arg(&args[0], span)?;
+                        let index = array_index(len, &args[0], span)?;
+                        modify_array(place, span, |values| values.remove(index))?
+                    }
+                    "clear" => {
+                        modify_array(place, span, Vec::clear)?;
+                        Value::unit()
+                    }
+                    "reverse" => {
+                        modify_array(place, span, |values| values.reverse())?;
+                        Value::unit()
+                    }
+                    "extend" => {
+                        let Value::Array(other) = deref(args.pop().unwrap(), span)? else {
+                            return Err(self.type_error("array", &args[0], span));
+                        };
+                        modify_array(place, span, |values| values.extend(other.iter().cloned()))?;
+                        Value::unit()
+                    }
+                    _ => unreachable!("unknown array method `{name}`"),
+                }
+            }
+        };
+        Ok(value)
+    }
+
+    fn str_method(&mut self, value: &str, name: &str, args: Vec<Value>, span: Span) -> RunResult {
+        let strings = |parts: Vec<&str>| {
+            let parts = parts.into_iter().map(Value::str).collect();
+            Value::Array(Rc::new(parts))
+        };
+        Ok(match name {
+            "len" => Value::Int(value.len() as i64),
+            "is_empty" => Value::Bool(value.is_empty()),
+            "contains" => Value::Bool(value.contains(&*self.str_arg(&args[0], span)?)),
+            "starts_with" => Value::Bool(value.starts_with(&*self.str_arg(&args[0], span)?)),
+            "ends_with" => Value::Bool(value.ends_with(&*self.str_arg(&args[0], span)?)),
+            "to_uppercase" => Value::str(&value.to_uppercase()),
+            "to_lowercase" => Value::str(&value.to_lowercase()),
+            "trim" => Value::str(value.trim()),
+            "split" => strings(value.split(&*self.str_arg(&args[0], span)?).collect()),
+            "chars" => Value::Iter(Rc::new(Iter::Chars(Rc::from(value), 0, value.len()))),
+            "repeat" => {
+                let count = self.int_arg(&args[0], span)?;
+                match usize::try_from(count) {
+                    Ok(count) => Value::str(&value.repeat(count)),
+                    Err(_) => {
+                        let message = format!("cannot repeat a string {count} times");
+                        return Err(error(span, message));
+                    }
+                }
+            }
+            "replace" => {
+                let from = self.str_arg(&args[0], span)?;
+                let to = self.str_arg(&args[1], span)?;
+                Value::str(&value.replace(&*from, &to))
+            }
+            _ => unreachable!("unknown str method `{name}`"),
+        })
+    }
+
+    fn map_method(
+        &mut self,
+        place: &Place,
+        map: Rc<Map>,
+        name: &str,
+        mut args: Vec<Value>,
+        span: Span,
+    ) -> RunResult {
+        let value = match name {
+            "len" => Value::Int(map.len() as i64),
+            "is_empty" => Value::Bool(map.is_empty()),
+            "contains_key" => Value::Bool(self.map_find(&map, &args[0], span)?.1.is_some()),
+            "get" => {
+                let found = self.map_find(&map, &args[0], span)?.1;
+                option(found.map(|i| map.entries()[i].1.clone()))
+            }
+            "iter" => {
+                let len = map.len();
+                Value::Iter(Rc::new(Iter::Entries(map, 0, len)))
+            }
+            "keys" => {
+                let keys = map.entries().iter().map(|(key, _)| key.clone());
+                Value::Array(Rc::new(keys.collect()))
+            }
+            "values" => {
+                let values = map.entries().iter().map(|(_, value)| value.clone());
+                Value::Array(Rc::new(values.collect()))
+            }
+            "insert" => {
+                let value = args.pop().expect("the arity was checked");
+                let key = args.pop().expect("the arity was checked");
+                let (hash, found) = self.map_find(&map, &key, span)?;
+                drop(map);
+                option(modify_map(place, span, |map| match found {
+                    Some(i) => {
+                        let current = map.value_mut(i).expect("the key was found");
+                        Some(std::mem::replace(current, value))
+                    }
+                    None => {
+                        map.push(hash, key, value);
+                        None
+                    }
+                })?)
+            }
+            "remove" => match self.map_find(&map, &args[0], span)?.1 {
+                Some(i) => {
+                    drop(map);
+                    option(Some(modify_map(place, span, |map| map.remove(i))?))
+                }
+                None => option(None),
+            },
+            "clear" => {
+                drop(map);
+                modify_map(place, span, Map::clear)?;
+                Value::unit()
+            }
+            _ => unreachable!("unknown map method `{name}`"),
+        };
+        Ok(value)
+    }
+
+    /// Calls a method of an iterator, or of a value iterated over like a range.
+    fn iterator_method(
+        &mut self,
+        place: &Place,
+        value: Value,
+        name: &str,
+        mut args: Vec<Value>,
+        span: Span,
+    ) -> RunResult {
+        if name == "next" {
+            return Ok(option(self.advance(place, span)?));
+        }
+        // The other methods consume a copy of the receiver, as they take it by value
+        let mut iter = self.iter(value, span)?;
+        let adapter = match name {
+            "map" => Iter::Map(Box::new(iter), args.pop().expect("the arity was checked")),
+            "filter" => Iter::Filter(Box::new(iter), args.pop().expect("the arity was checked")),
+            "enumerate" => Iter::Enumerate(Box::new(iter), 0),
+            "zip" | "chain" => {
+                let other = self.iter(args.pop().expect("the arity was checked"), span)?;
+                match name {
+                    "zip" => Iter::Zip(Box::new(iter), Box::new(other)),
+                    _ => Iter::Chain(Box::new(iter), Box::new(other)),
+                }
+            }
+            "take" | "skip" => {
+                let count = self.int_arg(&args[0], span)?;
+                let Ok(count) = usize::try_from(count) else {
+                    return Err(error(span, format!("cannot {name} {count} items")));
+                };
+                match name {
+                    "take" => Iter::Take(Box::new(iter), count),
+                    _ => Iter::Skip(Box::new(iter), count),
+                }
+            }
+            "rev" if iter.is_double_ended() => Iter::Rev(Box::new(iter)),
+            "rev" => {
+                // The iterations which cannot be advanced from their end are collected first
+                let mut items = self.collect(iter, span)?;
+                items.reverse();
+                let len = items.len();
+                Iter::Array(Rc::new(items), 0, len)
+            }
+            "fold" => {
+                let f = args.pop().expect("the arity was checked");
+                let mut accumulator = args.pop().expect("the arity was checked");
+                while let Some(item) = self.next(&mut iter, span)? {
+                    accumulator = self.call(f.clone(), vec![accumulator, item], span)?;
+                }
+                return Ok(accumulator);
+            }
+            "collect" => return Ok(Value::Array(Rc::new(self.collect(iter, span)?))),
+            _ => unreachable!("unknown iterator method `{name}`"),
+        };
+        Ok(Value::Iter(Rc::new(adapter)))
+    }
+
+    /// Returns the items left of the iteration.
+    fn collect(&mut self, mut iter: Iter, span: Span) -> RunResult<Vec<Value>> {
+        let mut items = vec![];
+        while let Some(item) = self.next(&mut iter, span)? {
+            self.root(&item);
+            items.push(item);
+        }
+        Ok(items)
+    }
+
+    fn type_error(&self, expected: &str, found: &Value, span: Span) -> Box<RuntimeError> {
+        let message = format!(
+            "expected `{expected}`, found `{}`",
+            self.decls().type_name(found)
+        );
+        error(span, message)
+    }
+
+    fn bool_arg(&self, value: &Value, span: Span) -> RunResult<bool> {
+        match value {
+            Value::Bool(value) => Ok(*value),
+            value => Err(self.type_error("bool", value, span)),
+        }
+    }
+
+    /// Returns the value of an integer argument, of any type if it fits in an `i64`.
+    fn int_arg(&self, value: &Value, span: Span) -> RunResult<i64> {
+        match (value.as_i64(), value.as_int()) {
+            (Some(value), _) => Ok(value),
+            (None, Some((value, ty))) => {
+                let message = format!(
+                    "integer {} out of range for `i64`",
+                    int::to_string(value, ty)
+                );
+                Err(error(span, message))
+            }
+            (None, None) => Err(self.type_error("i64", value, span)),
+        }
+    }
+
+    /// Returns the value of an integer argument of the given type, which an `i64` is converted to.
+    fn typed_int_arg(&self, value: &Value, ty: IntTy, span: Span) -> RunResult<i128> {
+        match value.as_int() {
+            Some((value, value_ty)) if value_ty == ty => Ok(value),
+            Some((value, IntTy::I64)) if int::in_range(value, ty) => Ok(value),
+            Some((value, IntTy::I64)) => {
+                let message = format!("integer {value} out of range for `{ty}`");
+                Err(error(span, message))
+            }
+            _ => Err(self.type_error(ty.name(), value, span)),
+        }
+    }
+
+    fn float_arg(&self, value: &Value, span: Span) -> RunResult<f64> {
+        match value {
+            Value::Float(value) => Ok(*value),
+            value => Err(self.type_error("f64", value, span)),
+        }
+    }
+
+    fn str_arg(&self, value: &Value, span: Span) -> RunResult<Rc<str>> {
+        match value {
+            Value::Str(value) => Ok(value.clone()),
+            value => Err(self.type_error("str", value, span)),
+        }
+    }
+
+    /// Calls a method of an integer of the given type. The `checked_*` methods return `None` on
+    /// overflow.
+    fn int_method(
+        &self,
+        value: i128,
+        ty: IntTy,
+        name: &str,
+        args: &[Value],
+        span: Span,
+    ) -> RunResult {
+        let arg = || self.typed_int_arg(&args[0], ty, span);
+        let op = |name: &str| match name.rsplit('_').next() {
+            Some("add") => BinOp::Add,
+            Some("sub") => BinOp::Sub,
+            Some("mul") => BinOp::Mul,
+            _ => BinOp::Div,
+        };
+        let value = match name {
+            "abs" if value < 0 && ty.is_signed() => int::int_op(BinOp::Sub, 0, value, ty)
+                .map_err(|_| error(span, "attempt to negate with overflow"))?,
+            "abs" => value,
+            "min" | "max" => {
+                let arg = arg()?;
+                let ordering = int::cmp((arg, ty), (value, ty));
+                let take_arg = if name == "min" {
+                    ordering.is_lt()
+                } else {
+                    ordering.is_gt()
+                };
+                if take_arg {
+                    arg
+                } else {
+                    value
+                }
+            }
+            "pow" => {
+                let exponent = self.int_arg(&args[0], span)?;
+                let exponent = u32::try_from(exponent).map_err(|_| {
+                    error(span, "the exponent of `pow` must be a non-negative `u32`")
+                })?;
+                pow(value, exponent, ty)
+                    .ok_or_else(|| error(span, "attempt to multiply with overflow"))?
+            }
+            name if name.starts_with("checked_") => {
+                let value = int::int_op(op(name), value, arg()?, ty).ok();
+                return Ok(option(value.map(|value| Value::int(value, ty))));
+            }
+            name if name.starts_with("saturating_") => {
+                int::saturating_op(op(name), value, arg()?, ty)
+            }
+            name if name.starts_with("wrapping_") => int::wrapping_op(op(name), value, arg()?, ty),
+            _ => unreachable!("unknown integer method `{name}`"),
+        };
+        Ok(Value::int(value, ty))
+    }
+
+    /// Calls a method of an `Option`, given as the value of a `Some` or `None`.
+    fn option_method(
+        &mut self,
+        value: Option<Value>,
+        name: &str,
+        args: Vec<Value>,
+        span: Span,
+    ) -> RunResult {
+        // Only used by the methods taking an argument, whose arity was checked
+        let arg = args.into_iter().next().unwrap_or(Value::Nil);
+        Ok(match (name, value) {
+            ("is_some", value) => Value::Bool(value.is_some()),
+            ("is_none", value) => Value::Bool(value.is_none()),
+            ("unwrap" | "expect" | "unwrap_or" | "unwrap_or_else", Some(value)) => value,
+            ("unwrap", None) => {
+                return Err(error(span, "called `Option::unwrap()` on a `None` value"));
+            }
+            ("expect", None) => return Err(error(span, self.display(&arg, span)?)),
+            ("unwrap_or", None) => arg,
+            ("unwrap_or_else", None) => self.call(arg, vec![], span)?,
+            ("map", Some(value)) => option(Some(self.call(arg, vec![value], span)?)),
+            ("and_then", Some(value)) => self.call(arg, vec![value], span)?,
+            ("filter", Some(value)) => {
+                self.root(&value);
+                let keep = self.call(arg, vec![value.clone()], span)?;
+                option(self.bool_arg(&keep, span)?.then_some(value))
+            }
+            ("map" | "and_then" | "filter", None) => option(None),
+            ("ok_or", value) => result(value.ok_or(arg)),
+            ("or_else", Some(value)) => option(Some(value)),
+            ("or_else", None) => self.call(arg, vec![], span)?,
+            _ => unreachable!("unknown `Option` method `{name}`"),
+        })
+    }
+
+    /// Calls a method of a `Result`, given as the value of an `Ok` or an `Err`.
+    fn result_method(
+        &mut self,
+        value: Result<Value, Value>,
+        name: &str,
+        args: Vec<Value>,
+        span: Span,
+    ) -> RunResult {
+        // Only used by the methods taking an argument, whose arity was checked
+        let arg = args.into_iter().next().unwrap_or(Value::Nil);
+        Ok(match (name, value) {
+            ("is_ok", value) => Value::Bool(value.is_ok()),
+            ("is_err", value) => Value::Bool(value.is_err()),
+            ("ok", value) => option(value.ok()),
+            ("err", value) => option(value.err()),
+            ("unwrap" | "expect" | "unwrap_or" | "unwrap_or_else", Ok(value)) => value,
+            ("unwrap_err", Err(err)) => err,
+            ("unwrap", Err(err)) => {
+                let message = format!(
+                    "called `Result::unwrap()` on an `Err` value: {}",
+                    self.repr(&err, span)?
+                );
+                return Err(error(span, message));
+            }
+            ("expect", Err(err)) => {
+                let message = format!("{}: {}", self.display(&arg, span)?, self.repr(&err, span)?);
+                return Err(error(span, message));
+            }
+            ("unwrap_err", Ok(value)) => {
+                let message = format!(
+                    "called `Result::unwrap_err()` on an `Ok` value: {}",
+                    self.repr(&value, span)?
+                );
+                return Err(error(span, message));
+            }
+            ("unwrap_or", Err(_)) => arg,
+            ("unwrap_or_else", Err(err)) => self.call(arg, vec![err], span)?,
+            ("map", Ok(value)) => result(Ok(self.call(arg, vec![value], span)?)),
+            ("map_err", Err(err)) => result(Err(self.call(arg, vec![err], span)?)),
+            ("and_then", Ok(value)) => self.call(arg, vec![value], span)?,
+            ("or_else", Err(err)) => self.call(arg, vec![err], span)?,
+            ("map" | "map_err" | "and_then" | "or_else", value) => result(value),
+            _ => unreachable!("unknown `Result` method `{name}`"),
+        })
+    }
+
+    fn float_method(&self, value: f64, name: &str, args: &[Value], span: Span) -> RunResult {
+        let arg = || self.float_arg(&args[0], span);
+        Ok(Value::Float(match name {
+            "abs" => value.abs(),
+            "sqrt" => value.sqrt(),
+            "floor" => value.floor(),
+            "ceil" => value.ceil(),
+            "round" => value.round(),
+            "min" => value.min(arg()?),
+            "max" => value.max(arg()?),
+            "pow" => value.powf(arg()?),
+            _ => unreachable!("unknown float method `{name}`"),
+        }))
+    }
+}
+
+impl<'a, T: Runtime<'a> + ?Sized> Builtins<'a> for T {}
+
+fn modify_array<R>(
+    place: &Place,
+    span: Span,
+    f: impl FnOnce(&mut Vec<Value>) -> R,
+) -> RunResult<R> {
+    let result = place.modify(|value| match value {
+        Value::Array(values) => Some(f(Rc::make_mut(values))),
+        _ => None,
+    });
+    result
+        .flatten()
+        .ok_or_else(|| error(span, "the referenced value no longer exists"))
+}
+
+/// Raises the integer of the given type to the power, by squaring, or returns `None` on overflow.
+fn pow(mut base: i128, mut exponent: u32, ty: IntTy) -> Option<i128> {
+    let mut value = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            value = int::int_op(BinOp::Mul, value, base, ty).ok()?;
+        }
+        exponent >>= 1;
+        // The last square is not needed, and could overflow
+        if exponent > 0 {
+            base = int::int_op(BinOp::Mul, base, base, ty).ok()?;
+        }
+    }
+    Some(value)
+}
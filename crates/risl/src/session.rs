@@ -0,0 +1,131 @@
+//! Interactive sessions, running inputs one after the other like the lines of a program.
+//!
+//! Each input is parsed, checked and run on its own, after the previous ones. The passes keep what
+//! they know of the top-level code of the previous inputs: the resolver their globals and items,
+//! the mutability and pattern checkers their variables, the constant evaluator the values of their
+//! constants, and the interpreter the values of their globals. The items and closures of the
+//! previous inputs are collected again for each input, which can use them, but their statements
+//! are neither checked nor run again.
+//!
+//! An input reporting errors in the static checks is forgotten, as if it was never entered. An
+//! input stopping on a runtime error is kept: the globals it declared before the error keep their
+//! value, and the ones after cannot be read.
+
+use std::io::Write;
+
+use crate::consteval::{self, Consts};
+use crate::parser::ast::{NodeId, Program};
+use crate::parser::context::ParseContext;
+use crate::parser::parse;
+use crate::resolve::{self, Resolutions, TopLevel};
+use crate::runtime::Declarations;
+use crate::value::{self, Env};
+use crate::{exhaustiveness, ice, interpreter, mutability, traits, typeck};
+use crate::{CheckOptions, RunError};
+
+/// An interactive session, whose inputs run on the tree-walking interpreter.
+///
+/// The values of the programs are not shared between threads, so unlike
+/// [`crate::run_with_options`], the inputs run on the thread of the caller, whose stack must be of
+/// [`crate::worker::STACK_SIZE`] bytes for deep recursion.
+pub struct Session {
+    /// The text of the inputs kept so far, the spans of their nodes being offsets in it.
+    source: String,
+    /// The inputs kept so far, whose items and closures the next inputs can use.
+    programs: Vec<Program>,
+    next_id: NodeId,
+    /// Whether the types are checked, since an input with the `#![type_check]` attribute.
+    type_check: bool,
+    top_level: TopLevel,
+    resolutions: Resolutions,
+    mutability: mutability::Globals,
+    patterns: exhaustiveness::Globals,
+    consts: Consts,
+    globals: Env,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Session {
+            source: String::new(),
+            programs: vec![],
+            next_id: 0,
+            type_check: false,
+            top_level: TopLevel::default(),
+            resolutions: Resolutions::default(),
+            mutability: mutability::Globals::default(),
+            patterns: exhaustiveness::Globals::default(),
+            consts: Consts::default(),
+            globals: value::globals_env(0),
+        }
+    }
+}
+
+impl Session {
+    /// Returns the text of the inputs kept so far. The source map of the context given to
+    /// [`Session::eval`] must be the one of this text followed by the input, for the diagnostics to
+    /// show their location.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Checks and runs the input after the previous ones, writing what it prints to the output and
+    /// reporting errors through the context.
+    pub fn eval(
+        &mut self,
+        context: &ParseContext,
+        input: &str,
+        options: CheckOptions,
+        output: &mut dyn Write,
+    ) -> Result<(), RunError> {
+        if let Some(source_map) = context.diag_ctx().source_map() {
+            ice::set_current_source(source_map);
+        }
+        let start = self.source.len();
+        self.source.push_str(input);
+        let (mut program, next_id) = parse::parse_input(context, &self.source, start, self.next_id);
+        self.next_id = next_id;
+        let previous = &self.programs;
+        let resolutions = &mut self.resolutions;
+        resolve::resolve_input(context, &mut program, &mut self.top_level, resolutions);
+        mutability::check_input(
+            context,
+            previous,
+            &program,
+            resolutions,
+            &mut self.mutability,
+        );
+        exhaustiveness::check_input(context, previous, &program, resolutions, &mut self.patterns);
+        traits::check_input(context, previous, &program);
+        let type_check = self.type_check || options.type_check || program.has_attr("type_check");
+        if type_check {
+            typeck::check_input(context, previous, &program, resolutions);
+        }
+        consteval::eval_input(context, previous, &program, resolutions, &mut self.consts);
+        consteval::fold(context, &mut program, resolutions, &self.consts);
+        if context.diag_ctx().has_errors() {
+            self.source.truncate(start);
+            return Err(RunError::Check);
+        }
+        self.top_level.commit();
+        self.type_check = type_check;
+        let decls = Declarations::new_input(previous, &program, resolutions, &self.consts);
+        let result = interpreter::run_input(
+            context,
+            previous,
+            &program,
+            resolutions,
+            &decls,
+            &self.globals,
+            output,
+        );
+        self.programs.push(program);
+        result.map_err(|error| {
+            error.emit(context.diag_ctx());
+            RunError::Runtime
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests;
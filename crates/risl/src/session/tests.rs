@@ -0,0 +1,103 @@
+use super::Session;
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::DiagContext;
+use crate::parser::emitter::new_emitter_none;
+use crate::parser::source_map::SourceMap;
+use crate::test_utils::diagnostics;
+use crate::CheckOptions;
+
+/// Evaluates the inputs one after the other in a session, returning what each printed followed by
+/// its diagnostics as `level: message`.
+fn eval(inputs: &[&str]) -> Vec<String> {
+    let mut session = Session::default();
+    let mut results = vec![];
+    for input in inputs {
+        let source = format!("{}{input}", session.source());
+        let source_map = SourceMap::new("<stdin>", &source);
+        let context =
+            ParseContext::new(DiagContext::new(new_emitter_none()).with_source_map(source_map));
+        let mut output = vec![];
+        let _ = session.eval(&context, input, CheckOptions::default(), &mut output);
+        let mut result = String::from_utf8(output).unwrap();
+        for diagnostic in diagnostics(&context) {
+            result.push_str(&diagnostic);
+            result.push('\n');
+        }
+        results.push(result);
+    }
+    results
+}
+
+#[test]
+fn globals_and_items() {
+    assert_eq!(
+        eval(&[
+            "let x = 2;\n",
+            "fn double(n: i64) -> i64 { n * x }\n",
+            "println(double(21));\n",
+        ]),
+        ["", "", "42\n"]
+    );
+}
+
+#[test]
+fn mutated_later() {
+    assert_eq!(
+        eval(&["let mut x = 1;\n", "x += 1;\n", "println(x);\n"]),
+        ["", "", "2\n"]
+    );
+}
+
+#[test]
+fn statements_run_once() {
+    assert_eq!(
+        eval(&["println(\"once\");\n", "println(\"twice\");\n"]),
+        ["once\n", "twice\n"]
+    );
+}
+
+#[test]
+fn failed_input_is_forgotten() {
+    let results = eval(&[
+        "let x = 1;\n",
+        "let y = z;\n",
+        "println(x);\n",
+        "println(y);\n",
+    ]);
+    assert_eq!(results[0], "");
+    assert!(results[1].starts_with("error"), "{}", results[1]);
+    assert_eq!(results[2], "1\n");
+    assert!(results[3].starts_with("error"), "{}", results[3]);
+}
+
+#[test]
+fn runtime_error_keeps_globals() {
+    let results = eval(&["let x = 1;\nlet y = [1][x];\n", "println(x);\n"]);
+    assert!(results[0].starts_with("error"), "{}", results[0]);
+    assert_eq!(results[1], "1\n");
+}
+
+#[test]
+fn redefined_items() {
+    assert_eq!(
+        eval(&[
+            "fn f() -> i64 { 1 }\n",
+            "fn f() -> i64 { 2 }\n",
+            "println(f());\n",
+        ]),
+        ["", "", "2\n"]
+    );
+}
+
+#[test]
+fn consts_and_enums() {
+    assert_eq!(
+        eval(&[
+            "const N: i64 = 3;\nenum E { A, B }\n",
+            "const M: i64 = N * 2;\n",
+            "let e = E::B;\n",
+            "println(M, match e { E::A => 0, E::B => N });\n",
+        ]),
+        ["", "", "", "6 3\n"]
+    );
+}
@@ -0,0 +1,29 @@
+//! Helpers shared by the unit tests.
+
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::DiagContext;
+use crate::parser::emitter::new_emitter_none;
+use crate::CheckOptions;
+
+/// Returns the diagnostics emitted through the context as `level: message`.
+pub fn diagnostics(context: &ParseContext) -> Vec<String> {
+    context
+        .diag_ctx()
+        .diagnostics()
+        .iter()
+        .map(|diagnostic| format!("{}: {}", diagnostic.level, diagnostic.message))
+        .collect()
+}
+
+/// Checks the source, returning the emitted diagnostics as `level: message`.
+pub fn check(source: &str) -> Vec<String> {
+    check_with_options(source, CheckOptions::default())
+}
+
+/// Checks the source with the given options, returning the emitted diagnostics as
+/// `level: message`.
+pub fn check_with_options(source: &str, options: CheckOptions) -> Vec<String> {
+    let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+    crate::check_with_options(&context, source, options);
+    diagnostics(&context)
+}
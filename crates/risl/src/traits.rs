@@ -0,0 +1,341 @@
+//! Checking of the traits declared in the program and of their implementations.
+//!
+//! A trait is either declared in the program, or built in: the interpreter calls the methods of
+//! the built-in traits implicitly, to print values, compare them, use them as map keys or iterate
+//! over them. The checker ensures that:
+//! - the trait of each `impl Trait for Type` block, and the bounds of each `impl Trait` type, name
+//!   a trait,
+//! - each implementation defines every required method of its trait, and no other method, with the
+//!   receiver and the number of parameters declared by the trait,
+//! - a trait is implemented at most once for a given type.
+//!
+//! As the checker runs without type information, the bounds of the parameters are checked when
+//! the functions are called, by the interpreter or by the type checker.
+
+use std::collections::HashMap;
+
+use crate::error_codes::{self, ErrorCode};
+use crate::parser::ast::*;
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::{Diagnostic, Level};
+use crate::parser::lexer::Span;
+use crate::parser::visit::{self, Visitor};
+
+/// A trait whose methods are called implicitly by the interpreter.
+#[derive(Debug)]
+pub struct BuiltinTrait {
+    pub name: &'static str,
+    /// The required methods of the trait. Built-in traits have no default method.
+    pub methods: &'static [BuiltinMethod],
+}
+
+#[derive(Debug)]
+pub struct BuiltinMethod {
+    pub name: &'static str,
+    pub receiver: SelfKind,
+    /// The number of parameters, the receiver excluded.
+    pub params: usize,
+}
+
+/// The built-in traits:
+/// - `Display`: `fn fmt(&self) -> str`, used to print values,
+/// - `Eq`: `fn eq(&self, other: &Self) -> bool`, used by `==` and `!=`,
+/// - `Ord`: `fn cmp(&self, other: &Self) -> i64`, returning a negative number, zero or a positive
+///   number when `self` is less than, equal to or greater than `other`, used by `<`, `<=`, `>` and
+///   `>=`,
+/// - `Hash`: `fn hash(&self) -> i64`, used along with `Eq` to look up map keys,
+/// - `Iterator`: `fn next(&mut self)`, returning `Some` of the next element or `None` once
+///   exhausted, used by `for` loops and by the built-in iterator methods like `map`.
+pub static BUILTIN_TRAITS: &[BuiltinTrait] = &[
+    BuiltinTrait {
+        name: "Display",
+        methods: &[BuiltinMethod {
+            name: "fmt",
+            receiver: SelfKind::Ref(Mutability::Not),
+            params: 0,
+        }],
+    },
+    BuiltinTrait {
+        name: "Eq",
+        methods: &[BuiltinMethod {
+            name: "eq",
+            receiver: SelfKind::Ref(Mutability::Not),
+            params: 1,
+        }],
+    },
+    BuiltinTrait {
+        name: "Ord",
+        methods: &[BuiltinMethod {
+            name: "cmp",
+            receiver: SelfKind::Ref(Mutability::Not),
+            params: 1,
+        }],
+    },
+    BuiltinTrait {
+        name: "Hash",
+        methods: &[BuiltinMethod {
+            name: "hash",
+            receiver: SelfKind::Ref(Mutability::Not),
+            params: 0,
+        }],
+    },
+    BuiltinTrait {
+        name: "Iterator",
+        methods: &[BuiltinMethod {
+            name: "next",
+            receiver: SelfKind::Ref(Mutability::Mut),
+            params: 0,
+        }],
+    },
+];
+
+/// Returns the built-in trait with the given name, if any.
+pub fn builtin_trait(name: &str) -> Option<&'static BuiltinTrait> {
+    BUILTIN_TRAITS.iter().find(|builtin| builtin.name == name)
+}
+
+/// Checks the traits and the trait implementations of the program, reporting errors through the
+/// context.
+pub fn check(context: &ParseContext, program: &Program) {
+    check_input(context, &[], program);
+}
+
+/// Checks the traits and the trait implementations of an input of an interactive session like
+/// [`check`], given the previous inputs, whose traits and types the input can use.
+pub fn check_input(context: &ParseContext, previous: &[Program], program: &Program) {
+    let mut collector = ItemCollector::default();
+    for program in previous.iter().chain([program]) {
+        collector.visit_program(program);
+    }
+    let mut checker = Checker {
+        context,
+        traits: collector.traits,
+        types: collector.types,
+        impls: HashMap::new(),
+    };
+    checker.visit_program(program);
+}
+
+/// Collects the traits and the types declared anywhere in the program, by name.
+#[derive(Default)]
+struct ItemCollector<'a> {
+    traits: HashMap<&'a str, &'a TraitDecl>,
+    types: HashMap<&'a str, &'static str>,
+}
+
+impl<'a> Visitor<'a> for ItemCollector<'a> {
+    fn visit_item(&mut self, item: &'a Item) {
+        match &item.kind {
+            ItemKind::Trait(decl) => {
+                self.traits.insert(&decl.name.name, decl);
+            }
+            ItemKind::Struct(decl) => {
+                self.types.insert(&decl.name.name, "struct");
+            }
+            ItemKind::Enum(decl) => {
+                self.types.insert(&decl.name.name, "enum");
+            }
+            ItemKind::Fn(_) | ItemKind::Impl(_) | ItemKind::Const(_) => (),
+        }
+        visit::walk_item(self, item);
+    }
+}
+
+/// A method of a trait, as seen by the implementations of the trait.
+struct TraitMethod<'a> {
+    name: &'a str,
+    receiver: Option<SelfKind>,
+    params: usize,
+    is_required: bool,
+}
+
+/// A trait declared in the program or built in.
+enum Trait<'a> {
+    Declared(&'a TraitDecl),
+    Builtin(&'static BuiltinTrait),
+}
+
+impl<'a> Trait<'a> {
+    fn methods(&self) -> Vec<TraitMethod<'a>> {
+        match self {
+            Trait::Declared(decl) => decl
+                .items
+                .iter()
+                .filter_map(|item| match &item.kind {
+                    ItemKind::Fn(decl) => Some(TraitMethod {
+                        name: &decl.name.name,
+                        receiver: decl.self_param.as_ref().map(|param| param.kind),
+                        params: decl.params.len(),
+                        is_required: decl.body.is_none(),
+                    }),
+                    _ => None,
+                })
+                .collect(),
+            Trait::Builtin(builtin) => builtin
+                .methods
+                .iter()
+                .map(|method| TraitMethod {
+                    name: method.name,
+                    receiver: Some(method.receiver),
+                    params: method.params,
+                    is_required: true,
+                })
+                .collect(),
+        }
+    }
+}
+
+struct Checker<'a> {
+    context: &'a ParseContext,
+    traits: HashMap<&'a str, &'a TraitDecl>,
+    types: HashMap<&'a str, &'static str>,
+    /// The span of the header of the implementations checked so far, by trait and type name.
+    impls: HashMap<(String, String), Span>,
+}
+
+impl<'a> Checker<'a> {
+    fn error(&self, code: ErrorCode, span: Span, message: String) {
+        self.context
+            .diag_ctx()
+            .emit(Diagnostic::spanned(Level::Error, span, message).with_code(code));
+    }
+
+    /// Returns the trait the path refers to, reporting an error if it does not name a trait.
+    fn resolve_trait(&self, path: &Path) -> Option<Trait<'a>> {
+        if let Some(ident) = path.as_ident() {
+            if let Some(decl) = self.traits.get(ident.name.as_str()) {
+                return Some(Trait::Declared(decl));
+            }
+            if let Some(builtin) = builtin_trait(&ident.name) {
+                return Some(Trait::Builtin(builtin));
+            }
+            if let Some(kind) = self.types.get(ident.name.as_str()) {
+                let message = format!("expected trait, found {kind} `{path}`");
+                self.error(error_codes::E0020, path.span, message);
+                return None;
+            }
+        }
+        let message = format!("cannot find trait `{path}` in this scope");
+        self.error(error_codes::E0020, path.span, message);
+        None
+    }
+
+    fn check_impl(&mut self, item: &Item, decl: &ImplDecl, trait_ref: &Path) {
+        let Some(trait_) = self.resolve_trait(trait_ref) else {
+            return;
+        };
+        let header = Span::new(item.span.start, decl.self_ty.span.end);
+        if let TypeKind::Path(path, _) = &decl.self_ty.kind {
+            let key = (trait_ref.to_string(), path.to_string());
+            if self.impls.insert(key, header).is_some() {
+                let message =
+                    format!("conflicting implementations of trait `{trait_ref}` for type `{path}`");
+                self.error(error_codes::E0024, header, message);
+            }
+        }
+
+        let methods = trait_.methods();
+        for item in &decl.items {
+            let ItemKind::Fn(method) = &item.kind else {
+                continue;
+            };
+            let name = &method.name;
+            let Some(declared) = methods.iter().find(|declared| declared.name == name.name) else {
+                let message = format!(
+                    "method `{}` is not a member of trait `{trait_ref}`",
+                    name.name
+                );
+                self.error(error_codes::E0022, name.span, message);
+                continue;
+            };
+            let receiver = method.self_param.as_ref().map(|param| param.kind);
+            if receiver != declared.receiver {
+                let message = format!(
+                    "method `{}` takes {} but its declaration in trait `{trait_ref}` takes {}",
+                    name.name,
+                    describe_receiver(receiver),
+                    describe_receiver(declared.receiver),
+                );
+                let span = method
+                    .self_param
+                    .as_ref()
+                    .map_or(name.span, |param| param.span);
+                self.error(error_codes::E0023, span, message);
+            } else if method.params.len() != declared.params {
+                let message = format!(
+                    "method `{}` has {} but its declaration in trait `{trait_ref}` has {}",
+                    name.name,
+                    describe_params(method.params.len()),
+                    describe_params(declared.params),
+                );
+                self.error(error_codes::E0023, name.span, message);
+            }
+        }
+
+        let missing: Vec<_> = methods
+            .iter()
+            .filter(|declared| declared.is_required)
+            .filter(|declared| {
+                !decl.items.iter().any(|item| match &item.kind {
+                    ItemKind::Fn(method) => method.name.name == declared.name,
+                    _ => false,
+                })
+            })
+            .map(|declared| format!("`{}`", declared.name))
+            .collect();
+        if !missing.is_empty() {
+            let message = format!(
+                "not all trait methods implemented, missing: {}",
+                missing.join(", ")
+            );
+            self.error(error_codes::E0021, header, message);
+        }
+    }
+}
+
+fn describe_receiver(receiver: Option<SelfKind>) -> &'static str {
+    match receiver {
+        None => "no `self` parameter",
+        Some(SelfKind::Value(Mutability::Not)) => "`self`",
+        Some(SelfKind::Value(Mutability::Mut)) => "`mut self`",
+        Some(SelfKind::Ref(Mutability::Not)) => "`&self`",
+        Some(SelfKind::Ref(Mutability::Mut)) => "`&mut self`",
+    }
+}
+
+fn describe_params(count: usize) -> String {
+    match count {
+        1 => String::from("1 parameter"),
+        count => format!("{count} parameters"),
+    }
+}
+
+impl<'a> Visitor<'a> for Checker<'a> {
+    fn visit_item(&mut self, item: &'a Item) {
+        crate::ice::set_current_span(item.span);
+        if let ItemKind::Impl(decl) = &item.kind {
+            if let Some(trait_ref) = &decl.trait_ref {
+                self.check_impl(item, decl, trait_ref);
+            }
+            // The trait reference was checked above
+            self.visit_ty(&decl.self_ty);
+            for item in &decl.items {
+                self.visit_item(item);
+            }
+            return;
+        }
+        visit::walk_item(self, item);
+    }
+
+    fn visit_ty(&mut self, ty: &'a Type) {
+        if let TypeKind::ImplTrait(bounds) = &ty.kind {
+            for bound in bounds {
+                self.resolve_trait(bound);
+            }
+        }
+        visit::walk_ty(self, ty);
+    }
+}
+
+#[cfg(test)]
+mod tests;
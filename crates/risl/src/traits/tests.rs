@@ -0,0 +1,124 @@
+use crate::test_utils::check;
+
+/// Checks the given items after the declaration of a `Shape` trait and of a `Circle` struct.
+fn check_with_shape(items: &str) -> Vec<String> {
+    check(&format!(
+        "trait Shape {{\n\
+             fn area(&self) -> f64;\n\
+             fn scale(&mut self, factor: f64);\n\
+             fn name(&self) -> str {{ \"shape\" }}\n\
+             fn unit() -> Self;\n\
+         }}\n\
+         struct Circle {{ radius: f64 }}\n\
+         {items}"
+    ))
+}
+
+const CIRCLE_SHAPE_METHODS: &str = "fn area(&self) -> f64 { 3.14 * self.radius * self.radius }\n\
+     fn scale(&mut self, factor: f64) { self.radius *= factor; }\n\
+     fn unit() -> Self { Circle { radius: 1.0 } }";
+
+#[test]
+fn valid_implementations() {
+    assert_eq!(
+        check_with_shape(&format!(
+            "impl Shape for Circle {{ {CIRCLE_SHAPE_METHODS} }}"
+        )),
+        Vec::<String>::new()
+    );
+    // Default methods can be overridden
+    assert_eq!(
+        check_with_shape(&format!(
+            "impl Shape for Circle {{ {CIRCLE_SHAPE_METHODS} fn name(&self) -> str {{ \"circle\" }} }}"
+        )),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn builtin_traits_can_be_implemented() {
+    let source = "struct Point { x: i64, y: i64 }\n\
+         impl Display for Point { fn fmt(&self) -> str { \"point\" } }\n\
+         impl Eq for Point { fn eq(&self, other: &Self) -> bool { self.x == other.x } }\n\
+         impl Ord for Point { fn cmp(&self, other: &Self) -> i64 { self.x - other.x } }\n\
+         impl Hash for Point { fn hash(&self) -> i64 { self.x } }\n\
+         struct Countdown(i64);\n\
+         impl Iterator for Countdown { fn next(&mut self) { self.0 -= 1; self.0 } }";
+    assert_eq!(check(source), Vec::<String>::new());
+}
+
+#[test]
+fn unknown_traits() {
+    assert_eq!(
+        check("struct Point; impl Printable for Point {}"),
+        ["error: cannot find trait `Printable` in this scope"]
+    );
+    assert_eq!(
+        check("struct Point; struct Other; impl Other for Point {}"),
+        ["error: expected trait, found struct `Other`"]
+    );
+    assert_eq!(
+        check("fn show(value: impl Display + Printable) {}"),
+        ["error: cannot find trait `Printable` in this scope"]
+    );
+    assert_eq!(
+        check("trait Printable {} fn show(value: impl Display + Printable) {}"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn missing_methods() {
+    assert_eq!(
+        check_with_shape("impl Shape for Circle {}"),
+        ["error: not all trait methods implemented, missing: `area`, `scale`, `unit`"]
+    );
+    assert_eq!(
+        check("struct Point; impl Display for Point {}"),
+        ["error: not all trait methods implemented, missing: `fmt`"]
+    );
+}
+
+#[test]
+fn methods_not_in_the_trait() {
+    assert_eq!(
+        check_with_shape(&format!(
+            "impl Shape for Circle {{ {CIRCLE_SHAPE_METHODS} fn radius(&self) -> f64 {{ self.radius }} }}"
+        )),
+        ["error: method `radius` is not a member of trait `Shape`"]
+    );
+}
+
+#[test]
+fn incompatible_signatures() {
+    assert_eq!(
+        check_with_shape(
+            "impl Shape for Circle {\n\
+                 fn area(self) -> f64 { 0.0 }\n\
+                 fn scale(&mut self) {}\n\
+                 fn unit(&self) -> Self { Circle { radius: 1.0 } }\n\
+             }"
+        ),
+        [
+            "error: method `area` takes `self` but its declaration in trait `Shape` takes `&self`",
+            "error: method `scale` has 0 parameters but its declaration in trait `Shape` has 1 parameter",
+            "error: method `unit` takes `&self` but its declaration in trait `Shape` takes no `self` parameter",
+        ]
+    );
+    assert_eq!(
+        check("struct Counter; impl Iterator for Counter { fn next(&self) { nil } }"),
+        ["error: method `next` takes `&self` but its declaration in trait `Iterator` takes `&mut self`"]
+    );
+}
+
+#[test]
+fn conflicting_implementations() {
+    assert_eq!(
+        check(
+            "struct Point;\n\
+             impl Hash for Point { fn hash(&self) -> i64 { 0 } }\n\
+             impl Hash for Point { fn hash(&self) -> i64 { 1 } }"
+        ),
+        ["error: conflicting implementations of trait `Hash` for type `Point`"]
+    );
+}
@@ -0,0 +1,1141 @@
+//! Static type checking, enabled by the `#![type_check]` attribute or the `--check` option.
+//!
+//! The checker is gradual: the values without a type annotation, like the parameters of functions
+//! and their return value, have the type `any`, compatible with every type, so that annotations
+//! can be added to a program little by little. Within the top-level code and the body of each
+//! function, the types of the `let` bindings, of the closures and of the expressions are inferred
+//! Hindley–Milner style: each unknown type is a type variable, and the constraints between types
+//! are solved by unification as the code is checked. The inferred types are then checked against
+//! the annotations, and the types of the operands of the operators.
+//!
+//! Integer literals without a suffix can have any integer type, and float literals any float type.
+//! References are erased: `&T` has the type `T`. The built-in functions and the methods of the
+//! built-in types return `any`, and the values of the built-in `Option` and `Result` enums have the
+//! type `any`.
+
+use std::collections::HashMap;
+
+use crate::error_codes::{self, ErrorCode};
+use crate::int::{self, IntTy};
+use crate::parser::ast::*;
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::{Diagnostic, Level};
+use crate::parser::lexer::Span;
+use crate::parser::visit::{self, Visitor};
+use crate::resolve::{Res, Resolutions, BUILTIN_TYPES};
+
+/// Checks the types of the program, reporting mismatches through the context.
+pub fn check(context: &ParseContext, program: &Program, resolutions: &Resolutions) {
+    check_input(context, &[], program, resolutions);
+}
+
+/// Checks the types of an input of an interactive session like [`check`], given the previous
+/// inputs, whose items the input can use. The globals of the previous inputs have the type `any`.
+pub fn check_input(
+    context: &ParseContext,
+    previous: &[Program],
+    program: &Program,
+    resolutions: &Resolutions,
+) {
+    let mut collector = ItemCollector::default();
+    for program in previous.iter().chain([program]) {
+        collector.visit_program(program);
+    }
+    let mut methods = HashMap::new();
+    for decl in collector.impls {
+        let Some(Res::Item(adt)) = resolutions.names.get(&decl.self_ty.id) else {
+            continue;
+        };
+        for item in &decl.items {
+            if let ItemKind::Fn(method) = &item.kind {
+                methods.insert((*adt, method.name.name.as_str()), method);
+            }
+        }
+    }
+    let mut checker = Checker {
+        context,
+        resolutions,
+        items: collector.items,
+        methods,
+        vars: vec![],
+        variables: HashMap::new(),
+        frames: vec![Frame {
+            id: None,
+            ret: Ty::Any,
+        }],
+        loops: HashMap::new(),
+    };
+    for stmt in &program.stmts {
+        checker.check_stmt(stmt);
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Ty {
+    /// The type of the values without annotation, compatible with every type.
+    Any,
+    /// The type of the expressions never producing a value, like `return`.
+    Never,
+    Bool,
+    Str,
+    Nil,
+    Int(IntTy),
+    Float(&'static str),
+    /// A tuple, the empty one being the type of the expressions without a value.
+    Tuple(Vec<Ty>),
+    Array(Box<Ty>),
+    Fn(Vec<Ty>, Box<Ty>),
+    /// A struct or an enum, by item id, along with its name.
+    Adt(NodeId, String),
+    /// A type to be inferred, by index in the type variables.
+    Var(usize),
+}
+
+impl Ty {
+    fn unit() -> Ty {
+        Ty::Tuple(vec![])
+    }
+
+    /// Returns the primitive type with the given name, if any.
+    fn primitive(name: &str) -> Option<Ty> {
+        if let Some(ty) = IntTy::from_name(name) {
+            return Some(Ty::Int(ty));
+        }
+        let name = *BUILTIN_TYPES.iter().find(|builtin| **builtin == name)?;
+        Some(match name {
+            "bool" => Ty::Bool,
+            "str" => Ty::Str,
+            "f32" | "f64" => Ty::Float(name),
+            // The keys and values of maps, and the values of the built-in enums are not typed
+            _ => return None,
+        })
+    }
+}
+
+/// The types a type variable can be bound to.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum VarKind {
+    General,
+    /// The type of an integer literal without suffix.
+    Int,
+    /// The type of a float literal without suffix.
+    Float,
+}
+
+#[derive(Clone, Debug)]
+enum VarState {
+    Unbound(VarKind),
+    Bound(Ty),
+}
+
+/// Where a variable is stored: in a slot of the frame of the function or closure with the given
+/// id, or of the top-level code, or in a global slot.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Variable {
+    Local(Option<NodeId>, usize),
+    Global(usize),
+}
+
+/// The top-level code, or a function or closure being checked.
+struct Frame {
+    id: Option<NodeId>,
+    /// The return type of the function or closure.
+    ret: Ty,
+}
+
+/// Collects the items declared anywhere in the program, by id, and the impl blocks.
+#[derive(Default)]
+struct ItemCollector<'a> {
+    items: HashMap<NodeId, &'a Item>,
+    impls: Vec<&'a ImplDecl>,
+}
+
+impl<'a> Visitor<'a> for ItemCollector<'a> {
+    fn visit_item(&mut self, item: &'a Item) {
+        self.items.insert(item.id, item);
+        if let ItemKind::Impl(decl) = &item.kind {
+            self.impls.push(decl);
+        }
+        visit::walk_item(self, item);
+    }
+}
+
+struct Checker<'a> {
+    context: &'a ParseContext,
+    resolutions: &'a Resolutions,
+    items: HashMap<NodeId, &'a Item>,
+    /// The methods and associated functions of the structs and enums, by type id and name.
+    methods: HashMap<(NodeId, &'a str), &'a FnDecl>,
+    vars: Vec<VarState>,
+    /// The types of the variables declared so far.
+    variables: HashMap<Variable, Ty>,
+    frames: Vec<Frame>,
+    /// The types of the values of the `break` expressions exiting the `loop` expressions being
+    /// checked so far, by expression id.
+    loops: HashMap<NodeId, Ty>,
+}
+
+impl<'a> Checker<'a> {
+    fn error(&self, code: ErrorCode, span: Span, message: String) {
+        self.context
+            .diag_ctx()
+            .emit(Diagnostic::spanned(Level::Error, span, message).with_code(code));
+    }
+
+    // Type variables and unification
+
+    fn new_var(&mut self, kind: VarKind) -> Ty {
+        self.vars.push(VarState::Unbound(kind));
+        Ty::Var(self.vars.len() - 1)
+    }
+
+    /// Returns the type, following the type variables bound to a type.
+    fn shallow(&self, ty: &Ty) -> Ty {
+        let mut ty = ty.clone();
+        while let Ty::Var(var) = ty {
+            match &self.vars[var] {
+                VarState::Bound(bound) => ty = bound.clone(),
+                VarState::Unbound(_) => break,
+            }
+        }
+        ty
+    }
+
+    fn var_kind(&self, var: usize) -> VarKind {
+        match self.vars[var] {
+            VarState::Unbound(kind) => kind,
+            VarState::Bound(_) => unreachable!("bound type variables are followed"),
+        }
+    }
+
+    /// Makes the two types equal by binding their type variables, returning false if they cannot
+    /// be.
+    fn unify(&mut self, a: &Ty, b: &Ty) -> bool {
+        let (a, b) = (self.shallow(a), self.shallow(b));
+        match (&a, &b) {
+            (Ty::Any | Ty::Never, _) | (_, Ty::Any | Ty::Never) => true,
+            (Ty::Var(a), Ty::Var(b)) if a == b => true,
+            // The newer variable is bound to the older one, so that the variables unified along
+            // an operator chain all point to its first operand instead of forming a chain
+            (Ty::Var(a), Ty::Var(b)) => self.bind(*a.max(b), &Ty::Var(*a.min(b))),
+            (Ty::Var(var), ty) | (ty, Ty::Var(var)) => self.bind(*var, ty),
+            (Ty::Tuple(a), Ty::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| self.unify(a, b))
+            }
+            (Ty::Array(a), Ty::Array(b)) => self.unify(a, b),
+            (Ty::Fn(a_params, a_ret), Ty::Fn(b_params, b_ret)) => {
+                a_params.len() == b_params.len()
+                    && a_params.iter().zip(b_params).all(|(a, b)| self.unify(a, b))
+                    && self.unify(a_ret, b_ret)
+            }
+            (Ty::Adt(a, _), Ty::Adt(b, _)) => a == b,
+            _ => a == b,
+        }
+    }
+
+    /// Binds the unbound type variable to the type, which is not the variable itself.
+    fn bind(&mut self, var: usize, ty: &Ty) -> bool {
+        let kind = self.var_kind(var);
+        let compatible = match ty {
+            Ty::Var(other) => {
+                let other_kind = self.var_kind(*other);
+                if kind != VarKind::General && other_kind == VarKind::General {
+                    self.vars[*other] = VarState::Bound(Ty::Var(var));
+                    return true;
+                }
+                other_kind == kind || kind == VarKind::General
+            }
+            Ty::Int(_) => kind != VarKind::Float,
+            Ty::Float(_) => kind != VarKind::Int,
+            _ => kind == VarKind::General && !self.occurs(var, ty),
+        };
+        if compatible {
+            self.vars[var] = VarState::Bound(ty.clone());
+        }
+        compatible
+    }
+
+    /// Returns true if the type variable appears in the type, which cannot then be bound to it.
+    fn occurs(&self, var: usize, ty: &Ty) -> bool {
+        match self.shallow(ty) {
+            Ty::Var(other) => other == var,
+            Ty::Tuple(tys) => tys.iter().any(|ty| self.occurs(var, ty)),
+            Ty::Array(elem) => self.occurs(var, &elem),
+            Ty::Fn(params, ret) => {
+                params.iter().any(|ty| self.occurs(var, ty)) || self.occurs(var, &ret)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the type as written in Risl, with the inferred types substituted.
+    fn display(&self, ty: &Ty) -> String {
+        let list = |tys: &[Ty]| {
+            tys.iter()
+                .map(|ty| self.display(ty))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self.shallow(ty) {
+            Ty::Any => String::from("any"),
+            Ty::Never => String::from("!"),
+            Ty::Bool => String::from("bool"),
+            Ty::Str => String::from("str"),
+            Ty::Nil => String::from("nil"),
+            Ty::Int(ty) => ty.to_string(),
+            Ty::Float(name) => String::from(name),
+            Ty::Tuple(tys) if tys.len() == 1 => format!("({},)", self.display(&tys[0])),
+            Ty::Tuple(tys) => format!("({})", list(&tys)),
+            Ty::Array(elem) => format!("[{}]", self.display(&elem)),
+            Ty::Fn(params, ret) => format!("fn({}) -> {}", list(&params), self.display(&ret)),
+            Ty::Adt(_, name) => name,
+            Ty::Var(var) => String::from(match self.var_kind(var) {
+                VarKind::General => "_",
+                VarKind::Int => "{integer}",
+                VarKind::Float => "{float}",
+            }),
+        }
+    }
+
+    /// Unifies the expected and found types, reporting a mismatch at the span if they differ.
+    fn expect(&mut self, span: Span, expected: &Ty, found: &Ty) {
+        if !self.unify(expected, found) {
+            let message = format!(
+                "mismatched types: expected `{}`, found `{}`",
+                self.display(expected),
+                self.display(found)
+            );
+            self.error(error_codes::E0060, span, message);
+        }
+    }
+
+    /// Returns the type of two branches, one of which may never produce a value.
+    fn join(&mut self, span: Span, first: Ty, second: Ty) -> Ty {
+        if self.shallow(&first) == Ty::Never {
+            return second;
+        }
+        self.expect(span, &first, &second);
+        first
+    }
+
+    // Types of the declarations
+
+    fn lower_ty(&mut self, ty: &Type) -> Ty {
+        match &ty.kind {
+            TypeKind::Path(..) => match self.resolutions.names.get(&ty.id) {
+                Some(Res::Builtin(name)) => Ty::primitive(name).unwrap_or(Ty::Any),
+                Some(Res::Item(id)) => self.adt(*id),
+                Some(Res::SelfTy(id)) => self.self_ty(*id),
+                _ => Ty::Any,
+            },
+            TypeKind::Tuple(tys) => Ty::Tuple(tys.iter().map(|ty| self.lower_ty(ty)).collect()),
+            TypeKind::Array(elem) => Ty::Array(Box::new(self.lower_ty(elem))),
+            TypeKind::Ref(_, ty) => self.lower_ty(ty),
+            TypeKind::Fn(params, ret) => {
+                let params = params.iter().map(|ty| self.lower_ty(ty)).collect();
+                let ret = ret.as_ref().map_or_else(Ty::unit, |ret| self.lower_ty(ret));
+                Ty::Fn(params, Box::new(ret))
+            }
+            TypeKind::Infer => self.new_var(VarKind::General),
+            TypeKind::ImplTrait(_) | TypeKind::Err => Ty::Any,
+        }
+    }
+
+    /// Returns the type of an annotation, or `any` without annotation.
+    fn annotation(&mut self, ty: Option<&Type>) -> Ty {
+        ty.map_or(Ty::Any, |ty| self.lower_ty(ty))
+    }
+
+    /// Returns the type of the struct or enum with the given id.
+    fn adt(&self, id: NodeId) -> Ty {
+        match self.items.get(&id).map(|item| &item.kind) {
+            Some(ItemKind::Struct(decl)) => Ty::Adt(id, decl.name.name.clone()),
+            Some(ItemKind::Enum(decl)) => Ty::Adt(id, decl.name.name.clone()),
+            _ => Ty::Any,
+        }
+    }
+
+    /// Returns the `Self` type of the item with the given id.
+    fn self_ty(&mut self, id: NodeId) -> Ty {
+        match self.items.get(&id).map(|item| &item.kind) {
+            Some(ItemKind::Impl(decl)) => self.lower_ty(&decl.self_ty),
+            Some(ItemKind::Struct(_) | ItemKind::Enum(_)) => self.adt(id),
+            _ => Ty::Any,
+        }
+    }
+
+    /// Returns the type of the function, given the type of its `self` parameter if it has one.
+    fn fn_ty(&mut self, decl: &FnDecl, self_ty: Ty) -> Ty {
+        let mut params = vec![];
+        if decl.self_param.is_some() {
+            params.push(self_ty);
+        }
+        for param in &decl.params {
+            params.push(self.annotation(param.ty.as_ref()));
+        }
+        let ret = self.annotation(decl.ret.as_ref());
+        Ty::Fn(params, Box::new(ret))
+    }
+
+    /// Returns the type of the constructor of a struct or variant with the given fields: a
+    /// function for tuple fields, or the type itself without fields.
+    fn constructor_ty(&mut self, adt: Ty, fields: &VariantFields) -> Ty {
+        match fields {
+            VariantFields::Tuple(fields) => {
+                let params = fields
+                    .iter()
+                    .map(|field| self.lower_ty(&field.ty))
+                    .collect();
+                Ty::Fn(params, Box::new(adt))
+            }
+            VariantFields::Unit => adt,
+            VariantFields::Named(_) => Ty::Any,
+        }
+    }
+
+    /// Returns the type and the fields of the struct or variant the path of a struct expression or
+    /// pattern refers to.
+    fn struct_of_path(&mut self, id: NodeId, path: &Path) -> Option<(Ty, &'a VariantFields)> {
+        let adt = match *self.resolutions.names.get(&id)? {
+            Res::Item(id) => id,
+            Res::SelfTy(id) => match self.self_ty(id) {
+                Ty::Adt(id, _) => id,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        let item = *self.items.get(&adt)?;
+        match (&item.kind, &path.segments[..]) {
+            (ItemKind::Struct(decl), [_]) => Some((self.adt(adt), &decl.fields)),
+            (ItemKind::Enum(decl), [_, variant]) => {
+                let variant = decl.variants.iter().find(|v| v.name.name == variant.name)?;
+                Some((self.adt(adt), &variant.fields))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the type of the field with the given name or index, if any.
+    fn field_ty(&mut self, fields: &VariantFields, name: &str) -> Option<Ty> {
+        let field = match fields {
+            VariantFields::Named(fields) => fields.iter().find(|field| field.name.name == name),
+            VariantFields::Tuple(fields) => fields.get(name.parse::<usize>().ok()?),
+            VariantFields::Unit => None,
+        }?;
+        Some(self.lower_ty(&field.ty))
+    }
+
+    fn unknown_field(&self, span: Span, name: &str, ty: &Ty) {
+        let message = format!("no field `{name}` on type `{}`", self.display(ty));
+        self.error(error_codes::E0063, span, message);
+    }
+
+    // Variables
+
+    /// Returns where the variable a name resolved to is stored, seen from the given frame.
+    fn variable(&self, res: Res, frame: usize) -> Option<Variable> {
+        match res {
+            Res::Local(slot) => Some(Variable::Local(self.frames[frame].id, slot)),
+            Res::Global(slot) => Some(Variable::Global(slot)),
+            Res::Upvalue(index) => {
+                let closure = self.frames[frame].id?;
+                let res = *self.resolutions.captures.get(&closure)?.get(index)?;
+                self.variable(res, frame.checked_sub(1)?)
+            }
+            Res::Item(_) | Res::SelfTy(_) | Res::Builtin(_) => None,
+        }
+    }
+
+    /// Records the type of the variable declared by the binding pattern or `self` parameter with
+    /// the given id.
+    fn declare(&mut self, id: NodeId, ty: Ty) {
+        let Some(res) = self.resolutions.names.get(&id) else {
+            return;
+        };
+        if let Some(variable) = self.variable(*res, self.frames.len() - 1) {
+            self.variables.insert(variable, ty);
+        }
+    }
+
+    // Items and statements
+
+    fn check_item(&mut self, item: &'a Item) {
+        match &item.kind {
+            ItemKind::Fn(decl) => self.check_fn(item.id, decl, Ty::Any),
+            ItemKind::Impl(decl) => {
+                let self_ty = self.lower_ty(&decl.self_ty);
+                for item in &decl.items {
+                    if let ItemKind::Fn(method) = &item.kind {
+                        self.check_fn(item.id, method, self_ty.clone());
+                    }
+                }
+            }
+            ItemKind::Trait(decl) => {
+                for item in &decl.items {
+                    if let ItemKind::Fn(method) = &item.kind {
+                        self.check_fn(item.id, method, Ty::Any);
+                    }
+                }
+            }
+            ItemKind::Const(decl) => {
+                let ty = self.lower_ty(&decl.ty);
+                self.frames.push(Frame {
+                    id: Some(item.id),
+                    ret: ty.clone(),
+                });
+                self.check_expr(&decl.expr, &ty);
+                self.frames.pop();
+            }
+            ItemKind::Struct(_) | ItemKind::Enum(_) => (),
+        }
+    }
+
+    fn check_fn(&mut self, id: NodeId, decl: &'a FnDecl, self_ty: Ty) {
+        let Some(body) = &decl.body else {
+            return;
+        };
+        let ret = self.annotation(decl.ret.as_ref());
+        self.frames.push(Frame {
+            id: Some(id),
+            ret: ret.clone(),
+        });
+        if let Some(self_param) = &decl.self_param {
+            self.declare(self_param.id, self_ty);
+        }
+        for param in &decl.params {
+            let ty = self.annotation(param.ty.as_ref());
+            self.bind_pat(&param.pat, &ty);
+        }
+        self.check_block(body, Some(&ret));
+        self.frames.pop();
+    }
+
+    /// Checks the statement, returning true if it never completes.
+    fn check_stmt(&mut self, stmt: &'a Stmt) -> bool {
+        let ty = match &stmt.kind {
+            StmtKind::Let(local) => {
+                let ty = match (&local.ty, &local.init) {
+                    (Some(ty), init) => {
+                        let ty = self.lower_ty(ty);
+                        if let Some(init) = init {
+                            self.check_expr(init, &ty);
+                        }
+                        ty
+                    }
+                    (None, Some(init)) => self.infer(init),
+                    (None, None) => self.new_var(VarKind::General),
+                };
+                self.bind_pat(&local.pat, &ty);
+                local.init.as_ref().map(|_| ty)
+            }
+            StmtKind::Item(item) => {
+                self.check_item(item);
+                None
+            }
+            StmtKind::Expr(expr) | StmtKind::Semi(expr) => Some(self.infer(expr)),
+            StmtKind::Empty | StmtKind::Err => None,
+        };
+        ty.is_some_and(|ty| self.shallow(&ty) == Ty::Never)
+    }
+
+    /// Checks the block, against the expected type if any, returning its type.
+    fn check_block(&mut self, block: &'a Block, expected: Option<&Ty>) -> Ty {
+        let tail = block.tail();
+        let stmts = &block.stmts[..block.stmts.len() - usize::from(tail.is_some())];
+        let mut diverges = false;
+        for stmt in stmts {
+            diverges |= self.check_stmt(stmt);
+        }
+        match (tail, expected) {
+            (Some(tail), Some(expected)) => {
+                self.check_expr(tail, expected);
+                expected.clone()
+            }
+            (Some(tail), None) => self.infer(tail),
+            (None, _) if diverges => Ty::Never,
+            (None, Some(expected)) => {
+                self.expect(block.span, expected, &Ty::unit());
+                Ty::unit()
+            }
+            (None, None) => Ty::unit(),
+        }
+    }
+
+    // Expressions
+
+    /// Checks the expression against the expected type.
+    fn check_expr(&mut self, expr: &'a Expr, expected: &Ty) {
+        crate::ice::set_current_span(expr.span);
+        match &expr.kind {
+            ExprKind::Block(block) => {
+                self.check_block(block, Some(expected));
+            }
+            ExprKind::Paren(inner) => self.check_expr(inner, expected),
+            _ => {
+                let found = self.infer(expr);
+                self.expect(expr.span, expected, &found);
+            }
+        }
+    }
+
+    /// Checks the operand of an operator, which must have one of the types accepted by the
+    /// operator, returning false if it does not.
+    fn check_operand(&self, op: &str, span: Span, ty: &Ty, accepts: fn(&Ty) -> bool) -> bool {
+        let accepted = match self.shallow(ty) {
+            Ty::Any | Ty::Never => true,
+            Ty::Var(var) => match self.var_kind(var) {
+                VarKind::General => true,
+                VarKind::Int => accepts(&Ty::Int(IntTy::I64)),
+                VarKind::Float => accepts(&Ty::Float("f64")),
+            },
+            ty => accepts(&ty),
+        };
+        if !accepted {
+            let message = format!(
+                "cannot apply operator `{op}` to type `{}`",
+                self.display(ty)
+            );
+            self.error(error_codes::E0062, span, message);
+        }
+        accepted
+    }
+
+    /// Checks the operands of a binary operator, other than the lazy and comparison ones,
+    /// returning the type of the result.
+    fn check_arithmetic(&mut self, op: BinOp, lhs: &'a Expr, rhs: &'a Expr) -> Ty {
+        let ty = self.infer(lhs);
+        let accepts: fn(&Ty) -> bool = match op {
+            BinOp::Add => |ty| matches!(ty, Ty::Int(_) | Ty::Float(_) | Ty::Str),
+            BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem => {
+                |ty| matches!(ty, Ty::Int(_) | Ty::Float(_))
+            }
+            BinOp::BitXor | BinOp::BitAnd | BinOp::BitOr => {
+                |ty| matches!(ty, Ty::Int(_) | Ty::Bool)
+            }
+            _ => |ty| matches!(ty, Ty::Int(_)),
+        };
+        if !self.check_operand(op.as_str(), lhs.span, &ty, accepts) {
+            self.infer(rhs);
+            return Ty::Any;
+        }
+        if matches!(op, BinOp::Shl | BinOp::Shr) {
+            // The shift amount can have any integer type
+            let amount = self.infer(rhs);
+            self.check_operand(op.as_str(), rhs.span, &amount, accepts);
+        } else {
+            self.check_expr(rhs, &ty);
+        }
+        ty
+    }
+
+    /// Checks the arguments of a call against the parameters of the called function.
+    fn check_args(&mut self, span: Span, params: &[Ty], args: &'a [Expr]) {
+        if params.len() != args.len() {
+            let message = format!(
+                "this function takes {} but {} {} supplied",
+                plural(params.len(), "argument"),
+                plural(args.len(), "argument"),
+                if args.len() == 1 { "was" } else { "were" }
+            );
+            self.error(error_codes::E0061, span, message);
+            for arg in args {
+                self.infer(arg);
+            }
+            return;
+        }
+        for (arg, param) in args.iter().zip(params) {
+            self.check_expr(arg, param);
+        }
+    }
+
+    /// Returns the type of the bounds of a range, which must be the same.
+    fn check_range(&mut self, start: Option<&'a Expr>, end: Option<&'a Expr>) -> Ty {
+        let ty = self.new_var(VarKind::General);
+        for bound in start.into_iter().chain(end) {
+            self.check_expr(bound, &ty);
+        }
+        ty
+    }
+
+    fn infer(&mut self, expr: &'a Expr) -> Ty {
+        crate::ice::set_current_span(expr.span);
+        match &expr.kind {
+            ExprKind::Lit(lit) => self.infer_lit(lit),
+            ExprKind::Path(path) => self.infer_path(expr.id, path),
+            ExprKind::Unary(op, operand) => {
+                let ty = self.infer(operand);
+                let accepted = match op {
+                    UnOp::Neg => self.check_operand("-", operand.span, &ty, |ty| {
+                        matches!(ty, Ty::Int(_) | Ty::Float(_))
+                    }),
+                    UnOp::Not => self.check_operand("!", operand.span, &ty, |ty| {
+                        matches!(ty, Ty::Int(_) | Ty::Bool)
+                    }),
+                    UnOp::Deref => true,
+                };
+                if accepted {
+                    ty
+                } else {
+                    Ty::Any
+                }
+            }
+            ExprKind::AddrOf(_, inner) | ExprKind::Paren(inner) => self.infer(inner),
+            ExprKind::Try(inner) => {
+                self.infer(inner);
+                Ty::Any
+            }
+            ExprKind::Binary(op, lhs, rhs) if op.is_lazy() => {
+                self.check_expr(lhs, &Ty::Bool);
+                self.check_expr(rhs, &Ty::Bool);
+                Ty::Bool
+            }
+            ExprKind::Binary(op, lhs, rhs) if op.is_comparison() => {
+                let ty = self.infer(lhs);
+                self.check_expr(rhs, &ty);
+                Ty::Bool
+            }
+            ExprKind::Binary(op, lhs, rhs) => self.check_arithmetic(*op, lhs, rhs),
+            ExprKind::Assign(place, value) => {
+                let ty = self.infer(place);
+                self.check_expr(value, &ty);
+                Ty::unit()
+            }
+            ExprKind::AssignOp(op, place, value) => {
+                self.check_arithmetic(*op, place, value);
+                Ty::unit()
+            }
+            ExprKind::Cast(inner, ty) => {
+                self.infer(inner);
+                self.lower_ty(ty)
+            }
+            ExprKind::Call(callee, args) => {
+                let callee_ty = self.infer(callee);
+                match self.shallow(&callee_ty) {
+                    Ty::Fn(params, ret) => {
+                        self.check_args(expr.span, &params, args);
+                        *ret
+                    }
+                    Ty::Var(var) if self.var_kind(var) == VarKind::General => {
+                        let params = args.iter().map(|arg| self.infer(arg)).collect();
+                        let ret = self.new_var(VarKind::General);
+                        self.unify(&callee_ty, &Ty::Fn(params, Box::new(ret.clone())));
+                        ret
+                    }
+                    Ty::Any | Ty::Never => {
+                        for arg in args {
+                            self.infer(arg);
+                        }
+                        Ty::Any
+                    }
+                    _ => {
+                        let message =
+                            format!("expected function, found `{}`", self.display(&callee_ty));
+                        self.error(error_codes::E0064, callee.span, message);
+                        for arg in args {
+                            self.infer(arg);
+                        }
+                        Ty::Any
+                    }
+                }
+            }
+            ExprKind::MethodCall(receiver, name, args) => {
+                let receiver_ty = self.infer(receiver);
+                let method = match self.shallow(&receiver_ty) {
+                    Ty::Adt(id, _) => self.methods.get(&(id, name.name.as_str())).copied(),
+                    _ => None,
+                };
+                match method {
+                    Some(method) if method.self_param.is_some() => {
+                        let Ty::Fn(params, ret) = self.fn_ty(method, receiver_ty) else {
+                            unreachable!("functions have a function type");
+                        };
+                        self.check_args(expr.span, &params[1..], args);
+                        *ret
+                    }
+                    _ => {
+                        for arg in args {
+                            self.infer(arg);
+                        }
+                        Ty::Any
+                    }
+                }
+            }
+            ExprKind::Field(inner, name) => {
+                let ty = self.infer(inner);
+                let field = match self.shallow(&ty) {
+                    Ty::Any | Ty::Never => Some(Ty::Any),
+                    Ty::Var(var) if self.var_kind(var) == VarKind::General => Some(Ty::Any),
+                    Ty::Adt(id, _) => match self.items.get(&id).map(|item| &item.kind) {
+                        Some(ItemKind::Struct(decl)) => self.field_ty(&decl.fields, &name.name),
+                        _ => None,
+                    },
+                    Ty::Tuple(tys) => name
+                        .name
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|index| tys.get(index).cloned()),
+                    _ => None,
+                };
+                field.unwrap_or_else(|| {
+                    self.unknown_field(name.span, &name.name, &ty);
+                    Ty::Any
+                })
+            }
+            ExprKind::Index(indexed, index) => {
+                let ty = self.infer(indexed);
+                let is_slicing = matches!(index.kind, ExprKind::Range(..));
+                let index_ty = if is_slicing {
+                    Ty::Any
+                } else {
+                    self.new_var(VarKind::Int)
+                };
+                self.check_expr(index, &index_ty);
+                match self.shallow(&ty) {
+                    Ty::Array(_) | Ty::Str if is_slicing => ty,
+                    Ty::Array(elem) => *elem,
+                    Ty::Str => Ty::Str,
+                    _ => Ty::Any,
+                }
+            }
+            ExprKind::Tuple(exprs) => {
+                Ty::Tuple(exprs.iter().map(|expr| self.infer(expr)).collect())
+            }
+            ExprKind::Array(exprs) => {
+                let elem = self.new_var(VarKind::General);
+                for expr in exprs {
+                    self.check_expr(expr, &elem);
+                }
+                Ty::Array(Box::new(elem))
+            }
+            ExprKind::Struct(struct_expr) => {
+                let Some((ty, fields)) = self.struct_of_path(expr.id, &struct_expr.path) else {
+                    for field in &struct_expr.fields {
+                        self.infer(&field.expr);
+                    }
+                    return Ty::Any;
+                };
+                for field in &struct_expr.fields {
+                    match self.field_ty(fields, &field.name.name) {
+                        Some(field_ty) => self.check_expr(&field.expr, &field_ty),
+                        None => {
+                            self.unknown_field(field.name.span, &field.name.name, &ty);
+                            self.infer(&field.expr);
+                        }
+                    }
+                }
+                if let Some(rest) = &struct_expr.rest {
+                    self.check_expr(rest, &ty);
+                }
+                ty
+            }
+            ExprKind::Block(block) => self.check_block(block, None),
+            ExprKind::If(cond, then, otherwise) => {
+                self.check_expr(cond, &Ty::Bool);
+                let then_ty = self.check_block(then, None);
+                match otherwise {
+                    Some(otherwise) => {
+                        let otherwise_ty = self.infer(otherwise);
+                        self.join(otherwise.span, then_ty, otherwise_ty)
+                    }
+                    None => Ty::unit(),
+                }
+            }
+            ExprKind::While(cond, body, _) => {
+                self.check_expr(cond, &Ty::Bool);
+                self.check_block(body, None);
+                Ty::unit()
+            }
+            ExprKind::For(pat, iter, body, _) => {
+                let elem = match &iter.kind {
+                    ExprKind::Range(start, end, _) => {
+                        self.check_range(start.as_deref(), end.as_deref())
+                    }
+                    _ => {
+                        let ty = self.infer(iter);
+                        match self.shallow(&ty) {
+                            Ty::Array(elem) => *elem,
+                            Ty::Str => Ty::Str,
+                            _ => Ty::Any,
+                        }
+                    }
+                };
+                self.bind_pat(pat, &elem);
+                self.check_block(body, None);
+                Ty::unit()
+            }
+            ExprKind::Loop(body, _) => {
+                // A loop without `break` never ends
+                self.loops.insert(expr.id, Ty::Never);
+                self.check_block(body, None);
+                self.loops.remove(&expr.id).unwrap_or(Ty::Never)
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                let scrutinee_ty = self.infer(scrutinee);
+                let mut ty = Ty::Never;
+                for arm in arms {
+                    self.bind_pat(&arm.pat, &scrutinee_ty);
+                    if let Some(guard) = &arm.guard {
+                        self.check_expr(guard, &Ty::Bool);
+                    }
+                    let arm_ty = self.infer(&arm.body);
+                    ty = self.join(arm.body.span, ty, arm_ty);
+                }
+                ty
+            }
+            ExprKind::Range(start, end, _) => {
+                self.check_range(start.as_deref(), end.as_deref());
+                Ty::Any
+            }
+            ExprKind::Return(value) => {
+                let ret = self
+                    .frames
+                    .last()
+                    .expect("a frame is always open")
+                    .ret
+                    .clone();
+                match value {
+                    Some(value) => self.check_expr(value, &ret),
+                    None => self.expect(expr.span, &ret, &Ty::unit()),
+                }
+                Ty::Never
+            }
+            ExprKind::Break(_, value) => {
+                let ty = value
+                    .as_ref()
+                    .map_or_else(Ty::unit, |value| self.infer(value));
+                // The values of the `break` of a `while` or `for` loop are reported by the resolver
+                let target = self.resolutions.loop_targets.get(&expr.id).copied();
+                if let Some((target, loop_ty)) = target.and_then(|id| self.loops.remove_entry(&id))
+                {
+                    let span = value.as_ref().map_or(expr.span, |value| value.span);
+                    let ty = self.join(span, loop_ty, ty);
+                    self.loops.insert(target, ty);
+                }
+                Ty::Never
+            }
+            ExprKind::Continue(_) => Ty::Never,
+            ExprKind::Closure(closure) => {
+                let ret = match &closure.ret {
+                    Some(ret) => self.lower_ty(ret),
+                    None => self.new_var(VarKind::General),
+                };
+                self.frames.push(Frame {
+                    id: Some(expr.id),
+                    ret: ret.clone(),
+                });
+                let mut params = vec![];
+                for param in &closure.params {
+                    let ty = match &param.ty {
+                        Some(ty) => self.lower_ty(ty),
+                        None => self.new_var(VarKind::General),
+                    };
+                    self.bind_pat(&param.pat, &ty);
+                    params.push(ty);
+                }
+                self.check_expr(&closure.body, &ret);
+                self.frames.pop();
+                Ty::Fn(params, Box::new(ret))
+            }
+            ExprKind::Err => Ty::Any,
+        }
+    }
+
+    fn infer_lit(&mut self, lit: &Lit) -> Ty {
+        let suffixed = |suffix: &Option<String>| suffix.as_deref().map(Ty::primitive);
+        match lit {
+            Lit::Int(lit) => match suffixed(&lit.suffix) {
+                Some(ty) => ty.unwrap_or(Ty::Any),
+                None => self.new_var(VarKind::Int),
+            },
+            Lit::Float(lit) => match suffixed(&lit.suffix) {
+                Some(ty) => ty.unwrap_or(Ty::Any),
+                None => self.new_var(VarKind::Float),
+            },
+            Lit::Str(_) => Ty::Str,
+            Lit::Bool(_) => Ty::Bool,
+            Lit::Nil => Ty::Nil,
+        }
+    }
+
+    fn infer_path(&mut self, id: NodeId, path: &Path) -> Ty {
+        let item = match self.resolutions.names.get(&id) {
+            Some(Res::Item(id)) => *id,
+            Some(Res::SelfTy(id)) => match self.self_ty(*id) {
+                Ty::Adt(id, _) => id,
+                _ => return Ty::Any,
+            },
+            // The associated constants of the integer types, like `u8::MAX`
+            Some(Res::Builtin(name)) => {
+                return match (IntTy::from_name(name), &path.segments[1..]) {
+                    (Some(ty), [assoc]) if int::associated_const(ty, &assoc.name).is_some() => {
+                        Ty::Int(ty)
+                    }
+                    _ => Ty::Any,
+                };
+            }
+            Some(res) => {
+                return match self.variable(*res, self.frames.len() - 1) {
+                    Some(variable) => self.variables.get(&variable).cloned().unwrap_or(Ty::Any),
+                    None => Ty::Any,
+                };
+            }
+            None => return Ty::Any,
+        };
+        let Some(decl) = self.items.get(&item).copied() else {
+            return Ty::Any;
+        };
+        match (&decl.kind, &path.segments[1..]) {
+            (ItemKind::Fn(decl), []) => self.fn_ty(decl, Ty::Any),
+            (ItemKind::Const(decl), []) => self.lower_ty(&decl.ty),
+            (ItemKind::Struct(decl), []) => {
+                let adt = self.adt(item);
+                self.constructor_ty(adt, &decl.fields)
+            }
+            (ItemKind::Struct(_) | ItemKind::Enum(_), [name]) => {
+                let adt = self.adt(item);
+                if let ItemKind::Enum(decl) = &decl.kind {
+                    if let Some(variant) = decl.variants.iter().find(|v| v.name.name == name.name) {
+                        return self.constructor_ty(adt, &variant.fields);
+                    }
+                }
+                match self.methods.get(&(item, name.name.as_str())).copied() {
+                    Some(method) => self.fn_ty(method, adt),
+                    None => Ty::Any,
+                }
+            }
+            _ => Ty::Any,
+        }
+    }
+
+    // Patterns
+
+    /// Checks the pattern against the type of the value it matches, recording the types of the
+    /// variables it declares.
+    fn bind_pat(&mut self, pat: &'a Pattern, ty: &Ty) {
+        match &pat.kind {
+            PatKind::Wild | PatKind::Rest | PatKind::Err => (),
+            PatKind::Ident(_, _, sub) => {
+                self.declare(pat.id, ty.clone());
+                if let Some(sub) = sub {
+                    self.bind_pat(sub, ty);
+                }
+            }
+            PatKind::Lit(expr) => {
+                let found = self.infer(expr);
+                self.expect(pat.span, ty, &found);
+            }
+            PatKind::Range(start, end, _) => {
+                let found = self.check_range(start.as_deref(), end.as_deref());
+                self.expect(pat.span, ty, &found);
+            }
+            PatKind::Tuple(pats) => {
+                let has_rest = pats.iter().any(|pat| matches!(pat.kind, PatKind::Rest));
+                let tys = match self.shallow(ty) {
+                    Ty::Tuple(tys) if has_rest || tys.len() == pats.len() => Some(tys),
+                    Ty::Any | Ty::Never => None,
+                    _ if has_rest => None,
+                    _ => {
+                        let tys: Vec<_> = pats
+                            .iter()
+                            .map(|_| self.new_var(VarKind::General))
+                            .collect();
+                        self.expect(pat.span, ty, &Ty::Tuple(tys.clone()));
+                        Some(tys)
+                    }
+                };
+                self.bind_elements(pats, tys.as_deref());
+            }
+            PatKind::Slice(pats) => {
+                let elem = match self.shallow(ty) {
+                    Ty::Array(elem) => *elem,
+                    Ty::Any | Ty::Never => Ty::Any,
+                    _ => {
+                        let elem = self.new_var(VarKind::General);
+                        self.expect(pat.span, ty, &Ty::Array(Box::new(elem.clone())));
+                        elem
+                    }
+                };
+                for pat in pats {
+                    match &pat.kind {
+                        PatKind::Ident(_, _, Some(sub)) if matches!(sub.kind, PatKind::Rest) => {
+                            self.declare(pat.id, Ty::Array(Box::new(elem.clone())));
+                        }
+                        _ => self.bind_pat(pat, &elem),
+                    }
+                }
+            }
+            PatKind::Or(alternatives) => {
+                for alternative in alternatives {
+                    self.bind_pat(alternative, ty);
+                }
+            }
+            PatKind::Path(path) => {
+                if let Some((adt, _)) = self.struct_of_path(pat.id, path) {
+                    self.expect(pat.span, ty, &adt);
+                }
+            }
+            PatKind::TupleStruct(path, pats) => {
+                let Some((adt, fields)) = self.struct_of_path(pat.id, path) else {
+                    self.bind_elements(pats, None);
+                    return;
+                };
+                self.expect(pat.span, ty, &adt);
+                let tys = match fields {
+                    VariantFields::Tuple(fields) => Some(
+                        fields
+                            .iter()
+                            .map(|field| self.lower_ty(&field.ty))
+                            .collect::<Vec<_>>(),
+                    ),
+                    _ => None,
+                };
+                self.bind_elements(pats, tys.as_deref());
+            }
+            PatKind::Struct(path, field_pats, _) => {
+                let Some((adt, fields)) = self.struct_of_path(pat.id, path) else {
+                    for field_pat in field_pats {
+                        self.bind_pat(&field_pat.pat, &Ty::Any);
+                    }
+                    return;
+                };
+                self.expect(pat.span, ty, &adt);
+                for field_pat in field_pats {
+                    let name = &field_pat.name;
+                    let field_ty = self.field_ty(fields, &name.name).unwrap_or_else(|| {
+                        self.unknown_field(name.span, &name.name, &adt);
+                        Ty::Any
+                    });
+                    self.bind_pat(&field_pat.pat, &field_ty);
+                }
+            }
+        }
+    }
+
+    /// Checks the element patterns of a tuple or tuple struct pattern, possibly containing a `..`,
+    /// against the types of the elements, if known.
+    fn bind_elements(&mut self, pats: &'a [Pattern], tys: Option<&[Ty]>) {
+        let rest = pats
+            .iter()
+            .position(|pat| matches!(pat.kind, PatKind::Rest));
+        for (index, pat) in pats.iter().enumerate() {
+            let ty = tys.and_then(|tys| match rest {
+                Some(rest) if index > rest => {
+                    tys.len().checked_sub(pats.len() - index).map(|i| &tys[i])
+                }
+                _ => tys.get(index),
+            });
+            let ty = ty.cloned().unwrap_or(Ty::Any);
+            self.bind_pat(pat, &ty);
+        }
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {noun}"),
+        count => format!("{count} {noun}s"),
+    }
+}
+
+#[cfg(test)]
+mod tests;
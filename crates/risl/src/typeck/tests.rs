@@ -0,0 +1,181 @@
+use crate::test_utils::{self, check_with_options};
+use crate::CheckOptions;
+
+/// Checks the source with the type checker enabled, returning the emitted diagnostics.
+fn check(source: &str) -> Vec<String> {
+    check_with_options(source, CheckOptions { type_check: true })
+}
+
+#[test]
+fn annotations_are_checked() {
+    assert_eq!(
+        check("let x: i64 = \"hello\";"),
+        ["error: mismatched types: expected `i64`, found `str`"]
+    );
+    assert_eq!(
+        check("fn f(a: str) -> bool { a }"),
+        ["error: mismatched types: expected `bool`, found `str`"]
+    );
+    assert_eq!(
+        check("fn f(a: str) -> bool { a == \"yes\" }\nlet b: bool = f(\"no\");"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn let_bindings_are_inferred() {
+    assert_eq!(
+        check("let x = 1;\nlet y: str = x;"),
+        ["error: mismatched types: expected `str`, found `{integer}`"]
+    );
+    // The type of an integer literal is given by its use
+    assert_eq!(
+        check("let x = 1;\nlet y: u8 = x;\nlet z: u8 = x + 2;"),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        check("let x = 1;\nlet y: u8 = x;\nlet z: i64 = x;"),
+        ["error: mismatched types: expected `i64`, found `u8`"]
+    );
+    assert_eq!(
+        check("let pair = (1.5, \"a\");\nlet (a, b) = pair;\nlet c: str = a;"),
+        ["error: mismatched types: expected `str`, found `{float}`"]
+    );
+}
+
+#[test]
+fn unannotated_values_are_any() {
+    let source = "fn f(a, b) { a + b }\n\
+         let x: str = f(1, 2);\n\
+         let y: i64 = f(\"a\", \"b\");";
+    assert_eq!(check(source), Vec::<String>::new());
+    assert_eq!(
+        check("fn f(a) { a.anything(1).field }"),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn calls() {
+    assert_eq!(
+        check("fn f(a: i64, b: str) {}\nf(1);"),
+        ["error: this function takes 2 arguments but 1 argument was supplied"]
+    );
+    assert_eq!(
+        check("fn f(a: i64, b: str) {}\nf(\"a\", \"b\");"),
+        ["error: mismatched types: expected `i64`, found `str`"]
+    );
+    assert_eq!(
+        check("let x = 1;\nx(2);"),
+        ["error: expected function, found `{integer}`"]
+    );
+    // The type of closures is inferred from their uses
+    assert_eq!(
+        check("let double = |x| x * 2;\nlet y: str = double(2);"),
+        ["error: mismatched types: expected `str`, found `{integer}`"]
+    );
+}
+
+#[test]
+fn operators() {
+    assert_eq!(
+        check("let x = true + 1;"),
+        ["error: cannot apply operator `+` to type `bool`"]
+    );
+    assert_eq!(
+        check("let x = 1 && true;"),
+        ["error: mismatched types: expected `bool`, found `{integer}`"]
+    );
+    assert_eq!(
+        check("let x = 1 + \"a\";"),
+        ["error: mismatched types: expected `{integer}`, found `str`"]
+    );
+    assert_eq!(
+        check("let x: bool = 1 < 2;\nlet y = \"a\" + \"b\";\nlet z = -1.5;"),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        check("if 1 { }"),
+        ["error: mismatched types: expected `bool`, found `{integer}`"]
+    );
+    // The type of the last operand of a chain is known from its first one
+    let chain = format!("let x: bool = {}1u8;", "1 + ".repeat(50));
+    assert_eq!(
+        check(&chain),
+        ["error: mismatched types: expected `bool`, found `u8`"]
+    );
+}
+
+#[test]
+fn branches_have_the_same_type() {
+    assert_eq!(
+        check("fn f(c: bool) -> i64 { if c { 1 } else { \"one\" } }"),
+        ["error: mismatched types: expected `{integer}`, found `str`"]
+    );
+    assert_eq!(
+        check("fn f(c: bool) -> i64 { if c { return 0; } else { 1 } }"),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        check("fn f(n: i64) -> str { match n { 0 => \"zero\", _ => 1 } }"),
+        ["error: mismatched types: expected `str`, found `{integer}`"]
+    );
+    assert_eq!(
+        check("fn f(n: i64) -> str { return n; }"),
+        ["error: mismatched types: expected `str`, found `i64`"]
+    );
+}
+
+#[test]
+fn structs_and_enums() {
+    let source = "struct Point { x: i64, y: i64 }\n\
+         enum Shape { Circle(f64), Square(f64) }\n\
+         impl Point { fn norm(&self) -> i64 { self.x * self.x + self.y * self.y } }\n";
+    assert_eq!(
+        check(&format!(
+            "{source}let p = Point {{ x: 1, y: 2 }};\n\
+             let n: i64 = p.norm();\n\
+             let s = Shape::Circle(1.0);\n\
+             let r: f64 = match s {{ Shape::Circle(r) => r, Shape::Square(side) => side }};"
+        )),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        check(&format!("{source}let p = Point {{ x: 1, y: \"2\" }};")),
+        ["error: mismatched types: expected `i64`, found `str`"]
+    );
+    assert_eq!(
+        check(&format!(
+            "{source}let p = Point {{ x: 1, y: 2 }};\nlet z = p.z;"
+        )),
+        ["error: no field `z` on type `Point`"]
+    );
+    assert_eq!(
+        check(&format!(
+            "{source}let s: str = Point {{ x: 1, y: 2 }}.norm();"
+        )),
+        ["error: mismatched types: expected `str`, found `i64`"]
+    );
+    assert_eq!(
+        check(&format!("{source}let p = Point {{ x: 1, y: 2 }};\nmatch p {{ Shape::Circle(r) => r, _ => 0.0 }};")),
+        ["error: mismatched types: expected `Point`, found `Shape`"]
+    );
+}
+
+#[test]
+fn variables_captured_by_closures() {
+    assert_eq!(
+        check("fn f() {\n    let name = \"risl\";\n    let g = || { let n: i64 = name; };\n}"),
+        ["error: mismatched types: expected `i64`, found `str`"]
+    );
+}
+
+#[test]
+fn type_checking_is_enabled_by_an_attribute() {
+    let source = "let x: i64 = \"hello\";";
+    assert_eq!(test_utils::check(source), Vec::<String>::new());
+    assert_eq!(
+        test_utils::check(&format!("#![type_check]\n{source}")),
+        ["error: mismatched types: expected `i64`, found `str`"]
+    );
+}
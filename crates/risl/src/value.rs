@@ -0,0 +1,437 @@
+//! The values manipulated by Risl programs at run time.
+//!
+//! Values follow the semantics of Rust values: assigning or passing a tuple, an array, a map or a
+//! struct gives a copy of it, and a place can only be modified through its variable or through a
+//! mutable reference created by `&mut`. The aggregates are shared behind reference-counted
+//! pointers and only copied when modified while shared, so that copying a value is cheap.
+//!
+//! The variables of a function call live in an [`Env`], shared with the closures capturing them
+//! and with the mutable references borrowing them.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::int::IntTy;
+use crate::parser::ast::NodeId;
+
+/// The variables of a function call, of a closure call or of the top-level code, by slot.
+pub type Env = Rc<RefCell<Vec<Value>>>;
+
+/// Creates an environment with the given number of slots, initialized to `nil`.
+pub fn new_env(size: usize) -> Env {
+    Rc::new(RefCell::new(vec![Value::Nil; size]))
+}
+
+/// Creates the environment of the globals, which cannot be read before their declaration runs.
+pub fn globals_env(size: usize) -> Env {
+    Rc::new(RefCell::new(vec![Value::Uninit; size]))
+}
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    /// An integer of type `i64`, the type of the integer literals without suffix.
+    Int(i64),
+    /// An integer of another type, like `255u8`, by value and type. The values of type `u128` are
+    /// stored as their bits.
+    Fixed(i128, IntTy),
+    Float(f64),
+    Str(Rc<str>),
+    /// A tuple, the empty tuple being the unit value `()`.
+    Tuple(Rc<Vec<Value>>),
+    Array(Rc<Vec<Value>>),
+    Map(Rc<Map>),
+    Range(Rc<Range>),
+    /// An iterator, returned by methods like `iter` and `map`.
+    Iter(Rc<Iter>),
+    /// A struct or an enum variant.
+    Adt(Rc<Adt>),
+    /// A function or a method declared in the program.
+    Fn(Function),
+    Closure(Rc<Closure>),
+    /// A built-in function, by name.
+    Builtin(&'static str),
+    /// The constructor of a tuple struct or tuple variant, by item id and variant index.
+    Ctor(NodeId, usize),
+    /// A mutable reference.
+    Ref(Rc<Place>),
+    /// The value of a global whose declaration has not run yet, which the program cannot read.
+    Uninit,
+}
+
+impl Value {
+    pub fn unit() -> Value {
+        Value::Tuple(Rc::new(vec![]))
+    }
+
+    pub fn str(value: &str) -> Value {
+        Value::Str(Rc::from(value))
+    }
+
+    /// Returns the integer of the given type, as a `Value::Int` for `i64`.
+    pub fn int(value: i128, ty: IntTy) -> Value {
+        match ty {
+            IntTy::I64 => Value::Int(value as i64),
+            ty => Value::Fixed(value, ty),
+        }
+    }
+
+    /// Returns the value and the type of an integer.
+    pub fn as_int(&self) -> Option<(i128, IntTy)> {
+        match self {
+            Value::Int(value) => Some((i128::from(*value), IntTy::I64)),
+            Value::Fixed(value, ty) => Some((*value, *ty)),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an integer which fits in an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(value) => Some(*value),
+            // The values of `u128` above the maximum of `i128` have negative bits
+            Value::Fixed(value, IntTy::U128) if *value < 0 => None,
+            Value::Fixed(value, _) => i64::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the type of the value, for the method lookup.
+    pub fn type_key(&self) -> TypeKey {
+        match self {
+            Value::Adt(adt) => TypeKey::Adt(adt.id),
+            Value::Nil => TypeKey::Builtin("nil"),
+            Value::Bool(_) => TypeKey::Builtin("bool"),
+            Value::Int(_) => TypeKey::Builtin("i64"),
+            Value::Fixed(_, ty) => TypeKey::Builtin(ty.name()),
+            Value::Float(_) => TypeKey::Builtin("f64"),
+            Value::Str(_) => TypeKey::Builtin("str"),
+            Value::Tuple(_) => TypeKey::Builtin("tuple"),
+            Value::Array(_) => TypeKey::Builtin("array"),
+            Value::Map(_) => TypeKey::Builtin("Map"),
+            Value::Range(_) => TypeKey::Builtin("range"),
+            Value::Iter(_) => TypeKey::Builtin("iterator"),
+            Value::Fn(_) | Value::Closure(_) | Value::Builtin(_) | Value::Ctor(..) => {
+                TypeKey::Builtin("fn")
+            }
+            Value::Ref(_) => TypeKey::Builtin("reference"),
+            Value::Uninit => TypeKey::Builtin("uninit"),
+        }
+    }
+}
+
+/// The type of a value, as named by the `impl` blocks.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TypeKey {
+    /// A struct or an enum, by item id.
+    Adt(NodeId),
+    /// A built-in type, by name.
+    Builtin(&'static str),
+}
+
+/// A function declared in the program, along with the type implementing it for a method.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Function {
+    pub id: NodeId,
+    pub self_ty: Option<TypeKey>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Closure {
+    /// The id of the closure expression.
+    pub id: NodeId,
+    /// The variables captured by the closure, in the order of its captures.
+    pub upvalues: Vec<Upvalue>,
+    /// The `Self` type of the function declaring the closure, if any.
+    pub self_ty: Option<TypeKey>,
+}
+
+/// A variable captured by closures, shared by all the closures capturing it: a slot of the
+/// environment declaring it, or of a private environment for a closure capturing by value.
+///
+/// An upvalue capturing a variable declared in a loop is closed when the iteration declaring it
+/// ends: the variable moves to a private environment, so that the closures created by each
+/// iteration capture their own variable, as the slot is reused by the next iteration.
+#[derive(Clone, Debug)]
+pub struct Upvalue(Rc<RefCell<(Env, usize)>>);
+
+impl Upvalue {
+    pub fn new(env: Env, slot: usize) -> Upvalue {
+        Upvalue(Rc::new(RefCell::new((env, slot))))
+    }
+
+    pub fn env(&self) -> Env {
+        self.0.borrow().0.clone()
+    }
+
+    pub fn place(&self) -> Place {
+        let (env, slot) = &*self.0.borrow();
+        Place::variable(env.clone(), *slot)
+    }
+
+    pub fn get(&self) -> Value {
+        let (env, slot) = &*self.0.borrow();
+        let value = env.borrow()[*slot].clone();
+        value
+    }
+
+    pub fn set(&self, value: Value) {
+        let (env, slot) = &*self.0.borrow();
+        env.borrow_mut()[*slot] = value;
+    }
+}
+
+/// The upvalues capturing the variables of a running frame that are not closed yet.
+#[derive(Default)]
+pub struct OpenUpvalues(Vec<Upvalue>);
+
+impl OpenUpvalues {
+    /// Returns the upvalue capturing the slot of the environment of the frame, shared with the
+    /// closures which already captured it.
+    pub fn capture(&mut self, env: &Env, slot: usize) -> Upvalue {
+        let open = self.0.iter().find(|upvalue| upvalue.0.borrow().1 == slot);
+        if let Some(upvalue) = open {
+            return upvalue.clone();
+        }
+        let upvalue = Upvalue::new(env.clone(), slot);
+        self.0.push(upvalue.clone());
+        upvalue
+    }
+
+    /// Closes the upvalues capturing the slots from the given one, calling `f` with the private
+    /// environment of each of them.
+    pub fn close_from(&mut self, first: usize, mut f: impl FnMut(&Env)) {
+        self.0.retain(|upvalue| {
+            let (env, slot) = &mut *upvalue.0.borrow_mut();
+            if *slot < first {
+                return true;
+            }
+            let value = env.borrow()[*slot].clone();
+            *env = Rc::new(RefCell::new(vec![value]));
+            *slot = 0;
+            f(env);
+            false
+        });
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Range {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub inclusive: bool,
+}
+
+impl Range {
+    /// Returns the end of the range, excluded, if any.
+    pub fn exclusive_end(&self) -> Option<i64> {
+        match (self.end, self.inclusive) {
+            (Some(end), true) => Some(end.saturating_add(1)),
+            (end, _) => end,
+        }
+    }
+}
+
+/// An iteration in progress, over the value of a `for` loop or held by an iterator value.
+///
+/// The adapters wrap the iteration they transform and only advance it when advanced themselves, so
+/// that `(0..).map(f).take(3)` calls `f` three times.
+#[derive(Clone, Debug)]
+pub enum Iter {
+    /// The elements of an array, between the given indices.
+    Array(Rc<Vec<Value>>, usize, usize),
+    /// The integers from the start, up to the excluded end if any.
+    Range(i64, Option<i64>),
+    /// The entries of a map as `(key, value)` tuples, between the given indices.
+    Entries(Rc<Map>, usize, usize),
+    /// The characters of a string, between the given byte offsets.
+    Chars(Rc<str>, usize, usize),
+    /// A value implementing `Iterator`, stored in the given place.
+    Iterator(Function, Rc<Place>),
+    /// The iterator value stored in the given place, which advances it, for `for x in &mut iter`.
+    Place(Rc<Place>),
+    /// The items of the iteration passed to the function.
+    Map(Box<Iter>, Value),
+    /// The items of the iteration for which the function returns `true`.
+    Filter(Box<Iter>, Value),
+    /// The items of the iteration as `(index, item)` tuples, from the given index.
+    Enumerate(Box<Iter>, i64),
+    /// The pairs of items of the two iterations, up to the end of the shorter one.
+    Zip(Box<Iter>, Box<Iter>),
+    /// The given number of items left of the iteration, at most.
+    Take(Box<Iter>, usize),
+    /// The items of the iteration after the given number of items left to skip.
+    Skip(Box<Iter>, usize),
+    /// The items of the first iteration, then of the second one.
+    Chain(Box<Iter>, Box<Iter>),
+    /// The items of a double-ended iteration, from its end.
+    Rev(Box<Iter>),
+}
+
+impl Iter {
+    /// Returns true if the iteration can be advanced from its end, which makes `rev` lazy.
+    pub fn is_double_ended(&self) -> bool {
+        match self {
+            Iter::Array(..) | Iter::Entries(..) | Iter::Chars(..) => true,
+            Iter::Range(_, end) => end.is_some(),
+            Iter::Map(iter, _) | Iter::Filter(iter, _) | Iter::Rev(iter) => iter.is_double_ended(),
+            Iter::Chain(first, second) => first.is_double_ended() && second.is_double_ended(),
+            Iter::Iterator(..)
+            | Iter::Place(_)
+            | Iter::Enumerate(..)
+            | Iter::Zip(..)
+            | Iter::Take(..)
+            | Iter::Skip(..) => false,
+        }
+    }
+
+    /// Returns true if advancing the iteration can call functions of the program.
+    pub fn calls_functions(&self) -> bool {
+        !matches!(
+            self,
+            Iter::Array(..) | Iter::Range(..) | Iter::Entries(..) | Iter::Chars(..)
+        )
+    }
+}
+
+/// The value of a struct or of an enum variant.
+#[derive(Clone, Debug)]
+pub struct Adt {
+    /// The id of the struct or enum item.
+    pub id: NodeId,
+    /// The index of the variant, always 0 for a struct.
+    pub variant: usize,
+    /// The values of the fields, in declaration order.
+    pub fields: Vec<Value>,
+}
+
+/// A place, that a mutable reference points to: a variable, or a part of its value.
+#[derive(Clone, Debug)]
+pub struct Place {
+    pub env: Env,
+    pub slot: usize,
+    pub projections: Vec<Projection>,
+}
+
+impl Place {
+    pub fn variable(env: Env, slot: usize) -> Place {
+        Place {
+            env,
+            slot,
+            projections: vec![],
+        }
+    }
+
+    /// Returns a copy of the value of the place, or `None` if the place no longer exists, like an
+    /// element removed from its array.
+    pub fn read(&self) -> Option<Value> {
+        let env = self.env.borrow();
+        let mut value = env.get(self.slot)?;
+        for projection in &self.projections {
+            value = match (value, projection) {
+                (Value::Tuple(values), Projection::Field(i)) => values.get(*i)?,
+                (Value::Adt(adt), Projection::Field(i)) => adt.fields.get(*i)?,
+                (Value::Array(values), Projection::Elem(i)) => values.get(*i)?,
+                (Value::Map(map), Projection::MapValue(i)) => &map.entries().get(*i)?.1,
+                _ => return None,
+            };
+        }
+        Some(value.clone())
+    }
+
+    /// Calls the given function with the value of the place, copying the aggregates containing it
+    /// if they are shared. Returns `None` if the place no longer exists.
+    pub fn modify<R>(&self, f: impl FnOnce(&mut Value) -> R) -> Option<R> {
+        let mut env = self.env.borrow_mut();
+        let mut value = env.get_mut(self.slot)?;
+        for projection in &self.projections {
+            value = match (value, projection) {
+                (Value::Tuple(values), Projection::Field(i)) => Rc::make_mut(values).get_mut(*i)?,
+                (Value::Adt(adt), Projection::Field(i)) => Rc::make_mut(adt).fields.get_mut(*i)?,
+                (Value::Array(values), Projection::Elem(i)) => Rc::make_mut(values).get_mut(*i)?,
+                (Value::Map(map), Projection::MapValue(i)) => Rc::make_mut(map).value_mut(*i)?,
+                _ => return None,
+            };
+        }
+        Some(f(value))
+    }
+
+    /// Returns the place of a part of the value of this place.
+    pub fn project(mut self, projection: Projection) -> Place {
+        self.projections.push(projection);
+        self
+    }
+}
+
+/// A step from a value to a part of it.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Projection {
+    /// A field of a tuple, struct or variant, by index.
+    Field(usize),
+    /// An element of an array, by index.
+    Elem(usize),
+    /// The value of an entry of a map, by index.
+    MapValue(usize),
+}
+
+/// A map from keys to values, iterated in insertion order.
+///
+/// The keys are compared with the `Eq` and `Hash` traits, which can be implemented by the
+/// program: the map only stores the hash of each key, computed by the interpreter, and leaves the
+/// comparison of the keys with the same hash to it.
+#[derive(Clone, Default, Debug)]
+pub struct Map {
+    entries: Vec<(Value, Value)>,
+    hashes: Vec<i64>,
+    /// The indices of the entries, by hash of their key.
+    index: HashMap<i64, Vec<usize>>,
+}
+
+impl Map {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> &[(Value, Value)] {
+        &self.entries
+    }
+
+    /// Returns the indices of the entries whose key has the given hash.
+    pub fn candidates(&self, hash: i64) -> &[usize] {
+        self.index.get(&hash).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn value_mut(&mut self, index: usize) -> Option<&mut Value> {
+        self.entries.get_mut(index).map(|(_, value)| value)
+    }
+
+    /// Adds an entry for a key not in the map yet.
+    pub fn push(&mut self, hash: i64, key: Value, value: Value) {
+        self.index.entry(hash).or_default().push(self.entries.len());
+        self.entries.push((key, value));
+        self.hashes.push(hash);
+    }
+
+    /// Removes the entry at the given index, returning its value.
+    pub fn remove(&mut self, index: usize) -> Value {
+        let (_, value) = self.entries.remove(index);
+        self.hashes.remove(index);
+        self.index.clear();
+        for (i, hash) in self.hashes.iter().enumerate() {
+            self.index.entry(*hash).or_default().push(i);
+        }
+        value
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.hashes.clear();
+        self.index.clear();
+    }
+}
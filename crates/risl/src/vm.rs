@@ -0,0 +1,1041 @@
+//! The virtual machine, running the bytecode compiled from a checked program.
+//!
+//! The [`compiler`] translates each function, closure and the top-level code to a [`Chunk`] of
+//! instructions for a stack machine, following the design of clox from Crafting Interpreters.
+//! The machine keeps the temporaries of all the running calls on a single stack, and a frame per
+//! call with the variables of the call in an [`Env`], shared with the closures capturing them like
+//! in the interpreter.
+//!
+//! Only the evaluation of the code differs from the tree-walking interpreter: the semantics of the
+//! operations, the built-ins and the runtime errors come from the [`runtime`](crate::runtime)
+//! module, so that both backends give the same results, backtraces included.
+//!
+//! The machine can trace the execution, printing the values on the stack of the running frame
+//! before each instruction, along with the instruction as printed by the [`disassembler`].
+//!
+//! The environments shared by closures and references can form cycles, which the reference
+//! counts alone would leak: the machine breaks them with the tracing collector of the [`gc`]
+//! module.
+//!
+//! A compiled module can be stored in a `.rislc` file and loaded back with the [`serialize`]
+//! module, to run a program without parsing and checking it again.
+//!
+//! [`Chunk`]: chunk::Chunk
+
+pub mod chunk;
+mod compiler;
+pub mod disassembler;
+pub mod gc;
+pub mod serialize;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::rc::Rc;
+
+use chunk::{Capture, Op, Pat, Proto, Slot, Target, BIND_FOR, BIND_PARAM, BIN_OPS, UN_OPS};
+use chunk::{RANGE_END, RANGE_INCLUSIVE, RANGE_START, TRY_OPTION, TRY_RESULT};
+pub use compiler::compile;
+use gc::Heap;
+
+use crate::int::{self, IntTy};
+use crate::parser::ast::{BinOp, NodeId};
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::Diagnostic;
+use crate::parser::lexer::Span;
+use crate::runtime::{
+    self, error, read, stack_overflow, temporary, write, Builtins, Declarations, Method, Operand,
+    RunResult, Runtime, RuntimeError, OPTION_ID, RESULT_ID,
+};
+use crate::value::{self, Adt, Env, Function, Iter, OpenUpvalues, Upvalue, Value};
+
+/// A compiled program.
+#[derive(Clone, Debug)]
+pub struct Module {
+    pub decls: Declarations,
+    /// The top-level code.
+    pub main: Rc<Proto>,
+    /// The functions and methods with a body by item id, and the closures by expression id.
+    pub protos: HashMap<NodeId, Rc<Proto>>,
+    /// The number of variables of the top-level code captured by functions.
+    pub globals: usize,
+    /// The warnings of the static checks of the program, for the callers running a stored module
+    /// to report them again.
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// Options of a run of the virtual machine.
+#[derive(Default)]
+pub struct Options<'a> {
+    /// Where to write the trace of the execution, if anywhere.
+    pub trace: Option<&'a mut dyn Write>,
+    /// Collects the cycles after every allocation, to test the collector.
+    pub gc_stress: bool,
+    /// Where to write the statistics of the collector at the end of the run, if anywhere.
+    pub gc_stats: Option<&'a mut dyn Write>,
+}
+
+/// Runs the compiled program with the given options, writing what it prints to the output.
+pub fn run<'a>(
+    context: &'a ParseContext,
+    module: &'a Module,
+    output: &'a mut dyn Write,
+    options: Options<'a>,
+) -> Result<(), RuntimeError> {
+    let mut vm = Vm {
+        context,
+        module,
+        globals: value::globals_env(module.globals),
+        stack: vec![],
+        frames: vec![],
+        heap: Heap::new(options.gc_stress),
+        output,
+        trace: options.trace,
+    };
+    let span = module.main.chunk.span(0);
+    let result = vm
+        .push_frame(Callee::Main, module.main.clone(), None, None, vec![], span)
+        .and_then(|()| vm.run_frames(0));
+    let mut flushed = vm.output.flush();
+    if let Some(gc_stats) = options.gc_stats {
+        flushed = flushed.and(writeln!(gc_stats, "{}", vm.heap.stats));
+    }
+    if let Err(error) = result {
+        return Err(*error);
+    }
+    flushed.map_err(|err| *runtime::error(span, runtime::output_error(err)))
+}
+
+/// What a frame is running, for the backtraces.
+#[derive(Clone, Copy)]
+enum Callee {
+    Main,
+    Function(Function),
+    Closure,
+}
+
+/// A running function, closure or top-level code.
+struct Frame {
+    proto: Rc<Proto>,
+    /// The offset of the next instruction, while the frame calls another one.
+    ip: usize,
+    env: Env,
+    /// The running closure, giving access to its captured variables.
+    closure: Option<Rc<value::Closure>>,
+    /// The type implementing the running method.
+    self_ty: Option<value::TypeKey>,
+    /// The height of the stack when the frame was called, below its arguments.
+    base: usize,
+    /// The iterations of the `for` loops in progress, innermost last.
+    iters: Vec<Iter>,
+    /// Whether closures or references can share the variables, tracked by the collector.
+    shared: bool,
+    /// The upvalues capturing variables of the frame declared in loops, closed at the end of
+    /// their iteration.
+    open_upvalues: OpenUpvalues,
+    callee: Callee,
+    call_span: Span,
+}
+
+struct Vm<'a> {
+    context: &'a ParseContext,
+    module: &'a Module,
+    globals: Env,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    heap: Heap,
+    output: &'a mut dyn Write,
+    trace: Option<&'a mut dyn Write>,
+}
+
+impl<'a> Vm<'a> {
+    fn bug(&self, span: Span, message: &str) -> ! {
+        self.context.diag_ctx().bug(Some(span), message)
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("a frame is always running")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("a frame is always running")
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the stack is not empty")
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack.last().expect("the stack is not empty")
+    }
+
+    fn pop_n(&mut self, count: usize) -> Vec<Value> {
+        self.stack.split_off(self.stack.len() - count)
+    }
+
+    fn callee_name(&self, callee: Callee) -> Option<String> {
+        match callee {
+            Callee::Main => None,
+            Callee::Function(function) => Some(self.module.decls.fn_name(function)),
+            Callee::Closure => Some(String::from("{closure}")),
+        }
+    }
+
+    /// Pushes a frame calling the function or closure, with its arguments on the stack in reverse
+    /// order for its parameters to pop them in order.
+    fn push_frame(
+        &mut self,
+        callee: Callee,
+        proto: Rc<Proto>,
+        closure: Option<Rc<value::Closure>>,
+        self_ty: Option<value::TypeKey>,
+        args: Vec<Value>,
+        span: Span,
+    ) -> RunResult<()> {
+        // Spans are only tracked per call, looking them up for each instruction would be too slow
+        crate::ice::set_current_span(span);
+        if self.frames.len() > runtime::MAX_CALL_DEPTH {
+            let mut error = stack_overflow(span);
+            if let Some(name) = self.callee_name(callee) {
+                error.trace.push((name, span));
+            }
+            return Err(error);
+        }
+        let base = self.stack.len();
+        self.stack.extend(args.into_iter().rev());
+        self.heap.allocate();
+        self.frames.push(Frame {
+            env: value::new_env(proto.frame_size),
+            proto,
+            ip: 0,
+            closure,
+            self_ty,
+            base,
+            iters: vec![],
+            shared: false,
+            open_upvalues: OpenUpvalues::default(),
+            callee,
+            call_span: span,
+        });
+        Ok(())
+    }
+
+    fn push_function(&mut self, function: Function, args: Vec<Value>, span: Span) -> RunResult<()> {
+        let Some(proto) = self.module.protos.get(&function.id) else {
+            self.bug(span, "call to a function without body");
+        };
+        let proto = proto.clone();
+        let callee = Callee::Function(function);
+        self.push_frame(callee, proto, None, function.self_ty, args, span)
+    }
+
+    fn push_closure(
+        &mut self,
+        closure: Rc<value::Closure>,
+        args: Vec<Value>,
+        span: Span,
+    ) -> RunResult<()> {
+        let proto = self.module.protos[&closure.id].clone();
+        let self_ty = closure.self_ty;
+        self.push_frame(Callee::Closure, proto, Some(closure), self_ty, args, span)
+    }
+
+    /// Runs the frames above the given number of frames until they return, returning the value
+    /// returned by the lowest one. On an error, the frames are popped and added to its stack
+    /// trace.
+    fn run_frames(&mut self, boundary: usize) -> RunResult {
+        let result = self.execute(boundary);
+        if let Err(mut error) = result {
+            while self.frames.len() > boundary {
+                let frame = self.frames.pop().expect("a frame is running");
+                self.stack.truncate(frame.base);
+                if let Some(name) = self.callee_name(frame.callee) {
+                    error.trace.push((name, frame.call_span));
+                }
+            }
+            return Err(error);
+        }
+        result
+    }
+
+    /// Runs the frame pushed by a built-in calling back into the program. The collector can only
+    /// run meanwhile if the built-in rooted the values it holds.
+    fn run_callback(&mut self) -> RunResult {
+        let boundary = self.frames.len() - 1;
+        let unrooted = self.heap.rooted != Some(boundary);
+        self.heap.unrooted += usize::from(unrooted);
+        let result = self.run_frames(boundary);
+        self.heap.unrooted -= usize::from(unrooted);
+        result
+    }
+
+    fn execute(&mut self, boundary: usize) -> RunResult {
+        let mut proto = self.frame().proto.clone();
+        let mut env = self.frame().env.clone();
+        let mut ip = self.frame().ip;
+        loop {
+            if self.heap.can_collect() {
+                self.heap.collect(&self.stack, &self.globals, &self.frames);
+            }
+            let offset = ip;
+            let chunk = &proto.chunk;
+            if self.trace.is_some() {
+                self.trace_instruction(&proto, offset)?;
+            }
+            let Some(op) = Op::from_byte(chunk.code[ip]) else {
+                self.bug(chunk.span(offset), "invalid instruction");
+            };
+            ip += 1;
+            macro_rules! read_u8 {
+                () => {{
+                    let operand = chunk.read_u8(ip);
+                    ip += 1;
+                    usize::from(operand)
+                }};
+            }
+            macro_rules! read_u16 {
+                () => {{
+                    let operand = chunk.read_u16(ip);
+                    ip += 2;
+                    usize::from(operand)
+                }};
+            }
+            macro_rules! span {
+                () => {
+                    chunk.span(offset)
+                };
+            }
+            match op {
+                Op::Constant => {
+                    let index = read_u16!();
+                    self.stack.push(chunk.constants[index].clone());
+                }
+                Op::Unit => self.stack.push(Value::unit()),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::PopN => {
+                    let count = read_u16!();
+                    self.stack.truncate(self.stack.len() - count);
+                }
+                Op::GetLocal => {
+                    let slot = read_u16!();
+                    let value = env.borrow()[slot].clone();
+                    self.stack.push(value);
+                }
+                Op::SetLocal => {
+                    let slot = read_u16!();
+                    env.borrow_mut()[slot] = self.pop();
+                }
+                Op::GetUpvalue => {
+                    let upvalue = self.upvalue(read_u16!(), span!());
+                    let value = upvalue.get();
+                    self.stack.push(value);
+                }
+                Op::SetUpvalue => {
+                    let upvalue = self.upvalue(read_u16!(), span!());
+                    upvalue.set(self.pop());
+                }
+                Op::GetGlobal => {
+                    let slot = read_u16!();
+                    let value = self.globals.borrow()[slot].clone();
+                    if let Value::Uninit = value {
+                        return Err(runtime::uninit_error(span!()));
+                    }
+                    self.stack.push(value);
+                }
+                Op::SetGlobal => {
+                    let slot = read_u16!();
+                    let value = self.pop();
+                    self.globals.borrow_mut()[slot] = value;
+                }
+                Op::RefLocal => {
+                    self.share_env();
+                    let place = value::Place::variable(env.clone(), read_u16!());
+                    self.stack.push(Value::Ref(Rc::new(place)));
+                }
+                Op::RefUpvalue => {
+                    let upvalue = self.upvalue(read_u16!(), span!());
+                    let place = upvalue.place();
+                    self.stack.push(Value::Ref(Rc::new(place)));
+                }
+                Op::RefGlobal => {
+                    let place = value::Place::variable(self.globals.clone(), read_u16!());
+                    self.stack.push(Value::Ref(Rc::new(place)));
+                }
+                Op::RefField => {
+                    let name = &chunk.names[read_u16!()];
+                    let base = to_operand(self.pop());
+                    let field = self.field_operand(base, name, span!())?;
+                    self.stack.push(from_operand(field));
+                }
+                Op::RefIndex => {
+                    let index = self.pop();
+                    let base = to_operand(self.pop());
+                    let element = self.index_operand(base, index, span!())?;
+                    self.stack.push(from_operand(element));
+                }
+                Op::BorrowMut => {
+                    let value = self.pop();
+                    self.stack.push(match value {
+                        Value::Ref(_) => value,
+                        value => {
+                            let place = temporary(value);
+                            self.heap.allocate();
+                            self.heap.track(&place.env);
+                            Value::Ref(Rc::new(place))
+                        }
+                    });
+                }
+                Op::CheckPlace => {
+                    if !matches!(self.peek(), Value::Ref(_)) {
+                        return Err(error(span!(), "invalid left-hand side of assignment"));
+                    }
+                }
+                Op::Store => {
+                    let target = self.pop();
+                    let value = self.pop();
+                    match target {
+                        Value::Ref(place) => write(&place, span!(), value)?,
+                        _ => return Err(error(span!(), "invalid left-hand side of assignment")),
+                    }
+                }
+                Op::StoreIndex => {
+                    let index = self.pop();
+                    let base = to_operand(self.pop());
+                    let value = self.pop();
+                    self.assign_index(base, index, value, span!())?;
+                }
+                Op::Update => {
+                    let op = BIN_OPS[read_u8!()];
+                    let value = self.pop();
+                    let Value::Ref(place) = self.pop() else {
+                        self.bug(span!(), "expected a place");
+                    };
+                    let current = read(&place, span!())?;
+                    let value = self.binary(op, current, value, span!())?;
+                    write(&place, span!(), value)?;
+                }
+                Op::UpdateLocal => {
+                    let slot = read_u16!();
+                    let op = BIN_OPS[read_u8!()];
+                    let value = self.pop();
+                    let current = env.borrow()[slot].clone();
+                    let value = self.binary(op, current, value, span!())?;
+                    env.borrow_mut()[slot] = value;
+                }
+                Op::Binary => {
+                    let op = BIN_OPS[read_u8!()];
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let value = self.binary(op, lhs, rhs, span!())?;
+                    self.stack.push(value);
+                }
+                Op::Unary => {
+                    let op = UN_OPS[read_u8!()];
+                    let value = self.pop();
+                    let value = self.unary_op(op, value, span!())?;
+                    self.stack.push(value);
+                }
+                Op::CheckBool => {
+                    if !matches!(self.peek(), Value::Bool(_)) {
+                        return Err(self.expected_bool(self.peek(), span!()));
+                    }
+                }
+                Op::Cast => {
+                    let Value::Str(target) = &chunk.constants[read_u16!()] else {
+                        self.bug(span!(), "expected the name of a type");
+                    };
+                    let value = self.pop();
+                    let value = self.cast(value, target, span!())?;
+                    self.stack.push(value);
+                }
+                Op::Jump => {
+                    let jump = read_u16!();
+                    ip += jump;
+                }
+                Op::JumpIfFalse => {
+                    let jump = read_u16!();
+                    match self.pop() {
+                        Value::Bool(true) => (),
+                        Value::Bool(false) => ip += jump,
+                        value => return Err(self.expected_bool(&value, span!())),
+                    }
+                }
+                Op::Loop => {
+                    let jump = read_u16!();
+                    ip -= jump;
+                }
+                Op::Call => {
+                    let args = self.pop_n(read_u8!());
+                    let span = span!();
+                    match self.pop() {
+                        Value::Fn(function) => {
+                            let args = self.function_args(function, args, span)?;
+                            self.frame_mut().ip = ip;
+                            self.push_function(function, args, span)?;
+                        }
+                        Value::Closure(closure) => {
+                            let args = self.closure_args(&closure, args, span)?;
+                            self.frame_mut().ip = ip;
+                            self.push_closure(closure, args, span)?;
+                        }
+                        callee => {
+                            let value = self.call(callee, args, span)?;
+                            self.stack.push(value);
+                            continue;
+                        }
+                    }
+                    proto = self.frame().proto.clone();
+                    env = self.frame().env.clone();
+                    ip = 0;
+                }
+                Op::Invoke => {
+                    let name = &chunk.names[read_u16!()];
+                    let args = self.pop_n(read_u8!());
+                    let span = span!();
+                    let receiver = to_operand(self.pop());
+                    match self.resolve_method(receiver, name)? {
+                        Method::Fn(function, receiver) => {
+                            let args = std::iter::once(receiver).chain(args).collect();
+                            let args = self.function_args(function, args, span)?;
+                            self.frame_mut().ip = ip;
+                            self.push_function(function, args, span)?;
+                            proto = self.frame().proto.clone();
+                            env = self.frame().env.clone();
+                            ip = 0;
+                        }
+                        Method::Builtin(receiver, value) => {
+                            let value =
+                                self.call_builtin_method(receiver, value, &name.name, args, span)?;
+                            self.stack.push(value);
+                        }
+                    }
+                }
+                Op::Return => {
+                    let value = self.pop();
+                    // An `Err` returned by the top-level code stops the program
+                    if boundary == 0 && self.frames.len() == 1 {
+                        self.check_exit(&value, span!())?;
+                    }
+                    let frame = self.frames.pop().expect("a frame is running");
+                    self.stack.truncate(frame.base);
+                    if self.frames.len() == boundary {
+                        return Ok(value);
+                    }
+                    self.stack.push(value);
+                    proto = self.frame().proto.clone();
+                    env = self.frame().env.clone();
+                    ip = self.frame().ip;
+                }
+                Op::Try => {
+                    let returns = match chunk.read_u8(ip) {
+                        TRY_OPTION => Some(OPTION_ID),
+                        TRY_RESULT => Some(RESULT_ID),
+                        _ => None,
+                    };
+                    ip += 1;
+                    let jump = read_u16!();
+                    let value = self.pop();
+                    match self.try_branch(value, returns, span!())? {
+                        ControlFlow::Continue(value) => {
+                            self.stack.push(value);
+                            ip += jump;
+                        }
+                        ControlFlow::Break(residual) => self.stack.push(residual),
+                    }
+                }
+                Op::Closure => {
+                    let id = chunk.read_u32(ip);
+                    ip += 4;
+                    let closure = self.closure(id, &env, span!());
+                    self.stack.push(closure);
+                }
+                Op::Field => {
+                    let name = &chunk.names[read_u16!()];
+                    let value = self.pop();
+                    let value = self.project(value, name, span!())?;
+                    self.stack.push(value);
+                }
+                Op::Index => {
+                    let index = self.pop();
+                    let base = self.pop();
+                    let value = self.index(base, index, span!())?;
+                    self.stack.push(value);
+                }
+                Op::Tuple => {
+                    let values = self.pop_n(read_u16!());
+                    self.stack.push(Value::Tuple(Rc::new(values)));
+                }
+                Op::Array => {
+                    let values = self.pop_n(read_u16!());
+                    self.stack.push(Value::Array(Rc::new(values)));
+                }
+                Op::Struct => {
+                    let layout = read_u16!();
+                    let value = self.make_struct(&proto, layout)?;
+                    self.stack.push(value);
+                }
+                Op::Range => {
+                    let flags = chunk.read_u8(ip);
+                    ip += 1;
+                    let end = (flags & RANGE_END != 0).then(|| self.pop());
+                    let start = (flags & RANGE_START != 0).then(|| self.pop());
+                    let inclusive = flags & RANGE_INCLUSIVE != 0;
+                    let value = self.range(start, end, inclusive, span!())?;
+                    self.stack.push(value);
+                }
+                Op::TestPattern => {
+                    let pat = &chunk.patterns[read_u16!()];
+                    let value = self.peek().clone();
+                    let matched = self.match_pat(pat, &value, span!())?;
+                    self.stack.push(Value::Bool(matched));
+                }
+                Op::NoMatch => {
+                    let value = self.pop();
+                    let message = format!("no match arm matches `{}`", self.repr(&value, span!())?);
+                    return Err(error(span!(), message));
+                }
+                Op::Bind => {
+                    let pat = &chunk.patterns[read_u16!()];
+                    let context = chunk.read_u8(ip);
+                    ip += 1;
+                    let value = self.pop();
+                    if !self.match_pat(pat, &value, span!())? {
+                        let context = match context {
+                            BIND_FOR => "`for` loop",
+                            BIND_PARAM => "function argument",
+                            _ => "local binding",
+                        };
+                        let message = format!(
+                            "refutable pattern in {context} does not match `{}`",
+                            self.repr(&value, span!())?
+                        );
+                        return Err(error(span!(), message));
+                    }
+                }
+                Op::CheckBounds => {
+                    let bounds = &chunk.bounds[read_u16!()];
+                    let value = self.pop();
+                    let value = self.module.decls.check_bounds(bounds, value, span!())?;
+                    self.stack.push(value);
+                }
+                Op::IterStart => {
+                    let value = self.pop();
+                    let iter = self.iter(value, span!())?;
+                    self.frame_mut().iters.push(iter);
+                }
+                Op::IterNext => {
+                    let jump = read_u16!();
+                    let mut iter = self
+                        .frame_mut()
+                        .iters
+                        .pop()
+                        .expect("an iteration is running");
+                    let next = if iter.calls_functions() {
+                        // The iteration is taken out of the frame while it calls its functions
+                        self.rooted(|vm| {
+                            vm.root(&Value::Iter(Rc::new(iter.clone())));
+                            vm.next(&mut iter, span!())
+                        })?
+                    } else {
+                        self.next(&mut iter, span!())?
+                    };
+                    self.frame_mut().iters.push(iter);
+                    match next {
+                        Some(item) => self.stack.push(item),
+                        None => ip += jump,
+                    }
+                }
+                Op::IterEnd => {
+                    self.frame_mut().iters.pop();
+                }
+                Op::CloseUpvalues => {
+                    let first = read_u16!();
+                    let frame = self.frames.last_mut().expect("a frame is always running");
+                    let heap = &mut self.heap;
+                    frame.open_upvalues.close_from(first, |env| {
+                        heap.allocate();
+                        heap.track(env);
+                    });
+                }
+                Op::Fail => {
+                    let index = usize::from(chunk.read_u16(ip));
+                    let Value::Str(message) = &chunk.constants[index] else {
+                        self.bug(span!(), "expected an error message");
+                    };
+                    return Err(error(span!(), &**message));
+                }
+                Op::SelfPath => {
+                    let path = &chunk.paths[read_u16!()];
+                    let Some(key) = self.frame().self_ty else {
+                        self.bug(span!(), "unknown `Self` type");
+                    };
+                    let value = self.module.decls.assoc_value(key, path, span!())?;
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+
+    /// Writes the values on the stack of the running frame, followed by the instruction about to
+    /// run.
+    fn trace_instruction(&mut self, proto: &Proto, offset: usize) -> RunResult<()> {
+        let decls = &self.module.decls;
+        let mut line = String::new();
+        let stack = &self.stack[self.frame().base..];
+        if !stack.is_empty() {
+            line.push_str("          ");
+            for value in stack {
+                line.push_str(&format!("[ {} ]", disassembler::value(decls, value)));
+            }
+            line.push('\n');
+        }
+        line.push_str(&format!("{:<10}", proto.name));
+        disassembler::instruction(&mut line, decls, &proto.chunk, offset);
+        let trace = self.trace.as_mut().expect("tracing is enabled");
+        writeln!(trace, "{line}")
+            .map_err(|err| error(proto.chunk.span(offset), runtime::output_error(err)))
+    }
+
+    fn upvalue(&self, index: usize, span: Span) -> Upvalue {
+        match &self.frame().closure {
+            Some(closure) => closure.upvalues[index].clone(),
+            None => self.bug(span, "expected a closure"),
+        }
+    }
+
+    fn expected_bool(&self, value: &Value, span: Span) -> Box<RuntimeError> {
+        let message = format!(
+            "expected `bool`, found `{}`",
+            self.module.decls.type_name(value)
+        );
+        error(span, message)
+    }
+
+    /// Applies a binary operator, with a fast path for integers.
+    fn binary(&mut self, op: BinOp, lhs: Value, rhs: Value, span: Span) -> RunResult {
+        let (Value::Int(lhs), Value::Int(rhs)) = (&lhs, &rhs) else {
+            return self.binary_op(op, lhs, rhs, span);
+        };
+        let (lhs, rhs) = (*lhs, *rhs);
+        Ok(match op {
+            BinOp::Eq => Value::Bool(lhs == rhs),
+            BinOp::Ne => Value::Bool(lhs != rhs),
+            BinOp::Lt => Value::Bool(lhs < rhs),
+            BinOp::Le => Value::Bool(lhs <= rhs),
+            BinOp::Gt => Value::Bool(lhs > rhs),
+            BinOp::Ge => Value::Bool(lhs >= rhs),
+            op => {
+                let (lhs, rhs) = (i128::from(lhs), i128::from(rhs));
+                let value = match op {
+                    BinOp::Shl | BinOp::Shr => {
+                        int::shift(op, lhs, u32::try_from(rhs).ok(), IntTy::I64)
+                    }
+                    op => int::int_op(op, lhs, rhs, IntTy::I64),
+                };
+                // The result is in the range of `i64`
+                Value::Int(value.map_err(|err| error(span, err.message(op)))? as i64)
+            }
+        })
+    }
+
+    fn closure(&mut self, id: u32, env: &Env, span: Span) -> Value {
+        let Some(proto) = self.module.protos.get(&id) else {
+            self.bug(span, "unknown closure");
+        };
+        let captures_locals = proto
+            .captures
+            .iter()
+            .any(|c| matches!(c, Capture::Local(_)));
+        if captures_locals && !proto.by_value {
+            self.share_env();
+        }
+        let mut upvalues: Vec<Upvalue> = proto
+            .captures
+            .iter()
+            .map(|capture| match *capture {
+                Capture::Local(slot) if proto.by_value => Upvalue::new(env.clone(), slot),
+                Capture::Local(slot) => self.frame_mut().open_upvalues.capture(env, slot),
+                Capture::Upvalue(index) => self.upvalue(index, span),
+                Capture::Global(slot) => Upvalue::new(self.globals.clone(), slot),
+            })
+            .collect();
+        if proto.by_value {
+            let env = value::new_env(upvalues.len());
+            self.heap.allocate();
+            self.heap.track(&env);
+            for (slot, upvalue) in upvalues.iter_mut().enumerate() {
+                env.borrow_mut()[slot] = upvalue.get();
+                *upvalue = Upvalue::new(env.clone(), slot);
+            }
+        }
+        Value::Closure(Rc::new(value::Closure {
+            id,
+            upvalues,
+            self_ty: self.frame().self_ty,
+        }))
+    }
+
+    /// Tracks the variables of the running frame, once closures or references can share them.
+    fn share_env(&mut self) {
+        let frame = self.frames.last_mut().expect("a frame is always running");
+        if !frame.shared {
+            frame.shared = true;
+            self.heap.track(&frame.env);
+        }
+    }
+
+    /// Creates the struct of the layout from the values on the stack.
+    fn make_struct(&mut self, proto: &Proto, layout: usize) -> RunResult {
+        let layout = &proto.chunk.layouts[layout];
+        let base = layout.rest.map(|_| self.pop());
+        let values = self.pop_n(layout.fields.len());
+        let Some((id, variant)) = self.resolve_target(&layout.target) else {
+            let message = format!("expected struct, found `{}`", layout.path);
+            return Err(error(layout.path_span, message));
+        };
+        let decl = self.module.decls.variant(id, variant);
+        let mut fields: Vec<Option<Value>> = vec![None; decl.fields.len()];
+        for (field, value) in layout.fields.iter().zip(values) {
+            let Some(index) = decl.fields.iter().position(|name| *name == field.name) else {
+                let message = format!("`{}` has no field named `{}`", decl.name, field.name);
+                return Err(error(field.span, message));
+            };
+            fields[index] = Some(value);
+        }
+        if let (Some(base), Some(span)) = (base, layout.rest) {
+            match runtime::deref(base, span)? {
+                Value::Adt(base) if base.id == id && base.variant == variant => {
+                    for (field, value) in fields.iter_mut().zip(base.fields.iter()) {
+                        field.get_or_insert_with(|| value.clone());
+                    }
+                }
+                base => {
+                    let message = format!(
+                        "expected `{}`, found `{}`",
+                        decl.name,
+                        self.module.decls.type_name(&base)
+                    );
+                    return Err(error(span, message));
+                }
+            }
+        }
+        let fields = fields
+            .into_iter()
+            .zip(&decl.fields)
+            .map(|(value, name)| {
+                value.ok_or_else(|| {
+                    let message =
+                        format!("missing field `{name}` in initializer of `{}`", decl.name);
+                    error(layout.path_span, message)
+                })
+            })
+            .collect::<RunResult<_>>()?;
+        Ok(Value::Adt(Rc::new(Adt {
+            id,
+            variant,
+            fields,
+        })))
+    }
+
+    // Patterns
+
+    /// Returns the struct or enum variant named by a path.
+    fn resolve_target(&self, target: &Target) -> Option<(NodeId, usize)> {
+        match target {
+            Target::Variant(id, variant) => Some((*id, *variant)),
+            // In the default methods of a trait, `Self` is the type implementing it
+            Target::SelfTy(rest) => self
+                .module
+                .decls
+                .resolve_variant(self.frame().self_ty?, rest),
+            Target::None => None,
+        }
+    }
+
+    fn bind(&self, slot: Slot, value: Value) {
+        match slot {
+            Slot::Local(slot) => self.frame().env.borrow_mut()[slot] = value,
+            Slot::Global(slot) => self.globals.borrow_mut()[slot] = value,
+        }
+    }
+
+    /// Matches the value against the pattern, binding its variables.
+    fn match_pat(&mut self, pat: &Pat, value: &Value, span: Span) -> RunResult<bool> {
+        // Patterns match the referenced value, while bindings bind the reference
+        if let Value::Ref(place) = value {
+            if !matches!(pat, Pat::Bind(..) | Pat::Wild) {
+                let value = read(place, span)?;
+                return self.match_pat(pat, &value, span);
+            }
+        }
+        Ok(match pat {
+            Pat::Wild | Pat::Rest(_) => true,
+            Pat::Bind(slot, sub) => {
+                if let Some(sub) = sub {
+                    if !self.match_pat(sub, value, span)? {
+                        return Ok(false);
+                    }
+                }
+                self.bind(*slot, value.clone());
+                true
+            }
+            Pat::Value(expected) => self.values_eq(expected, value, span)?,
+            Pat::Range(start, end, inclusive) => {
+                if let Some(start) = start {
+                    if self.compare(start, value, span)? == Some(Ordering::Greater) {
+                        return Ok(false);
+                    }
+                }
+                if let Some(end) = end {
+                    let ordering = self.compare(value, end, span)?;
+                    return Ok(match inclusive {
+                        false => ordering == Some(Ordering::Less),
+                        true => ordering.is_some_and(Ordering::is_le),
+                    });
+                }
+                true
+            }
+            Pat::Tuple(pats) => match value {
+                Value::Tuple(values) => self.match_seq(pats, values, span)?,
+                _ => false,
+            },
+            Pat::Slice(pats) => match value {
+                Value::Array(values) => self.match_seq(pats, values, span)?,
+                _ => false,
+            },
+            Pat::Or(alternatives) => {
+                for alternative in alternatives {
+                    if self.match_pat(alternative, value, span)? {
+                        return Ok(true);
+                    }
+                }
+                false
+            }
+            Pat::Unit(target) => self.match_variant(target, value).is_some(),
+            Pat::TupleVariant(target, pats) => match self.match_variant(target, value) {
+                Some(adt) => self.match_seq(pats, &adt.fields, span)?,
+                None => false,
+            },
+            Pat::Struct(target, fields) => {
+                let Some(adt) = self.match_variant(target, value) else {
+                    return Ok(false);
+                };
+                for (name, pat) in fields {
+                    let Some(index) = self.module.decls.field_index(adt, name) else {
+                        return Ok(false);
+                    };
+                    if !self.match_pat(pat, &adt.fields[index], span)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+            Pat::Fail(message, span) => return Err(error(*span, message.as_str())),
+        })
+    }
+
+    /// Returns the value if it is the struct or enum variant named by the target.
+    fn match_variant<'v>(&self, target: &Target, value: &'v Value) -> Option<&'v Adt> {
+        let (id, variant) = self.resolve_target(target)?;
+        match value {
+            Value::Adt(adt) if adt.id == id && adt.variant == variant => Some(adt),
+            _ => None,
+        }
+    }
+
+    /// Matches the elements of a tuple, array or tuple struct against the patterns, which can
+    /// contain a rest pattern `..`, bound to the remaining elements in a slice pattern.
+    fn match_seq(&mut self, pats: &[Pat], values: &[Value], span: Span) -> RunResult<bool> {
+        let Some(rest) = pats.iter().position(|pat| matches!(pat, Pat::Rest(_))) else {
+            if pats.len() != values.len() {
+                return Ok(false);
+            }
+            for (pat, value) in pats.iter().zip(values) {
+                if !self.match_pat(pat, value, span)? {
+                    return Ok(false);
+                }
+            }
+            return Ok(true);
+        };
+        let (before, after) = (&pats[..rest], &pats[rest + 1..]);
+        if values.len() < before.len() + after.len() {
+            return Ok(false);
+        }
+        let middle = &values[before.len()..values.len() - after.len()];
+        for (pat, value) in before.iter().zip(values) {
+            if !self.match_pat(pat, value, span)? {
+                return Ok(false);
+            }
+        }
+        for (pat, value) in after.iter().zip(&values[values.len() - after.len()..]) {
+            if !self.match_pat(pat, value, span)? {
+                return Ok(false);
+            }
+        }
+        if let Pat::Rest(Some(slot)) = pats[rest] {
+            self.bind(slot, Value::Array(Rc::new(middle.to_vec())));
+        }
+        Ok(true)
+    }
+}
+
+/// Converts a value of the stack to an operand, a reference standing for the place it points to.
+fn to_operand(value: Value) -> Operand {
+    match value {
+        Value::Ref(place) => Operand::Place((*place).clone()),
+        value => Operand::Value(value),
+    }
+}
+
+fn from_operand(operand: Operand) -> Value {
+    match operand {
+        Operand::Place(place) => Value::Ref(Rc::new(place)),
+        Operand::Value(value) => value,
+    }
+}
+
+impl<'a> Runtime<'a> for Vm<'a> {
+    fn decls(&self) -> &'a Declarations {
+        &self.module.decls
+    }
+
+    fn output(&mut self) -> &mut dyn Write {
+        self.output
+    }
+
+    fn call_function(&mut self, function: Function, args: Vec<Value>, span: Span) -> RunResult {
+        let args = self.function_args(function, args, span)?;
+        self.push_function(function, args, span)?;
+        self.run_callback()
+    }
+
+    fn call_closure(
+        &mut self,
+        closure: Rc<value::Closure>,
+        args: Vec<Value>,
+        span: Span,
+    ) -> RunResult {
+        let args = self.closure_args(&closure, args, span)?;
+        self.push_closure(closure, args, span)?;
+        self.run_callback()
+    }
+
+    fn roots(&mut self) -> Option<&mut Vec<Value>> {
+        self.heap.rooted.map(|_| &mut self.heap.roots)
+    }
+
+    fn rooted<T>(&mut self, f: impl FnOnce(&mut Self) -> RunResult<T>) -> RunResult<T> {
+        let len = self.heap.roots.len();
+        let rooted = self.heap.rooted.replace(self.frames.len());
+        let result = f(self);
+        self.heap.roots.truncate(len);
+        self.heap.rooted = rooted;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests;
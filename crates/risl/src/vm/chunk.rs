@@ -0,0 +1,324 @@
+//! The bytecode of the functions run by the virtual machine.
+
+use crate::parser::ast::{BinOp, Ident, NodeId, UnOp};
+use crate::parser::lexer::Span;
+use crate::runtime::Bounds;
+use crate::value::Value;
+
+macro_rules! opcodes {
+    ($($(#[$doc:meta])* $name:ident,)*) => {
+        /// An instruction, encoded as one byte followed by its operands in little endian.
+        #[repr(u8)]
+        #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+        pub enum Op {
+            $($(#[$doc])* $name,)*
+        }
+
+        impl Op {
+            const ALL: &'static [Op] = &[$(Op::$name,)*];
+
+            /// Decodes an instruction, returning `None` for an invalid byte.
+            pub fn from_byte(byte: u8) -> Option<Op> {
+                Op::ALL.get(usize::from(byte)).copied()
+            }
+
+            /// Returns the size in bytes of the operands of the instruction.
+            pub fn operands_size(self) -> usize {
+                match self {
+                    Op::Unit | Op::Pop | Op::RefIndex | Op::BorrowMut | Op::CheckPlace
+                    | Op::Store | Op::StoreIndex | Op::CheckBool | Op::Return | Op::Index
+                    | Op::NoMatch | Op::IterStart | Op::IterEnd => 0,
+                    Op::Update | Op::Binary | Op::Unary | Op::Call | Op::Range => 1,
+                    Op::UpdateLocal | Op::Invoke | Op::Bind | Op::Try => 3,
+                    Op::Closure => 4,
+                    _ => 2,
+                }
+            }
+        }
+    };
+}
+
+opcodes! {
+    /// `Constant(index: u16)`: pushes a value of the constant pool.
+    Constant,
+    /// Pushes the unit value `()`.
+    Unit,
+    Pop,
+    /// `PopN(count: u16)`: pops values, for the temporaries left by a `break` or `continue`.
+    PopN,
+    /// `GetLocal(slot: u16)`: pushes the value of a variable of the frame.
+    GetLocal,
+    /// `SetLocal(slot: u16)`: pops a value and stores it in a variable of the frame.
+    SetLocal,
+    /// `GetUpvalue(index: u16)`: pushes the value of a variable captured by the running closure.
+    GetUpvalue,
+    SetUpvalue,
+    /// `GetGlobal(slot: u16)`: pushes the value of a variable of the top-level code.
+    GetGlobal,
+    SetGlobal,
+    /// `RefLocal(slot: u16)`: pushes a reference to a variable of the frame, as an assignable
+    /// operand.
+    RefLocal,
+    RefUpvalue,
+    RefGlobal,
+    /// `RefField(name: u16)`: replaces an operand with its field, as an operand.
+    RefField,
+    /// Replaces an operand and an index with the indexed element, as an operand.
+    RefIndex,
+    /// Replaces an operand with a mutable reference to it, borrowing a temporary for a value.
+    BorrowMut,
+    /// Checks that the operand on the top of the stack is assignable.
+    CheckPlace,
+    /// Pops an operand and a value, and assigns the value to the operand.
+    Store,
+    /// Pops a value, an operand and an index, and assigns the value to the indexed element,
+    /// inserting the key into a map.
+    StoreIndex,
+    /// `Update(op: u8)`: pops an operand and a value, and applies the compound assignment.
+    Update,
+    /// `UpdateLocal(slot: u16, op: u8)`: pops a value, and applies the compound assignment to a
+    /// variable of the frame.
+    UpdateLocal,
+    /// `Binary(op: u8)`: replaces two values with the result of the operator.
+    Binary,
+    /// `Unary(op: u8)`: replaces a value with the result of the operator.
+    Unary,
+    /// Checks that the value on the top of the stack is a `bool`.
+    CheckBool,
+    /// `Cast(target: u16)`: casts a value to the built-in type named by a constant.
+    Cast,
+    /// `Jump(offset: u16)`: jumps forward.
+    Jump,
+    /// `JumpIfFalse(offset: u16)`: pops a `bool`, and jumps forward if it is false.
+    JumpIfFalse,
+    /// `Loop(offset: u16)`: jumps backward.
+    Loop,
+    /// `Call(argc: u8)`: calls the callee below the arguments.
+    Call,
+    /// `Invoke(name: u16, argc: u8)`: calls a method on the operand below the arguments.
+    Invoke,
+    /// Returns the value on the top of the stack from the running function.
+    Return,
+    /// `Try(returns: u8, offset: u16)`: pops an `Option` or a `Result`, and either pushes the
+    /// value of its `Some` or `Ok` and jumps forward, or pushes it back to be returned. The
+    /// operand must be of the enum the function returns, if `returns` names one.
+    Try,
+    /// `Closure(id: u32)`: creates a closure, capturing the variables of the frame.
+    Closure,
+    /// `Field(name: u16)`: replaces a value with its field.
+    Field,
+    /// Replaces a value and an index with the indexed element.
+    Index,
+    /// `Tuple(len: u16)`: replaces values with a tuple of them.
+    Tuple,
+    /// `Array(len: u16)`: replaces values with an array of them.
+    Array,
+    /// `Struct(layout: u16)`: replaces the values of the fields of a struct expression, followed
+    /// by its base struct if any, with the struct.
+    Struct,
+    /// `Range(flags: u8)`: replaces the bounds of a range with the range. The flags tell which
+    /// bounds are present, and if the range is inclusive.
+    Range,
+    /// `TestPattern(pattern: u16)`: matches the value on the top of the stack against a pattern,
+    /// binding its variables, and pushes whether it matched.
+    TestPattern,
+    /// Reports that no arm of a `match` matches the value on the top of the stack.
+    NoMatch,
+    /// `Bind(pattern: u16, context: u8)`: pops a value and matches it against an irrefutable
+    /// pattern, binding its variables.
+    Bind,
+    /// `CheckBounds(bounds: u16)`: checks the trait bounds of the value on the top of the stack.
+    CheckBounds,
+    /// Pops a value and starts iterating over it.
+    IterStart,
+    /// `IterNext(offset: u16)`: pushes the next item of the innermost iteration, or jumps forward
+    /// at its end.
+    IterNext,
+    /// Ends the innermost iteration.
+    IterEnd,
+    /// `CloseUpvalues(slot: u16)`: gives the variables of the frame from the slot captured by
+    /// closures their own storage, at the end of an iteration of a loop declaring them.
+    CloseUpvalues,
+    /// `Fail(message: u16)`: stops the program with a runtime error detected while compiling.
+    Fail,
+    /// `SelfPath(path: u16)`: pushes the value of a path starting with `Self` in a default method
+    /// of a trait, where it depends on the type implementing the trait.
+    SelfPath,
+}
+
+/// The binary operators, by operand of the instructions.
+pub const BIN_OPS: &[BinOp] = &[
+    BinOp::Add,
+    BinOp::Sub,
+    BinOp::Mul,
+    BinOp::Div,
+    BinOp::Rem,
+    BinOp::And,
+    BinOp::Or,
+    BinOp::BitXor,
+    BinOp::BitAnd,
+    BinOp::BitOr,
+    BinOp::Shl,
+    BinOp::Shr,
+    BinOp::Eq,
+    BinOp::Lt,
+    BinOp::Le,
+    BinOp::Ne,
+    BinOp::Ge,
+    BinOp::Gt,
+];
+
+pub const UN_OPS: &[UnOp] = &[UnOp::Neg, UnOp::Not, UnOp::Deref];
+
+/// The flags of the `Range` instruction.
+pub const RANGE_START: u8 = 1;
+pub const RANGE_END: u8 = 2;
+pub const RANGE_INCLUSIVE: u8 = 4;
+
+/// The enums the `Try` instruction checks its operand is of, from the return type of the
+/// function, or 0 for any.
+pub const TRY_OPTION: u8 = 1;
+pub const TRY_RESULT: u8 = 2;
+
+/// Where the values of the variables bound by a pattern are stored.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Slot {
+    Local(usize),
+    Global(usize),
+}
+
+/// Where a variable captured by a closure is taken from in the frame creating it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Capture {
+    Local(usize),
+    Upvalue(usize),
+    Global(usize),
+}
+
+/// The struct or enum variant named by a path.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Target {
+    Variant(NodeId, usize),
+    /// A path starting with `Self` in a default method of a trait, with its other segments.
+    SelfTy(Vec<Ident>),
+    /// A path naming no variant, which matches nothing.
+    None,
+}
+
+/// A compiled pattern.
+#[derive(Clone, Debug)]
+pub enum Pat {
+    Wild,
+    Bind(Slot, Option<Box<Pat>>),
+    /// A literal or a constant, compared for equality.
+    Value(Value),
+    Range(Option<Value>, Option<Value>, bool),
+    Tuple(Vec<Pat>),
+    Slice(Vec<Pat>),
+    /// The rest pattern `..`, binding the remaining elements in a slice pattern.
+    Rest(Option<Slot>),
+    Or(Vec<Pat>),
+    Unit(Target),
+    TupleVariant(Target, Vec<Pat>),
+    Struct(Target, Vec<(String, Pat)>),
+    /// A pattern raising a runtime error at the given span when matched.
+    Fail(String, Span),
+}
+
+/// What the pattern of a `Bind` instruction binds, for diagnostics.
+pub const BIND_LOCAL: u8 = 0;
+pub const BIND_FOR: u8 = 1;
+pub const BIND_PARAM: u8 = 2;
+
+/// The struct or variant created by a struct expression, with the names of its fields in the
+/// order of their values.
+#[derive(Clone, PartialEq, Debug)]
+pub struct StructLayout {
+    pub target: Target,
+    /// The path of the struct and its span, for diagnostics.
+    pub path: String,
+    pub path_span: Span,
+    pub fields: Vec<Ident>,
+    /// The span of the base struct, if any.
+    pub rest: Option<Span>,
+}
+
+/// The bytecode of a function, a closure or the top-level code, with its constant pool and side
+/// tables.
+#[derive(Clone, Default, Debug)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    /// The field and method names, with their spans.
+    pub names: Vec<Ident>,
+    pub patterns: Vec<Pat>,
+    pub bounds: Vec<Bounds>,
+    pub layouts: Vec<StructLayout>,
+    /// The remaining segments of the paths of `SelfPath` instructions.
+    pub paths: Vec<Vec<Ident>>,
+    /// The span of the instructions, as the offset of the first instruction with each span.
+    pub spans: Vec<(u32, Span)>,
+}
+
+impl Chunk {
+    /// Appends an instruction with the given span.
+    pub fn emit(&mut self, op: Op, span: Span) {
+        let offset = self.code.len() as u32;
+        if self.spans.last().is_none_or(|(_, last)| *last != span) {
+            self.spans.push((offset, span));
+        }
+        self.code.push(op as u8);
+    }
+
+    pub fn emit_u8(&mut self, operand: u8) {
+        self.code.push(operand);
+    }
+
+    pub fn emit_u16(&mut self, operand: u16) {
+        self.code.extend_from_slice(&operand.to_le_bytes());
+    }
+
+    pub fn emit_u32(&mut self, operand: u32) {
+        self.code.extend_from_slice(&operand.to_le_bytes());
+    }
+
+    pub fn read_u8(&self, offset: usize) -> u8 {
+        self.code[offset]
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    pub fn read_u32(&self, offset: usize) -> u32 {
+        let bytes = &self.code[offset..offset + 4];
+        u32::from_le_bytes(bytes.try_into().expect("4 bytes"))
+    }
+
+    /// Overwrites a 16-bit operand, to patch a forward jump.
+    pub fn patch_u16(&mut self, offset: usize, operand: u16) {
+        self.code[offset..offset + 2].copy_from_slice(&operand.to_le_bytes());
+    }
+
+    /// Returns the span of the instruction at the given offset.
+    pub fn span(&self, offset: usize) -> Span {
+        let index = self
+            .spans
+            .partition_point(|(start, _)| *start as usize <= offset);
+        self.spans[index.saturating_sub(1)].1
+    }
+}
+
+/// A compiled function, closure or top-level code.
+#[derive(Clone, Debug)]
+pub struct Proto {
+    /// The name of the function, for the disassembler.
+    pub name: String,
+    /// The number of slots of the frames running it.
+    pub frame_size: usize,
+    pub chunk: Chunk,
+    /// The variables captured by a closure.
+    pub captures: Vec<Capture>,
+    /// True for a closure capturing its variables by value.
+    pub by_value: bool,
+}
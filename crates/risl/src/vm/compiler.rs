@@ -0,0 +1,957 @@
+//! The compiler from the AST of a checked program to the bytecode of the virtual machine.
+//!
+//! Each function, closure and the top-level code is compiled to its own [`Chunk`]. Expressions
+//! push exactly one value on the stack of the virtual machine, and the compiler keeps track of the
+//! number of temporaries on it so that `break` and `continue` can pop them. The paths to items are
+//! resolved while compiling, the ones failing to resolve being compiled to a `Fail` instruction so
+//! that the error is only reported if the code runs, like the interpreter does.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::chunk::*;
+use super::Module;
+use crate::error_codes;
+use crate::parser::ast::*;
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::{Diagnostic, Level};
+use crate::parser::lexer::Span;
+use crate::resolve::{Res, Resolutions};
+use crate::runtime::{
+    builtin_type_key, builtin_variant, collect_items, lit_value, returned_enum, Bounds,
+    Declarations, OPTION_ID,
+};
+use crate::value::{Function, TypeKey, Value};
+
+/// Compiles the checked program, reporting the functions exceeding the limits of the bytecode
+/// through the context.
+pub fn compile(
+    context: &ParseContext,
+    program: &Program,
+    resolutions: &Resolutions,
+    decls: Declarations,
+) -> Module {
+    let (items, closures) = collect_items([program]);
+    let mut protos = HashMap::new();
+    for (id, item) in &items {
+        let ItemKind::Fn(decl) = &item.kind else {
+            continue;
+        };
+        let Some(body) = &decl.body else {
+            continue;
+        };
+        let mut compiler = Compiler::new(context, resolutions, &decls);
+        compiler.returns = returned_enum(decl.ret.as_ref(), resolutions);
+        // The arguments are on the stack when the function starts
+        compiler.depth = decl.params.len() + usize::from(decl.self_param.is_some());
+        if let Some(param) = &decl.self_param {
+            compiler.bind_param(param.id, None, param.span);
+        }
+        for param in &decl.params {
+            compiler.bind_param(param.pat.id, Some(&param.pat), param.span);
+        }
+        compiler.block(body);
+        let name = function_name(&decls, *id);
+        let proto = compiler.finish(name, resolutions.frame_sizes[id], body.span);
+        protos.insert(*id, Rc::new(proto));
+    }
+    for (id, closure) in &closures {
+        let mut compiler = Compiler::new(context, resolutions, &decls);
+        compiler.returns = returned_enum(closure.ret.as_ref(), resolutions);
+        compiler.depth = closure.params.len();
+        for param in &closure.params {
+            compiler.bind_param(param.pat.id, Some(&param.pat), param.span);
+        }
+        compiler.expr(&closure.body);
+        let name = String::from("{closure}");
+        let mut proto = compiler.finish(name, resolutions.frame_sizes[id], closure.body.span);
+        proto.by_value = closure.capture_by == CaptureBy::Value;
+        let captures = resolutions.captures.get(id).map_or(&[][..], Vec::as_slice);
+        proto.captures = captures
+            .iter()
+            .map(|res| match res {
+                Res::Local(slot) => Capture::Local(*slot),
+                Res::Upvalue(index) => Capture::Upvalue(*index),
+                Res::Global(slot) => Capture::Global(*slot),
+                _ => context
+                    .diag_ctx()
+                    .bug(Some(closure.body.span), "expected a captured variable"),
+            })
+            .collect();
+        protos.insert(*id, Rc::new(proto));
+    }
+    let mut compiler = Compiler::new(context, resolutions, &decls);
+    compiler.stmts(&program.stmts, program.span);
+    let name = String::from("<main>");
+    // An `Err` returned by the trailing expression is reported at its statement
+    let span = program.stmts.last().map_or(program.span, |stmt| stmt.span);
+    let main = compiler.finish(name, resolutions.main_frame_size, span);
+    Module {
+        decls,
+        main: Rc::new(main),
+        protos,
+        globals: resolutions.globals.len(),
+        warnings: vec![],
+    }
+}
+
+/// Returns the name of the function, qualified by the type implementing it for a method of a
+/// single type.
+fn function_name(decls: &Declarations, id: NodeId) -> String {
+    let methods = decls.inherent.values().flat_map(HashMap::values);
+    let trait_methods = decls
+        .trait_impls
+        .values()
+        .flat_map(HashMap::values)
+        .flat_map(HashMap::values);
+    let mut self_types = methods
+        .chain(trait_methods)
+        .filter(|function| function.id == id)
+        .map(|function| function.self_ty);
+    match (self_types.next(), self_types.next()) {
+        (Some(self_ty), None) => decls.fn_name(Function { id, self_ty }),
+        _ => decls.functions[&id].name.clone(),
+    }
+}
+
+/// A loop being compiled.
+struct Loop {
+    /// The id of the loop expression, targeted by `break` and `continue`.
+    id: NodeId,
+    /// Whether the loop is a `for` loop, whose iteration must be ended when exiting it.
+    iterates: bool,
+    /// The offset `continue` jumps back to.
+    start: usize,
+    /// The number of values on the stack when the loop starts.
+    depth: usize,
+    /// The first slot of the variables declared in the loop, if closures capture them.
+    first_slot: Option<usize>,
+    /// The jumps of the `break` expressions, to patch at the end of the loop. They leave the
+    /// value of the loop on the stack.
+    breaks: Vec<usize>,
+}
+
+struct Compiler<'a> {
+    context: &'a ParseContext,
+    resolutions: &'a Resolutions,
+    decls: &'a Declarations,
+    chunk: Chunk,
+    /// The number of values on the stack of the frame.
+    depth: usize,
+    loops: Vec<Loop>,
+    /// The built-in enum the function returns, which its `?` operators must return.
+    returns: Option<NodeId>,
+    /// Whether a limit of the bytecode was exceeded, reported once per function.
+    overflowed: bool,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(
+        context: &'a ParseContext,
+        resolutions: &'a Resolutions,
+        decls: &'a Declarations,
+    ) -> Self {
+        Compiler {
+            context,
+            resolutions,
+            decls,
+            chunk: Chunk::default(),
+            depth: 0,
+            loops: vec![],
+            returns: None,
+            overflowed: false,
+        }
+    }
+
+    fn finish(mut self, name: String, frame_size: usize, span: Span) -> Proto {
+        self.emit(Op::Return, span);
+        Proto {
+            name,
+            frame_size,
+            chunk: self.chunk,
+            captures: vec![],
+            by_value: false,
+        }
+    }
+
+    fn bug(&self, span: Span, message: &str) -> ! {
+        self.context.diag_ctx().bug(Some(span), message)
+    }
+
+    /// Reports that the function exceeds a limit of the bytecode.
+    fn overflow(&mut self, span: Span, what: &str) {
+        if !self.overflowed {
+            self.overflowed = true;
+            let message = format!("this function has too many {what} to be compiled");
+            let diagnostic =
+                Diagnostic::spanned(Level::Error, span, message).with_code(error_codes::E0090);
+            self.context.diag_ctx().emit(diagnostic);
+        }
+    }
+
+    fn res(&self, id: NodeId, span: Span) -> Res {
+        match self.resolutions.names.get(&id) {
+            Some(res) => *res,
+            None => self.bug(span, "unresolved name"),
+        }
+    }
+
+    // Emitting instructions
+
+    fn emit(&mut self, op: Op, span: Span) {
+        self.chunk.emit(op, span);
+    }
+
+    fn emit_with_u16(&mut self, op: Op, operand: usize, span: Span) {
+        self.emit(op, span);
+        let operand = match u16::try_from(operand) {
+            Ok(operand) => operand,
+            Err(_) => {
+                self.overflow(span, "constants or variables");
+                0
+            }
+        };
+        self.chunk.emit_u16(operand);
+    }
+
+    fn emit_with_u8(&mut self, op: Op, operand: usize, span: Span) {
+        self.emit(op, span);
+        let operand = match u8::try_from(operand) {
+            Ok(operand) => operand,
+            Err(_) => {
+                self.overflow(span, "arguments");
+                0
+            }
+        };
+        self.chunk.emit_u8(operand);
+    }
+
+    fn constant(&mut self, value: Value, span: Span) {
+        self.chunk.constants.push(value);
+        self.emit_with_u16(Op::Constant, self.chunk.constants.len() - 1, span);
+    }
+
+    /// Emits an instruction raising a runtime error.
+    fn fail(&mut self, message: String, span: Span) {
+        self.chunk.constants.push(Value::str(&message));
+        self.emit_with_u16(Op::Fail, self.chunk.constants.len() - 1, span);
+    }
+
+    fn name(&mut self, name: &Ident) -> usize {
+        self.chunk.names.push(name.clone());
+        self.chunk.names.len() - 1
+    }
+
+    /// Emits a forward jump, returning the offset of its operand to patch.
+    fn emit_jump(&mut self, op: Op, span: Span) -> usize {
+        self.emit(op, span);
+        self.chunk.emit_u16(0);
+        self.chunk.code.len() - 2
+    }
+
+    /// Makes the forward jump with the operand at the given offset jump to the current offset.
+    fn patch_jump(&mut self, offset: usize, span: Span) {
+        let jump = self.chunk.code.len() - offset - 2;
+        match u16::try_from(jump) {
+            Ok(jump) => self.chunk.patch_u16(offset, jump),
+            Err(_) => self.overflow(span, "instructions"),
+        }
+    }
+
+    fn emit_loop(&mut self, start: usize, span: Span) {
+        self.emit(Op::Loop, span);
+        let jump = self.chunk.code.len() + 2 - start;
+        match u16::try_from(jump) {
+            Ok(jump) => self.chunk.emit_u16(jump),
+            Err(_) => {
+                self.overflow(span, "instructions");
+                self.chunk.emit_u16(0);
+            }
+        }
+    }
+
+    /// Emits the instruction popping the temporaries above the given depth.
+    fn pop_to(&mut self, depth: usize, span: Span) {
+        match self.depth - depth {
+            0 => (),
+            1 => self.emit(Op::Pop, span),
+            count => self.emit_with_u16(Op::PopN, count, span),
+        }
+    }
+
+    // Bindings
+
+    /// Binds the argument on the top of the stack to a parameter: arguments are pushed in
+    /// reverse order, the first one being on the top of the stack.
+    fn bind_param(&mut self, id: NodeId, pat: Option<&Pattern>, span: Span) {
+        match pat {
+            Some(pat) => self.bind(pat, BIND_PARAM, span),
+            None => self.set_variable(self.res(id, span), span),
+        }
+    }
+
+    /// Pops the value on the top of the stack and binds it to the pattern, reporting a mismatch
+    /// at the given span.
+    fn bind(&mut self, pat: &Pattern, context: u8, span: Span) {
+        if let PatKind::Ident(_, _, None) = &pat.kind {
+            self.set_variable(self.res(pat.id, pat.span), pat.span);
+            return;
+        }
+        let pat_index = self.pattern(pat);
+        self.emit_with_u16(Op::Bind, pat_index, span);
+        self.chunk.emit_u8(context);
+        self.depth -= 1;
+    }
+
+    /// Pops the value on the top of the stack and stores it in the variable.
+    fn set_variable(&mut self, res: Res, span: Span) {
+        match res {
+            Res::Local(slot) => self.emit_with_u16(Op::SetLocal, slot, span),
+            Res::Upvalue(index) => self.emit_with_u16(Op::SetUpvalue, index, span),
+            Res::Global(slot) => self.emit_with_u16(Op::SetGlobal, slot, span),
+            _ => self.bug(span, "expected a variable"),
+        }
+        self.depth -= 1;
+    }
+
+    // Statements
+
+    /// Compiles the statements of a block spanning the given span.
+    fn stmts(&mut self, stmts: &[Stmt], span: Span) {
+        let mut has_value = false;
+        for stmt in stmts {
+            if has_value {
+                self.emit(Op::Pop, stmt.span);
+                self.depth -= 1;
+            }
+            has_value = false;
+            match &stmt.kind {
+                StmtKind::Let(local) => self.local(local),
+                StmtKind::Expr(expr) => {
+                    self.expr(expr);
+                    has_value = true;
+                }
+                StmtKind::Semi(expr) => {
+                    self.expr(expr);
+                    self.emit(Op::Pop, stmt.span);
+                    self.depth -= 1;
+                }
+                StmtKind::Item(_) | StmtKind::Empty => (),
+                StmtKind::Err => self.bug(stmt.span, "invalid statement"),
+            }
+        }
+        if !has_value {
+            let span = stmts.last().map_or(span, |stmt| stmt.span);
+            self.emit(Op::Unit, span);
+            self.depth += 1;
+        }
+    }
+
+    fn local(&mut self, local: &Local) {
+        let Some(init) = &local.init else {
+            return;
+        };
+        self.expr(init);
+        if let Some(bounds) = local.ty.as_ref().and_then(Bounds::of) {
+            self.chunk.bounds.push(bounds);
+            self.emit_with_u16(Op::CheckBounds, self.chunk.bounds.len() - 1, init.span);
+        }
+        self.bind(&local.pat, BIND_LOCAL, local.pat.span);
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.stmts(&block.stmts, block.span);
+    }
+
+    // Expressions
+
+    /// Compiles the expression, pushing its value.
+    fn expr(&mut self, expr: &Expr) {
+        crate::ice::set_current_span(expr.span);
+        let depth = self.depth;
+        self.expr_kind(expr);
+        debug_assert_eq!(self.depth, depth + 1, "an expression pushes one value");
+    }
+
+    fn expr_kind(&mut self, expr: &Expr) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Lit(lit) => self.lit(lit, false, span),
+            ExprKind::Path(path) => self.path(expr.id, path, span),
+            ExprKind::Unary(UnOp::Neg, operand) if matches!(operand.kind, ExprKind::Lit(_)) => {
+                // The minimum of `i64` is written as the negation of a literal out of its range
+                let ExprKind::Lit(lit) = &operand.kind else {
+                    unreachable!()
+                };
+                self.lit(lit, true, span);
+            }
+            ExprKind::Unary(op, operand) => {
+                self.expr(operand);
+                let op = UN_OPS.iter().position(|un_op| un_op == op).unwrap();
+                self.emit_with_u8(Op::Unary, op, span);
+            }
+            ExprKind::AddrOf(Mutability::Not, operand) => self.expr(operand),
+            ExprKind::AddrOf(Mutability::Mut, operand) => {
+                self.operand(operand);
+                self.emit(Op::BorrowMut, span);
+            }
+            ExprKind::Binary(op, lhs, rhs) if op.is_lazy() => self.lazy(*op, lhs, rhs),
+            ExprKind::Binary(op, lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+                self.emit_with_u8(Op::Binary, bin_op(*op), span);
+                self.depth -= 1;
+            }
+            ExprKind::Assign(place, value) => self.assign(place, value),
+            ExprKind::AssignOp(op, place, value) => self.assign_op(expr, *op, place, value),
+            ExprKind::Cast(operand, ty) => {
+                self.expr(operand);
+                let target = match self.resolutions.names.get(&ty.id) {
+                    Some(Res::Builtin(name)) => name,
+                    _ => "_",
+                };
+                self.chunk.constants.push(Value::str(target));
+                self.emit_with_u16(Op::Cast, self.chunk.constants.len() - 1, span);
+            }
+            ExprKind::Call(callee, args) => {
+                self.expr(callee);
+                self.args(args);
+                self.emit_with_u8(Op::Call, args.len(), span);
+                self.depth -= args.len();
+            }
+            ExprKind::MethodCall(receiver, name, args) => {
+                self.operand(receiver);
+                self.args(args);
+                let name = self.name(name);
+                self.emit_with_u16(Op::Invoke, name, span);
+                let argc = u8::try_from(args.len()).unwrap_or_else(|_| {
+                    self.overflow(span, "arguments");
+                    0
+                });
+                self.chunk.emit_u8(argc);
+                self.depth -= args.len();
+            }
+            ExprKind::Field(base, name) => {
+                self.expr(base);
+                let name = self.name(name);
+                self.emit_with_u16(Op::Field, name, span);
+            }
+            ExprKind::Index(base, index) => {
+                self.expr(base);
+                self.expr(index);
+                self.emit(Op::Index, span);
+                self.depth -= 1;
+            }
+            ExprKind::Try(operand) => {
+                // Jumps over the return with the unwrapped value
+                self.expr(operand);
+                self.emit(Op::Try, span);
+                self.chunk.emit_u8(match self.returns {
+                    Some(OPTION_ID) => TRY_OPTION,
+                    Some(_) => TRY_RESULT,
+                    None => 0,
+                });
+                self.chunk.emit_u16(0);
+                let jump = self.chunk.code.len() - 2;
+                self.emit(Op::Return, span);
+                self.patch_jump(jump, span);
+            }
+            ExprKind::Tuple(exprs) => {
+                self.args(exprs);
+                self.emit_with_u16(Op::Tuple, exprs.len(), span);
+                self.depth = self.depth + 1 - exprs.len();
+            }
+            ExprKind::Array(exprs) => {
+                self.args(exprs);
+                self.emit_with_u16(Op::Array, exprs.len(), span);
+                self.depth = self.depth + 1 - exprs.len();
+            }
+            ExprKind::Struct(struct_expr) => self.struct_expr(expr, struct_expr),
+            ExprKind::Paren(operand) => self.expr(operand),
+            ExprKind::Block(block) => self.block(block),
+            ExprKind::If(cond, then, otherwise) => {
+                self.expr(cond);
+                let else_jump = self.emit_jump(Op::JumpIfFalse, cond.span);
+                self.depth -= 1;
+                self.block(then);
+                let end_jump = self.emit_jump(Op::Jump, span);
+                self.patch_jump(else_jump, span);
+                self.depth -= 1;
+                match otherwise {
+                    Some(otherwise) => self.expr(otherwise),
+                    None => {
+                        self.emit(Op::Unit, span);
+                        self.depth += 1;
+                    }
+                }
+                self.patch_jump(end_jump, span);
+            }
+            ExprKind::While(cond, body, _) => {
+                let start = self.chunk.code.len();
+                self.start_loop(expr.id, false, start);
+                self.expr(cond);
+                let exit_jump = self.emit_jump(Op::JumpIfFalse, cond.span);
+                self.depth -= 1;
+                self.block(body);
+                self.emit(Op::Pop, span);
+                self.depth -= 1;
+                self.close_upvalues(self.loops.len() - 1, span);
+                self.emit_loop(start, span);
+                self.patch_jump(exit_jump, span);
+                self.emit(Op::Unit, span);
+                self.depth += 1;
+                self.end_loop(span);
+            }
+            ExprKind::For(pat, iterable, body, _) => {
+                self.expr(iterable);
+                self.emit(Op::IterStart, iterable.span);
+                self.depth -= 1;
+                let start = self.chunk.code.len();
+                let exit_jump = self.emit_jump(Op::IterNext, iterable.span);
+                self.depth += 1;
+                self.bind(pat, BIND_FOR, pat.span);
+                self.start_loop(expr.id, true, start);
+                self.block(body);
+                self.emit(Op::Pop, span);
+                self.depth -= 1;
+                self.close_upvalues(self.loops.len() - 1, span);
+                self.emit_loop(start, span);
+                self.patch_jump(exit_jump, span);
+                self.emit(Op::Unit, span);
+                self.depth += 1;
+                self.end_loop(span);
+                self.emit(Op::IterEnd, span);
+            }
+            ExprKind::Loop(body, _) => {
+                let start = self.chunk.code.len();
+                self.start_loop(expr.id, false, start);
+                self.block(body);
+                self.emit(Op::Pop, span);
+                self.depth -= 1;
+                self.close_upvalues(self.loops.len() - 1, span);
+                self.emit_loop(start, span);
+                // The loop is only exited by a `break`, leaving its value
+                self.depth += 1;
+                self.end_loop(span);
+            }
+            ExprKind::Match(scrutinee, arms) => self.match_expr(scrutinee, arms),
+            ExprKind::Range(start, end, limits) => {
+                let mut flags = 0;
+                for (bound, flag) in [(start, RANGE_START), (end, RANGE_END)] {
+                    if let Some(bound) = bound {
+                        self.expr(bound);
+                        flags |= flag;
+                    }
+                }
+                if *limits == RangeLimits::Closed {
+                    flags |= RANGE_INCLUSIVE;
+                }
+                self.emit(Op::Range, span);
+                self.chunk.emit_u8(flags);
+                let bounds = usize::from(start.is_some()) + usize::from(end.is_some());
+                self.depth = self.depth + 1 - bounds;
+            }
+            ExprKind::Return(value) => {
+                match value {
+                    Some(value) => self.expr(value),
+                    None => {
+                        self.emit(Op::Unit, span);
+                        self.depth += 1;
+                    }
+                }
+                self.emit(Op::Return, span);
+            }
+            ExprKind::Break(_, value) => {
+                let target = self.loop_target(expr);
+                let depth = self.depth;
+                // The temporaries are popped first, leaving the value of the loop on the stack
+                self.pop_to(self.loops[target].depth, span);
+                self.depth = self.loops[target].depth;
+                match value {
+                    Some(value) => self.expr(value),
+                    None => {
+                        self.emit(Op::Unit, span);
+                        self.depth += 1;
+                    }
+                }
+                self.end_iterations(target, span);
+                let jump = self.emit_jump(Op::Jump, span);
+                self.loops[target].breaks.push(jump);
+                // The value of the expression is never pushed as it diverges
+                self.depth = depth + 1;
+            }
+            ExprKind::Continue(_) => {
+                let target = self.loop_target(expr);
+                let (start, depth) = (self.loops[target].start, self.loops[target].depth);
+                self.pop_to(depth, span);
+                self.end_iterations(target, span);
+                self.close_upvalues(target, span);
+                self.emit_loop(start, span);
+                self.depth += 1;
+            }
+            ExprKind::Closure(_) => {
+                self.emit(Op::Closure, span);
+                self.chunk.emit_u32(expr.id);
+                self.depth += 1;
+            }
+            ExprKind::Err => self.bug(span, "invalid expression"),
+        }
+    }
+
+    fn lit(&mut self, lit: &Lit, negative: bool, span: Span) {
+        match lit_value(lit, negative) {
+            Ok(value) => self.constant(value, span),
+            Err(message) => self.fail(message, span),
+        }
+        self.depth += 1;
+    }
+
+    fn path(&mut self, id: NodeId, path: &Path, span: Span) {
+        let res = self.res(id, span);
+        let rest = &path.segments[1..];
+        self.depth += 1;
+        let value = match res {
+            Res::Local(slot) => return self.emit_with_u16(Op::GetLocal, slot, span),
+            Res::Upvalue(index) => return self.emit_with_u16(Op::GetUpvalue, index, span),
+            Res::Global(slot) => return self.emit_with_u16(Op::GetGlobal, slot, span),
+            Res::Item(id) => self.decls.item_value(id, rest, span),
+            Res::SelfTy(id) => match self.decls.self_ty(id) {
+                Some(key) => self.decls.assoc_value(key, rest, span),
+                None => {
+                    // In the default methods of a trait, `Self` is the type implementing it
+                    self.chunk.paths.push(rest.to_vec());
+                    return self.emit_with_u16(Op::SelfPath, self.chunk.paths.len() - 1, span);
+                }
+            },
+            Res::Builtin(name) => self.decls.builtin_value(name, rest, span),
+        };
+        match value {
+            Ok(value) => self.constant(value, span),
+            Err(error) => self.fail(error.message, error.span),
+        }
+    }
+
+    fn args(&mut self, exprs: &[Expr]) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    /// Compiles the expression, pushing a reference to the place it designates if any, and its
+    /// value otherwise.
+    fn operand(&mut self, expr: &Expr) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Path(_) => match self.res(expr.id, span) {
+                Res::Local(slot) => self.emit_with_u16(Op::RefLocal, slot, span),
+                Res::Upvalue(index) => self.emit_with_u16(Op::RefUpvalue, index, span),
+                Res::Global(slot) => self.emit_with_u16(Op::RefGlobal, slot, span),
+                _ => return self.expr(expr),
+            },
+            ExprKind::Paren(operand) => return self.operand(operand),
+            // The dereferenced value designates the referenced place
+            ExprKind::Unary(UnOp::Deref, operand) => return self.expr(operand),
+            ExprKind::Field(base, name) => {
+                self.operand(base);
+                let name = self.name(name);
+                self.emit_with_u16(Op::RefField, name, span);
+                return;
+            }
+            ExprKind::Index(base, index) => {
+                self.operand(base);
+                self.expr(index);
+                self.emit(Op::RefIndex, span);
+                self.depth -= 1;
+                return;
+            }
+            _ => return self.expr(expr),
+        }
+        self.depth += 1;
+    }
+
+    fn lazy(&mut self, op: BinOp, lhs: &Expr, rhs: &Expr) {
+        self.expr(lhs);
+        let short_circuit = self.emit_jump(Op::JumpIfFalse, lhs.span);
+        self.depth -= 1;
+        let end_jump = if op == BinOp::And {
+            self.expr(rhs);
+            self.emit(Op::CheckBool, rhs.span);
+            let end_jump = self.emit_jump(Op::Jump, rhs.span);
+            self.patch_jump(short_circuit, lhs.span);
+            self.constant(Value::Bool(false), lhs.span);
+            end_jump
+        } else {
+            self.constant(Value::Bool(true), lhs.span);
+            let end_jump = self.emit_jump(Op::Jump, lhs.span);
+            self.patch_jump(short_circuit, lhs.span);
+            self.expr(rhs);
+            self.emit(Op::CheckBool, rhs.span);
+            end_jump
+        };
+        // Both branches push one value, only counted for `rhs`
+        self.patch_jump(end_jump, rhs.span);
+    }
+
+    fn assign(&mut self, place: &Expr, value: &Expr) {
+        self.expr(value);
+        match &place.kind {
+            ExprKind::Path(_)
+                if matches!(
+                    self.res(place.id, place.span),
+                    Res::Local(_) | Res::Upvalue(_) | Res::Global(_)
+                ) =>
+            {
+                self.set_variable(self.res(place.id, place.span), place.span);
+            }
+            // Assigning to a missing key of a map inserts it
+            ExprKind::Index(base, index) => {
+                self.operand(base);
+                self.expr(index);
+                self.emit(Op::StoreIndex, place.span);
+                self.depth -= 3;
+            }
+            _ => {
+                self.operand(place);
+                self.emit(Op::Store, place.span);
+                self.depth -= 2;
+            }
+        }
+        self.emit(Op::Unit, place.span);
+        self.depth += 1;
+    }
+
+    fn assign_op(&mut self, expr: &Expr, op: BinOp, place: &Expr, value: &Expr) {
+        let local = match &place.kind {
+            ExprKind::Path(_) => match self.res(place.id, place.span) {
+                Res::Local(slot) => Some(slot),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(slot) = local {
+            self.expr(value);
+            self.emit_with_u16(Op::UpdateLocal, slot, expr.span);
+            self.chunk.emit_u8(bin_op(op) as u8);
+            self.depth -= 1;
+        } else {
+            self.operand(place);
+            self.emit(Op::CheckPlace, place.span);
+            self.expr(value);
+            self.emit_with_u8(Op::Update, bin_op(op), expr.span);
+            self.depth -= 2;
+        }
+        self.emit(Op::Unit, expr.span);
+        self.depth += 1;
+    }
+
+    fn struct_expr(&mut self, expr: &Expr, struct_expr: &StructExpr) {
+        for field in &struct_expr.fields {
+            self.expr(&field.expr);
+        }
+        if let Some(rest) = &struct_expr.rest {
+            self.expr(rest);
+        }
+        let layout = StructLayout {
+            target: self.target(expr.id, &struct_expr.path),
+            path: struct_expr.path.to_string(),
+            path_span: struct_expr.path.span,
+            fields: struct_expr
+                .fields
+                .iter()
+                .map(|field| field.name.clone())
+                .collect(),
+            rest: struct_expr.rest.as_ref().map(|rest| rest.span),
+        };
+        let values = layout.fields.len() + usize::from(layout.rest.is_some());
+        self.chunk.layouts.push(layout);
+        self.emit_with_u16(Op::Struct, self.chunk.layouts.len() - 1, expr.span);
+        self.depth = self.depth + 1 - values;
+    }
+
+    fn match_expr(&mut self, scrutinee: &Expr, arms: &[Arm]) {
+        self.expr(scrutinee);
+        let mut end_jumps = vec![];
+        for arm in arms {
+            let pat = self.pattern(&arm.pat);
+            self.emit_with_u16(Op::TestPattern, pat, arm.pat.span);
+            let mut next_jumps = vec![self.emit_jump(Op::JumpIfFalse, arm.pat.span)];
+            if let Some(guard) = &arm.guard {
+                self.expr(guard);
+                next_jumps.push(self.emit_jump(Op::JumpIfFalse, guard.span));
+                self.depth -= 1;
+            }
+            // The scrutinee is replaced by the value of the arm
+            self.emit(Op::Pop, arm.span);
+            self.depth -= 1;
+            self.expr(&arm.body);
+            end_jumps.push(self.emit_jump(Op::Jump, arm.span));
+            for jump in next_jumps {
+                self.patch_jump(jump, arm.span);
+            }
+        }
+        self.emit(Op::NoMatch, scrutinee.span);
+        for jump in end_jumps {
+            self.patch_jump(jump, scrutinee.span);
+        }
+    }
+
+    fn start_loop(&mut self, id: NodeId, iterates: bool, start: usize) {
+        self.loops.push(Loop {
+            id,
+            iterates,
+            start,
+            depth: self.depth,
+            first_slot: self.resolutions.loop_slots.get(&id).copied(),
+            breaks: vec![],
+        });
+    }
+
+    /// Closes the variables declared in the loop captured by closures, before its next iteration.
+    fn close_upvalues(&mut self, target: usize, span: Span) {
+        if let Some(slot) = self.loops[target].first_slot {
+            self.emit_with_u16(Op::CloseUpvalues, slot, span);
+        }
+    }
+
+    /// Ends the innermost loop, its value being on the stack.
+    fn end_loop(&mut self, span: Span) {
+        let target = self.loops.pop().expect("a loop is being compiled");
+        for jump in target.breaks {
+            self.patch_jump(jump, span);
+        }
+    }
+
+    /// Returns the index of the loop exited or continued by the `break` or `continue` expression.
+    fn loop_target(&self, expr: &Expr) -> usize {
+        let id = self.resolutions.loop_targets.get(&expr.id);
+        match self.loops.iter().rposition(|target| Some(&target.id) == id) {
+            Some(target) => target,
+            None => self.bug(expr.span, "`break` or `continue` outside of a loop"),
+        }
+    }
+
+    /// Ends the iterations of the `for` loops nested in the given loop, exited by a `break` or
+    /// `continue` of a labelled loop.
+    fn end_iterations(&mut self, target: usize, span: Span) {
+        let count = self.loops[target + 1..]
+            .iter()
+            .filter(|inner| inner.iterates)
+            .count();
+        for _ in 0..count {
+            self.emit(Op::IterEnd, span);
+        }
+    }
+
+    // Patterns
+
+    /// Compiles the pattern, returning its index in the chunk.
+    fn pattern(&mut self, pat: &Pattern) -> usize {
+        let pat = self.pat(pat);
+        self.chunk.patterns.push(pat);
+        self.chunk.patterns.len() - 1
+    }
+
+    fn pat(&mut self, pat: &Pattern) -> Pat {
+        match &pat.kind {
+            PatKind::Wild => Pat::Wild,
+            PatKind::Rest => Pat::Rest(None),
+            PatKind::Ident(_, _, Some(sub)) if matches!(sub.kind, PatKind::Rest) => {
+                Pat::Rest(Some(self.slot(pat)))
+            }
+            PatKind::Ident(_, _, sub) => {
+                let sub = sub.as_ref().map(|sub| Box::new(self.pat(sub)));
+                Pat::Bind(self.slot(pat), sub)
+            }
+            PatKind::Lit(expr) => match self.pat_value(expr) {
+                Ok(value) => Pat::Value(value),
+                Err(message) => Pat::Fail(message, expr.span),
+            },
+            PatKind::Range(start, end, limits) => {
+                let bound = |bound: &Option<Box<Expr>>| {
+                    bound
+                        .as_ref()
+                        .map(|bound| self.pat_value(bound).map_err(|err| (err, bound.span)))
+                        .transpose()
+                };
+                match (bound(start), bound(end)) {
+                    (Ok(start), Ok(end)) => Pat::Range(start, end, *limits == RangeLimits::Closed),
+                    (Err((message, span)), _) | (_, Err((message, span))) => {
+                        Pat::Fail(message, span)
+                    }
+                }
+            }
+            PatKind::Tuple(pats) => Pat::Tuple(self.pats(pats)),
+            PatKind::Slice(pats) => Pat::Slice(self.pats(pats)),
+            PatKind::Or(pats) => Pat::Or(self.pats(pats)),
+            PatKind::Path(path) => {
+                if let Some(Res::Item(id)) = self.resolutions.names.get(&pat.id) {
+                    if let Some(constant) = self.decls.consts.get(id) {
+                        return Pat::Value(constant.clone());
+                    }
+                }
+                Pat::Unit(self.target(pat.id, path))
+            }
+            PatKind::TupleStruct(path, pats) => {
+                Pat::TupleVariant(self.target(pat.id, path), self.pats(pats))
+            }
+            PatKind::Struct(path, fields, _) => {
+                let fields = fields
+                    .iter()
+                    .map(|field| (field.name.name.clone(), self.pat(&field.pat)))
+                    .collect();
+                Pat::Struct(self.target(pat.id, path), fields)
+            }
+            PatKind::Err => self.bug(pat.span, "invalid pattern"),
+        }
+    }
+
+    fn pats(&mut self, pats: &[Pattern]) -> Vec<Pat> {
+        pats.iter().map(|pat| self.pat(pat)).collect()
+    }
+
+    fn slot(&self, pat: &Pattern) -> Slot {
+        match self.res(pat.id, pat.span) {
+            Res::Local(slot) => Slot::Local(slot),
+            Res::Global(slot) => Slot::Global(slot),
+            _ => self.bug(pat.span, "expected a variable"),
+        }
+    }
+
+    /// Returns the value of a literal of a pattern.
+    fn pat_value(&self, expr: &Expr) -> Result<Value, String> {
+        match &expr.kind {
+            ExprKind::Lit(lit) => lit_value(lit, false),
+            ExprKind::Unary(UnOp::Neg, operand) => match &operand.kind {
+                ExprKind::Lit(lit) => lit_value(lit, true),
+                _ => self.bug(expr.span, "expected a literal"),
+            },
+            _ => self.bug(expr.span, "expected a literal"),
+        }
+    }
+
+    /// Returns the struct or enum variant named by the path of a pattern or struct expression.
+    fn target(&self, id: NodeId, path: &Path) -> Target {
+        let rest = &path.segments[1..];
+        let key = match self.resolutions.names.get(&id) {
+            Some(Res::Item(id)) => TypeKey::Adt(*id),
+            Some(Res::SelfTy(id)) => match self.decls.self_ty(*id) {
+                Some(key) => key,
+                None => return Target::SelfTy(rest.to_vec()),
+            },
+            Some(Res::Builtin(name)) if rest.is_empty() => match builtin_variant(name) {
+                Some((id, variant)) => return Target::Variant(id, variant),
+                None => return Target::None,
+            },
+            Some(Res::Builtin(name)) => builtin_type_key(name),
+            _ => return Target::None,
+        };
+        match self.decls.resolve_variant(key, rest) {
+            Some((id, variant)) => Target::Variant(id, variant),
+            None => Target::None,
+        }
+    }
+}
+
+fn bin_op(op: BinOp) -> usize {
+    BIN_OPS.iter().position(|bin_op| *bin_op == op).unwrap()
+}
@@ -0,0 +1,337 @@
+//! The disassembler, printing the bytecode of compiled programs in a readable form.
+//!
+//! Each instruction is printed on its own line with its offset in the chunk, its name and its
+//! decoded operands: the values of the constants, the names of the fields and methods, the
+//! targets of the jumps and the compiled patterns. Listings of whole programs are annotated with
+//! the source lines the instructions come from.
+
+use std::fmt::Write;
+
+use super::chunk::*;
+use super::Module;
+use crate::int;
+use crate::parser::lexer::Span;
+use crate::parser::source_map::SourceMap;
+use crate::runtime::{Bounds, Declarations, VariantKind};
+use crate::value::{Adt, Value};
+
+/// Returns the listing of the bytecode of the top-level code, followed by the functions and the
+/// closures in source order.
+pub fn disassemble(module: &Module, source: &str) -> String {
+    let lines = SourceMap::new("", source);
+    let mut protos: Vec<_> = module.protos.iter().collect();
+    protos.sort_by_key(|(id, proto)| (first_span(proto).map(|span| span.start), **id));
+    let mut out = String::new();
+    let protos = std::iter::once(&module.main).chain(protos.into_iter().map(|(_, proto)| proto));
+    for (index, proto) in protos.enumerate() {
+        if index > 0 {
+            out.push('\n');
+        }
+        proto_listing(&mut out, &module.decls, proto, &lines);
+    }
+    out
+}
+
+fn first_span(proto: &Proto) -> Option<Span> {
+    proto.chunk.spans.first().map(|(_, span)| *span)
+}
+
+fn proto_listing(out: &mut String, decls: &Declarations, proto: &Proto, lines: &SourceMap) {
+    let _ = write!(out, "== {} ==", proto.name);
+    let _ = write!(out, " ({} slots", proto.frame_size);
+    if !proto.captures.is_empty() {
+        let captures: Vec<_> = proto.captures.iter().map(capture).collect();
+        let by = if proto.by_value {
+            "by value"
+        } else {
+            "by reference"
+        };
+        let _ = write!(out, ", captures {} {by}", captures.join(", "));
+    }
+    out.push_str(")\n");
+    let chunk = &proto.chunk;
+    let mut line = None;
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let current = lines.location(chunk.span(offset).start).line;
+        if line != Some(current) {
+            line = Some(current);
+            let _ = writeln!(out, "      ; {current:>4} | {}", lines.line(current));
+        }
+        offset = instruction(out, decls, chunk, offset);
+        out.push('\n');
+    }
+}
+
+/// Writes the instruction at the given offset, without a trailing newline, returning the offset
+/// of the next one.
+pub(crate) fn instruction(
+    out: &mut String,
+    decls: &Declarations,
+    chunk: &Chunk,
+    offset: usize,
+) -> usize {
+    let Some(op) = Op::from_byte(chunk.code[offset]) else {
+        let _ = write!(out, "{offset:04}  <invalid {}>", chunk.code[offset]);
+        return offset + 1;
+    };
+    let _ = write!(out, "{offset:04}  {:<13}", format!("{op:?}"));
+    let next = offset + 1 + op.operands_size();
+    let u8_at = |at: usize| usize::from(chunk.read_u8(offset + 1 + at));
+    let u16_at = |at: usize| usize::from(chunk.read_u16(offset + 1 + at));
+    let operands = match op {
+        Op::Constant | Op::Cast | Op::Fail => {
+            let index = u16_at(0);
+            format!("{index} ({})", value(decls, &chunk.constants[index]))
+        }
+        Op::PopN | Op::Tuple | Op::Array => u16_at(0).to_string(),
+        Op::GetLocal | Op::SetLocal | Op::RefLocal | Op::CloseUpvalues => {
+            format!("local {}", u16_at(0))
+        }
+        Op::GetUpvalue | Op::SetUpvalue | Op::RefUpvalue => format!("upvalue {}", u16_at(0)),
+        Op::GetGlobal | Op::SetGlobal | Op::RefGlobal => format!("global {}", u16_at(0)),
+        Op::RefField | Op::Field => {
+            let index = u16_at(0);
+            format!("{index} (.{})", chunk.names[index].name)
+        }
+        Op::Invoke => {
+            let index = u16_at(0);
+            format!("{index} (.{}) {} args", chunk.names[index].name, u8_at(2))
+        }
+        Op::Update => format!("{}=", BIN_OPS[u8_at(0)].as_str()),
+        Op::UpdateLocal => format!("local {} {}=", u16_at(0), BIN_OPS[u8_at(2)].as_str()),
+        Op::Binary => BIN_OPS[u8_at(0)].as_str().to_string(),
+        Op::Unary => UN_OPS[u8_at(0)].as_str().to_string(),
+        Op::Jump | Op::JumpIfFalse | Op::IterNext => format!("-> {:04}", next + u16_at(0)),
+        Op::Try => {
+            let returns = match chunk.read_u8(offset + 1) {
+                TRY_OPTION => " Option",
+                TRY_RESULT => " Result",
+                _ => "",
+            };
+            format!("-> {:04}{returns}", next + u16_at(1))
+        }
+        Op::Loop => format!("-> {:04}", next - u16_at(0)),
+        Op::Call => format!("{} args", u8_at(0)),
+        Op::Closure => format!("{}", chunk.read_u32(offset + 1)),
+        Op::Struct => {
+            let index = u16_at(0);
+            let layout = &chunk.layouts[index];
+            let mut fields: Vec<_> = layout.fields.iter().map(|f| f.name.clone()).collect();
+            if layout.rest.is_some() {
+                fields.push(String::from(".."));
+            }
+            format!("{index} ({} {{ {} }})", layout.path, fields.join(", "))
+        }
+        Op::Range => {
+            let flags = chunk.read_u8(offset + 1);
+            let start = if flags & RANGE_START != 0 {
+                "start"
+            } else {
+                ""
+            };
+            let end = if flags & RANGE_END != 0 { "end" } else { "" };
+            let limits = if flags & RANGE_INCLUSIVE != 0 {
+                "..="
+            } else {
+                ".."
+            };
+            format!("{start}{limits}{end}")
+        }
+        Op::TestPattern => {
+            let index = u16_at(0);
+            format!("{index} ({})", pat(decls, &chunk.patterns[index]))
+        }
+        Op::Bind => {
+            let index = u16_at(0);
+            let context = match chunk.read_u8(offset + 3) {
+                BIND_FOR => "for",
+                BIND_PARAM => "param",
+                _ => "let",
+            };
+            format!("{index} ({}) {context}", pat(decls, &chunk.patterns[index]))
+        }
+        Op::CheckBounds => {
+            let index = u16_at(0);
+            format!("{index} ({})", bounds(&chunk.bounds[index]))
+        }
+        Op::SelfPath => {
+            let index = u16_at(0);
+            let segments: Vec<_> = chunk.paths[index].iter().map(|s| s.name.as_str()).collect();
+            format!("{index} (Self::{})", segments.join("::"))
+        }
+        Op::Unit
+        | Op::Pop
+        | Op::RefIndex
+        | Op::BorrowMut
+        | Op::CheckPlace
+        | Op::Store
+        | Op::StoreIndex
+        | Op::CheckBool
+        | Op::Return
+        | Op::Index
+        | Op::NoMatch
+        | Op::IterStart
+        | Op::IterEnd => String::new(),
+    };
+    out.push_str(operands.trim_end());
+    let len = out.trim_end_matches(' ').len();
+    out.truncate(len);
+    next
+}
+
+fn capture(capture: &Capture) -> String {
+    match capture {
+        Capture::Local(slot) => format!("local {slot}"),
+        Capture::Upvalue(index) => format!("upvalue {index}"),
+        Capture::Global(slot) => format!("global {slot}"),
+    }
+}
+
+/// Formats a value without calling the program, unlike printing it.
+pub(crate) fn value(decls: &Declarations, value: &Value) -> String {
+    let list = |values: &[Value]| {
+        let values: Vec<_> = values.iter().map(|v| self::value(decls, v)).collect();
+        values.join(", ")
+    };
+    match value {
+        Value::Nil => String::from("nil"),
+        Value::Bool(value) => value.to_string(),
+        Value::Int(value) => value.to_string(),
+        Value::Fixed(value, ty) => format!("{}{ty}", int::to_string(*value, *ty)),
+        Value::Float(value) => format!("{value:?}"),
+        Value::Str(value) => format!("{value:?}"),
+        Value::Tuple(values) if values.len() == 1 => format!("({},)", list(values)),
+        Value::Tuple(values) => format!("({})", list(values)),
+        Value::Array(values) => format!("[{}]", list(values)),
+        Value::Map(map) => {
+            let entries: Vec<_> = map
+                .entries()
+                .iter()
+                .map(|(key, v)| format!("{}: {}", self::value(decls, key), self::value(decls, v)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Value::Range(range) => {
+            let bound = |bound: Option<i64>| bound.map(|b| b.to_string()).unwrap_or_default();
+            let limits = if range.inclusive { "..=" } else { ".." };
+            format!("{}{limits}{}", bound(range.start), bound(range.end))
+        }
+        Value::Iter(_) => String::from("{iterator}"),
+        Value::Adt(adt) => self::adt(decls, adt),
+        Value::Fn(function) => format!("fn {}", decls.fn_name(*function)),
+        Value::Closure(_) => String::from("{closure}"),
+        Value::Uninit => String::from("{uninit}"),
+        Value::Builtin(name) => format!("fn {name}"),
+        Value::Ctor(id, variant) => format!("fn {}", variant_path(decls, *id, *variant)),
+        Value::Ref(place) => match place.read() {
+            Some(referenced) => format!("&mut {}", self::value(decls, &referenced)),
+            None => String::from("&mut <dropped>"),
+        },
+    }
+}
+
+fn adt(decls: &Declarations, adt: &Adt) -> String {
+    let decl = decls.variant(adt.id, adt.variant);
+    let path = variant_path(decls, adt.id, adt.variant);
+    let fields = adt.fields.iter().map(|field| value(decls, field));
+    match decl.kind {
+        VariantKind::Unit => path,
+        VariantKind::Tuple => format!("{path}({})", fields.collect::<Vec<_>>().join(", ")),
+        VariantKind::Named => {
+            let fields: Vec<_> = decl
+                .fields
+                .iter()
+                .zip(fields)
+                .map(|(name, value)| format!("{name}: {value}"))
+                .collect();
+            format!("{path} {{ {} }}", fields.join(", "))
+        }
+    }
+}
+
+fn variant_path(decls: &Declarations, id: u32, variant: usize) -> String {
+    let decl = &decls.types[&id];
+    match decl.is_enum {
+        true => format!("{}::{}", decl.name, decl.variants[variant].name),
+        false => decl.name.clone(),
+    }
+}
+
+fn target(decls: &Declarations, target: &Target) -> String {
+    match target {
+        Target::Variant(id, variant) => variant_path(decls, *id, *variant),
+        Target::SelfTy(rest) => {
+            let segments = rest.iter().map(|segment| format!("::{}", segment.name));
+            format!("Self{}", segments.collect::<String>())
+        }
+        Target::None => String::from("<none>"),
+    }
+}
+
+/// Formats a compiled pattern, with the slots of the variables it binds.
+fn pat(decls: &Declarations, pat: &Pat) -> String {
+    let list = |pats: &[Pat]| {
+        let pats: Vec<_> = pats.iter().map(|p| self::pat(decls, p)).collect();
+        pats.join(", ")
+    };
+    match pat {
+        Pat::Wild => String::from("_"),
+        Pat::Bind(slot, None) => self::slot(slot),
+        Pat::Bind(slot, Some(sub)) => format!("{} @ {}", self::slot(slot), self::pat(decls, sub)),
+        Pat::Value(value) => self::value(decls, value),
+        Pat::Range(start, end, inclusive) => {
+            let bound =
+                |bound: &Option<Value>| bound.as_ref().map(|b| value(decls, b)).unwrap_or_default();
+            let limits = if *inclusive { "..=" } else { ".." };
+            format!("{}{limits}{}", bound(start), bound(end))
+        }
+        Pat::Tuple(pats) => format!("({})", list(pats)),
+        Pat::Slice(pats) => format!("[{}]", list(pats)),
+        Pat::Rest(None) => String::from(".."),
+        Pat::Rest(Some(slot)) => format!("{} @ ..", self::slot(slot)),
+        Pat::Or(pats) => {
+            let pats: Vec<_> = pats.iter().map(|p| self::pat(decls, p)).collect();
+            pats.join(" | ")
+        }
+        Pat::Unit(target) => self::target(decls, target),
+        Pat::TupleVariant(target, pats) => {
+            format!("{}({})", self::target(decls, target), list(pats))
+        }
+        Pat::Struct(target, fields) => {
+            let fields: Vec<_> = fields
+                .iter()
+                .map(|(name, p)| format!("{name}: {}", self::pat(decls, p)))
+                .collect();
+            format!(
+                "{} {{ {} }}",
+                self::target(decls, target),
+                fields.join(", ")
+            )
+        }
+        Pat::Fail(message, _) => format!("<error: {message}>"),
+    }
+}
+
+fn slot(slot: &Slot) -> String {
+    match slot {
+        Slot::Local(slot) => format!("local {slot}"),
+        Slot::Global(slot) => format!("global {slot}"),
+    }
+}
+
+fn bounds(bounds: &Bounds) -> String {
+    match bounds {
+        Bounds::Traits(traits) => format!("impl {}", traits.join(" + ")),
+        Bounds::Int(ty) => ty.to_string(),
+        Bounds::Ref(bounds) => format!("&{}", self::bounds(bounds)),
+        Bounds::Array(bounds) => format!("[{}]", self::bounds(bounds)),
+        Bounds::Tuple(elements) => {
+            let elements: Vec<_> = elements
+                .iter()
+                .map(|bounds| bounds.as_ref().map_or(String::from("_"), self::bounds))
+                .collect();
+            format!("({})", elements.join(", "))
+        }
+    }
+}
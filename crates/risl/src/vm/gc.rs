@@ -0,0 +1,247 @@
+//! The cycle collector of the virtual machine.
+//!
+//! Values are reference counted, which frees them as soon as they are no longer used, except for
+//! the values forming a cycle. Since the aggregates are copied on assignment, a cycle always goes
+//! through an environment: a closure stored in a variable it captures, or a reference stored in
+//! the variable it borrows, possibly inside a struct or an array.
+//!
+//! The heap tracks the environments allocated by the machine once closures or references can share
+//! them, which the variables of most calls never are. A collection marks the values
+//! reachable from the roots: the stack, the global variables and the running frames, with their
+//! closures, whose upvalues are the open ones, and their iterations. The tracked environments left
+//! unmarked but still alive are only kept alive by cycles: the sweep clears their variables, which
+//! breaks the cycles and lets the reference counts free them.
+//!
+//! The machine collects between two instructions, at the first opportunity after the allocation
+//! triggering the collection. The built-ins calling back into the program, like the iterator
+//! adapters or `catch_unwind`, root the values they hold meanwhile, as do their receivers and
+//! arguments. The few operations calling the program without rooting them, like the comparisons
+//! of values implementing `PartialEq` or `Ord`, postpone the collection until they return.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+use super::Frame;
+use crate::value::{Env, Iter, Value};
+
+/// The number of tracked environments triggering the first collection.
+const INITIAL_THRESHOLD: usize = 1024;
+
+/// Statistics about the allocations and the collections of a run.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct GcStats {
+    /// The number of environments allocated by the machine.
+    pub allocations: usize,
+    pub collections: usize,
+    /// The number of environments freed by breaking cycles.
+    pub freed: usize,
+    pub total_pause: Duration,
+    pub max_pause: Duration,
+}
+
+impl fmt::Display for GcStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "gc: {} allocations, {} collections, {} freed, pauses of {:?} in total and {:?} at most",
+            self.allocations, self.collections, self.freed, self.total_pause, self.max_pause
+        )
+    }
+}
+
+/// The environments allocated by the machine, with the state of the collector.
+pub(super) struct Heap {
+    tracked: Vec<Weak<RefCell<Vec<Value>>>>,
+    /// The number of tracked environments triggering the next collection.
+    threshold: usize,
+    /// Collects after every allocation.
+    stress: bool,
+    /// Whether an allocation triggered a collection, run at the next safe point.
+    pub pending: bool,
+    /// The values held by the running built-ins while they call back into the program.
+    pub roots: Vec<Value>,
+    /// The number of frames below the innermost built-in rooting its values, if one is running.
+    pub rooted: Option<usize>,
+    /// The number of calls back into the program from built-ins which do not root their values,
+    /// during which the collector cannot run.
+    pub unrooted: usize,
+    pub stats: GcStats,
+}
+
+impl Heap {
+    pub fn new(stress: bool) -> Heap {
+        Heap {
+            tracked: vec![],
+            threshold: INITIAL_THRESHOLD,
+            stress,
+            pending: false,
+            roots: vec![],
+            rooted: None,
+            unrooted: 0,
+            stats: GcStats::default(),
+        }
+    }
+
+    /// Counts an environment allocated by the machine.
+    pub fn allocate(&mut self) {
+        self.stats.allocations += 1;
+        if self.stress {
+            self.pending = true;
+        }
+    }
+
+    /// Tracks an environment shared by closures or references, which can be part of a cycle.
+    pub fn track(&mut self, env: &Env) {
+        self.tracked.push(Rc::downgrade(env));
+        if self.tracked.len() >= self.threshold {
+            self.pending = true;
+        }
+    }
+
+    /// Whether a collection can run between two instructions, which requires the built-ins
+    /// calling back into the program to root the values they hold.
+    pub fn can_collect(&self) -> bool {
+        self.pending && self.unrooted == 0
+    }
+
+    /// Collects the tracked environments unreachable from the roots: the stack, the global
+    /// variables, the running frames and the values rooted by the built-ins.
+    pub fn collect(&mut self, stack: &[Value], globals: &Env, frames: &[Frame]) {
+        let start = Instant::now();
+        let mut marker = Marker::default();
+        marker.gray.extend(stack.iter().cloned());
+        marker.gray.extend(self.roots.iter().cloned());
+        marker.mark_env(globals);
+        for frame in frames {
+            marker.mark_env(&frame.env);
+            if let Some(closure) = &frame.closure {
+                marker.gray.push(Value::Closure(closure.clone()));
+            }
+            for iter in &frame.iters {
+                marker.mark_iter(iter);
+            }
+        }
+        marker.trace();
+
+        let mut garbage = vec![];
+        self.tracked.retain(|env| match env.upgrade() {
+            Some(env) if marker.envs.contains(&Rc::as_ptr(&env)) => true,
+            Some(env) => {
+                garbage.push(env);
+                false
+            }
+            None => false,
+        });
+        // Takes the variables out before dropping them, as dropping them can free other garbage
+        let variables: Vec<_> = garbage
+            .iter()
+            .map(|env| std::mem::take(&mut *env.borrow_mut()))
+            .collect();
+        drop(variables);
+
+        self.stats.collections += 1;
+        self.stats.freed += garbage.len();
+        self.threshold = INITIAL_THRESHOLD.max(self.tracked.len() * 2);
+        self.pending = false;
+        let pause = start.elapsed();
+        self.stats.total_pause += pause;
+        self.stats.max_pause = self.stats.max_pause.max(pause);
+    }
+}
+
+/// Marks the reachable values, keeping the values left to visit on a stack rather than recursing,
+/// as the values can be deeply nested.
+#[derive(Default)]
+struct Marker {
+    /// The marked environments, by address.
+    envs: HashSet<*const RefCell<Vec<Value>>>,
+    /// The visited aggregates, by address, to visit the shared ones once.
+    aggregates: HashSet<*const ()>,
+    gray: Vec<Value>,
+}
+
+impl Marker {
+    fn mark_env(&mut self, env: &Env) {
+        if self.envs.insert(Rc::as_ptr(env)) {
+            self.gray.extend(env.borrow().iter().cloned());
+        }
+    }
+
+    /// Marks the values held by the iteration, like the functions passed to its adapters.
+    fn mark_iter(&mut self, iter: &Iter) {
+        match iter {
+            Iter::Array(values, ..) => self.gray.push(Value::Array(values.clone())),
+            Iter::Entries(map, ..) => self.gray.push(Value::Map(map.clone())),
+            Iter::Iterator(_, place) | Iter::Place(place) => self.mark_env(&place.env),
+            Iter::Map(iter, f) | Iter::Filter(iter, f) => {
+                self.gray.push(f.clone());
+                self.mark_iter(iter);
+            }
+            Iter::Enumerate(iter, _) | Iter::Take(iter, _) | Iter::Skip(iter, _) => {
+                self.mark_iter(iter)
+            }
+            Iter::Rev(iter) => self.mark_iter(iter),
+            Iter::Zip(first, second) | Iter::Chain(first, second) => {
+                self.mark_iter(first);
+                self.mark_iter(second);
+            }
+            Iter::Range(..) | Iter::Chars(..) => {}
+        }
+    }
+
+    fn visit<T>(&mut self, aggregate: &Rc<T>) -> bool {
+        self.aggregates.insert(Rc::as_ptr(aggregate).cast())
+    }
+
+    fn trace(&mut self) {
+        while let Some(value) = self.gray.pop() {
+            match &value {
+                Value::Tuple(values) | Value::Array(values) => {
+                    if self.visit(values) {
+                        self.gray.extend(values.iter().cloned());
+                    }
+                }
+                Value::Map(map) => {
+                    if self.visit(map) {
+                        for (key, value) in map.entries() {
+                            self.gray.push(key.clone());
+                            self.gray.push(value.clone());
+                        }
+                    }
+                }
+                Value::Adt(adt) => {
+                    if self.visit(adt) {
+                        self.gray.extend(adt.fields.iter().cloned());
+                    }
+                }
+                Value::Closure(closure) => {
+                    if self.visit(closure) {
+                        for upvalue in &closure.upvalues {
+                            self.mark_env(&upvalue.env());
+                        }
+                    }
+                }
+                Value::Iter(iter) => {
+                    if self.visit(iter) {
+                        self.mark_iter(iter);
+                    }
+                }
+                Value::Ref(place) => self.mark_env(&place.env),
+                Value::Nil
+                | Value::Bool(_)
+                | Value::Int(_)
+                | Value::Fixed(..)
+                | Value::Float(_)
+                | Value::Str(_)
+                | Value::Range(_)
+                | Value::Fn(_)
+                | Value::Builtin(_)
+                | Value::Ctor(..)
+                | Value::Uninit => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,916 @@
+//! The `.rislc` files, storing a compiled module to run it without parsing and checking its source
+//! again.
+//!
+//! A file starts with a header made of the [`MAGIC`] bytes, the [`VERSION`] of the format, the
+//! hash of the source the module was compiled from, the check options it was compiled with and the
+//! hash of the rest of the file. Then come the declarations of the program, the number of global
+//! variables, the functions with their bytecode, constant pool, side tables and span table, and the
+//! warnings of the static checks.
+//!
+//! Integers are stored in little endian, and lengths as 32-bit integers. The entries of the maps
+//! are sorted, so that compiling the same source always gives the same file.
+//!
+//! The bytecode is trusted once the header is checked: the hash of the content only detects
+//! truncated or damaged files.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use super::chunk::{Capture, Chunk, Pat, Proto, Slot, StructLayout, Target};
+use super::Module;
+use crate::int::{in_range, IntTy};
+use crate::parser::ast::{Ident, Mutability, SelfKind};
+use crate::parser::diagnostic::{Diagnostic, Level};
+use crate::parser::lexer::Span;
+use crate::resolve::{BUILTIN_FUNCTIONS, BUILTIN_TYPES};
+use crate::runtime::{Bounds, Declarations, FnSig, TypeDecl, VariantDecl, VariantKind};
+use crate::value::{Adt, Function, Range, TypeKey, Value};
+use crate::CheckOptions;
+
+/// The bytes starting every compiled module.
+pub const MAGIC: &[u8; 6] = b"\0rislc";
+
+/// The version of the format, to increment whenever the encoding or the instructions change.
+pub const VERSION: u32 = 9;
+
+/// The maximum nesting of the values, patterns and bounds of a file.
+const MAX_DEPTH: usize = 512;
+
+/// The names of the built-in types and functions that are not in the lists of the resolver.
+const RUNTIME_NAMES: &[&str] = &["tuple", "array", "Map::new"];
+
+/// Why a module could not be stored.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum EncodeError {
+    /// A constant of the program holds a value of the given kind, which only exists at run time.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::Unsupported(kind) => {
+                write!(f, "a constant holding {kind} cannot be stored")
+            }
+        }
+    }
+}
+
+/// Why a compiled module could not be loaded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LoadError {
+    /// The file is not a compiled module, or it is damaged.
+    Malformed,
+    /// The file was written with another version of the format.
+    Version(u32),
+    /// The source changed since the module was compiled.
+    SourceChanged,
+    /// The module was compiled with other check options.
+    OptionsChanged,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Malformed => write!(f, "not a valid compiled module"),
+            LoadError::Version(version) => write!(
+                f,
+                "compiled with version {version} of the format, expected version {VERSION}"
+            ),
+            LoadError::SourceChanged => write!(f, "the source changed since it was compiled"),
+            LoadError::OptionsChanged => write!(f, "compiled with other check options"),
+        }
+    }
+}
+
+/// Stores the module compiled from the source with the given options.
+pub fn encode(
+    module: &Module,
+    source: &str,
+    options: CheckOptions,
+) -> Result<Vec<u8>, EncodeError> {
+    let mut content = Writer::default();
+    content.module(module);
+    if let Some(kind) = content.unsupported {
+        return Err(EncodeError::Unsupported(kind));
+    }
+    let mut file = Writer::default();
+    file.bytes.extend_from_slice(MAGIC);
+    file.u32(VERSION);
+    file.u64(hash(source.as_bytes()));
+    file.u8(options_flags(options));
+    file.u64(hash(&content.bytes));
+    file.bytes.extend(content.bytes);
+    Ok(file.bytes)
+}
+
+/// Loads a compiled module, checking that it was compiled from the given source, if any, and with
+/// the given options.
+pub fn decode(
+    bytes: &[u8],
+    source: Option<&str>,
+    options: CheckOptions,
+) -> Result<Module, LoadError> {
+    let mut reader = Reader { bytes, depth: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(LoadError::Malformed);
+    }
+    let version = reader.u32()?;
+    if version != VERSION {
+        return Err(LoadError::Version(version));
+    }
+    let source_hash = reader.u64()?;
+    let flags = reader.u8()?;
+    if reader.u64()? != hash(reader.bytes) {
+        return Err(LoadError::Malformed);
+    }
+    if source.is_some_and(|source| hash(source.as_bytes()) != source_hash) {
+        return Err(LoadError::SourceChanged);
+    }
+    if flags != options_flags(options) {
+        return Err(LoadError::OptionsChanged);
+    }
+    let module = reader.module()?;
+    if !reader.bytes.is_empty() {
+        return Err(LoadError::Malformed);
+    }
+    Ok(module)
+}
+
+/// Hashes bytes with the 64-bit FNV-1a function, which unlike the hasher of the standard library
+/// gives the same hashes in every build.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn options_flags(options: CheckOptions) -> u8 {
+    u8::from(options.type_check)
+}
+
+// Encoding
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+    /// The kind of the first value that could not be stored, if any.
+    unsupported: Option<&'static str>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u32(u32::try_from(len).expect("the module is too large to be stored"));
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value.into());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn option<T>(&mut self, value: &Option<T>, f: impl FnOnce(&mut Self, &T)) {
+        match value {
+            None => self.u8(0),
+            Some(value) => {
+                self.u8(1);
+                f(self, value);
+            }
+        }
+    }
+
+    fn seq<T>(&mut self, values: &[T], mut f: impl FnMut(&mut Self, &T)) {
+        self.len(values.len());
+        for value in values {
+            f(self, value);
+        }
+    }
+
+    /// Writes the entries of a map, sorted by their encoding.
+    fn map<K, V>(&mut self, map: &HashMap<K, V>, mut f: impl FnMut(&mut Self, &K, &V)) {
+        let mut entries = vec![];
+        for (key, value) in map {
+            let mut entry = Writer::default();
+            f(&mut entry, key, value);
+            self.unsupported = self.unsupported.or(entry.unsupported);
+            entries.push(entry.bytes);
+        }
+        entries.sort_unstable();
+        self.len(entries.len());
+        for entry in entries {
+            self.bytes.extend(entry);
+        }
+    }
+
+    fn span(&mut self, span: &Span) {
+        self.u32(span.start);
+        self.u32(span.end);
+    }
+
+    fn ident(&mut self, ident: &Ident) {
+        self.str(&ident.name);
+        self.span(&ident.span);
+    }
+
+    fn type_key(&mut self, key: &TypeKey) {
+        match key {
+            TypeKey::Adt(id) => {
+                self.u8(0);
+                self.u32(*id);
+            }
+            TypeKey::Builtin(name) => {
+                self.u8(1);
+                self.str(name);
+            }
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.u32(function.id);
+        self.option(&function.self_ty, Self::type_key);
+    }
+
+    fn bounds(&mut self, bounds: &Bounds) {
+        match bounds {
+            Bounds::Traits(traits) => {
+                self.u8(0);
+                self.seq(traits, |w, name| w.str(name));
+            }
+            Bounds::Ref(bounds) => {
+                self.u8(1);
+                self.bounds(bounds);
+            }
+            Bounds::Array(bounds) => {
+                self.u8(2);
+                self.bounds(bounds);
+            }
+            Bounds::Tuple(bounds) => {
+                self.u8(3);
+                self.seq(bounds, |w, bounds| w.option(bounds, Self::bounds));
+            }
+            Bounds::Int(ty) => {
+                self.u8(4);
+                self.str(ty.name());
+            }
+        }
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Nil => self.u8(0),
+            Value::Bool(value) => {
+                self.u8(1);
+                self.bool(*value);
+            }
+            Value::Int(value) => {
+                self.u8(2);
+                self.u64(*value as u64);
+            }
+            Value::Float(value) => {
+                self.u8(3);
+                self.u64(value.to_bits());
+            }
+            Value::Str(value) => {
+                self.u8(4);
+                self.str(value);
+            }
+            Value::Tuple(values) => {
+                self.u8(5);
+                self.seq(values, Self::value);
+            }
+            Value::Array(values) => {
+                self.u8(6);
+                self.seq(values, Self::value);
+            }
+            Value::Range(range) => {
+                self.u8(7);
+                self.option(&range.start, |w, start| w.u64(*start as u64));
+                self.option(&range.end, |w, end| w.u64(*end as u64));
+                self.bool(range.inclusive);
+            }
+            Value::Adt(adt) => {
+                self.u8(8);
+                self.u32(adt.id);
+                self.len(adt.variant);
+                self.seq(&adt.fields, Self::value);
+            }
+            Value::Fn(function) => {
+                self.u8(9);
+                self.function(function);
+            }
+            Value::Builtin(name) => {
+                self.u8(10);
+                self.str(name);
+            }
+            Value::Ctor(id, variant) => {
+                self.u8(11);
+                self.u32(*id);
+                self.len(*variant);
+            }
+            Value::Fixed(value, ty) => {
+                self.u8(12);
+                self.u64(*value as u64);
+                self.u64((*value >> 64) as u64);
+                self.str(ty.name());
+            }
+            Value::Map(_) | Value::Iter(_) | Value::Closure(_) | Value::Ref(_) | Value::Uninit => {
+                let kind = match value {
+                    Value::Map(_) => "a map",
+                    Value::Iter(_) => "an iterator",
+                    Value::Closure(_) => "a closure",
+                    Value::Uninit => "an uninitialized variable",
+                    _ => "a reference",
+                };
+                self.unsupported = self.unsupported.or(Some(kind));
+                self.u8(0);
+            }
+        }
+    }
+
+    fn slot(&mut self, slot: &Slot) {
+        let (tag, slot) = match slot {
+            Slot::Local(slot) => (0, slot),
+            Slot::Global(slot) => (1, slot),
+        };
+        self.u8(tag);
+        self.len(*slot);
+    }
+
+    fn capture(&mut self, capture: &Capture) {
+        let (tag, slot) = match capture {
+            Capture::Local(slot) => (0, slot),
+            Capture::Upvalue(index) => (1, index),
+            Capture::Global(slot) => (2, slot),
+        };
+        self.u8(tag);
+        self.len(*slot);
+    }
+
+    fn target(&mut self, target: &Target) {
+        match target {
+            Target::Variant(id, variant) => {
+                self.u8(0);
+                self.u32(*id);
+                self.len(*variant);
+            }
+            Target::SelfTy(segments) => {
+                self.u8(1);
+                self.seq(segments, Self::ident);
+            }
+            Target::None => self.u8(2),
+        }
+    }
+
+    fn pat(&mut self, pat: &Pat) {
+        match pat {
+            Pat::Wild => self.u8(0),
+            Pat::Bind(slot, sub_pat) => {
+                self.u8(1);
+                self.slot(slot);
+                self.option(sub_pat, |w, pat| w.pat(pat));
+            }
+            Pat::Value(value) => {
+                self.u8(2);
+                self.value(value);
+            }
+            Pat::Range(start, end, inclusive) => {
+                self.u8(3);
+                self.option(start, Self::value);
+                self.option(end, Self::value);
+                self.bool(*inclusive);
+            }
+            Pat::Tuple(pats) => {
+                self.u8(4);
+                self.seq(pats, Self::pat);
+            }
+            Pat::Slice(pats) => {
+                self.u8(5);
+                self.seq(pats, Self::pat);
+            }
+            Pat::Rest(slot) => {
+                self.u8(6);
+                self.option(slot, Self::slot);
+            }
+            Pat::Or(pats) => {
+                self.u8(7);
+                self.seq(pats, Self::pat);
+            }
+            Pat::Unit(target) => {
+                self.u8(8);
+                self.target(target);
+            }
+            Pat::TupleVariant(target, pats) => {
+                self.u8(9);
+                self.target(target);
+                self.seq(pats, Self::pat);
+            }
+            Pat::Struct(target, fields) => {
+                self.u8(10);
+                self.target(target);
+                self.seq(fields, |w, (name, pat)| {
+                    w.str(name);
+                    w.pat(pat);
+                });
+            }
+            Pat::Fail(message, span) => {
+                self.u8(11);
+                self.str(message);
+                self.span(span);
+            }
+        }
+    }
+
+    fn layout(&mut self, layout: &StructLayout) {
+        self.target(&layout.target);
+        self.str(&layout.path);
+        self.span(&layout.path_span);
+        self.seq(&layout.fields, Self::ident);
+        self.option(&layout.rest, Self::span);
+    }
+
+    fn chunk(&mut self, chunk: &Chunk) {
+        self.len(chunk.code.len());
+        self.bytes.extend_from_slice(&chunk.code);
+        self.seq(&chunk.constants, Self::value);
+        self.seq(&chunk.names, Self::ident);
+        self.seq(&chunk.patterns, Self::pat);
+        self.seq(&chunk.bounds, Self::bounds);
+        self.seq(&chunk.layouts, Self::layout);
+        self.seq(&chunk.paths, |w, path| w.seq(path, Self::ident));
+        self.seq(&chunk.spans, |w, (offset, span)| {
+            w.u32(*offset);
+            w.span(span);
+        });
+    }
+
+    fn proto(&mut self, proto: &Proto) {
+        self.str(&proto.name);
+        self.len(proto.frame_size);
+        self.chunk(&proto.chunk);
+        self.seq(&proto.captures, Self::capture);
+        self.bool(proto.by_value);
+    }
+
+    fn params(&mut self, params: &[Option<Bounds>]) {
+        self.seq(params, |w, bounds| w.option(bounds, Self::bounds));
+    }
+
+    fn decls(&mut self, decls: &Declarations) {
+        self.map(&decls.types, |w, id, decl| {
+            w.u32(*id);
+            w.str(&decl.name);
+            w.bool(decl.is_enum);
+            w.seq(&decl.variants, |w, variant| {
+                w.str(&variant.name);
+                w.u8(match variant.kind {
+                    VariantKind::Named => 0,
+                    VariantKind::Tuple => 1,
+                    VariantKind::Unit => 2,
+                });
+                w.seq(&variant.fields, |w, field| w.str(field));
+            });
+        });
+        self.map(&decls.functions, |w, id, sig| {
+            w.u32(*id);
+            w.str(&sig.name);
+            w.option(&sig.self_param, |w, kind| {
+                w.u8(match kind {
+                    SelfKind::Value(Mutability::Not) => 0,
+                    SelfKind::Value(Mutability::Mut) => 1,
+                    SelfKind::Ref(Mutability::Not) => 2,
+                    SelfKind::Ref(Mutability::Mut) => 3,
+                });
+            });
+            w.params(&sig.params);
+        });
+        self.map(&decls.closures, |w, id, params| {
+            w.u32(*id);
+            w.params(params);
+        });
+        self.map(&decls.consts, |w, id, value| {
+            w.u32(*id);
+            w.value(value);
+        });
+        self.map(&decls.other_items, |w, id, name| {
+            w.u32(*id);
+            w.str(name);
+        });
+        self.map(&decls.self_types, |w, id, key| {
+            w.u32(*id);
+            w.type_key(key);
+        });
+        self.map(&decls.inherent, |w, key, functions| {
+            w.type_key(key);
+            w.map(functions, |w, name, function| {
+                w.str(name);
+                w.function(function);
+            });
+        });
+        self.map(&decls.trait_impls, |w, key, traits| {
+            w.type_key(key);
+            w.map(traits, |w, name, functions| {
+                w.str(name);
+                w.map(functions, |w, name, function| {
+                    w.str(name);
+                    w.function(function);
+                });
+            });
+        });
+    }
+
+    fn module(&mut self, module: &Module) {
+        self.decls(&module.decls);
+        self.len(module.globals);
+        self.proto(&module.main);
+        self.map(&module.protos, |w, id, proto| {
+            w.u32(*id);
+            w.proto(proto);
+        });
+        self.seq(&module.warnings, Self::diagnostic);
+    }
+
+    fn diagnostic(&mut self, diagnostic: &Diagnostic) {
+        self.u8(match diagnostic.level {
+            Level::Bug => 0,
+            Level::Fatal => 1,
+            Level::Error => 2,
+            Level::Warning => 3,
+            Level::Note => 4,
+            Level::Help => 5,
+        });
+        self.option(&diagnostic.code, |w, code| w.str(&code.to_string()));
+        self.str(&diagnostic.message);
+        self.option(&diagnostic.span, Self::span);
+    }
+}
+
+// Decoding
+
+type ReadResult<T> = Result<T, LoadError>;
+
+struct Reader<'b> {
+    /// The bytes remaining to read.
+    bytes: &'b [u8],
+    /// The nesting of the value, pattern or bounds being read.
+    depth: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, len: usize) -> ReadResult<&'b [u8]> {
+        let (bytes, rest) = self
+            .bytes
+            .split_at_checked(len)
+            .ok_or(LoadError::Malformed)?;
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> ReadResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> ReadResult<u32> {
+        Ok(u32::from_le_bytes(
+            self.take(4)?.try_into().expect("4 bytes"),
+        ))
+    }
+
+    fn u64(&mut self) -> ReadResult<u64> {
+        Ok(u64::from_le_bytes(
+            self.take(8)?.try_into().expect("8 bytes"),
+        ))
+    }
+
+    fn len(&mut self) -> ReadResult<usize> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn bool(&mut self) -> ReadResult<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(LoadError::Malformed),
+        }
+    }
+
+    fn string(&mut self) -> ReadResult<String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| LoadError::Malformed)
+    }
+
+    fn option<T>(&mut self, f: impl FnOnce(&mut Self) -> ReadResult<T>) -> ReadResult<Option<T>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => f(self).map(Some),
+            _ => Err(LoadError::Malformed),
+        }
+    }
+
+    fn seq<T>(&mut self, mut f: impl FnMut(&mut Self) -> ReadResult<T>) -> ReadResult<Vec<T>> {
+        let len = self.len()?;
+        (0..len).map(|_| f(self)).collect()
+    }
+
+    fn map<K: Eq + Hash, V>(
+        &mut self,
+        mut f: impl FnMut(&mut Self) -> ReadResult<(K, V)>,
+    ) -> ReadResult<HashMap<K, V>> {
+        let len = self.len()?;
+        (0..len).map(|_| f(self)).collect()
+    }
+
+    /// Reads a value, pattern or bounds nested in another one.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> ReadResult<T>) -> ReadResult<T> {
+        if self.depth == MAX_DEPTH {
+            return Err(LoadError::Malformed);
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Reads the name of a built-in type or function.
+    fn builtin_name(&mut self) -> ReadResult<&'static str> {
+        let name = self.string()?;
+        BUILTIN_FUNCTIONS
+            .iter()
+            .chain(BUILTIN_TYPES)
+            .chain(RUNTIME_NAMES)
+            .find(|builtin| **builtin == name)
+            .copied()
+            .ok_or(LoadError::Malformed)
+    }
+
+    fn span(&mut self) -> ReadResult<Span> {
+        let (start, end) = (self.u32()?, self.u32()?);
+        if start > end {
+            return Err(LoadError::Malformed);
+        }
+        Ok(Span { start, end })
+    }
+
+    fn ident(&mut self) -> ReadResult<Ident> {
+        Ok(Ident {
+            name: self.string()?,
+            span: self.span()?,
+        })
+    }
+
+    fn type_key(&mut self) -> ReadResult<TypeKey> {
+        match self.u8()? {
+            0 => Ok(TypeKey::Adt(self.u32()?)),
+            1 => Ok(TypeKey::Builtin(self.builtin_name()?)),
+            _ => Err(LoadError::Malformed),
+        }
+    }
+
+    fn function(&mut self) -> ReadResult<Function> {
+        Ok(Function {
+            id: self.u32()?,
+            self_ty: self.option(Self::type_key)?,
+        })
+    }
+
+    fn bounds(&mut self) -> ReadResult<Bounds> {
+        self.nested(|r| match r.u8()? {
+            0 => Ok(Bounds::Traits(r.seq(Self::string)?)),
+            1 => Ok(Bounds::Ref(Box::new(r.bounds()?))),
+            2 => Ok(Bounds::Array(Box::new(r.bounds()?))),
+            3 => Ok(Bounds::Tuple(r.params()?)),
+            4 => Ok(Bounds::Int(
+                IntTy::from_name(r.builtin_name()?).ok_or(LoadError::Malformed)?,
+            )),
+            _ => Err(LoadError::Malformed),
+        })
+    }
+
+    fn value(&mut self) -> ReadResult<Value> {
+        self.nested(|r| {
+            Ok(match r.u8()? {
+                0 => Value::Nil,
+                1 => Value::Bool(r.bool()?),
+                2 => Value::Int(r.u64()? as i64),
+                3 => Value::Float(f64::from_bits(r.u64()?)),
+                4 => Value::str(&r.string()?),
+                5 => Value::Tuple(Rc::new(r.seq(Self::value)?)),
+                6 => Value::Array(Rc::new(r.seq(Self::value)?)),
+                7 => Value::Range(Rc::new(Range {
+                    start: r.option(|r| Ok(r.u64()? as i64))?,
+                    end: r.option(|r| Ok(r.u64()? as i64))?,
+                    inclusive: r.bool()?,
+                })),
+                8 => Value::Adt(Rc::new(Adt {
+                    id: r.u32()?,
+                    variant: r.len()?,
+                    fields: r.seq(Self::value)?,
+                })),
+                9 => Value::Fn(r.function()?),
+                10 => Value::Builtin(r.builtin_name()?),
+                11 => Value::Ctor(r.u32()?, r.len()?),
+                12 => {
+                    let value = i128::from(r.u64()?) | (i128::from(r.u64()?) << 64);
+                    let ty = IntTy::from_name(r.builtin_name()?).ok_or(LoadError::Malformed)?;
+                    if !in_range(value, ty) {
+                        return Err(LoadError::Malformed);
+                    }
+                    Value::int(value, ty)
+                }
+                _ => return Err(LoadError::Malformed),
+            })
+        })
+    }
+
+    fn slot(&mut self) -> ReadResult<Slot> {
+        match self.u8()? {
+            0 => Ok(Slot::Local(self.len()?)),
+            1 => Ok(Slot::Global(self.len()?)),
+            _ => Err(LoadError::Malformed),
+        }
+    }
+
+    fn capture(&mut self) -> ReadResult<Capture> {
+        match self.u8()? {
+            0 => Ok(Capture::Local(self.len()?)),
+            1 => Ok(Capture::Upvalue(self.len()?)),
+            2 => Ok(Capture::Global(self.len()?)),
+            _ => Err(LoadError::Malformed),
+        }
+    }
+
+    fn target(&mut self) -> ReadResult<Target> {
+        match self.u8()? {
+            0 => Ok(Target::Variant(self.u32()?, self.len()?)),
+            1 => Ok(Target::SelfTy(self.seq(Self::ident)?)),
+            2 => Ok(Target::None),
+            _ => Err(LoadError::Malformed),
+        }
+    }
+
+    fn pat(&mut self) -> ReadResult<Pat> {
+        self.nested(|r| {
+            Ok(match r.u8()? {
+                0 => Pat::Wild,
+                1 => Pat::Bind(r.slot()?, r.option(|r| r.pat().map(Box::new))?),
+                2 => Pat::Value(r.value()?),
+                3 => Pat::Range(r.option(Self::value)?, r.option(Self::value)?, r.bool()?),
+                4 => Pat::Tuple(r.seq(Self::pat)?),
+                5 => Pat::Slice(r.seq(Self::pat)?),
+                6 => Pat::Rest(r.option(Self::slot)?),
+                7 => Pat::Or(r.seq(Self::pat)?),
+                8 => Pat::Unit(r.target()?),
+                9 => Pat::TupleVariant(r.target()?, r.seq(Self::pat)?),
+                10 => Pat::Struct(r.target()?, r.seq(|r| Ok((r.string()?, r.pat()?)))?),
+                11 => Pat::Fail(r.string()?, r.span()?),
+                _ => return Err(LoadError::Malformed),
+            })
+        })
+    }
+
+    fn layout(&mut self) -> ReadResult<StructLayout> {
+        Ok(StructLayout {
+            target: self.target()?,
+            path: self.string()?,
+            path_span: self.span()?,
+            fields: self.seq(Self::ident)?,
+            rest: self.option(Self::span)?,
+        })
+    }
+
+    fn chunk(&mut self) -> ReadResult<Chunk> {
+        let len = self.len()?;
+        Ok(Chunk {
+            code: self.take(len)?.to_vec(),
+            constants: self.seq(Self::value)?,
+            names: self.seq(Self::ident)?,
+            patterns: self.seq(Self::pat)?,
+            bounds: self.seq(Self::bounds)?,
+            layouts: self.seq(Self::layout)?,
+            paths: self.seq(|r| r.seq(Self::ident))?,
+            spans: self.seq(|r| Ok((r.u32()?, r.span()?)))?,
+        })
+    }
+
+    fn proto(&mut self) -> ReadResult<Rc<Proto>> {
+        let proto = Proto {
+            name: self.string()?,
+            frame_size: self.len()?,
+            chunk: self.chunk()?,
+            captures: self.seq(Self::capture)?,
+            by_value: self.bool()?,
+        };
+        // The machine looks up the span of every instruction
+        if proto
+            .chunk
+            .spans
+            .first()
+            .is_none_or(|(offset, _)| *offset != 0)
+        {
+            return Err(LoadError::Malformed);
+        }
+        Ok(Rc::new(proto))
+    }
+
+    fn params(&mut self) -> ReadResult<Vec<Option<Bounds>>> {
+        self.seq(|r| r.option(Self::bounds))
+    }
+
+    fn decls(&mut self) -> ReadResult<Declarations> {
+        Ok(Declarations {
+            types: self.map(|r| {
+                let id = r.u32()?;
+                let decl = TypeDecl {
+                    name: r.string()?,
+                    is_enum: r.bool()?,
+                    variants: r.seq(|r| {
+                        Ok(VariantDecl {
+                            name: r.string()?,
+                            kind: match r.u8()? {
+                                0 => VariantKind::Named,
+                                1 => VariantKind::Tuple,
+                                2 => VariantKind::Unit,
+                                _ => return Err(LoadError::Malformed),
+                            },
+                            fields: r.seq(Self::string)?,
+                        })
+                    })?,
+                };
+                Ok((id, decl))
+            })?,
+            functions: self.map(|r| {
+                let id = r.u32()?;
+                let sig = FnSig {
+                    name: r.string()?,
+                    self_param: r.option(|r| match r.u8()? {
+                        0 => Ok(SelfKind::Value(Mutability::Not)),
+                        1 => Ok(SelfKind::Value(Mutability::Mut)),
+                        2 => Ok(SelfKind::Ref(Mutability::Not)),
+                        3 => Ok(SelfKind::Ref(Mutability::Mut)),
+                        _ => Err(LoadError::Malformed),
+                    })?,
+                    params: r.params()?,
+                };
+                Ok((id, sig))
+            })?,
+            closures: self.map(|r| Ok((r.u32()?, r.params()?)))?,
+            consts: self.map(|r| Ok((r.u32()?, r.value()?)))?,
+            other_items: self.map(|r| Ok((r.u32()?, r.string()?)))?,
+            self_types: self.map(|r| Ok((r.u32()?, r.type_key()?)))?,
+            inherent: self
+                .map(|r| Ok((r.type_key()?, r.map(|r| Ok((r.string()?, r.function()?)))?)))?,
+            trait_impls: self.map(|r| {
+                let key = r.type_key()?;
+                let traits = r.map(|r| {
+                    let name = r.string()?;
+                    Ok((name, r.map(|r| Ok((r.string()?, r.function()?)))?))
+                })?;
+                Ok((key, traits))
+            })?,
+        })
+    }
+
+    fn module(&mut self) -> ReadResult<Module> {
+        Ok(Module {
+            decls: self.decls()?,
+            globals: self.len()?,
+            main: self.proto()?,
+            protos: self.map(|r| Ok((r.u32()?, r.proto()?)))?,
+            warnings: self.seq(Self::diagnostic)?,
+        })
+    }
+
+    fn diagnostic(&mut self) -> ReadResult<Diagnostic> {
+        let level = match self.u8()? {
+            0 => Level::Bug,
+            1 => Level::Fatal,
+            2 => Level::Error,
+            3 => Level::Warning,
+            4 => Level::Note,
+            5 => Level::Help,
+            _ => return Err(LoadError::Malformed),
+        };
+        Ok(Diagnostic {
+            level,
+            code: self.option(|r| r.string()?.parse().map_err(|()| LoadError::Malformed))?,
+            message: self.string()?,
+            span: self.option(Self::span)?,
+        })
+    }
+}
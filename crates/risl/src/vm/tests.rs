@@ -0,0 +1,392 @@
+use super::chunk::Op;
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::DiagContext;
+use crate::parser::emitter::new_emitter_none;
+use crate::parser::source_map::SourceMap;
+use crate::test_utils::diagnostics;
+use crate::{Backend, CheckOptions, RunOptions};
+
+/// Runs the source on the virtual machine, returning what it printed and the emitted diagnostics
+/// as `level: message`.
+fn run(source: &str) -> (String, Vec<String>) {
+    let source_map = SourceMap::new("test.risl", source);
+    let context =
+        ParseContext::new(DiagContext::new(new_emitter_none()).with_source_map(source_map));
+    let mut output = vec![];
+    let options = RunOptions {
+        backend: Backend::Vm,
+        ..RunOptions::default()
+    };
+    let _ = crate::run_with_options(
+        &context,
+        source,
+        CheckOptions::default(),
+        options,
+        &mut output,
+    );
+    let diagnostics = diagnostics(&context);
+    (String::from_utf8(output).unwrap(), diagnostics)
+}
+
+fn output(source: &str) -> String {
+    let (output, diagnostics) = run(source);
+    assert_eq!(diagnostics, Vec::<String>::new());
+    output
+}
+
+fn errors(source: &str) -> Vec<String> {
+    run(source).1
+}
+
+/// Compiles the source, returning the instructions of its top-level code.
+fn main_ops(source: &str) -> Vec<Op> {
+    let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+    let module = crate::compile(&context, source, CheckOptions::default()).unwrap();
+    let chunk = &module.main.chunk;
+    let mut ops = vec![];
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let op = Op::from_byte(chunk.code[offset]).unwrap();
+        offset += 1 + op.operands_size();
+        ops.push(op);
+    }
+    ops
+}
+
+#[test]
+fn bytecode() {
+    assert_eq!(
+        main_ops("{ let x = 1; x + 2 * x }"),
+        [
+            Op::Constant,
+            Op::SetLocal,
+            Op::GetLocal,
+            Op::Constant,
+            Op::GetLocal,
+            Op::Binary,
+            Op::Binary,
+            Op::Return,
+        ]
+    );
+    assert_eq!(
+        main_ops("{ let mut x = 1; x += 2; }"),
+        [
+            Op::Constant,
+            Op::SetLocal,
+            Op::Constant,
+            Op::UpdateLocal,
+            Op::Unit,
+            Op::Pop,
+            Op::Unit,
+            Op::Return,
+        ]
+    );
+}
+
+#[test]
+fn break_pops_temporaries() {
+    assert_eq!(
+        output(
+            "let mut total = 0;\n\
+             for i in 0..5 {\n\
+                 for j in 0..5 {\n\
+                     total += 1 + (2, if j == i { continue; } else if j > i { break; } else { j }).1;\n\
+                 }\n\
+             }\n\
+             let mut n = 0;\n\
+             while true { n = [n, match n { 3 => break, n => n + 1 }][1]; }\n\
+             println(total, n);"
+        ),
+        "20 3\n"
+    );
+}
+
+#[test]
+fn labelled_break_ends_inner_iterations() {
+    assert_eq!(
+        output(
+            "let mut total = 0;\n\
+             'outer: for i in 0..4 {\n\
+                 for j in [1, 2, 3] {\n\
+                     for k in 0..j {\n\
+                         if k == i { continue 'outer; }\n\
+                         total += [k, loop { break (k, j); }].len();\n\
+                     }\n\
+                 }\n\
+             }\n\
+             let value = 'a: loop { for x in 0..3 { (1, 'b: loop { for y in 0..x { break 'a y + 10; } break 'b; }); } break 0; };\n\
+             println(total, value);"
+        ),
+        "26 10\n"
+    );
+}
+
+#[test]
+fn closures_capture_each_iteration() {
+    // Each iteration declares its own variables, which the closures keep after it ends
+    assert_eq!(
+        output(
+            "let mut fs = [];\n\
+             for i in 0..3 { fs.push(|| i); }\n\
+             let mut n = 0;\n\
+             while n < 2 { let mut count = n * 10; fs.push(|| { count += 1; count }); n += 1; }\n\
+             'outer: for i in 0..2 {\n\
+                 loop { let k = i + 5; fs.push(|| k); continue 'outer; }\n\
+             }\n\
+             println(fs[0](), fs[2](), fs[3](), fs[3](), fs[4](), fs[5](), fs[6]());"
+        ),
+        "0 2 1 2 11 5 6\n"
+    );
+}
+
+#[test]
+fn deep_recursion_without_host_stack() {
+    // The machine does not recurse on the host stack, unlike the tree-walking interpreter
+    assert_eq!(
+        output(
+            "fn depth(n) { if n == 0 { 0 } else { 1 + depth(n - 1) } }\n\
+             println(depth(1990));"
+        ),
+        "1990\n"
+    );
+}
+
+#[test]
+fn runtime_errors() {
+    assert_eq!(
+        errors(
+            "fn inner(values) { values[3] }\n\
+             fn outer() { let c = |v| inner(v); 1 + c([1, 2]) }\n\
+             outer();"
+        ),
+        [
+            "error: index out of bounds: the length is 2 but the index is 3",
+            "note: backtrace:\n   \
+             0: inner at test.risl:1:20\n   \
+             1: {closure} at test.risl:2:26\n   \
+             2: outer at test.risl:2:40\n   \
+             3: <main> at test.risl:3:1",
+        ]
+    );
+    assert_eq!(
+        errors("fn f() { x += 1; }\nf();\nlet mut x = 1;"),
+        [
+            "error: use of uninitialized variable",
+            "note: backtrace:\n   0: f at test.risl:1:10\n   1: <main> at test.risl:2:1",
+        ]
+    );
+    assert_eq!(
+        errors("let f = |x| -> Option<i64> { Some(Ok(x)? + 1) };\nf(1);"),
+        [
+            "error: the `?` operator can only be used on `Option`s, not `Result`s, in a function \
+             that returns `Option`",
+            "note: backtrace:\n   0: {closure} at test.risl:1:35\n   1: <main> at test.risl:2:1",
+        ]
+    );
+    assert_eq!(
+        errors("let (a, 2) = (1, 3);"),
+        ["error: refutable pattern in local binding does not match `(1, 3)`"]
+    );
+    assert_eq!(
+        errors("struct S { a: i64 }\nlet s = S { a: 1 };\ns.b;"),
+        ["error: no field `b` on type `S`"]
+    );
+}
+
+#[test]
+fn path_errors_are_reported_when_run() {
+    // Paths failing to resolve are compiled to instructions raising the error if they run
+    assert_eq!(
+        run("enum E { A }\n\
+             fn f(run) { if run { E::B } else { 0 } }\n\
+             println(f(false));\n\
+             f(true);"),
+        (
+            String::from("0\n"),
+            vec![
+                String::from("error: no function or associated item named `B` found for `E`"),
+                String::from(
+                    "note: backtrace:\n   0: f at test.risl:2:25\n   1: <main> at test.risl:4:1"
+                ),
+            ]
+        )
+    );
+}
+
+#[test]
+fn disassembler() {
+    let source = "fn add(a, b) {\n    a + b\n}\nlet s = \"x\";\nprintln(add(1, 2), s);\n";
+    let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+    let module = crate::compile(&context, source, CheckOptions::default()).unwrap();
+    assert_eq!(
+        super::disassembler::disassemble(&module, source),
+        "== <main> == (0 slots)\n\
+         \x20     ;    4 | let s = \"x\";\n\
+         0000  Constant     0 (\"x\")\n\
+         0003  SetGlobal    global 0\n\
+         \x20     ;    5 | println(add(1, 2), s);\n\
+         0006  Constant     1 (fn println)\n\
+         0009  Constant     2 (fn add)\n\
+         0012  Constant     3 (1)\n\
+         0015  Constant     4 (2)\n\
+         0018  Call         2 args\n\
+         0020  GetGlobal    global 0\n\
+         0023  Call         2 args\n\
+         0025  Pop\n\
+         0026  Unit\n\
+         0027  Return\n\
+         \n\
+         == add == (2 slots)\n\
+         \x20     ;    1 | fn add(a, b) {\n\
+         0000  SetLocal     local 0\n\
+         0003  SetLocal     local 1\n\
+         \x20     ;    2 |     a + b\n\
+         0006  GetLocal     local 0\n\
+         0009  GetLocal     local 1\n\
+         0012  Binary       +\n\
+         \x20     ;    1 | fn add(a, b) {\n\
+         0014  Return\n"
+    );
+}
+
+#[test]
+fn trace() {
+    let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+    let module = crate::compile(&context, "let x = (1, 2).0;", CheckOptions::default()).unwrap();
+    let (mut output, mut trace) = (vec![], vec![]);
+    let options = super::Options {
+        trace: Some(&mut trace),
+        ..super::Options::default()
+    };
+    super::run(&context, &module, &mut output, options).unwrap();
+    assert_eq!(
+        String::from_utf8(trace).unwrap(),
+        "<main>    0000  Constant     0 (1)\n\
+         \x20         [ 1 ]\n\
+         <main>    0003  Constant     1 (2)\n\
+         \x20         [ 1 ][ 2 ]\n\
+         <main>    0006  Tuple        2\n\
+         \x20         [ (1, 2) ]\n\
+         <main>    0009  Field        0 (.0)\n\
+         \x20         [ 1 ]\n\
+         <main>    0012  SetGlobal    global 0\n\
+         <main>    0015  Unit\n\
+         \x20         [ () ]\n\
+         <main>    0016  Return\n"
+    );
+}
+
+#[test]
+fn serialize() {
+    use super::serialize::{decode, encode, LoadError, VERSION};
+
+    let source = "struct P { x: i64 }\n\
+                  impl P { fn get(&self) -> i64 { self.x } }\n\
+                  const C: (i64, f64) = (1, 2.5);\n\
+                  let mut unused = 0;\n\
+                  let add = |a| a + C.0;\n\
+                  match (P { x: 1 }) { P { x } => println(add(x), P { x: 3 }.get(), C.1) }";
+    let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+    let options = CheckOptions::default();
+    let module = crate::compile(&context, source, options).unwrap();
+    let bytes = encode(&module, source, options).unwrap();
+    // Compiling the same source gives the same file
+    let module = crate::compile(&context, source, options).unwrap();
+    assert_eq!(encode(&module, source, options).unwrap(), bytes);
+    let warnings = module.warnings;
+    assert_eq!(warnings.len(), 1);
+
+    let module = decode(&bytes, Some(source), options).unwrap();
+    assert_eq!(module.warnings, warnings);
+    let mut output = vec![];
+    super::run(&context, &module, &mut output, super::Options::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "2 3 2.5\n");
+    assert!(decode(&bytes, None, options).is_ok());
+
+    let error = |bytes: &[u8], source, options| decode(bytes, source, options).err();
+    assert_eq!(
+        error(&bytes, Some("println(1);"), options),
+        Some(LoadError::SourceChanged)
+    );
+    let type_check = CheckOptions { type_check: true };
+    assert_eq!(
+        error(&bytes, Some(source), type_check),
+        Some(LoadError::OptionsChanged)
+    );
+    let mut other_version = bytes.clone();
+    other_version[6..10].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert_eq!(
+        error(&other_version, None, options),
+        Some(LoadError::Version(VERSION + 1))
+    );
+    let mut damaged = bytes.clone();
+    *damaged.last_mut().unwrap() ^= 1;
+    assert_eq!(error(&damaged, None, options), Some(LoadError::Malformed));
+    let truncated = &bytes[..bytes.len() - 1];
+    assert_eq!(error(truncated, None, options), Some(LoadError::Malformed));
+    assert_eq!(
+        error(b"println(1);", None, options),
+        Some(LoadError::Malformed)
+    );
+}
+
+/// Runs the program collecting the cycles after every allocation, returning its output and the
+/// statistics of the collector.
+fn run_gc_stress(source: &str) -> (String, String) {
+    let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+    let module = crate::compile(&context, source, CheckOptions::default()).unwrap();
+    let (mut output, mut gc_stats) = (vec![], vec![]);
+    let options = super::Options {
+        gc_stress: true,
+        gc_stats: Some(&mut gc_stats),
+        ..super::Options::default()
+    };
+    super::run(&context, &module, &mut output, options).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    (output, String::from_utf8(gc_stats).unwrap())
+}
+
+#[test]
+fn gc_collects_cycles() {
+    // Each closure is stored in the variable it captures, forming a cycle
+    let source = "fn make(n) {\n\
+                      let mut f = || 0;\n\
+                      f = || n + 1;\n\
+                      f\n\
+                  }\n\
+                  let kept = [make(1), make(2)];\n\
+                  for i in 0..10 { make(i); }\n\
+                  println(kept[0]() + kept[1]());";
+    let (output, gc_stats) = run_gc_stress(source);
+    assert_eq!(output, "5\n");
+    assert!(
+        gc_stats.starts_with("gc: 15 allocations, 15 collections, 10 freed, pauses of "),
+        "{gc_stats}"
+    );
+}
+
+#[test]
+fn gc_collects_during_callbacks() {
+    // The cycles are garbage while the built-ins calling the closures still hold their receivers,
+    // arguments and the items collected so far
+    let source = "fn make(n) {\n\
+                      let mut f = || 0;\n\
+                      f = || n + 1;\n\
+                      f\n\
+                  }\n\
+                  let caught = catch_unwind(|| {\n\
+                      for i in 0..10 { make(i); }\n\
+                      make(10)()\n\
+                  });\n\
+                  let total = (0..10).map(|i| make(i)()).fold(0, |acc, x| acc + x);\n\
+                  let fs = (0..3).map(make).zip((3..6).map(make)).collect();\n\
+                  let kept = fs.iter().map(|(f, g)| f() + g()).collect();\n\
+                  for f in (0..4).map(make).filter(|f| f() > 2) { make(f()); }\n\
+                  println(caught, total, kept);";
+    let (output, gc_stats) = run_gc_stress(source);
+    assert_eq!(output, "Ok(11) 55 [5, 7, 9]\n");
+    assert!(
+        gc_stats.starts_with("gc: 85 allocations, 85 collections, 25 freed, pauses of "),
+        "{gc_stats}"
+    );
+}
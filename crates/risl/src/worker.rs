@@ -0,0 +1,140 @@
+//! The thread running the programs, whose stack is large enough for the passes and the backends
+//! recursing on it, whatever the stack of the thread calling the library.
+//!
+//! The values of the programs are not shared between threads, so the thread gets its own context
+//! and output, which forward the diagnostics and what the program prints to the ones of the caller
+//! as they come.
+
+use std::io::{self, Write};
+use std::panic;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+use crate::parser::context::ParseContext;
+use crate::parser::diagnostic::{DiagContext, Diagnostic};
+use crate::parser::emitter::Emitter;
+use crate::parser::source_map::SourceMap;
+use crate::runtime;
+
+/// The size of the stack of the thread running the programs. The passes recurse on it for each
+/// level of the syntax tree, up to [`crate::parser::parse::MAX_NESTING_DEPTH`] levels, the
+/// tree-walking interpreter for each nested expression, up to [`runtime::MAX_EVAL_DEPTH`]
+/// expressions across calls, and the virtual machine for each call of a built-in back into the
+/// program. An expression takes about 12 KiB of stack in debug builds, so 32 KiB each leave room
+/// for the constant evaluation, which is bounded by its own call limit.
+pub const STACK_SIZE: usize = runtime::MAX_EVAL_DEPTH * (32 << 10);
+
+/// What the thread running the program sends to the thread calling the library.
+enum Event {
+    Output(Vec<u8>),
+    Flush,
+    Diagnostic(Diagnostic),
+}
+
+/// The first error writing to the output of the caller, shared with the thread.
+type OutputError = Arc<Mutex<Option<io::Error>>>;
+
+fn output_error(error: &OutputError) -> io::Result<()> {
+    match &*error.lock().unwrap_or_else(PoisonError::into_inner) {
+        Some(error) => Err(io::Error::new(error.kind(), error.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// The output of the program on its thread. The writes do not wait for the caller, so its write
+/// errors are returned by the next write or flush, the flushes waiting for the caller.
+struct ForwardOutput {
+    events: Sender<Event>,
+    flushed: Receiver<io::Result<()>>,
+    error: OutputError,
+}
+
+impl ForwardOutput {
+    fn send(&self, event: Event) -> io::Result<()> {
+        output_error(&self.error)?;
+        self.events
+            .send(event)
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+impl Write for ForwardOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(Event::Output(buf.to_vec()))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send(Event::Flush)?;
+        self.flushed
+            .recv()
+            .unwrap_or_else(|_| Err(io::Error::from(io::ErrorKind::BrokenPipe)))
+    }
+}
+
+struct ForwardEmitter(Sender<Event>);
+
+impl Emitter for ForwardEmitter {
+    fn emit_diagnostic(&self, diagnostic: &Diagnostic, _: Option<&SourceMap>) {
+        // The caller is waiting for the thread to end, it cannot stop receiving before
+        let _ = self.0.send(Event::Diagnostic(diagnostic.clone()));
+    }
+}
+
+/// Calls the function on a thread with a stack of [`STACK_SIZE`] bytes, with a context and an
+/// output forwarding to the given ones. A panic of the thread is resumed on the caller, after
+/// the panic hook reported it.
+pub(crate) fn run<R: Send>(
+    context: &ParseContext,
+    output: &mut dyn Write,
+    f: impl FnOnce(&ParseContext, &mut dyn Write) -> R + Send,
+) -> R {
+    let (events, received) = mpsc::channel();
+    let (flush_results, flushed) = mpsc::channel();
+    let source_map = context.diag_ctx().source_map().cloned();
+    let error = OutputError::default();
+    let mut forward_output = ForwardOutput {
+        events: events.clone(),
+        flushed,
+        error: error.clone(),
+    };
+    thread::scope(|scope| {
+        let worker = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, move || {
+                let mut diag_ctx = DiagContext::new(Box::new(ForwardEmitter(events)));
+                if let Some(source_map) = source_map {
+                    diag_ctx = diag_ctx.with_source_map(source_map);
+                }
+                f(&ParseContext::new(diag_ctx), &mut forward_output)
+            })
+            .expect("cannot spawn the thread running the program");
+        // The events end once the thread drops its context and output
+        for event in received {
+            let is_flush = matches!(event, Event::Flush);
+            // The output is not written anymore after an error
+            let written = match event {
+                Event::Output(bytes) => {
+                    output_error(&error).and_then(|()| output.write_all(&bytes))
+                }
+                Event::Flush => output_error(&error).and_then(|()| output.flush()),
+                Event::Diagnostic(diagnostic) => {
+                    context.diag_ctx().emit(diagnostic);
+                    continue;
+                }
+            };
+            if let Err(err) = written {
+                let mut error = error.lock().unwrap_or_else(PoisonError::into_inner);
+                error.get_or_insert(err);
+            }
+            if is_flush {
+                let _ = flush_results.send(output_error(&error));
+            }
+        }
+        match worker.join() {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    })
+}
@@ -0,0 +1,44 @@
+//! Snapshot tests of the AST printer.
+//!
+//! Every `tests/ast/*.risl` program is parsed and printed as an indented tree and as an
+//! S-expression, which are compared to the `.tree` and `.sexp` files next to it. Set the
+//! `RISL_BLESS` environment variable to write the snapshots instead of checking them.
+
+mod common;
+
+use std::fs;
+
+use risl::parser::context::ParseContext;
+use risl::parser::diagnostic::DiagContext;
+use risl::parser::emitter::new_emitter_none;
+use risl::parser::printer::{self, Format};
+
+#[test]
+fn ast_snapshots() {
+    let sources = common::sources("ast");
+
+    let mut failures = vec![];
+    for source_path in &sources {
+        let source = fs::read_to_string(source_path).unwrap();
+        let context = ParseContext::new(DiagContext::new(new_emitter_none()));
+        let program = risl::parser::parse(&context, &source);
+        // Only the recovery snapshot is expected to contain errors
+        let expects_errors = source_path.ends_with("recovery.risl");
+        if context.diag_ctx().has_errors() != expects_errors {
+            failures.push(format!(
+                "unexpected diagnostics for '{}': {:?}",
+                source_path.display(),
+                context.diag_ctx().diagnostics()
+            ));
+        }
+        for (format, extension) in [(Format::Tree, "tree"), (Format::Sexp, "sexp")] {
+            let actual = printer::print(&program, format);
+            if let Err(failure) =
+                common::check_snapshot(&source_path.with_extension(extension), &actual)
+            {
+                failures.push(failure);
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
@@ -0,0 +1,39 @@
+//! Helpers shared by the snapshot tests.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Returns the `.risl` programs of the directory of the tests, sorted by path.
+pub fn sources(dir: &str) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(dir);
+    let mut sources: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "risl"))
+        .collect();
+    sources.sort();
+    assert!(!sources.is_empty());
+    sources
+}
+
+/// Compares the output to the snapshot at the path, or writes it there if the `RISL_BLESS`
+/// environment variable is set.
+pub fn check_snapshot(path: &Path, actual: &str) -> Result<(), String> {
+    if std::env::var_os("RISL_BLESS").is_some() {
+        fs::write(path, actual).unwrap();
+        return Ok(());
+    }
+    let expected = fs::read_to_string(path)
+        .map_err(|err| format!("cannot read snapshot '{}': {err}", path.display()))?;
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(format!(
+            "snapshot '{}' does not match, run with RISL_BLESS=1 to update it\n\
+             --- expected\n{expected}\n--- actual\n{actual}",
+            path.display()
+        ))
+    }
+}
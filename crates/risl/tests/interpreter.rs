@@ -0,0 +1,134 @@
+//! Snapshot tests of the interpreter and of the virtual machine.
+//!
+//! Every `tests/run/*.risl` program is run with both backends, on the virtual machine collecting
+//! the garbage after every allocation, and from its compiled module stored and loaded back. What
+//! it prints followed by the emitted diagnostics is compared to the `.out` file next to it, shared
+//! by all the runs. Set the `RISL_BLESS` environment variable to write the snapshots instead of
+//! checking them.
+
+mod common;
+
+use std::fs;
+use std::thread;
+
+use risl::parser::context::ParseContext;
+use risl::parser::diagnostic::DiagContext;
+use risl::parser::emitter::new_emitter_none;
+use risl::parser::source_map::SourceMap;
+use risl::vm::serialize;
+use risl::{Backend, CheckOptions, RunOptions};
+
+/// How the programs are run.
+#[derive(Clone, Copy)]
+enum Mode {
+    Run(RunOptions),
+    /// The module compiled from the source is stored and loaded back before running it.
+    Compiled,
+}
+
+/// Runs the source of the named file, returning what it printed followed by the diagnostics, one
+/// per line.
+fn run(name: String, source: String, mode: Mode) -> String {
+    let run = move || {
+        let source_map = SourceMap::new(name, source.as_str());
+        let context =
+            ParseContext::new(DiagContext::new(new_emitter_none()).with_source_map(source_map));
+        let mut output = vec![];
+        let options = CheckOptions::default();
+        match mode {
+            Mode::Run(run_options) => {
+                let _ =
+                    risl::run_with_options(&context, &source, options, run_options, &mut output);
+            }
+            Mode::Compiled => {
+                if let Some(module) = risl::compile(&context, &source, options) {
+                    let bytes = serialize::encode(&module, &source, options).unwrap();
+                    let module = serialize::decode(&bytes, Some(&source), options).unwrap();
+                    let _ = risl::run_module(&context, &module, RunOptions::default(), &mut output);
+                }
+            }
+        }
+        let mut output = String::from_utf8(output).unwrap();
+        for diagnostic in context.diag_ctx().diagnostics().iter() {
+            output += &format!("{}: {}\n", diagnostic.level, diagnostic.message);
+        }
+        output
+    };
+    match mode {
+        // The library runs the programs on a thread of its own, whatever the stack of the caller
+        Mode::Run(_) => run(),
+        // Unlike the compiled modules, which run on the thread of the caller
+        Mode::Compiled => thread::Builder::new()
+            .stack_size(risl::worker::STACK_SIZE)
+            .spawn(run)
+            .unwrap()
+            .join()
+            .unwrap(),
+    }
+}
+
+fn run_snapshots(mode: Mode) {
+    let sources = common::sources("run");
+
+    let mut failures = vec![];
+    for source_path in &sources {
+        let source = fs::read_to_string(source_path).unwrap();
+        let name = source_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let actual = run(name, source, mode);
+        if let Err(failure) = common::check_snapshot(&source_path.with_extension("out"), &actual) {
+            failures.push(failure);
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn tree_walk_snapshots() {
+    run_snapshots(Mode::Run(RunOptions {
+        backend: Backend::TreeWalk,
+        ..RunOptions::default()
+    }));
+}
+
+#[test]
+fn vm_snapshots() {
+    run_snapshots(Mode::Run(RunOptions::default()));
+}
+
+#[test]
+fn vm_gc_stress_snapshots() {
+    run_snapshots(Mode::Run(RunOptions {
+        backend: Backend::Vm,
+        gc_stress: true,
+        ..RunOptions::default()
+    }));
+}
+
+#[test]
+fn compiled_snapshots() {
+    run_snapshots(Mode::Compiled);
+}
+
+#[test]
+fn tree_walk_nesting_limit() {
+    // The tree-walking interpreter recurses for each nested expression, on top of each call
+    let source = format!(
+        "fn f(n: i64) -> i64 {{ {}f(n + 1){} }}\nf(0);",
+        "(".repeat(200),
+        ")".repeat(200)
+    );
+    let options = RunOptions {
+        backend: Backend::TreeWalk,
+        ..RunOptions::default()
+    };
+    let output = run("nesting.risl".into(), source, Mode::Run(options));
+    let message = format!(
+        "error: stack overflow: more than {} nested expressions\n",
+        risl::runtime::MAX_EVAL_DEPTH
+    );
+    assert!(output.starts_with(&message), "{output}");
+}
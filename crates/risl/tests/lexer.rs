@@ -42,7 +42,7 @@ fn lex_simple_assignment() {
 
 #[test]
 fn lex_invalid() {
-    let source = "@@@@@";
+    let source = "$$$$$";
     let context = stubbed_parse_context();
     let tokens = lex(&context, source).collect::<Vec<_>>();
     assert_eq!(tokens, vec![Token::Err(Span::new(0, 5))]);
@@ -50,7 +50,7 @@ fn lex_invalid() {
 
 #[test]
 fn lex_ident_then_invalid_then_ident() {
-    let source = "hello@@@@@world";
+    let source = "hello$$$$$world";
     let context = stubbed_parse_context();
     let tokens = lex(&context, source).collect::<Vec<_>>();
     assert_eq!(
@@ -65,7 +65,7 @@ fn lex_ident_then_invalid_then_ident() {
 
 #[test]
 fn lex_invalid_then_ident_then_invalid() {
-    let source = "@@@@@hello@@@@@";
+    let source = "$$$$$hello$$$$$";
     let context = stubbed_parse_context();
     let tokens = lex(&context, source).collect::<Vec<_>>();
     assert_eq!(